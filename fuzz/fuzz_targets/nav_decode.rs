@@ -0,0 +1,48 @@
+// Subframes of arbitrary content through the LNAV decoding of a channel:
+//   cargo +nightly fuzz run nav_decode
+// Random bits would hardly ever pass the parity check: the input is the data
+// of a subframe, given its parity, the first byte choosing whether it gets a
+// valid preamble and which bit gets corrupted.
+#![no_main]
+
+use gnss_rcv::channel::Channel;
+use gnss_rcv::code::CodeTables;
+use gnss_rcv::navigation::nav_encode_lnav_parity;
+use gnss_rcv::state::GnssState;
+use gnss_rcv::util::setbitu;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use libfuzzer_sys::fuzz_target;
+use rustfft::FftPlanner;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+thread_local! {
+    static CHANNEL: RefCell<Channel> = RefCell::new(Channel::new(
+        "L1CA",
+        SV::new(Constellation::GPS, 5),
+        &CodeTables::new(),
+        2046000.0,
+        0.0,
+        Arc::new(Mutex::new(GnssState::new())),
+        &mut FftPlanner::new(),
+    ));
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, payload)) = data.split_first() else {
+        return;
+    };
+    let mut nav_data = vec![0; 300];
+    let len = usize::min(payload.len(), 38);
+    nav_data[..len].copy_from_slice(&payload[..len]);
+    if flags & 1 != 0 {
+        setbitu(&mut nav_data, 0, 8, 0x8b);
+        setbitu(&mut nav_data, 58, 2, 0);
+    }
+    let mut bits = nav_encode_lnav_parity(&nav_data);
+    if flags & 2 != 0 {
+        bits[(flags as usize >> 2) * 300 / 64] ^= 1;
+    }
+    CHANNEL.with(|channel| channel.borrow_mut().nav_decode_lnav_bits(&bits));
+});
@@ -0,0 +1,331 @@
+use std::ffi::{c_int, c_void};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::DeviceConfig;
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleQueue, SampleSource};
+
+// libairspy converts the 12-bit real ADC stream to IQ at half the ADC rate:
+// its int16 samples span the whole 16-bit range.
+const AIRSPY_SAMPLE_INT16_IQ: c_int = 2;
+const AIRSPY_SUCCESS: c_int = 0;
+const AIRSPY_TRUE: c_int = 1;
+const AIRSPY_FULL_SCALE: Float = 32768.0;
+const AIRSPY_TIMEOUT_SEC: f64 = 1.0; // without samples: the device is lost
+const AIRSPY_REOPEN_PERIOD_SEC: f64 = 1.0;
+const AIRSPY_DEFAULT_LNA_GAIN: u32 = 10; // 0-14
+const AIRSPY_DEFAULT_MIXER_GAIN: u32 = 10; // 0-15
+const AIRSPY_DEFAULT_VGA_GAIN: u32 = 10; // 0-15
+
+#[repr(C)]
+struct AirspyDeviceHandle {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct AirspyTransfer {
+    device: *mut AirspyDeviceHandle,
+    ctx: *mut c_void,
+    samples: *mut c_void,
+    sample_count: c_int,
+    dropped_samples: u64,
+    sample_type: c_int,
+}
+
+type AirspySampleBlockCb = extern "C" fn(transfer: *mut AirspyTransfer) -> c_int;
+
+#[link(name = "airspy")]
+unsafe extern "C" {
+    fn airspy_open(device: *mut *mut AirspyDeviceHandle) -> c_int;
+    fn airspy_close(device: *mut AirspyDeviceHandle) -> c_int;
+    fn airspy_get_samplerates(device: *mut AirspyDeviceHandle, buf: *mut u32, len: u32) -> c_int;
+    fn airspy_set_samplerate(device: *mut AirspyDeviceHandle, samplerate: u32) -> c_int;
+    fn airspy_set_sample_type(device: *mut AirspyDeviceHandle, sample_type: c_int) -> c_int;
+    fn airspy_set_freq(device: *mut AirspyDeviceHandle, freq_hz: u32) -> c_int;
+    fn airspy_set_lna_gain(device: *mut AirspyDeviceHandle, value: u8) -> c_int;
+    fn airspy_set_mixer_gain(device: *mut AirspyDeviceHandle, value: u8) -> c_int;
+    fn airspy_set_vga_gain(device: *mut AirspyDeviceHandle, value: u8) -> c_int;
+    fn airspy_start_rx(
+        device: *mut AirspyDeviceHandle,
+        callback: AirspySampleBlockCb,
+        rx_ctx: *mut c_void,
+    ) -> c_int;
+    fn airspy_stop_rx(device: *mut AirspyDeviceHandle) -> c_int;
+    fn airspy_is_streaming(device: *mut AirspyDeviceHandle) -> c_int;
+}
+
+// state shared with the libairspy streaming thread
+struct AirspyShared {
+    queue: SampleQueue,
+    num_dropped: Mutex<u64>, // by libairspy
+}
+
+pub struct AirspyDevice {
+    dev: *mut AirspyDeviceHandle, // null once lost, until re-opened
+    shared: Arc<AirspyShared>,
+    num_sleep: u64,
+    num_dropped_reported: u64,
+    sig: String,
+    fs: f64,
+    config: DeviceConfig,
+    lost: bool,
+    last_reopen: Option<Instant>,
+    time_jump: bool,
+}
+
+// the handle is only used from the thread owning the AirspyDevice.
+unsafe impl Send for AirspyDevice {}
+
+impl Drop for AirspyDevice {
+    fn drop(&mut self) {
+        tracing::info!(
+            "airspy: stopping read. num_samples={} dropped={}+{}",
+            self.shared.queue.get_num_samples_total(),
+            self.shared.num_dropped.lock().unwrap(),
+            self.shared.queue.get_num_dropped_total()
+        );
+        tracing::debug!("airspy: num_sleep={}", self.num_sleep);
+
+        self.close();
+        // release the reference held by the callback context
+        unsafe {
+            drop(Arc::from_raw(Arc::as_ptr(&self.shared)));
+        }
+    }
+}
+
+extern "C" fn airspy_rx_callback(transfer: *mut AirspyTransfer) -> c_int {
+    let transfer = unsafe { &*transfer };
+    let shared = unsafe { &*(transfer.ctx as *const AirspyShared) };
+    let samples = unsafe {
+        std::slice::from_raw_parts(
+            transfer.samples as *const i16,
+            2 * transfer.sample_count as usize,
+        )
+    };
+    let v: Vec<_> = samples
+        .chunks_exact(2)
+        .map(|s| IQSample {
+            re: s[0] as Float / AIRSPY_FULL_SCALE,
+            im: s[1] as Float / AIRSPY_FULL_SCALE,
+        })
+        .collect();
+    shared.queue.push(v);
+    *shared.num_dropped.lock().unwrap() += transfer.dropped_samples;
+    0
+}
+
+impl SampleSource for AirspyDevice {
+    fn read_block(
+        &mut self,
+        _off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        let mut start = Instant::now();
+        let block = loop {
+            if let Some(block) = self.shared.queue.pop(num_samples) {
+                break block;
+            }
+            if !self.lost
+                && (unsafe { airspy_is_streaming(self.dev) } != AIRSPY_TRUE
+                    || start.elapsed() > Duration::from_secs_f64(AIRSPY_TIMEOUT_SEC))
+            {
+                tracing::warn!("airspy: no more samples");
+                self.lost = true;
+            }
+            if self.lost {
+                // back to the receiver while it fails: it checks its exit
+                // request in holdover
+                self.reopen()?;
+                start = Instant::now();
+                continue;
+            }
+            thread::sleep(Duration::from_millis(1));
+            self.num_sleep += 1;
+        };
+
+        // libairspy reports the samples it couldn't hand over in time
+        let num_dropped = *self.shared.num_dropped.lock().unwrap();
+        let block = SampleBlock {
+            num_dropped: block.num_dropped + (num_dropped - self.num_dropped_reported) as usize,
+            time_jump: std::mem::take(&mut self.time_jump),
+            ..block
+        };
+        self.num_dropped_reported = num_dropped;
+
+        Ok(block)
+    }
+
+    fn is_device_lost(&self) -> bool {
+        self.lost
+    }
+
+    fn get_num_samples_queued(&self) -> Option<usize> {
+        Some(self.shared.queue.get_num_samples())
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+fn check(res: c_int, what: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if res != AIRSPY_SUCCESS {
+        return Err(format!("airspy: {what} failed: {res}").into());
+    }
+    Ok(())
+}
+
+fn get_samplerates(dev: *mut AirspyDeviceHandle) -> Vec<u32> {
+    let mut count = 0u32;
+    unsafe {
+        airspy_get_samplerates(dev, &mut count, 0);
+    }
+    let mut rates = vec![0u32; count as usize];
+    unsafe {
+        airspy_get_samplerates(dev, rates.as_mut_ptr(), count);
+    }
+    rates
+}
+
+impl AirspyDevice {
+    pub fn new(
+        sig: &str,
+        fs: f64,
+        config: &DeviceConfig,
+    ) -> Result<AirspyDevice, Box<dyn std::error::Error>> {
+        let shared = Arc::new(AirspyShared {
+            queue: config.new_sample_queue(fs),
+            num_dropped: Mutex::new(0),
+        });
+        // the callback context keeps its own reference, released on drop
+        let ctx = Arc::into_raw(shared.clone());
+        let dev = match Self::open(sig, fs, config, &shared) {
+            Ok(dev) => dev,
+            Err(e) => {
+                unsafe {
+                    drop(Arc::from_raw(ctx));
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            dev,
+            shared,
+            num_sleep: 0,
+            num_dropped_reported: 0,
+            sig: sig.to_string(),
+            fs,
+            config: config.clone(),
+            lost: false,
+            last_reopen: None,
+            time_jump: false,
+        })
+    }
+
+    fn close(&mut self) {
+        if self.dev.is_null() {
+            return;
+        }
+        unsafe {
+            airspy_stop_rx(self.dev);
+            airspy_close(self.dev);
+        }
+        self.dev = std::ptr::null_mut();
+    }
+
+    // unplugged or stalled: try to open it again, at most once per
+    // AIRSPY_REOPEN_PERIOD_SEC. Errors until the device is back.
+    fn reopen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self
+            .last_reopen
+            .is_some_and(|ts| ts.elapsed() < Duration::from_secs_f64(AIRSPY_REOPEN_PERIOD_SEC))
+        {
+            return Err("airspy: device lost".into());
+        }
+        self.last_reopen = Some(Instant::now());
+        tracing::warn!("airspy: device lost, trying to re-open..");
+
+        self.close();
+        // samples left from before the loss aren't contiguous with the new ones
+        self.shared.queue.clear();
+        self.dev = Self::open(&self.sig, self.fs, &self.config, &self.shared)
+            .map_err(|e| format!("airspy: device lost: {e}"))?;
+
+        tracing::info!("airspy: device re-opened");
+        self.lost = false;
+        self.time_jump = true;
+        Ok(())
+    }
+
+    // the device configured and streaming into the queue of shared
+    fn open(
+        sig: &str,
+        fs: f64,
+        config: &DeviceConfig,
+        shared: &Arc<AirspyShared>,
+    ) -> Result<*mut AirspyDeviceHandle, Box<dyn std::error::Error>> {
+        let mut dev = std::ptr::null_mut();
+        check(unsafe { airspy_open(&mut dev) }, "open")?;
+
+        // R2: 10 and 2.5 MSPS, Mini: 10, 6 and 3 MSPS. The lowest rate is
+        // plenty for L1 C/A and gets resampled to the internal rate.
+        let rates = get_samplerates(dev);
+        tracing::info!("airspy: supported sample rates: {rates:?}");
+        if !rates.contains(&(fs as u32)) {
+            unsafe {
+                airspy_close(dev);
+            }
+            let min_rate = rates.iter().min().copied().unwrap_or_default();
+            return Err(format!(
+                "airspy: unsupported fs={fs}, use one of {rates:?}, e.g. --fs {min_rate}"
+            )
+            .into());
+        }
+
+        let lna_gain = config.lna_gain.unwrap_or(AIRSPY_DEFAULT_LNA_GAIN);
+        let mixer_gain = config.mixer_gain.unwrap_or(AIRSPY_DEFAULT_MIXER_GAIN);
+        let vga_gain = config.vga_gain.unwrap_or(AIRSPY_DEFAULT_VGA_GAIN);
+        tracing::info!("airspy: lna_gain={lna_gain} mixer_gain={mixer_gain} vga_gain={vga_gain}");
+
+        let res = unsafe {
+            check(
+                airspy_set_sample_type(dev, AIRSPY_SAMPLE_INT16_IQ),
+                "set_sample_type",
+            )
+            .and_then(|_| check(airspy_set_samplerate(dev, fs as u32), "set_samplerate"))
+            .and_then(|_| {
+                check(
+                    airspy_set_freq(dev, config.get_tuning_freq(sig).round() as u32),
+                    "set_freq",
+                )
+            })
+            .and_then(|_| check(airspy_set_lna_gain(dev, lna_gain as u8), "set_lna_gain"))
+            .and_then(|_| {
+                check(
+                    airspy_set_mixer_gain(dev, mixer_gain as u8),
+                    "set_mixer_gain",
+                )
+            })
+            .and_then(|_| check(airspy_set_vga_gain(dev, vga_gain as u8), "set_vga_gain"))
+        };
+        let ctx = Arc::as_ptr(shared) as *mut c_void;
+        let res = res.and_then(|_| {
+            check(
+                unsafe { airspy_start_rx(dev, airspy_rx_callback, ctx) },
+                "start_rx",
+            )
+        });
+        if let Err(e) = res {
+            unsafe {
+                airspy_close(dev);
+            }
+            return Err(e);
+        }
+        Ok(dev)
+    }
+}
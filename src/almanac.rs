@@ -1,9 +1,16 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    constants::{P2_11, P2_19, P2_20, P2_21, P2_23, P2_38, SC2RAD},
-    util::{getbitu, getbitu2},
+    constants::{
+        EARTH_MU_GPS, EARTH_ROTATION_RATE, P2_11, P2_19, P2_20, P2_21, P2_23, P2_38, SC2RAD,
+    },
+    util::{getbits, getbits2, getbitu},
 };
 
-#[derive(Default, Clone, Debug)]
+const SECS_PER_WEEK: f64 = 604800.0;
+const I0_REF: f64 = 0.3 * SC2RAD; // reference inclination of the almanac
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Almanac {
     pub sat: u32,    /* satellite number */
     pub svh: u32,    /* sv health (0:ok) */
@@ -12,6 +19,7 @@ pub struct Almanac {
     /* SV orbit parameters */
     pub a: f64,
     pub e: f64,
+    pub delta_i: f64, // inclination relative to I0_REF
     pub omg0: f64,
     pub omg: f64,
     pub m0: f64,
@@ -28,16 +36,47 @@ impl Almanac {
         self.sat = svid;
         self.e = getbitu(buf, 68, 16) as f64 * P2_21;
         self.toas = getbitu(buf, 90, 8) * 4096;
-        let _delta_i = getbitu(buf, 98, 16) as f64 * P2_19 * SC2RAD;
+        self.delta_i = getbits(buf, 98, 16) as f64 * P2_19 * SC2RAD;
 
-        self.omg_dot = getbitu(buf, 120, 16) as f64 * P2_38 * SC2RAD;
+        self.omg_dot = getbits(buf, 120, 16) as f64 * P2_38 * SC2RAD;
         self.svh = getbitu(buf, 136, 8);
         let sqrt_a = getbitu(buf, 150, 24) as f64 * P2_11;
         self.a = sqrt_a * sqrt_a;
-        self.omg0 = getbitu(buf, 180, 24) as f64 * P2_23 * SC2RAD;
-        self.omg = getbitu(buf, 210, 24) as f64 * P2_23 * SC2RAD;
-        self.m0 = getbitu(buf, 240, 24) as f64 * P2_23 * SC2RAD;
-        self.f0 = getbitu2(buf, 270, 8, 289, 3) as f64 * P2_20;
-        self.f1 = getbitu(buf, 278, 11) as f64 * P2_38;
+        self.omg0 = getbits(buf, 180, 24) as f64 * P2_23 * SC2RAD;
+        self.omg = getbits(buf, 210, 24) as f64 * P2_23 * SC2RAD;
+        self.m0 = getbits(buf, 240, 24) as f64 * P2_23 * SC2RAD;
+        // af0 and af1: two's complement, af0 split around af1
+        self.f0 = getbits2(buf, 270, 8, 289, 3) as f64 * P2_20;
+        self.f1 = getbits(buf, 278, 11) as f64 * P2_38;
+    }
+
+    // Coarse satellite position, good to a few km: enough to tell which
+    // satellites are up and predict their doppler for a warm start.
+    pub fn get_position_ecef(&self, tow_sec: f64) -> [f64; 3] {
+        let mut t_k = (tow_sec - self.toas as f64).rem_euclid(SECS_PER_WEEK);
+        if t_k > SECS_PER_WEEK / 2.0 {
+            t_k -= SECS_PER_WEEK;
+        }
+        let n = (EARTH_MU_GPS / self.a.powi(3)).sqrt();
+        let m_k = self.m0 + n * t_k;
+
+        let mut e_k = m_k;
+        for _ in 0..10 {
+            e_k = m_k + self.e * e_k.sin();
+        }
+        let v_k = ((1.0 - self.e.powi(2)).sqrt() * e_k.sin()).atan2(e_k.cos() - self.e);
+        let phi_k = v_k + self.omg;
+        let r_k = self.a * (1.0 - self.e * e_k.cos());
+        let i_k = I0_REF + self.delta_i;
+        let omega = self.omg0 + (self.omg_dot - EARTH_ROTATION_RATE) * t_k
+            - EARTH_ROTATION_RATE * self.toas as f64;
+
+        let x = r_k * phi_k.cos();
+        let y = r_k * phi_k.sin();
+        [
+            x * omega.cos() - y * i_k.cos() * omega.sin(),
+            x * omega.sin() + y * i_k.cos() * omega.cos(),
+            y * i_k.sin(),
+        ]
     }
 }
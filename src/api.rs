@@ -0,0 +1,308 @@
+use gnss_rs::sv::SV;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::code::CodeTables;
+use crate::device::DeviceConfig;
+use crate::quality::SignalQuality;
+use crate::receiver::{DEFAULT_FS, ReceiverBuilder};
+use crate::recording::IQFileType;
+use crate::sim::SimConfig;
+use crate::start::get_unix_time_now;
+use crate::state::GnssState;
+use crate::timing::{DEFAULT_SURVEY_SEC, TimingStatus};
+
+// Where the samples come from.
+#[derive(Clone)]
+pub enum GnssSource {
+    File(PathBuf), // IQ file(s): comma-separated list or glob pattern
+    Device(DeviceConfig),
+    RtlTcp(String, DeviceConfig), // host:port, the device settings sent to the server
+    Noise,
+    Memory(Arc<[u8]>), // content of an IQ file, e.g. uploaded to a browser
+    Sim(SimConfig),    // simulated satellites, endless: set num_msec
+}
+
+// Everything a GnssReceiver needs, with the defaults of the command line:
+//   GnssConfig { source: GnssSource::File(path), fs: 4092000.0, ..Default::default() }
+#[derive(Clone)]
+pub struct GnssConfig {
+    pub source: GnssSource,
+    pub iq_file_type: IQFileType,
+    pub loop_file: bool,
+    pub fs: f64,
+    pub fs_internal: f64,
+    pub fi: f64,
+    pub off_msec: usize,
+    pub num_msec: usize, // of signal processed, 0 for all of it
+    pub sig: String,
+    pub sats: String, // comma-separated prns, all the gps satellites when empty
+    pub timing: bool, // timing mode, see on_timing
+    pub survey_sec: f64,
+    pub timing_position: Option<[f64; 3]>, // latitude, longitude (degrees), height (m)
+    pub codes: CodeTables,                 // memory codes, GLONASS frequency channels
+    pub deterministic: bool,               // bit-identical output, sequential channels
+}
+
+impl Default for GnssConfig {
+    fn default() -> Self {
+        Self {
+            source: GnssSource::Noise,
+            iq_file_type: IQFileType::TypePairFloat32,
+            loop_file: false,
+            fs: DEFAULT_FS,
+            fs_internal: DEFAULT_FS,
+            fi: 0.0,
+            off_msec: 0,
+            num_msec: 0,
+            sig: "L1CA".to_string(),
+            sats: String::new(),
+            timing: false,
+            survey_sec: DEFAULT_SURVEY_SEC,
+            timing_position: None,
+            codes: CodeTables::new(),
+            deterministic: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GnssFix {
+    pub ts_sec: f64,   // receiver time
+    pub unix_sec: f64, // from the navigation data once decoded, rough before
+    pub lat: f64,
+    pub lon: f64,
+    pub height: f64,
+    pub num_svs: usize,
+}
+
+// a tracked satellite, once per second of signal
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GnssMeasurement {
+    pub sv: SV,
+    pub ts_sec: f64,
+    pub cn0: f64,
+    pub doppler_hz: f64, // as tracked, front-end clock error included
+    pub code_off_sec: f64,
+    pub has_ephemeris: bool,
+    pub quality: SignalQuality, // multipath indicators
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ChannelEventKind {
+    Locked,
+    Lost,
+    Ephemeris, // complete, the satellite can contribute to fixes
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ChannelEvent {
+    pub sv: SV,
+    pub ts_sec: f64,
+    pub kind: ChannelEventKind,
+}
+
+type FixCallback = Box<dyn FnMut(&GnssFix) + Send>;
+type MeasurementCallback = Box<dyn FnMut(&[GnssMeasurement]) + Send>;
+type ChannelEventCallback = Box<dyn FnMut(&ChannelEvent) + Send>;
+type TimingCallback = Box<dyn FnMut(&TimingStatus) + Send>;
+
+// called from the receiver thread: they should return quickly, e.g. by
+// forwarding to a channel
+#[derive(Default)]
+pub struct Callbacks {
+    fix: Option<FixCallback>,
+    measurements: Option<MeasurementCallback>,
+    channel_event: Option<ChannelEventCallback>,
+    timing: Option<TimingCallback>,
+}
+
+impl Callbacks {
+    pub(crate) fn fix(&mut self, fix: &GnssFix) {
+        if let Some(func) = &mut self.fix {
+            func(fix);
+        }
+    }
+
+    pub(crate) fn has_measurements(&self) -> bool {
+        self.measurements.is_some()
+    }
+
+    pub(crate) fn measurements(&mut self, measurements: &[GnssMeasurement]) {
+        if let Some(func) = &mut self.measurements {
+            func(measurements);
+        }
+    }
+
+    pub(crate) fn channel_event(&mut self, event: &ChannelEvent) {
+        if let Some(func) = &mut self.channel_event {
+            func(event);
+        }
+    }
+
+    pub(crate) fn timing(&mut self, status: &TimingStatus) {
+        if let Some(func) = &mut self.timing {
+            func(status);
+        }
+    }
+}
+
+// A receiver running on its own thread, for embedding:
+//   let mut rcv = GnssReceiver::new(config);
+//   rcv.on_fix(|fix| println!("{:.5},{:.5}", fix.lat, fix.lon));
+//   rcv.start()?;
+//   ...
+//   rcv.stop();
+pub struct GnssReceiver {
+    config: GnssConfig,
+    callbacks: Callbacks, // on the receiver thread while running
+    exit_req: Arc<AtomicBool>,
+    pub_state: Arc<Mutex<GnssState>>,
+    thread: Option<JoinHandle<Callbacks>>,
+}
+
+impl GnssReceiver {
+    pub fn new(config: GnssConfig) -> Self {
+        Self {
+            config,
+            callbacks: Callbacks::default(),
+            exit_req: Arc::new(AtomicBool::new(false)),
+            pub_state: Arc::new(Mutex::new(GnssState::new())),
+            thread: None,
+        }
+    }
+
+    pub fn on_fix(&mut self, func: impl FnMut(&GnssFix) + Send + 'static) {
+        self.callbacks.fix = Some(Box::new(func));
+    }
+
+    pub fn on_measurements(&mut self, func: impl FnMut(&[GnssMeasurement]) + Send + 'static) {
+        self.callbacks.measurements = Some(Box::new(func));
+    }
+
+    pub fn on_channel_event(&mut self, func: impl FnMut(&ChannelEvent) + Send + 'static) {
+        self.callbacks.channel_event = Some(Box::new(func));
+    }
+
+    // every second of signal in timing mode
+    pub fn on_timing(&mut self, func: impl FnMut(&TimingStatus) + Send + 'static) {
+        self.callbacks.timing = Some(Box::new(func));
+    }
+
+    fn get_builder(&self) -> ReceiverBuilder {
+        let config = &self.config;
+        let builder = match &config.source {
+            GnssSource::File(file) => ReceiverBuilder::from_file(file)
+                .iq_file_type(&config.iq_file_type)
+                .loop_file(config.loop_file),
+            GnssSource::Device(device) => ReceiverBuilder::from_device(device),
+            GnssSource::RtlTcp(hostname, device) => ReceiverBuilder::from_rtl_tcp(hostname, device),
+            GnssSource::Noise => ReceiverBuilder::from_noise(),
+            GnssSource::Memory(data) => {
+                ReceiverBuilder::from_memory(data.clone()).iq_file_type(&config.iq_file_type)
+            }
+            GnssSource::Sim(sim) => ReceiverBuilder::from_sim(sim.clone()),
+        };
+        builder
+            .fs(config.fs)
+            .fs_internal(config.fs_internal)
+            .fi(config.fi)
+            .off_msec(config.off_msec)
+            .signal(&config.sig)
+            .sats(&config.sats)
+            .code_tables(config.codes.clone())
+            .exit_req(self.exit_req.clone())
+            .state(self.pub_state.clone())
+    }
+
+    // Returns once the receiver is up: the configuration errors come back
+    // from here, the samples are processed on a new thread (in wasm32, before
+    // it returns).
+    pub fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_running() {
+            return Err("receiver already running".into());
+        }
+        self.exit_req.store(false, Ordering::SeqCst);
+        let builder = self.get_builder();
+        builder.validate()?;
+        let callbacks = std::mem::take(&mut self.callbacks);
+        let num_msec = self.config.num_msec;
+        let config = self.config.clone();
+        let (sender, receiver) = channel::<Result<(), String>>();
+
+        let run = move || {
+            let built = builder.build().and_then(|mut receiver| {
+                if config.deterministic {
+                    receiver.set_deterministic()?;
+                }
+                Ok(receiver)
+            });
+            let mut receiver = match built {
+                Ok(receiver) => receiver,
+                Err(e) => {
+                    let _ = sender.send(Err(e.to_string()));
+                    return callbacks;
+                }
+            };
+            let _ = sender.send(Ok(()));
+            receiver.set_callbacks(callbacks);
+            if config.timing {
+                let (survey_sec, position) = (config.survey_sec, config.timing_position);
+                receiver.set_timing(survey_sec, position, get_unix_time_now());
+            }
+            receiver.run_loop(num_msec);
+            receiver.take_callbacks()
+        };
+        if cfg!(target_arch = "wasm32") {
+            // no threads in wasm32: the whole input processed before start() returns
+            self.callbacks = run();
+        } else {
+            self.thread = Some(thread::spawn(run));
+        }
+        match receiver.recv() {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                self.wait();
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // asks the receiver to stop and waits for it
+    pub fn stop(&mut self) {
+        self.exit_req.store(true, Ordering::SeqCst);
+        self.wait();
+    }
+
+    // until the end of the input, or stop()
+    pub fn wait(&mut self) {
+        if let Some(thread) = self.thread.take()
+            && let Ok(callbacks) = thread.join()
+        {
+            self.callbacks = callbacks;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    // latest position, if any
+    pub fn get_position(&self) -> Option<[f64; 3]> {
+        self.pub_state.lock().unwrap().fixes.last().copied()
+    }
+}
+
+impl Drop for GnssReceiver {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
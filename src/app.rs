@@ -1,198 +1,1292 @@
+use bytesize::ByteSize;
+use chrono::{DateTime, Local};
 use egui_extras::{Column, TableBuilder};
 use egui_extras::{Size, StripBuilder};
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Epoch;
+use log::Level;
+use rustfft::num_complex::Complex64;
+use serde::{Deserialize, Serialize};
 
-use crate::channel::State;
-use crate::receiver::Receiver;
-use crate::recording::IQFileType;
-use crate::state::GnssState;
+use crate::capture::{CAPTURE_FOLDER, CaptureFile, get_capture_list};
+use crate::channel::{State, SvPriority};
+use crate::console::{CONSOLE_MAX_LINES, LogLine, take_log_lines};
+use crate::device::DeviceType;
+use crate::navigation::SubframeLog;
+use crate::output::OutputConfig;
+use crate::plots::PlotJob;
+use crate::quality::SignalQuality;
+use crate::receiver::ReceiverBuilder;
+use crate::settings::{Settings, SourceKind};
+use crate::sky::get_east_north;
+use crate::start::ALMANAC_MAX_AGE_SEC;
+use crate::start::StartMode;
+use crate::state::SvRequest;
+use crate::state::{AcquisitionGrid, GnssState, PlaybackEvent, PlaybackEventKind};
+use crate::timing::TimingStage;
+use crate::ttff::{Milestone, TimeToFix};
+use crate::util::getbitu;
 
 const PI: f64 = std::f64::consts::PI;
 
 const WIDTH: usize = 800;
 const HEIGHT: usize = 600;
+const PLOT_WIDTH: f32 = 180.0;
+const PLOT_HEIGHT: f32 = 100.0;
+const SKYPLOT_SIZE: f32 = 240.0;
+const SKY_MARKER_SIZE: f32 = 5.0;
+const MAP_SIZE: f32 = 240.0;
+const MAP_MIN_RANGE_M: f64 = 10.0;
+const ACCURACY_NUM: usize = 30; // recent fixes the accuracy is estimated from
+const CN0_BAR_WIDTH: f32 = 24.0;
+const CN0_CHART_HEIGHT: f32 = 120.0;
+const CN0_CHART_MAX: f64 = 55.0; // dB-Hz
+const WATERFALL_WIDTH: f32 = 360.0;
+const WATERFALL_ROWS: usize = 150;
+const ACQ_HEATMAP_HEIGHT: f32 = 150.0;
+const SECS_PER_WEEK: f64 = 604800.0;
+const TIMELINE_WIDTH: f32 = 300.0;
+const TIMELINE_HEIGHT: f32 = 16.0;
+const EVENT_HOVER_PX: f32 = 3.0;
+const CAPTURE_SCAN_SEC: u64 = 2;
+const MAX_RECENT_FILES: usize = 10;
+const RECENT_FILES_KEY: &str = "recent_files";
+const SETTINGS_KEY: &str = "settings";
+const SHOW_CONSOLE_KEY: &str = "show_console";
+const OUTPUTS_KEY: &str = "outputs";
+const APP_NAME: &str = "gnss-rcv";
+const SESSION_STATE_FILE: &str = "state.json"; // position, almanac and ephemeris
+
+// Spectra of the incoming samples as they come, the newest on top.
+#[derive(Default)]
+struct Waterfall {
+    rows: VecDeque<Vec<f64>>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Waterfall {
+    fn push(&mut self, ctx: &egui::Context, spectrum: Vec<f64>) {
+        if self.rows.len() >= WATERFALL_ROWS {
+            self.rows.pop_back();
+        }
+        self.rows.push_front(spectrum);
+
+        let (min, max) = (self.rows.iter().flatten())
+            .fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let span = f64::max(max - min, 1e-9);
+        let width = self.rows[0].len();
+        let pixels = (0..WATERFALL_ROWS)
+            .flat_map(|i| match self.rows.get(i) {
+                Some(row) if row.len() == width => row
+                    .iter()
+                    .map(|v| get_heat_color((v - min) / span))
+                    .collect(),
+                _ => vec![egui::Color32::BLACK; width],
+            })
+            .collect();
+        let image = egui::ColorImage {
+            size: [width, WATERFALL_ROWS],
+            pixels,
+        };
+        match &mut self.texture {
+            Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+            None => {
+                let texture = ctx.load_texture("waterfall", image, egui::TextureOptions::NEAREST);
+                self.texture = Some(texture);
+            }
+        }
+    }
+}
+
+// Last acquisition attempt of the selected channel, drawn as an image: the
+// highest doppler on top.
+#[derive(Default)]
+struct AcquisitionHeatmap {
+    grid: Option<AcquisitionGrid>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl AcquisitionHeatmap {
+    fn set(&mut self, ctx: &egui::Context, grid: AcquisitionGrid) {
+        let max_db = 10.0
+            * grid
+                .power
+                .iter()
+                .flatten()
+                .fold(1.0, |max: f64, &p| max.max(p))
+                .log10();
+        let width = grid.power.first().map_or(0, |row| row.len());
+        let pixels = (grid.power.iter().rev())
+            .flat_map(|row| {
+                row.iter()
+                    .map(|p| get_heat_color(10.0 * p.log10() / max_db))
+            })
+            .collect();
+        let image = egui::ColorImage {
+            size: [width, grid.power.len()],
+            pixels,
+        };
+        match &mut self.texture {
+            Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+            None => {
+                let texture = ctx.load_texture("acquisition", image, egui::TextureOptions::NEAREST);
+                self.texture = Some(texture);
+            }
+        }
+        self.grid = Some(grid);
+    }
+}
+
+// black to blue to yellow as the power goes from 0 to 1
+fn get_heat_color(x: f64) -> egui::Color32 {
+    let x = x.clamp(0.0, 1.0) as f32;
+    if x < 0.5 {
+        egui::Color32::BLACK.lerp_to_gamma(egui::Color32::BLUE, 2.0 * x)
+    } else {
+        egui::Color32::BLUE.lerp_to_gamma(egui::Color32::YELLOW, 2.0 * x - 1.0)
+    }
+}
+
+// log records of the run, filtered by severity and text
+struct Console {
+    lines: VecDeque<LogLine>,
+    level: Level,
+    filter: String,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            level: Level::Info,
+            filter: String::new(),
+        }
+    }
+}
+
+impl Console {
+    fn update(&mut self) {
+        self.lines.extend(take_log_lines());
+        let num_extra = self.lines.len().saturating_sub(CONSOLE_MAX_LINES);
+        self.lines.drain(..num_extra);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for level in [Level::Error, Level::Warn, Level::Info] {
+                ui.selectable_value(&mut self.level, level, level.as_str());
+            }
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("filter")
+                    .desired_width(150.0),
+            );
+            if ui.button("clear").clicked() {
+                self.lines.clear();
+            }
+        });
+        let lines: Vec<_> = (self.lines.iter())
+            .filter(|line| line.level <= self.level && line.text.contains(&self.filter))
+            .collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, lines.len(), |ui, rows| {
+                for line in &lines[rows] {
+                    let color = match line.level {
+                        Level::Error => egui::Color32::RED,
+                        Level::Warn => egui::Color32::YELLOW,
+                        _ => ui.visuals().text_color(),
+                    };
+                    ui.label(
+                        egui::RichText::new(format!("{} {:5} {}", line.ts, line.level, line.text))
+                            .monospace()
+                            .color(color),
+                    );
+                }
+            });
+    }
+}
+
+// files under CAPTURE_FOLDER, looked up again every CAPTURE_SCAN_SEC
+#[derive(Default)]
+struct CaptureList {
+    files: Vec<CaptureFile>,
+    last_scan: Option<Instant>,
+}
+
+impl CaptureList {
+    fn update(&mut self) {
+        let period = Duration::from_secs(CAPTURE_SCAN_SEC);
+        if self.last_scan.is_some_and(|ts| ts.elapsed() < period) {
+            return;
+        }
+        self.files = get_capture_list();
+        self.last_scan = Some(Instant::now());
+    }
+}
+
+// kept across sessions, the latest first
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Sv,
+    Cn0,
+    Elevation,
+}
+
+// a satellite of the table, copied out of the state
+struct TableRow {
+    sv: SV,
+    label: String, // with the signal when several bands track the satellite
+    state: State,
+    cn0: f64,
+    elevation_deg: Option<f64>, // once there is a fix
+    doppler_hz: f64,
+    code_idx: f64,
+    phi: f64,
+    has_eph: bool,
+    quality: SignalQuality,
+    enabled: bool,
+    priority: SvPriority,
+}
+
+// tracked satellites first
+fn get_state_rank(state: &State) -> usize {
+    match state {
+        State::Tracking => 0,
+        State::Acquisition => 1,
+        State::Idle => 2,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecentFile {
+    path: String,
+    iq_file_type: String,
+}
 
 pub struct GnssRcvApp {
-    iq_file: String,
-    iq_file_choice: usize,
-    iq_type_choice: usize,
-    sig_choice: usize,
+    settings: Settings,
+    show_settings: bool,
+    recent_files: Vec<RecentFile>,
+    console: Console,
+    show_console: bool,
+    captures: CaptureList,
+    last_session: Option<Settings>, // offered for resuming until started
+    outputs: OutputConfig,
+    start_mode: StartMode,
+    seek_sec: Option<f64>, // while dragging the playback position
     needs_stop: Arc<AtomicBool>,
     active: Arc<AtomicBool>,
     pub_state: Arc<Mutex<GnssState>>,
+    waterfall: Waterfall,
+    acq_heatmap: AcquisitionHeatmap,
+    detail_sv: Option<SV>, // channel window open
+    sort_column: SortColumn,
+    sort_desc: bool,
 }
 
 impl Default for GnssRcvApp {
     fn default() -> Self {
         Self {
-            iq_file: "resources/nov_3_time_18_48_st_ives".to_owned(),
-            iq_file_choice: 0,
-            iq_type_choice: 0,
-            sig_choice: 0,
+            settings: Settings::default(),
+            show_settings: false,
+            recent_files: vec![],
+            console: Console::default(),
+            show_console: false,
+            captures: CaptureList::default(),
+            last_session: None,
+            outputs: OutputConfig::default(),
+            start_mode: StartMode::Cold,
+            seek_sec: None,
             active: Arc::new(AtomicBool::new(false)),
             needs_stop: Arc::new(AtomicBool::new(false)),
             pub_state: Arc::new(Mutex::new(GnssState::new())),
+            waterfall: Waterfall::default(),
+            acq_heatmap: AcquisitionHeatmap::default(),
+            detail_sv: None,
+            sort_column: SortColumn::Sv,
+            sort_desc: false,
         }
     }
 }
 
+// where what the receiver learns is kept between sessions
+fn get_session_state_file() -> Option<PathBuf> {
+    let dir = eframe::storage_dir(APP_NAME)?;
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(SESSION_STATE_FILE))
+}
+
 fn async_receive(
     active: Arc<AtomicBool>,
-    needs_stop: Arc<AtomicBool>,
-    file: PathBuf,
-    iq_file_type: IQFileType,
-    sig: &str,
-    pub_state: Arc<Mutex<GnssState>>,
+    builder: ReceiverBuilder,
+    start_mode: StartMode,
+    start_unix: f64,
 ) {
-    log::info!("start_receiving");
+    tracing::debug!("start_receiving");
 
     active.store(true, Ordering::SeqCst);
 
-    let mut receiver = Receiver::new(
-        false,
-        "",
-        &file,
-        &iq_file_type,
-        2046000.0,
-        0.0,
-        0,
-        sig,
-        "",
-        needs_stop.clone(),
-        pub_state,
-    );
-
-    log::info!("run_loop");
-
-    receiver.run_loop(0);
+    let state_file = get_session_state_file();
+    let res = builder.build().and_then(|mut receiver| {
+        if let Some(path) = &state_file {
+            receiver.set_start(start_mode, Some(path), start_unix)?;
+        }
+        Ok(receiver)
+    });
+    match res {
+        Ok(mut receiver) => {
+            tracing::debug!("run_loop");
+            receiver.run_loop(0);
+        }
+        Err(e) => tracing::error!("Failed to start the receiver: {e}"),
+    }
 
     active.store(false, Ordering::SeqCst);
-    log::info!("start_receiving: done");
+    tracing::debug!("start_receiving: done");
 }
 
 impl GnssRcvApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    // the sampling parameters start from the command line's
+    pub fn new(cc: &eframe::CreationContext<'_>, fs: f64, fi: f64, sig: &str) -> Self {
+        let mut settings = Settings {
+            fs_khz: fs / 1000.0,
+            fs_internal_khz: fs / 1000.0,
+            fi_khz: fi / 1000.0,
+            sig: sig.to_string(),
+            ..Default::default()
+        };
+        settings.refresh_devices();
+        let recent_files: Vec<RecentFile> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, RECENT_FILES_KEY))
+            .unwrap_or_default();
+        if let Some(recent) = recent_files.first() {
+            settings.iq_file = recent.path.clone();
+            if let Ok(t) = recent.iq_file_type.parse() {
+                settings.iq_file_type = t;
+            }
+        }
+        let last_session = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, SETTINGS_KEY));
+        let show_console = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, SHOW_CONSOLE_KEY))
+            .unwrap_or(false);
+        let outputs = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, OUTPUTS_KEY))
+            .unwrap_or_default();
+        Self {
+            settings,
+            recent_files,
+            last_session,
+            show_console,
+            outputs,
+            ..Default::default()
+        }
+    }
+
+    // The settings of the last session, and a hot start from what it knew:
+    // the receiver falls back to warm or cold if that's too old.
+    fn update_resume_prompt(&mut self, ctx: &egui::Context) {
+        let Some(last_session) = &self.last_session else {
+            return;
+        };
+        let mut resume = None;
+        egui::Modal::new(egui::Id::new("resume_prompt")).show(ctx, |ui| {
+            ui.heading("resume last session?");
+            ui.label(format!(
+                "{}: {}",
+                last_session.source,
+                match last_session.source {
+                    SourceKind::File => last_session.iq_file.as_str(),
+                    SourceKind::RtlTcp => last_session.hostname.as_str(),
+                    _ => "",
+                }
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("resume").clicked() {
+                    resume = Some(true);
+                }
+                if ui.button("new session").clicked() {
+                    resume = Some(false);
+                }
+            });
+        });
+        match resume {
+            Some(true) => {
+                let mut settings = self.last_session.take().unwrap();
+                settings.rtlsdr_devices = std::mem::take(&mut self.settings.rtlsdr_devices);
+                self.settings = settings;
+                self.start_mode = StartMode::Hot;
+                self.start_async(ctx);
+            }
+            Some(false) => self.last_session = None,
+            None => {}
+        }
+    }
+
+    fn add_recent_file(&mut self) {
+        let path = self.settings.iq_file.clone();
+        self.recent_files.retain(|recent| recent.path != path);
+        self.recent_files.insert(
+            0,
+            RecentFile {
+                path,
+                iq_file_type: self.settings.iq_file_type.to_string(),
+            },
+        );
+        self.recent_files.truncate(MAX_RECENT_FILES);
     }
 
     fn stop_async(&mut self) {
         self.needs_stop.store(true, Ordering::SeqCst);
-        log::info!("stop_async");
+        tracing::debug!("stop_async");
     }
 
     fn start_async(&mut self, ctx: &egui::Context) {
-        log::info!("start_async");
+        tracing::debug!("start_async");
         self.needs_stop.store(false, Ordering::SeqCst);
+        if self.settings.source == SourceKind::File {
+            self.add_recent_file();
+        }
 
         let active = self.active.clone();
-        let needs_stop = self.needs_stop.clone();
-        let iq_file = self.iq_file.clone();
-
         self.pub_state = Arc::new(Mutex::new(GnssState::new()));
-        let pub_state = self.pub_state.clone();
-        let sig = "L1CA";
         let ctx_clone = ctx.clone();
-        let iq_file_type = if self.iq_file_choice == 0 {
-            IQFileType::TypePairFloat32
-        } else {
-            IQFileType::TypePairInt16
-        };
+        let start_mode = self.start_mode;
+        let start_unix = self.settings.get_start_unix();
+        let builder = self
+            .settings
+            .get_builder()
+            .exit_req(self.needs_stop.clone())
+            .state(self.pub_state.clone());
 
         let update_func = move || {
             ctx_clone.request_repaint_after_secs(0.05);
         };
 
-        self.pub_state
-            .lock()
-            .unwrap()
-            .set_update_func(Box::new(update_func.clone()));
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.set_update_func(Box::new(update_func.clone()));
+        pub_state.live_plots = true;
+        if self.outputs.is_enabled() {
+            pub_state.output_req = Some(self.outputs.clone());
+        }
+        drop(pub_state);
 
         thread::spawn(move || {
-            log::info!("thread_start");
-            async_receive(
-                active,
-                needs_stop,
-                iq_file.into(),
-                iq_file_type,
-                sig,
-                pub_state,
+            tracing::debug!("thread_start");
+            async_receive(active, builder, start_mode, start_unix);
+            tracing::debug!("thread_stop");
+        });
+    }
+}
+
+fn get_milestones_text(ttff: &TimeToFix) -> String {
+    [
+        Milestone::Acquisition,
+        Milestone::Subframe,
+        Milestone::Ephemeris,
+    ]
+    .iter()
+    .filter_map(|&m| Some(format!("{m}: {:.1}s", ttff.get(m)?)))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+// enable/disable and search priority of each satellite, applied by the
+// receiver at its next step
+fn update_sv_controls(ui: &mut egui::Ui, pub_state: &mut GnssState) {
+    let svs = pub_state.get_svs();
+    let mut reqs = vec![];
+    egui::Grid::new("SvGrid").show(ui, |ui| {
+        for (i, sv) in svs.iter().enumerate() {
+            let channel = pub_state.get_sv_channel(*sv).unwrap();
+            let mut enabled = channel.enabled;
+            let mut priority = channel.priority;
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut enabled, sv.to_string()).changed() {
+                    reqs.push(SvRequest::Enable(*sv, enabled));
+                }
+                egui::ComboBox::from_id_salt(sv)
+                    .width(60.0)
+                    .selected_text(priority.to_string())
+                    .show_ui(ui, |ui| {
+                        for p in [SvPriority::High, SvPriority::Normal, SvPriority::Low] {
+                            ui.selectable_value(&mut priority, p, p.to_string());
+                        }
+                    });
+            });
+            if priority != channel.priority {
+                reqs.push(SvRequest::Priority(*sv, priority));
+            }
+            if i % 6 == 5 {
+                ui.end_row();
+            }
+        }
+    });
+    pub_state.sv_requests.extend(reqs);
+}
+
+// one history of the channel as a line, scaled to its range
+fn draw_history(
+    ui: &mut egui::Ui,
+    size: egui::Vec2,
+    title: &str,
+    values: &[f64],
+    color: egui::Color32,
+) {
+    ui.vertical(|ui| {
+        ui.label(title);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let frame = ui.visuals().widgets.noninteractive.bg_stroke;
+        painter.rect_stroke(rect, 0.0, frame, egui::StrokeKind::Inside);
+        if values.len() < 2 {
+            return;
+        }
+        let (min, max) = values
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let span = f64::max(max - min, 1e-9);
+        let n = (values.len() - 1) as f32;
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let x = rect.left() + rect.width() * i as f32 / n;
+                let y = rect.bottom() - rect.height() * ((v - min) / span) as f32;
+                egui::pos2(x, y)
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        let font = egui::FontId::monospace(10.0);
+        let text_color = ui.visuals().weak_text_color();
+        let top = format!("{max:.2}");
+        let bottom = format!("{min:.2}");
+        painter.text(
+            rect.left_top(),
+            egui::Align2::LEFT_TOP,
+            top,
+            font.clone(),
+            text_color,
+        );
+        painter.text(
+            rect.left_bottom(),
+            egui::Align2::LEFT_BOTTOM,
+            bottom,
+            font,
+            text_color,
+        );
+    });
+}
+
+// Prompt correlator outputs in the I/Q plane: once the carrier is locked the
+// navigation bits show up as two lobes on the I axis.
+fn draw_iq_scatter(ui: &mut egui::Ui, corr_p: &[Complex64]) {
+    ui.vertical(|ui| {
+        ui.label("prompt I/Q");
+        let size = egui::vec2(PLOT_HEIGHT, PLOT_HEIGHT);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+        painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+        painter.hline(rect.x_range(), rect.center().y, stroke);
+        painter.vline(rect.center().x, rect.y_range(), stroke);
+
+        let max = corr_p.iter().fold(1e-9, |max, c| {
+            f64::max(max, f64::max(c.re.abs(), c.im.abs()))
+        });
+        let scale = rect.width() / 2.0 / max as f32;
+        let color = ui.visuals().text_color();
+        for c in corr_p {
+            let pos = rect.center() + egui::vec2(c.re as f32 * scale, -c.im as f32 * scale);
+            painter.circle_filled(pos, 1.0, color);
+        }
+    });
+}
+
+// doppler x code phase search of the selected channel while it acquires
+fn update_acquisition(ui: &mut egui::Ui, heatmap: &AcquisitionHeatmap, sv: SV) {
+    let grid = heatmap.grid.as_ref().filter(|grid| grid.sv == sv);
+    let (Some(grid), Some(texture)) = (grid, &heatmap.texture) else {
+        ui.label("no acquisition attempt yet");
+        return;
+    };
+    let result = if grid.locked {
+        "locked"
+    } else {
+        "below threshold"
+    };
+    ui.label(format!("search peak: {:.1} dB-Hz, {result}", grid.cn0));
+    ui.image((
+        texture.id(),
+        egui::vec2(WATERFALL_WIDTH, ACQ_HEATMAP_HEIGHT),
+    ));
+    let doppler_max_hz = grid.doppler_min_hz + grid.doppler_step_hz * grid.power.len() as f64;
+    ui.monospace(format!(
+        "doppler {:.0}..{:.0} Hz up, code phase 0..{} chips right",
+        grid.doppler_min_hz, doppler_max_hz, grid.code_len
+    ));
+}
+
+// Tracking histories of the selected channel as published by the receiver,
+// or its acquisition search while it has no lock.
+fn update_plots(ui: &mut egui::Ui, pub_state: &mut GnssState, heatmap: &AcquisitionHeatmap) {
+    let svs = pub_state.get_svs();
+
+    let mut plot_sv = pub_state.plot_sv;
+    egui::ComboBox::from_label("channel")
+        .width(60.0)
+        .selected_text(plot_sv.map_or("-".to_string(), |sv| sv.to_string()))
+        .show_ui(ui, |ui| {
+            for sv in svs {
+                ui.selectable_value(&mut plot_sv, Some(sv), sv.to_string());
+            }
+        });
+    if plot_sv != pub_state.plot_sv {
+        pub_state.plot_sv = plot_sv;
+        pub_state.plot = None;
+        pub_state.acq_grid = None;
+    }
+
+    let Some(sv) = plot_sv else {
+        return;
+    };
+    if pub_state.get_sv_channel(sv).unwrap().state != State::Tracking {
+        update_acquisition(ui, heatmap, sv);
+        return;
+    }
+    if let Some(plot) = &pub_state.plot {
+        draw_channel_plots(ui, plot);
+    }
+}
+
+// Latest subframes of a channel: the TLM and HOW words decoded, the others
+// in hex, data bits only when the parity passed, all 30 bits otherwise.
+fn draw_subframes(ui: &mut egui::Ui, subframes: &VecDeque<SubframeLog>) {
+    if subframes.is_empty() {
+        ui.label("no subframe yet");
+        return;
+    }
+    for subframe in subframes {
+        let buf = &subframe.data;
+        let preamble = getbitu(buf, 0, 8);
+        let tlm = getbitu(buf, 8, 14);
+        let tow_sec = getbitu(buf, 30, 17) * 6; // at the start of the next subframe
+        let id = getbitu(buf, 49, 3);
+        let (parity, color) = if subframe.parity_ok {
+            ("parity ok", ui.visuals().text_color())
+        } else {
+            ("parity error", egui::Color32::RED)
+        };
+        ui.colored_label(color, format!("t={:.3}s id={id} {parity}", subframe.ts_sec));
+        ui.monospace(format!(
+            "TLM preamble={preamble:02x} msg={tlm:04x}  HOW tow={tow_sec}s id={id}"
+        ));
+        let words: Vec<_> = if subframe.parity_ok {
+            (2..10)
+                .map(|i| format!("{:06x}", getbitu(buf, 30 * i, 24)))
+                .collect()
+        } else {
+            (2..10)
+                .map(|i| format!("{:08x}", getbitu(buf, 30 * i, 30)))
+                .collect()
+        };
+        ui.monospace(words.join(" "));
+    }
+}
+
+fn draw_channel_plots(ui: &mut egui::Ui, plot: &PlotJob) {
+    let nav_symbols: Vec<_> = plot.corr_p.iter().map(|c| c.re).collect();
+    let plot_size = egui::vec2(PLOT_WIDTH, PLOT_HEIGHT);
+    ui.horizontal_wrapped(|ui| {
+        let color = ui.visuals().text_color();
+        draw_history(ui, plot_size, "doppler (Hz)", &plot.doppler_hz, color);
+        draw_history(
+            ui,
+            plot_size,
+            "code phase offset",
+            &plot.code_phase_offset,
+            egui::Color32::LIGHT_BLUE,
+        );
+        draw_history(ui, plot_size, "phase error", &plot.phi_error, color);
+        draw_history(ui, plot_size, "nav symbols", &nav_symbols, color);
+        draw_iq_scatter(ui, &plot.corr_p);
+    });
+}
+
+// position in the skyplot: the horizon on the outer circle, the zenith at the
+// center and north up
+fn get_sky_point(
+    center: egui::Pos2,
+    radius: f32,
+    azimuth_deg: f64,
+    elevation_deg: f64,
+) -> egui::Pos2 {
+    let r = radius * (90.0 - elevation_deg as f32) / 90.0;
+    let az = (azimuth_deg as f32).to_radians();
+    center + egui::vec2(r * az.sin(), -r * az.cos())
+}
+
+fn get_cn0_color(cn0: f64) -> egui::Color32 {
+    match cn0 {
+        cn0 if cn0 >= 45.0 => egui::Color32::GREEN,
+        cn0 if cn0 >= 35.0 => egui::Color32::YELLOW,
+        _ => egui::Color32::RED,
+    }
+}
+
+// the shape of a marker tells the constellation
+fn draw_sky_marker(
+    painter: &egui::Painter,
+    pos: egui::Pos2,
+    constellation: Constellation,
+    color: egui::Color32,
+) {
+    let r = SKY_MARKER_SIZE;
+    let polygon = |points: Vec<egui::Pos2>| {
+        painter.add(egui::Shape::convex_polygon(
+            points,
+            color,
+            egui::Stroke::NONE,
+        ));
+    };
+    match constellation {
+        Constellation::GPS => {
+            painter.circle_filled(pos, r, color);
+        }
+        Constellation::Galileo => {
+            let rect = egui::Rect::from_center_size(pos, egui::vec2(2.0 * r, 2.0 * r));
+            painter.rect_filled(rect, 0.0, color);
+        }
+        Constellation::Glonass => polygon(vec![
+            pos + egui::vec2(0.0, -r),
+            pos + egui::vec2(r, r),
+            pos + egui::vec2(-r, r),
+        ]),
+        Constellation::BeiDou => polygon(vec![
+            pos + egui::vec2(0.0, -r),
+            pos + egui::vec2(r, 0.0),
+            pos + egui::vec2(0.0, r),
+            pos + egui::vec2(-r, 0.0),
+        ]),
+        _ => {
+            let stroke = egui::Stroke::new(2.0, color);
+            painter.line_segment([pos + egui::vec2(-r, -r), pos + egui::vec2(r, r)], stroke);
+            painter.line_segment([pos + egui::vec2(-r, r), pos + egui::vec2(r, -r)], stroke);
+        }
+    }
+}
+
+// azimuth/elevation of the tracked satellites, colored by C/N0
+fn update_skyplot(ui: &mut egui::Ui, pub_state: &GnssState) {
+    if pub_state.sky.is_empty() {
+        ui.label("no position fix");
+        return;
+    }
+    let size = egui::vec2(SKYPLOT_SIZE, SKYPLOT_SIZE);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let center = rect.center();
+    let radius = rect.width() / 2.0 - 15.0;
+    let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+    let font = egui::FontId::monospace(10.0);
+    let text_color = ui.visuals().text_color();
+
+    for elevation_deg in [0.0, 30.0, 60.0] {
+        let r = radius * (90.0 - elevation_deg) / 90.0;
+        painter.circle_stroke(center, r, stroke);
+    }
+    for (label, azimuth_deg) in [("N", 0.0), ("E", 90.0), ("S", 180.0), ("W", 270.0)] {
+        let pos = get_sky_point(center, radius + 8.0, azimuth_deg, 0.0);
+        painter.text(
+            pos,
+            egui::Align2::CENTER_CENTER,
+            label,
+            font.clone(),
+            text_color,
+        );
+    }
+    for sat in &pub_state.sky {
+        // just set or rising: on the horizon
+        let elevation_deg = f64::max(sat.elevation_deg, 0.0);
+        let pos = get_sky_point(center, radius, sat.azimuth_deg, elevation_deg);
+        let color = get_cn0_color(sat.cn0);
+        draw_sky_marker(&painter, pos, sat.sv.constellation, color);
+        let text_pos = pos + egui::vec2(SKY_MARKER_SIZE + 2.0, 0.0);
+        let text = sat.sv.to_string();
+        painter.text(
+            text_pos,
+            egui::Align2::LEFT_CENTER,
+            text,
+            font.clone(),
+            text_color,
+        );
+    }
+}
+
+// Position track around its mean, east/north in meters: the last fix is
+// circled by the spread of the recent ones (2drms).
+fn update_map(ui: &mut egui::Ui, pub_state: &GnssState) {
+    let fixes = &pub_state.fixes;
+    let Some(&last) = fixes.last() else {
+        ui.label("no position fix");
+        return;
+    };
+    let n = fixes.len() as f64;
+    let mean = [0, 1, 2].map(|i| fixes.iter().map(|fix| fix[i]).sum::<f64>() / n);
+    let points: Vec<_> = fixes.iter().map(|&fix| get_east_north(mean, fix)).collect();
+    let current = get_east_north(mean, last);
+
+    let recent = &points[points.len().saturating_sub(ACCURACY_NUM)..];
+    let num_recent = recent.len() as f64;
+    let east_mean = recent.iter().map(|p| p.0).sum::<f64>() / num_recent;
+    let north_mean = recent.iter().map(|p| p.1).sum::<f64>() / num_recent;
+    let mean_square = (recent.iter())
+        .map(|(east, north)| (east - east_mean).powi(2) + (north - north_mean).powi(2))
+        .sum::<f64>()
+        / num_recent;
+    let accuracy_m = 2.0 * mean_square.sqrt();
+    let range_m = (points.iter())
+        .map(|(east, north)| east.hypot(*north) + accuracy_m)
+        .fold(MAP_MIN_RANGE_M, f64::max)
+        * 1.1;
+
+    ui.label(format!("{} fixes, 2drms {accuracy_m:.1} m", fixes.len()));
+    let size = egui::vec2(MAP_SIZE, MAP_SIZE);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let center = rect.center();
+    let scale = rect.width() / 2.0 / range_m as f32;
+    let to_screen =
+        |(east, north): (f64, f64)| center + egui::vec2(east as f32 * scale, -north as f32 * scale);
+
+    let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+    painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+    painter.hline(rect.x_range(), center.y, stroke);
+    painter.vline(center.x, rect.y_range(), stroke);
+    let weak_color = ui.visuals().weak_text_color();
+    for &point in &points {
+        painter.circle_filled(to_screen(point), 2.0, weak_color);
+    }
+    let accuracy_stroke = egui::Stroke::new(1.0, egui::Color32::LIGHT_BLUE);
+    painter.circle_stroke(
+        to_screen(current),
+        accuracy_m as f32 * scale,
+        accuracy_stroke,
+    );
+    painter.circle_filled(to_screen(current), 4.0, egui::Color32::RED);
+
+    let font = egui::FontId::monospace(10.0);
+    let text = format!("±{range_m:.0} m, north up");
+    painter.text(
+        rect.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        text,
+        font,
+        weak_color,
+    );
+}
+
+// C/N0 of each satellite, the ones still being searched greyed out at the
+// last value they had
+fn update_cn0_chart(ui: &mut egui::Ui, pub_state: &GnssState) {
+    let mut keys: Vec<_> = (pub_state.channels.iter())
+        .filter(|(_key, channel)| channel.state != State::Idle)
+        .map(|(key, _channel)| key)
+        .collect();
+    keys.sort();
+
+    let label_height = 14.0;
+    let size = egui::vec2(
+        CN0_BAR_WIDTH * keys.len() as f32,
+        CN0_CHART_HEIGHT + 2.0 * label_height,
+    );
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let font = egui::FontId::monospace(10.0);
+    let text_color = ui.visuals().text_color();
+    let weak_color = ui.visuals().weak_text_color();
+    let bottom = rect.bottom() - label_height;
+
+    for (i, key) in keys.iter().enumerate() {
+        let channel = &pub_state.channels[*key];
+        let tracking = channel.state == State::Tracking;
+        let x = rect.left() + CN0_BAR_WIDTH * i as f32;
+        let cn0 = channel.cn0.clamp(0.0, CN0_CHART_MAX);
+        let height = CN0_CHART_HEIGHT * (cn0 / CN0_CHART_MAX) as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(x + 2.0, bottom - height),
+            egui::pos2(x + CN0_BAR_WIDTH - 2.0, bottom),
+        );
+        let color = if tracking {
+            get_cn0_color(channel.cn0)
+        } else {
+            weak_color
+        };
+        painter.rect_filled(bar, 0.0, color);
+
+        let center_x = x + CN0_BAR_WIDTH / 2.0;
+        let label_color = if tracking { text_color } else { weak_color };
+        painter.text(
+            egui::pos2(center_x, rect.bottom()),
+            egui::Align2::CENTER_BOTTOM,
+            pub_state.get_channel_label(key),
+            font.clone(),
+            label_color,
+        );
+        if tracking {
+            painter.text(
+                egui::pos2(center_x, bar.top()),
+                egui::Align2::CENTER_BOTTOM,
+                format!("{:.0}", channel.cn0),
+                font.clone(),
+                text_color,
             );
-            log::info!("thread_stop");
+        }
+    }
+    let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+    painter.hline(rect.x_range(), bottom, stroke);
+}
+
+// spectrum of the incoming samples, then its waterfall, frequencies relative
+// to the carrier
+fn update_spectrum(ui: &mut egui::Ui, waterfall: &Waterfall, fs: f64) {
+    let (Some(spectrum), Some(texture)) = (waterfall.rows.front(), &waterfall.texture) else {
+        ui.label("no samples");
+        return;
+    };
+    let width = egui::vec2(WATERFALL_WIDTH, PLOT_HEIGHT);
+    let color = ui.visuals().text_color();
+    draw_history(ui, width, "spectrum (dB)", spectrum, color);
+    ui.image((
+        texture.id(),
+        egui::vec2(WATERFALL_WIDTH, WATERFALL_ROWS as f32),
+    ));
+    ui.horizontal(|ui| {
+        ui.set_width(WATERFALL_WIDTH);
+        ui.monospace(format!("{:.0} kHz", -fs / 2000.0));
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.monospace(format!("+{:.0} kHz", fs / 2000.0));
         });
+    });
+}
+
+fn get_validity_color(valid: Option<bool>) -> egui::Color32 {
+    match valid {
+        Some(true) => egui::Color32::GREEN,
+        Some(false) => egui::Color32::RED,
+        None => egui::Color32::YELLOW, // incomplete, or no time yet to tell
     }
 }
 
-pub fn egui_main() {
-    log::warn!("egui_main");
+// Decoded ephemeris of each channel and the almanac, their age relative to the
+// gps time of the signal: expired ones in red.
+fn update_nav_data(ui: &mut egui::Ui, pub_state: &GnssState) {
+    let now = Some(pub_state.tow_gpst).filter(|&tow_gpst| tow_gpst != Epoch::default());
+    let mut ephs: Vec<_> = (pub_state.channels.values())
+        .filter_map(|channel| Some((channel.eph?, channel.has_eph)))
+        .collect();
+    ephs.sort_by_key(|(eph, _complete)| eph.sv);
+    ephs.dedup_by_key(|(eph, _complete)| eph.sv); // one per satellite, whatever its bands
+
+    ui.label("ephemeris");
+    egui::Grid::new("EphGrid").striped(true).show(ui, |ui| {
+        for title in [
+            "SV", "IODE", "IODC", "toe", "age", "af0", "af1", "tgd", "health",
+        ] {
+            ui.strong(title);
+        }
+        ui.end_row();
+        for (eph, complete) in ephs {
+            let age_sec = now
+                .filter(|_| complete)
+                .map(|now| (now - eph.toe_gpst).to_seconds());
+            let valid = age_sec.map(|age_sec| age_sec.abs() <= eph.get_fit_interval_sec() / 2.0);
+            ui.colored_label(get_validity_color(valid), eph.sv.to_string());
+            ui.monospace(eph.iode.to_string());
+            ui.monospace(eph.iodc.to_string());
+            ui.monospace(eph.toe.to_string());
+            ui.monospace(age_sec.map_or("-".to_string(), |age_sec| format!("{:.0} s", age_sec)));
+            ui.monospace(format!("{:+.3e}", eph.f0));
+            ui.monospace(format!("{:+.3e}", eph.f1));
+            ui.monospace(format!("{:+.3e}", eph.tgd));
+            ui.monospace(eph.svh.to_string());
+            ui.end_row();
+        }
+    });
+
+    ui.label("almanac");
+    egui::Grid::new("AlmGrid").striped(true).show(ui, |ui| {
+        for title in ["SV", "week", "toa", "age", "ecc", "health"] {
+            ui.strong(title);
+        }
+        ui.end_row();
+        for alm in pub_state.almanac.iter().filter(|alm| alm.sat != 0) {
+            let toa = Epoch::from_gpst_seconds(alm.week as f64 * SECS_PER_WEEK + alm.toas as f64);
+            let age_sec = now.map(|now| (now - toa).to_seconds());
+            let valid = age_sec.map(|age_sec| age_sec.abs() < ALMANAC_MAX_AGE_SEC);
+            ui.colored_label(get_validity_color(valid), format!("G{:02}", alm.sat));
+            ui.monospace(alm.week.to_string());
+            ui.monospace(alm.toas.to_string());
+            ui.monospace(age_sec.map_or("-".to_string(), |age_sec| {
+                format!("{:.1} d", age_sec / 86400.0)
+            }));
+            ui.monospace(format!("{:.4}", alm.e));
+            ui.monospace(alm.svh.to_string());
+            ui.end_row();
+        }
+    });
+}
+
+// pause/resume, and seek for recordings
+fn update_playback(ui: &mut egui::Ui, pub_state: &mut GnssState, seek_sec: &mut Option<f64>) {
+    ui.horizontal(|ui| {
+        let text = if pub_state.paused { "resume" } else { "pause" };
+        if ui.button(text).clicked() {
+            pub_state.pause_req = Some(!pub_state.paused);
+        }
+        let Some(progress) = &pub_state.progress else {
+            return;
+        };
+        let duration_sec = progress.duration_sec;
+        let pos_sec = seek_sec.unwrap_or(progress.pos_sec);
+        if let Some(sec) = draw_timeline(ui, pos_sec, duration_sec, &pub_state.events, seek_sec) {
+            pub_state.seek_req = Some(sec);
+        }
+        ui.monospace(format!("{pos_sec:.1} / {duration_sec:.1} s"));
+    });
+}
+
+fn update_port(ui: &mut egui::Ui, enabled: &mut bool, port: &mut u16, name: &str) {
+    ui.checkbox(enabled, name);
+    ui.add_enabled(*enabled, egui::DragValue::new(port).prefix("tcp:"));
+    ui.end_row();
+}
+
+// The sinks the fixes are sent to: taken into account on "apply" while
+// running, and at each start.
+fn update_outputs(ui: &mut egui::Ui, pub_state: &mut GnssState, config: &mut OutputConfig) {
+    egui::Grid::new("OutputGrid").num_columns(2).show(ui, |ui| {
+        update_port(ui, &mut config.nmea, &mut config.nmea_port, "NMEA");
+        update_port(ui, &mut config.gpsd, &mut config.gpsd_port, "gpsd");
+        update_port(ui, &mut config.ws, &mut config.ws_port, "WebSocket");
+        ui.checkbox(&mut config.csv, "CSV");
+        ui.add_enabled(
+            config.csv,
+            egui::TextEdit::singleline(&mut config.csv_file).desired_width(150.0),
+        );
+        ui.end_row();
+    });
+    if ui.button("apply").clicked() {
+        pub_state.output_req = Some(config.clone());
+    }
+    for status in &pub_state.outputs {
+        ui.horizontal(|ui| {
+            let (color, text) = match (&status.error, status.num_clients) {
+                (Some(e), _) => (egui::Color32::RED, e.clone()),
+                (None, Some(0)) => (egui::Color32::GRAY, "no client".to_string()),
+                (None, Some(n)) => (egui::Color32::GREEN, format!("{n} clients")),
+                (None, None) => (egui::Color32::GREEN, "writing".to_string()),
+            };
+            ui.colored_label(color, "●");
+            ui.monospace(&status.name);
+            ui.label(text);
+        });
+    }
+}
+
+// Records the live source to disk, and lists the previous recordings and
+// pre-trigger captures. Returns the one to replay.
+fn update_captures(
+    ui: &mut egui::Ui,
+    pub_state: &mut GnssState,
+    captures: &mut CaptureList,
+    active: bool,
+) -> Option<PathBuf> {
+    captures.update();
+    ui.horizontal(|ui| {
+        let recording = pub_state.recording.is_some();
+        let text = if recording {
+            "stop recording"
+        } else {
+            "record"
+        };
+        if ui.add_enabled(active, egui::Button::new(text)).clicked() {
+            pub_state.record_req = Some(!recording);
+        }
+        if let Some(status) = &pub_state.recording {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!(
+                    "{}: {:.1}s {}",
+                    status.path.display(),
+                    status.duration_sec,
+                    ByteSize::b(status.num_bytes)
+                ),
+            );
+            if status.num_dropped > 0 {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("{} samples dropped: disk too slow", status.num_dropped),
+                );
+            }
+        }
+    });
+    let total: u64 = captures.files.iter().map(|file| file.num_bytes).sum();
+    ui.label(format!(
+        "{CAPTURE_FOLDER}/: {} files, {}",
+        captures.files.len(),
+        ByteSize::b(total)
+    ));
+
+    let mut replay = None;
+    egui::Grid::new("CaptureGrid").striped(true).show(ui, |ui| {
+        for file in &captures.files {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            ui.monospace(name);
+            ui.monospace(ByteSize::b(file.num_bytes).to_string());
+            let date = file.modified.map(|ts| {
+                let ts: DateTime<Local> = ts.into();
+                ts.format("%Y-%m-%d %H:%M:%S").to_string()
+            });
+            ui.monospace(date.unwrap_or_default());
+            if ui
+                .add_enabled(!active, egui::Button::new("replay"))
+                .clicked()
+            {
+                replay = Some(file.path.clone());
+            }
+            ui.end_row();
+        }
+    });
+    replay
+}
+
+fn get_event_color(kind: &PlaybackEventKind) -> egui::Color32 {
+    match kind {
+        PlaybackEventKind::Fix => egui::Color32::GREEN,
+        PlaybackEventKind::Lock(_) => egui::Color32::LIGHT_BLUE,
+        PlaybackEventKind::Loss(_) => egui::Color32::RED,
+        PlaybackEventKind::Interference => egui::Color32::ORANGE,
+    }
+}
+
+// Position in the recording, with a tick per event: click or drag to seek.
+// Returns where to seek once the pointer is released.
+fn draw_timeline(
+    ui: &mut egui::Ui,
+    pos_sec: f64,
+    duration_sec: f64,
+    events: &[PlaybackEvent],
+    seek_sec: &mut Option<f64>,
+) -> Option<f64> {
+    let size = egui::vec2(TIMELINE_WIDTH, TIMELINE_HEIGHT);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    let duration_sec = duration_sec.max(f64::EPSILON);
+    let get_x = |sec: f64| rect.left() + (sec / duration_sec).clamp(0.0, 1.0) as f32 * rect.width();
+    let get_sec = |x: f32| ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) as f64 * duration_sec;
+
+    let painter = ui.painter_at(rect);
+    let bar = rect.shrink2(egui::vec2(0.0, rect.height() / 3.0));
+    painter.rect_filled(bar, 2.0, ui.visuals().extreme_bg_color);
+    let played = egui::Rect::from_min_max(bar.min, egui::pos2(get_x(pos_sec), bar.max.y));
+    painter.rect_filled(played, 2.0, ui.visuals().selection.bg_fill);
+    for event in events {
+        let x = get_x(event.pos_sec);
+        let (top, bottom) = match event.kind {
+            PlaybackEventKind::Fix => (rect.top(), rect.bottom()),
+            _ => (bar.top(), bar.bottom()),
+        };
+        painter.line_segment(
+            [egui::pos2(x, top), egui::pos2(x, bottom)],
+            egui::Stroke::new(1.5, get_event_color(&event.kind)),
+        );
+    }
+    painter.circle_filled(
+        egui::pos2(get_x(pos_sec), rect.center().y),
+        rect.height() / 3.0,
+        ui.visuals().strong_text_color(),
+    );
+
+    let mut seek_req = None;
+    if response.dragged() {
+        *seek_sec = response.interact_pointer_pos().map(|p| get_sec(p.x));
+    } else if response.drag_stopped() || response.clicked() {
+        let pointer_sec = response.interact_pointer_pos().map(|p| get_sec(p.x));
+        seek_req = seek_sec.take().or(pointer_sec);
+    }
+    if let Some(hover) = response.hover_pos() {
+        let text: Vec<_> = (events.iter())
+            .filter(|event| (get_x(event.pos_sec) - hover.x).abs() < EVENT_HOVER_PX)
+            .map(|event| format!("{:.1}s: {}", event.pos_sec, event.kind))
+            .collect();
+        let text = if text.is_empty() {
+            format!("{:.1}s", get_sec(hover.x))
+        } else {
+            text.join("\n")
+        };
+        response.on_hover_text(text);
+    }
+    seek_req
+}
+
+pub fn egui_main(fs: f64, fi: f64, sig: &str) {
+    tracing::debug!("egui_main");
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([WIDTH as f32, HEIGHT as f32]),
         ..eframe::NativeOptions::default()
     };
     eframe::run_native(
-        "gnss-rcv",
+        APP_NAME,
         native_options,
-        Box::new(|cc| Ok(Box::new(GnssRcvApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(GnssRcvApp::new(cc, fs, fi, sig)))),
     )
     .unwrap();
 }
 
 impl eframe::App for GnssRcvApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, RECENT_FILES_KEY, &self.recent_files);
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings);
+        eframe::set_value(storage, SHOW_CONSOLE_KEY, &self.show_console);
+        eframe::set_value(storage, OUTPUTS_KEY, &self.outputs);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.update_top(ctx);
         self.update_mid(ctx);
+        self.update_console(ctx);
         self.update_table(ctx);
+        self.update_channel_window(ctx);
+        self.update_resume_prompt(ctx);
     }
 }
 
 impl GnssRcvApp {
-    fn update_iq_type(&mut self, ui: &mut egui::Ui) {
-        let type_str = ["2xf32", "2xi16"];
-        egui::ComboBox::from_label("iq-format")
-            .width(30.0)
-            .selected_text(type_str[self.iq_type_choice])
-            .show_ui(ui, |ui| {
-                for (i, s) in type_str.iter().enumerate() {
-                    let value = ui.selectable_value(&mut self.iq_type_choice, i, s.to_string());
-                    if value.clicked() {
-                        self.iq_type_choice = i;
-                    }
-                }
-            });
-    }
-    fn update_sig_type(&mut self, ui: &mut egui::Ui) {
-        let sig_str = ["L1CA"];
-
-        egui::ComboBox::from_label("signal")
-            .width(30.0)
-            .selected_text(sig_str[self.sig_choice])
-            .show_ui(ui, |ui| {
-                for (i, s) in sig_str.iter().enumerate() {
-                    let value = ui.selectable_value(&mut self.iq_type_choice, i, s.to_string());
-                    if value.clicked() {
-                        self.sig_choice = i;
-                    }
-                }
-            });
-    }
-    fn update_start_stop(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
-        let button_text = if self.active.load(Ordering::SeqCst) {
-            "stop"
-        } else {
-            "start"
-        };
+    fn update_start_stop(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, valid: bool) {
+        let active = self.active.load(Ordering::SeqCst);
+        let button_text = if active { "stop" } else { "start" };
         if ui
             //  .add_sized([150.0, 25.], egui::Button::new(button_text.to_owned()))
-            .add_sized(
-                ui.available_size(),
-                egui::Button::new(button_text.to_owned()),
+            .add_enabled(
+                active || valid,
+                egui::Button::new(button_text.to_owned()).min_size(ui.available_size()),
             )
             .clicked()
         {
@@ -204,52 +1298,109 @@ impl GnssRcvApp {
         }
     }
     fn update_top(&mut self, ctx: &egui::Context) {
-        let vec_str = [
-            "nov_3_time_18_48_st_ives",
-            "gpssim_2xi16",
-            "L1_20211202_084700_4MHz_IQ.bin",
-            "GPS-L1-2022-03-27.sigmf-data",
-        ];
+        // taken into account at the next start
+        let error = self
+            .settings
+            .get_builder()
+            .validate()
+            .map_err(|e| e.to_string());
 
         egui::TopBottomPanel::top("top_panel")
             .resizable(false)
             .min_height(25.0)
             .show(ctx, |ui| {
                 egui::Grid::new("TopGrid").show(ui, |ui| {
-                    egui::ComboBox::from_label("Pick file")
-                        .width(230.0)
-                        .selected_text(vec_str[self.iq_file_choice])
-                        .show_ui(ui, |ui| {
-                            for (i, s) in vec_str.iter().enumerate() {
-                                let value =
-                                    ui.selectable_value(&mut self.iq_file_choice, i, s.to_string());
-                                if value.clicked() {
-                                    self.iq_file_choice = i;
-                                    self.iq_file = format!("resources/{}", vec_str[i]);
-                                }
-                            }
-                        });
-                    ui.horizontal(|ui| {
-                        ui.add(
-                            egui::TextEdit::singleline(&mut self.iq_file)
-                                .desired_width(f32::INFINITY)
-                                .clip_text(false),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        self.update_iq_type(ui);
-                    });
+                    ui.horizontal(|ui| self.settings.update_source_kind(ui));
+                    match self.settings.source {
+                        SourceKind::File => self.update_file_pick(ui),
+                        SourceKind::Device | SourceKind::RtlTcp => self.update_device_pick(ui),
+                        SourceKind::Noise => {
+                            ui.label("");
+                        }
+                    }
                     ui.horizontal(|ui| {
-                        self.update_sig_type(ui);
+                        ui.toggle_value(&mut self.show_settings, "settings");
+                        ui.toggle_value(&mut self.show_console, "console");
                     });
                     ui.end_row();
-                    self.update_start_stop(ui, ctx);
+                    ui.label("");
+                    if let Err(e) = &error {
+                        ui.colored_label(egui::Color32::RED, e);
+                    } else {
+                        ui.label("");
+                    }
+                    self.update_start_stop(ui, ctx, error.is_ok());
                 });
             });
+
+        egui::Window::new("settings")
+            .open(&mut self.show_settings)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.settings.ui(ui);
+            });
+    }
+
+    fn update_file_pick(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("open..").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .set_directory("resources")
+                    .pick_file()
+            {
+                self.settings.iq_file = path.display().to_string();
+            }
+            ui.add_enabled_ui(!self.recent_files.is_empty(), |ui| {
+                egui::ComboBox::from_id_salt("recent_files")
+                    .width(60.0)
+                    .selected_text("recent")
+                    .show_ui(ui, |ui| {
+                        for recent in &self.recent_files {
+                            let text = format!("{} ({})", recent.path, recent.iq_file_type);
+                            if ui.selectable_label(false, text).clicked() {
+                                self.settings.iq_file = recent.path.clone();
+                                if let Ok(t) = recent.iq_file_type.parse() {
+                                    self.settings.iq_file_type = t;
+                                }
+                            }
+                        }
+                    });
+            });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.iq_file)
+                    .desired_width(f32::INFINITY)
+                    .clip_text(false),
+            );
+        });
+    }
+
+    // live reception: the device or the rtl_tcp host, and its gains
+    fn update_device_pick(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.settings.source == SourceKind::RtlTcp {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.settings.hostname).desired_width(120.0),
+                );
+            } else if self.settings.device_type == DeviceType::RtlSdr {
+                self.settings.update_rtlsdr_pick(ui);
+            } else {
+                ui.label(self.settings.device_type.to_string());
+            }
+            ui.add(egui::Separator::default().vertical());
+            self.settings.update_gain(ui);
+        });
     }
 
     fn update_mid(&mut self, ctx: &egui::Context) {
-        let pub_state = self.pub_state.lock().unwrap();
+        let active = self.active.load(Ordering::SeqCst);
+        let mut replay = None;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if let Some(spectrum) = pub_state.spectrum.take() {
+            self.waterfall.push(ctx, spectrum);
+        }
+        if let Some(grid) = pub_state.acq_grid.take() {
+            self.acq_heatmap.set(ctx, grid);
+        }
         egui::TopBottomPanel::top("mid_panel")
             .resizable(true)
             .min_height(50.0)
@@ -275,6 +1426,91 @@ impl GnssRcvApp {
                                 ui.add(egui::Separator::default().vertical());
                             });
                         }
+                        if pub_state.device_lost {
+                            ui.horizontal(|ui| {
+                                ui.colored_label(egui::Color32::RED, "device lost: holdover");
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if pub_state.lagging || pub_state.num_skipped_msec > 0 {
+                            ui.horizontal(|ui| {
+                                let color = if pub_state.lagging {
+                                    egui::Color32::ORANGE
+                                } else {
+                                    egui::Color32::GRAY
+                                };
+                                ui.colored_label(
+                                    color,
+                                    format!("lagging: {} ms skipped", pub_state.num_skipped_msec),
+                                );
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if pub_state.interference.is_detected() {
+                            ui.horizontal(|ui| {
+                                ui.colored_label(egui::Color32::RED, "interference")
+                                    .on_hover_text(pub_state.interference.to_string());
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if let Some(timing) = &pub_state.timing {
+                            ui.horizontal(|ui| {
+                                let color = match timing.stage {
+                                    TimingStage::Locked => egui::Color32::GREEN,
+                                    TimingStage::Holdover => egui::Color32::ORANGE,
+                                    TimingStage::Survey => egui::Color32::GRAY,
+                                };
+                                ui.colored_label(color, format!("timing: {}", timing.stage))
+                                    .on_hover_text(timing.to_string());
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if let Some(ttff_sec) = pub_state.ttff.get(Milestone::Fix) {
+                            ui.horizontal(|ui| {
+                                ui.monospace(format!(
+                                    "ttff: {ttff_sec:.1}s ({})",
+                                    pub_state.start_mode
+                                ))
+                                .on_hover_text(get_milestones_text(&pub_state.ttff));
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if let Some(estimate) = &pub_state.ppm_estimate {
+                            ui.horizontal(|ui| {
+                                ui.monospace(format!("clock: {:+.2} ppm", estimate.ppm))
+                                    .on_hover_text(estimate.to_string());
+                                // corrected at tuning time from the next start
+                                let is_device = matches!(
+                                    self.settings.source,
+                                    SourceKind::Device | SourceKind::RtlTcp
+                                );
+                                if is_device
+                                    && ui
+                                        .small_button("use")
+                                        .on_hover_text("add to the ppm of the settings")
+                                        .clicked()
+                                {
+                                    self.settings.ppm += estimate.ppm.round() as i32;
+                                }
+                                ui.add(egui::Separator::default().vertical());
+                            });
+                        }
+                        if let Some(progress) = &pub_state.progress {
+                            let text = match progress.speed.zip(progress.eta_sec) {
+                                Some((speed, eta_sec)) => format!("{speed:.1}x eta: {eta_sec:.0}s"),
+                                None => format!("{:.1}%", progress.percent),
+                            };
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::ProgressBar::new(progress.percent as f32 / 100.0)
+                                        .desired_width(150.0)
+                                        .text(text),
+                                );
+                            });
+                        }
+                        if self.active.load(Ordering::SeqCst) {
+                            update_playback(ui, &mut pub_state, &mut self.seek_sec);
+                        }
                         ui.end_row();
                     });
                     egui::Grid::new("MidGrid1").show(ui, |ui| {
@@ -293,8 +1529,56 @@ impl GnssRcvApp {
                             ui.monospace(s);
                         };
                     });
+                    ui.collapsing("navigation data", |ui| {
+                        update_nav_data(ui, &pub_state);
+                    });
+                    ui.collapsing("map", |ui| {
+                        update_map(ui, &pub_state);
+                    });
+                    ui.collapsing("spectrum", |ui| {
+                        update_spectrum(ui, &self.waterfall, pub_state.spectrum_fs);
+                    });
+                    ui.collapsing("C/N0", |ui| {
+                        egui::ScrollArea::horizontal().show(ui, |ui| {
+                            update_cn0_chart(ui, &pub_state);
+                        });
+                    });
+                    ui.collapsing("satellites", |ui| {
+                        update_sv_controls(ui, &mut pub_state);
+                    });
+                    ui.collapsing("sky", |ui| {
+                        update_skyplot(ui, &pub_state);
+                    });
+                    ui.collapsing("plots", |ui| {
+                        update_plots(ui, &mut pub_state, &self.acq_heatmap);
+                    });
+                    ui.collapsing("outputs", |ui| {
+                        update_outputs(ui, &mut pub_state, &mut self.outputs);
+                    });
+                    ui.collapsing("captures", |ui| {
+                        replay = update_captures(ui, &mut pub_state, &mut self.captures, active);
+                    });
                 });
             });
+        drop(pub_state);
+
+        if let Some(path) = replay {
+            self.settings.set_replay(&path);
+            self.start_async(ctx);
+        }
+    }
+
+    fn update_console(&mut self, ctx: &egui::Context) {
+        self.console.update();
+        if !self.show_console {
+            return;
+        }
+        egui::TopBottomPanel::bottom("console_panel")
+            .resizable(true)
+            .default_height(150.0)
+            .show(ctx, |ui| {
+                self.console.ui(ui);
+            });
     }
 
     fn update_table(&mut self, ctx: &egui::Context) {
@@ -314,28 +1598,32 @@ impl GnssRcvApp {
     }
     fn table_ui(&mut self, ui: &mut egui::Ui) {
         let available_height = ui.available_height();
+        let text_color = ui.visuals().text_color();
+        let weak_color = ui.visuals().weak_text_color();
+        let rows = self.get_table_rows();
+        let mut clicked_sv = None;
         let table = TableBuilder::new(ui)
             .resizable(true)
             .striped(true)
+            .sense(egui::Sense::click())
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-            .column(Column::auto())
+            .column(Column::auto().at_least(50.0))
             .column(Column::auto().at_least(30.0).resizable(true))
             .column(Column::auto())
             .column(Column::auto())
             .column(Column::auto())
             .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::auto())
             .column(Column::remainder())
             .min_scrolled_height(0.0)
             .max_scroll_height(available_height);
 
         table
             .header(20.0, |mut header| {
-                header.col(|ui| {
-                    ui.strong("SV");
-                });
-                header.col(|ui| {
-                    ui.strong("dB-Hz");
-                });
+                header.col(|ui| self.update_sort_header(ui, "SV", SortColumn::Sv));
+                header.col(|ui| self.update_sort_header(ui, "dB-Hz", SortColumn::Cn0));
+                header.col(|ui| self.update_sort_header(ui, "elev", SortColumn::Elevation));
                 header.col(|ui| {
                     ui.strong("doppler");
                 });
@@ -348,55 +1636,239 @@ impl GnssRcvApp {
                 header.col(|ui| {
                     ui.strong("ephemeris");
                 });
+                header.col(|ui| {
+                    ui.strong("multipath")
+                        .on_hover_text("code minus carrier, detrended");
+                });
                 header.col(|ui| {
                     ui.strong("other");
                 });
             })
             .body(|mut body| {
-                for row_index in 1..=32 {
-                    let row_height = 20.0;
-                    let sv = SV::new(Constellation::GPS, row_index);
-                    let pub_state = self.pub_state.lock().unwrap();
-                    let channel = pub_state.channels.get(&sv);
-
-                    if channel.is_none() {
-                        continue;
-                    }
-                    let state = channel.unwrap().state.clone();
-                    if state != State::Tracking {
-                        continue;
+                let row_height = 20.0;
+                let mut group = None;
+                for (i, row_data) in rows.iter().enumerate() {
+                    let sv = row_data.sv;
+                    let rank = get_state_rank(&row_data.state);
+                    if group != Some((sv.constellation, rank)) {
+                        group = Some((sv.constellation, rank));
+                        let num = rows[i..]
+                            .iter()
+                            .take_while(|r| {
+                                r.sv.constellation == sv.constellation
+                                    && get_state_rank(&r.state) == rank
+                            })
+                            .count();
+                        body.row(row_height, |mut row| {
+                            row.col(|ui| {
+                                ui.strong(sv.constellation.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.strong(format!("{:?} ({num})", row_data.state).to_lowercase());
+                            });
+                        });
                     }
-                    let cn0 = channel.unwrap().cn0;
-                    let phi = (channel.unwrap().phi % 1.0) * 2.0 * PI;
-                    let doppler_hz = channel.unwrap().doppler_hz;
-                    let code_idx = channel.unwrap().code_idx;
-                    let has_eph = channel.unwrap().has_eph;
+
+                    let tracking = row_data.state == State::Tracking;
+                    let color = if tracking && row_data.enabled {
+                        text_color
+                    } else {
+                        weak_color
+                    };
+                    let phi = (row_data.phi % 1.0) * 2.0 * PI;
+                    let elevation = row_data
+                        .elevation_deg
+                        .map_or("-".to_string(), |e| format!("{e:.0}°"));
+                    let quality = &row_data.quality;
+                    let multipath = match (tracking, quality.cmc_rms_m) {
+                        (true, Some(rms_m)) if quality.multipath => format!("{rms_m:.1} m !"),
+                        (true, Some(rms_m)) => format!("{rms_m:.1} m"),
+                        (true, None) if quality.multipath => "!".to_string(),
+                        _ => "-".to_string(),
+                    };
+                    let other = match (row_data.enabled, row_data.priority) {
+                        (false, _) => "disabled".to_string(),
+                        (true, SvPriority::Normal) => String::new(),
+                        (true, priority) => format!("{priority:?} priority").to_lowercase(),
+                    };
+                    let cells = [
+                        row_data.label.clone(),
+                        format!("{:.1}", row_data.cn0),
+                        elevation,
+                        format!("{:.0}", row_data.doppler_hz),
+                        format!("{:4.0}", row_data.code_idx),
+                        format!("{:.2}", phi),
+                        (if row_data.has_eph { "1" } else { "-" }).to_string(),
+                        multipath,
+                        other,
+                    ];
 
                     body.row(row_height, |mut row| {
-                        row.col(|ui| {
-                            ui.label(format!("{}", sv).to_string());
-                        });
-                        row.col(|ui| {
-                            ui.label(format!("{:.1}", cn0).to_string());
-                        });
-                        row.col(|ui| {
-                            ui.label(format!("{:.0}", doppler_hz).to_string());
-                        });
-                        row.col(|ui| {
-                            ui.label(format!("{:4.0}", code_idx).to_string());
-                        });
-                        row.col(|ui| {
-                            ui.label(format!("{:.2}", phi).to_string());
-                        });
-                        row.col(|ui| {
-                            let s = if has_eph { "1" } else { "-" };
-                            ui.label(s.to_string());
-                        });
-                        row.col(|ui| {
-                            ui.label("".to_string());
-                        });
+                        for cell in cells {
+                            row.col(|ui| {
+                                ui.colored_label(color, cell);
+                            });
+                        }
+                        if row.response().clicked() {
+                            clicked_sv = Some(sv);
+                        }
+                    });
+                }
+            });
+        if let Some(sv) = clicked_sv {
+            self.detail_sv = Some(sv);
+            self.pub_state.lock().unwrap().plot_sv = Some(sv);
+        }
+    }
+
+    // a click sorts by the column, a second one reverses the order
+    fn update_sort_header(&mut self, ui: &mut egui::Ui, name: &str, column: SortColumn) {
+        let selected = self.sort_column == column;
+        let text = match (selected, self.sort_desc) {
+            (false, _) => name.to_string(),
+            (true, false) => format!("{name} ⬆"),
+            (true, true) => format!("{name} ⬇"),
+        };
+        if ui
+            .selectable_label(selected, egui::RichText::new(text).strong())
+            .clicked()
+        {
+            if selected {
+                self.sort_desc = !self.sort_desc;
+            } else {
+                self.sort_column = column;
+                // strongest and highest first
+                self.sort_desc = column != SortColumn::Sv;
+            }
+        }
+    }
+
+    // all the configured satellites, grouped by constellation and state, each
+    // group sorted by the selected column
+    fn get_table_rows(&self) -> Vec<TableRow> {
+        let pub_state = self.pub_state.lock().unwrap();
+        let mut rows: Vec<_> = pub_state
+            .channels
+            .iter()
+            .map(|(key @ (sv, _sig), channel)| TableRow {
+                sv: *sv,
+                label: pub_state.get_channel_label(key),
+                state: channel.state.clone(),
+                cn0: channel.cn0,
+                elevation_deg: (pub_state.sky.iter())
+                    .find(|pos| pos.sv == *sv)
+                    .map(|pos| pos.elevation_deg),
+                doppler_hz: channel.doppler_hz,
+                code_idx: channel.code_idx,
+                phi: channel.phi,
+                has_eph: channel.has_eph,
+                quality: channel.quality,
+                enabled: channel.enabled,
+                priority: channel.priority,
+            })
+            .collect();
+        drop(pub_state);
+
+        // unknown elevations last whatever the order
+        let unknown = if self.sort_desc {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+        rows.sort_by(|a, b| {
+            let order = match self.sort_column {
+                SortColumn::Sv => a.sv.cmp(&b.sv),
+                SortColumn::Cn0 => a.cn0.total_cmp(&b.cn0),
+                SortColumn::Elevation => (a.elevation_deg.unwrap_or(unknown))
+                    .total_cmp(&b.elevation_deg.unwrap_or(unknown)),
+            };
+            let order = if self.sort_desc {
+                order.reverse()
+            } else {
+                order
+            };
+            (a.sv.constellation.cmp(&b.sv.constellation))
+                .then(get_state_rank(&a.state).cmp(&get_state_rank(&b.state)))
+                .then(order)
+        });
+        rows
+    }
+
+    // loops, lock detectors and navigation decoding of the channel clicked in
+    // the table, with its recent histories
+    fn update_channel_window(&mut self, ctx: &egui::Context) {
+        let Some(sv) = self.detail_sv else {
+            return;
+        };
+        let pub_state = self.pub_state.lock().unwrap();
+        let Some(channel) = pub_state.get_sv_channel(sv) else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(format!("channel {sv}"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                egui::Grid::new("ChannelGrid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        let nav = &channel.nav;
+                        let frame_sync = match (nav.frame_sync, nav.reversed) {
+                            (false, _) => "no",
+                            (true, false) => "yes",
+                            (true, true) => "yes, reversed",
+                        };
+                        let rows = [
+                            ("state", format!("{:?}", channel.state)),
+                            ("tracked", format!("{:.1} s", channel.trk_sec)),
+                            ("cn0", format!("{:.1} dB-Hz", channel.cn0)),
+                            ("doppler", format!("{:.1} Hz", channel.doppler_hz)),
+                            ("code phase", format!("{:.1}", channel.code_idx)),
+                            ("carrier loop", channel.loop_stage.to_string()),
+                            ("pll lock", format!("{:.2}", channel.pll_lock)),
+                            ("early-late", format!("{:+.3}", channel.quality.delta)),
+                            ("peak ratio", format!("{:.3}", channel.quality.ratio)),
+                            (
+                                "code-carrier",
+                                (channel.quality.cmc_rms_m)
+                                    .map_or("-".to_string(), |rms_m| format!("{rms_m:.2} m rms")),
+                            ),
+                            (
+                                "multipath",
+                                (if channel.quality.multipath {
+                                    "suspected"
+                                } else {
+                                    "no"
+                                })
+                                .to_string(),
+                            ),
+                            (
+                                "bit sync",
+                                (if nav.bit_sync { "yes" } else { "no" }).to_string(),
+                            ),
+                            ("frame sync", frame_sync.to_string()),
+                            ("subframes", nav.num_subframes.to_string()),
+                            ("parity errors", nav.num_parity_errors.to_string()),
+                            (
+                                "ephemeris",
+                                (if channel.has_eph { "yes" } else { "no" }).to_string(),
+                            ),
+                        ];
+                        for (name, value) in rows {
+                            ui.label(name);
+                            ui.monospace(value);
+                            ui.end_row();
+                        }
                     });
+                ui.collapsing("subframes", |ui| {
+                    draw_subframes(ui, &channel.subframes);
+                });
+                if let Some(plot) = pub_state.plot.as_ref().filter(|plot| plot.sv == sv) {
+                    draw_channel_plots(ui, plot);
                 }
             });
+        drop(pub_state);
+        if !open {
+            self.detail_sv = None;
+        }
     }
 }
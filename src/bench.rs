@@ -0,0 +1,113 @@
+use colored::Colorize;
+use gnss_rs::sv::SV;
+use std::fmt;
+use std::time::Duration;
+
+use crate::clock::Instant;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stage {
+    Read,
+    Acquisition,
+    Tracking,
+    Nav,
+    Solve,
+    Plots,
+}
+
+const STAGES: [Stage; 6] = [
+    Stage::Read,
+    Stage::Acquisition,
+    Stage::Tracking,
+    Stage::Nav,
+    Stage::Solve,
+    Stage::Plots,
+];
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stage::Read => write!(f, "read"),
+            Stage::Acquisition => write!(f, "acquisition"),
+            Stage::Tracking => write!(f, "tracking"),
+            Stage::Nav => write!(f, "nav"),
+            Stage::Solve => write!(f, "solve"),
+            Stage::Plots => write!(f, "plots"),
+        }
+    }
+}
+
+// Time spent per processing stage, when benchmarking.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct StageTimes {
+    times: [Duration; STAGES.len()],
+}
+
+impl StageTimes {
+    // ts comes from start(): None when not benchmarking
+    pub fn add(times: &mut Option<StageTimes>, stage: Stage, ts: Option<Instant>) {
+        if let (Some(times), Some(ts)) = (times, ts) {
+            times.times[stage as usize] += ts.elapsed();
+        }
+    }
+
+    pub fn start(times: &Option<StageTimes>) -> Option<Instant> {
+        times.is_some().then(Instant::now)
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.times[stage as usize]
+    }
+
+    pub fn merge(&mut self, other: &StageTimes) {
+        for (t, o) in self.times.iter_mut().zip(other.times) {
+            *t += o;
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+}
+
+// Processing time of a whole run vs the duration of the signal: the stages
+// run by the channels are summed over them, so they add up to cpu time and
+// can exceed the wall clock time.
+pub fn print_bench_report(
+    signal_sec: f64,
+    wall_sec: f64,
+    receiver_times: &StageTimes,
+    channel_times: &[(SV, StageTimes)],
+) {
+    let mut times = *receiver_times;
+    channel_times.iter().for_each(|(_sv, t)| times.merge(t));
+
+    println!(
+        "bench: {signal_sec:.3} sec of signal in {wall_sec:.3} sec: {}",
+        format!("{:.2}x real-time", signal_sec / wall_sec).bold()
+    );
+    for stage in STAGES {
+        let sec = times.get(stage).as_secs_f64();
+        println!(
+            "  {:<12} {:>9.3} sec {:>6.1}% {:>8.1} us/ms",
+            stage.to_string(),
+            sec,
+            100.0 * sec / wall_sec,
+            1e3 * sec / signal_sec
+        );
+    }
+
+    let mut channel_times = channel_times.to_vec();
+    channel_times.sort_by_key(|(_sv, t)| std::cmp::Reverse(t.total()));
+    println!("  per channel, sec: total acq / trk / nav / plots");
+    for (sv, t) in channel_times {
+        println!(
+            "  {sv}: {:>7.3} {:>7.3} / {:>7.3} / {:>7.3} / {:>7.3}",
+            t.total().as_secs_f64(),
+            t.get(Stage::Acquisition).as_secs_f64(),
+            t.get(Stage::Tracking).as_secs_f64(),
+            t.get(Stage::Nav).as_secs_f64(),
+            t.get(Stage::Plots).as_secs_f64(),
+        );
+    }
+}
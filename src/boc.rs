@@ -0,0 +1,78 @@
+use rustfft::num_complex::Complex64;
+use std::fmt;
+
+const JUMP_COUNT: usize = 5; // windows the prompt is outweighed in before a jump
+
+// Side of the prompt the code jumps to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Jump {
+    Early,
+    Late,
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Jump::Early => write!(f, "early"),
+            Jump::Late => write!(f, "late"),
+        }
+    }
+}
+
+// Bump-jump of a BOC(1,1) signal. Its correlation has, half a chip either
+// side of the main peak, side peaks half as high on which the early and late
+// correlators balance as they do on the main one: a code loop pulled onto one
+// stays there, half a chip off. The very early and very late correlators, a
+// side peak away from the prompt, sit on the side peaks while the main one is
+// tracked, but one of them is on the main peak when a side one is: outweighing
+// the prompt in window after window, it calls for a jump of the code to it.
+// An up/down count keeps a noisy window from either triggering or resetting it.
+#[derive(Default)]
+pub struct BumpJump {
+    sum_p: f64,
+    sum_ve: f64,
+    sum_vl: f64,
+    count_ve: usize,
+    count_vl: usize,
+}
+
+impl BumpJump {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // every code period
+    pub fn add_correlations(&mut self, c_p: Complex64, c_ve: Complex64, c_vl: Complex64) {
+        self.sum_p += c_p.norm();
+        self.sum_ve += c_ve.norm();
+        self.sum_vl += c_vl.norm();
+    }
+
+    // At the end of a window: the side to jump to, once the count of one of
+    // them reaches JUMP_COUNT. The counts start over after a jump.
+    pub fn update(&mut self) -> Option<Jump> {
+        let (p, ve, vl) = (self.sum_p, self.sum_ve, self.sum_vl);
+        (self.sum_p, self.sum_ve, self.sum_vl) = (0.0, 0.0, 0.0);
+
+        if ve > p && ve >= vl {
+            self.count_ve += 1;
+            self.count_vl = self.count_vl.saturating_sub(1);
+        } else if vl > p {
+            self.count_vl += 1;
+            self.count_ve = self.count_ve.saturating_sub(1);
+        } else {
+            self.count_ve = self.count_ve.saturating_sub(1);
+            self.count_vl = self.count_vl.saturating_sub(1);
+        }
+
+        let jump = if self.count_ve >= JUMP_COUNT {
+            Jump::Early
+        } else if self.count_vl >= JUMP_COUNT {
+            Jump::Late
+        } else {
+            return None;
+        };
+        (self.count_ve, self.count_vl) = (0, 0);
+        Some(jump)
+    }
+}
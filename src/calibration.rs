@@ -0,0 +1,94 @@
+use map_3d::{Ellipsoid, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::constants::SPEED_OF_LIGHT;
+use crate::ephemeris::Ephemeris;
+use crate::sky::get_sv_position_at;
+
+pub const CALIBRATION_PERIOD_SEC: f64 = 5.0; // of signal between two estimates
+const MIN_CALIBRATION_SVS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum CalibrationMethod {
+    Mean,      // satellite dopplers average out over the sky: ~1 ppm
+    Predicted, // measured against the ephemeris from the fix: ~0.01 ppm
+}
+
+impl fmt::Display for CalibrationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationMethod::Mean => write!(f, "mean doppler"),
+            CalibrationMethod::Predicted => write!(f, "predicted doppler"),
+        }
+    }
+}
+
+// Frequency error of the front-end oscillator as seen in the signal, i.e.
+// left after the correction applied at tuning time, if any.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PpmEstimate {
+    pub ppm: f64,
+    pub std_ppm: f64, // of the estimate
+    pub num_svs: usize,
+    pub method: CalibrationMethod,
+}
+
+impl fmt::Display for PpmEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:+.2} ppm ±{:.2} ({}, {} satellites)",
+            self.ppm, self.std_ppm, self.method, self.num_svs
+        )
+    }
+}
+
+// doppler of a satellite seen from a static receiver at pos: latitude,
+// longitude (degrees) and height (m), for a carrier at fc.
+pub fn get_predicted_doppler(eph: &Ephemeris, ts_sec: f64, pos: [f64; 3], fc: f64) -> f64 {
+    let (x, y, z) = geodetic2ecef(
+        pos[0].to_radians(),
+        pos[1].to_radians(),
+        pos[2],
+        Ellipsoid::WGS84,
+    );
+    let get_range = |sv_ecef: [f64; 3]| {
+        let [dx, dy, dz] = [sv_ecef[0] - x, sv_ecef[1] - y, sv_ecef[2] - z];
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    };
+    let range = get_range(get_sv_position_at(eph, ts_sec));
+    let range_next = get_range(get_sv_position_at(eph, ts_sec + 1.0));
+    -(range_next - range) * fc / SPEED_OF_LIGHT
+}
+
+// The oscillator error shifts all the dopplers by -ppm * fc: estimated from
+// the measured dopplers of the tracked satellites and their predicted ones
+// when all have one, their mean otherwise.
+pub fn estimate_ppm(dopplers: &[(f64, Option<f64>)], fc: f64) -> Option<PpmEstimate> {
+    let num_svs = dopplers.len();
+    if num_svs < MIN_CALIBRATION_SVS {
+        return None;
+    }
+    let has_predictions = dopplers.iter().all(|(_, predicted)| predicted.is_some());
+    let biases: Vec<_> = dopplers
+        .iter()
+        .map(|(measured, predicted)| match predicted {
+            Some(p) if has_predictions => measured - p,
+            _ => *measured,
+        })
+        .collect();
+    let mean = biases.iter().sum::<f64>() / num_svs as f64;
+    let var = biases.iter().map(|b| (b - mean).powi(2)).sum::<f64>() / (num_svs - 1) as f64;
+    let std_mean = (var / num_svs as f64).sqrt();
+    Some(PpmEstimate {
+        ppm: -mean / fc * 1e6,
+        std_ppm: std_mean / fc * 1e6,
+        num_svs,
+        method: if has_predictions {
+            CalibrationMethod::Predicted
+        } else {
+            CalibrationMethod::Mean
+        },
+    })
+}
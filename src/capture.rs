@@ -0,0 +1,291 @@
+use chrono::Local;
+use colored::Colorize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{SyncSender, TrySendError, sync_channel};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+
+use crate::recording::IQFileType;
+use crate::recording::IQRecording;
+use crate::recording::write_iq_data;
+use crate::sample::IQSample;
+use crate::sidecar::{IQSidecar, SIDECAR_EXT};
+use crate::state::RecordingStatus;
+
+pub const CAPTURE_FOLDER: &str = "captures";
+const RECORD_FILE_TYPE: IQFileType = IQFileType::TypePairInt16; // half the size of 2xf32
+const RECORD_CHUNK_SEC: f64 = 0.1; // of samples handed to the writer at once
+const RECORD_QUEUE_LEN: usize = 20; // chunks queued for the disk, then dropped
+
+pub struct CaptureFile {
+    pub path: PathBuf,
+    pub num_bytes: u64,
+    pub modified: Option<SystemTime>,
+}
+
+// the IQ files under CAPTURE_FOLDER, the latest first
+pub fn get_capture_list() -> Vec<CaptureFile> {
+    let Ok(entries) = std::fs::read_dir(CAPTURE_FOLDER) else {
+        return vec![];
+    };
+    let mut captures: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.path().to_string_lossy().ends_with(SIDECAR_EXT))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some(CaptureFile {
+                path: entry.path(),
+                num_bytes: metadata.len(),
+                modified: metadata.modified().ok(),
+            })
+        })
+        .collect();
+    captures.sort_by_key(|capture| std::cmp::Reverse(capture.modified));
+    captures
+}
+
+// Streams the raw IQ of a live source to disk from a separate thread, until
+// stopped: the sidecar is written once the file is complete. The queue to
+// the writer is bounded: when the disk falls behind, the samples are dropped
+// and counted rather than piling up in memory, the file then has gaps.
+pub struct IQRecorder {
+    sender: SyncSender<Vec<IQSample>>,
+    path: PathBuf,
+    file_type: IQFileType,
+    fs: f64,
+    chunk: Vec<IQSample>, // not queued yet
+    chunk_len: usize,
+    num_samples: usize,
+    num_dropped: usize,
+    writer_thread: JoinHandle<()>,
+}
+
+impl IQRecorder {
+    // under CAPTURE_FOLDER, named after the current time
+    pub fn start(fs: f64, fi: f64, sig: &str) -> std::io::Result<Self> {
+        let path = PathBuf::from(format!(
+            "{}/record-{}.bin",
+            CAPTURE_FOLDER,
+            Local::now().format("%Y%m%d-%H%M%S"),
+        ));
+        std::fs::create_dir_all(CAPTURE_FOLDER)?;
+        Self::create(&path, &RECORD_FILE_TYPE, fs, fi, sig)
+    }
+
+    pub fn create(
+        path: &Path,
+        file_type: &IQFileType,
+        fs: f64,
+        fi: f64,
+        sig: &str,
+    ) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let start_time = Some(Local::now().to_rfc3339());
+        let (sender, receiver) = sync_channel::<Vec<IQSample>>(RECORD_QUEUE_LEN);
+
+        tracing::info!(
+            "recording: writing to {}",
+            path.display().to_string().green()
+        );
+
+        let path_clone = path.to_path_buf();
+        let file_type_clone = file_type.clone();
+        let sig = sig.to_string();
+        let writer_thread = thread::spawn(move || {
+            let res = receiver
+                .iter()
+                .try_for_each(|iq_vec| write_iq_data(&mut writer, &iq_vec, &file_type_clone))
+                .and_then(|_| writer.into_inner().map_err(|e| e.into_error()))
+                .and_then(|_| {
+                    IQSidecar::new(&path_clone, &file_type_clone, fs, fi, &sig, start_time)
+                })
+                .and_then(|sidecar| sidecar.write(&path_clone));
+            match res {
+                Ok(()) => tracing::info!("recording: {} complete", path_clone.display()),
+                Err(e) => tracing::error!("failed to write {}: {e}", path_clone.display()),
+            }
+        });
+
+        let chunk_len = (RECORD_CHUNK_SEC * fs) as usize;
+        Ok(Self {
+            sender,
+            path: path.to_path_buf(),
+            file_type: file_type.clone(),
+            fs,
+            chunk: Vec::with_capacity(chunk_len),
+            chunk_len,
+            num_samples: 0,
+            num_dropped: 0,
+            writer_thread,
+        })
+    }
+
+    // waits for the samples pushed to be on disk, with their sidecar
+    pub fn finish(self) {
+        let _ = self.stop().join();
+    }
+
+    // the writer thread completes the file on its own
+    pub fn stop(self) -> JoinHandle<()> {
+        let Self {
+            sender,
+            path,
+            chunk,
+            num_dropped,
+            writer_thread,
+            ..
+        } = self;
+        if !chunk.is_empty() {
+            let _ = sender.send(chunk);
+        }
+        if num_dropped > 0 {
+            tracing::warn!(
+                "recording: {num_dropped} samples dropped in {}: the disk fell behind",
+                path.display()
+            );
+        }
+        writer_thread
+    }
+
+    pub fn push(&mut self, iq_vec: &[IQSample]) {
+        self.chunk.extend_from_slice(iq_vec);
+        self.num_samples += iq_vec.len();
+        if self.chunk.len() < self.chunk_len {
+            return;
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(self.chunk_len));
+        let len = chunk.len();
+        match self.sender.try_send(chunk) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                if self.num_dropped == 0 {
+                    tracing::warn!("recording: the disk falls behind, dropping samples");
+                }
+                self.num_samples -= len;
+                self.num_dropped += len;
+            }
+            Err(TrySendError::Disconnected(_)) => self.num_samples -= len,
+        }
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_duration_sec(&self) -> f64 {
+        self.num_samples as f64 / self.fs
+    }
+
+    pub fn get_num_bytes(&self) -> u64 {
+        (self.num_samples * IQRecording::get_sample_size_bytes(&self.file_type)) as u64
+    }
+
+    pub fn get_num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    pub fn get_status(&self) -> RecordingStatus {
+        RecordingStatus {
+            path: self.path.clone(),
+            duration_sec: self.get_duration_sec(),
+            num_bytes: self.get_num_bytes(),
+            num_dropped: self.num_dropped,
+        }
+    }
+
+    // the counters only, the path is the same
+    pub fn update_status(&self, status: &mut RecordingStatus) {
+        status.duration_sec = self.get_duration_sec();
+        status.num_bytes = self.get_num_bytes();
+        status.num_dropped = self.num_dropped;
+    }
+}
+
+// Keeps the last few seconds of raw IQ around so that the samples leading
+// to an interesting event (fix lost, interference, ..) can be saved to disk.
+pub struct IQRingBuffer {
+    samples: VecDeque<IQSample>,
+    capacity: usize,
+    fs: f64,
+    fi: f64,
+    sig: String,
+    file_type: IQFileType,
+}
+
+impl IQRingBuffer {
+    pub fn new(duration_sec: f64, fs: f64, fi: f64, sig: &str) -> Self {
+        let capacity = (duration_sec * fs) as usize;
+        tracing::info!(
+            "pre-trigger capture: keeping {:.1} sec of IQ ({} samples)",
+            duration_sec,
+            capacity
+        );
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            fs,
+            fi,
+            sig: sig.to_string(),
+            file_type: IQFileType::TypePairFloat32,
+        }
+    }
+
+    pub fn push(&mut self, iq_vec: &[IQSample]) {
+        self.samples.extend(iq_vec.iter());
+        if self.samples.len() > self.capacity {
+            let n = self.samples.len() - self.capacity;
+            let _ = self.samples.drain(0..n);
+        }
+    }
+
+    pub fn duration_sec(&self) -> f64 {
+        self.samples.len() as f64 / self.fs
+    }
+
+    // the file is written from a separate thread so that the sample path is not stalled.
+    pub fn dump(&self, reason: &str) -> PathBuf {
+        let file_name = format!(
+            "{}/capture-{}-{}.bin",
+            CAPTURE_FOLDER,
+            Local::now().format("%Y%m%d-%H%M%S"),
+            reason
+        );
+        let path = PathBuf::from(file_name);
+        let samples: Vec<_> = self.samples.iter().copied().collect();
+        let file_type = self.file_type.clone();
+        let (fs, fi, sig) = (self.fs, self.fi, self.sig.clone());
+        let start_time =
+            Local::now() - chrono::Duration::milliseconds((self.duration_sec() * 1000.0) as i64);
+
+        tracing::info!(
+            "pre-trigger capture: {} -- dumping {:.1} sec to {}",
+            reason.yellow(),
+            self.duration_sec(),
+            path.display()
+        );
+
+        let path_clone = path.clone();
+        thread::spawn(move || {
+            let res = std::fs::create_dir_all(CAPTURE_FOLDER)
+                .and_then(|_| File::create(&path_clone))
+                .and_then(|file| {
+                    let mut writer = BufWriter::new(file);
+                    write_iq_data(&mut writer, &samples, &file_type)
+                })
+                .and_then(|_| {
+                    let start_time = Some(start_time.to_rfc3339());
+                    IQSidecar::new(&path_clone, &file_type, fs, fi, &sig, start_time)
+                })
+                .and_then(|sidecar| sidecar.write(&path_clone));
+            if let Err(e) = res {
+                tracing::error!("failed to write {}: {e}", path_clone.display());
+            }
+        });
+
+        path
+    }
+}
@@ -1,21 +1,29 @@
 use colored::Colorize;
+use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
-use plotters::prelude::*;
-use rustfft::FftPlanner;
 use rustfft::num_complex::Complex64;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
 
 const PI: f64 = std::f64::consts::PI;
 
-use crate::code::Code;
+use crate::bench::{Stage, StageTimes};
+use crate::boc::{BumpJump, Jump};
+use crate::code::{Code, CodeTables};
+use crate::constants::SPEED_OF_LIGHT;
 use crate::navigation::Navigation;
-use crate::plots::plot_iq_scatter;
-use crate::plots::plot_time_graph;
-use crate::plots::plot_time_graph_with_sz;
-use crate::state::ChannelState;
+use crate::plots::PlotJob;
+use crate::quality::{QualityMonitor, SignalQuality};
+use crate::sample::{Float, IQSample, to_complex64};
 use crate::state::GnssState;
-use crate::util::calc_correlation;
+use crate::state::{AcquisitionGrid, ChannelKey, ChannelState};
+use crate::ttff::Milestone;
+use crate::util::calc_correlation_in_place;
 use crate::util::doppler_shift;
 use crate::util::get_max_with_idx;
 
@@ -26,27 +34,79 @@ const T_FPULLIN: f64 = 1.0;
 const T_NPULLIN: f64 = 1.5; // navigation data pullin time (s)
 const T_DLL: f64 = 0.01; // non-coherent integration time for DLL
 const T_CN0: f64 = 1.0; // averaging time for C/N0
+const T_ARRAY: f64 = 0.2; // averaging time for the antenna gains
+const T_LOCK: f64 = 0.1; // averaging time for the pll lock indicator
+const T_DETAILS: f64 = 0.5; // loop and navigation status published to the UI
+const T_QUALITY: f64 = 1.0; // between two updates of the multipath indicators
+const T_QPULLIN: f64 = 5.0; // code loop settled for the multipath indicators
+const T_BUMP: f64 = 0.02; // window of the bump-jump of BOC signals
 const B_FLL_WIDE: f64 = 10.0; // bandwidth of FLL wide Hz
 const B_FLL_NARROW: f64 = 2.0; // bandwidth of FLL narrow Hz
 const B_PLL: f64 = 10.0; // bandwidth of PLL filter Hz
 const B_DLL: f64 = 0.5; // bandwidth of DLL filter Hz
 
 const DOPPLER_SPREAD_HZ: f64 = 8000.0;
+// with a disciplined clock only the satellite motion contributes to doppler
+const DOPPLER_SPREAD_DISCIPLINED_HZ: f64 = 5000.0;
+const B_FLL_NARROW_DISCIPLINED: f64 = 1.0;
+const B_PLL_DISCIPLINED: f64 = 5.0;
 const DOPPLER_SPREAD_BINS: usize = 50;
 const HISTORY_NUM: usize = 20000;
+const LIVE_PLOT_SEC: f64 = 0.2; // how often the UI plots are refreshed
+pub const PLOT_PERIOD_SEC: f64 = 2.0; // of signal between two rounds of charts
+const LIVE_PLOT_NUM: usize = 2000; // points shown by the UI plots
+const LIVE_ACQ_CODE_BINS: usize = 256; // code phase resolution of the UI heatmap
 const CN0_THRESHOLD_LOCKED: f64 = 35.0;
 const CN0_THRESHOLD_LOST: f64 = 29.0;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum State {
     Tracking,
     Acquisition,
     Idle,
 }
 
+// Which satellites get searched first when cpu is short: only the high
+// priority ones while lagging, the low priority ones when no other is.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum SvPriority {
+    High,
+    Normal,
+    Low,
+}
+
+// What steers the carrier: the fll pulls in, then the pll takes over.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum LoopStage {
+    #[default]
+    FllWide,
+    FllNarrow,
+    Pll,
+}
+
+impl fmt::Display for LoopStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoopStage::FllWide => write!(f, "fll wide"),
+            LoopStage::FllNarrow => write!(f, "fll narrow"),
+            LoopStage::Pll => write!(f, "pll"),
+        }
+    }
+}
+
+impl fmt::Display for SvPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SvPriority::High => write!(f, "high"),
+            SvPriority::Normal => write!(f, "normal"),
+            SvPriority::Low => write!(f, "low"),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Tracking {
-    prn_code: Vec<Complex64>, // upsampled
+    prn_code: Vec<IQSample>, // upsampled
     doppler_hz: f64,
     code_off_sec: f64,
     cn0: f64,
@@ -57,12 +117,22 @@ pub struct Tracking {
     sum_corr_l: f64,
     sum_corr_p: f64,
     sum_corr_n: f64,
+    array_cross: Vec<Complex64>, // prompt of each antenna times the reference's
+    array_power: f64,            // of the reference antenna prompt
+    pll_lock: f64,               // cos(2 * phase error): 1 when locked
+    dll_sum_sec: f64,            // code loop corrections: the code minus carrier
+    quality: QualityMonitor,
+    bump_jump: Option<BumpJump>,   // BOC signals only
+    secondary_code: Vec<i8>,       // over the code periods, empty if none
+    secondary_sync: Option<isize>, // num_trk_samples at a start of it
 }
 
 #[derive(Default)]
 pub struct History {
     last_log_ts: f64,
     last_plot_ts: f64,
+    final_plot_ts: Option<f64>, // charts handed over at the end of a run
+    last_details_ts: f64,
     code_phase_offset: Vec<f64>,
     phi_error: Vec<f64>,
     doppler_hz: Vec<f64>,
@@ -90,40 +160,87 @@ impl History {
     }
 }
 
+// Correlators of a code period: the prompt, the early and late ones, the
+// neutral one far off the peak for the noise floor, and for BOC signals the
+// very early and very late ones, a side peak away from the prompt.
+#[derive(Clone, Copy, Default)]
+struct Correlations {
+    p: Complex64,
+    e: Complex64,
+    l: Complex64,
+    n: Complex64,
+    ve: Complex64,
+    vl: Complex64,
+}
+
 #[derive(Default)]
 pub struct Acquisition {
-    prn_code_fft: Vec<Complex64>,
-    sum_p: Vec<Vec<f64>>,
+    prn_code_fft: Vec<IQSample>, // over the code periods integrated coherently
+    sum_p: Vec<Vec<f64>>,        // code periods folded onto one
+    p_total: f64,                // of the cells before folding, for the noise floor
+    block: Vec<IQSample>,        // the code periods gathered for the next integration
 }
 
 pub struct Channel {
     pub pub_state: Arc<Mutex<GnssState>>,
     pub sv: SV,
-    fc: f64, // carrier frequency
-    fs: f64, // sampling frequency
-    fi: f64, // intermediate frequency
-
-    code_sec: f64,   // code duration in sec
-    code_len: usize, // prn code len: e.g. 1023
-    code_sp: usize,  // samples per upsampled code: e.g. 2046 for L1CA
-
-    fft_planner: FftPlanner<f64>,
+    pub sig: String,
+    fc: f64,                // carrier frequency
+    fs: f64,                // sampling frequency
+    fi: f64,                // intermediate frequency
+    doppler_center_hz: f64, // center of the acquisition doppler search
+    doppler_spread_hz: f64,
+    doppler_bins: usize,              // of a full search, finer with longer codes
+    doppler_hint: Option<(f64, f64)>, // expected doppler and its uncertainty
+    b_fll_narrow: f64,
+    b_pll: f64,
+    pub(crate) freq_channel: Option<i8>, // FDMA signals only
+    pub(crate) key: ChannelKey,          // of its state in pub_state
+
+    code_sec: f64,      // code duration in sec
+    code_len: usize,    // prn code len: e.g. 1023
+    code_sp: usize,     // samples per upsampled code: e.g. 2046 for L1CA
+    code_msec: usize,   // receiver periods per code: e.g. 4 for E1
+    acq_periods: usize, // code periods integrated coherently by the acquisition
+
+    // codes longer than a receiver period: the samples not processed yet, one
+    // buffer per antenna, from pending_ts. The samples to drop first for the
+    // next window to start on a code period.
+    pending: Vec<Vec<IQSample>>,
+    pending_ts: Option<f64>,
+    pending_skip: usize,
+
+    fft_fw: Arc<dyn Fft<Float>>, // plans shared with the other channels
+    fft_bw: Arc<dyn Fft<Float>>,
+    scratch: Vec<IQSample>,     // one code period, reused every ms
+    fft_scratch: Vec<IQSample>, // for the in-place FFTs
     state: State,
+    enabled: bool,
+    priority: SvPriority,
 
     pub ts_sec: f64, // current time
     pub num_trk_samples: usize,
     num_acq_samples: usize,
     num_idl_samples: usize,
+    num_acq_attempts: usize, // over the run, for its charts
+    num_acq_locks: usize,
 
     pub hist: History,
     pub nav: Navigation,
     trk: Tracking,
     acq: Acquisition,
+    bench: Option<StageTimes>, // only when benchmarking
+    plot_tx: Option<SyncSender<PlotJob>>,
+    plot_period_sec: Option<f64>, // of signal, none: no charts
+    live_plots: bool,             // drawn by the UI instead of written as png files
+    span: tracing::Span,          // what the channel logs is recorded in, for per-SV filtering
 }
 
 impl Drop for Channel {
     fn drop(&mut self) {
-        self.update_all_plots(true);
+        if self.hist.final_plot_ts != Some(self.ts_sec) {
+            self.update_all_plots(true);
+        }
     }
 }
 
@@ -141,11 +258,12 @@ impl Channel {
     }
 
     pub fn is_ephemeris_complete(&self) -> bool {
-        self.nav.eph.ts_sec != 0.0
-            && self.nav.eph.week != 0
-            && self.nav.eph.toe != 0
-            && self.nav.eph.i0 != 0.0
-            && self.nav.eph.a >= 20_000_000.0
+        let eph = &self.nav.eph;
+        let orbit = match eph.sv.constellation {
+            Constellation::Glonass => eph.pos.iter().any(|&p| p != 0.0),
+            _ => eph.i0 != 0.0 && eph.a >= 20_000_000.0,
+        };
+        eph.ts_sec != 0.0 && eph.week != 0 && eph.toe != 0 && orbit
     }
 
     fn set_state(&mut self, state: State) {
@@ -154,7 +272,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .state
             .clone();
@@ -163,7 +281,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .state = state.clone();
 
@@ -182,7 +300,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .state
             .clone();
@@ -191,7 +309,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .phi = self.trk.phi;
 
@@ -206,7 +324,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .state
             .clone();
@@ -215,7 +333,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .code_idx = *self.hist.code_phase_offset.last().unwrap();
 
@@ -229,7 +347,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .state
             .clone();
@@ -238,7 +356,7 @@ impl Channel {
             .lock()
             .unwrap()
             .channels
-            .get_mut(&self.sv)
+            .get_mut(&self.key)
             .unwrap()
             .doppler_hz = self.trk.doppler_hz;
 
@@ -246,74 +364,124 @@ impl Channel {
             (self.pub_state.lock().unwrap().update_func.func)();
         }
     }
+    pub fn record_milestone(&self, milestone: Milestone) {
+        let first =
+            (self.pub_state.lock().unwrap().ttff).record(milestone, self.ts_sec, Some(self.sv));
+        if first {
+            tracing::info!("t={:.3} -- {}: {milestone}", self.ts_sec, self.sv);
+        }
+    }
+
     fn update_state_cn0(&mut self) {
         let need_update = {
             let mut st = self.pub_state.lock().unwrap();
-            st.channels.get_mut(&self.sv).unwrap().cn0 = self.trk.cn0;
-            st.channels.get(&self.sv).unwrap().state == State::Tracking
+            st.channels.get_mut(&self.key).unwrap().cn0 = self.trk.cn0;
+            st.channels.get(&self.key).unwrap().state == State::Tracking
         };
         if need_update {
             (self.pub_state.lock().unwrap().update_func.func)();
         }
     }
 
-    pub fn new(sig: &str, sv: SV, fs: f64, fi: f64, pub_state: Arc<Mutex<GnssState>>) -> Self {
-        let code_buf = Code::gen_code(sig, sv.prn).unwrap();
+    pub fn new(
+        sig: &str,
+        sv: SV,
+        codes: &CodeTables,
+        fs: f64,
+        fi: f64,
+        pub_state: Arc<Mutex<GnssState>>,
+        fft_planner: &mut FftPlanner<Float>,
+    ) -> Self {
+        let code_buf = codes.gen_sampled_code(sig, sv.prn, fs).unwrap();
         let code_sec = Code::get_code_period(sig);
         let code_len = Code::get_code_len(sig);
-        let code_sp = (fs * code_sec) as usize;
-        let mut fft_planner = FftPlanner::new();
+        let code_sp = Code::get_code_sp(sig, fs);
+        // FDMA: each satellite on its own carrier, off the tuning frequency
+        let freq_channel = codes.get_freq_channel(sig, sv.prn);
+        let carrier_offset = codes.get_carrier_offset(sig, sv.prn);
+        let doppler_bins = DOPPLER_SPREAD_BINS * Code::get_code_msec(sig);
 
         let prn_code: Vec<_> = code_buf
             .iter()
-            .map(|&x| Complex64::new(x as f64, 0.0))
-            .flat_map(|x| [x, x])
+            .map(|&x| IQSample::new(x as Float, 0.0))
             .collect();
 
         let mut prn_code_fft = prn_code.clone();
 
         let fft_fw = fft_planner.plan_fft_forward(prn_code_fft.len());
+        let fft_bw = fft_planner.plan_fft_inverse(prn_code_fft.len());
         fft_fw.process(&mut prn_code_fft);
 
-        pub_state
-            .lock()
-            .unwrap()
-            .channels
-            .insert(sv, ChannelState::default());
+        let key = (sv, sig.to_string());
+        let live_plots = {
+            let mut pub_state = pub_state.lock().unwrap();
+            pub_state
+                .channels
+                .insert(key.clone(), ChannelState::default());
+            pub_state.live_plots
+        };
 
         Self {
             pub_state: pub_state.clone(),
             sv,
-            fft_planner,
+            sig: sig.to_string(),
+            key,
+            freq_channel,
+            fft_fw,
+            fft_bw,
+            scratch: Vec::with_capacity(code_sp),
+            fft_scratch: Vec::new(),
             ts_sec: 0.0,
-            fc: Code::get_code_freq(sig),
+            fc: Code::get_code_freq(sig) + carrier_offset,
             fs,
-            fi,
+            fi: fi + carrier_offset,
+            doppler_center_hz: 0.0,
+            doppler_spread_hz: DOPPLER_SPREAD_HZ,
+            doppler_bins,
+            doppler_hint: None,
+            b_fll_narrow: B_FLL_NARROW,
+            b_pll: B_PLL,
             code_sec,
             code_len,
             code_sp,
+            code_msec: Code::get_code_msec(sig),
+            acq_periods: 1,
+            pending: vec![],
+            pending_ts: None,
+            pending_skip: 0,
 
             num_acq_samples: 0,
             num_idl_samples: 0,
             num_trk_samples: 0,
+            num_acq_attempts: 0,
+            num_acq_locks: 0,
 
             state: State::Acquisition,
+            enabled: true,
+            priority: SvPriority::Normal,
+            bench: None,
+            plot_tx: None,
+            plot_period_sec: Some(PLOT_PERIOD_SEC),
+            live_plots,
+            span: tracing::info_span!("channel", sv = %sv),
             nav: Navigation::new(sv),
             hist: History::default(),
             trk: Tracking {
                 prn_code,
+                secondary_code: Code::get_secondary_code(sig, sv.prn),
                 ..Default::default()
             },
             acq: Acquisition {
                 prn_code_fft,
-                sum_p: vec![vec![0.0; code_sp]; DOPPLER_SPREAD_BINS],
+                sum_p: vec![vec![0.0; code_sp]; doppler_bins],
+                ..Default::default()
             },
         }
     }
 
     fn idle_start(&mut self) {
         if self.state == State::Tracking {
-            log::warn!(
+            tracing::warn!(
                 "{}: {} cn0={:.1} ts_sec={:.3}",
                 self.sv,
                 "LOST".red(),
@@ -321,7 +489,7 @@ impl Channel {
                 self.ts_sec,
             );
         } else {
-            log::info!(
+            tracing::info!(
                 "{}: IDLE cn0={:.1} ts_sec={:.3}",
                 self.sv,
                 self.trk.cn0,
@@ -343,7 +511,9 @@ impl Channel {
     }
 
     fn acquisition_init(&mut self) {
-        self.acq.sum_p = vec![vec![0.0; self.code_sp]; DOPPLER_SPREAD_BINS];
+        self.acq.sum_p = vec![vec![0.0; self.code_sp]; self.acquisition_doppler_bins()];
+        self.acq.p_total = 0.0;
+        self.acq.block.clear();
         self.num_acq_samples = 0;
         self.num_idl_samples = 0;
         self.num_trk_samples = 0;
@@ -354,6 +524,140 @@ impl Channel {
         self.set_state(State::Acquisition);
     }
 
+    // e.g. to compensate for the frequency offset of the front-end oscillator
+    pub fn set_doppler_center(&mut self, doppler_center_hz: f64) {
+        self.doppler_center_hz = doppler_center_hz;
+    }
+
+    // the front-end runs off a stable reference (GPSDO, ..): narrow the
+    // doppler search and the tracking loops.
+    pub fn set_disciplined_clock(&mut self) {
+        self.doppler_spread_hz = DOPPLER_SPREAD_DISCIPLINED_HZ;
+        self.b_fll_narrow = B_FLL_NARROW_DISCIPLINED;
+        self.b_pll = B_PLL_DISCIPLINED;
+    }
+
+    // narrows the next acquisition around doppler_hz, e.g. predicted from the
+    // almanac or remembered from a previous run.
+    // Acquisition over periods code periods at once, correlated coherently
+    // with the code and its secondary code, instead of their powers added up
+    // period by period: the doppler bins get as many times finer. A secondary
+    // code needs to fit whole in the periods, the correlation finds its phase
+    // along with that of the code; the power of the periods is folded onto
+    // one, keeping the strongest.
+    pub fn set_acq_coherent_periods(
+        &mut self,
+        periods: usize,
+        fft_planner: &mut FftPlanner<Float>,
+    ) {
+        let secondary = &self.trk.secondary_code;
+        let mut code: Vec<_> = (0..periods)
+            .flat_map(|k| {
+                let chip = match secondary.len() {
+                    0 => 1,
+                    len => secondary[k % len],
+                };
+                self.trk.prn_code.iter().map(move |&c| c * chip as Float)
+            })
+            .collect();
+        self.fft_fw = fft_planner.plan_fft_forward(code.len());
+        self.fft_bw = fft_planner.plan_fft_inverse(code.len());
+        self.fft_fw.process(&mut code);
+        self.acq.prn_code_fft = code;
+        self.acq_periods = periods;
+        self.doppler_bins = DOPPLER_SPREAD_BINS * self.code_msec * periods;
+        self.acquisition_init();
+    }
+
+    pub fn get_acq_coherent_periods(&self) -> usize {
+        self.acq_periods
+    }
+
+    pub fn set_doppler_hint(&mut self, doppler_hz: f64, spread_hz: f64) {
+        self.doppler_hint = Some((doppler_hz, spread_hz));
+    }
+
+    // acquisition attempts and locks since the start
+    pub fn get_acquisition_counts(&self) -> (usize, usize) {
+        (self.num_acq_attempts, self.num_acq_locks)
+    }
+
+    // code phase and cn0 of a tracked satellite
+    pub fn get_code_off_sec(&self) -> Option<(f64, f64)> {
+        if self.state != State::Tracking {
+            return None;
+        }
+        Some((self.trk.code_off_sec, self.trk.cn0))
+    }
+
+    // relative to the center of the doppler search, as the hints
+    pub fn get_doppler_hz(&self) -> Option<f64> {
+        if self.state != State::Tracking {
+            return None;
+        }
+        Some(self.trk.doppler_hz - self.doppler_center_hz)
+    }
+
+    // as tracked: satellite motion plus the front-end oscillator error
+    pub fn get_measured_doppler_hz(&self) -> Option<f64> {
+        if self.state != State::Tracking {
+            return None;
+        }
+        Some(self.trk.doppler_hz)
+    }
+
+    // A disabled channel drops its satellite and isn't processed anymore
+    // until enabled again, when it starts over with an acquisition.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        tracing::info!(
+            "{}: {}",
+            self.sv,
+            if enabled { "enabled" } else { "disabled" }
+        );
+        if enabled {
+            self.acquisition_start();
+        } else {
+            self.idle_start();
+        }
+        self.enabled = enabled;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.channels.get_mut(&self.key).unwrap().enabled = enabled;
+        (pub_state.update_func.func)();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_priority(&mut self, priority: SvPriority) {
+        if priority == self.priority {
+            return;
+        }
+        tracing::info!("{}: {priority} priority", self.sv);
+        self.priority = priority;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.channels.get_mut(&self.key).unwrap().priority = priority;
+        (pub_state.update_func.func)();
+    }
+
+    pub fn get_priority(&self) -> SvPriority {
+        self.priority
+    }
+
+    pub fn restart_acquisition(&mut self) {
+        if self.state == State::Tracking {
+            tracing::info!("{}: restarting acquisition", self.sv);
+        }
+        self.nav.init();
+        self.pending.clear();
+        self.pending_ts = None;
+        self.pending_skip = 0;
+        self.acquisition_start();
+    }
+
     fn tracking_init(&mut self) {
         self.trk.doppler_hz = 0.0;
         self.trk.cn0 = 0.0;
@@ -364,6 +668,13 @@ impl Channel {
         self.trk.sum_corr_e = 0.0;
         self.trk.sum_corr_l = 0.0;
         self.trk.sum_corr_n = 0.0;
+        self.trk.array_cross.clear();
+        self.trk.array_power = 0.0;
+        self.trk.pll_lock = 0.0;
+        self.trk.dll_sum_sec = 0.0;
+        self.trk.quality = QualityMonitor::new();
+        self.trk.bump_jump = Code::is_boc(&self.sig).then(BumpJump::new);
+        self.trk.secondary_sync = None;
         self.num_trk_samples = 0;
         self.num_acq_samples = 0;
         self.num_idl_samples = 0;
@@ -378,7 +689,7 @@ impl Channel {
         code_off_sec: f64,
         code_offset_idx: usize,
     ) {
-        log::warn!(
+        tracing::info!(
             "{}: {} cn0={cn0:.1} dopp={doppler_hz:5.0} code_off={code_offset_idx:4} ts_sec={:.3}",
             self.sv,
             "LOCK".green(),
@@ -386,6 +697,7 @@ impl Channel {
         );
         self.tracking_init();
         self.set_state(State::Tracking);
+        self.record_milestone(Milestone::Acquisition);
 
         self.trk.code_off_sec = code_off_sec;
         self.trk.doppler_hz = doppler_hz;
@@ -394,134 +706,313 @@ impl Channel {
         self.update_state_cn0();
     }
 
-    fn acquisition_integrate_correlation(
-        &mut self,
-        iq_vec_slice: &[Complex64],
-        doppler_hz: f64,
-    ) -> Vec<f64> {
-        let mut iq_vec = iq_vec_slice.to_vec();
+    // center and half-width of the doppler search
+    fn acquisition_doppler_window(&self) -> (f64, f64) {
+        match self.doppler_hint {
+            Some((doppler_hz, spread_hz)) => (
+                self.doppler_center_hz + doppler_hz,
+                f64::min(spread_hz, self.doppler_spread_hz),
+            ),
+            None => (self.doppler_center_hz, self.doppler_spread_hz),
+        }
+    }
+
+    // Bins of the next search: as narrow as those of a full search, the code
+    // period integrated coherently, and never fewer than DOPPLER_SPREAD_BINS,
+    // e.g. a narrow hint for a 20 msec code is searched over a few dozen bins
+    // instead of the thousand of a full search.
+    fn acquisition_doppler_bins(&self) -> usize {
+        let step_hz = 2.0 * DOPPLER_SPREAD_HZ / self.doppler_bins as f64;
+        let bins = (2.0 * self.acquisition_doppler_window().1 / step_hz).ceil() as usize;
+        bins.clamp(DOPPLER_SPREAD_BINS, self.doppler_bins)
+    }
 
-        assert_eq!(iq_vec.len(), self.acq.prn_code_fft.len());
+    fn acquisition_doppler_step_hz(&self) -> f64 {
+        2.0 * self.acquisition_doppler_window().1 / self.acq.sum_p.len() as f64
+    }
 
-        doppler_shift(&mut iq_vec, self.fi + doppler_hz, 0.0, self.fs);
+    fn acquisition_doppler_hz(&self, bin: usize) -> f64 {
+        let (center_hz, spread_hz) = self.acquisition_doppler_window();
+        center_hz - spread_hz + bin as f64 * self.acquisition_doppler_step_hz()
+    }
 
-        let corr = calc_correlation(&mut self.fft_planner, &iq_vec, &self.acq.prn_code_fft);
-        let corr_vec: Vec<_> = corr.iter().map(|v| v.norm_sqr()).collect();
+    fn acquisition_integrate_correlation(&mut self, iq_vec_slice: &[IQSample], bin: usize) {
+        let doppler_hz = self.fi + self.acquisition_doppler_hz(bin);
+        let corr = &mut self.scratch;
+        corr.clear();
+        corr.extend_from_slice(iq_vec_slice);
+
+        assert_eq!(corr.len(), self.acq.prn_code_fft.len());
+
+        doppler_shift(corr, doppler_hz, 0.0, self.fs);
+        calc_correlation_in_place(
+            self.fft_fw.as_ref(),
+            self.fft_bw.as_ref(),
+            corr,
+            &self.acq.prn_code_fft,
+            &mut self.fft_scratch,
+        );
 
-        corr_vec
+        let code_sp = self.code_sp;
+        let periods = corr.len() / code_sp;
+        let mut p_total = 0.0;
+        for (j, p) in self.acq.sum_p[bin].iter_mut().enumerate() {
+            let mut p_max: f64 = 0.0;
+            for k in 0..periods {
+                let v = to_complex64(corr[k * code_sp + j]).norm_sqr();
+                p_total += v;
+                p_max = p_max.max(v);
+            }
+            *p += p_max;
+        }
+        self.acq.p_total += p_total / periods as f64;
     }
 
     fn update_all_plots(&mut self, force: bool) {
-        if !force && self.ts_sec - self.hist.last_plot_ts <= 2.0 {
+        if self.live_plots {
+            self.update_live_plot();
+            return;
+        }
+        let Some(period_sec) = self.plot_period_sec else {
+            return;
+        };
+        if !force && self.ts_sec - self.hist.last_plot_ts <= period_sec {
             return;
         }
 
-        self.plot_iq_scatter();
-        self.plot_code_phase_offset();
-        self.plot_phi_error();
-        self.plot_doppler_hz();
-        self.plot_nav_msg();
+        let job = self.get_plot_job();
+        match &self.plot_tx {
+            // a busy plot worker skips a round rather than holding us up
+            Some(plot_tx) if !force => {
+                let _ = plot_tx.try_send(job);
+            }
+            Some(plot_tx) => {
+                if let Err(mpsc::SendError(job)) = plot_tx.send(job) {
+                    job.draw();
+                }
+            }
+            None => job.draw(),
+        }
 
         self.hist.last_plot_ts = self.ts_sec;
     }
 
-    fn plot_nav_msg(&self) {
-        let v_re: Vec<_> = self.hist.corr_p.iter().map(|c| c.re).collect();
-        plot_time_graph_with_sz(self.sv, "nav-msg", v_re.as_slice(), 0.001, &BLACK, 400, 200);
+    fn get_plot_job(&self) -> PlotJob {
+        PlotJob {
+            sv: self.sv,
+            corr_p: self.hist.corr_p.clone(),
+            code_phase_offset: self.hist.code_phase_offset.clone(),
+            phi_error: self.hist.phi_error.clone(),
+            doppler_hz: self.hist.doppler_hz.clone(),
+        }
     }
 
-    fn plot_code_phase_offset(&self) {
-        plot_time_graph(
-            self.sv,
-            "code-phase-offset",
-            self.hist.code_phase_offset.as_slice(),
-            50.0,
-            &BLUE,
-        );
+    // The charts of the end of a run, to be drawn elsewhere: not drawn again
+    // when the channel drops, unless it tracked further.
+    pub fn take_final_plot(&mut self) -> Option<PlotJob> {
+        if self.live_plots || self.plot_period_sec.is_none() {
+            return None;
+        }
+        self.hist.final_plot_ts = Some(self.ts_sec);
+        Some(self.get_plot_job())
     }
 
-    fn plot_phi_error(&self) {
-        plot_time_graph(
-            self.sv,
-            "phi-error",
-            self.hist.phi_error.as_slice(),
-            0.5,
-            &BLACK,
-        );
+    // The latest histories of the channel selected in the UI, published a few
+    // times per second for it to draw.
+    fn update_live_plot(&mut self) {
+        if self.ts_sec - self.hist.last_plot_ts < LIVE_PLOT_SEC {
+            return;
+        }
+        self.hist.last_plot_ts = self.ts_sec;
+
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if pub_state.plot_sv != Some(self.sv) {
+            return;
+        }
+        fn tail<T: Clone>(v: &[T]) -> Vec<T> {
+            v[v.len().saturating_sub(LIVE_PLOT_NUM)..].to_vec()
+        }
+        pub_state.plot = Some(PlotJob {
+            sv: self.sv,
+            corr_p: tail(&self.hist.corr_p),
+            code_phase_offset: tail(&self.hist.code_phase_offset),
+            phi_error: tail(&self.hist.phi_error),
+            doppler_hz: tail(&self.hist.doppler_hz),
+        });
+        (pub_state.update_func.func)();
     }
 
-    fn plot_doppler_hz(&self) {
-        plot_time_graph(
-            self.sv,
-            "doppler-hz",
-            self.hist.doppler_hz.as_slice(),
-            10.0,
-            &BLACK,
-        );
+    // charts handed to a plot worker instead of drawn here
+    pub fn set_plot_sender(&mut self, plot_tx: Option<SyncSender<PlotJob>>) {
+        self.plot_tx = plot_tx;
     }
 
-    fn plot_iq_scatter(&self) {
-        let len = self.hist.corr_p.len();
-        let n = usize::min(len, 2000);
-        plot_iq_scatter(self.sv, &self.hist.corr_p[len - n..len]);
+    pub fn set_plot_period(&mut self, period_sec: Option<f64>) {
+        self.plot_period_sec = period_sec;
     }
 
-    fn acquisition_process(&mut self, iq_vec: &[Complex64]) {
-        // only take the last minute worth of data
+    pub fn is_acquiring(&self) -> bool {
+        self.state == State::Acquisition
+    }
+
+    // receiver periods per code period
+    pub fn get_code_msec(&self) -> usize {
+        self.code_msec
+    }
+
+    pub fn get_prn_code_fft(&self) -> &[IQSample] {
+        &self.acq.prn_code_fft
+    }
+
+    // The last code period shifted by each doppler bin, back to back: what
+    // acquisition correlates against the prn code when the FFTs are done
+    // elsewhere, e.g. batched on a GPU.
+    pub fn get_acquisition_inputs(&self, iq_vec: &[IQSample]) -> Vec<IQSample> {
         let iq_vec_slice = &iq_vec[self.code_sp..];
-        let step_hz = 2.0 * DOPPLER_SPREAD_HZ / DOPPLER_SPREAD_BINS as f64;
+        let mut inputs = Vec::with_capacity(self.acq.sum_p.len() * self.code_sp);
 
-        for i in 0..DOPPLER_SPREAD_BINS {
-            let doppler_hz = -DOPPLER_SPREAD_HZ + i as f64 * step_hz;
-            let c_non_coherent = self.acquisition_integrate_correlation(iq_vec_slice, doppler_hz);
-            assert_eq!(c_non_coherent.len(), self.code_sp);
+        for i in 0..self.acq.sum_p.len() {
+            let off = inputs.len();
+            inputs.extend_from_slice(iq_vec_slice);
+            let doppler_hz = self.fi + self.acquisition_doppler_hz(i);
+            doppler_shift(&mut inputs[off..], doppler_hz, 0.0, self.fs);
+        }
+        inputs
+    }
 
-            #[allow(clippy::needless_range_loop)]
-            for j in 0..self.code_sp {
-                self.acq.sum_p[i][j] += c_non_coherent[j];
+    // correlations of get_acquisition_inputs() with the prn code
+    pub fn process_acquisition_corr(&mut self, corr: &[IQSample], ts_sec: f64) {
+        assert_eq!(corr.len(), self.acq.sum_p.len() * self.code_sp);
+        self.ts_sec = ts_sec;
+
+        for (sum_p, c) in self.acq.sum_p.iter_mut().zip(corr.chunks(self.code_sp)) {
+            for (p, &v) in sum_p.iter_mut().zip(c.iter()) {
+                let v = to_complex64(v).norm_sqr();
+                *p += v;
+                self.acq.p_total += v;
             }
         }
+        self.acquisition_check();
+    }
 
-        self.num_acq_samples += 1;
+    fn acquisition_process(&mut self, iq_vec: &[IQSample]) {
+        // only take the last minute worth of data
+        let iq_vec_slice = &iq_vec[self.code_sp..];
+        if self.acq_periods == 1 {
+            for i in 0..self.acq.sum_p.len() {
+                self.acquisition_integrate_correlation(iq_vec_slice, i);
+            }
+            return self.acquisition_check();
+        }
+        let mut block = std::mem::take(&mut self.acq.block);
+        block.extend_from_slice(iq_vec_slice);
+        if block.len() == self.acq.prn_code_fft.len() {
+            for i in 0..self.acq.sum_p.len() {
+                self.acquisition_integrate_correlation(&block, i);
+            }
+            block.clear();
+        }
+        self.acq.block = block;
+        self.acquisition_check();
+    }
 
-        if self.num_acq_samples as f64 * self.code_sec >= T_ACQ {
-            let mut code_offset_idx = 0;
-            let mut idx = 0;
-            let mut p_max = 0.0;
-            let mut p_peak = 0.0;
-            let mut p_total = 0.0;
-
-            for i in 0..DOPPLER_SPREAD_BINS {
-                let p_sum = self.acq.sum_p[i].iter().sum();
-                let (j_peak, v_peak) = get_max_with_idx(&self.acq.sum_p[i]);
-
-                if p_sum > p_max {
-                    idx = i;
-                    p_max = p_sum;
-                    p_peak = v_peak;
-                    code_offset_idx = j_peak;
-                }
-                p_total += p_sum;
+    // strongest cell of the search grid: doppler, code offset in samples, cn0
+    // and the average power of the grid
+    fn acquisition_peak(&self) -> (f64, usize, f64, f64) {
+        let mut code_offset_idx = 0;
+        let mut idx = 0;
+        let mut p_max = 0.0;
+        let mut p_peak = 0.0;
+
+        for i in 0..self.acq.sum_p.len() {
+            let p_sum = self.acq.sum_p[i].iter().sum();
+            let (j_peak, v_peak) = get_max_with_idx(&self.acq.sum_p[i]);
+
+            if p_sum > p_max {
+                idx = i;
+                p_max = p_sum;
+                p_peak = v_peak;
+                code_offset_idx = j_peak;
             }
+        }
 
-            let doppler_hz = -DOPPLER_SPREAD_HZ + (idx as f64 + 0.5) * step_hz;
+        let doppler_hz =
+            self.acquisition_doppler_hz(idx) + 0.5 * self.acquisition_doppler_step_hz();
+        let p_avg =
+            self.acq.p_total / self.acq.sum_p[idx].len() as f64 / self.acq.sum_p.len() as f64;
+        let t_coh = self.code_sec * self.acq_periods as f64;
+        let cn0 = 10.0 * ((p_peak - p_avg) / p_avg / t_coh).log10();
+        (doppler_hz, code_offset_idx, cn0, p_avg)
+    }
+
+    // Acquisition over all the code periods of iq_vec at once, the channel
+    // left as it was: doppler, code offset (sec) and cn0 when detected.
+    pub fn scan(&mut self, iq_vec: &[IQSample]) -> Option<(f64, f64, f64)> {
+        let _span = self.span.clone().entered();
+        self.acquisition_init();
+        for block in iq_vec.chunks_exact(self.acq.prn_code_fft.len()) {
+            for i in 0..self.acq.sum_p.len() {
+                self.acquisition_integrate_correlation(block, i);
+            }
+        }
+        let (doppler_hz, code_offset_idx, cn0, _p_avg) = self.acquisition_peak();
+        self.acquisition_init();
+        let code_off_sec = code_offset_idx as f64 / self.code_sp as f64 * self.code_sec;
+        (cn0 >= CN0_THRESHOLD_LOCKED).then_some((doppler_hz, code_off_sec, cn0))
+    }
+
+    fn acquisition_check(&mut self) {
+        self.num_acq_samples += 1;
+
+        // whole coherent integrations
+        if self.num_acq_samples as f64 * self.code_sec >= T_ACQ && self.acq.block.is_empty() {
+            let (doppler_hz, code_offset_idx, cn0, p_avg) = self.acquisition_peak();
             let code_off_sec = code_offset_idx as f64 / self.code_sp as f64 * self.code_sec;
-            let p_avg = p_total / self.acq.sum_p[idx].len() as f64 / DOPPLER_SPREAD_BINS as f64;
-            let cn0 = 10.0 * ((p_peak - p_avg) / p_avg / self.code_sec).log10();
+            self.update_live_acquisition(p_avg, cn0);
 
+            self.num_acq_attempts += 1;
             if cn0 >= CN0_THRESHOLD_LOCKED {
+                self.num_acq_locks += 1;
                 self.tracking_start(doppler_hz, cn0, code_off_sec, code_offset_idx);
             } else {
+                // the hint didn't pan out: back to a full search
+                if self.doppler_hint.take().is_some() {
+                    tracing::info!("{}: no signal around the expected doppler", self.sv);
+                }
                 self.idle_start();
             }
             self.acquisition_init();
         }
     }
 
-    fn tracking_compute_correlation(
-        &mut self,
-        iq_vec2: &[Complex64],
-    ) -> (Complex64, Complex64, Complex64, Complex64) {
+    // The search grid of the channel selected in the UI, at each attempt: how
+    // close to the threshold a failed acquisition came.
+    fn update_live_acquisition(&self, p_avg: f64, cn0: f64) {
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if pub_state.plot_sv != Some(self.sv) {
+            return;
+        }
+        let chunk_len = self.code_sp.div_ceil(LIVE_ACQ_CODE_BINS);
+        let power = (self.acq.sum_p.iter())
+            .map(|sum_p| {
+                (sum_p.chunks(chunk_len))
+                    .map(|chunk| chunk.iter().fold(0.0, |max: f64, &p| max.max(p)) / p_avg)
+                    .collect()
+            })
+            .collect();
+        pub_state.acq_grid = Some(AcquisitionGrid {
+            sv: self.sv,
+            doppler_min_hz: self.acquisition_doppler_hz(0),
+            doppler_step_hz: self.acquisition_doppler_step_hz(),
+            code_len: self.code_len,
+            power,
+            cn0,
+            locked: cn0 >= CN0_THRESHOLD_LOCKED,
+        });
+        (pub_state.update_func.func)();
+    }
+
+    fn tracking_compute_correlation(&mut self, iq_vec2: &[IQSample]) -> Correlations {
         let n = self.code_sp as i32;
         let code_idx = *self.hist.code_phase_offset.last().unwrap() as i32;
         assert!(-n < code_idx && code_idx < n);
@@ -538,35 +1029,39 @@ impl Channel {
         assert!(lo >= 0);
         let lo_u = lo as usize;
         let hi_u = (lo + n) as usize;
-        let mut signal = iq_vec2[lo_u..hi_u].to_vec();
+        let signal = &mut self.scratch;
+        signal.clear();
+        signal.extend_from_slice(&iq_vec2[lo_u..hi_u]);
 
-        doppler_shift(&mut signal, self.trk.doppler_hz, self.trk.phi, self.fs);
+        doppler_shift(signal, self.trk.doppler_hz, self.trk.phi, self.fs);
 
+        // at least a sample off the prompt, e.g. L5 sampled at its chip rate
         let pos = (SP_CORR * self.code_sec * self.fs / self.code_len as f64) as usize;
+        let pos = pos.max(1);
 
-        let mut corr_prompt = Complex64::default();
-        let mut corr_early = Complex64::default();
-        let mut corr_late = Complex64::default();
-        let mut corr_neutral = Complex64::default();
+        let mut corr_prompt = IQSample::default();
+        let mut corr_early = IQSample::default();
+        let mut corr_late = IQSample::default();
+        let mut corr_neutral = IQSample::default();
 
         // PROMPT
         for (j, sig_val) in signal.iter().enumerate() {
             corr_prompt += sig_val * self.trk.prn_code[j];
         }
-        corr_prompt /= signal.len() as f64;
+        corr_prompt /= signal.len() as Float;
 
         // EARLY:
         #[allow(clippy::needless_range_loop)]
         for j in 0..signal.len() - pos {
             corr_early += signal[j] * self.trk.prn_code[pos + j];
         }
-        corr_early /= (signal.len() - pos) as f64;
+        corr_early /= (signal.len() - pos) as Float;
 
         // LATE:
         for j in 0..signal.len() - pos {
             corr_late += signal[pos + j] * self.trk.prn_code[j];
         }
-        corr_late /= (signal.len() - pos) as f64;
+        corr_late /= (signal.len() - pos) as Float;
 
         // NEUTRAL:
         let pos_neutral: usize = 80;
@@ -574,9 +1069,80 @@ impl Channel {
         for j in 0..signal.len() - pos_neutral {
             corr_neutral += signal[j] * self.trk.prn_code[pos_neutral + j];
         }
-        corr_neutral /= (signal.len() - pos_neutral) as f64;
+        corr_neutral /= (signal.len() - pos_neutral) as Float;
+
+        // VERY EARLY and VERY LATE: a subcarrier half-chip off, on the side
+        // peaks of a BOC correlation
+        let mut corr_very_early = IQSample::default();
+        let mut corr_very_late = IQSample::default();
+        if self.trk.bump_jump.is_some() {
+            let pos_very =
+                ((self.code_sec * self.fs / self.code_len as f64).round() as usize).max(1);
+            #[allow(clippy::needless_range_loop)]
+            for j in 0..signal.len() - pos_very {
+                corr_very_early += signal[j] * self.trk.prn_code[pos_very + j];
+                corr_very_late += signal[pos_very + j] * self.trk.prn_code[j];
+            }
+            corr_very_early /= (signal.len() - pos_very) as Float;
+            corr_very_late /= (signal.len() - pos_very) as Float;
+        }
+
+        Correlations {
+            p: to_complex64(corr_prompt),
+            e: to_complex64(corr_early),
+            l: to_complex64(corr_late),
+            n: to_complex64(corr_neutral),
+            ve: to_complex64(corr_very_early),
+            vl: to_complex64(corr_very_late),
+        }
+    }
 
-        (corr_prompt, corr_early, corr_late, corr_neutral)
+    // Gain of each antenna relative to the first one, the reference: the
+    // average of their prompt correlations, in which the data bits cancel.
+    pub fn get_array_gains(&self) -> Vec<Complex64> {
+        if self.trk.array_power == 0.0 {
+            return vec![];
+        }
+        (self.trk.array_cross.iter())
+            .map(|cross| cross / self.trk.array_power)
+            .collect()
+    }
+
+    // Correlations of several coherent antennas, combined with weights
+    // matching their gains (maximal ratio): the signal adds up coherently
+    // while the noise of each antenna doesn't. Until the gains are known the
+    // reference antenna is on its own.
+    fn tracking_combine_correlation(&mut self, iq_vecs: &[&[IQSample]]) -> Correlations {
+        let corrs: Vec<_> = (iq_vecs.iter())
+            .map(|iq_vec| self.tracking_compute_correlation(iq_vec))
+            .collect();
+
+        let alpha = self.code_sec / T_ARRAY;
+        let c_ref = corrs[0].p;
+        self.trk
+            .array_cross
+            .resize(corrs.len(), Complex64::default());
+        for (cross, corr) in self.trk.array_cross.iter_mut().zip(&corrs) {
+            *cross += alpha * (corr.p * c_ref.conj() - *cross);
+        }
+        self.trk.array_power += alpha * (c_ref.norm_sqr() - self.trk.array_power);
+
+        let gains = self.get_array_gains();
+        let norm: f64 = gains.iter().map(|g| g.norm_sqr()).sum();
+        if norm == 0.0 {
+            return corrs[0];
+        }
+        let mut combined = Correlations::default();
+        for (g, corr) in gains.iter().zip(corrs) {
+            let w = g.conj() / norm;
+            combined.p += w * corr.p;
+            combined.e += w * corr.e;
+            combined.l += w * corr.l;
+            combined.n += w * corr.n;
+            combined.ve += w * corr.ve;
+            combined.vl += w * corr.vl;
+        }
+        combined
     }
 
     fn run_fll(&mut self) {
@@ -593,10 +1159,10 @@ impl Channel {
             return;
         }
 
-        let b = if self.num_trk_samples as f64 * self.code_sec < T_FPULLIN / 2.0 {
+        let b = if self.get_loop_stage() == LoopStage::FllWide {
             B_FLL_WIDE // 10.0
         } else {
-            B_FLL_NARROW // 2.-
+            self.b_fll_narrow // 2.-
         };
         let err_freq = (cross / dot).atan() / 2.0 / PI;
 
@@ -604,12 +1170,118 @@ impl Channel {
         self.update_state_doppler_hz();
     }
 
+    fn get_loop_stage(&self) -> LoopStage {
+        let trk_sec = self.num_trk_samples as f64 * self.code_sec;
+        if trk_sec < T_FPULLIN / 2.0 {
+            LoopStage::FllWide
+        } else if trk_sec < T_FPULLIN {
+            LoopStage::FllNarrow
+        } else {
+            LoopStage::Pll
+        }
+    }
+
+    // narrow band phase lock detector, averaged
+    fn update_pll_lock(&mut self, c_p: Complex64) {
+        let power = c_p.norm_sqr();
+        if power == 0.0 {
+            return;
+        }
+        let cos_2phi = (c_p.re.powi(2) - c_p.im.powi(2)) / power;
+        self.trk.pll_lock += (cos_2phi - self.trk.pll_lock) * self.code_sec / T_LOCK;
+    }
+
+    // multipath indicators once the code loop has settled
+    fn update_quality(&mut self, c_p: Complex64, c_e: Complex64, c_l: Complex64) {
+        if (self.num_trk_samples as f64 * self.code_sec) < T_QPULLIN {
+            return;
+        }
+        self.trk.quality.add_correlations(c_p, c_e, c_l);
+        if self.num_trk_samples % (T_QUALITY / self.code_sec) as usize != 0 {
+            return;
+        }
+        // thermal noise of the code loop (m), for the early-late spacing
+        let chip_m = SPEED_OF_LIGHT * self.code_sec / self.code_len as f64;
+        let cn0 = 10f64.powf(self.trk.cn0 / 10.0);
+        let noise_m = chip_m * (B_DLL * 2.0 * SP_CORR / (2.0 * cn0)).sqrt();
+        let cmc_m = SPEED_OF_LIGHT * self.trk.dll_sum_sec;
+        if self.trk.quality.update(self.ts_sec, cmc_m, noise_m) {
+            let quality = self.trk.quality.get_quality();
+            if quality.multipath {
+                tracing::warn!("{}: {}: {quality}", self.sv, "multipath".yellow());
+            } else {
+                tracing::info!("{}: multipath cleared: {quality}", self.sv);
+            }
+        }
+    }
+
+    // multipath indicators, while tracking
+    pub fn get_quality(&self) -> Option<SignalQuality> {
+        (self.state == State::Tracking).then(|| self.trk.quality.get_quality())
+    }
+
+    // loop and navigation status, for the channel window of the UI
+    fn update_state_details(&mut self) {
+        if self.ts_sec - self.hist.last_details_ts < T_DETAILS {
+            return;
+        }
+        self.hist.last_details_ts = self.ts_sec;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        let channel = pub_state.channels.get_mut(&self.key).unwrap();
+        channel.loop_stage = self.get_loop_stage();
+        channel.pll_lock = self.trk.pll_lock;
+        channel.quality = self.trk.quality.get_quality();
+        channel.trk_sec = self.num_trk_samples as f64 * self.code_sec;
+        channel.nav = self.nav.get_status();
+        channel.nav.secondary_sync = self.trk.secondary_sync.is_some();
+    }
+
+    // A secondary code modulates the code periods: its phase is found once the
+    // pll tracks, on two periods of it in which all the prompts agree with it,
+    // then it's wiped off the prompts, those in the history included, for the
+    // navigation bits to see the data only.
+    fn secondary_wipe_off(&mut self, c_p: Complex64) -> Complex64 {
+        let code = &self.trk.secondary_code;
+        let len = code.len();
+        let n = self.num_trk_samples as isize;
+        if len == 0 {
+            return c_p;
+        }
+        if let Some(start) = self.trk.secondary_sync {
+            return c_p * code[(n - start).rem_euclid(len as isize) as usize] as f64;
+        }
+        let hist_len = self.hist.corr_p.len();
+        if self.get_loop_stage() != LoopStage::Pll || hist_len + 1 < 2 * len {
+            return c_p;
+        }
+        let first = hist_len + 1 - 2 * len;
+        let prompts: Vec<_> = (self.hist.corr_p[first..].iter())
+            .chain(std::iter::once(&c_p))
+            .collect();
+        let sync = prompts.chunks(len).all(|period| {
+            let sum: f64 = (period.iter().zip(code))
+                .map(|(c, &s)| c.re.signum() * s as f64)
+                .sum();
+            sum.abs() == len as f64
+        });
+        if !sync {
+            return c_p;
+        }
+        // this period is the last one of the code
+        tracing::info!("{}: SECONDARY SYNC: ts={:.3}", self.sv, self.ts_sec);
+        self.trk.secondary_sync = Some(n + 1 - len as isize);
+        for (j, c) in self.hist.corr_p[first..].iter_mut().enumerate() {
+            *c *= self.trk.secondary_code[j % len] as f64;
+        }
+        c_p * self.trk.secondary_code[len - 1] as f64
+    }
+
     fn run_pll(&mut self, c_p: Complex64) {
         if c_p.re == 0.0 {
             return;
         }
         let err_phase = (c_p.im / c_p.re).atan() / 2.0 / PI;
-        let w = B_PLL / 0.53; // ~18.9
+        let w = self.b_pll / 0.53; // ~18.9
         self.trk.doppler_hz +=
             1.4 * w * (err_phase - self.trk.err_phase) + w * w * err_phase * self.code_sec;
         self.update_state_doppler_hz();
@@ -619,19 +1291,54 @@ impl Channel {
 
     fn run_dll(&mut self, c_e: Complex64, c_l: Complex64) {
         let n = usize::max(1, (T_DLL / self.code_sec) as usize);
-        assert_eq!(n, 10);
         self.trk.sum_corr_e += c_e.norm();
         self.trk.sum_corr_l += c_l.norm();
         if self.num_trk_samples % n == 0 {
             let e = self.trk.sum_corr_e;
             let l = self.trk.sum_corr_l;
             let err_code = (e - l) / (e + l) / 2.0 * self.code_sec / self.code_len as f64;
-            self.trk.code_off_sec -= B_DLL / 0.25 * err_code * self.code_sec * n as f64;
+            let correction = B_DLL / 0.25 * err_code * self.code_sec * n as f64;
+            self.trk.code_off_sec -= correction;
+            self.trk.dll_sum_sec -= correction;
             self.trk.sum_corr_e = 0.0;
             self.trk.sum_corr_l = 0.0;
         }
     }
 
+    // BOC signals: the code jumps to the main peak of the correlation once
+    // the very early or very late correlator outweighs the prompt. The code
+    // minus carrier up to the jump was that of a side peak: the multipath
+    // indicators start over.
+    fn run_bump_jump(&mut self, corr: Correlations) {
+        let Some(bump_jump) = &mut self.trk.bump_jump else {
+            return;
+        };
+        bump_jump.add_correlations(corr.p, corr.ve, corr.vl);
+        let n = usize::max(1, (T_BUMP / self.code_sec) as usize);
+        if self.num_trk_samples % n != 0 {
+            return;
+        }
+        let Some(jump) = bump_jump.update() else {
+            return;
+        };
+        // a side peak away: a subcarrier half-chip
+        let spacing_sec = self.code_sec / self.code_len as f64;
+        let correction = match jump {
+            Jump::Early => -spacing_sec,
+            Jump::Late => spacing_sec,
+        };
+        self.trk.code_off_sec += correction;
+        self.trk.dll_sum_sec += correction;
+        self.trk.quality = QualityMonitor::new();
+        tracing::warn!(
+            "{}: {}: code jumps {jump} by {:.1} m ts_sec={:.3}",
+            self.sv,
+            "side peak".yellow(),
+            SPEED_OF_LIGHT * spacing_sec,
+            self.ts_sec,
+        );
+    }
+
     fn update_cn0(&mut self, c_p: Complex64, c_n: Complex64) {
         self.trk.sum_corr_p += c_p.norm_sqr();
         self.trk.sum_corr_n += c_n.norm_sqr();
@@ -682,7 +1389,7 @@ impl Channel {
     fn log_periodically(&mut self) {
         let code_idx = self.hist.code_phase_offset.last().unwrap();
         if self.ts_sec - self.hist.last_log_ts > 3.0 {
-            log::warn!(
+            tracing::debug!(
                 "{}: {} cn0={:.1} dopp={:5.0} code_idx={:4.0} phi={:5.2} ts_sec={:.3} code_off_sec={:+.3e}",
                 self.sv,
                 "TRCK".green(),
@@ -694,12 +1401,26 @@ impl Channel {
                 self.trk.code_off_sec
             );
             self.hist.last_log_ts = self.ts_sec;
+            let gains = self.get_array_gains();
+            if gains.len() > 1 {
+                let gains: Vec<_> = (gains.iter())
+                    .map(|g| format!("{:.2}/{:+4.0}", g.norm(), g.arg().to_degrees()))
+                    .collect();
+                tracing::debug!("{}: antenna gains: {}", self.sv, gains.join(" "));
+            }
         }
     }
 
-    fn tracking_process(&mut self, iq_vec: &[Complex64]) {
+    fn tracking_process(&mut self, iq_vecs: &[&[IQSample]]) {
+        let ts = StageTimes::start(&self.bench);
         self.get_code_and_carrier_phase();
-        let (c_p, c_e, c_l, c_n) = self.tracking_compute_correlation(iq_vec);
+        let corr = if iq_vecs.len() > 1 {
+            self.tracking_combine_correlation(iq_vecs)
+        } else {
+            self.tracking_compute_correlation(iq_vecs[0])
+        };
+        let (c_e, c_l, c_n) = (corr.e, corr.l, corr.n);
+        let c_p = self.secondary_wipe_off(corr.p);
         self.hist.corr_p.push(c_p);
         self.num_trk_samples += 1;
 
@@ -710,16 +1431,25 @@ impl Channel {
         }
 
         self.run_dll(c_e, c_l);
+        self.run_bump_jump(corr);
         self.update_cn0(c_p, c_n);
+        self.update_pll_lock(c_p);
+        self.update_quality(c_p, c_e, c_l);
+        StageTimes::add(&mut self.bench, Stage::Tracking, ts);
 
         if self.num_trk_samples as f64 * self.code_sec >= T_NPULLIN {
+            let ts = StageTimes::start(&self.bench);
             self.nav_decode();
+            StageTimes::add(&mut self.bench, Stage::Nav, ts);
         }
 
         self.hist.doppler_hz.push(self.trk.doppler_hz);
         self.hist.trim();
+        let ts = StageTimes::start(&self.bench);
         self.update_all_plots(false);
+        StageTimes::add(&mut self.bench, Stage::Plots, ts);
         self.log_periodically();
+        self.update_state_details();
         self.nav.eph.cn0 = self.trk.cn0;
         self.nav.eph.code_off_sec = self.trk.code_off_sec;
 
@@ -728,12 +1458,51 @@ impl Channel {
         }
     }
 
-    pub fn process_samples(&mut self, iq_vec: &[Complex64], ts_sec: f64) {
+    // The receiver skipped num periods to catch up with a real-time source.
+    // Tracking coasts through them at the current doppler so that the code
+    // phase and the count of code periods stay right; the navigation bits of
+    // the gap are repeats and get rejected by the parity checks. Longer codes
+    // coast when the next block shows the gap.
+    pub fn skip_code_periods(&mut self, num: usize) {
+        if self.code_msec == 1 {
+            self.coast_code_periods(num);
+        }
+    }
+
+    fn coast_code_periods(&mut self, num: usize) {
+        match self.state {
+            State::Tracking => {
+                for _ in 0..num {
+                    self.get_code_and_carrier_phase();
+                    let c_p = self.hist.corr_p.last().copied().unwrap_or_default();
+                    self.hist.corr_p.push(c_p);
+                    self.num_trk_samples += 1;
+                    if self.num_trk_samples as f64 * self.code_sec >= T_NPULLIN {
+                        self.nav_decode();
+                    }
+                    self.hist.doppler_hz.push(self.trk.doppler_hz);
+                    self.hist.trim();
+                }
+            }
+            State::Idle => self.num_idl_samples += num,
+            State::Acquisition => {}
+        }
+        self.ts_sec += num as f64 * self.code_sec;
+    }
+
+    pub fn process_samples(&mut self, iq_vec: &[IQSample], ts_sec: f64) {
+        self.process_array_samples(&[iq_vec], ts_sec);
+    }
+
+    // the same code periods seen by several antennas, the first one being
+    // the reference: acquisition only uses it.
+    fn process_array_samples(&mut self, iq_vecs: &[&[IQSample]], ts_sec: f64) {
         self.ts_sec = ts_sec;
+        let iq_vec = iq_vecs[0];
 
         #[allow(clippy::overly_complex_bool_expr)]
         if false && self.state != State::Idle {
-            log::info!(
+            tracing::info!(
                 "{}: processing: ts={:.3}: cn0={:.1} dopp={:5.0} code_off_sec={:2.6}",
                 self.sv,
                 self.ts_sec,
@@ -744,9 +1513,98 @@ impl Channel {
         }
 
         match self.state {
-            State::Acquisition => self.acquisition_process(iq_vec),
-            State::Tracking => self.tracking_process(iq_vec),
+            State::Acquisition => {
+                let ts = StageTimes::start(&self.bench);
+                self.acquisition_process(iq_vec);
+                StageTimes::add(&mut self.bench, Stage::Acquisition, ts);
+            }
+            State::Tracking => self.tracking_process(iq_vecs),
             State::Idle => self.idle_process(),
         }
     }
+
+    // A block of num code periods preceded by the last period of the previous
+    // block: each period is processed along with the one before it, as with
+    // process_samples(). ts_sec is the time of the first period.
+    pub fn process_block(&mut self, block: &[IQSample], ts_sec: f64) {
+        let _span = self.span.clone().entered();
+        if self.code_msec > 1 {
+            return self.gather_blocks(&[block], ts_sec);
+        }
+        let num = block.len() / self.code_sp - 1;
+        for k in 0..num {
+            let window = &block[k * self.code_sp..(k + 2) * self.code_sp];
+            self.process_samples(window, ts_sec + (k * self.code_sp) as f64 / self.fs);
+        }
+    }
+
+    // process_block() for the blocks of an antenna array, first the reference
+    pub fn process_array_block(&mut self, blocks: &[Arc<[IQSample]>], ts_sec: f64) {
+        if blocks.len() == 1 {
+            return self.process_block(&blocks[0], ts_sec);
+        }
+        let _span = self.span.clone().entered();
+        if self.code_msec > 1 {
+            let blocks: Vec<&[IQSample]> = blocks.iter().map(|block| &block[..]).collect();
+            return self.gather_blocks(&blocks, ts_sec);
+        }
+        let num = blocks[0].len() / self.code_sp - 1;
+        for k in 0..num {
+            let windows: Vec<_> = (blocks.iter())
+                .map(|block| &block[k * self.code_sp..(k + 2) * self.code_sp])
+                .collect();
+            self.process_array_samples(&windows, ts_sec + (k * self.code_sp) as f64 / self.fs);
+        }
+    }
+
+    // Codes longer than a receiver period (E1: 4 msec): the new periods of
+    // the blocks are gathered until two code periods are there, processed as
+    // a window like process_block() does, the second one kept for the next
+    // window. A gap in the blocks, skipped by the receiver or missed by the
+    // acquisition stage, is coasted through by whole code periods, dropping
+    // the samples up to the next code period.
+    fn gather_blocks(&mut self, blocks: &[&[IQSample]], ts_sec: f64) {
+        let period_sp = self.code_sp / self.code_msec;
+        let pending_ts = *self.pending_ts.get_or_insert(ts_sec);
+        self.pending.resize(blocks.len(), vec![]);
+        let pending_sp = self.pending[0].len() as f64 - self.pending_skip as f64;
+        let gap_sp = ((ts_sec - pending_ts) * self.fs - pending_sp).round();
+        if gap_sp != 0.0 {
+            let num = ((ts_sec - pending_ts) * self.fs / self.code_sp as f64).ceil() as usize;
+            let window_ts = pending_ts + (num * self.code_sp) as f64 / self.fs;
+            self.coast_code_periods(num);
+            self.pending.iter_mut().for_each(|pending| pending.clear());
+            self.pending_ts = Some(window_ts);
+            self.pending_skip = ((window_ts - ts_sec) * self.fs).round().max(0.0) as usize;
+        }
+
+        for (pending, block) in self.pending.iter_mut().zip(blocks) {
+            let new = &block[period_sp.min(block.len())..];
+            pending.extend_from_slice(&new[self.pending_skip.min(new.len())..]);
+        }
+        let num_new = blocks[0].len().saturating_sub(period_sp);
+        self.pending_skip -= self.pending_skip.min(num_new);
+
+        while self.pending[0].len() >= 2 * self.code_sp {
+            let window_ts = self.pending_ts.unwrap() + self.code_sp as f64 / self.fs;
+            let mut pending = std::mem::take(&mut self.pending);
+            let windows: Vec<_> = (pending.iter())
+                .map(|pending| &pending[..2 * self.code_sp])
+                .collect();
+            self.process_array_samples(&windows, window_ts);
+            for pending in &mut pending {
+                pending.drain(..self.code_sp);
+            }
+            self.pending = pending;
+            self.pending_ts = Some(window_ts);
+        }
+    }
+
+    pub fn enable_bench(&mut self) {
+        self.bench = Some(StageTimes::default());
+    }
+
+    pub fn get_bench(&self) -> Option<StageTimes> {
+        self.bench
+    }
 }
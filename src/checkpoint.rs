@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::sidecar::IQSidecar;
+use crate::start::StartState;
+
+// A file of a recording, as it was when the checkpoint was saved: the
+// checksum of its sidecar, if any, and its modification time.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RecordingFile {
+    pub path: PathBuf,
+    pub sha256: Option<String>,
+    pub modified: Option<SystemTime>,
+}
+
+impl RecordingFile {
+    pub fn new(path: &Path) -> Self {
+        let sidecar = IQSidecar::read(path).ok().flatten();
+        Self {
+            path: path.to_path_buf(),
+            sha256: sidecar.map(|sidecar| sidecar.sha256),
+            modified: (path.metadata()).and_then(|m| m.modified()).ok(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BandCheckpoint {
+    pub sig: String,
+    pub off_samples: usize,       // read position in the recording
+    pub num_samples_total: usize, // to tell the recording apart
+    #[serde(default)]
+    pub files: Vec<RecordingFile>, // and its files
+}
+
+// Where a run on a recording was, saved periodically so that a run cut short
+// (crash, Ctrl-C) resumes from there. The channels come back through the
+// start state: doppler and ephemeris, as for a hot start.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Checkpoint {
+    pub saved_at: f64, // unix time of the signal, seconds
+    pub clock_samples: u64,
+    pub processed_msec: usize,
+    pub bands: Vec<BandCheckpoint>,
+    pub state: StartState,
+}
+
+impl Checkpoint {
+    // written aside then renamed: a crash while writing leaves the previous
+    // checkpoint intact.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, json)?;
+        std::fs::rename(&tmp_path, path)
+    }
+
+    // None when there is no checkpoint: a new run.
+    pub fn read(path: &Path) -> std::io::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(path)?;
+        let checkpoint = serde_json::from_str(&json).map_err(std::io::Error::other)?;
+        Ok(Some(checkpoint))
+    }
+
+    pub fn remove(path: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// The wall clock of the progress reports, the playback pacing, the spectrum
+// updates and the benchmarks. wasm32-unknown-unknown has none: time stands
+// still there, nothing gets paced and no speed is reported.
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+pub use frozen::Instant;
+
+#[cfg(target_arch = "wasm32")]
+mod frozen {
+    use std::ops::AddAssign;
+    use std::time::Duration;
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct Instant;
+
+    impl Instant {
+        pub fn now() -> Self {
+            Instant
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    impl AddAssign<Duration> for Instant {
+        fn add_assign(&mut self, _duration: Duration) {}
+    }
+}
@@ -1,8 +1,32 @@
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
 pub const L1CA_CODE_LEN: usize = 1023;
+pub const QZSS_PRNS: RangeInclusive<u8> = 193..=202;
+pub const E1_CODE_LEN: usize = 4092;
+pub const L2CM_CODE_LEN: usize = 10230;
+pub const L2CL_CODE_LEN: usize = 767250;
+const L2C_FEEDBACK: u32 = 0o445112474; // x^27 + x^24 + ... + x^3 + 1, shifted right
+pub const L1OF_CODE_LEN: usize = 511;
+pub const GLO_FREQ_STEP_HZ: f64 = 562.5e3; // between two GLONASS L1 frequency channels
+pub const B1I_CODE_LEN: usize = 2046;
+// Neuman-Hofman code over the 20 code periods of a bit: BeiDou D1
+pub const NH20_CODE: [i8; 20] = [
+    1, 1, 1, 1, 1, -1, 1, 1, -1, -1, 1, -1, 1, -1, 1, 1, -1, -1, -1, 1,
+];
+pub const L5_CODE_LEN: usize = 10230;
+// over the 10 code periods of a symbol of L5I, NH20 over those of L5Q
+pub const NH10_CODE: [i8; 10] = [1, 1, 1, 1, -1, -1, 1, -1, 1, -1];
+const PERIOD_RCV: f64 = 1e-3; // the receiver hands over the samples by the msec
 
 pub struct Code {}
 
 impl Code {
+    // G2 delays by prn: gps 1 to 63, SBAS 120 to 158 and QZSS 193 to 202
     fn gen_l1ca_code(prn: u8) -> Vec<i8> {
         const G2_DELAY: [usize; 210] = [
             5, 6, 7, 8, 17, 18, 139, 140, 141, 251, 252, 254, 255, 256, 257, 258, 469, 470, 471,
@@ -45,34 +69,317 @@ impl Code {
         g
     }
 
-    pub fn gen_code(sig: &str, prn: u8) -> Option<Vec<i8>> {
+    // The GLONASS L1OF code, the same for all the satellites: the maximal
+    // sequence of a 9-stage register, 1 + x^5 + x^9, from all ones, output
+    // from the 7th stage.
+    fn gen_l1of_code() -> Vec<i8> {
+        let mut r = [-1i8; 9];
+        let mut g = Vec::with_capacity(L1OF_CODE_LEN);
+        for _ in 0..L1OF_CODE_LEN {
+            g.push(-r[6]);
+            let c = r[4] * r[8];
+            r.rotate_right(1);
+            r[0] = c;
+        }
+        g
+    }
+
+    // The BeiDou B1I code of a prn: two 11-stage registers from 01010101010,
+    // the second one output from the taps of the prn (BDS-SIS-ICD-B1I table
+    // 4-1), truncated to 2046 chips.
+    fn gen_b1i_code(prn: u8) -> Option<Vec<i8>> {
+        const G2_TAP1: [usize; 37] = [
+            1, 1, 1, 1, 1, 1, 1, 1, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5, 5, 6, 6,
+            6, 6, 8, 8, 8, 9, 9, 10,
+        ];
+        const G2_TAP2: [usize; 37] = [
+            3, 4, 5, 6, 8, 9, 10, 11, 7, 4, 5, 6, 8, 9, 10, 11, 5, 6, 8, 9, 10, 11, 6, 8, 9, 10,
+            11, 8, 9, 10, 11, 9, 10, 11, 10, 11, 11,
+        ];
+        let i = (prn as usize)
+            .checked_sub(1)
+            .filter(|&i| i < G2_TAP1.len())?;
+        let (t1, t2) = (G2_TAP1[i], G2_TAP2[i]);
+        let mut r1: [i8; 11] = std::array::from_fn(|i| if i % 2 == 0 { 1 } else { -1 });
+        let mut r2 = r1;
+        let mut g = Vec::with_capacity(B1I_CODE_LEN);
+        for _ in 0..B1I_CODE_LEN {
+            g.push(r1[10] * r2[t1 - 1] * r2[t2 - 1]);
+            // 1 + x + x^7 + x^8 + x^9 + x^10 + x^11
+            let c1 = r1[0] * r1[6] * r1[7] * r1[8] * r1[9] * r1[10];
+            // 1 + x + x^2 + x^3 + x^4 + x^5 + x^8 + x^9 + x^11
+            let c2 = r2[0] * r2[1] * r2[2] * r2[3] * r2[4] * r2[7] * r2[8] * r2[10];
+            r1.rotate_right(1);
+            r2.rotate_right(1);
+            r1[0] = c1;
+            r2[0] = c2;
+        }
+        Some(g)
+    }
+
+    // The L5 code of a prn, I5 or Q5: XA, 1 + x^9 + x^10 + x^12 + x^13
+    // short-cycled to 8190 chips, times XB, 1 + x + x^3 + x^4 + x^6 + x^7 +
+    // x^8 + x^12 + x^13, advanced by the chips of the prn (IS-GPS-705 table
+    // 3-Ia). Both registers start from all ones.
+    fn gen_l5_code(prn: u8, pilot: bool) -> Option<Vec<i8>> {
+        const I5_ADVANCE: [usize; 37] = [
+            266, 365, 804, 1138, 1509, 1559, 1756, 2084, 2170, 2303, 2527, 2687, 2930, 3471, 3940,
+            4132, 4332, 4924, 5343, 5443, 5641, 5816, 5898, 5918, 5955, 6243, 6345, 6477, 6518,
+            6875, 7168, 7187, 7329, 7577, 7720, 7777, 8057,
+        ];
+        const Q5_ADVANCE: [usize; 37] = [
+            1701, 323, 5292, 2020, 5429, 7136, 1041, 5947, 4315, 148, 535, 1939, 5206, 5910, 3595,
+            5135, 6082, 6990, 3546, 1523, 4548, 4484, 1893, 3961, 7106, 5299, 4660, 276, 4389,
+            3783, 1591, 1601, 749, 1387, 1661, 3210, 708,
+        ];
+        const XA_LEN: usize = 8190;
+        const XB_LEN: usize = 8191;
+        let advance = if pilot { Q5_ADVANCE } else { I5_ADVANCE };
+        let advance = *advance.get((prn as usize).checked_sub(1)?)?;
+        let mut xa = [0i8; XA_LEN];
+        let mut xb = [0i8; XB_LEN];
+        let mut ra = [-1i8; 13];
+        let mut rb = [-1i8; 13];
+        for i in 0..XB_LEN {
+            if i < XA_LEN {
+                xa[i] = ra[12];
+            }
+            xb[i] = rb[12];
+            let ca = ra[8] * ra[9] * ra[11] * ra[12];
+            let cb = rb[0] * rb[2] * rb[3] * rb[5] * rb[6] * rb[7] * rb[11] * rb[12];
+            ra.rotate_right(1);
+            rb.rotate_right(1);
+            ra[0] = ca;
+            rb[0] = cb;
+        }
+        let code = (0..L5_CODE_LEN)
+            .map(|i| -xa[i % XA_LEN] * xb[(i + advance) % XB_LEN])
+            .collect();
+        Some(code)
+    }
+
+    // The L2 CM (20 msec) or CL (1.5 sec) code of a prn: a 27-stage register
+    // from the initial state of the prn (IS-GPS-200 table 3-IIa).
+    fn gen_l2c_code(prn: u8, long: bool) -> Option<Vec<i8>> {
+        const CM_INIT: [u32; 37] = [
+            0o742417664,
+            0o756014035,
+            0o002747144,
+            0o066265724,
+            0o601403471,
+            0o703232733,
+            0o124510070,
+            0o617316361,
+            0o047541621,
+            0o733031046,
+            0o713512145,
+            0o024437606,
+            0o021264003,
+            0o230655351,
+            0o001314400,
+            0o222021506,
+            0o540264026,
+            0o205521705,
+            0o064022144,
+            0o120161274,
+            0o044023533,
+            0o724744327,
+            0o045743577,
+            0o741201660,
+            0o700274134,
+            0o010247261,
+            0o713433445,
+            0o737324162,
+            0o311627434,
+            0o710452007,
+            0o722462133,
+            0o050172213,
+            0o500653703,
+            0o755077436,
+            0o136717361,
+            0o756675453,
+            0o435506112,
+        ];
+        const CL_INIT: [u32; 37] = [
+            0o624145772,
+            0o506610362,
+            0o220360016,
+            0o710406104,
+            0o001143345,
+            0o053023326,
+            0o652521276,
+            0o206124777,
+            0o015563374,
+            0o561522076,
+            0o023163525,
+            0o117776450,
+            0o606516355,
+            0o003037343,
+            0o046515565,
+            0o671511621,
+            0o605402220,
+            0o002576207,
+            0o525163451,
+            0o266527765,
+            0o006760703,
+            0o501474556,
+            0o743747443,
+            0o615534726,
+            0o763621420,
+            0o720727474,
+            0o700521043,
+            0o222567263,
+            0o132765304,
+            0o746332245,
+            0o102300466,
+            0o255231716,
+            0o437661701,
+            0o717047302,
+            0o222614207,
+            0o561123307,
+            0o240713073,
+        ];
+        let (init, len) = if long {
+            (CL_INIT, L2CL_CODE_LEN)
+        } else {
+            (CM_INIT, L2CM_CODE_LEN)
+        };
+        let mut r = *init.get((prn as usize).checked_sub(1)?)?;
+        let mut code = Vec::with_capacity(len);
+        for _ in 0..len {
+            code.push(if r & 1 != 0 { 1 } else { -1 });
+            r = (r >> 1) ^ ((r & 1) * L2C_FEEDBACK);
+        }
+        Some(code)
+    }
+
+    // L2C as the channels track it: the CM chips time-multiplexed with those
+    // of CL at 1.023 Mcps, CM first, the CL slots left at zero. CM carries
+    // the data, one symbol per code period; CL isn't correlated, its 1.5 sec
+    // period is out of reach of the acquisition.
+    fn gen_l2c_cm_code(prn: u8) -> Option<Vec<i8>> {
+        let cm = Self::gen_l2c_code(prn, false)?;
+        Some(cm.iter().flat_map(|&c| [c, 0]).collect())
+    }
+
+    // Signals on a BOC(1,1) subcarrier: their correlation has side peaks half
+    // a chip off the main one. The CBOC of E1 is correlated as BOC(1,1), its
+    // BOC(6,1) share left out.
+    pub fn is_boc(sig: &str) -> bool {
+        matches!(sig, "E1" | "E1B" | "E1C")
+    }
+
+    // The secondary code over consecutive code periods, empty for the signals
+    // without: the BeiDou GEO satellites, prns 1 to 5, send their D2 message
+    // at 500 bps without one.
+    pub fn get_secondary_code(sig: &str, prn: u8) -> Vec<i8> {
         match sig {
-            "L1CA" => Some(Self::gen_l1ca_code(prn)),
-            _ => None,
+            "B1I" if prn > 5 => NH20_CODE.to_vec(),
+            "L5" | "L5I" => NH10_CODE.to_vec(),
+            "L5Q" => NH20_CODE.to_vec(),
+            _ => vec![],
         }
     }
 
+    // Samples of a code period at fs: whole receiver periods of 1 msec, as
+    // the receiver hands the samples over.
+    pub fn get_code_sp(sig: &str, fs: f64) -> usize {
+        (fs * PERIOD_RCV) as usize * Self::get_code_msec(sig)
+    }
+
+    // receiver periods per code period
+    pub fn get_code_msec(sig: &str) -> usize {
+        (Self::get_code_period(sig) / PERIOD_RCV).round() as usize
+    }
+
     pub fn get_code_period(sig: &str) -> f64 {
         match sig {
             "L1CA" => 1e-3,
+            "E1" | "E1B" | "E1C" => 4e-3,
+            "L2C" | "L2CM" => 20e-3,
+            "L2CL" => 1.5,
+            "L1OF" => 1e-3,
+            "B1I" => 1e-3,
+            "L5" | "L5I" | "L5Q" => 1e-3,
             _ => 0.0,
         }
     }
 
+    // elements of the code as correlated with: the subcarrier halves of the
+    // chips for BOC signals
     pub fn get_code_len(sig: &str) -> usize {
         match sig {
             "L1CA" => L1CA_CODE_LEN,
+            "E1" | "E1B" | "E1C" => 2 * E1_CODE_LEN,
+            "L2C" | "L2CM" => 2 * L2CM_CODE_LEN,
+            "L2CL" => L2CL_CODE_LEN,
+            "L1OF" => L1OF_CODE_LEN,
+            "B1I" => B1I_CODE_LEN,
+            "L5" | "L5I" | "L5Q" => L5_CODE_LEN,
             _ => 0,
         }
     }
 
     pub fn get_code_freq(sig: &str) -> f64 {
         match sig {
-            "L1CA" => 1575.42e6,
+            "L1CA" | "E1" | "E1B" | "E1C" => 1575.42e6,
+            "L2C" | "L2CM" | "L2CL" => 1227.60e6,
+            "L1OF" => 1602e6, // frequency channel 0
+            "B1I" => 1561.098e6,
+            "L5" | "L5I" | "L5Q" => 1176.45e6,
             _ => 0.0,
         }
     }
 
+    pub fn get_constellation(sig: &str) -> Option<Constellation> {
+        match sig {
+            "L1CA" | "L2C" | "L2CM" | "L2CL" | "L5" | "L5I" | "L5Q" => Some(Constellation::GPS),
+            "E1" | "E1B" | "E1C" => Some(Constellation::Galileo),
+            "L1OF" => Some(Constellation::Glonass),
+            "B1I" => Some(Constellation::BeiDou),
+            _ => None,
+        }
+    }
+
+    // the satellite of a code of sig: QZSS broadcasts on the L1CA codes 193
+    // to 202 and goes by them
+    pub fn get_sv(sig: &str, prn: u8) -> Option<SV> {
+        let constellation = match sig {
+            "L1CA" if QZSS_PRNS.contains(&prn) => Constellation::QZSS,
+            _ => Self::get_constellation(sig)?,
+        };
+        Some(SV::new(constellation, prn))
+    }
+
+    // the signals of a comma-separated list, e.g. L1CA,E1B: one per
+    // constellation, received together on the carrier of the first
+    pub fn get_sig_list(sig: &str) -> Vec<&str> {
+        sig.split(',').map(str::trim).collect()
+    }
+
+    // the signal of sigs the satellites of a constellation are received on
+    pub fn get_constellation_signal<'a>(
+        sigs: &[&'a str],
+        constellation: Constellation,
+    ) -> Option<&'a str> {
+        if constellation == Constellation::QZSS {
+            return sigs.iter().copied().find(|&sig| sig == "L1CA");
+        }
+        (sigs.iter().copied()).find(|sig| Self::get_constellation(sig) == Some(constellation))
+    }
+
+    // the prns a signal has codes for, and those searched by default
+    pub fn get_prns(sig: &str) -> (RangeInclusive<u8>, RangeInclusive<u8>) {
+        match sig {
+            "E1" | "E1B" | "E1C" => (1..=50, 1..=36),
+            "L2C" | "L2CM" | "L2CL" => (1..=37, 1..=32),
+            "L1OF" => (1..=24, 1..=24), // orbital slots
+            "B1I" => (1..=37, 1..=37),
+            "L5" | "L5I" | "L5Q" => (1..=37, 1..=32),
+            _ => (1..=210, 1..=32),
+        }
+    }
+
     pub fn print_l1ca_codes() {
         println!("generating gold codes for L1CA");
         for i in 1..=32 {
@@ -81,3 +388,148 @@ impl Code {
         }
     }
 }
+
+// Frequency channel k of each GLONASS slot, the carrier at 1602 MHz + k *
+// 562.5 kHz: the assignment at the time of writing, antipodal satellites
+// sharing a channel.
+const GLO_FREQ_CHANNELS: [i8; 24] = [
+    1, -4, 5, 6, 1, -4, 5, 6, -2, -7, 0, -1, -2, -7, 0, -1, 4, -3, 3, 2, 4, -3, 3, 2,
+];
+
+// What the codes of a receiver depend on besides their signal and prn: the
+// memory codes that no register generates, e.g. those of Galileo E1, keyed
+// by signal and prn, and the frequency channels of the GLONASS slots. Each
+// receiver has its own, handed to its channels and its simulated satellites.
+#[derive(Clone, Debug)]
+pub struct CodeTables {
+    memory_codes: BTreeMap<(String, u8), Vec<i8>>,
+    glo_freq_channels: [i8; 24],
+}
+
+impl Default for CodeTables {
+    fn default() -> Self {
+        Self {
+            memory_codes: BTreeMap::new(),
+            glo_freq_channels: GLO_FREQ_CHANNELS,
+        }
+    }
+}
+
+impl CodeTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn gen_code(&self, sig: &str, prn: u8) -> Option<Vec<i8>> {
+        match sig {
+            "L1CA" => Some(Code::gen_l1ca_code(prn)),
+            "E1" | "E1B" => self.gen_e1_code("E1B", prn),
+            "E1C" => self.gen_e1_code("E1C", prn),
+            "L2C" | "L2CM" => Code::gen_l2c_cm_code(prn),
+            "L2CL" => Code::gen_l2c_code(prn, true),
+            "L1OF" => self
+                .get_freq_channel(sig, prn)
+                .map(|_| Code::gen_l1of_code()),
+            "B1I" => Code::gen_b1i_code(prn),
+            "L5" | "L5I" => Code::gen_l5_code(prn, false),
+            "L5Q" => Code::gen_l5_code(prn, true),
+            _ => None,
+        }
+    }
+
+    // code sampled at fs over one code period
+    pub fn gen_sampled_code(&self, sig: &str, prn: u8, fs: f64) -> Option<Vec<i8>> {
+        let code = self.gen_code(sig, prn)?;
+        let code_len = code.len();
+        let code_sp = Code::get_code_sp(sig, fs);
+        let sampled_code = (0..code_sp).map(|i| code[i * code_len / code_sp]).collect();
+        Some(sampled_code)
+    }
+
+    // The E1 memory code of the data (E1B) or pilot (E1C) component, on its
+    // BOC(1,1) subcarrier: each chip is followed by its opposite. The
+    // BOC(6,1) part of CBOC carries a tenth of the power and doesn't make it
+    // through the few MHz of band of the usual front-ends: the receivers
+    // correlate with BOC(1,1) alone.
+    fn gen_e1_code(&self, sig: &str, prn: u8) -> Option<Vec<i8>> {
+        let code = self.memory_codes.get(&(sig.to_string(), prn))?;
+        Some(code.iter().flat_map(|&c| [c, -c]).collect())
+    }
+
+    // The memory codes of a file, a line per code: the signal, the prn and
+    // the chips in hex, msb first, as tabulated by the Galileo OS SIS ICD:
+    //   E1B 1 <1023 hex digits>
+    // Blank lines and lines starting with # are skipped. The number of codes
+    // loaded.
+    pub fn load_memory_codes(&mut self, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let mut num = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || format!("{}:{}: invalid code", path.display(), i + 1);
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [sig, prn, hex] = fields[..] else {
+                return Err(err().into());
+            };
+            let code_len = match sig {
+                "E1B" | "E1C" => E1_CODE_LEN,
+                _ => return Err(format!("{}: no memory codes for {sig}", err()).into()),
+            };
+            let prn = prn.parse::<u8>().map_err(|_| err())?;
+            let chips: Vec<i8> = (hex.chars())
+                .map(|c| c.to_digit(16))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(err)?
+                .into_iter()
+                .flat_map(|d| (0..4).rev().map(move |j| (d >> j) & 1))
+                .take(code_len)
+                .map(|bit| if bit == 0 { 1 } else { -1 })
+                .collect();
+            if chips.len() != code_len || hex.len() != code_len.div_ceil(4) {
+                return Err(format!("{}: {} hex digits", err(), hex.len()).into());
+            }
+            self.memory_codes.insert((sig.to_string(), prn), chips);
+            num += 1;
+        }
+        tracing::info!("{num} memory codes loaded from {}", path.display());
+        Ok(num)
+    }
+
+    // The frequency channel of a satellite of an FDMA signal, None for the
+    // others.
+    pub fn get_freq_channel(&self, sig: &str, prn: u8) -> Option<i8> {
+        match sig {
+            "L1OF" => (self.glo_freq_channels)
+                .get((prn as usize).checked_sub(1)?)
+                .copied(),
+            _ => None,
+        }
+    }
+
+    // carrier of a satellite relative to get_code_freq(): FDMA signals only
+    pub fn get_carrier_offset(&self, sig: &str, prn: u8) -> f64 {
+        self.get_freq_channel(sig, prn)
+            .map_or(0.0, |k| k as f64 * GLO_FREQ_STEP_HZ)
+    }
+
+    // GLONASS frequency channels as slot:k pairs, e.g. "3:5,7:5", as in the
+    // almanac published for the current constellation: those of the other
+    // slots stay.
+    pub fn set_freq_channels(&mut self, spec: &str) -> Result<(), Box<dyn Error>> {
+        for pair in spec.split(',').filter(|s| !s.is_empty()) {
+            let err = || format!("invalid frequency channel: {pair}");
+            let (slot, k) = pair.split_once(':').ok_or_else(err)?;
+            let slot = slot.trim().parse::<usize>().map_err(|_| err())?;
+            let k = k.trim().parse::<i8>().map_err(|_| err())?;
+            if !(-7..=6).contains(&k) {
+                return Err(err().into());
+            }
+            let channel = (self.glo_freq_channels.get_mut(slot.wrapping_sub(1))).ok_or_else(err)?;
+            *channel = k;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use gnss_rs::sv::SV;
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex64;
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::code::{Code, CodeTables};
+
+// csv: one line per value, e.g. G01,0,1 or G01,0,-3.0,12.5 for the fft.
+// bin: the satellites one after the other, a code period each: i8 per chip
+// or sample, f32 little-endian I/Q pairs for the fft (as 2xf32 files).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CodeFormat {
+    Csv,
+    Bin,
+}
+
+impl FromStr for CodeFormat {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<CodeFormat, Self::Err> {
+        match input {
+            "csv" => Ok(CodeFormat::Csv),
+            "bin" => Ok(CodeFormat::Bin),
+            _ => Err(format!("Failed to parse {}", input).into()),
+        }
+    }
+}
+
+impl fmt::Display for CodeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodeFormat::Csv => write!(f, "csv"),
+            CodeFormat::Bin => write!(f, "bin"),
+        }
+    }
+}
+
+// what is written of the code
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CodeForm {
+    Chips,
+    Sampled(f64), // at fs over a code period, as the channels correlate with
+    Fft(f64),     // of the sampled code, as acquisition uses
+}
+
+enum CodeValues {
+    Chips(Vec<i8>),
+    Spectrum(Vec<Complex64>),
+}
+
+fn get_code_values(codes: &CodeTables, sig: &str, prn: u8, form: CodeForm) -> Option<CodeValues> {
+    let values = match form {
+        CodeForm::Chips => CodeValues::Chips(codes.gen_code(sig, prn)?),
+        CodeForm::Sampled(fs) => CodeValues::Chips(codes.gen_sampled_code(sig, prn, fs)?),
+        CodeForm::Fft(fs) => {
+            let code = codes.gen_sampled_code(sig, prn, fs)?;
+            let mut spectrum: Vec<_> = (code.iter())
+                .map(|&x| Complex64::new(x as f64, 0.0))
+                .collect();
+            let fft = FftPlanner::new().plan_fft_forward(spectrum.len());
+            fft.process(&mut spectrum);
+            CodeValues::Spectrum(spectrum)
+        }
+    };
+    Some(values)
+}
+
+// The codes of the satellites, each on the signal of its constellation in
+// sig, for external tools or to check them against a reference.
+pub fn write_codes(
+    writer: &mut dyn Write,
+    codes: &CodeTables,
+    sig: &str,
+    svs: &[SV],
+    form: CodeForm,
+    format: CodeFormat,
+) -> Result<(), Box<dyn Error>> {
+    let sigs = Code::get_sig_list(sig);
+    let values = (svs.iter())
+        .map(|sv| {
+            let sig = Code::get_constellation_signal(&sigs, sv.constellation).unwrap_or(sig);
+            let values = get_code_values(codes, sig, sv.prn, form);
+            Ok((sv, values.ok_or(format!("{sig}: no code for {sv}"))?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if format == CodeFormat::Csv {
+        match form {
+            CodeForm::Fft(_) => writeln!(writer, "sv,index,re,im")?,
+            _ => writeln!(writer, "sv,index,value")?,
+        }
+    }
+    for (sv, values) in values {
+        match (values, format) {
+            (CodeValues::Chips(chips), CodeFormat::Csv) => {
+                for (i, chip) in chips.iter().enumerate() {
+                    writeln!(writer, "{sv},{i},{chip}")?;
+                }
+            }
+            (CodeValues::Chips(chips), CodeFormat::Bin) => {
+                let bytes: Vec<_> = chips.iter().map(|&chip| chip as u8).collect();
+                writer.write_all(&bytes)?;
+            }
+            (CodeValues::Spectrum(spectrum), CodeFormat::Csv) => {
+                for (i, c) in spectrum.iter().enumerate() {
+                    writeln!(writer, "{sv},{i},{},{}", c.re, c.im)?;
+                }
+            }
+            (CodeValues::Spectrum(spectrum), CodeFormat::Bin) => {
+                for c in &spectrum {
+                    writer.write_all(&(c.re as f32).to_le_bytes())?;
+                    writer.write_all(&(c.im as f32).to_le_bytes())?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const CONSOLE_MAX_LINES: usize = 5000;
+const CONSOLE_LEVEL: LevelFilter = LevelFilter::Info;
+const CONSOLE_TARGET: &str = "gnss_rcv"; // not the ui toolkit's own records
+
+pub struct LogLine {
+    pub ts: String,
+    pub level: Level,
+    pub text: String,
+}
+
+// records not taken by the UI yet, the oldest dropped first
+static LOG_LINES: Lazy<Mutex<VecDeque<LogLine>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+// Forwards to env_logger as configured, and keeps the records of the receiver
+// up to CONSOLE_LEVEL for the UI console whatever the RUST_LOG filter.
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+}
+
+impl ConsoleLogger {
+    fn is_captured(metadata: &Metadata) -> bool {
+        metadata.level() <= CONSOLE_LEVEL && metadata.target().starts_with(CONSOLE_TARGET)
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        Self::is_captured(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if !Self::is_captured(record.metadata()) {
+            return;
+        }
+        let line = LogLine {
+            ts: Local::now().format("%H:%M:%S%.3f").to_string(),
+            level: record.level(),
+            text: record.args().to_string(),
+        };
+        let mut lines = LOG_LINES.lock().unwrap();
+        if lines.len() >= CONSOLE_MAX_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// instead of env_logger's init(), when running with the UI
+pub fn init_console_logger(inner: env_logger::Logger) {
+    let max_level = inner.filter().max(CONSOLE_LEVEL);
+    log::set_boxed_logger(Box::new(ConsoleLogger { inner })).expect("Failed to set the logger");
+    log::set_max_level(max_level);
+}
+
+pub fn take_log_lines() -> VecDeque<LogLine> {
+    std::mem::take(&mut *LOG_LINES.lock().unwrap())
+}
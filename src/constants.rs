@@ -1,4 +1,5 @@
 pub const P2_5: f64 = 0.03125; /* 2^-5 */
+pub const P2_6: f64 = 0.015625; /* 2^-6 */
 pub const P2_11: f64 = 4.882_812_5e-4; /* 2^-11 */
 pub const P2_19: f64 = 1.907_348_632_812_5e-6; /* 2^-19 */
 pub const P2_20: f64 = 9.536_743_164_062_5e-7; /* 2^-20 */
@@ -11,9 +12,12 @@ pub const P2_30: f64 = 9.313_225_746_154_785e-10; /* 2^-30 */
 pub const P2_31: f64 = 4.656_612_873_077_393e-10; /* 2^-31 */
 pub const P2_33: f64 = 1.164_153_218_269_348e-10; /* 2^-33 */
 pub const P2_38: f64 = 3.637_978_807_091_71e-12; /* 2^-38 */
+pub const P2_39: f64 = 1.818_989_403_545_856_5e-12; /* 2^-39 */
+pub const P2_40: f64 = 9.094_947_017_729_282e-13; /* 2^-40 */
 pub const P2_43: f64 = 1.136_868_377_216_16e-13; /* 2^-43 */
 pub const P2_50: f64 = 8.881_784_197_001_252e-16; /* 2^-50 */
 pub const P2_55: f64 = 2.775_557_561_562_891e-17; /* 2^-55 */
+pub const P2_66: f64 = 1.355_252_715_606_881e-20; /* 2^-66 */
 
 #[allow(clippy::approx_constant)]
 pub const SC2RAD: f64 = 3.141_592_653_589_8; /* semi-circle to radian (IS-GPS) */
@@ -21,3 +25,13 @@ pub const SC2RAD: f64 = 3.141_592_653_589_8; /* semi-circle to radian (IS-GPS) *
 pub const SPEED_OF_LIGHT: f64 = 299_792_458.0;
 pub const EARTH_MU_GPS: f64 = 3.9860058e14; // earth gravitational constant
 pub const EARTH_ROTATION_RATE: f64 = 7.2921151467e-5;
+// PZ-90, as the GLONASS ephemerides are integrated with
+pub const EARTH_MU_GLO: f64 = 3.9860044e14;
+pub const EARTH_J2_GLO: f64 = 1.0826257e-3; // second zonal harmonic
+pub const EARTH_RADIUS_GLO: f64 = 6378136.0; // equatorial
+pub const EARTH_ROTATION_RATE_GLO: f64 = 7.292115e-5;
+// CGCS2000, as the BeiDou ephemerides are given in
+pub const EARTH_MU_BDS: f64 = 3.986004418e14;
+pub const EARTH_ROTATION_RATE_BDS: f64 = 7.292115e-5;
+pub const BDT_GPST_OFFSET_SEC: u32 = 14; // BDT started on 2006-01-01, 14 sec behind gps time
+pub const BDT_GPST_OFFSET_WEEKS: u32 = 1356;
@@ -0,0 +1,80 @@
+use bytesize::ByteSize;
+use chrono::DateTime;
+use colored::Colorize;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::recording::IQFileType;
+use crate::recording::IQRecording;
+use crate::recording::get_file_list;
+use crate::recording::write_iq_data;
+use crate::sidecar::IQSidecar;
+use crate::source::SampleSource;
+
+const CONVERT_CHUNK_SEC: f64 = 1.0;
+
+// Copies [start_sec, start_sec + duration_sec) of the input recording to
+// output, re-encoded as out_type. A duration of 0 copies up to the end.
+#[allow(clippy::too_many_arguments)]
+pub fn cut_iq_file(
+    input: &Path,
+    file_type: &IQFileType,
+    fs: f64,
+    fi: f64,
+    sig: &str,
+    output: &Path,
+    out_type: &IQFileType,
+    start_sec: f64,
+    duration_sec: f64,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if !file_type.is_real() && out_type.is_real() {
+        tracing::warn!("{out_type} output keeps only the in-phase component");
+    }
+    let mut recording = IQRecording::new(input, fs, file_type, false)?;
+    let num_samples_total = recording.get_num_samples_total().unwrap_or_default();
+    let off_start = (start_sec * fs) as usize;
+    if off_start >= num_samples_total {
+        return Err(
+            format!("start offset {start_sec} sec is past the end of the recording").into(),
+        );
+    }
+    let off_end = if duration_sec > 0.0 {
+        usize::min(num_samples_total, off_start + (duration_sec * fs) as usize)
+    } else {
+        num_samples_total
+    };
+
+    // the snippet start time is derived from the input sidecar, if any
+    let start_time = get_file_list(input)
+        .first()
+        .and_then(|file| IQSidecar::read(file).ok().flatten())
+        .and_then(|sidecar| sidecar.start_time)
+        .and_then(|ts| DateTime::parse_from_rfc3339(&ts).ok())
+        .map(|ts| (ts + chrono::Duration::milliseconds((start_sec * 1000.0) as i64)).to_rfc3339());
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    let chunk_size = (CONVERT_CHUNK_SEC * fs) as usize;
+    let mut off = off_start;
+
+    while off < off_end {
+        let n = usize::min(chunk_size, off_end - off);
+        let iq_vec = recording.read_block(off, n)?.iq_vec;
+        write_iq_data(&mut writer, &iq_vec, out_type)?;
+        off += n;
+    }
+
+    drop(writer);
+    IQSidecar::new(output, out_type, fs, fi, sig, start_time)?.write(output)?;
+
+    let num_samples = off_end - off_start;
+    println!(
+        "wrote {}: {out_type} {} duration: {:.3} secs",
+        output.display().to_string().green(),
+        ByteSize::b((num_samples * IQRecording::get_sample_size_bytes(out_type)) as u64)
+            .to_string()
+            .bold(),
+        num_samples as f64 / fs
+    );
+    Ok(num_samples)
+}
@@ -0,0 +1,243 @@
+use bytesize::ByteSize;
+use colored::Colorize;
+use rayon::prelude::*;
+use rustfft::FftPlanner;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::code::{Code, CodeTables};
+use crate::recording::IQ_FILE_TYPES;
+use crate::recording::IQFileType;
+use crate::recording::IQRecording;
+use crate::recording::decode_iq_data;
+use crate::sample::{Float, IQSample, to_complex64};
+use crate::sidecar::IQSidecar;
+use crate::util::calc_correlation;
+use crate::util::doppler_shift;
+
+const DETECT_NUM_BYTES: usize = 1024 * 1024;
+const DETECT_SKIP_BYTES: u64 = 1024 * 1024; // skip the front-end settling time
+const DETECT_NUM_MSEC: usize = 3;
+const DETECT_DOPPLER_HZ: f64 = 6000.0;
+const DETECT_DOPPLER_STEP_HZ: f64 = 1000.0;
+const PLAUSIBILITY_THRESHOLD: f64 = 0.2;
+
+const CANDIDATE_FS: [f64; 11] = [
+    2_046_000.0,
+    2_048_000.0,
+    2_500_000.0,
+    4_000_000.0,
+    4_092_000.0,
+    5_000_000.0,
+    8_000_000.0,
+    8_184_000.0,
+    10_000_000.0,
+    16_367_600.0,
+    16_368_000.0,
+];
+
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub file_type: IQFileType,
+    pub fs: f64,
+    pub plausibility: f64, // 0..1, from sample statistics
+    pub score: f64,        // normalized acquisition peak metric
+}
+
+fn read_file_head(file_path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(file_path)?;
+    let file_size = file.metadata()?.len();
+    let mut buf = vec![];
+
+    if file_size > DETECT_SKIP_BYTES + DETECT_NUM_BYTES as u64 {
+        let mut skip = vec![0u8; DETECT_SKIP_BYTES as usize];
+        file.read_exact(&mut skip)?;
+    }
+    file.take(DETECT_NUM_BYTES as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+// Score how likely the samples were decoded with the right format: values must
+// be finite, roughly zero-mean and their distribution not too far from gaussian.
+fn get_plausibility(iq_vec: &[IQSample], file_type: &IQFileType) -> f64 {
+    if iq_vec.is_empty() {
+        return 0.0;
+    }
+    let vals: Vec<f64> = match file_type {
+        IQFileType::TypeOneInt8 => iq_vec.iter().map(|&c| to_complex64(c).re).collect(),
+        _ => iq_vec
+            .iter()
+            .map(|&c| to_complex64(c))
+            .flat_map(|c| [c.re, c.im])
+            .collect(),
+    };
+    if vals.iter().any(|v| !v.is_finite() || v.abs() > 1e3) {
+        return 0.0;
+    }
+    let n = vals.len() as f64;
+    let mean = vals.iter().sum::<f64>() / n;
+    let var = vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    if var <= 0.0 {
+        return 0.0;
+    }
+    let kurtosis = vals.iter().map(|v| (v - mean).powi(4)).sum::<f64>() / n / var.powi(2);
+    let dc_ratio = mean * mean / var;
+    let max_ratio = vals.iter().fold(0.0f64, |acc, v| acc.max(v.abs())) / var.sqrt();
+
+    let mut score = 1.0 / (1.0 + 10.0 * dc_ratio);
+    score /= 1.0 + (kurtosis - 3.0).abs() / 3.0;
+    if max_ratio > 100.0 {
+        score /= max_ratio / 100.0;
+    }
+    score
+}
+
+fn gen_l1ca_code_fft(planner: &mut FftPlanner<Float>, prn: u8, fs: f64, n: usize) -> Vec<IQSample> {
+    let code = CodeTables::default()
+        .gen_sampled_code("L1CA", prn, fs)
+        .unwrap();
+    let mut code_fft: Vec<_> = (0..n)
+        .map(|i| IQSample::new(code[i % code.len()] as Float, 0.0))
+        .collect();
+    planner.plan_fft_forward(n).process(&mut code_fft);
+    code_fft
+}
+
+// Non-coherent acquisition over all GPS PRNs. Returns the best peak to mean
+// ratio normalized by the expected noise-only peak for that many samples.
+fn get_acquisition_score(iq_vec: &[IQSample], fs: f64) -> f64 {
+    let n = (fs * Code::get_code_period("L1CA")).round() as usize;
+    if iq_vec.len() < n * DETECT_NUM_MSEC {
+        return 0.0;
+    }
+    let num_bins = (2.0 * DETECT_DOPPLER_HZ / DETECT_DOPPLER_STEP_HZ) as usize + 1;
+
+    let best = (1..=32_u8)
+        .into_par_iter()
+        .map(|prn| {
+            let mut planner = FftPlanner::new();
+            let code_fft = gen_l1ca_code_fft(&mut planner, prn, fs, n);
+            let mut best = 0.0f64;
+
+            for bin in 0..num_bins {
+                let doppler_hz = -DETECT_DOPPLER_HZ + bin as f64 * DETECT_DOPPLER_STEP_HZ;
+                let mut sum_p = vec![0.0; n];
+                for ms in 0..DETECT_NUM_MSEC {
+                    let mut samples = iq_vec[ms * n..(ms + 1) * n].to_vec();
+                    doppler_shift(&mut samples, doppler_hz, 0.0, fs);
+                    let corr = calc_correlation(&mut planner, &samples, &code_fft);
+                    for (p, c) in sum_p.iter_mut().zip(corr.iter()) {
+                        *p += to_complex64(*c).norm_sqr();
+                    }
+                }
+                let mean = sum_p.iter().sum::<f64>() / n as f64;
+                let peak = sum_p.iter().fold(0.0f64, |acc, v| acc.max(*v));
+                if mean > 0.0 {
+                    best = best.max(peak / mean);
+                }
+            }
+            best
+        })
+        .reduce(|| 0.0, f64::max);
+
+    best / (n as f64).ln()
+}
+
+pub fn detect_iq_format(file_path: &Path) -> std::io::Result<Vec<Detection>> {
+    let buf = read_file_head(file_path)?;
+    let file_size = file_path.metadata()?.len();
+    let mut res = vec![];
+
+    for file_type in IQ_FILE_TYPES.iter() {
+        let sample_size = IQRecording::get_sample_size_bytes(file_type) as u64;
+        if file_size % sample_size != 0 {
+            continue;
+        }
+        let iq_vec = decode_iq_data(&buf, file_type);
+        let plausibility = get_plausibility(&iq_vec, file_type);
+        tracing::debug!("detect: {file_type} plausibility={plausibility:.3}");
+
+        if plausibility < PLAUSIBILITY_THRESHOLD {
+            continue;
+        }
+        for fs in CANDIDATE_FS {
+            let score = get_acquisition_score(&iq_vec, fs);
+            tracing::debug!("detect: {file_type} fs={fs} score={score:.2}");
+            res.push(Detection {
+                file_type: file_type.clone(),
+                fs,
+                plausibility,
+                score,
+            });
+        }
+    }
+    res.sort_by(|a, b| (b.score * b.plausibility).total_cmp(&(a.score * a.plausibility)));
+    Ok(res)
+}
+
+// Cheap check of the user-provided format; only runs the full detection when
+// the samples don't look right.
+pub fn check_iq_format(file_path: &Path, file_type: &IQFileType) -> std::io::Result<bool> {
+    let buf = read_file_head(file_path)?;
+    let iq_vec = decode_iq_data(&buf, file_type);
+    Ok(get_plausibility(&iq_vec, file_type) >= PLAUSIBILITY_THRESHOLD)
+}
+
+pub fn print_detection_report(detections: &[Detection]) {
+    println!("{}", "IQ format detection:".bold());
+    for d in detections.iter().take(5) {
+        println!(
+            "  {:12} fs={:10.1} KHz plausibility={:.2} score={:.2}",
+            d.file_type.to_string(),
+            d.fs / 1000.0,
+            d.plausibility,
+            d.score
+        );
+    }
+    match detections.first() {
+        Some(d) => println!(
+            "best guess: {} fs={:.1} KHz",
+            d.file_type.to_string().green(),
+            d.fs / 1000.0
+        ),
+        None => println!("{}", "no plausible IQ format found".red()),
+    }
+}
+
+// Size and duration of a recording as file_type at fs, what its sidecar says
+// and what its samples look like.
+pub fn print_file_info(file_path: &Path, file_type: &IQFileType, fs: f64) -> std::io::Result<()> {
+    let file_size = file_path.metadata()?.len();
+    println!(
+        "{}: {}",
+        file_path.display().to_string().bold(),
+        ByteSize::b(file_size)
+    );
+    let sidecar = IQSidecar::read(file_path)?;
+    let (file_type, fs) = match &sidecar {
+        Some(sidecar) => (
+            sidecar.file_type.parse().unwrap_or(file_type.clone()),
+            sidecar.fs,
+        ),
+        None => (file_type.clone(), fs),
+    };
+    let num_samples = file_size / IQRecording::get_sample_size_bytes(&file_type) as u64;
+    println!(
+        "  {file_type} fs={:.1} KHz: {num_samples} samples, {:.3} secs",
+        fs / 1000.0,
+        num_samples as f64 / fs
+    );
+    match &sidecar {
+        Some(sidecar) => println!(
+            "  sidecar: fi={:.1} KHz sig={} start={} sha256={}",
+            sidecar.fi / 1000.0,
+            sidecar.sig,
+            sidecar.start_time.as_deref().unwrap_or("unknown"),
+            sidecar.sha256
+        ),
+        None => println!("  no sidecar"),
+    }
+    print_detection_report(&detect_iq_format(file_path)?);
+    Ok(())
+}
@@ -1,139 +1,113 @@
-use rustfft::num_complex::Complex64;
-use std::collections::VecDeque;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::thread;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 use crate::code::Code;
-use crate::receiver::IQReader;
+use crate::source::{DropPolicy, SampleQueue};
 
-pub struct RtlSdrDevice {
-    controller: rtlsdr_mt::Controller,
-    iq_deque: Arc<Mutex<VecDeque<Vec<Complex64>>>>,
-    num_samples_total: Arc<Mutex<usize>>,
-    num_samples: Arc<Mutex<usize>>,
-    num_sleep: u64,
-}
+pub const DEVICE_QUEUE_SEC: f64 = 2.0;
+const DEVICE_QUEUE_MIN_SEC: f64 = 0.1;
 
-impl Drop for RtlSdrDevice {
-    fn drop(&mut self) {
-        log::warn!(
-            "rtlsdr: stopping read. num_samples={}",
-            self.num_samples.lock().unwrap()
-        );
-        log::warn!("rtlsdr: num_sleep={}", self.num_sleep);
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum DeviceType {
+    RtlSdr,
+    HackRf,
+    Airspy,
+    Uhd,
+}
 
-        self.controller.cancel_async_read();
+impl FromStr for DeviceType {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<DeviceType, Self::Err> {
+        match input {
+            "rtlsdr" => Ok(DeviceType::RtlSdr),
+            "hackrf" => Ok(DeviceType::HackRf),
+            "airspy" => Ok(DeviceType::Airspy),
+            "uhd" => Ok(DeviceType::Uhd),
+            _ => Err(format!("Failed to parse {}", input).into()),
+        }
     }
 }
 
-impl IQReader for RtlSdrDevice {
-    fn get_iq_data(
-        &mut self,
-        _off_samples: usize,
-        num_samples: usize,
-    ) -> Result<Vec<Complex64>, Box<dyn std::error::Error>> {
-        loop {
-            if *self.num_samples.lock().unwrap() >= num_samples {
-                break;
-            }
-            thread::sleep(std::time::Duration::from_millis(1));
-            self.num_sleep += 1;
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeviceType::RtlSdr => write!(f, "rtlsdr"),
+            DeviceType::HackRf => write!(f, "hackrf"),
+            DeviceType::Airspy => write!(f, "airspy"),
+            DeviceType::Uhd => write!(f, "uhd"),
         }
-        let mut vec = vec![];
-        let mut iq_deq = self.iq_deque.lock().unwrap();
+    }
+}
 
-        let v_front = iq_deq.front_mut().unwrap();
-        let n = usize::min(num_samples, v_front.len());
-        for v in v_front.iter().take(n) {
-            vec.push(*v);
-        }
-        let _ = v_front.drain(0..n);
-        if v_front.is_empty() {
-            let _ = iq_deq.pop_front();
-        }
+// front-end settings; None gains leave the choice to the backend.
+#[derive(Clone, Debug)]
+pub struct DeviceConfig {
+    pub device_type: DeviceType,
+    pub device_args: String, // e.g. "type=b200" for uhd
+    pub gain: Option<f64>,   // overall rx gain in dB
+    pub lna_gain: Option<u32>,
+    pub mixer_gain: Option<u32>,
+    pub vga_gain: Option<u32>,
+    pub amp: bool,
+    pub clock_source: Option<String>, // internal, external, gpsdo
+    pub agc: Option<bool>,            // None: backend default
+    pub bias_tee: Option<bool>,
+    pub ppm: i32,
+    pub queue_sec: f64, // samples buffered before dropping some
+    pub drop_policy: DropPolicy,
+}
 
-        if n < num_samples {
-            let v_front = iq_deq.front_mut().unwrap();
-            for i in n..num_samples {
-                vec.push(v_front[i - n]);
-            }
-            let _ = v_front.drain(0..num_samples - n);
-        }
+impl DeviceConfig {
+    pub fn has_external_reference(&self) -> bool {
+        matches!(
+            self.clock_source.as_deref(),
+            Some("external") | Some("gpsdo")
+        )
+    }
 
-        *self.num_samples.lock().unwrap() -= num_samples;
+    // tuning frequency compensating for the oscillator error, for front-ends
+    // without a native frequency correction.
+    pub fn get_tuning_freq(&self, sig: &str) -> f64 {
+        Code::get_code_freq(sig) / (1.0 + self.ppm as f64 * 1e-6)
+    }
 
-        Ok(vec)
+    pub fn new_sample_queue(&self, fs: f64) -> SampleQueue {
+        let max_samples = (f64::max(self.queue_sec, DEVICE_QUEUE_MIN_SEC) * fs) as usize;
+        SampleQueue::new(max_samples, self.drop_policy)
     }
 }
 
-impl RtlSdrDevice {
-    #[allow(clippy::result_unit_err)]
-    pub fn new(sig: &str, fs: f64) -> Result<RtlSdrDevice, ()> {
-        let devices = rtlsdr_mt::devices();
-
-        for dev in devices {
-            log::warn!("found rtl-sdr: {:?}", dev);
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            device_type: DeviceType::RtlSdr,
+            device_args: String::new(),
+            gain: None,
+            lna_gain: None,
+            mixer_gain: None,
+            vga_gain: None,
+            amp: false,
+            clock_source: None,
+            agc: None,
+            bias_tee: None,
+            ppm: 0,
+            queue_sec: DEVICE_QUEUE_SEC,
+            drop_policy: DropPolicy::DropOldest,
         }
+    }
+}
 
-        let (ctl, mut reader) = rtlsdr_mt::open(0)?;
-        let mut m = Self {
-            controller: ctl,
-            iq_deque: Arc::new(Mutex::new(VecDeque::new())),
-            num_samples_total: Arc::new(Mutex::new(0)),
-            num_samples: Arc::new(Mutex::new(0)),
-            num_sleep: 0,
-        };
-
-        let mut tunes = rtlsdr_mt::TunerGains::default();
-        let gains = m.controller.tuner_gains(&mut tunes);
-        log::warn!("gain: {:?}", gains);
-        let g_max = gains.iter().max().unwrap();
-
-        log::warn!("Using gain: {g_max}");
-
-        //m.controller.enable_agc().expect("Failed to enable agc");
-        m.controller
-            .set_tuner_gain(*g_max)
-            .expect("Failed to enable agc");
-        m.controller
-            .set_bias_tee(1)
-            .expect("Failed to set bias tee");
-        m.controller
-            .set_center_freq(Code::get_code_freq(sig) as u32)
-            .expect("Failed to change center freq");
-        m.controller
-            .set_sample_rate(fs as u32)
-            .expect("Failed to change sample rate");
-        m.controller.reset_buffer().expect("Failed to reset buffer");
-        let ppm = m.controller.ppm();
-
-        log::warn!("ppm={ppm}");
-
-        let iq_deq = m.iq_deque.clone();
-        let num_samples_total = m.num_samples_total.clone();
-        let num_samples = m.num_samples.clone();
-        thread::spawn(move || {
-            loop {
-                log::warn!("starting async_read");
-                reader
-                    .read_async(0, 0, |array| {
-                        let mut v = vec![Complex64::default(); array.len()];
-                        for i in 0..array.len() / 2 {
-                            let re = (array[2 * i] as f64 - 127.3) / 128.0;
-                            let im = (array[2 * i + 1] as f64 - 127.3) / 128.0;
-                            v[i] = Complex64 { re, im };
-                        }
-
-                        let n = v.len();
-                        iq_deq.lock().unwrap().push_back(v);
-                        *num_samples.lock().unwrap() += n;
-                        *num_samples_total.lock().unwrap() += n;
-                    })
-                    .unwrap();
-            }
-        });
+// names of the rtl-sdr dongles plugged in, by index
+#[cfg(all(unix, feature = "rtlsdr"))]
+pub fn get_rtlsdr_devices() -> Vec<String> {
+    rtlsdr_mt::devices()
+        .map(|dev| dev.to_string_lossy().into_owned())
+        .collect()
+}
 
-        Ok(m)
-    }
+#[cfg(not(all(unix, feature = "rtlsdr")))]
+pub fn get_rtlsdr_devices() -> Vec<String> {
+    vec![]
 }
@@ -0,0 +1,27 @@
+use crate::sample::{Float, IQSample};
+
+const PI: f64 = std::f64::consts::PI;
+
+// Shifts a real-sampled IF signal down to baseband. The image at -2*fi and the
+// out-of-band noise are then removed by the low-pass filter of the resampler.
+pub struct IFMixer {
+    fi: f64,
+    fs: f64,
+    phase: f64, // in cycles, kept in [0, 1) across calls
+}
+
+impl IFMixer {
+    pub fn new(fi: f64, fs: f64) -> Self {
+        tracing::info!("real samples: mixing down from fi={:.1} KHz", fi / 1000.0);
+        Self { fi, fs, phase: 0.0 }
+    }
+
+    pub fn mix(&mut self, iq_vec: &mut [IQSample]) {
+        let step = self.fi / self.fs;
+        for v in iq_vec.iter_mut() {
+            // the real signal power is split between +fi and -fi
+            *v *= IQSample::from_polar(2.0, (-2.0 * PI * self.phase) as Float);
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
@@ -1,13 +1,31 @@
 use colored::Colorize;
+use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
 use gnss_rtk::prelude::Epoch;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     constants::{P2_5, P2_19, P2_29, P2_31, P2_33, P2_43, P2_55, SC2RAD},
-    util::{getbits, getbits2, getbitu, getbitu2},
+    util::{getbits, getbits2, getbitu, getbitu2, setbitu, setbitu2},
 };
 
-#[derive(Default, Clone, Copy)]
+const SECS_PER_WEEK: u32 = 7 * 24 * 60 * 60;
+const FIT_INTERVAL_GPS_SEC: f64 = 4.0 * 3600.0;
+const FIT_INTERVAL_QZS_SEC: f64 = 2.0 * 3600.0;
+
+// QZSS satellites broadcast the LNAV of gps on the L1CA codes 193 to 202,
+// they go by that PRN
+pub fn is_qzss(sv: SV) -> bool {
+    sv.constellation == Constellation::QZSS
+}
+
+// a parameter in units of scale, as a field of a subframe: signed ones as
+// their two's complement
+fn to_field(v: f64, scale: f64) -> u32 {
+    (v / scale).round() as i64 as u32
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
 pub struct Ephemeris {
     pub sv: SV,
     pub tow: u32,
@@ -47,7 +65,22 @@ pub struct Ephemeris {
     pub deln: f64, // Mean Motion Difference From Computed Value
     pub toc: u32, // Time of Clock
     pub toe: u32, // Reference Time Ephemeris
-    pub fit: u32, // fit interval (h)
+    pub fit: u32, // fit interval flag, 0: GPS 4 h, QZS 2 h
+    // GLO: the orbit as a state at toe, PZ-90 ecef, integrated to any time;
+    // the clock as -tau_n in f0 and gamma_n in f1, toc = toe
+    #[serde(default)]
+    pub pos: [f64; 3], // GLO: position (m)
+    #[serde(default)]
+    pub vel: [f64; 3], // GLO: velocity (m/s)
+    #[serde(default)]
+    pub acc: [f64; 3], // GLO: lunisolar acceleration (m/s^2)
+    #[serde(default)]
+    pub frq: i32, // GLO: frequency channel
+    // the SBAS corrections of a satellite, applied for a fix
+    #[serde(default)]
+    pub prc: f64, // fast correction, added to the pseudorange (m)
+    #[serde(default)]
+    pub dpos: [f64; 3], // long-term correction of the position (m)
 }
 
 impl Ephemeris {
@@ -57,11 +90,34 @@ impl Ephemeris {
             ..Default::default()
         }
     }
+    // fit interval of the orbit, centered on toe: the flag set only tells it
+    // is longer, so the shortest one
+    pub fn get_fit_interval_sec(&self) -> f64 {
+        if is_qzss(self.sv) {
+            FIT_INTERVAL_QZS_SEC
+        } else {
+            FIT_INTERVAL_GPS_SEC
+        }
+    }
+
+    // gps times of the week, tow, toe and toc once subframe 1 gave the week
+    pub fn set_gpst(&mut self) {
+        let week_to_secs = self.week * SECS_PER_WEEK;
+        let tow_secs_gpst = week_to_secs + self.tow;
+        let toe_secs_gpst = week_to_secs + self.toe;
+        let toc_secs_gpst = week_to_secs + self.toc;
+
+        self.tow_gpst = Epoch::from_gpst_seconds(tow_secs_gpst.into());
+        self.toe_gpst = Epoch::from_gpst_seconds(toe_secs_gpst.into());
+        self.toc_gpst = Epoch::from_gpst_seconds(toc_secs_gpst.into());
+    }
+
     pub fn nav_decode_lnav_subframe1(&mut self, buf: &[u8], sv: SV) {
         self.tow = getbitu(buf, 30, 17) * 6;
         // GPS Time started on Jan 6, 1980
         // 1st GPS Time Epoch ended on 21 August 1999
         // 2nd GPS Time Epoch ended on 06 April 2019
+        // QZSS: the same gps week modulo 1024, with the same rollovers
         self.week = getbitu(buf, 60, 10) + 2048;
         // 00 = Invalid,
         // 01 = P-code ON,
@@ -82,7 +138,7 @@ impl Ephemeris {
         self.f1 = getbits(buf, 248, 16) as f64 * P2_43;
         self.f0 = getbits(buf, 270, 22) as f64 * P2_31;
 
-        log::warn!(
+        tracing::debug!(
             "{sv}: {} tow={} week={} code={} sva={} svh={} iodc={} tgd={:+e} toc={} f0={:+e} f1={:+e} f2={:+e}",
             "subframe-1".blue(),
             self.tow,
@@ -113,7 +169,7 @@ impl Ephemeris {
         self.toe = getbitu(buf, 270, 16) * 16;
         self.fit = getbitu(buf, 286, 1);
 
-        log::warn!(
+        tracing::debug!(
             "{sv}: {} tow={} a={} iode={} crs={} crc={} cuc={:+e} cus={:+e} ecc={} m0={} toe={}",
             "subframe-2".blue(),
             self.tow,
@@ -141,7 +197,7 @@ impl Ephemeris {
         self.iode = getbitu(buf, 270, 8);
         self.i_dot = getbits(buf, 278, 14) as f64 * P2_43 * SC2RAD;
 
-        log::warn!(
+        tracing::debug!(
             "{sv}: {} tow={} cic={:+e} cis={:+e} omg={} omg0={} omgd={:+e} i0={} idot={:+e}",
             "subframe-3".blue(),
             self.tow,
@@ -154,4 +210,62 @@ impl Ephemeris {
             self.i_dot
         );
     }
+
+    // The subframe id of an LNAV frame, the reverse of the decoding above: its
+    // data bits, without parity, as nav_decode_lnav_subframe*() take them.
+    // tow is that of the next subframe, as in the HOW. Subframes 4 and 5 carry
+    // no page.
+    pub fn nav_encode_lnav_subframe(&self, id: u32, tow: u32) -> Vec<u8> {
+        let mut buf = vec![0; 300];
+        setbitu(&mut buf, 0, 8, 0x8b);
+        setbitu(&mut buf, 8, 14, self.tlm);
+        setbitu(&mut buf, 30, 17, tow / 6);
+        setbitu(&mut buf, 49, 3, id);
+        match id {
+            1 => self.nav_encode_lnav_subframe1(&mut buf),
+            2 => self.nav_encode_lnav_subframe2(&mut buf),
+            3 => self.nav_encode_lnav_subframe3(&mut buf),
+            _ => {}
+        }
+        buf
+    }
+
+    fn nav_encode_lnav_subframe1(&self, buf: &mut [u8]) {
+        setbitu(buf, 60, 10, self.week % 1024);
+        setbitu(buf, 70, 2, self.code);
+        setbitu(buf, 72, 4, self.sva);
+        setbitu(buf, 76, 6, self.svh);
+        setbitu2(buf, 82, 2, 210, 8, self.iodc);
+        setbitu(buf, 90, 1, self.flag);
+        setbitu(buf, 196, 8, to_field(self.tgd, P2_31));
+        setbitu(buf, 218, 16, self.toc / 16);
+        setbitu(buf, 240, 8, to_field(self.f2, P2_55));
+        setbitu(buf, 248, 16, to_field(self.f1, P2_43));
+        setbitu(buf, 270, 22, to_field(self.f0, P2_31));
+    }
+
+    fn nav_encode_lnav_subframe2(&self, buf: &mut [u8]) {
+        setbitu(buf, 60, 8, self.iode);
+        setbitu(buf, 68, 16, to_field(self.crs, P2_5));
+        setbitu(buf, 90, 16, to_field(self.deln, P2_43 * SC2RAD));
+        setbitu2(buf, 106, 8, 120, 24, to_field(self.m0, P2_31 * SC2RAD));
+        setbitu(buf, 150, 16, to_field(self.cuc, P2_29));
+        setbitu2(buf, 166, 8, 180, 24, to_field(self.ecc, P2_33));
+        setbitu(buf, 210, 16, to_field(self.cus, P2_29));
+        setbitu2(buf, 226, 8, 240, 24, to_field(self.a.sqrt(), P2_19));
+        setbitu(buf, 270, 16, self.toe / 16);
+        setbitu(buf, 286, 1, self.fit);
+    }
+
+    fn nav_encode_lnav_subframe3(&self, buf: &mut [u8]) {
+        setbitu(buf, 60, 16, to_field(self.cic, P2_29));
+        setbitu2(buf, 76, 8, 90, 24, to_field(self.omg0, P2_31 * SC2RAD));
+        setbitu(buf, 120, 16, to_field(self.cis, P2_29));
+        setbitu2(buf, 136, 8, 150, 24, to_field(self.i0, P2_31 * SC2RAD));
+        setbitu(buf, 180, 16, to_field(self.crc, P2_5));
+        setbitu2(buf, 196, 8, 210, 24, to_field(self.omg, P2_31 * SC2RAD));
+        setbitu(buf, 240, 24, to_field(self.omg_dot, P2_43 * SC2RAD));
+        setbitu(buf, 270, 8, self.iode);
+        setbitu(buf, 278, 14, to_field(self.i_dot, P2_43 * SC2RAD));
+    }
 }
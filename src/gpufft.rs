@@ -0,0 +1,286 @@
+use gnss_rs::sv::SV;
+use rayon::ThreadPool;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::ffi::{c_int, c_void};
+
+use crate::channel::Channel;
+use crate::sample::{Float, IQSample};
+use crate::threads::install;
+
+type CufftHandle = c_int;
+
+const CUDA_SUCCESS: c_int = 0;
+const CUDA_MEMCPY_HOST_TO_DEVICE: c_int = 1;
+const CUDA_MEMCPY_DEVICE_TO_HOST: c_int = 2;
+const CUFFT_SUCCESS: c_int = 0;
+const CUFFT_FORWARD: c_int = -1;
+const CUFFT_INVERSE: c_int = 1;
+#[cfg(not(feature = "f32"))]
+const CUFFT_TYPE: c_int = 0x69; // CUFFT_Z2Z
+#[cfg(feature = "f32")]
+const CUFFT_TYPE: c_int = 0x29; // CUFFT_C2C
+
+#[link(name = "cudart")]
+unsafe extern "C" {
+    fn cudaGetDeviceCount(count: *mut c_int) -> c_int;
+    fn cudaMalloc(ptr: *mut *mut c_void, size: usize) -> c_int;
+    fn cudaFree(ptr: *mut c_void) -> c_int;
+    fn cudaMemcpy(dst: *mut c_void, src: *const c_void, count: usize, kind: c_int) -> c_int;
+}
+
+#[link(name = "cufft")]
+unsafe extern "C" {
+    fn cufftPlanMany(
+        plan: *mut CufftHandle,
+        rank: c_int,
+        n: *mut c_int,
+        inembed: *mut c_int,
+        istride: c_int,
+        idist: c_int,
+        onembed: *mut c_int,
+        ostride: c_int,
+        odist: c_int,
+        fft_type: c_int,
+        batch: c_int,
+    ) -> c_int;
+    fn cufftDestroy(plan: CufftHandle) -> c_int;
+    #[cfg(not(feature = "f32"))]
+    fn cufftExecZ2Z(
+        plan: CufftHandle,
+        idata: *mut c_void,
+        odata: *mut c_void,
+        direction: c_int,
+    ) -> c_int;
+    #[cfg(feature = "f32")]
+    fn cufftExecC2C(
+        plan: CufftHandle,
+        idata: *mut c_void,
+        odata: *mut c_void,
+        direction: c_int,
+    ) -> c_int;
+}
+
+fn check(res: c_int, what: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if res != CUDA_SUCCESS && res != CUFFT_SUCCESS {
+        return Err(format!("cuda: {what} failed: {res}").into());
+    }
+    Ok(())
+}
+
+// Batched FFTs on a CUDA device through cuFFT. The acquisition correlations of
+// all the channels of a band go in a single forward and a single inverse
+// dispatch; the product with the prn codes is done on the host.
+pub struct GpuFft {
+    plan: Option<CufftHandle>,
+    n: usize,
+    batch: usize,
+    dev_buf: *mut c_void,
+    num_dispatch: u64,
+}
+
+// the plan and the buffer are only used from the thread owning the GpuFft.
+unsafe impl Send for GpuFft {}
+
+impl Drop for GpuFft {
+    fn drop(&mut self) {
+        tracing::debug!("cuda: num_dispatch={}", self.num_dispatch);
+        self.release();
+    }
+}
+
+impl GpuFft {
+    pub fn new() -> Result<GpuFft, Box<dyn std::error::Error>> {
+        let mut count = 0;
+        check(
+            unsafe { cudaGetDeviceCount(&mut count) },
+            "get_device_count",
+        )?;
+        if count == 0 {
+            return Err("cuda: no device found".into());
+        }
+        tracing::info!(
+            "cuda: {count} device(s), fft in {}",
+            std::any::type_name::<Float>()
+        );
+
+        Ok(Self {
+            plan: None,
+            n: 0,
+            batch: 0,
+            dev_buf: std::ptr::null_mut(),
+            num_dispatch: 0,
+        })
+    }
+
+    fn release(&mut self) {
+        unsafe {
+            if let Some(plan) = self.plan.take() {
+                cufftDestroy(plan);
+            }
+            if !self.dev_buf.is_null() {
+                cudaFree(self.dev_buf);
+                self.dev_buf = std::ptr::null_mut();
+            }
+        }
+    }
+
+    // plans are costly: only re-plan when the shape changes
+    fn prepare(&mut self, n: usize, batch: usize) -> Result<(), Box<dyn std::error::Error>> {
+        if self.plan.is_some() && self.n == n && self.batch == batch {
+            return Ok(());
+        }
+        self.release();
+
+        let size = n * batch * std::mem::size_of::<IQSample>();
+        check(unsafe { cudaMalloc(&mut self.dev_buf, size) }, "malloc")?;
+
+        let mut plan = 0;
+        let mut dims = [n as c_int];
+        check(
+            unsafe {
+                cufftPlanMany(
+                    &mut plan,
+                    1,
+                    dims.as_mut_ptr(),
+                    std::ptr::null_mut(),
+                    1,
+                    n as c_int,
+                    std::ptr::null_mut(),
+                    1,
+                    n as c_int,
+                    CUFFT_TYPE,
+                    batch as c_int,
+                )
+            },
+            "plan_many",
+        )?;
+        self.plan = Some(plan);
+        self.n = n;
+        self.batch = batch;
+        tracing::debug!("cuda: fft plan n={n} batch={batch}");
+        Ok(())
+    }
+
+    // in-place transform of the batch back to back in data
+    fn exec(
+        &mut self,
+        data: &mut [IQSample],
+        direction: c_int,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let size = std::mem::size_of_val(data);
+        let plan = self.plan.unwrap();
+        unsafe {
+            check(
+                cudaMemcpy(
+                    self.dev_buf,
+                    data.as_ptr() as *const c_void,
+                    size,
+                    CUDA_MEMCPY_HOST_TO_DEVICE,
+                ),
+                "memcpy",
+            )?;
+            #[cfg(not(feature = "f32"))]
+            check(
+                cufftExecZ2Z(plan, self.dev_buf, self.dev_buf, direction),
+                "exec",
+            )?;
+            #[cfg(feature = "f32")]
+            check(
+                cufftExecC2C(plan, self.dev_buf, self.dev_buf, direction),
+                "exec",
+            )?;
+            check(
+                cudaMemcpy(
+                    data.as_mut_ptr() as *mut c_void,
+                    self.dev_buf,
+                    size,
+                    CUDA_MEMCPY_DEVICE_TO_HOST,
+                ),
+                "memcpy",
+            )?;
+        }
+        self.num_dispatch += 1;
+        Ok(())
+    }
+
+    // Circular correlations of each n-sample vector of data with the prn code
+    // of its group: data holds groups of group_len vectors, one per code. The
+    // products on the cpu run on pool.
+    pub fn correlate(
+        &mut self,
+        data: &mut [IQSample],
+        n: usize,
+        group_len: usize,
+        prn_code_ffts: &[&[IQSample]],
+        pool: Option<&ThreadPool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(data.len(), n * group_len * prn_code_ffts.len());
+        self.prepare(n, data.len() / n)?;
+
+        self.exec(data, CUFFT_FORWARD)?;
+        install(pool, || {
+            data.par_chunks_mut(n * group_len)
+                .zip(prn_code_ffts.par_iter())
+                .for_each(|(group, code_fft)| {
+                    for v in group.chunks_mut(n) {
+                        for (x, c) in v.iter_mut().zip(code_fft.iter()) {
+                            *x *= c.conj();
+                        }
+                    }
+                })
+        });
+        self.exec(data, CUFFT_INVERSE)?;
+
+        let len = n as Float;
+        install(pool, || data.par_iter_mut().for_each(|x| *x /= len));
+        Ok(())
+    }
+
+    // Runs the acquisition step of the acquiring channels of a band that are
+    // due, e.g. not while lagging, in one batch. Returns the channels
+    // processed, the others still need their process_samples() call.
+    pub fn process_acquisition(
+        &mut self,
+        channels: &mut BTreeMap<SV, Channel>,
+        iq_vec: &[IQSample],
+        ts_sec: f64,
+        is_due: impl Fn(&Channel) -> bool,
+        pool: Option<&ThreadPool>,
+    ) -> Result<Vec<SV>, Box<dyn std::error::Error>> {
+        // longer codes and coherent integrations over several code periods
+        // gather their periods first: acquired on the cpu
+        let mut acq: Vec<_> = (channels.values_mut())
+            .filter(|c| {
+                c.is_acquiring()
+                    && is_due(c)
+                    && c.get_code_msec() == 1
+                    && c.get_acq_coherent_periods() == 1
+            })
+            .collect();
+        if acq.is_empty() {
+            return Ok(vec![]);
+        }
+        let n = acq[0].get_prn_code_fft().len();
+        acq.retain(|c| c.get_prn_code_fft().len() == n);
+
+        let inputs: Vec<_> = install(pool, || {
+            acq.par_iter()
+                .map(|c| c.get_acquisition_inputs(iq_vec))
+                .collect()
+        });
+        let group_len = inputs[0].len() / n;
+        let mut data = inputs.concat();
+
+        let prn_code_ffts: Vec<_> = acq.iter().map(|c| c.get_prn_code_fft()).collect();
+        self.correlate(&mut data, n, group_len, &prn_code_ffts, pool)?;
+
+        install(pool, || {
+            acq.par_iter_mut()
+                .zip(data.par_chunks(n * group_len))
+                .for_each(|(c, corr)| c.process_acquisition_corr(corr, ts_sec))
+        });
+
+        Ok(acq.iter().map(|c| c.sv).collect())
+    }
+}
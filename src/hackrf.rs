@@ -0,0 +1,141 @@
+use hackrfone::{HackRfOne, RxMode, UnknownMode};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::device::DeviceConfig;
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleQueue, SampleSource};
+
+const HACKRF_DEFAULT_LNA_GAIN: u32 = 32; // 0-40 dB, 8 dB steps
+const HACKRF_DEFAULT_VGA_GAIN: u32 = 30; // 0-62 dB, 2 dB steps
+const HACKRF_MIN_BASEBAND_FILTER_HZ: u32 = 1_750_000;
+
+pub struct HackRfDevice {
+    queue: Arc<SampleQueue>,
+    num_sleep: u64,
+    stop_req: Arc<AtomicBool>,
+    read_th: Option<JoinHandle<()>>,
+}
+
+impl Drop for HackRfDevice {
+    fn drop(&mut self) {
+        tracing::info!(
+            "hackrf: stopping read. num_samples={} dropped={}",
+            self.queue.get_num_samples_total(),
+            self.queue.get_num_dropped_total()
+        );
+        tracing::debug!("hackrf: num_sleep={}", self.num_sleep);
+
+        self.stop_req.store(true, Ordering::SeqCst);
+        if let Some(th) = self.read_th.take() {
+            let _ = th.join();
+        }
+    }
+}
+
+impl SampleSource for HackRfDevice {
+    fn read_block(
+        &mut self,
+        _off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        loop {
+            if let Some(block) = self.queue.pop(num_samples) {
+                return Ok(block);
+            }
+            if self.read_th.as_ref().is_none_or(|th| th.is_finished()) {
+                return Err("hackrf: read thread exited".into());
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+            self.num_sleep += 1;
+        }
+    }
+
+    fn get_num_samples_queued(&self) -> Option<usize> {
+        Some(self.queue.get_num_samples())
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+// the hackrf streams interleaved signed 8-bit I/Q samples
+fn decode_hackrf_samples(buf: &[u8]) -> Vec<IQSample> {
+    buf.chunks_exact(2)
+        .map(|b| IQSample {
+            re: b[0] as i8 as Float / 128.0,
+            im: b[1] as i8 as Float / 128.0,
+        })
+        .collect()
+}
+
+fn configure(
+    dev: &mut HackRfOne<UnknownMode>,
+    sig: &str,
+    fs: f64,
+    config: &DeviceConfig,
+) -> Result<(), hackrfone::Error> {
+    let lna_gain = config.lna_gain.unwrap_or(HACKRF_DEFAULT_LNA_GAIN);
+    let vga_gain = config.vga_gain.unwrap_or(HACKRF_DEFAULT_VGA_GAIN);
+    let filter_hz = u32::max(HACKRF_MIN_BASEBAND_FILTER_HZ, (0.75 * fs) as u32);
+
+    tracing::info!(
+        "hackrf: lna_gain={lna_gain} vga_gain={vga_gain} amp={} filter={:.1} KHz",
+        config.amp,
+        filter_hz as f64 / 1000.0
+    );
+
+    dev.set_sample_rate(fs as u32, 1)?;
+    dev.set_baseband_filter_bandwidth(filter_hz)?;
+    dev.set_freq(config.get_tuning_freq(sig).round() as u64)?;
+    dev.set_lna_gain(lna_gain as u16)?;
+    dev.set_vga_gain(vga_gain as u16)?;
+    dev.set_amp_enable(config.amp)?;
+    Ok(())
+}
+
+impl HackRfDevice {
+    pub fn new(
+        sig: &str,
+        fs: f64,
+        config: &DeviceConfig,
+    ) -> Result<HackRfDevice, Box<dyn std::error::Error>> {
+        let mut dev = HackRfOne::new().ok_or("no hackrf found")?;
+
+        tracing::info!("found hackrf: {:?}", dev.board_id());
+
+        configure(&mut dev, sig, fs, config)?;
+        let mut dev: HackRfOne<RxMode> = dev.into_rx_mode()?;
+
+        let queue = Arc::new(config.new_sample_queue(fs));
+        let stop_req = Arc::new(AtomicBool::new(false));
+
+        let queue_clone = queue.clone();
+        let stop_req_clone = stop_req.clone();
+        let read_th = thread::spawn(move || {
+            tracing::debug!("hackrf: starting rx");
+            while !stop_req_clone.load(Ordering::SeqCst) {
+                match dev.rx() {
+                    Ok(buf) => queue_clone.push(decode_hackrf_samples(&buf)),
+                    Err(e) => {
+                        tracing::warn!("hackrf: rx error: {e:?}");
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = dev.stop_rx() {
+                tracing::warn!("hackrf: failed to stop rx: {e:?}");
+            }
+        });
+
+        Ok(Self {
+            queue,
+            num_sleep: 0,
+            stop_req,
+            read_th: Some(read_th),
+        })
+    }
+}
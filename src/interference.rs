@@ -0,0 +1,166 @@
+use gnss_rs::sv::SV;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use crate::sample::IQSample;
+
+pub const INTERFERENCE_PERIOD_SEC: f64 = 0.1; // of signal between two checks
+const AGC_THRESHOLD_DB: f64 = 3.0; // input power over its baseline
+const NOISE_THRESHOLD_DB: f64 = 3.0; // mean cn0 loss of the tracked satellites
+const NOISE_MIN_SVS: usize = 2;
+const LOSS_WINDOW_SEC: f64 = 1.0;
+const LOSS_THRESHOLD: usize = 3; // channels lost within LOSS_WINDOW_SEC
+const BASELINE_TC_SEC: f64 = 30.0; // time constant of the baselines
+const CLEAR_SEC: f64 = 2.0; // without any sign of it, to end an event
+const MAX_INTERFERENCE_EVENTS: usize = 100;
+
+// What points at interference at the moment, and since when it's been
+// detected if it is.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct InterferenceStatus {
+    pub agc_db: f64,        // power of the raw samples over its baseline
+    pub noise_rise_db: f64, // mean cn0 loss of the tracked satellites
+    pub num_lost: usize,    // channels lost within the last second
+    pub since_sec: Option<f64>,
+}
+
+impl InterferenceStatus {
+    pub fn is_detected(&self) -> bool {
+        self.since_sec.is_some()
+    }
+}
+
+impl fmt::Display for InterferenceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "agc={:+.1} dB noise={:+.1} dB lost={}",
+            self.agc_db, self.noise_rise_db, self.num_lost
+        )
+    }
+}
+
+// An interval of signal time with interference detected, and how bad it got.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct InterferenceEvent {
+    pub start_sec: f64,
+    pub end_sec: Option<f64>, // still going on otherwise
+    pub max_agc_db: f64,
+    pub max_noise_rise_db: f64,
+    pub max_lost: usize,
+}
+
+// Wideband jamming raises the power at the input of the front-end and, once
+// its AGC compensates, the noise floor under the satellites: all their C/N0
+// drop together and several channels get lost at once. Each of these, over
+// baselines learnt in the clear, is a sign of it.
+#[derive(Default)]
+pub struct InterferenceDetector {
+    power_sum: f64,
+    num_samples: usize,
+    agc_baseline: Option<f64>,        // mean power of the raw samples
+    cn0_baselines: BTreeMap<SV, f64>, // dB-Hz
+    losses: VecDeque<f64>,
+    last_update_sec: Option<f64>,
+    last_sign_sec: f64,
+    status: InterferenceStatus,
+    events: Vec<InterferenceEvent>,
+}
+
+impl InterferenceDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_samples(&mut self, iq_vec: &[IQSample]) {
+        self.power_sum += iq_vec.iter().map(|s| s.norm_sqr() as f64).sum::<f64>();
+        self.num_samples += iq_vec.len();
+    }
+
+    pub fn add_loss(&mut self, ts_sec: f64) {
+        self.losses.push_back(ts_sec);
+    }
+
+    pub fn get_status(&self) -> InterferenceStatus {
+        self.status
+    }
+
+    pub fn get_events(&self) -> &[InterferenceEvent] {
+        &self.events
+    }
+
+    // Every INTERFERENCE_PERIOD_SEC, with the cn0 of the tracked satellites:
+    // true when the detection starts or ends.
+    pub fn update(&mut self, ts_sec: f64, cn0s: &[(SV, f64)]) -> bool {
+        if self.num_samples == 0 {
+            return false;
+        }
+        let dt = ts_sec - self.last_update_sec.unwrap_or(ts_sec);
+        self.last_update_sec = Some(ts_sec);
+        let a = f64::min(dt / BASELINE_TC_SEC, 1.0);
+        let detected = self.status.is_detected();
+
+        let power = self.power_sum / self.num_samples as f64;
+        (self.power_sum, self.num_samples) = (0.0, 0);
+        let agc_baseline = *self.agc_baseline.get_or_insert(power);
+        self.status.agc_db = 10.0 * (power / agc_baseline).log10();
+
+        let rises: Vec<_> = (cn0s.iter())
+            .filter_map(|(sv, cn0)| Some(self.cn0_baselines.get(sv)? - cn0))
+            .collect();
+        self.status.noise_rise_db = if rises.len() >= NOISE_MIN_SVS {
+            rises.iter().sum::<f64>() / rises.len() as f64
+        } else {
+            0.0
+        };
+
+        while (self.losses.front()).is_some_and(|&t| ts_sec - t > LOSS_WINDOW_SEC) {
+            self.losses.pop_front();
+        }
+        self.status.num_lost = self.losses.len();
+
+        // the baselines only learn in the clear
+        if !detected {
+            self.agc_baseline = Some(agc_baseline + a * (power - agc_baseline));
+            for &(sv, cn0) in cn0s {
+                let baseline = self.cn0_baselines.entry(sv).or_insert(cn0);
+                *baseline += a * (cn0 - *baseline);
+            }
+        }
+
+        let sign = self.status.agc_db >= AGC_THRESHOLD_DB
+            || self.status.noise_rise_db >= NOISE_THRESHOLD_DB
+            || self.status.num_lost >= LOSS_THRESHOLD;
+        if sign {
+            self.last_sign_sec = ts_sec;
+        }
+        if sign && !detected {
+            self.status.since_sec = Some(ts_sec);
+            if self.events.len() >= MAX_INTERFERENCE_EVENTS {
+                self.events.remove(0);
+            }
+            self.events.push(InterferenceEvent {
+                start_sec: ts_sec,
+                end_sec: None,
+                max_agc_db: self.status.agc_db,
+                max_noise_rise_db: self.status.noise_rise_db,
+                max_lost: self.status.num_lost,
+            });
+            return true;
+        }
+        if !detected {
+            return false;
+        }
+        let event = self.events.last_mut().unwrap();
+        event.max_agc_db = event.max_agc_db.max(self.status.agc_db);
+        event.max_noise_rise_db = event.max_noise_rise_db.max(self.status.noise_rise_db);
+        event.max_lost = event.max_lost.max(self.status.num_lost);
+        if ts_sec - self.last_sign_sec >= CLEAR_SEC {
+            event.end_sec = Some(ts_sec);
+            self.status.since_sec = None;
+            return true;
+        }
+        false
+    }
+}
@@ -1,19 +1,79 @@
+#[cfg(feature = "airspy")]
+pub mod airspy;
 pub mod almanac;
+pub mod api;
+#[cfg(feature = "ui")]
 pub mod app;
+pub mod bench;
+pub mod boc;
+pub mod calibration;
+pub mod capture;
 pub mod channel;
+pub mod checkpoint;
+pub mod clock;
 pub mod code;
+pub mod code_dump;
+pub mod console;
 pub mod constants;
+pub mod convert;
+pub mod detect;
 pub mod device;
+pub mod downconvert;
 pub mod ephemeris;
+#[cfg(feature = "cuda")]
+pub mod gpufft;
+#[cfg(feature = "hackrf")]
+pub mod hackrf;
+pub mod interference;
 pub mod navigation;
+pub mod navigation_bds;
+pub mod navigation_glo;
+pub mod navigation_sbas;
+#[cfg(feature = "net")]
 pub mod network;
+pub mod noise;
+pub mod notch;
+pub mod observables;
+pub mod output;
+pub mod pipeline;
 pub mod plots;
+pub mod quality;
 pub mod receiver;
 pub mod recording;
+pub mod resampler;
+pub mod residuals;
+pub mod rinex;
+#[cfg(all(unix, feature = "rtlsdr"))]
+pub mod rtlsdr;
+#[cfg(feature = "net")]
+pub mod runtime;
+pub mod sample;
+#[cfg(feature = "ui")]
+pub mod settings;
+pub mod sidecar;
+pub mod sim;
+pub mod sky;
+pub mod snapshot;
 pub mod solver;
+pub mod source;
+pub mod spectrum;
+pub mod start;
 pub mod state;
+pub mod summary;
+pub mod threads;
+pub mod timing;
+pub mod ttff;
+#[cfg(feature = "uhd")]
+pub mod uhd;
 pub mod util;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(feature = "net")]
+pub mod web;
 
+pub use api::{GnssConfig, GnssReceiver, GnssSource};
+#[cfg(feature = "ui")]
 pub use app::egui_main;
 
+#[cfg(all(unix, feature = "rtlsdr"))]
 extern crate rtlsdr_mt;
@@ -1,21 +1,47 @@
-use chrono::Local;
+use chrono::{DateTime, Datelike, Local, Timelike};
 use colored::Colorize;
 use coredump::register_panic_handler;
 use log::LevelFilter;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
 
-use gnss_rcv::code::Code;
-use gnss_rcv::plots::plot_remove_old_graph;
-use gnss_rcv::receiver::Receiver;
+use gnss_rcv::code::{Code, CodeTables};
+use gnss_rcv::code_dump::{CodeForm, CodeFormat, write_codes};
+use gnss_rcv::console::init_console_logger;
+use gnss_rcv::convert::cut_iq_file;
+use gnss_rcv::detect::Detection;
+use gnss_rcv::detect::check_iq_format;
+use gnss_rcv::detect::detect_iq_format;
+use gnss_rcv::detect::print_detection_report;
+use gnss_rcv::detect::print_file_info;
+use gnss_rcv::device::DeviceConfig;
+use gnss_rcv::device::DeviceType;
+use gnss_rcv::observables::read_obs_file;
+use gnss_rcv::output::OutputConfig;
+use gnss_rcv::plots::{
+    PlotClean, PlotFormat, plot_remove_old_graph, set_plot_dir, set_plot_format,
+};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
 use gnss_rcv::recording::IQFileType;
+use gnss_rcv::recording::check_recording_range;
+use gnss_rcv::recording::get_file_list;
+use gnss_rcv::rinex::{get_first_toe_sec, get_gps_sec_from_date, read_rinex_nav};
+use gnss_rcv::sidecar::IQSidecar;
+use gnss_rcv::sidecar::check_sidecar;
+use gnss_rcv::sim::{MultipathRay, SimConfig, SimImpairments, Trajectory};
+use gnss_rcv::solver::PositionSolver;
+use gnss_rcv::source::DropPolicy;
+use gnss_rcv::start::StartMode;
+use gnss_rcv::start::get_unix_time_now;
 use gnss_rcv::state::GnssState;
+use gnss_rcv::threads::{build_thread_pool, install, parse_cpu_list};
+use gnss_rcv::web::start_web_dashboard;
 
 #[derive(StructOpt)]
 #[structopt(name = "gnss-rcv", about = "gnss-rcv: GNSS receiver")]
@@ -23,21 +49,171 @@ struct Options {
     #[structopt(
         short = "f",
         long,
+        help = "IQ file(s): comma-separated list or glob pattern",
         default_value = "resources/nov_3_time_18_48_st_ives"
     )]
     file: PathBuf,
     #[structopt(short = "s", long, help = "host for rtl-sdr-tcp", default_value = "")]
     hostname: String,
-    #[structopt(long, help = "signal: L1CA, etc.", default_value = "L1CA")]
+    #[structopt(
+        long,
+        help = "signal: L1CA, E1 (E1B), E1C, L2C, L1OF, B1I, L5 (L5I), L5Q, or a list on one carrier, e.g. L1CA,E1B",
+        default_value = "L1CA"
+    )]
     sig: String,
-    #[structopt(short = "d", long, help = "use rtl-sdr device")]
+    #[structopt(long, help = "memory codes, e.g. of Galileo E1: a line per prn")]
+    code_file: Option<PathBuf>,
+    #[structopt(long, help = "GLONASS frequency channels of the slots, e.g. 1:1,2:-4")]
+    glo_channels: Option<String>,
+    #[structopt(short = "d", long, help = "use sdr device")]
     use_device: bool,
+    #[structopt(
+        long,
+        help = "sdr device: rtlsdr, hackrf, airspy, uhd",
+        default_value = "rtlsdr"
+    )]
+    device_type: DeviceType,
+    #[structopt(long, help = "device arguments, e.g. type=b200", default_value = "")]
+    device_args: String,
+    #[structopt(long, help = "device rx gain in dB, automatic if not set")]
+    gain: Option<f64>,
+    #[structopt(long, help = "rtl-sdr agc: true or false", parse(try_from_str))]
+    agc: Option<bool>,
+    #[structopt(long, help = "antenna bias-tee: true or false", parse(try_from_str))]
+    bias_tee: Option<bool>,
+    #[structopt(long, help = "device lna gain in dB")]
+    lna_gain: Option<u32>,
+    #[structopt(long, help = "device mixer gain")]
+    mixer_gain: Option<u32>,
+    #[structopt(long, help = "device vga gain in dB")]
+    vga_gain: Option<u32>,
+    #[structopt(long, help = "enable device rf amplifier")]
+    amp: bool,
+    #[structopt(
+        long,
+        help = "front-end oscillator error in ppm: corrected at tuning time for devices, in the doppler search for files",
+        default_value = "0"
+    )]
+    ppm: i32,
+    #[structopt(long, help = "device clock/time reference: internal, external, gpsdo")]
+    clock_source: Option<String>,
+    #[structopt(
+        long,
+        help = "front-end clock is disciplined (GPSDO, ..): narrower doppler search and loops"
+    )]
+    disciplined: bool,
+    #[structopt(
+        long,
+        help = "center the doppler search on the front-end clock error estimated from the tracked satellites"
+    )]
+    auto_ppm: bool,
+    #[structopt(
+        long,
+        help = "code periods integrated coherently by the acquisition, e.g. 10 for L5",
+        default_value = "1"
+    )]
+    acq_coherent: usize,
+    #[structopt(
+        long,
+        help = "detect CW interference and notch it out of the samples before correlation"
+    )]
+    notch: bool,
+    #[structopt(
+        long,
+        help = "timing mode for a static antenna: survey its position in, then estimate the clock offset and frequency error"
+    )]
+    timing: bool,
+    #[structopt(
+        long,
+        help = "seconds of fixes averaged to survey the position in",
+        default_value = "600"
+    )]
+    survey_sec: f64,
+    #[structopt(
+        long,
+        help = "known antenna position in timing mode, no survey: lat,lon,height"
+    )]
+    timing_position: Option<String>,
+    #[structopt(long, help = "batch the acquisition FFTs on a CUDA gpu")]
+    gpu_fft: bool,
+    #[structopt(
+        long,
+        help = "seconds of device samples buffered when processing falls behind",
+        default_value = "2.0"
+    )]
+    queue_sec: f64,
+    #[structopt(
+        long,
+        help = "when the buffer is full: drop-oldest or drop-newest",
+        default_value = "drop-oldest"
+    )]
+    drop_policy: DropPolicy,
+    #[structopt(long = "noise", help = "use synthetic noise source instead of a file")]
+    use_noise: bool,
+    #[structopt(long = "sim", help = "use simulated GPS satellites instead of a file")]
+    use_sim: bool,
+    #[structopt(long, help = "RINEX navigation file of the simulated satellites")]
+    sim_nav: Option<PathBuf>,
+    #[structopt(long, help = "simulated receiver position: lat,lon,height")]
+    sim_position: Option<String>,
+    #[structopt(
+        long,
+        help = "simulated receiver trajectory: csv of t_sec,lat,lon,height"
+    )]
+    sim_trajectory: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "gps time of the simulation start, e.g. 2022-01-01T01:02:03Z: the earliest ephemeris by default"
+    )]
+    sim_start: Option<String>,
+    #[structopt(
+        long,
+        help = "C/N0 of the simulated satellites (dB-Hz)",
+        default_value = "45.0"
+    )]
+    sim_cn0: f64,
+    #[structopt(long, help = "C/N0 of a simulated satellite, e.g. 5:32, repeatable")]
+    sim_sv_cn0: Vec<String>,
+    #[structopt(
+        long,
+        help = "simulated reflection of the signals: delay_m:attenuation_db[:fading_hz], repeatable"
+    )]
+    sim_multipath: Vec<MultipathRay>,
+    #[structopt(
+        long,
+        help = "frequency error of the simulated receiver clock (ppm)",
+        default_value = "0.0"
+    )]
+    sim_clock_ppm: f64,
+    #[structopt(
+        long,
+        help = "drift of the simulated receiver clock (ppm/s)",
+        default_value = "0.0"
+    )]
+    sim_clock_drift: f64,
+    #[structopt(
+        long,
+        help = "simulated L1 ionospheric delay at the zenith (m)",
+        default_value = "0.0"
+    )]
+    sim_iono: f64,
     #[structopt(short = "l", long, help = "path to log file", default_value = "")]
     log_file: PathBuf,
+    #[structopt(
+        long,
+        help = "log through a tracing subscriber, RUST_LOG filtering on spans too, e.g. gnss_rcv[channel{sv=G05}]=debug: not with the UI"
+    )]
+    tracing: bool,
     #[structopt(short = "t", long, help = "type of IQ file", default_value = "2xf32")]
     iq_file_type: IQFileType,
     #[structopt(long, help = "sampling frequency", default_value = "2046000.0")]
     fs: f64,
+    #[structopt(
+        long,
+        help = "internal sampling frequency used for processing",
+        default_value = "2046000.0"
+    )]
+    fs_internal: f64,
     #[structopt(long, help = "intermediate frequency", default_value = "0.0")]
     fi: f64,
     #[structopt(long, help = "offset in file", default_value = "0")]
@@ -48,13 +224,331 @@ struct Options {
     sats: String,
     #[structopt(short = "-u", long, help = "use ui")]
     use_ui: bool,
+    #[structopt(
+        long,
+        help = "seconds of IQ kept in memory for pre-trigger capture",
+        default_value = "0"
+    )]
+    pre_trigger_sec: f64,
+    #[structopt(long, help = "detect IQ file type and sampling frequency")]
+    detect: bool,
+    #[structopt(long, help = "check the IQ file against the sha256 of its sidecar")]
+    verify: bool,
+    #[structopt(long = "loop", help = "loop back to start of file on EOF")]
+    loop_file: bool,
+    #[structopt(long, help = "pace file playback at the capture's real-time rate")]
+    realtime: bool,
+    #[structopt(
+        long,
+        help = "playback speed multiplier, implies --realtime",
+        default_value = "1.0"
+    )]
+    speed: f64,
+    #[structopt(
+        long,
+        help = "signal of a second front-end, e.g. L5, processed alongside"
+    )]
+    sig2: Option<String>,
+    #[structopt(long, help = "IQ file of the second front-end", default_value = "")]
+    file2: PathBuf,
+    #[structopt(
+        long,
+        help = "rtl_tcp host of the second front-end",
+        default_value = ""
+    )]
+    hostname2: String,
+    #[structopt(
+        long,
+        help = "device arguments of the second front-end",
+        default_value = ""
+    )]
+    device_args2: String,
+    #[structopt(
+        long,
+        help = "sampling frequency of the second front-end, defaults to --fs"
+    )]
+    fs2: Option<f64>,
+    #[structopt(
+        long,
+        help = "intermediate frequency of the second front-end",
+        default_value = "0.0"
+    )]
+    fi2: f64,
+    #[structopt(
+        long,
+        help = "IQ file of another antenna, sampled coherently with --file"
+    )]
+    antenna_file: Vec<PathBuf>,
+    #[structopt(long, help = "rtl_tcp host of another coherent antenna")]
+    antenna_hostname: Vec<String>,
+    #[structopt(long, help = "device arguments of another coherent antenna")]
+    antenna_device_args: Vec<String>,
+    #[structopt(
+        long,
+        help = "start mode: cold, warm (almanac, needs --state-file), hot (ephemeris, needs --state-file)",
+        default_value = "cold"
+    )]
+    start: StartMode,
+    #[structopt(
+        long,
+        help = "json file with the almanac, position and ephemeris kept across runs"
+    )]
+    state_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "json file where a run on a recording is checkpointed, and resumed from if present"
+    )]
+    checkpoint: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "seconds of signal between checkpoints",
+        default_value = "60"
+    )]
+    checkpoint_sec: f64,
+    #[structopt(
+        long,
+        help = "worker threads for the channels, 0 for one per cpu",
+        default_value = "0"
+    )]
+    threads: usize,
+    #[structopt(
+        long,
+        help = "pin the worker threads to cpus, e.g. 2,3 or 4-7",
+        default_value = ""
+    )]
+    cpus: String,
+    #[structopt(
+        long,
+        help = "bit-identical results for a given input and config, e.g. for regression tests"
+    )]
+    deterministic: bool,
+    #[structopt(
+        long,
+        help = "msec of samples read and dispatched to the channels at once",
+        default_value = "1"
+    )]
+    block_msec: usize,
+    #[structopt(
+        long,
+        help = "run acquisition, solving and plots as stages off the sample path"
+    )]
+    pipeline: bool,
+    #[structopt(long, help = "report throughput and per-stage timing of the run")]
+    bench: bool,
+    #[structopt(
+        long,
+        help = "json file for the end-of-run statistics: per satellite and of the fixes"
+    )]
+    stats_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "csv file of the pseudorange residuals and bias flags of the satellites after each fix"
+    )]
+    residuals_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "directory of the run outputs: charts, and the relative log, csv, obs and stats files",
+        default_value = "."
+    )]
+    output_dir: PathBuf,
+    #[structopt(
+        long,
+        help = "the outputs of each run in a timestamped subdirectory of --output-dir"
+    )]
+    run_subdir: bool,
+    #[structopt(
+        long,
+        help = "directory of the charts, relative to the run's output directory",
+        default_value = "plots"
+    )]
+    plot_dir: PathBuf,
+    #[structopt(
+        long,
+        help = "charts of a previous run at startup: remove, keep or dry (only list them)",
+        default_value = "remove"
+    )]
+    plot_clean: PlotClean,
+    #[structopt(
+        long,
+        help = "charts as png, svg or html (interactive, drawn by plotly in the browser)",
+        default_value = "png"
+    )]
+    plot_format: PlotFormat,
+    #[structopt(
+        long,
+        help = "seconds of signal between two rounds of charts of a channel",
+        default_value = "2"
+    )]
+    plot_period_sec: f64,
+    #[structopt(long, help = "no charts of the channels nor of the run")]
+    no_plots: bool,
+    #[structopt(
+        long,
+        help = "address the nmea, gpsd and websocket servers listen on, 0.0.0.0 for all",
+        default_value = "127.0.0.1"
+    )]
+    output_bind: String,
+    #[structopt(long, help = "serve NMEA sentences of the fixes on this tcp port")]
+    nmea_port: Option<u16>,
+    #[structopt(long, help = "serve gpsd TPV reports of the fixes on this tcp port")]
+    gpsd_port: Option<u16>,
+    #[structopt(long, help = "serve the fixes as json over a websocket on this port")]
+    ws_port: Option<u16>,
+    #[structopt(long, help = "append the fixes to this csv file")]
+    csv_file: Option<String>,
+    #[structopt(
+        long,
+        help = "serve a live dashboard to browsers on this http port, headless"
+    )]
+    web_port: Option<u16>,
+    #[structopt(
+        long,
+        help = "address the web dashboard listens on, 0.0.0.0 for all",
+        default_value = "127.0.0.1"
+    )]
+    web_bind: String,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+// without one, track
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(about = "scan --file for the visible satellites, no navigation")]
+    Acquire {
+        #[structopt(long, help = "duration of the scan", default_value = "1000")]
+        msec: usize,
+    },
+    #[structopt(about = "acquisition only on a slice of --file: fast, no tracking")]
+    Scan {
+        #[structopt(long, help = "duration of the slice", default_value = "20")]
+        msec: usize,
+    },
+    #[structopt(about = "track the satellites and compute fixes: the default")]
+    Track {
+        #[structopt(
+            long,
+            help = "write the observables of every fix attempt to this json lines file"
+        )]
+        obs_file: Option<PathBuf>,
+    },
+    #[structopt(about = "compute the fixes again from the observables of a track run")]
+    Pvt {
+        #[structopt(long, help = "json lines file written by track --obs-file")]
+        obs_file: PathBuf,
+    },
+    #[structopt(about = "record the raw IQ of the device to a file")]
+    Record {
+        #[structopt(short = "o", long, help = "output IQ file")]
+        output: PathBuf,
+        #[structopt(long, help = "type of output IQ file", default_value = "2xi16")]
+        out_type: IQFileType,
+        #[structopt(
+            long,
+            help = "duration in seconds, 0 for until interrupted",
+            default_value = "0"
+        )]
+        duration_sec: f64,
+    },
+    #[structopt(
+        alias = "cut",
+        about = "extract a time range of --file and/or convert its sample format"
+    )]
+    Convert {
+        #[structopt(short = "o", long, help = "output IQ file")]
+        output: PathBuf,
+        #[structopt(long, help = "type of output IQ file, defaults to the input type")]
+        out_type: Option<IQFileType>,
+        #[structopt(long, help = "start offset in seconds", default_value = "0")]
+        start_sec: f64,
+        #[structopt(
+            long,
+            help = "duration in seconds, 0 for up to the end",
+            default_value = "0"
+        )]
+        duration_sec: f64,
+    },
+    #[structopt(about = "size, duration, sidecar and detected format of --file")]
+    Info,
+    #[structopt(about = "write the prn codes of --sig for --sats, no IQ involved")]
+    Codes {
+        #[structopt(short = "o", long, help = "output file, stdout when absent")]
+        output: Option<PathBuf>,
+        #[structopt(long, help = "csv or bin", default_value = "csv")]
+        format: CodeFormat,
+        #[structopt(long, help = "sampled at --fs-internal over a code period")]
+        sampled: bool,
+        #[structopt(long, help = "fft of the code sampled at --fs-internal")]
+        fft: bool,
+    },
+    #[structopt(
+        about = "coarse position from a short snapshot of --file, with the ephemeris and position of --state-file"
+    )]
+    Snapshot {
+        #[structopt(long, help = "duration of the snapshot", default_value = "300")]
+        msec: usize,
+    },
+}
+
+// The output directory of the run, a fresh run-YYYYmmdd-HHMMSS one with
+// --run-subdir: the charts and the output files given as relative paths go
+// there.
+fn init_output_dirs(opt: &mut Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut run_dir = opt.output_dir.clone();
+    if opt.run_subdir {
+        run_dir.push(Local::now().format("run-%Y%m%d-%H%M%S").to_string());
+    }
+    std::fs::create_dir_all(&run_dir)?;
+    set_plot_dir(&run_dir.join(&opt.plot_dir))?;
+    set_plot_format(opt.plot_format);
+
+    let in_run_dir = |path: &Path| {
+        if path.as_os_str().is_empty() || path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            run_dir.join(path)
+        }
+    };
+    opt.log_file = in_run_dir(&opt.log_file);
+    opt.stats_file = opt.stats_file.as_deref().map(in_run_dir);
+    opt.residuals_file = opt.residuals_file.as_deref().map(in_run_dir);
+    opt.csv_file = opt.csv_file.as_ref().map(|file| {
+        let path = in_run_dir(Path::new(file));
+        path.to_string_lossy().into_owned()
+    });
+    if let Some(Command::Track {
+        obs_file: Some(path),
+    }) = &mut opt.cmd
+    {
+        *path = in_run_dir(path);
+    }
+    Ok(())
 }
 
-fn init_logging(log_file: &PathBuf) {
+// A tracing subscriber instead of env_logger: the records of a channel are
+// in its span, those of the fixes in "pvt". The log records of the other
+// modules are forwarded to it.
+fn init_tracing(log_file: &Path) {
+    let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+    if log_file.as_os_str().is_empty() {
+        builder.init();
+    } else {
+        println!("using log file: {}", log_file.display());
+        let target = File::create(log_file).expect("log file err");
+        builder
+            .with_ansi(false)
+            .with_writer(Mutex::new(target))
+            .init();
+    }
+}
+
+// with the UI, the records also go to its console
+fn init_logging(log_file: &PathBuf, use_ui: bool) {
+    let mut builder = env_logger::Builder::new();
     if !log_file.as_os_str().is_empty() {
         println!("using log file: {}", log_file.display());
         let target = Box::new(File::create(log_file).expect("log file err"));
-        env_logger::Builder::new()
+        builder
             .target(env_logger::Target::Pipe(target))
             .filter(None, LevelFilter::Debug)
             .format(|buf, record| {
@@ -65,68 +559,590 @@ fn init_logging(log_file: &PathBuf) {
                     record.level(),
                     record.args()
                 )
-            })
-            .init();
+            });
     } else {
-        env_logger::Builder::from_default_env()
+        builder = env_logger::Builder::from_default_env();
+        builder
             .format_target(false)
             .format_module_path(false)
-            .format_timestamp_millis()
-            .init();
+            .format_timestamp_millis();
+    }
+    if use_ui {
+        init_console_logger(builder.build());
+    } else {
+        builder.init();
+    }
+}
+
+// the format detection, on the workers of --threads and --cpus like the
+// channels
+fn run_detection(opt: &Options, file: &Path) -> Result<Vec<Detection>, Box<dyn std::error::Error>> {
+    let cpus = parse_cpu_list(&opt.cpus)?;
+    let pool = if opt.threads != 0 || !cpus.is_empty() {
+        Some(build_thread_pool(opt.threads, &cpus)?)
+    } else {
+        None
+    };
+    let detections = install(pool.as_ref(), || detect_iq_format(file))?;
+    print_detection_report(&detections);
+    Ok(detections)
+}
+
+fn check_file_format(opt: &mut Options) -> Result<(), Box<dyn std::error::Error>> {
+    if opt.use_device || opt.use_noise || opt.use_sim || opt.use_ui || !opt.hostname.is_empty() {
+        return Ok(());
+    }
+    let files = get_file_list(&opt.file);
+    let Some(file) = files.first() else {
+        return Err(format!("no IQ file found: {}", opt.file.display()).into());
+    };
+    let sig = Code::get_sig_list(&opt.sig)[0];
+    check_sidecar(file, &opt.iq_file_type, opt.fs, opt.fi, sig, opt.verify)?;
+    if opt.detect {
+        let detections = run_detection(opt, file)?;
+        if let Some(best) = detections.first() {
+            opt.iq_file_type = best.file_type.clone();
+            opt.fs = best.fs;
+        }
+    } else if !check_iq_format(file, &opt.iq_file_type)? {
+        tracing::warn!(
+            "{}: samples don't look like {}, running detection..",
+            file.display(),
+            opt.iq_file_type.to_string().red()
+        );
+        run_detection(opt, file)?;
+    }
+    if !opt.loop_file {
+        let num_msec = match opt.cmd {
+            Some(Command::Scan { msec }) => msec,
+            _ => opt.num_msec,
+        };
+        check_recording_range(&opt.file, &opt.iq_file_type, opt.fs, opt.off_msec, num_msec)?;
+    }
+    Ok(())
+}
+
+// Rough time of the first sample processed, for warm/hot starts: taken from
+// the sidecar of a recording, the system clock otherwise.
+fn get_start_time(opt: &Options) -> f64 {
+    let off_sec = opt.off_msec as f64 / 1000.0;
+    if opt.use_device || opt.use_noise || opt.use_sim || !opt.hostname.is_empty() {
+        return get_unix_time_now();
+    }
+    let files = get_file_list(&opt.file);
+    let start_time = files
+        .first()
+        .and_then(|file| IQSidecar::read(file).ok().flatten())
+        .and_then(|sidecar| sidecar.start_time)
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok());
+    match start_time {
+        Some(t) => t.timestamp_millis() as f64 / 1000.0 + off_sec,
+        None => {
+            tracing::warn!("no recording start time: using the system clock");
+            get_unix_time_now()
+        }
     }
 }
 
+// the satellites of get_sim_satellites(), with the C/N0 and impairments options
+fn get_sim_config(opt: &Options) -> Result<SimConfig, Box<dyn std::error::Error>> {
+    let mut config = get_sim_satellites(opt)?;
+    for s in &opt.sim_sv_cn0 {
+        let (prn, cn0) = s
+            .split_once(':')
+            .ok_or_else(|| format!("--sim-sv-cn0 {s}: expected prn:cn0"))?;
+        let prn: u8 = prn
+            .trim()
+            .parse()
+            .map_err(|e| format!("--sim-sv-cn0 {s}: {e}"))?;
+        let cn0: f64 = cn0
+            .trim()
+            .parse()
+            .map_err(|e| format!("--sim-sv-cn0 {s}: {e}"))?;
+        for sim_sv in config.svs.iter_mut().filter(|sim_sv| sim_sv.sv.prn == prn) {
+            sim_sv.cn0 = cn0;
+        }
+    }
+    config.impairments = SimImpairments {
+        multipath: opt.sim_multipath.clone(),
+        clock_ppm: opt.sim_clock_ppm,
+        clock_drift_ppm: opt.sim_clock_drift,
+        iono_zenith_m: opt.sim_iono,
+    };
+    Ok(config)
+}
+
+// the lat,lon,height of a position option, name for the errors
+fn parse_position(name: &str, pos: &str) -> Result<[f64; 3], Box<dyn std::error::Error>> {
+    let v = (pos.split(','))
+        .map(|s| s.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{name} {pos}: {e}"))?;
+    let [lat, lon, height] = v[..] else {
+        return Err(format!("{name} {pos}: expected lat,lon,height").into());
+    };
+    Ok([lat, lon, height])
+}
+
+// the satellites of --sats, or those of --sim-nav above the horizon of
+// --sim-position or --sim-trajectory
+fn get_sim_satellites(opt: &Options) -> Result<SimConfig, Box<dyn std::error::Error>> {
+    let Some(nav_file) = &opt.sim_nav else {
+        let svs = get_sat_list(&opt.sig, &opt.sats)?;
+        return Ok(SimConfig::from_sats(&svs, opt.sim_cn0));
+    };
+    let ephs = read_rinex_nav(nav_file)?;
+    let trajectory = match (&opt.sim_trajectory, &opt.sim_position) {
+        (Some(path), _) => Trajectory::read_csv(path)?,
+        (None, Some(pos)) => {
+            let [lat, lon, height] = parse_position("--sim-position", pos)?;
+            Trajectory::fixed(lat, lon, height)
+        }
+        (None, None) => return Err("--sim-nav needs --sim-position or --sim-trajectory".into()),
+    };
+    let start_gps_sec = match &opt.sim_start {
+        Some(s) => {
+            let t = DateTime::parse_from_rfc3339(s)
+                .map_err(|e| format!("--sim-start {s}: {e}"))?
+                .naive_utc();
+            get_gps_sec_from_date(
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second() as f64 + t.nanosecond() as f64 * 1e-9,
+            )
+            .ok_or_else(|| format!("--sim-start {s}: invalid date"))?
+        }
+        None => get_first_toe_sec(&ephs)
+            .ok_or_else(|| format!("{}: no GPS ephemeris", nav_file.display()))?,
+    };
+    let sats = if opt.sats.is_empty() {
+        vec![]
+    } else {
+        get_sat_list(&opt.sig, &opt.sats)?
+    };
+    let config = SimConfig::from_ephemerides(&ephs, &sats, trajectory, start_gps_sec, opt.sim_cn0);
+    if config.svs.is_empty() {
+        return Err("sim: no satellite in view".into());
+    }
+    Ok(config)
+}
+
 fn init_ctrl_c(exit_req: Arc<AtomicBool>) {
     register_panic_handler().unwrap();
     ctrlc::set_handler(move || {
-        exit_req.store(true, Ordering::SeqCst);
+        // the first one lets the run wrap up: outputs, checkpoint, plots
+        if exit_req.swap(true, Ordering::SeqCst) {
+            tracing::warn!("exiting without wrapping up");
+            std::process::exit(130);
+        }
+        tracing::info!("exit requested: Ctrl-C again to quit now");
     })
     .expect("Error setting Ctrl-C handler");
 }
 
+// The codes of the receiver: the memory codes of --code-file and the GLONASS
+// frequency channels of --glo-channels on top of the defaults.
+fn get_code_tables(opt: &Options) -> Result<CodeTables, Box<dyn std::error::Error>> {
+    let mut codes = CodeTables::new();
+    if let Some(path) = &opt.code_file {
+        codes.load_memory_codes(path)?;
+    }
+    if let Some(spec) = &opt.glo_channels {
+        codes.set_freq_channels(spec)?;
+    }
+    Ok(codes)
+}
+
+// Fixes from the observables saved by a previous run, no IQ involved.
+fn run_pvt(obs_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let epochs = read_obs_file(obs_file)?;
+    let mut solver = PositionSolver::new(Arc::new(Mutex::new(GnssState::new())));
+    let mut num_fixes = 0;
+    for epoch in &epochs {
+        let ephs: Vec<_> = epoch.sats.iter().map(|sat| sat.get_ephemeris()).collect();
+        match solver.compute_position(epoch.ts_sec, &ephs) {
+            Some(fix) => {
+                num_fixes += 1;
+                println!(
+                    "t={:.3} lat/lon: {:.5},{:.5} h={:.0}m {} SVs",
+                    epoch.ts_sec,
+                    fix[0],
+                    fix[1],
+                    fix[2],
+                    ephs.len()
+                );
+            }
+            None => println!("t={:.3} {}", epoch.ts_sec, "no fix".red()),
+        }
+    }
+    println!("pvt: {num_fixes} fixes from {} epochs", epochs.len());
+    Ok(())
+}
+
+fn run_codes(
+    opt: &Options,
+    codes: &CodeTables,
+    output: &Option<PathBuf>,
+    format: CodeFormat,
+    sampled: bool,
+    fft: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let form = if fft {
+        CodeForm::Fft(opt.fs_internal)
+    } else if sampled {
+        CodeForm::Sampled(opt.fs_internal)
+    } else {
+        CodeForm::Chips
+    };
+    let svs = get_sat_list(&opt.sig, &opt.sats)?;
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    write_codes(&mut writer, codes, &opt.sig, &svs, form, format)
+}
+
+fn get_output_config(opt: &Options) -> OutputConfig {
+    let default = OutputConfig::default();
+    OutputConfig {
+        bind: opt.output_bind.clone(),
+        nmea: opt.nmea_port.is_some(),
+        nmea_port: opt.nmea_port.unwrap_or(default.nmea_port),
+        gpsd: opt.gpsd_port.is_some(),
+        gpsd_port: opt.gpsd_port.unwrap_or(default.gpsd_port),
+        ws: opt.ws_port.is_some(),
+        ws_port: opt.ws_port.unwrap_or(default.ws_port),
+        csv: opt.csv_file.is_some(),
+        csv_file: opt.csv_file.clone().unwrap_or(default.csv_file),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opt = Options::from_args();
+    let mut opt = Options::from_args();
     let exit_req = Arc::new(AtomicBool::new(false));
 
-    init_logging(&opt.log_file);
+    init_output_dirs(&mut opt)?;
+    if opt.tracing && !opt.use_ui {
+        init_tracing(&opt.log_file);
+    } else {
+        init_logging(&opt.log_file, opt.use_ui);
+    }
     init_ctrl_c(exit_req.clone());
-    plot_remove_old_graph();
+    plot_remove_old_graph(opt.plot_clean);
+    let codes = get_code_tables(&opt)?;
+
+    match &opt.cmd {
+        Some(Command::Pvt { obs_file }) => return run_pvt(obs_file),
+        Some(Command::Codes {
+            output,
+            format,
+            sampled,
+            fft,
+        }) => return run_codes(&opt, &codes, output, *format, *sampled, *fft),
+        Some(Command::Info) => {
+            for file in get_file_list(&opt.file) {
+                print_file_info(&file, &opt.iq_file_type, opt.fs)?;
+            }
+            return Ok(());
+        }
+        Some(Command::Record { .. }) => {}
+        _ => check_file_format(&mut opt)?,
+    }
+
+    if let Some(Command::Convert {
+        output,
+        out_type,
+        start_sec,
+        duration_sec,
+    }) = &opt.cmd
+    {
+        cut_iq_file(
+            &opt.file,
+            &opt.iq_file_type,
+            opt.fs,
+            opt.fi,
+            Code::get_sig_list(&opt.sig)[0],
+            output,
+            out_type.as_ref().unwrap_or(&opt.iq_file_type),
+            *start_sec,
+            *duration_sec,
+        )?;
+        return Ok(());
+    }
 
-    log::warn!(
+    tracing::info!(
         "gnss-rcv: sampling: {} fi: {} off_msec={} num_msec={}",
         format!("{:.1} KHz", opt.fs / 1000.0).bold(),
         format!("{:.1} KHz", opt.fi / 1000.0).bold(),
         opt.off_msec,
         opt.num_msec,
     );
-    log::warn!(
+    tracing::info!(
         "gnss-rcv: using signal {} frequency: {:.1} MHz",
         &opt.sig,
-        Code::get_code_freq(&opt.sig) / 1_000_000.0
+        Code::get_code_freq(Code::get_sig_list(&opt.sig)[0]) / 1_000_000.0
     );
 
     if opt.use_ui {
-        gnss_rcv::egui_main();
+        gnss_rcv::egui_main(opt.fs, opt.fi, &opt.sig);
         return Ok(());
     }
 
-    let mut receiver = Receiver::new(
-        opt.use_device,
-        &opt.hostname,
-        &opt.file,
-        &opt.iq_file_type,
-        opt.fs,
-        opt.fi,
-        opt.off_msec,
-        &opt.sig,
-        &opt.sats,
-        exit_req.clone(),
-        Arc::new(Mutex::new(GnssState::new())),
-    );
+    // device settings also apply to rtl_tcp
+    let device = (opt.use_device || !opt.hostname.is_empty()).then(|| DeviceConfig {
+        device_type: opt.device_type.clone(),
+        device_args: opt.device_args.clone(),
+        gain: opt.gain,
+        lna_gain: opt.lna_gain,
+        mixer_gain: opt.mixer_gain,
+        vga_gain: opt.vga_gain,
+        amp: opt.amp,
+        clock_source: opt.clock_source.clone(),
+        ppm: opt.ppm,
+        agc: opt.agc,
+        bias_tee: opt.bias_tee,
+        queue_sec: opt.queue_sec,
+        drop_policy: opt.drop_policy,
+    });
+    let builder = match &device {
+        Some(config) if !opt.hostname.is_empty() => {
+            ReceiverBuilder::from_rtl_tcp(&opt.hostname, config)
+        }
+        Some(config) => ReceiverBuilder::from_device(config),
+        None if opt.use_sim => ReceiverBuilder::from_sim(get_sim_config(&opt)?),
+        None if opt.use_noise => ReceiverBuilder::from_noise(),
+        None => ReceiverBuilder::from_file(&opt.file)
+            .iq_file_type(&opt.iq_file_type)
+            .loop_file(opt.loop_file),
+    };
+    let pub_state = Arc::new(Mutex::new(GnssState::new()));
+    if let Some(port) = opt.web_port {
+        // the dashboard draws the plots: no png files then
+        pub_state.lock().unwrap().live_plots = true;
+        start_web_dashboard(&opt.web_bind, port, pub_state.clone())?;
+    }
+    let mut receiver = builder
+        .state(pub_state.clone())
+        .fs(opt.fs)
+        .fs_internal(opt.fs_internal)
+        .fi(opt.fi)
+        .off_msec(opt.off_msec)
+        .signal(&opt.sig)
+        .sats(&opt.sats)
+        .code_tables(codes)
+        .exit_req(exit_req.clone())
+        .build()?;
+
+    if let Some(sig2) = &opt.sig2 {
+        let device2 = (opt.use_device || !opt.hostname2.is_empty()).then(|| DeviceConfig {
+            device_args: opt.device_args2.clone(),
+            ..device.clone().unwrap_or_default()
+        });
+        receiver.add_band(
+            device2.as_ref(),
+            &opt.hostname2,
+            &opt.file2,
+            &opt.iq_file_type,
+            opt.fs2.unwrap_or(opt.fs),
+            opt.fi2,
+            opt.off_msec,
+            sig2,
+        )?;
+    }
+    for file in &opt.antenna_file {
+        receiver.add_antenna(None, "", file, &opt.iq_file_type)?;
+    }
+    for hostname in &opt.antenna_hostname {
+        let config = device.clone().unwrap_or_default();
+        receiver.add_antenna(Some(&config), hostname, Path::new(""), &opt.iq_file_type)?;
+    }
+    for device_args in &opt.antenna_device_args {
+        let config = DeviceConfig {
+            device_args: device_args.clone(),
+            ..device.clone().unwrap_or_default()
+        };
+        receiver.add_antenna(Some(&config), "", Path::new(""), &opt.iq_file_type)?;
+    }
+    if opt.deterministic {
+        receiver.set_deterministic()?;
+    }
+    if opt.bench {
+        if device.is_some() {
+            return Err("--bench processes a file, not a device".into());
+        }
+        receiver.enable_bench();
+    }
+    if opt.pre_trigger_sec > 0.0 {
+        receiver.set_pre_trigger_capture(opt.pre_trigger_sec);
+    }
+    if opt.disciplined || device.as_ref().is_some_and(|d| d.has_external_reference()) {
+        receiver.set_disciplined_clock();
+    }
+    if opt.auto_ppm {
+        receiver.set_auto_ppm();
+    }
+    if opt.acq_coherent != 1 {
+        receiver.set_acq_coherent(opt.acq_coherent)?;
+    }
+    if opt.notch {
+        receiver.set_notch_filter();
+    }
+    if opt.gpu_fft {
+        if opt.block_msec > 1 {
+            return Err("--gpu-fft processes 1 msec blocks".into());
+        }
+        receiver.enable_gpu_fft();
+    }
+    if opt.block_msec > 1 {
+        receiver.set_block_msec(opt.block_msec);
+    }
+    if opt.pipeline {
+        if opt.deterministic || opt.gpu_fft {
+            return Err("--pipeline excludes --deterministic and --gpu-fft".into());
+        }
+        receiver.set_pipelined();
+    }
+    let cpus = parse_cpu_list(&opt.cpus)?;
+    if opt.threads != 0 || !cpus.is_empty() {
+        receiver.set_thread_pool(opt.threads, &cpus)?;
+    }
+    if device.is_none() && opt.ppm != 0 {
+        receiver.set_clock_offset_ppm(opt.ppm as f64);
+    }
+    if opt.start != StartMode::Cold && opt.state_file.is_none() {
+        return Err(format!("{} start needs --state-file", opt.start).into());
+    }
+    if opt.state_file.is_some() {
+        // a snapshot has no time to decode the ephemeris
+        let start = match opt.cmd {
+            Some(Command::Snapshot { .. }) => StartMode::Hot,
+            _ => opt.start,
+        };
+        receiver.set_start(start, opt.state_file.as_deref(), get_start_time(&opt))?;
+    }
+    if opt.timing {
+        let position = (opt.timing_position.as_deref())
+            .map(|pos| parse_position("--timing-position", pos))
+            .transpose()?;
+        receiver.set_timing(opt.survey_sec, position, get_start_time(&opt));
+    } else if opt.timing_position.is_some() {
+        return Err("--timing-position needs --timing".into());
+    }
+    let outputs = get_output_config(&opt);
+    if outputs.is_enabled() {
+        receiver.set_outputs(&outputs);
+    }
+    if let Some(path) = &opt.checkpoint {
+        if !matches!(opt.cmd, None | Some(Command::Track { .. })) {
+            return Err("--checkpoint applies to a full run".into());
+        }
+        receiver.set_checkpoint(path, opt.checkpoint_sec)?;
+    }
+    if opt.realtime || opt.speed != 1.0 {
+        if opt.speed <= 0.0 {
+            return Err(format!("invalid playback speed: {}", opt.speed).into());
+        }
+        receiver.set_playback_speed(opt.speed);
+    }
 
     let ts = Instant::now();
 
-    receiver.run_loop(opt.num_msec);
+    if let Some(Command::Acquire { msec }) = opt.cmd {
+        let svs = receiver.run_acquire(msec);
+        println!(
+            "acquire: {} SVs in {:.2} sec",
+            svs.len().to_string().bold(),
+            ts.elapsed().as_secs_f32()
+        );
+        for acq in &svs {
+            println!(
+                "  {} cn0={:.1} dB-Hz doppler={:+.0} Hz code_off={:.3} msec",
+                acq.sv.to_string().green(),
+                acq.cn0,
+                acq.doppler_hz,
+                acq.code_off_sec * 1000.0
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Scan { msec }) = opt.cmd {
+        let svs = receiver.run_scan(msec)?;
+        println!(
+            "scan: {} SVs in {msec} msec from {} msec, {:.2} sec",
+            svs.len().to_string().bold(),
+            opt.off_msec,
+            ts.elapsed().as_secs_f32()
+        );
+        if !svs.is_empty() {
+            println!("  sv   cn0 dB-Hz  doppler Hz  code phase chips");
+        }
+        let sigs = Code::get_sig_list(&opt.sig);
+        for acq in &svs {
+            let sig =
+                Code::get_constellation_signal(&sigs, acq.sv.constellation).unwrap_or(sigs[0]);
+            let chips_per_sec = Code::get_code_len(sig) as f64 / Code::get_code_period(sig);
+            println!(
+                "  {}  {:>9.1}  {:>+10.0}  {:>17.1}",
+                acq.sv.to_string().green(),
+                acq.cn0,
+                acq.doppler_hz,
+                acq.code_off_sec * chips_per_sec
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Record {
+        output,
+        out_type,
+        duration_sec,
+    }) = &opt.cmd
+    {
+        return receiver.run_record(output, out_type, *duration_sec);
+    }
+
+    if let Some(Command::Snapshot { msec }) = opt.cmd {
+        if opt.state_file.is_none() {
+            return Err("snapshot needs --state-file".into());
+        }
+        let fix = receiver.run_snapshot(msec)?;
+        println!(
+            "snapshot: lat/lon: {:.5},{:.5} h={:.0}m time offset={:+.3}s {} SVs rms={:.0}m in {:.2} sec",
+            fix.lat,
+            fix.lon,
+            fix.height,
+            fix.time_offset_sec,
+            fix.num_svs,
+            fix.residual_rms_m,
+            ts.elapsed().as_secs_f32()
+        );
+        return Ok(());
+    }
+
+    if let Some(Command::Track {
+        obs_file: Some(path),
+    }) = &opt.cmd
+    {
+        receiver.set_obs_file(path)?;
+    }
+    if let Some(path) = &opt.stats_file {
+        receiver.set_stats_file(path);
+    }
+    if let Some(path) = &opt.residuals_file {
+        receiver.set_residuals_file(path)?;
+    }
+    receiver.set_plot_period((!opt.no_plots).then_some(opt.plot_period_sec));
+    let summary = receiver.run_loop(opt.num_msec);
+    let (ttff, start_mode) = {
+        let state = pub_state.lock().unwrap();
+        (state.ttff, state.start_mode)
+    };
+    ttff.print_report(start_mode);
+    summary.print();
 
     println!("GNSS terminating: {:.2} sec", ts.elapsed().as_secs_f32());
     exit_req.store(true, Ordering::SeqCst);
@@ -1,19 +1,29 @@
 use crate::{
     channel::Channel,
     constants::{P2_24, P2_27, P2_30, P2_50},
-    ephemeris::Ephemeris,
+    ephemeris::{Ephemeris, is_qzss},
+    navigation_glo::GloFrame,
+    navigation_sbas::{SbasData, is_sbas},
+    ttff::Milestone,
     util::{bits_equal, bits_opposed, getbits, getbits2, getbitu, hex_str, setbitu, xor_bits},
 };
 use colored::Colorize;
+use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
 use gnss_rtk::prelude::Epoch;
+use serde::{Deserialize, Serialize};
 
-const SECS_PER_WEEK: u32 = 7 * 24 * 60 * 60;
 const SDR_MAX_NSYM: usize = 18000;
+const SUBFRAME_LOG_NUM: usize = 10; // kept per channel for the UI
 
 const THRESHOLD_SYNC: f64 = 0.4; // 0.02
 const THRESHOLD_LOST: f64 = 0.03; // 0.002
 
+// data bits, preceded by D29* and D30*, each parity bit covers
+const LNAV_PARITY_MASK: [u32; 6] = [
+    0x2EC7CD2, 0x1763E69, 0x2BB1F34, 0x15D8F9A, 0x1AEC7CD, 0x22DEA27,
+];
+
 #[derive(PartialEq, Debug, Default)]
 enum SyncState {
     #[default]
@@ -22,14 +32,39 @@ enum SyncState {
     None,
 }
 
+// A subframe as received, for the UI: 10 words of 30 bits, the parity bits
+// zeroed when it passed the checks, the raw bits when it didn't.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SubframeLog {
+    pub ts_sec: f64,
+    pub parity_ok: bool,
+    pub data: Vec<u8>, // packed, msb first
+}
+
+// How far the decoding of the navigation message got, for the UI.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
+pub struct NavStatus {
+    pub bit_sync: bool,
+    pub frame_sync: bool,
+    pub reversed: bool, // phase ambiguity of the pll resolved by the preamble
+    pub num_subframes: usize,
+    pub num_parity_errors: usize,
+    #[serde(default)]
+    pub secondary_sync: bool, // phase of the secondary code found, if the signal has one
+}
+
 pub struct Navigation {
     // pub_state: Arc<Mutex<GnssState>>,
     bit_sync: usize, // beginning of a navigation bit in num_trk_samples
     nav_sync: usize, // beginning/end of a navigation frame in num_trk_samples
     sync_state: SyncState,
-    bits: Vec<u8>, // navigation bits
-    count_parity_err: usize,
+    pub(crate) bits: Vec<u8>, // navigation bits, or the symbols of the GLONASS meander and SBAS
+    pub(crate) count_parity_err: usize,
+    pub(crate) count_subframes: usize, // or GLONASS strings
     pub eph: Ephemeris,
+    pub subframes: [Vec<u8>; 3], // raw subframes 1-3, saved for hot starts
+    pub(crate) glo: GloFrame,
+    pub(crate) sbas: SbasData,
 }
 
 impl Navigation {
@@ -41,7 +76,22 @@ impl Navigation {
             sync_state: SyncState::Normal,
             bits: vec![0; SDR_MAX_NSYM],
             count_parity_err: 0,
+            count_subframes: 0,
             eph: Ephemeris::new(sv),
+            subframes: Default::default(),
+            glo: GloFrame::default(),
+            sbas: SbasData::default(),
+        }
+    }
+
+    pub fn get_status(&self) -> NavStatus {
+        NavStatus {
+            bit_sync: self.bit_sync != 0,
+            frame_sync: self.nav_sync != 0,
+            reversed: self.sync_state == SyncState::Reversed,
+            num_subframes: self.count_subframes,
+            num_parity_errors: self.count_parity_err,
+            secondary_sync: false, // the channel knows
         }
     }
 
@@ -50,6 +100,8 @@ impl Navigation {
         self.nav_sync = 0;
         self.sync_state = SyncState::Normal;
         self.bits.fill(0);
+        self.glo.clear();
+        self.sbas.clear();
     }
 }
 
@@ -83,7 +135,7 @@ impl Channel {
             sync_state = SyncState::Reversed;
         }
         if sync_state != SyncState::None {
-            log::info!(
+            tracing::info!(
                 "{}: FRAME SYNC {sync_state:?}: ts={:.3}",
                 self.sv,
                 self.ts_sec
@@ -93,7 +145,7 @@ impl Channel {
         sync_state
     }
 
-    fn nav_sync_symbol(&mut self, num: usize) -> bool {
+    pub(crate) fn nav_sync_symbol(&mut self, num: usize) -> bool {
         if self.nav.bit_sync == 0 {
             let n = if num <= 2 { 1 } else { num - 1 };
             let len = self.hist.corr_p.len();
@@ -114,7 +166,7 @@ impl Channel {
 
             if p.abs() >= r && r >= THRESHOLD_SYNC {
                 self.nav.bit_sync = self.num_trk_samples - n;
-                log::info!("{}: SYNC: p={:.5} ssync={}", self.sv, p, self.nav.bit_sync);
+                tracing::info!("{}: SYNC: p={:.5} ssync={}", self.sv, p, self.nav.bit_sync);
             }
         } else if (self.num_trk_samples - self.nav.bit_sync) % num == 0 {
             let p = self.nav_mean_ip(num);
@@ -125,7 +177,7 @@ impl Channel {
             } else {
                 self.nav.bit_sync = 0;
                 self.nav.sync_state = SyncState::Normal;
-                log::info!("{}: SYNC {} p={}", self.sv, "LOST".to_string().red(), p)
+                tracing::info!("{}: SYNC {} p={}", self.sv, "LOST".to_string().red(), p)
             }
         }
         false
@@ -145,6 +197,11 @@ impl Channel {
 
     fn nav_decode_lnav_subframe4(&mut self, buf: &[u8]) {
         self.nav.eph.tow = getbitu(buf, 30, 17) * 6;
+        // QZSS pages: almanacs of QZSS and the ionosphere over Japan, not
+        // mixed with those of gps
+        if is_qzss(self.sv) {
+            return;
+        }
         let data_id = getbitu(buf, 60, 2);
         let svid = getbitu(buf, 62, 6);
 
@@ -155,7 +212,7 @@ impl Channel {
             if (25..=32).contains(&svid) {
                 let alm = alm_array.get_mut(svid as usize - 1).unwrap();
                 alm.nav_decode_alm(buf, svid);
-                log::warn!("{}: {:?}", self.sv, alm);
+                tracing::debug!("{}: {:?}", self.sv, alm);
             } else if svid == 63 {
                 /* page 25 */
                 const ARRAY_SVCONF_IDX: [usize; 32] = [
@@ -176,7 +233,7 @@ impl Channel {
                     let pos = ARRAY_SVH_IDX[sv - 25];
                     alm.svh = getbitu(buf, pos, 6);
                     if alm.svh != 0 {
-                        log::warn!("{}: sv {} is unhealthy", self.sv, sv)
+                        tracing::info!("{}: sv {} is unhealthy", self.sv, sv)
                     }
                 }
             } else if svid == 55 {
@@ -208,7 +265,7 @@ impl Channel {
             }
         }
 
-        log::warn!(
+        tracing::debug!(
             "{}: {}: data_id={data_id} svid={svid} tow={}",
             self.sv,
             "subframe-4".blue(),
@@ -218,15 +275,18 @@ impl Channel {
 
     fn nav_decode_lnav_subframe5(&mut self, buf: &[u8]) {
         self.nav.eph.tow = getbitu(buf, 30, 17) * 6;
+        if is_qzss(self.sv) {
+            return;
+        }
         let data_id = getbitu(buf, 60, 2);
-        let svid = getbitu(buf, 62, 4);
+        let svid = getbitu(buf, 62, 6);
         let alm_array = &mut self.pub_state.lock().unwrap().almanac;
 
         if data_id == 1 {
             if (1..=24).contains(&svid) {
                 let alm = alm_array.get_mut(svid as usize - 1).unwrap();
                 alm.nav_decode_alm(buf, svid);
-                log::warn!("{}: {:?}", self.sv, alm);
+                tracing::debug!("{}: {:?}", self.sv, alm);
             } else if svid == 51 {
                 let toas = getbitu(buf, 68, 8) * 4096;
                 let week = getbitu(buf, 76, 8) + 2048;
@@ -237,10 +297,10 @@ impl Channel {
                 ];
                 for sv in 1..=24 {
                     let alm = alm_array.get_mut(sv - 1).unwrap();
-                    let pos = ARRAY_SVH_IDX[sv - 25];
+                    let pos = ARRAY_SVH_IDX[sv - 1];
                     alm.svh = getbitu(buf, pos, 6);
                     if alm.svh != 0 {
-                        log::warn!("{}: sv {} is unhealthy", self.sv, sv)
+                        tracing::info!("{}: sv {} is unhealthy", self.sv, sv)
                     }
                 }
                 for sv in 1..=32 {
@@ -249,11 +309,11 @@ impl Channel {
                     alm.toas = toas;
                 }
             } else {
-                log::warn!("XXX unknown svid={}", svid);
+                tracing::debug!("XXX unknown svid={}", svid);
             }
         }
 
-        log::warn!(
+        tracing::debug!(
             "{}: {}: data_id={data_id} svid={svid} tow={}",
             self.sv,
             "subframe-5".blue(),
@@ -267,29 +327,23 @@ impl Channel {
         (self.pub_state.lock().unwrap().update_func.func)();
     }
 
-    fn nav_subframe_post(&mut self) {
+    pub(crate) fn nav_subframe_post(&mut self) {
+        self.record_milestone(Milestone::Subframe);
         if self.is_ephemeris_complete() {
+            self.record_milestone(Milestone::Ephemeris);
             self.pub_state
                 .lock()
                 .unwrap()
                 .channels
-                .get_mut(&self.sv)
+                .get_mut(&self.key)
                 .unwrap()
                 .has_eph = true;
         }
         if self.nav.eph.week != 0 {
-            let week_to_secs = self.nav.eph.week * SECS_PER_WEEK;
-            let tow_secs_gpst = week_to_secs + self.nav.eph.tow;
-            let toe_secs_gpst = week_to_secs + self.nav.eph.toe;
-            let toc_secs_gpst = week_to_secs + self.nav.eph.toc;
-
-            self.nav.eph.tow_gpst = Epoch::from_gpst_seconds(tow_secs_gpst.into());
-            self.nav.eph.toe_gpst = Epoch::from_gpst_seconds(toe_secs_gpst.into());
-            self.nav.eph.toc_gpst = Epoch::from_gpst_seconds(toc_secs_gpst.into());
-
+            self.nav.eph.set_gpst();
             self.nav.eph.ts_sec = self.ts_sec;
 
-            log::warn!(
+            tracing::debug!(
                 "{}: tow={:?} tgd={:+e} toe={:?}",
                 self.sv,
                 self.nav.eph.tow_gpst,
@@ -299,32 +353,43 @@ impl Channel {
 
             self.update_gpst_time(self.nav.eph.tow_gpst);
         }
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.channels.get_mut(&self.key).unwrap().eph = Some(self.nav.eph);
     }
 
-    fn nav_decode_lnav_subframe(&mut self, buf: &[u8]) -> u32 {
+    // the subframe id, None when the parity was right but not the content
+    fn nav_decode_lnav_subframe(&mut self, buf: &[u8]) -> Option<u32> {
         let preamble = getbitu(buf, 0, 8);
-        assert_eq!(preamble, 0x8b);
+        let zero = getbitu(buf, 58, 2);
+        if preamble != 0x8b || zero != 0 {
+            tracing::warn!(
+                "{}: malformed subframe: preamble={preamble:#x} zero={zero}",
+                self.sv
+            );
+            return None;
+        }
         self.nav.eph.tlm = getbitu(buf, 8, 14);
         let _isf = getbitu(buf, 22, 1);
         let _rsvd = getbitu(buf, 23, 1);
         let _alert = getbitu(buf, 47, 1);
         let _anti_spoof = getbitu(buf, 48, 1);
         let subframe_id = getbitu(buf, 49, 3);
-        let zero = getbitu(buf, 58, 2);
-        assert_eq!(zero, 0);
 
+        if (1..=3).contains(&subframe_id) {
+            self.nav.subframes[subframe_id as usize - 1] = buf.to_vec();
+        }
         match subframe_id {
             1 => self.nav_decode_lnav_subframe1(buf),
             2 => self.nav_decode_lnav_subframe2(buf),
             3 => self.nav_decode_lnav_subframe3(buf),
             4 => self.nav_decode_lnav_subframe4(buf),
             5 => self.nav_decode_lnav_subframe5(buf),
-            _ => log::warn!("{}: invalid subframe id={subframe_id}", self.sv),
+            _ => tracing::warn!("{}: invalid subframe id={subframe_id}", self.sv),
         }
 
         self.nav_subframe_post();
 
-        subframe_id
+        Some(subframe_id)
     }
 
     fn nav_decode_lnav(&mut self, sync: SyncState) {
@@ -332,61 +397,96 @@ impl Channel {
         let bits_len = self.nav.bits.len();
         let bits_raw = &self.nav.bits[bits_len - 308..bits_len - 8];
         let bits: Vec<_> = bits_raw.iter().map(|v| v ^ rev).collect();
-        let mut nav_data = vec![0; 300];
 
-        if Self::nav_test_lnav_parity(&bits, &mut nav_data) {
+        if self.nav_decode_lnav_bits(&bits).is_some() {
             self.nav.nav_sync = self.num_trk_samples;
             self.nav.sync_state = sync;
-
-            let id = self.nav_decode_lnav_subframe(&nav_data);
-            let hex_str = hex_str(&nav_data[0..300]);
-            log::info!("{}: LNAV: id={id} -- {hex_str}", self.sv);
         } else {
             self.nav.nav_sync = 0;
             self.nav.sync_state = SyncState::Normal;
-            self.nav.count_parity_err += 1;
-
-            log::warn!("{}: PARITY ERROR", self.sv);
         }
     }
 
-    fn nav_test_lnav_parity(bits: &[u8], nav_data: &mut [u8]) -> bool {
-        const MASK: [u32; 6] = [
-            0x2EC7CD2, 0x1763E69, 0x2BB1F34, 0x15D8F9A, 0x1AEC7CD, 0x22DEA27,
-        ];
-        assert_eq!(bits.len(), 300);
+    // The 300 bits of a subframe, polarity resolved: the subframe id once its
+    // parity is checked and it is decoded. Whatever the bits, it doesn't
+    // panic: malformed subframes count as parity errors.
+    pub fn nav_decode_lnav_bits(&mut self, bits: &[u8]) -> Option<u32> {
+        let mut nav_data = vec![0; 300];
+        let parity_ok = nav_test_lnav_parity(bits, &mut nav_data);
+        self.log_subframe(bits, &nav_data, parity_ok);
 
-        let mut data: u32 = 0;
-        for i in 0..10 {
-            for j in 0..30 {
-                data = (data << 1) | bits[i * 30 + j] as u32;
-            }
-            if data & (1 << 30) != 0 {
-                data ^= 0x3FFFFFC0;
+        let id = if parity_ok {
+            self.nav_decode_lnav_subframe(&nav_data)
+        } else {
+            tracing::warn!("{}: PARITY ERROR", self.sv);
+            None
+        };
+        match id {
+            Some(id) => {
+                self.nav.count_subframes += 1;
+                let hex_str = hex_str(&nav_data[0..300]);
+                tracing::info!("{}: LNAV: id={id} -- {hex_str}", self.sv);
             }
-            #[allow(clippy::needless_range_loop)]
-            for j in 0..6 {
-                let v0 = (data >> 6) & MASK[j];
-                let v1: u8 = ((data >> (5 - j)) & 1) as u8;
-                if xor_bits(v0) != v1 {
-                    return false;
-                }
+            None => self.nav.count_parity_err += 1,
+        }
+        id
+    }
+
+    pub(crate) fn log_subframe(&self, bits: &[u8], nav_data: &[u8], parity_ok: bool) {
+        let len = bits.len().div_ceil(8);
+        let data = if parity_ok {
+            nav_data[..len].to_vec()
+        } else {
+            let mut data = vec![0; len];
+            for (i, &bit) in bits.iter().enumerate() {
+                setbitu(&mut data, i, 1, bit as u32);
             }
-            setbitu(nav_data, 30 * i, 24, (data >> 6) & 0xFFFFFF);
-            setbitu(nav_data, 30 * i + 24, 6, 0);
+            data
+        };
+        let mut pub_state = self.pub_state.lock().unwrap();
+        let subframes = &mut pub_state.channels.get_mut(&self.key).unwrap().subframes;
+        if subframes.len() >= SUBFRAME_LOG_NUM {
+            subframes.pop_back();
         }
-        true
+        subframes.push_front(SubframeLog {
+            ts_sec: self.ts_sec,
+            parity_ok,
+            data,
+        });
     }
 
-    fn nav_decode_sbas(&mut self) {
-        log::warn!("{}: SBAS frame", self.sv);
+    // Ephemeris saved by a previous run. The time of week still has to come
+    // from a live subframe, which completes the ephemeris. Not kept in
+    // nav.subframes: only what was received live gets saved again.
+    pub fn nav_load_subframes(&mut self, subframes: &[Vec<u8>]) {
+        for buf in subframes.iter().filter(|buf| buf.len() == 300) {
+            match getbitu(buf, 49, 3) {
+                1 => self.nav_decode_lnav_subframe1(buf),
+                2 => self.nav_decode_lnav_subframe2(buf),
+                3 => self.nav_decode_lnav_subframe3(buf),
+                id => tracing::warn!("{}: unexpected saved subframe id={id}", self.sv),
+            }
+        }
     }
 
     pub fn nav_decode(&mut self) {
         const PREAMBULE: [u8; 8] = [1, 0, 0, 0, 1, 0, 1, 1];
         let preambule = &PREAMBULE[0..];
 
-        if self.sv.prn >= 120 && self.sv.prn <= 158 {
+        if self.sv.constellation == Constellation::Glonass {
+            self.nav_decode_glo();
+            return;
+        }
+        if self.sv.constellation == Constellation::BeiDou {
+            self.nav_decode_bds();
+            return;
+        }
+        // the I/NAV of E1 and the CNAV of L2C and L5 aren't decoded: these
+        // satellites are tracked only
+        if self.sig != "L1CA" {
+            return;
+        }
+        if is_sbas(self.sv) {
             self.nav_decode_sbas();
             return;
         }
@@ -415,3 +515,65 @@ impl Channel {
         }
     }
 }
+
+// The data bits of the 300 bits of a subframe into nav_data, packed, 30 bits
+// per word with the parity bits zeroed: false if any word fails its parity.
+pub fn nav_test_lnav_parity(bits: &[u8], nav_data: &mut [u8]) -> bool {
+    if bits.len() != 300 || nav_data.len() < 38 {
+        return false;
+    }
+
+    let mut data: u32 = 0;
+    for i in 0..10 {
+        for j in 0..30 {
+            data = (data << 1) | (bits[i * 30 + j] & 1) as u32;
+        }
+        if data & (1 << 30) != 0 {
+            data ^= 0x3FFFFFC0;
+        }
+        #[allow(clippy::needless_range_loop)]
+        for j in 0..6 {
+            let v0 = (data >> 6) & LNAV_PARITY_MASK[j];
+            let v1: u8 = ((data >> (5 - j)) & 1) as u8;
+            if xor_bits(v0) != v1 {
+                return false;
+            }
+        }
+        setbitu(nav_data, 30 * i, 24, (data >> 6) & 0xFFFFFF);
+        setbitu(nav_data, 30 * i + 24, 6, 0);
+    }
+    true
+}
+
+// parity bits D25..D30 of a word, prev holding D29* and D30* of the previous one
+fn lnav_word_parity(prev: u32, data: u32) -> u32 {
+    let v = (prev << 24) | data;
+    (LNAV_PARITY_MASK.iter()).fold(0, |parity, &mask| (parity << 1) | xor_bits(v & mask) as u32)
+}
+
+// The 300 bits of a subframe as transmitted, from its 24 data bits per 30-bit
+// word: complemented after a word ending with a 1, followed by their parity.
+// The last 2 data bits of words 2 and 10 are chosen for these words to end
+// with zeros, as the decoding of the next word and subframe expects.
+pub fn nav_encode_lnav_parity(nav_data: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(300);
+    let mut prev = 0;
+    for i in 0..10 {
+        let mut data = getbitu(nav_data, 30 * i, 24);
+        let mut parity = lnav_word_parity(prev, data);
+        if i == 1 || i == 9 {
+            for t in 0..4 {
+                data = (data & !3) | t;
+                parity = lnav_word_parity(prev, data);
+                if parity & 3 == 0 {
+                    break;
+                }
+            }
+        }
+        let word = if prev & 1 != 0 { data ^ 0xFFFFFF } else { data };
+        bits.extend((0..24).rev().map(|j| ((word >> j) & 1) as u8));
+        bits.extend((0..6).rev().map(|j| ((parity >> j) & 1) as u8));
+        prev = parity & 3;
+    }
+    bits
+}
@@ -0,0 +1,339 @@
+use colored::Colorize;
+
+use crate::{
+    channel::Channel,
+    code::Code,
+    constants::{
+        BDT_GPST_OFFSET_SEC, BDT_GPST_OFFSET_WEEKS, P2_6, P2_19, P2_31, P2_33, P2_43, P2_50, P2_66,
+        SC2RAD,
+    },
+    ephemeris::Ephemeris,
+    util::{
+        bits_equal, bits_opposed, getbits, getbits2, getbitu, getbitu2, hex_str, setbitu, setbitu2,
+    },
+};
+
+const PREAMBLE: [u8; 11] = [1, 1, 1, 0, 0, 0, 1, 0, 0, 1, 0];
+const SUBFRAME_BITS: usize = 300;
+const DECODE_DELAY_BITS: usize = 8; // as the GPS subframes: NAV_DECODE_DELAY_SEC
+const BCH_GENERATOR: u32 = 0b10011; // x^4 + x + 1
+const SECS_PER_WEEK: u32 = 604800;
+const TOE_MSB_MASK: u32 = 0xC0000; // toe bits of subframe 2, those of subframe 3 below
+
+// remainder of a 15-bit word by the generator of the BCH(15,11) code
+fn bch_remainder(word: u32) -> u32 {
+    let mut r = word;
+    for i in (4..15).rev() {
+        if r & (1 << i) != 0 {
+            r ^= BCH_GENERATOR << (i - 4);
+        }
+    }
+    r
+}
+
+// The 11 data bits of a BCH(15,11) word, msb first, a single error corrected:
+// all the syndromes are those of an error.
+fn bch_decode(word: u32) -> (u32, bool) {
+    let syndrome = bch_remainder(word);
+    match (0..15).find(|&p| syndrome != 0 && bch_remainder(1 << p) == syndrome) {
+        Some(p) => ((word ^ (1 << p)) >> 4, true),
+        None => (word >> 4, false),
+    }
+}
+
+fn bch_encode(data: u32) -> u32 {
+    let word = data << 4;
+    word | bch_remainder(word)
+}
+
+fn pack<'a>(bits: impl Iterator<Item = &'a u8>) -> u32 {
+    bits.fold(0, |w, &b| (w << 1) | (b & 1) as u32)
+}
+
+// The 300 bits of a D1 subframe, polarity resolved, into nav_data packed as
+// 30-bit words with their data bits first, the parity bits zeroed: the 15
+// uncoded bits and the 11 of the BCH word of word 1, the 22 of the two
+// interleaved BCH words of the others. The number of errors corrected, None
+// if bits isn't a subframe.
+pub fn bds_d1_decode_bits(bits: &[u8], nav_data: &mut [u8]) -> Option<usize> {
+    if bits.len() != SUBFRAME_BITS || nav_data.len() < SUBFRAME_BITS.div_ceil(8) {
+        return None;
+    }
+    let mut corrected = 0;
+    for (i, word) in bits.chunks(30).enumerate() {
+        let (pos, codewords) = if i == 0 {
+            setbitu(nav_data, 0, 15, pack(word[..15].iter()));
+            (15, vec![pack(word[15..].iter())])
+        } else {
+            let even = pack(word.iter().step_by(2));
+            let odd = pack(word.iter().skip(1).step_by(2));
+            (30 * i, vec![even, odd])
+        };
+        let end = pos + 11 * codewords.len();
+        for (j, codeword) in codewords.into_iter().enumerate() {
+            let (data, fixed) = bch_decode(codeword);
+            corrected += fixed as usize;
+            setbitu(nav_data, pos + 11 * j, 11, data);
+        }
+        setbitu(nav_data, end, 30 * (i + 1) - end, 0);
+    }
+    Some(corrected)
+}
+
+// The 300 bits of a D1 subframe as transmitted, from nav_data as
+// bds_d1_decode_bits() gives it.
+pub fn bds_d1_encode_bits(nav_data: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(SUBFRAME_BITS);
+    let to_bits = |word: u32, len: usize| (0..len).rev().map(move |j| ((word >> j) & 1) as u8);
+    for i in 0..10 {
+        if i == 0 {
+            bits.extend(to_bits(getbitu(nav_data, 0, 15), 15));
+            bits.extend(to_bits(bch_encode(getbitu(nav_data, 15, 11)), 15));
+        } else {
+            let w1: Vec<_> = to_bits(bch_encode(getbitu(nav_data, 30 * i, 11)), 15).collect();
+            let w2: Vec<_> = to_bits(bch_encode(getbitu(nav_data, 30 * i + 11, 11)), 15).collect();
+            bits.extend(w1.into_iter().zip(w2).flat_map(|(b1, b2)| [b1, b2]));
+        }
+    }
+    bits
+}
+
+// the fields of a toe in BDT, seconds, in gps time as eph.toe keeps it
+fn set_toe_bdt(eph: &mut Ephemeris, mask: u32, toe_bdt: u32) {
+    let old = eph.toe.saturating_sub(BDT_GPST_OFFSET_SEC);
+    eph.toe = ((old & !mask) | (toe_bdt & mask)) + BDT_GPST_OFFSET_SEC;
+}
+
+// BeiDou D1 subframes 1 to 3: the ephemeris in BDT, kept in gps time, toe
+// split over subframes 2 and 3 (BDS-SIS-ICD-B1I section 5.2.4).
+impl Ephemeris {
+    // the subframe id, its sow as the time of week of the next subframe
+    pub fn nav_decode_d1_subframe(&mut self, buf: &[u8]) -> u32 {
+        let id = getbitu(buf, 15, 3);
+        let sow = getbitu2(buf, 18, 8, 30, 12);
+        self.tow = sow + 6 + BDT_GPST_OFFSET_SEC;
+        if self.tow >= SECS_PER_WEEK {
+            self.tow -= SECS_PER_WEEK;
+            if self.week != 0 {
+                self.week += 1;
+            }
+        }
+        match id {
+            1 => self.nav_decode_d1_subframe1(buf),
+            2 => self.nav_decode_d1_subframe2(buf),
+            3 => self.nav_decode_d1_subframe3(buf),
+            _ => {}
+        }
+        id
+    }
+
+    fn nav_decode_d1_subframe1(&mut self, buf: &[u8]) {
+        self.svh = getbitu(buf, 42, 1);
+        self.iodc = getbitu(buf, 43, 5);
+        self.sva = getbitu(buf, 48, 4);
+        self.week = getbitu(buf, 60, 13) + BDT_GPST_OFFSET_WEEKS;
+        if self.tow < SECS_PER_WEEK / 2 && getbitu2(buf, 18, 8, 30, 12) > SECS_PER_WEEK / 2 {
+            self.week += 1; // the gps week started first
+        }
+        self.toc = getbitu2(buf, 73, 9, 90, 8) * 8 + BDT_GPST_OFFSET_SEC;
+        self.tgd = getbits(buf, 98, 10) as f64 * 0.1e-9; // B1I
+        self.f2 = getbits(buf, 214, 11) as f64 * P2_66;
+        self.f0 = getbits2(buf, 225, 7, 240, 17) as f64 * P2_33;
+        self.f1 = getbits2(buf, 257, 5, 270, 17) as f64 * P2_50;
+        self.iode = getbitu(buf, 287, 5);
+
+        tracing::debug!(
+            "{}: {} tow={} week={} sva={} svh={} aodc={} aode={} tgd={:+e} toc={} f0={:+e} f1={:+e} f2={:+e}",
+            self.sv,
+            "D1 subframe-1".blue(),
+            self.tow,
+            self.week,
+            self.sva,
+            self.svh,
+            self.iodc,
+            self.iode,
+            self.tgd,
+            self.toc,
+            self.f0,
+            self.f1,
+            self.f2
+        );
+    }
+
+    fn nav_decode_d1_subframe2(&mut self, buf: &[u8]) {
+        self.deln = getbits2(buf, 42, 10, 60, 6) as f64 * P2_43 * SC2RAD;
+        self.cuc = getbits2(buf, 66, 16, 90, 2) as f64 * P2_31;
+        self.m0 = getbits2(buf, 92, 20, 120, 12) as f64 * P2_31 * SC2RAD;
+        self.ecc = getbitu2(buf, 132, 10, 150, 22) as f64 * P2_33;
+        self.cus = getbits(buf, 180, 18) as f64 * P2_31;
+        self.crc = getbits2(buf, 198, 4, 210, 14) as f64 * P2_6;
+        self.crs = getbits2(buf, 224, 8, 240, 10) as f64 * P2_6;
+        let sqrt_a = getbitu2(buf, 250, 12, 270, 20) as f64 * P2_19;
+        self.a = sqrt_a * sqrt_a;
+        set_toe_bdt(self, TOE_MSB_MASK, getbitu(buf, 290, 2) << 18);
+
+        tracing::debug!(
+            "{}: {} tow={} a={} crs={} crc={} cuc={:+e} cus={:+e} ecc={} m0={}",
+            self.sv,
+            "D1 subframe-2".blue(),
+            self.tow,
+            self.a,
+            self.crs,
+            self.crc,
+            self.cuc,
+            self.cus,
+            self.ecc,
+            self.m0,
+        );
+    }
+
+    fn nav_decode_d1_subframe3(&mut self, buf: &[u8]) {
+        set_toe_bdt(self, !TOE_MSB_MASK, getbitu2(buf, 42, 10, 60, 5) << 3);
+        self.i0 = getbits2(buf, 65, 17, 90, 15) as f64 * P2_31 * SC2RAD;
+        self.cic = getbits2(buf, 105, 7, 120, 11) as f64 * P2_31;
+        self.omg_dot = getbits2(buf, 131, 11, 150, 13) as f64 * P2_43 * SC2RAD;
+        self.cis = getbits2(buf, 163, 9, 180, 9) as f64 * P2_31;
+        self.i_dot = getbits2(buf, 189, 13, 210, 1) as f64 * P2_43 * SC2RAD;
+        self.omg0 = getbits2(buf, 211, 21, 240, 11) as f64 * P2_31 * SC2RAD;
+        self.omg = getbits2(buf, 251, 11, 270, 21) as f64 * P2_31 * SC2RAD;
+
+        tracing::debug!(
+            "{}: {} tow={} toe={} cic={:+e} cis={:+e} omg={} omg0={} omgd={:+e} i0={} idot={:+e}",
+            self.sv,
+            "D1 subframe-3".blue(),
+            self.tow,
+            self.toe,
+            self.cic,
+            self.cis,
+            self.omg,
+            self.omg0,
+            self.omg_dot,
+            self.i0,
+            self.i_dot
+        );
+    }
+
+    // The reverse of nav_decode_d1_subframe(): subframe id of a D1 frame
+    // starting at the BDT sow, without the BCH parity. Subframes 4 and 5
+    // carry no page.
+    pub fn nav_encode_d1_subframe(&self, id: u32, sow: u32) -> Vec<u8> {
+        let mut buf = vec![0; SUBFRAME_BITS];
+        let preamble = PREAMBLE.iter().fold(0, |w, &b| (w << 1) | b as u32);
+        setbitu(&mut buf, 0, 11, preamble);
+        setbitu(&mut buf, 15, 3, id);
+        setbitu2(&mut buf, 18, 8, 30, 12, sow);
+        let toe = self.toe.saturating_sub(BDT_GPST_OFFSET_SEC) / 8;
+        let scaled = |v: f64, scale: f64| (v / scale).round() as i64 as u32;
+        match id {
+            1 => {
+                setbitu(&mut buf, 42, 1, self.svh);
+                setbitu(&mut buf, 43, 5, self.iodc);
+                setbitu(&mut buf, 48, 4, self.sva);
+                setbitu(
+                    &mut buf,
+                    60,
+                    13,
+                    self.week.saturating_sub(BDT_GPST_OFFSET_WEEKS),
+                );
+                let toc = self.toc.saturating_sub(BDT_GPST_OFFSET_SEC) / 8;
+                setbitu2(&mut buf, 73, 9, 90, 8, toc);
+                setbitu(&mut buf, 98, 10, scaled(self.tgd, 0.1e-9));
+                setbitu(&mut buf, 214, 11, scaled(self.f2, P2_66));
+                setbitu2(&mut buf, 225, 7, 240, 17, scaled(self.f0, P2_33));
+                setbitu2(&mut buf, 257, 5, 270, 17, scaled(self.f1, P2_50));
+                setbitu(&mut buf, 287, 5, self.iode);
+            }
+            2 => {
+                setbitu2(&mut buf, 42, 10, 60, 6, scaled(self.deln, P2_43 * SC2RAD));
+                setbitu2(&mut buf, 66, 16, 90, 2, scaled(self.cuc, P2_31));
+                setbitu2(&mut buf, 92, 20, 120, 12, scaled(self.m0, P2_31 * SC2RAD));
+                setbitu2(&mut buf, 132, 10, 150, 22, scaled(self.ecc, P2_33));
+                setbitu(&mut buf, 180, 18, scaled(self.cus, P2_31));
+                setbitu2(&mut buf, 198, 4, 210, 14, scaled(self.crc, P2_6));
+                setbitu2(&mut buf, 224, 8, 240, 10, scaled(self.crs, P2_6));
+                setbitu2(&mut buf, 250, 12, 270, 20, scaled(self.a.sqrt(), P2_19));
+                setbitu(&mut buf, 290, 2, toe >> 15);
+            }
+            3 => {
+                setbitu2(&mut buf, 42, 10, 60, 5, toe & 0x7FFF);
+                setbitu2(&mut buf, 65, 17, 90, 15, scaled(self.i0, P2_31 * SC2RAD));
+                setbitu2(&mut buf, 105, 7, 120, 11, scaled(self.cic, P2_31));
+                setbitu2(
+                    &mut buf,
+                    131,
+                    11,
+                    150,
+                    13,
+                    scaled(self.omg_dot, P2_43 * SC2RAD),
+                );
+                setbitu2(&mut buf, 163, 9, 180, 9, scaled(self.cis, P2_31));
+                setbitu2(
+                    &mut buf,
+                    189,
+                    13,
+                    210,
+                    1,
+                    scaled(self.i_dot, P2_43 * SC2RAD),
+                );
+                setbitu2(
+                    &mut buf,
+                    211,
+                    21,
+                    240,
+                    11,
+                    scaled(self.omg0, P2_31 * SC2RAD),
+                );
+                setbitu2(&mut buf, 251, 11, 270, 21, scaled(self.omg, P2_31 * SC2RAD));
+            }
+            _ => {}
+        }
+        buf
+    }
+}
+
+impl Channel {
+    // D1 subframes are searched bit by bit: the preamble at the start of the
+    // 300 bits and at that of the next subframe, 8 bits of it, decoded there
+    // as the GPS subframes for the same delay to the receiver time.
+    pub fn nav_decode_bds(&mut self) {
+        // the D2 message of the GEO satellites isn't decoded
+        if Code::get_secondary_code(&self.sig, self.sv.prn).is_empty() {
+            return;
+        }
+        if !self.nav_sync_symbol(20) {
+            return;
+        }
+        let len = self.nav.bits.len();
+        let window = &self.nav.bits[len - SUBFRAME_BITS - DECODE_DELAY_BITS..];
+        let (head, next) = (&window[..PREAMBLE.len()], &window[SUBFRAME_BITS..]);
+        let rev = if bits_equal(&PREAMBLE, head) && bits_equal(&PREAMBLE[..DECODE_DELAY_BITS], next)
+        {
+            0
+        } else if bits_opposed(&PREAMBLE, head)
+            && bits_opposed(&PREAMBLE[..DECODE_DELAY_BITS], next)
+        {
+            1
+        } else {
+            return;
+        };
+        let bits: Vec<_> = window[..SUBFRAME_BITS].iter().map(|b| b ^ rev).collect();
+        let mut nav_data = vec![0; SUBFRAME_BITS];
+        let corrected = bds_d1_decode_bits(&bits, &mut nav_data).unwrap_or_default();
+        let id = getbitu(&nav_data, 15, 3);
+        if !(1..=5).contains(&id) {
+            tracing::warn!("{}: malformed D1 subframe: id={id}", self.sv);
+            self.log_subframe(&bits, &nav_data, false);
+            self.nav.count_parity_err += 1;
+            return;
+        }
+        self.log_subframe(&bits, &nav_data, true);
+        self.nav.count_subframes += 1;
+        tracing::info!(
+            "{}: D1: id={id} corrected={corrected} -- {}",
+            self.sv,
+            hex_str(&nav_data)
+        );
+        self.nav.eph.nav_decode_d1_subframe(&nav_data);
+        self.nav_subframe_post();
+    }
+}
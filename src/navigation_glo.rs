@@ -0,0 +1,227 @@
+use colored::Colorize;
+
+use crate::{
+    channel::Channel,
+    constants::{P2_11, P2_20, P2_30, P2_40},
+    start::get_gps_sec,
+    util::{bits_equal, bits_opposed, getbitu, hex_str, setbitu},
+};
+
+const STRING_SYMS: usize = 200; // 2 sec of 10 msec symbols
+const MARK_SYMS: usize = 30; // 0.3 sec
+const STRING_BITS: usize = 85;
+const DECODE_DELAY_SYMS: usize = 16; // as the GPS subframes: NAV_DECODE_DELAY_SEC
+const TIME_MARK: [u8; MARK_SYMS] = [
+    1, 1, 1, 1, 1, 0, 0, 0, 1, 1, 0, 1, 1, 1, 0, 1, 0, 1, 0, 0, 0, 0, 1, 0, 0, 1, 0, 1, 1, 0,
+];
+const GLO_EPOCH_UNIX_SEC: f64 = 820_454_400.0; // 1996-01-01, first four-year interval
+const MOSCOW_UTC_OFFSET_SEC: f64 = 3.0 * 3600.0;
+const SECS_PER_DAY: f64 = 86400.0;
+const SECS_PER_WEEK: f64 = 604800.0;
+
+// The immediate data being received: strings 1 to 4 of the current frame,
+// the date and the offset to gps time from the last strings 4 and 5.
+#[derive(Default)]
+pub struct GloFrame {
+    strings: [Option<Vec<u8>>; 4],
+    start: Option<(f64, f64)>, // Moscow time of day of the frame, receiver time of string 1
+    nt: Option<u32>,           // day within the four-year interval
+    n4: Option<u32>,           // four-year interval since 1996
+    tau_gps: f64,              // fraction of a second of gps minus GLONASS time
+}
+
+impl GloFrame {
+    pub fn clear(&mut self) {
+        self.strings = Default::default();
+        self.start = None;
+    }
+}
+
+// GLONASS fields are sign and magnitude
+fn getbitg(buf: &[u8], pos: usize, len: usize) -> f64 {
+    let v = getbitu(buf, pos + 1, len - 1) as f64;
+    if getbitu(buf, pos, 1) != 0 { -v } else { v }
+}
+
+// bit n of a string as the ICD numbers them, 85 first
+fn get_string_bit(buf: &[u8], n: usize) -> u32 {
+    getbitu(buf, STRING_BITS - n, 1)
+}
+
+// The check bits b1..b8 of a string: an extended Hamming code over b9..b85,
+// these at the positions of the code that aren't powers of 2.
+pub fn glo_string_check(buf: &[u8]) -> bool {
+    let mut syndrome = 0;
+    let mut pos: u32 = 2;
+    for n in 9..=STRING_BITS {
+        pos += 1;
+        while pos.is_power_of_two() {
+            pos += 1;
+        }
+        if get_string_bit(buf, n) != 0 {
+            syndrome ^= pos;
+        }
+    }
+    for j in 0..7 {
+        syndrome ^= get_string_bit(buf, j + 1) << j;
+    }
+    let parity = (1..=STRING_BITS).fold(0, |p, n| p ^ get_string_bit(buf, n));
+    syndrome == 0 && parity == 0
+}
+
+// The 170 symbols of the data of a string into its 85 bits, packed with bit
+// 85 first: the 10 msec meander removed, then the relative code. None when
+// a bit doesn't have the two opposite halves of the meander.
+pub fn glo_string_from_symbols(syms: &[u8]) -> Option<Vec<u8>> {
+    if syms.len() != 2 * STRING_BITS {
+        return None;
+    }
+    let mut buf = vec![0; STRING_BITS];
+    let mut prev = 0;
+    for (i, pair) in syms.chunks(2).enumerate() {
+        if pair[0] == pair[1] {
+            return None;
+        }
+        // bit 85 is an idle 0
+        if i > 0 {
+            setbitu(&mut buf, i, 1, (pair[0] ^ prev) as u32);
+        }
+        prev = pair[0];
+    }
+    Some(buf)
+}
+
+// Gps seconds of a Moscow time of day tod_sec on day nt of the four-year
+// interval n4. Unix time has no leap seconds: whole days add up.
+fn get_glo_gps_sec(n4: u32, nt: u32, tod_sec: f64) -> f64 {
+    let days = (n4.saturating_sub(1) * 1461 + nt.saturating_sub(1)) as f64;
+    get_gps_sec(GLO_EPOCH_UNIX_SEC + days * SECS_PER_DAY + tod_sec - MOSCOW_UTC_OFFSET_SEC)
+}
+
+impl Channel {
+    // Strings are searched by their time mark, at its end: the data are the
+    // 170 symbols before it. Decoded 16 symbols later, the receiver time of
+    // a string relates to its end as that of a GPS subframe to its start.
+    pub fn nav_decode_glo(&mut self) {
+        if !self.nav_sync_symbol(10) {
+            return;
+        }
+        let len = self.nav.bits.len();
+        let syms = &self.nav.bits[len - DECODE_DELAY_SYMS - STRING_SYMS..len - DECODE_DELAY_SYMS];
+        let (data, mark) = syms.split_at(STRING_SYMS - MARK_SYMS);
+        // the polarity doesn't matter to the relative code, only to the mark
+        if !bits_equal(&TIME_MARK, mark) && !bits_opposed(&TIME_MARK, mark) {
+            return;
+        }
+        let syms = data.to_vec();
+        let Some(buf) = glo_string_from_symbols(&syms).filter(|buf| glo_string_check(buf)) else {
+            tracing::warn!("{}: STRING ERROR", self.sv);
+            self.log_subframe(&syms, &[], false);
+            self.nav.count_parity_err += 1;
+            return;
+        };
+        let bits: Vec<_> = (0..STRING_BITS)
+            .map(|i| getbitu(&buf, i, 1) as u8)
+            .collect();
+        self.log_subframe(&bits, &buf, true);
+        self.nav.count_subframes += 1;
+        self.nav_decode_glo_string(&buf);
+    }
+
+    fn nav_decode_glo_string(&mut self, buf: &[u8]) {
+        let m = getbitu(buf, 1, 4) as usize;
+        tracing::info!("{}: STRING: m={m} -- {}", self.sv, hex_str(buf));
+        if m == 0 {
+            return;
+        }
+        let frame = &mut self.nav.glo;
+        match m {
+            1 => {
+                let tk_sec = getbitu(buf, 9, 5) * 3600 + getbitu(buf, 14, 6) * 60;
+                let tk_sec = tk_sec + getbitu(buf, 20, 1) * 30;
+                frame.clear();
+                frame.start = Some((tk_sec as f64, self.ts_sec));
+            }
+            4 => frame.nt = Some(getbitu(buf, 59, 11)),
+            5 => {
+                frame.n4 = Some(getbitu(buf, 49, 5));
+                frame.tau_gps = getbitg(buf, 54, 22) * P2_30;
+            }
+            _ => {}
+        }
+        // the receiver time tells whether the string belongs to the frame
+        let Some((tk_sec, ts_sec)) = frame.start else {
+            return;
+        };
+        let t_frame = 2.0 * (m as f64 - 1.0);
+        if (self.ts_sec - ts_sec - t_frame).abs() > 0.5 {
+            frame.clear();
+            return;
+        }
+        if m <= 4 {
+            frame.strings[m - 1] = Some(buf.to_vec());
+        }
+        let (Some(nt), Some(n4)) = (frame.nt, frame.n4) else {
+            return;
+        };
+        // the end of the string
+        let gps_sec = get_glo_gps_sec(n4, nt, tk_sec + 2.0 * m as f64);
+        let week = (gps_sec / SECS_PER_WEEK).floor();
+        self.nav.eph.week = week as u32;
+        self.nav.eph.tow = (gps_sec - week * SECS_PER_WEEK).round() as u32;
+        if self.nav.glo.strings.iter().all(|s| s.is_some()) {
+            self.nav_decode_glo_ephemeris(n4, nt, gps_sec, week);
+        }
+        self.nav_subframe_post();
+    }
+
+    // strings 1 to 4 of a frame
+    fn nav_decode_glo_ephemeris(&mut self, n4: u32, nt: u32, gps_sec: f64, week: f64) {
+        let strings: Vec<_> = self.nav.glo.strings.iter().flatten().cloned().collect();
+        let [s1, s2, s3, s4] = &strings[..] else {
+            return;
+        };
+        let tau_gps = self.nav.glo.tau_gps;
+        let eph = &mut self.nav.eph;
+        for (i, s) in [s1, s2, s3].into_iter().enumerate() {
+            eph.vel[i] = getbitg(s, 21, 24) * P2_20 * 1e3;
+            eph.acc[i] = getbitg(s, 45, 5) * P2_30 * 1e3;
+            eph.pos[i] = getbitg(s, 50, 27) * P2_11 * 1e3;
+        }
+        eph.svh = getbitu(s2, 5, 3) >> 2; // the msb of Bn
+        let tb_sec = getbitu(s2, 9, 7) as f64 * 900.0;
+        let gamma_n = getbitg(s3, 6, 11) * P2_40;
+        let tau_n = getbitg(s4, 5, 22) * P2_30;
+        eph.sva = getbitu(s4, 52, 4);
+        let slot = getbitu(s4, 70, 5);
+        if slot != self.sv.prn as u32 {
+            tracing::debug!("{}: string 4 of slot {slot}", self.sv);
+        }
+        eph.iode = tb_sec as u32 / 900;
+        eph.frq = self.freq_channel.unwrap_or(0) as i32;
+        eph.f0 = -tau_n - tau_gps;
+        eph.f1 = gamma_n;
+        eph.f2 = 0.0;
+        eph.tgd = 0.0;
+
+        // tb on the day of the string, within 12 hours of it
+        let mut toe_sec = get_glo_gps_sec(n4, nt, tb_sec);
+        toe_sec += ((gps_sec - toe_sec) / SECS_PER_DAY).round() * SECS_PER_DAY;
+        eph.toe = (toe_sec - week * SECS_PER_WEEK)
+            .rem_euclid(SECS_PER_WEEK)
+            .round() as u32;
+        eph.toc = eph.toe;
+
+        tracing::debug!(
+            "{}: {} tb={tb_sec} pos={:.1?} vel={:.3?} acc={:?} tau_n={:+e} gamma_n={:+e} svh={}",
+            self.sv,
+            "ephemeris".blue(),
+            eph.pos,
+            eph.vel,
+            eph.acc,
+            tau_n,
+            gamma_n,
+            eph.svh
+        );
+    }
+}
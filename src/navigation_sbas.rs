@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+
+use crate::{
+    channel::Channel,
+    constants::{P2_11, P2_31, P2_39, P2_40},
+    ephemeris::Ephemeris,
+    util::{getbits, getbitu, hex_str, setbitu},
+};
+
+const MSG_BITS: usize = 250;
+const CRC_BITS: usize = 24;
+const PREAMBLES: [u32; 3] = [0x53, 0x9A, 0xC6]; // in turn, 8 bits each
+const CRC24Q_POLY: u32 = 0x864CFB; // x^24 omitted
+const MASK_BITS: usize = 210;
+const FAST_SLOTS: usize = 13; // per message of types 2 to 5
+const INTEGRITY_SLOTS: usize = 51; // of the mask, in messages 6 and 7
+
+// rate 1/2, constraint length 7: the input bit is the msb of the 7 bits
+const G1: usize = 0o171; // its symbol first
+const G2: usize = 0o133;
+const NUM_STATES: usize = 64;
+const MARGIN_BITS: usize = 35; // from the ends of a window: 5 constraint lengths
+
+const SYMBOL_SEC: f64 = 0.002;
+const WINDOW_SYMS: usize = 800; // 0.8 messages past the margins
+const DECODE_PERIOD_SYMS: usize = 100; // less than the room left in a window
+const DECODE_DELAY_SEC: f64 = 0.16; // as the GPS subframes: NAV_DECODE_DELAY_SEC
+const GEO_EXTRA_DELAY_SEC: f64 = 0.05; // a GEO is ~15000 km further than a gps satellite
+
+const UDREI_NOT_MONITORED: u32 = 14;
+const UDREI_DONT_USE: u32 = 15;
+const URA_DONT_USE: u32 = 15;
+const FAST_CORR_TIMEOUT_SEC: f64 = 60.0;
+const LONG_CORR_TIMEOUT_SEC: f64 = 360.0;
+const GEO_EPH_TIMEOUT_SEC: f64 = 360.0;
+const SECS_PER_DAY: f64 = 86400.0;
+const SECS_PER_WEEK: f64 = 604800.0;
+
+// SBAS GEO satellites go by their PRN on the L1CA codes of gps
+pub fn is_sbas(sv: SV) -> bool {
+    sv.constellation == Constellation::GPS && (120..=158).contains(&sv.prn)
+}
+
+// satellite of a bit of the PRN mask, 1 to 210: None for those not tracked
+// here
+fn get_mask_sv(i: usize) -> Option<SV> {
+    match i {
+        1..=37 => Some(SV::new(Constellation::GPS, i as u8)),
+        38..=61 => Some(SV::new(Constellation::Glonass, (i - 37) as u8)),
+        120..=158 => Some(SV::new(Constellation::GPS, i as u8)),
+        193..=202 => Some(SV::new(Constellation::QZSS, i as u8)),
+        _ => None,
+    }
+}
+
+fn wrap_day(dt: f64) -> f64 {
+    dt - (dt / SECS_PER_DAY).round() * SECS_PER_DAY
+}
+
+fn parity(v: usize) -> u8 {
+    (v.count_ones() & 1) as u8
+}
+
+// CRC-24Q of the first len bits of buf, as the one of the messages over
+// their first 226 bits
+pub fn sbas_crc24q(buf: &[u8], len: usize) -> u32 {
+    let mut crc = 0;
+    for i in 0..len {
+        let msb = (crc >> 23) & 1;
+        crc = (crc << 1) & 0xFFFFFF;
+        if msb ^ getbitu(buf, i, 1) != 0 {
+            crc ^= CRC24Q_POLY;
+        }
+    }
+    crc
+}
+
+// The two symbols of each bit, from the encoder at 0: for the tests and the
+// simulations, the receiver has no use for it.
+pub fn sbas_encode_symbols(bits: &[u8]) -> Vec<u8> {
+    let mut state = 0;
+    let mut syms = Vec::with_capacity(2 * bits.len());
+    for &bit in bits {
+        let reg = ((bit as usize) << 6) | state;
+        syms.extend([parity(reg & G1), parity(reg & G2)]);
+        state = reg >> 1;
+    }
+    syms
+}
+
+// Hard decisions on the pairs of symbols, from and to any state of the
+// encoder: the bits within MARGIN_BITS of either end are less reliable. Both
+// generators have an odd weight: the symbols inverted decode to the bits
+// inverted.
+pub fn sbas_viterbi_decode(syms: &[u8]) -> Vec<u8> {
+    let num = syms.len() / 2;
+    let mut metrics = [0u32; NUM_STATES];
+    let mut decisions = vec![0u64; num]; // the lsb of the previous state
+    for (k, pair) in syms.chunks_exact(2).enumerate() {
+        let mut next = [u32::MAX; NUM_STATES];
+        for (s, metric) in next.iter_mut().enumerate() {
+            let bit = s >> 5;
+            for x in 0..2 {
+                let prev = ((s << 1) & (NUM_STATES - 1)) | x;
+                let reg = (bit << 6) | prev;
+                let m = metrics[prev]
+                    + (parity(reg & G1) != pair[0]) as u32
+                    + (parity(reg & G2) != pair[1]) as u32;
+                if m < *metric {
+                    *metric = m;
+                    decisions[k] = (decisions[k] & !(1 << s)) | ((x as u64) << s);
+                }
+            }
+        }
+        metrics = next;
+    }
+    let mut s = (0..NUM_STATES).min_by_key(|&s| metrics[s]).unwrap_or(0);
+    let mut bits = vec![0; num];
+    for k in (0..num).rev() {
+        bits[k] = (s >> 5) as u8;
+        s = ((s << 1) & (NUM_STATES - 1)) | ((decisions[k] >> s) & 1) as usize;
+    }
+    bits
+}
+
+// The messages of a window of symbols, of either polarity, away from its
+// ends: the symbol each one starts at and its 250 bits, packed. The
+// preambles change from a message to the next: any of them will do, the CRC
+// tells.
+pub fn sbas_decode_symbols(syms: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut msgs = vec![];
+    for align in 0..2 {
+        let bits = sbas_viterbi_decode(&syms[align..]);
+        if bits.len() < MSG_BITS + 2 * MARGIN_BITS {
+            continue;
+        }
+        for p in MARGIN_BITS..=bits.len() - MARGIN_BITS - MSG_BITS {
+            let head = bits[p..p + 8].iter().fold(0, |v, &b| (v << 1) | b as u32);
+            let rev = if PREAMBLES.contains(&head) {
+                0
+            } else if PREAMBLES.contains(&(head ^ 0xFF)) {
+                1
+            } else {
+                continue;
+            };
+            let mut buf = vec![0; MSG_BITS.div_ceil(8)];
+            for (i, &b) in bits[p..p + MSG_BITS].iter().enumerate() {
+                setbitu(&mut buf, i, 1, (b ^ rev) as u32);
+            }
+            let crc_len = MSG_BITS - CRC_BITS;
+            if sbas_crc24q(&buf, crc_len) == getbitu(&buf, crc_len, CRC_BITS) {
+                msgs.push((align + 2 * p, buf));
+            }
+        }
+    }
+    msgs.sort_by_key(|&(off, _)| off);
+    msgs
+}
+
+// Message types 2 to 5, and 6 and 7, for a satellite of the mask.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct SbasFastCorr {
+    pub prc: f64,    // added to the pseudorange (m)
+    pub udrei: u32,  // 14: not monitored, 15: don't use
+    pub iodf: u32,   // matches the UDREIs of type 6 to the corrections
+    pub ai: u32,     // degradation factor indicator
+    pub ts_sec: f64, // receiver time of the message
+}
+
+// Message type 25, for a satellite of the mask of the same IODE.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct SbasLongCorr {
+    pub iode: u32,
+    pub dpos: [f64; 3], // ecef (m)
+    pub dvel: [f64; 3], // m/s, with velocity code 1 only
+    pub daf0: f64,
+    pub daf1: f64,
+    pub t0: u32,     // time of day of the rates
+    pub ts_sec: f64, // receiver time of the message
+}
+
+// Message type 17, by PRN: a GEO state to within a few km.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct SbasAlmanac {
+    pub health: u32,
+    pub pos: [f64; 3], // ecef (m)
+    pub vel: [f64; 3], // m/s
+    pub t0: u32,       // time of day
+}
+
+// What a GEO broadcasts: its own ephemeris goes to the ephemeris of the
+// channel, the corrections of the satellites of its mask are kept here until
+// a fix applies them.
+#[derive(Default)]
+pub struct SbasData {
+    num_syms: usize,
+    last_msg_sec: f64,     // receiver time of the start of the last message
+    pub iodp: Option<u32>, // of the mask, once it came
+    pub mask: Vec<Option<SV>>,
+    pub fast: BTreeMap<SV, SbasFastCorr>,
+    pub long: BTreeMap<SV, SbasLongCorr>,
+    pub tlat: u32,           // system latency of type 7
+    pub geo_t0: Option<u32>, // time of day of the GEO ephemeris, once type 9 came
+    pub geo_ts_sec: f64,     // receiver time of type 9
+    pub almanac: BTreeMap<u32, SbasAlmanac>,
+}
+
+impl SbasData {
+    pub fn clear(&mut self) {
+        self.num_syms = 0;
+    }
+
+    pub fn has_corrections(&self) -> bool {
+        !self.fast.is_empty() || !self.long.is_empty()
+    }
+
+    // the message type; the GEO ephemeris of type 9 goes to eph
+    pub fn decode_message(&mut self, buf: &[u8], eph: &mut Ephemeris, ts_sec: f64) -> u32 {
+        let id = getbitu(buf, 8, 6);
+        match id {
+            1 => self.decode_mask(buf),
+            // type 0, the test mode of the WAAS, repeats type 2
+            0 | 2..=5 => self.decode_fast(buf, id.max(2) as usize, ts_sec),
+            6 => self.decode_integrity(buf),
+            7 => self.decode_degradation(buf),
+            9 => self.decode_geo(buf, eph, ts_sec),
+            17 => self.decode_almanac(buf),
+            25 => {
+                self.decode_long_half(buf, 14, ts_sec);
+                self.decode_long_half(buf, 120, ts_sec);
+            }
+            _ => {}
+        }
+        id
+    }
+
+    // the corrections of the previous mask are about other satellites
+    fn decode_mask(&mut self, buf: &[u8]) {
+        let iodp = getbitu(buf, 224, 2);
+        if self.iodp != Some(iodp) {
+            self.fast.clear();
+            self.long.clear();
+        }
+        self.iodp = Some(iodp);
+        self.mask = (1..=MASK_BITS)
+            .filter(|&i| getbitu(buf, 13 + i, 1) != 0)
+            .map(get_mask_sv)
+            .collect();
+    }
+
+    fn get_slot_sv(&self, slot: usize) -> Option<SV> {
+        self.mask.get(slot).copied().flatten()
+    }
+
+    fn decode_fast(&mut self, buf: &[u8], id: usize, ts_sec: f64) {
+        if self.iodp != Some(getbitu(buf, 16, 2)) {
+            return;
+        }
+        let iodf = getbitu(buf, 14, 2);
+        for i in 0..FAST_SLOTS {
+            let Some(sv) = self.get_slot_sv(FAST_SLOTS * (id - 2) + i) else {
+                continue;
+            };
+            let corr = self.fast.entry(sv).or_default();
+            corr.prc = getbits(buf, 18 + 12 * i, 12) as f64 * 0.125;
+            corr.udrei = getbitu(buf, 174 + 4 * i, 4);
+            corr.iodf = iodf;
+            corr.ts_sec = ts_sec;
+        }
+    }
+
+    // the UDREIs of the fast corrections of the same IODF, 13 slots each
+    fn decode_integrity(&mut self, buf: &[u8]) {
+        for slot in 0..self.mask.len().min(INTEGRITY_SLOTS) {
+            let iodf = getbitu(buf, 14 + 2 * (slot / FAST_SLOTS), 2);
+            if let Some(sv) = self.get_slot_sv(slot)
+                && let Some(corr) = self.fast.get_mut(&sv)
+                && corr.iodf == iodf
+            {
+                corr.udrei = getbitu(buf, 22 + 4 * slot, 4);
+            }
+        }
+    }
+
+    fn decode_degradation(&mut self, buf: &[u8]) {
+        if self.iodp != Some(getbitu(buf, 18, 2)) {
+            return;
+        }
+        self.tlat = getbitu(buf, 14, 4);
+        for slot in 0..self.mask.len().min(INTEGRITY_SLOTS) {
+            if let Some(sv) = self.get_slot_sv(slot)
+                && let Some(corr) = self.fast.get_mut(&sv)
+            {
+                corr.ai = getbitu(buf, 22 + 4 * slot, 4);
+            }
+        }
+    }
+
+    // a state vector at t0, in ecef
+    fn decode_geo(&mut self, buf: &[u8], eph: &mut Ephemeris, ts_sec: f64) {
+        eph.iode = getbitu(buf, 14, 8); // IODN
+        self.geo_t0 = Some(getbitu(buf, 22, 13) * 16);
+        self.geo_ts_sec = ts_sec;
+        eph.sva = getbitu(buf, 35, 4);
+        eph.svh = (eph.sva == URA_DONT_USE) as u32;
+        eph.pos = [
+            getbits(buf, 39, 30) as f64 * 0.08,
+            getbits(buf, 69, 30) as f64 * 0.08,
+            getbits(buf, 99, 25) as f64 * 0.4,
+        ];
+        eph.vel = [
+            getbits(buf, 124, 17) as f64 * 0.000625,
+            getbits(buf, 141, 17) as f64 * 0.000625,
+            getbits(buf, 158, 18) as f64 * 0.004,
+        ];
+        eph.acc = [
+            getbits(buf, 176, 10) as f64 * 0.0000125,
+            getbits(buf, 186, 10) as f64 * 0.0000125,
+            getbits(buf, 196, 10) as f64 * 0.0000625,
+        ];
+        eph.f0 = getbits(buf, 206, 12) as f64 * P2_31;
+        eph.f1 = getbits(buf, 218, 8) as f64 * P2_40;
+        eph.tgd = 0.0;
+    }
+
+    // up to 3 GEOs of 67 bits, a PRN of 0 for none
+    fn decode_almanac(&mut self, buf: &[u8]) {
+        let t0 = getbitu(buf, 215, 11) * 64;
+        for p in (0..3).map(|k| 14 + 67 * k) {
+            let prn = getbitu(buf, p + 2, 8);
+            if prn == 0 {
+                continue;
+            }
+            let alm = SbasAlmanac {
+                health: getbitu(buf, p + 10, 8),
+                pos: [
+                    getbits(buf, p + 18, 15) as f64 * 2600.0,
+                    getbits(buf, p + 33, 15) as f64 * 2600.0,
+                    getbits(buf, p + 48, 9) as f64 * 26000.0,
+                ],
+                vel: [
+                    getbits(buf, p + 57, 3) as f64 * 10.0,
+                    getbits(buf, p + 60, 3) as f64 * 10.0,
+                    getbits(buf, p + 63, 4) as f64 * 60.0,
+                ],
+                t0,
+            };
+            self.almanac.insert(prn, alm);
+        }
+    }
+
+    // half a message of type 25: two satellites without their rates, or one
+    // with them, according to the velocity code
+    fn decode_long_half(&mut self, buf: &[u8], p: usize, ts_sec: f64) {
+        if getbitu(buf, p, 1) == 0 {
+            if self.iodp == Some(getbitu(buf, p + 103, 2)) {
+                self.decode_long_sat(buf, p + 1, false, ts_sec);
+                self.decode_long_sat(buf, p + 52, false, ts_sec);
+            }
+        } else if self.iodp == Some(getbitu(buf, p + 104, 2)) {
+            self.decode_long_sat(buf, p + 1, true, ts_sec);
+        }
+    }
+
+    fn decode_long_sat(&mut self, buf: &[u8], p: usize, rates: bool, ts_sec: f64) {
+        // the slot in the mask, from 1
+        let n = getbitu(buf, p, 6) as usize;
+        let Some(sv) = n.checked_sub(1).and_then(|slot| self.get_slot_sv(slot)) else {
+            return;
+        };
+        let mut corr = SbasLongCorr {
+            iode: getbitu(buf, p + 6, 8),
+            ts_sec,
+            ..Default::default()
+        };
+        if rates {
+            corr.dpos = [0, 1, 2].map(|i| getbits(buf, p + 14 + 11 * i, 11) as f64 * 0.125);
+            corr.dvel = [0, 1, 2].map(|i| getbits(buf, p + 58 + 8 * i, 8) as f64 * P2_11);
+            corr.daf0 = getbits(buf, p + 47, 11) as f64 * P2_31;
+            corr.daf1 = getbits(buf, p + 82, 8) as f64 * P2_39;
+            corr.t0 = getbitu(buf, p + 90, 13) * 16;
+        } else {
+            corr.dpos = [0, 1, 2].map(|i| getbits(buf, p + 14 + 9 * i, 9) as f64 * 0.125);
+            corr.daf0 = getbits(buf, p + 41, 10) as f64 * P2_31;
+        }
+        self.long.insert(sv, corr);
+    }
+
+    // The corrections of a satellite for a fix at receiver time ts_sec:
+    // false when the SBAS says not to use it.
+    pub fn apply(&self, eph: &mut Ephemeris, ts_sec: f64) -> bool {
+        if let Some(corr) = self.fast.get(&eph.sv) {
+            if corr.udrei == UDREI_DONT_USE {
+                return false;
+            }
+            if corr.udrei < UDREI_NOT_MONITORED && ts_sec - corr.ts_sec <= FAST_CORR_TIMEOUT_SEC {
+                eph.prc = corr.prc;
+            }
+        }
+        if let Some(corr) = self.long.get(&eph.sv)
+            && corr.iode == eph.iode
+            && ts_sec - corr.ts_sec <= LONG_CORR_TIMEOUT_SEC
+        {
+            // the time of day, that of the satellite
+            let tod = (eph.tow as f64 + ts_sec - eph.ts_sec).rem_euclid(SECS_PER_DAY);
+            let dt = wrap_day(tod - corr.t0 as f64);
+            eph.dpos = [0, 1, 2].map(|i| corr.dpos[i] + corr.dvel[i] * dt);
+            eph.f0 += corr.daf0 + corr.daf1 * dt;
+        }
+        true
+    }
+
+    // The GEO ephemeris of the channel, in gps time: messages start on gps
+    // seconds, the one at eph.ts_sec told by the time of another satellite.
+    // None before type 9, once it's old, or when the GEO isn't for ranging.
+    pub fn get_geo_ephemeris(
+        &self,
+        eph: &Ephemeris,
+        other: &Ephemeris,
+        ts_sec: f64,
+    ) -> Option<Ephemeris> {
+        let t0 = self.geo_t0?;
+        if eph.sva == URA_DONT_USE
+            || ts_sec - self.geo_ts_sec > GEO_EPH_TIMEOUT_SEC
+            || other.week == 0
+        {
+            return None;
+        }
+        let other_sec = other.week as f64 * SECS_PER_WEEK + other.tow as f64;
+        let gps_sec = (other_sec + eph.ts_sec - other.ts_sec - GEO_EXTRA_DELAY_SEC).round();
+        let week = (gps_sec / SECS_PER_WEEK).floor();
+        let tow = gps_sec - week * SECS_PER_WEEK;
+        // t0 on the day of the message, within 12 hours of it
+        let toe = tow + wrap_day(t0 as f64 - tow.rem_euclid(SECS_PER_DAY));
+
+        let mut geo = *eph;
+        geo.week = week as u32;
+        geo.tow = tow as u32;
+        geo.toe = toe.rem_euclid(SECS_PER_WEEK) as u32;
+        geo.toc = geo.toe;
+        geo.set_gpst();
+        Some(geo)
+    }
+}
+
+impl Channel {
+    // The 2 msec symbols are decoded a window at a time, often enough for
+    // each message to be in one away from its ends: a message seen twice is
+    // told by the receiver time of its start. The receiver time of the GEO
+    // ephemeris relates to the start of the last message as that of a GPS
+    // subframe to its start.
+    pub fn nav_decode_sbas(&mut self) {
+        if !self.nav_sync_symbol(2) {
+            return;
+        }
+        self.nav.sbas.num_syms += 1;
+        if self.nav.sbas.num_syms < WINDOW_SYMS || self.nav.sbas.num_syms % DECODE_PERIOD_SYMS != 0
+        {
+            return;
+        }
+        let len = self.nav.bits.len();
+        let window = self.nav.bits[len - WINDOW_SYMS..].to_vec();
+        for (off, buf) in sbas_decode_symbols(&window) {
+            let start_sec = self.ts_sec - (WINDOW_SYMS - off) as f64 * SYMBOL_SEC;
+            if start_sec < self.nav.sbas.last_msg_sec + 0.5 {
+                continue;
+            }
+            self.nav.sbas.last_msg_sec = start_sec;
+            let bits: Vec<_> = (0..MSG_BITS).map(|i| getbitu(&buf, i, 1) as u8).collect();
+            self.log_subframe(&bits, &buf, true);
+            self.nav.count_subframes += 1;
+            self.nav.eph.ts_sec = start_sec + DECODE_DELAY_SEC;
+            let id = self
+                .nav
+                .sbas
+                .decode_message(&buf, &mut self.nav.eph, start_sec);
+            tracing::info!("{}: SBAS: type={id} -- {}", self.sv, hex_str(&buf));
+            match id {
+                1 => tracing::info!(
+                    "{}: mask: iodp={:?} {} satellites",
+                    self.sv,
+                    self.nav.sbas.iodp,
+                    self.nav.sbas.mask.len()
+                ),
+                9 => {
+                    let eph = &self.nav.eph;
+                    tracing::debug!(
+                        "{}: {} t0={:?} pos={:.1?} vel={:.3?} acc={:?} ura={}",
+                        self.sv,
+                        "ephemeris".blue(),
+                        self.nav.sbas.geo_t0,
+                        eph.pos,
+                        eph.vel,
+                        eph.acc,
+                        eph.sva
+                    );
+                }
+                _ => {}
+            }
+            self.nav_subframe_post();
+        }
+    }
+}
@@ -1,150 +1,279 @@
 use core::sync::atomic::Ordering;
-use rustfft::num_complex::Complex64;
-use std::collections::VecDeque;
-use std::io::Read;
-use std::io::Write;
-use std::net::TcpStream;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::sync::atomic::AtomicBool;
-use std::thread;
-use std::thread::JoinHandle;
+use std::time::Duration;
 use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
 
 use crate::code::Code;
-use crate::receiver::IQReader;
+use crate::device::DeviceConfig;
+use crate::runtime::get_net_runtime;
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleQueue, SampleSource};
+
+const RTL_TCP_MAGIC: &[u8; 4] = b"RTL0";
+const RTL_TCP_RECONNECT_MAX_ATTEMPTS: usize = 10;
+const RTL_TCP_RECONNECT_DELAY_SEC: u64 = 1;
+const RTL_TCP_READ_WAIT_MSEC: u64 = 100; // then check the connection
+
+const RTL_TCP_CMD_SET_FREQ: u8 = 0x01;
+const RTL_TCP_CMD_SET_SAMPLE_RATE: u8 = 0x02;
+const RTL_TCP_CMD_SET_GAIN_MODE: u8 = 0x03;
+const RTL_TCP_CMD_SET_GAIN: u8 = 0x04;
+const RTL_TCP_CMD_SET_FREQ_CORRECTION: u8 = 0x05;
+const RTL_TCP_CMD_SET_AGC_MODE: u8 = 0x08;
+const RTL_TCP_CMD_SET_BIAS_TEE: u8 = 0x0e;
 
 pub struct RtlSdrTcp {
-    iq_deque: Arc<Mutex<VecDeque<Vec<Complex64>>>>,
-    num_samples_total: Arc<Mutex<usize>>,
-    num_samples: Arc<Mutex<usize>>,
-    num_sleep: u64,
-    read_th: Option<JoinHandle<()>>,
+    queue: Arc<SampleQueue>,
+    num_waits: u64,
+    read_task: Option<JoinHandle<()>>,
     ts: Instant,
+    time_jump: Arc<AtomicBool>,
 }
 
 impl Drop for RtlSdrTcp {
     fn drop(&mut self) {
-        let tot = *self.num_samples_total.lock().unwrap();
-        log::warn!(
-            "num_samples={}/{} sleep={} -- {:.2} sec. rate={:.1}/sec",
-            self.num_samples.lock().unwrap(),
+        let tot = self.queue.get_num_samples_total();
+        tracing::info!(
+            "num_samples={} dropped={} waits={} -- {:.2} sec. rate={:.1}/sec",
             tot,
-            self.num_sleep,
+            self.queue.get_num_dropped_total(),
+            self.num_waits,
             self.ts.elapsed().as_secs_f64(),
             tot as f64 / self.ts.elapsed().as_secs_f64()
         );
+        if let Some(task) = self.read_task.take() {
+            task.abort();
+        }
     }
 }
 
-impl IQReader for RtlSdrTcp {
-    fn get_iq_data(
+impl SampleSource for RtlSdrTcp {
+    fn read_block(
         &mut self,
         _off_samples: usize,
         num_samples: usize,
-    ) -> Result<Vec<Complex64>, Box<dyn std::error::Error>> {
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        let timeout = Duration::from_millis(RTL_TCP_READ_WAIT_MSEC);
         loop {
-            if *self.num_samples.lock().unwrap() >= num_samples {
-                break;
+            if let Some(block) = self.queue.pop_wait(num_samples, timeout) {
+                return Ok(SampleBlock {
+                    time_jump: self.time_jump.swap(false, Ordering::SeqCst),
+                    ..block
+                });
             }
-            thread::sleep(std::time::Duration::from_millis(1));
-            self.num_sleep += 1;
+            if self
+                .read_task
+                .as_ref()
+                .is_none_or(|task| task.is_finished())
+            {
+                return Err("rtl_tcp: connection lost".into());
+            }
+            self.num_waits += 1;
         }
-        let mut vec = vec![];
-        let mut iq_deq = self.iq_deque.lock().unwrap();
+    }
 
-        let v_front = iq_deq.front_mut().unwrap();
-        let n = usize::min(num_samples, v_front.len());
-        for v in v_front.iter().take(n) {
-            vec.push(*v);
-        }
-        let _ = v_front.drain(0..n);
-        if v_front.is_empty() {
-            let _ = iq_deq.pop_front();
-        }
+    fn get_num_samples_queued(&self) -> Option<usize> {
+        Some(self.queue.get_num_samples())
+    }
 
-        if n < num_samples {
-            let v_front = iq_deq.front_mut().unwrap();
-            for i in n..num_samples {
-                vec.push(v_front[i - n]);
-            }
-            let _ = v_front.drain(0..num_samples - n);
-        }
+    fn is_live(&self) -> bool {
+        true
+    }
+}
 
-        *self.num_samples.lock().unwrap() -= num_samples;
+async fn rtl_sdr_send_cmd(socket: &mut TcpStream, cmd: u8, param: u32) -> std::io::Result<()> {
+    let mut buf = [0u8; 5];
+    buf[0] = cmd;
+    buf[1..].copy_from_slice(&param.to_be_bytes());
+    socket.write_all(&buf).await
+}
 
-        Ok(vec)
+fn get_tuner_name(tuner_type: u32) -> &'static str {
+    match tuner_type {
+        1 => "E4000",
+        2 => "FC0012",
+        3 => "FC0013",
+        4 => "FC2580",
+        5 => "R820T",
+        6 => "R828D",
+        _ => "unknown",
     }
 }
 
-fn rtl_sdr_send_cmd(socket: &mut TcpStream, cmd: u8, param: u32) -> std::io::Result<()> {
-    let res = socket.write(&cmd.to_be_bytes())?;
-    assert_eq!(res, 1);
-    let res = socket.write(&param.to_be_bytes())?;
-    assert_eq!(res, 4);
+// rtl_tcp starts the stream with a 12-byte dongle info header: magic, tuner
+// type and number of gain steps, big-endian.
+async fn rtl_tcp_read_header(socket: &mut TcpStream) -> std::io::Result<()> {
+    let mut hdr = [0u8; 12];
+    socket.read_exact(&mut hdr).await?;
+    if &hdr[0..4] != RTL_TCP_MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "rtl_tcp: bad dongle info header",
+        ));
+    }
+    let tuner_type = u32::from_be_bytes([hdr[4], hdr[5], hdr[6], hdr[7]]);
+    let gain_count = u32::from_be_bytes([hdr[8], hdr[9], hdr[10], hdr[11]]);
+    tracing::info!(
+        "rtl_tcp: tuner {} with {gain_count} gain steps",
+        get_tuner_name(tuner_type)
+    );
     Ok(())
 }
 
+async fn rtl_tcp_connect(
+    hostname: &str,
+    sig: &str,
+    fs: f64,
+    config: &DeviceConfig,
+) -> std::io::Result<TcpStream> {
+    let mut socket = TcpStream::connect(hostname).await?;
+
+    rtl_tcp_read_header(&mut socket).await?;
+
+    rtl_sdr_send_cmd(
+        &mut socket,
+        RTL_TCP_CMD_SET_BIAS_TEE,
+        config.bias_tee.unwrap_or(true) as u32,
+    )
+    .await?;
+    rtl_sdr_send_cmd(
+        &mut socket,
+        RTL_TCP_CMD_SET_AGC_MODE,
+        config.agc.unwrap_or(true) as u32,
+    )
+    .await?;
+    match config.gain {
+        Some(gain) => {
+            // manual gain, in tenths of dB
+            rtl_sdr_send_cmd(&mut socket, RTL_TCP_CMD_SET_GAIN_MODE, 1).await?;
+            rtl_sdr_send_cmd(&mut socket, RTL_TCP_CMD_SET_GAIN, (gain * 10.0) as u32).await?;
+        }
+        None => rtl_sdr_send_cmd(&mut socket, RTL_TCP_CMD_SET_GAIN_MODE, 0).await?,
+    }
+    rtl_sdr_send_cmd(
+        &mut socket,
+        RTL_TCP_CMD_SET_FREQ_CORRECTION,
+        config.ppm as u32,
+    )
+    .await?;
+    rtl_sdr_send_cmd(
+        &mut socket,
+        RTL_TCP_CMD_SET_FREQ,
+        Code::get_code_freq(sig) as u32,
+    )
+    .await?;
+    rtl_sdr_send_cmd(&mut socket, RTL_TCP_CMD_SET_SAMPLE_RATE, fs as u32).await?;
+
+    tracing::info!(
+        "rtl_tcp: connected to {hostname} gain={:?} agc={:?} bias_tee={:?} ppm={}",
+        config.gain,
+        config.agc,
+        config.bias_tee,
+        config.ppm
+    );
+    Ok(socket)
+}
+
+// reconnects with the same settings, giving up after a few attempts.
+async fn rtl_tcp_reconnect(
+    hostname: &str,
+    sig: &str,
+    fs: f64,
+    config: &DeviceConfig,
+    exit_req: &AtomicBool,
+) -> Option<TcpStream> {
+    for attempt in 1..=RTL_TCP_RECONNECT_MAX_ATTEMPTS {
+        if exit_req.load(Ordering::SeqCst) {
+            return None;
+        }
+        tokio::time::sleep(Duration::from_secs(RTL_TCP_RECONNECT_DELAY_SEC)).await;
+        tracing::warn!(
+            "rtl_tcp: reconnecting to {hostname} ({attempt}/{RTL_TCP_RECONNECT_MAX_ATTEMPTS})"
+        );
+        match rtl_tcp_connect(hostname, sig, fs, config).await {
+            Ok(socket) => return Some(socket),
+            Err(e) => tracing::warn!("rtl_tcp: {e}"),
+        }
+    }
+    None
+}
+
+// Streams the samples into the queue, reconnecting when the connection
+// drops. Runs as a task on the network runtime.
+#[allow(clippy::too_many_arguments)]
+async fn rtl_tcp_read(
+    mut socket: TcpStream,
+    queue: Arc<SampleQueue>,
+    time_jump: Arc<AtomicBool>,
+    exit_req: Arc<AtomicBool>,
+    hostname: String,
+    sig: String,
+    fs: f64,
+    config: DeviceConfig,
+) {
+    let mut data = [0u8; 2036 * 2];
+    loop {
+        if let Err(e) = socket.read_exact(&mut data).await {
+            tracing::warn!("Failed to read from rtl-sdr: {e}");
+            match rtl_tcp_reconnect(&hostname, &sig, fs, &config, &exit_req).await {
+                Some(new_socket) => {
+                    // the samples streamed meanwhile are lost
+                    socket = new_socket;
+                    time_jump.store(true, Ordering::SeqCst);
+                    continue;
+                }
+                None => {
+                    exit_req.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+        }
+
+        let v: Vec<_> = data
+            .chunks_exact(2)
+            .map(|b| IQSample {
+                re: (b[0] as Float - 127.3) / 128.0,
+                im: (b[1] as Float - 127.3) / 128.0,
+            })
+            .collect();
+        queue.push(v);
+    }
+}
+
 impl RtlSdrTcp {
     pub fn new(
         hostname: &str,
         exit_req: Arc<AtomicBool>,
         sig: &str,
         fs: f64,
+        config: &DeviceConfig,
     ) -> std::io::Result<RtlSdrTcp> {
-        let mut socket = TcpStream::connect(hostname)?;
-
-        let mut m = RtlSdrTcp {
-            iq_deque: Arc::new(Mutex::new(VecDeque::new())),
-            num_samples_total: Arc::new(Mutex::new(0)),
-            num_samples: Arc::new(Mutex::new(0)),
-            num_sleep: 0,
-            read_th: None,
-            ts: Instant::now(),
-        };
-
-        let iq_deq = m.iq_deque.clone();
-        let num_samples_total = m.num_samples_total.clone();
-        let num_samples = m.num_samples.clone();
-
-        // set bias-t
-        rtl_sdr_send_cmd(&mut socket, 0xe, 1)?;
-        // set automatic gain control
-        rtl_sdr_send_cmd(&mut socket, 0x8, 1)?;
-        // set center frequency
-        rtl_sdr_send_cmd(&mut socket, 0x1, Code::get_code_freq(sig) as u32)?;
-        // set sample rate
-        rtl_sdr_send_cmd(&mut socket, 0x2, fs as u32)?;
-
-        // set tuner gain
-        //rtl_sdr_send_cmd(&mut socket, 0x4, 480)?;
-
-        let th = thread::spawn(move || {
-            loop {
-                let mut data = [0u8; 2036 * 2];
-                let mut v = vec![Complex64::default(); data.len()];
-                let res = socket.read_exact(&mut data);
-                if res.is_err() {
-                    log::warn!("Failed to read from rtl-sdr");
-                    exit_req.store(true, Ordering::SeqCst);
-                    break;
-                }
+        let runtime = get_net_runtime();
+        let socket = runtime.block_on(rtl_tcp_connect(hostname, sig, fs, config))?;
 
-                for i in 0..data.len() / 2 {
-                    let re = (data[2 * i] as f64 - 127.3) / 128.0;
-                    let im = (data[2 * i + 1] as f64 - 127.3) / 128.0;
+        let queue = Arc::new(config.new_sample_queue(fs));
+        let time_jump = Arc::new(AtomicBool::new(false));
+        let read_task = runtime.spawn(rtl_tcp_read(
+            socket,
+            queue.clone(),
+            time_jump.clone(),
+            exit_req,
+            hostname.to_string(),
+            sig.to_string(),
+            fs,
+            config.clone(),
+        ));
 
-                    v[i] = Complex64 { re, im };
-                }
-
-                let n = v.len();
-                iq_deq.lock().unwrap().push_back(v);
-                *num_samples.lock().unwrap() += n;
-                *num_samples_total.lock().unwrap() += n;
-            }
-        });
-        m.read_th = Some(th);
-
-        Ok(m)
+        Ok(RtlSdrTcp {
+            queue,
+            num_waits: 0,
+            read_task: Some(read_task),
+            ts: Instant::now(),
+            time_jump,
+        })
     }
 }
@@ -0,0 +1,47 @@
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleSource};
+
+const PI: f64 = std::f64::consts::PI;
+
+// Synthetic source producing complex white gaussian noise of unit power, to
+// exercise the whole pipeline without any capture file or device.
+pub struct NoiseSource {
+    state: u64,
+}
+
+impl NoiseSource {
+    pub fn new(fs: f64, seed: u64) -> Self {
+        tracing::info!("Using noise source: {:.1} KHz seed={seed}", fs / 1000.0);
+        Self { state: seed | 1 }
+    }
+
+    // xorshift64*, uniform in (0, 1]
+    fn next_uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let v = self.state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        ((v >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // box-muller
+    fn next_gaussian_pair(&mut self) -> IQSample {
+        let r = (-self.next_uniform().ln()).sqrt();
+        let theta = 2.0 * PI * self.next_uniform();
+        IQSample::from_polar(r as Float, theta as Float)
+    }
+}
+
+impl SampleSource for NoiseSource {
+    fn read_block(
+        &mut self,
+        _off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        Ok(SampleBlock::new(
+            (0..num_samples)
+                .map(|_| self.next_gaussian_pair())
+                .collect(),
+        ))
+    }
+}
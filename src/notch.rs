@@ -0,0 +1,206 @@
+use rustfft::num_complex::Complex64;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+use crate::sample::{Float, IQSample, to_complex64};
+
+const PI: f64 = std::f64::consts::PI;
+
+const NOTCH_FFT_LEN: usize = 1024; // 2 KHz bins at the default sampling rate
+const NOTCH_NUM_SEGMENTS: usize = 8; // averaged per detection
+const NOTCH_DETECT_PERIOD_SEC: f64 = 0.1;
+const NOTCH_THRESHOLD_DB: f64 = 15.0; // peak over the median of the bins
+const NOTCH_RELEASE_DB: f64 = 10.0; // the carrier is gone below this
+const NOTCH_MATCH_BINS: f64 = 2.0; // a peak this close is the notch's carrier
+const NOTCH_SPACING_BINS: f64 = 4.0; // closer ones are taken for its sidelobes
+const NOTCH_MAX: usize = 3;
+const NOTCH_POLE: f64 = 0.9; // the closer to 1, the narrower the notch
+const NOTCH_MU: f64 = 0.002; // adaptation step, normalized by the power
+
+// Complex adaptive notch filter (Borio et al.): a zero on the unit circle at
+// the carrier frequency and a pole just inside it, the zero following the
+// carrier by minimizing the output power.
+struct AdaptiveNotch {
+    z0: Complex64, // the zero: e^(j 2 pi f / fs)
+    x1: Complex64, // previous output of the pole section
+    power: f64,    // of the pole section output, for the normalized step
+}
+
+impl AdaptiveNotch {
+    fn new(freq_hz: f64, fs: f64) -> Self {
+        Self {
+            z0: Complex64::from_polar(1.0, 2.0 * PI * freq_hz / fs),
+            x1: Complex64::default(),
+            power: 0.0,
+        }
+    }
+
+    fn get_freq_hz(&self, fs: f64) -> f64 {
+        self.z0.arg() * fs / (2.0 * PI)
+    }
+
+    fn filter(&mut self, x: Complex64) -> Complex64 {
+        let x1 = x + NOTCH_POLE * self.z0 * self.x1;
+        let y = x1 - self.z0 * self.x1;
+        self.power += 0.01 * (self.x1.norm_sqr() - self.power);
+        self.z0 += NOTCH_MU * y * self.x1.conj() / (self.power + 1e-20);
+        self.z0 /= self.z0.norm().max(1e-20);
+        self.x1 = x1;
+        y
+    }
+}
+
+// Narrowband (CW) interference, e.g. spurs of cheap front-ends: carriers
+// standing out of the spectrum of the incoming samples are notched out, up to
+// NOTCH_MAX of them, before the channels correlate. The spectrum is checked
+// every NOTCH_DETECT_PERIOD_SEC: the notches follow their carrier in between,
+// and are released once it's gone.
+pub struct NotchFilter {
+    fs: f64,
+    fft: Arc<dyn Fft<Float>>,
+    window: Vec<Float>, // hann
+    notches: Vec<AdaptiveNotch>,
+    detect_buf: Vec<IQSample>,
+    num_until_detect: usize,
+}
+
+impl NotchFilter {
+    pub fn new(fs: f64) -> Self {
+        tracing::info!("notch filter: up to {NOTCH_MAX} carriers");
+        let n = NOTCH_FFT_LEN as f64;
+        let window = (0..NOTCH_FFT_LEN)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / n).cos()) as Float)
+            .collect();
+        Self {
+            fs,
+            fft: FftPlanner::new().plan_fft_forward(NOTCH_FFT_LEN),
+            window,
+            notches: vec![],
+            detect_buf: Vec::with_capacity(NOTCH_FFT_LEN * NOTCH_NUM_SEGMENTS),
+            num_until_detect: 0,
+        }
+    }
+
+    // frequencies (Hz) of the carriers notched out
+    pub fn get_notches_hz(&self) -> Vec<f64> {
+        (self.notches.iter())
+            .map(|notch| notch.get_freq_hz(self.fs))
+            .collect()
+    }
+
+    pub fn process(&mut self, iq_vec: &mut [IQSample]) {
+        self.collect(iq_vec);
+        if self.notches.is_empty() {
+            return;
+        }
+        for s in iq_vec.iter_mut() {
+            let y = (self.notches.iter_mut()).fold(to_complex64(*s), |x, notch| notch.filter(x));
+            *s = IQSample::new(y.re as Float, y.im as Float);
+        }
+    }
+
+    // the input samples of the next detection, every NOTCH_DETECT_PERIOD_SEC
+    fn collect(&mut self, iq_vec: &[IQSample]) {
+        let mut iq_vec = iq_vec;
+        while !iq_vec.is_empty() {
+            if self.num_until_detect > 0 {
+                let n = usize::min(self.num_until_detect, iq_vec.len());
+                self.num_until_detect -= n;
+                iq_vec = &iq_vec[n..];
+                continue;
+            }
+            let n = usize::min(
+                self.detect_buf.capacity() - self.detect_buf.len(),
+                iq_vec.len(),
+            );
+            self.detect_buf.extend_from_slice(&iq_vec[..n]);
+            iq_vec = &iq_vec[n..];
+            if self.detect_buf.len() == self.detect_buf.capacity() {
+                self.detect();
+                self.detect_buf.clear();
+                self.num_until_detect = (NOTCH_DETECT_PERIOD_SEC * self.fs) as usize;
+            }
+        }
+    }
+
+    // power per bin, the bins in fft order
+    fn get_spectrum(&self) -> Vec<f64> {
+        let mut power = vec![0.0; NOTCH_FFT_LEN];
+        let mut scratch = vec![IQSample::default(); NOTCH_FFT_LEN];
+        for segment in self.detect_buf.chunks_exact(NOTCH_FFT_LEN) {
+            for ((s, &x), &w) in scratch.iter_mut().zip(segment).zip(&self.window) {
+                *s = x * w;
+            }
+            self.fft.process(&mut scratch);
+            for (p, &s) in power.iter_mut().zip(&scratch) {
+                *p += to_complex64(s).norm_sqr();
+            }
+        }
+        power
+    }
+
+    // of a bin, the ones past the middle being the negative frequencies
+    fn get_freq_hz(&self, k: usize) -> f64 {
+        let n = NOTCH_FFT_LEN;
+        (k as f64 - if k > n / 2 { n as f64 } else { 0.0 }) * self.fs / n as f64
+    }
+
+    fn get_bin(&self, freq_hz: f64) -> f64 {
+        (freq_hz / self.fs * NOTCH_FFT_LEN as f64).rem_euclid(NOTCH_FFT_LEN as f64)
+    }
+
+    // in bins, around the spectrum
+    fn get_distance(&self, k: usize, notch: &AdaptiveNotch) -> f64 {
+        let d =
+            (k as f64 - self.get_bin(notch.get_freq_hz(self.fs))).rem_euclid(NOTCH_FFT_LEN as f64);
+        f64::min(d, NOTCH_FFT_LEN as f64 - d)
+    }
+
+    // Peaks over the median of the spectrum: the notches whose carrier fell
+    // below NOTCH_RELEASE_DB are released, the strongest new carriers over
+    // NOTCH_THRESHOLD_DB get one.
+    fn detect(&mut self) {
+        let power = self.get_spectrum();
+        let mut sorted = power.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[NOTCH_FFT_LEN / 2].max(1e-20);
+        let get_db = |k: usize| 10.0 * (power[k] / median).log10();
+        let n = NOTCH_FFT_LEN;
+        let mut peaks: Vec<_> = (0..n)
+            .filter(|&k| power[k] >= power[(k + n - 1) % n] && power[k] >= power[(k + 1) % n])
+            .filter(|&k| get_db(k) >= NOTCH_RELEASE_DB)
+            .collect();
+        peaks.sort_by(|&a, &b| power[b].total_cmp(&power[a]));
+
+        let fs = self.fs;
+        let notches = std::mem::take(&mut self.notches);
+        for notch in notches {
+            let freq_hz = notch.get_freq_hz(fs);
+            if (peaks.iter()).any(|&k| self.get_distance(k, &notch) <= NOTCH_MATCH_BINS) {
+                self.notches.push(notch);
+            } else {
+                tracing::info!("notch filter: carrier at {:+.1} KHz gone", freq_hz / 1000.0);
+            }
+        }
+
+        for k in peaks
+            .into_iter()
+            .filter(|&k| get_db(k) >= NOTCH_THRESHOLD_DB)
+        {
+            if self.notches.len() >= NOTCH_MAX {
+                break;
+            }
+            let spacing = |notch| self.get_distance(k, notch);
+            if (self.notches.iter()).any(|notch| spacing(notch) <= NOTCH_SPACING_BINS) {
+                continue;
+            }
+            let freq_hz = self.get_freq_hz(k);
+            tracing::warn!(
+                "notch filter: carrier at {:+.1} KHz, {:.1} dB over the noise",
+                freq_hz / 1000.0,
+                get_db(k)
+            );
+            self.notches.push(AdaptiveNotch::new(freq_hz, fs));
+        }
+    }
+}
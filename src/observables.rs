@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::code::Code;
+use crate::ephemeris::Ephemeris;
+use crate::quality::SignalQuality;
+use crate::util::getbitu;
+
+// What the solver gets of a satellite at a fix attempt: its code phase and
+// the raw subframes its ephemeris is decoded from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ObsSat {
+    pub prn: u8,
+    pub tow: u32,
+    pub ts_sec: f64, // receiver time of the subframe that gave tow
+    pub code_off_sec: f64,
+    pub cn0: f64,
+    pub subframes: Vec<Vec<u8>>, // raw subframes 1-3
+    #[serde(default)]
+    pub quality: Option<SignalQuality>, // multipath indicators
+}
+
+impl ObsSat {
+    pub fn new(eph: &Ephemeris, subframes: &[Vec<u8>]) -> Self {
+        Self {
+            prn: eph.sv.prn,
+            tow: eph.tow,
+            ts_sec: eph.ts_sec,
+            code_off_sec: eph.code_off_sec,
+            cn0: eph.cn0,
+            subframes: subframes.to_vec(),
+            quality: None,
+        }
+    }
+
+    pub fn get_ephemeris(&self) -> Ephemeris {
+        let sv = Code::get_sv("L1CA", self.prn).unwrap();
+        let mut eph = Ephemeris::new(sv);
+        for buf in self.subframes.iter().filter(|buf| buf.len() == 300) {
+            match getbitu(buf, 49, 3) {
+                1 => eph.nav_decode_lnav_subframe1(buf, sv),
+                2 => eph.nav_decode_lnav_subframe2(buf, sv),
+                3 => eph.nav_decode_lnav_subframe3(buf, sv),
+                id => tracing::warn!("{sv}: unexpected subframe id={id}"),
+            }
+        }
+        // the latest live subframe, not the last one decoded above
+        eph.tow = self.tow;
+        eph.ts_sec = self.ts_sec;
+        eph.code_off_sec = self.code_off_sec;
+        eph.cn0 = self.cn0;
+        eph.set_gpst();
+        eph
+    }
+}
+
+// A fix attempt, at receiver time ts_sec.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ObsEpoch {
+    pub ts_sec: f64,
+    pub sats: Vec<ObsSat>,
+}
+
+// The fix attempts of a run as json lines, for the pvt subcommand to solve
+// again, e.g. with another solver configuration.
+pub struct ObsWriter {
+    writer: BufWriter<File>,
+}
+
+impl ObsWriter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, epoch: &ObsEpoch) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.writer, epoch)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+pub fn read_obs_file(path: &Path) -> std::io::Result<Vec<ObsEpoch>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut epochs = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        epochs.push(serde_json::from_str(&line).map_err(std::io::Error::other)?);
+    }
+    Ok(epochs)
+}
@@ -0,0 +1,531 @@
+use chrono::{DateTime, Utc};
+#[cfg(feature = "net")]
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+#[cfg(feature = "net")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "net")]
+use tokio::io::AsyncWriteExt;
+#[cfg(feature = "net")]
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(feature = "net")]
+use tokio::sync::broadcast;
+#[cfg(feature = "net")]
+use tokio::sync::broadcast::error::RecvError;
+#[cfg(feature = "net")]
+use tokio::task::JoinHandle;
+#[cfg(feature = "net")]
+use tokio_tungstenite::tungstenite::Message;
+
+#[cfg(feature = "net")]
+use crate::runtime::get_net_runtime;
+
+#[cfg(feature = "net")]
+const OUTPUT_QUEUE_LEN: usize = 16; // fixes queued for a slow client before it skips some
+#[cfg(feature = "net")]
+const OUTPUT_DRAIN_SEC: f64 = 1.0; // given to the clients at exit to get the queued fixes
+#[cfg(feature = "net")]
+const OUTPUT_ACCEPT_BACKOFF_MSEC: u64 = 10; // after a failed accept, doubled up to the max
+#[cfg(feature = "net")]
+const OUTPUT_ACCEPT_BACKOFF_MAX_MSEC: u64 = 1000;
+#[cfg(feature = "net")]
+const GPSD_VERSION: &str =
+    "{\"class\":\"VERSION\",\"release\":\"gnss-rcv\",\"proto_major\":3,\"proto_minor\":14}\r\n";
+
+#[cfg(feature = "net")]
+type ClientResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+// a position fix, as sent to the outputs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Solution {
+    pub unix_sec: f64, // UTC
+    pub lat: f64,
+    pub lon: f64,
+    pub height: f64,
+    pub num_svs: usize,
+}
+
+impl Solution {
+    fn get_utc(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis((self.unix_sec * 1000.0) as i64).unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub bind: String, // address of the tcp servers, 0.0.0.0 for all the interfaces
+    pub nmea: bool,
+    pub nmea_port: u16,
+    pub gpsd: bool,
+    pub gpsd_port: u16,
+    pub ws: bool,
+    pub ws_port: u16,
+    pub csv: bool,
+    pub csv_file: String,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            nmea: false,
+            nmea_port: 10110,
+            gpsd: false,
+            gpsd_port: 2947,
+            ws: false,
+            ws_port: 9001,
+            csv: false,
+            csv_file: "fixes.csv".to_string(),
+        }
+    }
+}
+
+impl OutputConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.nmea || self.gpsd || self.ws || self.csv
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Protocol {
+    Nmea,
+    Gpsd,
+    WebSocket,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Protocol::Nmea => write!(f, "nmea"),
+            Protocol::Gpsd => write!(f, "gpsd"),
+            Protocol::WebSocket => write!(f, "websocket"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct OutputStatus {
+    pub name: String,
+    pub num_clients: Option<usize>, // None for a file
+    pub error: Option<String>,
+}
+
+// Sends each line to all the clients connected to port, from the network
+// runtime: a slow or gone client never holds the receiver back.
+#[cfg(feature = "net")]
+struct TcpServer {
+    protocol: Protocol,
+    bind: String,
+    port: u16,
+    sender: broadcast::Sender<String>,
+    task: JoinHandle<()>,
+}
+
+#[cfg(feature = "net")]
+impl Drop for TcpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(feature = "net")]
+impl TcpServer {
+    fn start(protocol: Protocol, bind: &str, port: u16) -> std::io::Result<Self> {
+        let runtime = get_net_runtime();
+        let listener = runtime.block_on(TcpListener::bind((bind, port)))?;
+        let (sender, _) = broadcast::channel(OUTPUT_QUEUE_LEN);
+        let sender_clone = sender.clone();
+        tracing::info!("{protocol}: listening on {}", listener.local_addr()?);
+
+        let task = runtime.spawn(async move {
+            // e.g. out of file descriptors: retried, without spinning
+            let mut backoff_msec = 0;
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(client) => {
+                        backoff_msec = 0;
+                        client
+                    }
+                    Err(e) => {
+                        if backoff_msec == 0 {
+                            tracing::warn!("{protocol}: accept failed: {e}");
+                        }
+                        backoff_msec = (backoff_msec * 2)
+                            .clamp(OUTPUT_ACCEPT_BACKOFF_MSEC, OUTPUT_ACCEPT_BACKOFF_MAX_MSEC);
+                        tokio::time::sleep(Duration::from_millis(backoff_msec)).await;
+                        continue;
+                    }
+                };
+                tracing::info!("{protocol}: client {addr} connected");
+                let receiver = sender_clone.subscribe();
+                tokio::spawn(async move {
+                    let res = match protocol {
+                        Protocol::WebSocket => serve_websocket(stream, receiver).await,
+                        _ => serve_lines(protocol, stream, receiver).await,
+                    };
+                    match res {
+                        Ok(()) => tracing::info!("{protocol}: client {addr} done"),
+                        Err(e) => tracing::warn!("{protocol}: client {addr} disconnected: {e}"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            protocol,
+            bind: bind.to_string(),
+            port,
+            sender,
+            task,
+        })
+    }
+
+    fn send(&self, line: String) {
+        // no client connected is not an error
+        let _ = self.sender.send(line);
+    }
+
+    fn is_bound_to(&self, bind: &str, port: u16) -> bool {
+        self.bind == bind && self.port == port
+    }
+
+    // until every client got the queued lines, or gave up on them
+    fn drain(&self) {
+        let start = Instant::now();
+        while !self.sender.is_empty() && start.elapsed().as_secs_f64() < OUTPUT_DRAIN_SEC {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn get_num_clients(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    fn get_status(&self) -> OutputStatus {
+        OutputStatus {
+            name: format!("{} tcp:{}", self.protocol, self.port),
+            num_clients: Some(self.get_num_clients()),
+            error: None,
+        }
+    }
+}
+
+// Without the net feature, e.g. in wasm, the tcp outputs fail to start.
+#[cfg(not(feature = "net"))]
+enum TcpServer {}
+
+#[cfg(not(feature = "net"))]
+impl TcpServer {
+    fn start(_protocol: Protocol, _bind: &str, _port: u16) -> std::io::Result<Self> {
+        Err(std::io::Error::other("built without the net feature"))
+    }
+
+    fn send(&self, _line: String) {
+        match *self {}
+    }
+
+    fn is_bound_to(&self, _bind: &str, _port: u16) -> bool {
+        match *self {}
+    }
+
+    fn drain(&self) {
+        match *self {}
+    }
+
+    fn get_num_clients(&self) -> usize {
+        match *self {}
+    }
+
+    fn get_status(&self) -> OutputStatus {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "net")]
+async fn serve_lines(
+    protocol: Protocol,
+    mut stream: TcpStream,
+    mut receiver: broadcast::Receiver<String>,
+) -> ClientResult {
+    // what gpsd clients expect first; their ?WATCH requests are not needed
+    if protocol == Protocol::Gpsd {
+        stream.write_all(GPSD_VERSION.as_bytes()).await?;
+    }
+    loop {
+        match receiver.recv().await {
+            Ok(line) => stream.write_all(line.as_bytes()).await?,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+async fn serve_websocket(
+    stream: TcpStream,
+    mut receiver: broadcast::Receiver<String>,
+) -> ClientResult {
+    let mut ws = tokio_tungstenite::accept_async(stream).await?;
+    loop {
+        match receiver.recv().await {
+            Ok(line) => ws.send(Message::text(line)).await?,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+fn get_nmea_checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |acc, b| acc ^ b)
+}
+
+fn get_nmea_angle(deg: f64, is_lat: bool) -> String {
+    let hemisphere = match (is_lat, deg >= 0.0) {
+        (true, true) => "N",
+        (true, false) => "S",
+        (false, true) => "E",
+        (false, false) => "W",
+    };
+    // in 1e-4 minutes, rounded before the split: 59.99996' carries into the
+    // degrees instead of printing as 60.0000'
+    let units = (deg.abs() * 60.0 * 1e4).round() as u64;
+    let (deg, units) = (units / 600_000, units % 600_000);
+    let (minutes, frac) = (units / 10_000, units % 10_000);
+    if is_lat {
+        format!("{deg:02}{minutes:02}.{frac:04},{hemisphere}")
+    } else {
+        format!("{deg:03}{minutes:02}.{frac:04},{hemisphere}")
+    }
+}
+
+// GGA and RMC sentences
+fn get_nmea_sentences(sol: &Solution) -> String {
+    let utc = sol.get_utc();
+    let time = utc.format("%H%M%S%.3f");
+    let lat = get_nmea_angle(sol.lat, true);
+    let lon = get_nmea_angle(sol.lon, false);
+    let gga = format!(
+        "GPGGA,{time},{lat},{lon},1,{:02},,{:.1},M,,M,,",
+        sol.num_svs, sol.height
+    );
+    let rmc = format!("GPRMC,{time},A,{lat},{lon},,,{},,,A", utc.format("%d%m%y"));
+    [gga, rmc]
+        .iter()
+        .map(|body| format!("${body}*{:02X}\r\n", get_nmea_checksum(body)))
+        .collect()
+}
+
+fn get_gpsd_tpv(sol: &Solution) -> String {
+    let tpv = serde_json::json!({
+        "class": "TPV",
+        "device": "gnss-rcv",
+        "mode": 3,
+        "time": sol.get_utc().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "lat": sol.lat,
+        "lon": sol.lon,
+        "altHAE": sol.height,
+    });
+    format!("{tpv}\r\n")
+}
+
+fn get_json(sol: &Solution) -> String {
+    serde_json::json!({
+        "time": sol.get_utc().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "lat": sol.lat,
+        "lon": sol.lon,
+        "height": sol.height,
+        "num_svs": sol.num_svs,
+    })
+    .to_string()
+}
+
+struct CsvFile {
+    path: String,
+    writer: BufWriter<File>,
+}
+
+impl CsvFile {
+    fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_empty {
+            writeln!(writer, "time,lat,lon,height,num_svs")?;
+        }
+        Ok(Self {
+            path: path.to_string(),
+            writer,
+        })
+    }
+
+    fn write(&mut self, sol: &Solution) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{:.8},{:.8},{:.2},{}",
+            sol.get_utc()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            sol.lat,
+            sol.lon,
+            sol.height,
+            sol.num_svs
+        )?;
+        self.writer.flush()
+    }
+}
+
+// The sinks the fixes go to, started, moved or stopped as the config changes.
+#[derive(Default)]
+pub struct Outputs {
+    nmea: Option<TcpServer>,
+    gpsd: Option<TcpServer>,
+    ws: Option<TcpServer>,
+    csv: Option<CsvFile>,
+    errors: Vec<OutputStatus>, // sinks that failed to start
+    num_clients: usize,
+}
+
+fn configure_server(
+    server: &mut Option<TcpServer>,
+    protocol: Protocol,
+    enabled: bool,
+    bind: &str,
+    port: u16,
+) -> Option<OutputStatus> {
+    if !enabled {
+        *server = None;
+        return None;
+    }
+    if server.as_ref().is_some_and(|s| s.is_bound_to(bind, port)) {
+        return None;
+    }
+    *server = None; // frees the port when it's the same
+    match TcpServer::start(protocol, bind, port) {
+        Ok(s) => {
+            *server = Some(s);
+            None
+        }
+        Err(e) => {
+            tracing::error!("{protocol}: failed to listen on {bind}:{port}: {e}");
+            Some(OutputStatus {
+                name: format!("{protocol} tcp:{port}"),
+                num_clients: None,
+                error: Some(e.to_string()),
+            })
+        }
+    }
+}
+
+impl Outputs {
+    pub fn configure(&mut self, config: &OutputConfig) {
+        self.errors = [
+            configure_server(
+                &mut self.nmea,
+                Protocol::Nmea,
+                config.nmea,
+                &config.bind,
+                config.nmea_port,
+            ),
+            configure_server(
+                &mut self.gpsd,
+                Protocol::Gpsd,
+                config.gpsd,
+                &config.bind,
+                config.gpsd_port,
+            ),
+            configure_server(
+                &mut self.ws,
+                Protocol::WebSocket,
+                config.ws,
+                &config.bind,
+                config.ws_port,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if !config.csv {
+            self.csv = None;
+        } else if self
+            .csv
+            .as_ref()
+            .is_none_or(|csv| csv.path != config.csv_file)
+        {
+            self.csv = None;
+            match CsvFile::open(&config.csv_file) {
+                Ok(csv) => self.csv = Some(csv),
+                Err(e) => {
+                    tracing::error!("csv: failed to open {}: {e}", config.csv_file);
+                    self.errors.push(OutputStatus {
+                        name: format!("csv {}", config.csv_file),
+                        num_clients: None,
+                        error: Some(e.to_string()),
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn send(&mut self, sol: &Solution) {
+        if let Some(server) = &self.nmea {
+            server.send(get_nmea_sentences(sol));
+        }
+        if let Some(server) = &self.gpsd {
+            server.send(get_gpsd_tpv(sol));
+        }
+        if let Some(server) = &self.ws {
+            server.send(get_json(sol));
+        }
+        if let Some(csv) = &mut self.csv
+            && let Err(e) = csv.write(sol)
+        {
+            tracing::error!("csv: failed to write {}: {e}", csv.path);
+        }
+    }
+
+    // at exit: what is queued or buffered goes out first
+    pub fn flush(&mut self) {
+        [&self.nmea, &self.gpsd, &self.ws]
+            .into_iter()
+            .flatten()
+            .for_each(|server| server.drain());
+        if let Some(csv) = &mut self.csv
+            && let Err(e) = csv.writer.flush()
+        {
+            tracing::error!("csv: failed to write {}: {e}", csv.path);
+        }
+    }
+
+    // whether clients came or left since the last call
+    pub fn check_clients(&mut self) -> bool {
+        let num_clients = [&self.nmea, &self.gpsd, &self.ws]
+            .into_iter()
+            .flatten()
+            .map(|server| server.get_num_clients())
+            .sum();
+        let changed = num_clients != self.num_clients;
+        self.num_clients = num_clients;
+        changed
+    }
+
+    pub fn get_status(&self) -> Vec<OutputStatus> {
+        let mut status: Vec<_> = [&self.nmea, &self.gpsd, &self.ws]
+            .into_iter()
+            .flatten()
+            .map(|server| server.get_status())
+            .collect();
+        if let Some(csv) = &self.csv {
+            status.push(OutputStatus {
+                name: format!("csv {}", csv.path),
+                num_clients: None,
+                error: None,
+            });
+        }
+        status.extend(self.errors.iter().cloned());
+        status
+    }
+}
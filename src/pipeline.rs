@@ -0,0 +1,185 @@
+use gnss_rs::sv::SV;
+use rayon::ThreadPool;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::channel::{Channel, SvPriority};
+use crate::ephemeris::Ephemeris;
+use crate::sample::IQSample;
+use crate::solver::PositionSolver;
+use crate::state::GnssState;
+use crate::threads::install;
+
+const ACQ_QUEUE_BLOCKS: usize = 8; // past this, acquisition misses blocks
+
+type Block = (Vec<Arc<[IQSample]>>, f64); // one buffer per band, timestamp
+type BandChannel = (usize, Channel); // with the index of its band
+
+// The stages the sample path hands work to, each on its own thread:
+// - acquisition: the channels searching for their satellite move there and
+//   get the sample blocks, they come back once locked or given up,
+// - solve: position fixes from the ephemeris of the tracked satellites.
+// The charts of the channels have their own worker, pipelined or not.
+// The sample path never waits for them: a stage behind misses blocks or fixes
+// instead. Except with a recording, where acquisition gets all the
+// blocks: no real-time to keep up with.
+pub struct Pipeline {
+    acq_block_tx: Option<mpsc::SyncSender<Block>>,
+    acq_channel_tx: mpsc::Sender<BandChannel>,
+    acq_done_rx: mpsc::Receiver<BandChannel>,
+    acq_th: Option<JoinHandle<Vec<BandChannel>>>,
+    acquiring: BTreeMap<(usize, SV), SvPriority>, // handed over, by band
+    num_missed_blocks: usize,
+    lossless: bool,
+    fix_tx: Option<mpsc::SyncSender<(f64, Vec<Ephemeris>)>>,
+    fix_rx: mpsc::Receiver<Option<[f64; 3]>>,
+    solve_th: Option<JoinHandle<()>>,
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_acquisition(
+    block_rx: mpsc::Receiver<Block>,
+    channel_rx: mpsc::Receiver<BandChannel>,
+    done_tx: mpsc::Sender<BandChannel>,
+    pool: Option<Arc<ThreadPool>>,
+) -> Vec<BandChannel> {
+    let mut acquiring: Vec<BandChannel> = vec![];
+    for (blocks, ts_sec) in block_rx {
+        acquiring.extend(channel_rx.try_iter());
+        install(pool.as_deref(), || {
+            acquiring
+                .par_iter_mut()
+                .for_each(|(idx, channel)| channel.process_block(&blocks[*idx], ts_sec))
+        });
+
+        // locked or back to idle: tracking resumes on the sample path
+        let (done, rest) = acquiring
+            .into_iter()
+            .partition(|(_idx, channel)| !channel.is_acquiring());
+        acquiring = rest;
+        for band_channel in done {
+            let _ = done_tx.send(band_channel);
+        }
+    }
+    acquiring.extend(channel_rx.try_iter());
+    acquiring
+}
+
+impl Pipeline {
+    pub fn new(
+        pub_state: Arc<Mutex<GnssState>>,
+        pool: Option<Arc<ThreadPool>>,
+        lossless: bool,
+    ) -> Self {
+        let (acq_block_tx, block_rx) = mpsc::sync_channel(ACQ_QUEUE_BLOCKS);
+        let (acq_channel_tx, channel_rx) = mpsc::channel();
+        let (done_tx, acq_done_rx) = mpsc::channel();
+        let acq_th = thread::spawn(move || run_acquisition(block_rx, channel_rx, done_tx, pool));
+
+        let (fix_tx, req_rx) = mpsc::sync_channel::<(f64, Vec<Ephemeris>)>(1);
+        let (res_tx, fix_rx) = mpsc::channel();
+        let solve_th = thread::spawn(move || {
+            let mut solver = PositionSolver::new(pub_state);
+            for (ts_sec, ephs) in req_rx {
+                if res_tx.send(solver.compute_position(ts_sec, &ephs)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        tracing::info!("pipeline: acquisition and solve on their own threads");
+        Self {
+            acq_block_tx: Some(acq_block_tx),
+            acq_channel_tx,
+            acq_done_rx,
+            acq_th: Some(acq_th),
+            acquiring: BTreeMap::new(),
+            num_missed_blocks: 0,
+            lossless,
+            fix_tx: Some(fix_tx),
+            fix_rx,
+            solve_th: Some(solve_th),
+        }
+    }
+
+    pub fn hand_over(&mut self, band_idx: usize, channel: Channel) {
+        self.acquiring
+            .insert((band_idx, channel.sv), channel.get_priority());
+        let _ = self.acq_channel_tx.send((band_idx, channel));
+    }
+
+    // some channel of the band above low priority still acquiring
+    pub fn is_band_acquiring(&self, band_idx: usize) -> bool {
+        (self.acquiring.iter())
+            .any(|(&(idx, _sv), &priority)| idx == band_idx && priority != SvPriority::Low)
+    }
+
+    // the channels done with acquisition since the last call
+    pub fn take_acquired(&mut self) -> Vec<BandChannel> {
+        let done: Vec<_> = self.acq_done_rx.try_iter().collect();
+        for (idx, channel) in &done {
+            self.acquiring.remove(&(*idx, channel.sv));
+        }
+        done
+    }
+
+    pub fn send_block(&mut self, blocks: &[Arc<[IQSample]>], ts_sec: f64) {
+        let Some(block_tx) = &self.acq_block_tx else {
+            return;
+        };
+        if self.acquiring.is_empty() {
+            return;
+        }
+        let block = (blocks.to_vec(), ts_sec);
+        if self.lossless {
+            let _ = block_tx.send(block);
+        } else if let Err(mpsc::TrySendError::Full(_)) = block_tx.try_send(block) {
+            self.num_missed_blocks += 1;
+        }
+    }
+
+    // false when the solve stage is still busy with the previous request
+    pub fn request_fix(&mut self, ts_sec: f64, ephs: Vec<Ephemeris>) -> bool {
+        self.fix_tx
+            .as_ref()
+            .is_some_and(|fix_tx| fix_tx.try_send((ts_sec, ephs)).is_ok())
+    }
+
+    // results of the fixes requested, in order
+    pub fn take_fixes(&mut self) -> Vec<Option<[f64; 3]>> {
+        self.fix_rx.try_iter().collect()
+    }
+
+    // Waits for the acquisition and solve stages to finish what they were
+    // given, and returns the channels that were still acquiring.
+    pub fn stop(&mut self) -> Vec<BandChannel> {
+        self.acq_block_tx = None;
+        self.fix_tx = None;
+        let mut channels = match self.acq_th.take() {
+            Some(th) => th.join().unwrap_or_default(),
+            None => vec![],
+        };
+        channels.extend(self.acq_done_rx.try_iter());
+        self.acquiring.clear();
+        if let Some(th) = self.solve_th.take() {
+            let _ = th.join();
+        }
+        if self.num_missed_blocks > 0 {
+            tracing::warn!(
+                "pipeline: acquisition missed {} blocks",
+                self.num_missed_blocks
+            );
+            self.num_missed_blocks = 0;
+        }
+        channels
+    }
+}
@@ -1,34 +1,341 @@
 use glob::glob;
 use gnss_rs::sv::SV;
+use once_cell::sync::Lazy;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use rustfft::num_complex::Complex64;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::sync::mpsc;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::summary::{get_enus, get_scatter};
 
 const PLOT_FONT_SIZE: u32 = 15;
 const PLOT_SIZE_X: u32 = 200;
 const PLOT_SIZE_Y: u32 = 200;
+const PLOT_SIZE_RUN_X: u32 = 800; // the charts of the whole run
+const PLOT_SIZE_RUN_Y: u32 = 400;
+const PLOT_SVG_SCALE: u32 = 3; // svg charts get room for their labels
 const PLOT_FOLDER: &str = "plots";
+const PLOTLY_URL: &str = "https://cdn.plot.ly/plotly-2.35.2.min.js";
+const MIN_POINTS: usize = 10;
+const PLOT_QUEUE_JOBS: usize = 32; // past this, the channels skip a round
+#[cfg(target_os = "linux")]
+const PLOT_NICE: i32 = 10;
 
-pub fn plot_remove_old_graph() {
-    let pattern = format!("{}/*.png", PLOT_FOLDER);
+// where the charts go, plots/ of the working directory unless set
+static PLOT_DIR: Lazy<RwLock<PathBuf>> = Lazy::new(|| RwLock::new(PathBuf::from(PLOT_FOLDER)));
+static PLOT_FORMAT: Lazy<RwLock<PlotFormat>> = Lazy::new(|| RwLock::new(PlotFormat::Png));
 
-    for path in glob(&pattern).unwrap() {
-        match path {
-            Ok(path) => {
-                log::info!("Removing chart: {:?}", path.display());
-                std::fs::remove_file(path).unwrap();
-            }
-            Err(e) => println!("{:?}", e),
+// What the charts are written as: small images, vector images, or web pages
+// drawn by plotly in the browser, with zoom and pan.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlotFormat {
+    Png,
+    Svg,
+    Html,
+}
+
+impl FromStr for PlotFormat {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<PlotFormat, Self::Err> {
+        match input {
+            "png" => Ok(PlotFormat::Png),
+            "svg" => Ok(PlotFormat::Svg),
+            "html" => Ok(PlotFormat::Html),
+            _ => Err(format!("Failed to parse {}", input).into()),
         }
     }
 }
 
+impl fmt::Display for PlotFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlotFormat::Png => write!(f, "png"),
+            PlotFormat::Svg => write!(f, "svg"),
+            PlotFormat::Html => write!(f, "html"),
+        }
+    }
+}
+
+const PLOT_FORMATS: [PlotFormat; 3] = [PlotFormat::Png, PlotFormat::Svg, PlotFormat::Html];
+
+// What to do with the charts of a previous run found in the plot directory
+// at startup.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlotClean {
+    Remove,
+    Keep,
+    Dry, // only list what would be removed
+}
+
+impl FromStr for PlotClean {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<PlotClean, Self::Err> {
+        match input {
+            "remove" => Ok(PlotClean::Remove),
+            "keep" => Ok(PlotClean::Keep),
+            "dry" => Ok(PlotClean::Dry),
+            _ => Err(format!("Failed to parse {}", input).into()),
+        }
+    }
+}
+
+impl fmt::Display for PlotClean {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlotClean::Remove => write!(f, "remove"),
+            PlotClean::Keep => write!(f, "keep"),
+            PlotClean::Dry => write!(f, "dry"),
+        }
+    }
+}
+
+// The directory the charts are drawn to from now on, created if missing.
+pub fn set_plot_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    *PLOT_DIR.write().unwrap() = dir.to_path_buf();
+    Ok(())
+}
+
+pub fn get_plot_dir() -> PathBuf {
+    PLOT_DIR.read().unwrap().clone()
+}
+
+pub fn set_plot_format(format: PlotFormat) {
+    *PLOT_FORMAT.write().unwrap() = format;
+}
+
+pub fn get_plot_format() -> PlotFormat {
+    *PLOT_FORMAT.read().unwrap()
+}
+
+fn get_plot_path(file_name: &str) -> PathBuf {
+    get_plot_dir().join(file_name)
+}
+
+// A copy of the history of a channel, what its charts are drawn from: they
+// can then be drawn away from the sample path.
+pub struct PlotJob {
+    pub sv: SV,
+    pub corr_p: Vec<Complex64>,
+    pub code_phase_offset: Vec<f64>,
+    pub phi_error: Vec<f64>,
+    pub doppler_hz: Vec<f64>,
+}
+
+impl PlotJob {
+    pub fn draw(&self) {
+        let len = self.corr_p.len();
+        let n = usize::min(len, 2000);
+        plot_iq_scatter(self.sv, &self.corr_p[len - n..len]);
+        plot_time_graph(
+            self.sv,
+            "code-phase-offset",
+            "samples",
+            &self.code_phase_offset,
+            50.0,
+            &BLUE,
+        );
+        plot_time_graph(self.sv, "phi-error", "rad", &self.phi_error, 0.5, &BLACK);
+        plot_time_graph(self.sv, "doppler-hz", "Hz", &self.doppler_hz, 10.0, &BLACK);
+        let v_re: Vec<_> = self.corr_p.iter().map(|c| c.re).collect();
+        plot_time_graph_with_sz(
+            self.sv,
+            "nav-msg",
+            "prompt I",
+            &v_re,
+            0.001,
+            &BLACK,
+            2 * PLOT_SIZE_X,
+            PLOT_SIZE_Y,
+        );
+    }
+}
+
+// The charts of a whole run, drawn at its end: the C/N0 of all the satellites
+// over time, their acquisition attempts and locks, and the scatter of the
+// fixes around their average position.
+pub struct OverviewJob {
+    pub cn0: Vec<(SV, Vec<(f64, f64)>)>, // receiver time and C/N0
+    pub acquisitions: Vec<(SV, usize, usize)>, // attempts and locks
+    pub fixes: Vec<[f64; 3]>,
+}
+
+impl OverviewJob {
+    pub fn draw(&self) {
+        self.draw_cn0();
+        self.draw_acquisitions();
+        self.draw_fixes();
+    }
+
+    fn draw_cn0(&self) {
+        let values = || self.cn0.iter().flat_map(|(_, points)| points.iter());
+        let x_max = values().map(|p| p.0).fold(1.0, f64::max);
+        let y_min = values().map(|p| p.1).fold(f64::MAX, f64::min).min(30.0) - 5.0;
+        let y_max = values().map(|p| p.1).fold(f64::MIN, f64::max).max(45.0) + 5.0;
+        Chart {
+            name: "run-cn0".to_string(),
+            title: "C/N0 of the tracked satellites".to_string(),
+            x_desc: "time (s)",
+            y_desc: "C/N0 (dB-Hz)",
+            x_labels: vec![],
+            x_range: 0.0..x_max,
+            y_range: y_min..y_max,
+            series: (self.cn0.iter().enumerate())
+                .map(|(i, (sv, points))| {
+                    let RGBAColor(r, g, b, _) = Palette99::pick(i).to_rgba();
+                    Series::new(&sv.to_string(), points.clone(), RGBColor(r, g, b))
+                })
+                .collect(),
+            style: Style::Lines,
+            size: (PLOT_SIZE_RUN_X, PLOT_SIZE_RUN_Y),
+        }
+        .draw();
+    }
+
+    fn draw_acquisitions(&self) {
+        let num = self.acquisitions.len();
+        let attempts = (self.acquisitions.iter().enumerate())
+            .map(|(i, &(_, num_attempts, _))| (i as f64, num_attempts as f64))
+            .collect();
+        let locks = (self.acquisitions.iter().enumerate())
+            .map(|(i, &(_, _, num_locks))| (i as f64, num_locks as f64))
+            .collect();
+        let y_max = (self.acquisitions.iter())
+            .map(|&(_, num_attempts, _)| num_attempts as f64)
+            .fold(1.0, f64::max);
+        Chart {
+            name: "run-acquisitions".to_string(),
+            title: "acquisition attempts and locks".to_string(),
+            x_desc: "satellite",
+            y_desc: "count",
+            x_labels: self.acquisitions.iter().map(|a| a.0.to_string()).collect(),
+            x_range: -0.5..num as f64 - 0.5,
+            y_range: 0.0..y_max * 1.1,
+            series: vec![
+                Series::new("attempts", attempts, BLUE),
+                Series::new("locks", locks, GREEN),
+            ],
+            style: Style::Bars,
+            size: (PLOT_SIZE_RUN_X, PLOT_SIZE_RUN_Y),
+        }
+        .draw();
+    }
+
+    fn draw_fixes(&self) {
+        let points: Vec<_> = get_enus(&self.fixes)
+            .iter()
+            .map(|&[e, n, _]| (e, n))
+            .collect();
+        let title = match get_scatter(&self.fixes) {
+            Some((mean_m, h_rms_m, v_rms_m)) => format!(
+                "{} fixes: mean {mean_m:.1} m, rms h {h_rms_m:.1} m v {v_rms_m:.1} m",
+                self.fixes.len()
+            ),
+            None => "no fix".to_string(),
+        };
+        let r = (points.iter())
+            .map(|&(e, n): &(f64, f64)| e.abs().max(n.abs()))
+            .fold(1.0, f64::max)
+            * 1.1;
+        Chart {
+            name: "run-fixes".to_string(),
+            title,
+            x_desc: "east (m)",
+            y_desc: "north (m)",
+            x_labels: vec![],
+            x_range: -r..r,
+            y_range: -r..r,
+            series: vec![Series::new("fixes", points, BLUE)],
+            style: Style::Dots,
+            size: (PLOT_SIZE_RUN_Y, PLOT_SIZE_RUN_Y),
+        }
+        .draw();
+    }
+}
+
+// The thread the channels hand their charts to: drawing them takes tens of
+// msec, not to be spent on the sample path. It runs at a lower priority than
+// the processing threads where the system allows it.
+pub struct PlotWorker {
+    plot_tx: Option<mpsc::SyncSender<PlotJob>>,
+    plot_th: Option<JoinHandle<()>>,
+}
+
+impl Drop for PlotWorker {
+    fn drop(&mut self) {
+        // the channels must have dropped their senders by now
+        self.plot_tx = None;
+        if let Some(th) = self.plot_th.take() {
+            let _ = th.join();
+        }
+    }
+}
+
+impl PlotWorker {
+    pub fn start() -> std::io::Result<Self> {
+        let (plot_tx, plot_rx) = mpsc::sync_channel::<PlotJob>(PLOT_QUEUE_JOBS);
+        let plot_th = thread::Builder::new()
+            .name("gnss-rcv-plots".to_string())
+            .spawn(move || {
+                lower_thread_priority();
+                plot_rx.into_iter().for_each(|job| job.draw())
+            })?;
+        Ok(Self {
+            plot_tx: Some(plot_tx),
+            plot_th: Some(plot_th),
+        })
+    }
+
+    pub fn get_sender(&self) -> Option<mpsc::SyncSender<PlotJob>> {
+        self.plot_tx.clone()
+    }
+}
+
+// on linux, the nice value of the calling thread only
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    // SAFETY: no memory is involved, 0 is the calling thread
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, PLOT_NICE) } != 0 {
+        tracing::debug!("plots: failed to lower the thread priority");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_thread_priority() {}
+
+pub fn plot_remove_old_graph(policy: PlotClean) {
+    for format in PLOT_FORMATS {
+        remove_charts(&format!("*.{format}"), policy);
+    }
+}
+
 pub fn plot_remove(sv: SV) {
-    let pattern = format!("{}/sat-{}-*.png", PLOT_FOLDER, sv.prn);
+    for format in PLOT_FORMATS {
+        remove_charts(&format!("sat-{}-*.{format}", sv.prn), PlotClean::Remove);
+    }
+}
 
-    for path in glob(&pattern).unwrap() {
+fn remove_charts(file_pattern: &str, policy: PlotClean) {
+    if policy == PlotClean::Keep {
+        return;
+    }
+    let pattern = get_plot_path(file_pattern);
+
+    for path in glob(&pattern.to_string_lossy()).unwrap() {
         match path {
+            Ok(path) if policy == PlotClean::Dry => {
+                tracing::info!("Would remove chart: {:?}", path.display());
+            }
             Ok(path) => {
-                log::info!("Removing chart: {:?}", path.display());
+                tracing::debug!("Removing chart: {:?}", path.display());
                 std::fs::remove_file(path).unwrap();
             }
             Err(e) => println!("{:?}", e),
@@ -36,10 +343,188 @@ pub fn plot_remove(sv: SV) {
     }
 }
 
-pub fn plot_time_graph(sv: SV, name: &str, time_series: &[f64], y_delta: f64, color: &RGBColor) {
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    Dots,
+    Lines,
+    Bars, // grouped per x, at integer x
+}
+
+struct Series {
+    label: String, // in the legend when more than one
+    points: Vec<(f64, f64)>,
+    color: RGBColor,
+}
+
+impl Series {
+    fn new(label: &str, points: Vec<(f64, f64)>, color: RGBColor) -> Self {
+        Self {
+            label: label.to_string(),
+            points,
+            color,
+        }
+    }
+}
+
+// A chart as any of the formats draws it: nothing to draw gives an empty one.
+struct Chart<'a> {
+    name: String, // of the file, without its extension
+    title: String,
+    x_desc: &'a str,
+    y_desc: &'a str,
+    x_labels: Vec<String>, // of the integer x, instead of the values
+    x_range: Range<f64>,
+    y_range: Range<f64>,
+    series: Vec<Series>,
+    style: Style,
+    size: (u32, u32), // of the png, the svg is larger
+}
+
+impl Chart<'_> {
+    fn draw(&self) {
+        let format = get_plot_format();
+        let path = get_plot_path(&format!("{}.{format}", self.name));
+        let res = match format {
+            PlotFormat::Png => {
+                self.draw_on(BitMapBackend::new(&path, self.size).into_drawing_area())
+            }
+            PlotFormat::Svg => {
+                let size = (self.size.0 * PLOT_SVG_SCALE, self.size.1 * PLOT_SVG_SCALE);
+                self.draw_on(SVGBackend::new(&path, size).into_drawing_area())
+            }
+            PlotFormat::Html => self.write_html(&path),
+        };
+        if let Err(e) = res {
+            tracing::warn!("{}: failed to draw: {e}", path.display());
+        }
+    }
+
+    fn get_x_label(&self, x: f64) -> String {
+        if self.x_labels.is_empty() {
+            return format!("{x}");
+        }
+        let idx = x.round();
+        match self.x_labels.get(idx as usize) {
+            Some(label) if (x - idx).abs() < 1e-6 && idx >= 0.0 => label.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn draw_on<DB: DrawingBackend>(
+        &self,
+        root: DrawingArea<DB, Shift>,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+        if self.series.iter().all(|series| series.points.is_empty()) {
+            return Ok(root.present()?);
+        }
+
+        let mut ctx = ChartBuilder::on(&root)
+            .set_label_area_size(LabelAreaPosition::Left, 50)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .caption(&self.title, ("sans-serif", PLOT_FONT_SIZE))
+            .build_cartesian_2d(self.x_range.clone(), self.y_range.clone())?;
+
+        let x_formatter = |x: &f64| self.get_x_label(*x);
+        let mut mesh = ctx.configure_mesh();
+        mesh.x_desc(self.x_desc).y_desc(self.y_desc);
+        if !self.x_labels.is_empty() {
+            mesh.x_labels(self.x_labels.len() + 1)
+                .x_label_formatter(&x_formatter);
+        }
+        mesh.draw()?;
+
+        let bar_width = 0.8 / self.series.len() as f64;
+        for (i, series) in self.series.iter().enumerate() {
+            let color = series.color;
+            let drawn = match self.style {
+                Style::Dots => ctx.draw_series(
+                    (series.points.iter()).map(|&(x, y)| Circle::new((x, y), 1, color)),
+                )?,
+                Style::Lines => ctx.draw_series(LineSeries::new(series.points.clone(), color))?,
+                Style::Bars => ctx.draw_series(series.points.iter().map(|&(x, y)| {
+                    let x0 = x - 0.4 + i as f64 * bar_width;
+                    Rectangle::new([(x0, 0.0), (x0 + bar_width, y)], color.filled())
+                }))?,
+            };
+            if self.series.len() > 1 {
+                drawn.label(&series.label).legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 4), (x + 8, y + 4)], color.filled())
+                });
+            }
+        }
+        if self.series.len() > 1 {
+            ctx.configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .draw()?;
+        }
+        Ok(root.present()?)
+    }
+
+    // a page drawing the chart with plotly, loaded from its cdn
+    fn write_html(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let data: Vec<_> = (self.series.iter())
+            .map(|series| {
+                let (x, y): (Vec<_>, Vec<_>) = series.points.iter().copied().unzip();
+                let RGBColor(r, g, b) = series.color;
+                let color = format!("rgb({r},{g},{b})");
+                match self.style {
+                    Style::Dots => serde_json::json!({
+                        "x": x, "y": y, "name": series.label, "type": "scattergl",
+                        "mode": "markers", "marker": { "size": 3, "color": color },
+                    }),
+                    Style::Lines => serde_json::json!({
+                        "x": x, "y": y, "name": series.label, "type": "scattergl",
+                        "mode": "lines", "line": { "color": color },
+                    }),
+                    Style::Bars => serde_json::json!({
+                        "x": x, "y": y, "name": series.label, "type": "bar",
+                        "marker": { "color": color },
+                    }),
+                }
+            })
+            .collect();
+        let mut layout = serde_json::json!({
+            "title": { "text": self.title },
+            "xaxis": { "title": { "text": self.x_desc } },
+            "yaxis": { "title": { "text": self.y_desc } },
+            "showlegend": self.series.len() > 1,
+            "barmode": "group",
+        });
+        if !self.x_labels.is_empty() {
+            layout["xaxis"]["tickvals"] = (0..self.x_labels.len()).collect();
+            layout["xaxis"]["ticktext"] = self.x_labels.clone().into();
+        }
+        let data = serde_json::Value::from(data);
+        let html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <script src=\"{PLOTLY_URL}\"></script>\n</head>\n<body>\n\
+             <div id=\"chart\" style=\"width:100%;height:90vh\"></div>\n\
+             <script>Plotly.newPlot(\"chart\", {data}, {layout}, {{responsive: true}});</script>\n\
+             </body>\n</html>\n",
+            self.title
+        );
+        std::fs::write(path, html)?;
+        Ok(())
+    }
+}
+
+pub fn plot_time_graph(
+    sv: SV,
+    name: &str,
+    unit: &str,
+    time_series: &[f64],
+    y_delta: f64,
+    color: &RGBColor,
+) {
     plot_time_graph_with_sz(
         sv,
         name,
+        unit,
         time_series,
         y_delta,
         color,
@@ -48,66 +533,54 @@ pub fn plot_time_graph(sv: SV, name: &str, time_series: &[f64], y_delta: f64, co
     );
 }
 
+// one point per code period
+#[allow(clippy::too_many_arguments)]
 pub fn plot_time_graph_with_sz(
     sv: SV,
     name: &str,
+    unit: &str,
     time_series: &[f64],
     y_delta: f64,
     color: &RGBColor,
     size_x: u32,
     size_y: u32,
 ) {
-    let file_name = format!("{}/sat-{}-{}.png", PLOT_FOLDER, sv.prn, name);
-    let root_area = BitMapBackend::new(&file_name, (size_x, size_y)).into_drawing_area();
-    root_area.fill(&WHITE).unwrap();
-
-    if time_series.len() < 10 {
-        return;
-    }
-
+    let points = if time_series.len() < MIN_POINTS {
+        vec![]
+    } else {
+        (time_series.iter().enumerate())
+            .map(|(idx, v)| (idx as f64 * 0.001, *v))
+            .collect()
+    };
     let x_max = time_series.len() as f64 * 0.001;
+    let y_max = time_series.iter().copied().fold(f64::MIN, f64::max) + y_delta;
+    let y_min = time_series.iter().copied().fold(f64::MAX, f64::min) - y_delta;
 
-    let mut y_max = time_series
-        .iter()
-        .fold(f64::MIN, |acc, v| if *v > acc { *v } else { acc });
-    y_max += y_delta;
-    let mut y_min = time_series
-        .iter()
-        .fold(f64::MAX, |acc, v| if *v < acc { *v } else { acc });
-    y_min -= y_delta;
-
-    let mut ctx = ChartBuilder::on(&root_area)
-        .set_label_area_size(LabelAreaPosition::Left, 40)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .caption(
-            format!("sat {}: {}", sv.prn, name),
-            ("sans-serif", PLOT_FONT_SIZE),
-        )
-        .build_cartesian_2d(0.0..x_max, y_min..y_max)
-        .unwrap();
-
-    ctx.configure_mesh().draw().unwrap();
-
-    ctx.draw_series(
-        time_series
-            .iter()
-            .enumerate()
-            .map(|(idx, v)| Circle::new((idx as f64 * 0.001, *v), 1, color)),
-    )
-    .unwrap();
+    Chart {
+        name: format!("sat-{}-{}", sv.prn, name),
+        title: format!("sat {}: {}", sv.prn, name),
+        x_desc: "time (s)",
+        y_desc: unit,
+        x_labels: vec![],
+        x_range: 0.0..x_max,
+        y_range: y_min..y_max,
+        series: vec![Series::new(name, points, *color)],
+        style: Style::Dots,
+        size: (size_x, size_y),
+    }
+    .draw();
 }
 
 pub fn plot_iq_scatter(sv: SV, series: &[Complex64]) {
-    let file_name = format!("{}/sat-{}-iq-scatter.png", PLOT_FOLDER, sv.prn);
-    let root_area = BitMapBackend::new(&file_name, (PLOT_SIZE_X, PLOT_SIZE_Y)).into_drawing_area();
-    root_area.fill(&WHITE).unwrap();
-
-    if series.len() < 10 {
-        return;
-    }
-
     let delta = 1.4;
     let factor = 1000.0;
+    let points = if series.len() < MIN_POINTS {
+        vec![]
+    } else {
+        (series.iter())
+            .map(|c| (c.re * factor, c.im * factor))
+            .collect()
+    };
     let mut y_max = f64::MIN;
     let mut y_min = f64::MAX;
     let mut x_max = f64::MIN;
@@ -136,22 +609,17 @@ pub fn plot_iq_scatter(sv: SV, series: &[Complex64]) {
     x_min = f64::min(x_min, y_min);
     y_min = x_min;
 
-    let mut ctx = ChartBuilder::on(&root_area)
-        .set_label_area_size(LabelAreaPosition::Left, 40)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .caption(
-            format!("sat {}: iq-scatter", sv.prn),
-            ("sans-serif", PLOT_FONT_SIZE),
-        )
-        .build_cartesian_2d(x_min..x_max, y_min..y_max)
-        .unwrap();
-
-    ctx.configure_mesh().draw().unwrap();
-
-    ctx.draw_series(
-        series
-            .iter()
-            .map(|c| Circle::new((c.re * factor, c.im * factor), 1, RED)),
-    )
-    .unwrap();
+    Chart {
+        name: format!("sat-{}-iq-scatter", sv.prn),
+        title: format!("sat {}: iq-scatter", sv.prn),
+        x_desc: "prompt I (x1000)",
+        y_desc: "prompt Q (x1000)",
+        x_labels: vec![],
+        x_range: x_min..x_max,
+        y_range: y_min..y_max,
+        series: vec![Series::new("iq-scatter", points, RED)],
+        style: Style::Dots,
+        size: (PLOT_SIZE_X, PLOT_SIZE_Y),
+    }
+    .draw();
 }
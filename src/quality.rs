@@ -0,0 +1,96 @@
+use rustfft::num_complex::Complex64;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::util::fit_line;
+
+const CMC_WINDOW_SEC: f64 = 60.0; // of code minus carrier the rms is taken over
+const CMC_MIN_POINTS: usize = 10;
+const CMC_NOISE_FACTOR: f64 = 3.0; // rms over the code tracking noise
+const DELTA_THRESHOLD: f64 = 0.1; // early-late asymmetry
+
+// Multipath indicators of a tracked satellite: the shape of its correlation
+// peak from the early, prompt and late correlators, and the code minus
+// carrier.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct SignalQuality {
+    pub delta: f64,             // (|E| - |L|) / |P|: 0 for a symmetric peak
+    pub ratio: f64,             // (|E| + |L|) / |P|: how flat the peak is
+    pub cmc_rms_m: Option<f64>, // code minus carrier, detrended, once enough of it
+    pub multipath: bool,
+}
+
+impl fmt::Display for SignalQuality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "delta={:+.3} ratio={:.3}", self.delta, self.ratio)?;
+        if let Some(rms_m) = self.cmc_rms_m {
+            write!(f, " cmc={rms_m:.2} m")?;
+        }
+        if self.multipath {
+            write!(f, " multipath")?;
+        }
+        Ok(())
+    }
+}
+
+// A reflection adds a delayed copy of the signal: it distorts the correlation
+// peak, seen as early and late correlators out of balance, and biases the
+// code while barely moving the carrier. The code minus carrier is then what
+// the code loop corrects on top of the carrier aiding: apart from the
+// ionospheric divergence, a slow drift removed by a line fit, it's the code
+// noise and the multipath. Either one standing out flags the satellite.
+#[derive(Default)]
+pub struct QualityMonitor {
+    sum_p: f64,
+    sum_e: f64,
+    sum_l: f64,
+    cmc: VecDeque<(f64, f64)>, // receiver time and code minus carrier (m)
+    quality: SignalQuality,
+}
+
+impl QualityMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_quality(&self) -> SignalQuality {
+        self.quality
+    }
+
+    // every code period
+    pub fn add_correlations(&mut self, c_p: Complex64, c_e: Complex64, c_l: Complex64) {
+        self.sum_p += c_p.norm();
+        self.sum_e += c_e.norm();
+        self.sum_l += c_l.norm();
+    }
+
+    // Periodically, with the code minus carrier (m) and the rms of the code
+    // tracking noise expected at the cn0 of the satellite: true when the
+    // multipath flag changes.
+    pub fn update(&mut self, ts_sec: f64, cmc_m: f64, noise_m: f64) -> bool {
+        if self.sum_p > 0.0 {
+            self.quality.delta = (self.sum_e - self.sum_l) / self.sum_p;
+            self.quality.ratio = (self.sum_e + self.sum_l) / self.sum_p;
+        }
+        (self.sum_p, self.sum_e, self.sum_l) = (0.0, 0.0, 0.0);
+
+        self.cmc.push_back((ts_sec, cmc_m));
+        while (self.cmc.front()).is_some_and(|&(t, _)| ts_sec - t > CMC_WINDOW_SEC) {
+            self.cmc.pop_front();
+        }
+        self.quality.cmc_rms_m = (self.cmc.len() >= CMC_MIN_POINTS).then(|| {
+            let (offset, slope) = fit_line(&self.cmc, ts_sec);
+            let sum: f64 = (self.cmc.iter())
+                .map(|&(t, y)| (y - offset - slope * (t - ts_sec)).powi(2))
+                .sum();
+            (sum / self.cmc.len() as f64).sqrt()
+        });
+
+        let multipath = self.quality.delta.abs() >= DELTA_THRESHOLD
+            || (self.quality.cmc_rms_m).is_some_and(|rms_m| rms_m >= CMC_NOISE_FACTOR * noise_m);
+        let changed = multipath != self.quality.multipath;
+        self.quality.multipath = multipath;
+        changed
+    }
+}
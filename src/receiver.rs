@@ -1,224 +1,3038 @@
 use colored::Colorize;
 use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Epoch;
+use rayon::ThreadPool;
 use rayon::prelude::*;
-use rustfft::num_complex::Complex64;
-use std::collections::HashMap;
-use std::path::Path;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
 
-use crate::channel::Channel;
-use crate::device::RtlSdrDevice;
+#[cfg(feature = "airspy")]
+use crate::airspy::AirspyDevice;
+use crate::api::{Callbacks, ChannelEvent, ChannelEventKind, GnssFix, GnssMeasurement};
+use crate::bench::{Stage, StageTimes, print_bench_report};
+use crate::calibration::{CALIBRATION_PERIOD_SEC, estimate_ppm, get_predicted_doppler};
+use crate::capture::{IQRecorder, IQRingBuffer};
+use crate::channel::{Channel, PLOT_PERIOD_SEC, SvPriority};
+use crate::checkpoint::{BandCheckpoint, Checkpoint, RecordingFile};
+use crate::clock::Instant;
+use crate::code::{Code, CodeTables};
+use crate::device::DeviceConfig;
+use crate::device::DeviceType;
+use crate::downconvert::IFMixer;
+use crate::ephemeris::Ephemeris;
+#[cfg(feature = "cuda")]
+use crate::gpufft::GpuFft;
+#[cfg(feature = "hackrf")]
+use crate::hackrf::HackRfDevice;
+use crate::interference::{INTERFERENCE_PERIOD_SEC, InterferenceDetector};
+use crate::navigation_sbas::is_sbas;
+#[cfg(feature = "net")]
 use crate::network::RtlSdrTcp;
+use crate::noise::NoiseSource;
+use crate::notch::NotchFilter;
+use crate::observables::{ObsEpoch, ObsSat, ObsWriter};
+use crate::output::{OutputConfig, Outputs, Solution};
+use crate::pipeline::Pipeline;
+use crate::plots::{OverviewJob, PlotWorker};
+use crate::recording::IQBuffer;
 use crate::recording::IQFileType;
 use crate::recording::IQRecording;
+use crate::recording::check_recording_range;
+use crate::resampler::Resampler;
+use crate::residuals::{ResidualMonitor, ResidualWriter};
+#[cfg(all(unix, feature = "rtlsdr"))]
+use crate::rtlsdr::RtlSdrDevice;
+use crate::sample::{Float, IQSample};
+use crate::sim::{SimConfig, SimSource};
+use crate::sky::{SkyPosition, get_azimuth_elevation, get_dop, get_sv_position_at};
+use crate::snapshot::{SnapshotFix, SnapshotMeas, solve_coarse_time};
 use crate::solver::PositionSolver;
+use crate::source::SampleSource;
+use crate::spectrum::Spectrum;
+use crate::start::{
+    SatState, StartMode, StartState, WARM_DOPPLER_SPREAD_HZ, get_gps_sec, get_tow_sec,
+    get_unix_time_now, predict_doppler_from_almanac,
+};
 use crate::state::GnssState;
+use crate::state::SvRequest;
+use crate::state::{PlaybackEvent, PlaybackEventKind, PlaybackProgress};
+use crate::summary::{RunStats, RunSummary};
+use crate::threads::{build_thread_pool, install};
+use crate::timing::{TIMING_PERIOD_SEC, TimingSolver};
+use crate::ttff::Milestone;
+#[cfg(feature = "uhd")]
+use crate::uhd::UhdDevice;
 
+pub const DEFAULT_FS: f64 = 2046000.0;
+const DEFAULT_SIG: &str = "L1CA";
 const PERIOD_RCV: f64 = 0.001;
+const PROGRESS_PERIOD_SEC: f64 = 5.0;
+const PROGRESS_PERIOD_UI_SEC: f64 = 0.2; // the playback timeline follows
+const PROGRESS_MIN_SEC: f64 = 1.0; // of wall clock before a speed and an eta
+const NOISE_SEED: u64 = 0x5eed;
+const HOLDOVER_SLEEP_MSEC: u64 = 100;
+const PAUSE_SLEEP_MSEC: u64 = 50;
+const CHECKPOINT_MIN_SEC: f64 = 1.0;
+const LAG_DEGRADED_SEC: f64 = 0.2; // stop acquiring new satellites
+const LAG_SKIP_SEC: f64 = 0.5; // skip code periods back to LAG_DEGRADED_SEC
+const MAX_FIXES: usize = 3600; // position track kept for the UI map
+const MAX_EVENTS: usize = 1000; // along the playback timeline
+const SPECTRUM_PERIOD_MSEC: u64 = 200; // UI spectrum refresh
+const VISIBILITY_PERIOD_SEC: f64 = 30.0; // of signal between two predictions
+const AIDING_PERIOD_SEC: f64 = 1.0;
+const AIDED_DOPPLER_SPREAD_HZ: f64 = 100.0;
+const MAX_CODE_SEC: f64 = 0.02; // longest code a channel can acquire
+const AUTO_PPM_MAX_STD: f64 = 0.3; // estimates applied when this good
+const AUTO_PPM_MIN_CHANGE: f64 = 0.1; // ppm
+const RECORD_BLOCK_SEC: f64 = 0.1;
+const MEASUREMENT_PERIOD_SEC: f64 = 1.0; // of signal, to the callback
 
-pub trait IQReader {
-    fn get_iq_data(
-        &mut self,
-        off_samples: usize,
-        num_samples: usize,
-    ) -> Result<Vec<Complex64>, Box<dyn std::error::Error>>;
+type Blocks = Vec<Arc<[IQSample]>>; // one per antenna, the reference first
+
+// One front-end and the bank of channels tracking its signal. A receiver has
+// a primary band and optionally secondary ones (e.g. L5 on a second device),
+// all sampled at the same internal frequency and feeding a common solver.
+struct Band {
+    iq_feed: Box<dyn SampleSource>,
+    if_mixer: Option<IFMixer>,
+    resampler: Option<Resampler>,
+    notch: Option<NotchFilter>, // at the internal sampling frequency
+    fs: f64,                    // sampling frequency of the input
+    fi: f64,
+    sig: String,       // the carrier the front-end is tuned to, the first of sigs
+    sigs: Vec<String>, // received together, one per constellation
+    off_samples: usize,
+    cached_iq_vec: Vec<IQSample>,
+    resampled_iq_vec: Vec<IQSample>,
+    block: Arc<[IQSample]>, // shared with the channels, reused once they're done
+    channels: BTreeMap<SV, Channel>, // ordered: reproducible iterations
+    ring_buf: Option<IQRingBuffer>,
+    recorder: Option<IQRecorder>,
+    time_jump: bool,
+    num_dropped: usize,  // reported by the source, since the last time jump
+    num_lost: usize,     // dropped or skipped to realign the antennas, in all
+    antennas: Vec<Band>, // more coherent streams of the same signal, no channels
+}
+
+// Receiver time as a count of samples at the internal sampling frequency,
+// converted to seconds on demand: no drift over long runs.
+struct SampleClock {
+    fs: f64,
+    num_samples: u64,
+}
+
+impl SampleClock {
+    fn new(fs: f64) -> Self {
+        Self { fs, num_samples: 0 }
+    }
+
+    fn advance(&mut self, num_samples: usize) {
+        self.num_samples += num_samples as u64;
+    }
+
+    // samples counted at another rate, e.g. dropped by a source
+    fn advance_at(&mut self, num_samples: usize, fs: f64) {
+        self.num_samples += (num_samples as f64 * self.fs / fs).round() as u64;
+    }
+
+    // by a seek, back when negative
+    fn shift_sec(&mut self, delta_sec: f64) {
+        let num_samples = self.num_samples as f64 + delta_sec * self.fs;
+        self.num_samples = num_samples.max(0.0).round() as u64;
+    }
+
+    fn get_sec(&self) -> f64 {
+        self.num_samples as f64 / self.fs
+    }
+
+    // time of the sample num_samples before now
+    fn get_sec_before(&self, num_samples: usize) -> f64 {
+        self.num_samples.saturating_sub(num_samples as u64) as f64 / self.fs
+    }
 }
 
 pub struct Receiver {
-    iq_feed: Box<dyn IQReader>,
-    period_sp: usize, // samples per period
-    off_samples: usize,
-    cached_iq_vec: Vec<Complex64>,
-    cached_ts_sec_tail: f64,
-    channels: HashMap<SV, Channel>,
+    bands: Vec<Band>,
+    period_sp: usize,   // samples per period, at the internal sampling frequency
+    clock: SampleClock, // end of the cached samples
     solver: PositionSolver,
     last_fix_sec: f64,
+    num_fix_svs: usize,
+    fix_requests: VecDeque<(f64, Vec<Ephemeris>)>, // all the tracked satellites, until solved
+    residuals: ResidualMonitor,
+    residual_writer: Option<ResidualWriter>,
     exit_req: Arc<AtomicBool>,
+    pub_state: Arc<Mutex<GnssState>>,
+    fs_internal: f64,
+    sats: String,
+    codes: CodeTables,
+    fft_planner: FftPlanner<Float>, // caches the plans shared by all channels
+    playback_speed: Option<f64>,
+    lagging: bool,
+    start_mode: StartMode,
+    start_state: StartState, // loaded, then updated as the run goes
+    state_file: Option<PathBuf>,
+    start_unix: f64,                      // rough time of the first sample
+    thread_pool: Option<Arc<ThreadPool>>, // rayon's global pool otherwise
+    bench: Option<StageTimes>,            // read and solve, the channels keep theirs
+    deterministic: bool,
+    block_msec: usize, // code periods per processing step
+    pipelined: bool,
+    pipeline: Option<Pipeline>,            // while running, when pipelined
+    sv_enabled: BTreeMap<SV, bool>,        // set while running, applied to the
+    sv_priority: BTreeMap<SV, SvPriority>, // channels as they're at hand
+    paused: bool,
+    checkpoint_file: Option<PathBuf>,
+    checkpoint_sec: f64, // of signal between checkpoints
+    last_checkpoint_sec: f64,
+    resumed_msec: usize,        // processed before the checkpoint resumed from
+    spectrum: Option<Spectrum>, // once the UI shows it
+    last_spectrum: Instant,
+    tracking_svs: BTreeSet<SV>, // to spot locks and losses
+    outputs: Outputs,
+    auto_ppm: bool,
+    clock_offset_ppm: f64, // applied to the doppler search
+    last_calibration_sec: f64,
+    interference: InterferenceDetector,
+    last_interference_sec: f64,
+    timing: Option<TimingSolver>,
+    last_timing_sec: f64,
+    visible_svs: Option<BTreeSet<SV>>, // above the horizon, per the almanac
+    last_visibility_sec: f64,
+    last_aiding_sec: f64,
+    obs_writer: Option<ObsWriter>,
+    callbacks: Callbacks,
+    eph_svs: BTreeSet<SV>, // with a complete ephemeris, for the channel events
+    last_measurement_sec: f64,
+    stats: RunStats, // for the summary at the end of the run
+    stats_file: Option<PathBuf>,
+    plot_period_sec: Option<f64>, // of signal between two rounds of charts, none: no charts
+    plot_worker: Option<PlotWorker>, // while running
+    #[cfg(feature = "cuda")]
+    gpu_fft: Option<GpuFft>,
+}
+
+// a satellite found by run_acquire() or run_scan()
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct AcquiredSv {
+    pub sv: SV,
+    pub cn0: f64,
+    pub doppler_hz: f64,
+    pub code_off_sec: f64,
 }
 
-fn get_sat_list(sats: &str) -> Vec<SV> {
+fn get_constellation_letter(c: char) -> Option<Constellation> {
+    match c {
+        'G' => Some(Constellation::GPS),
+        'E' => Some(Constellation::Galileo),
+        'R' => Some(Constellation::Glonass),
+        'C' => Some(Constellation::BeiDou),
+        'J' => Some(Constellation::QZSS),
+        _ => None,
+    }
+}
+
+// Comma-separated satellites of the signals of sig, all theirs when empty:
+// for L1CA, those of gps and QZSS. A prn alone is one of the first signal,
+// after a constellation letter (G, E, R, C or J) one of the signal of that
+// constellation, e.g. "3,E11" with L1CA,E1B; skipped without such a signal.
+pub fn get_sat_list(sig: &str, sats: &str) -> Result<Vec<SV>, Box<dyn std::error::Error>> {
+    let sigs = Code::get_sig_list(sig);
+    let mut constellations = vec![];
+    for sig in &sigs {
+        let constellation =
+            Code::get_constellation(sig).ok_or(format!("signal {sig} not supported"))?;
+        if constellations.contains(&constellation) {
+            return Err(format!("{sig}: one signal per constellation").into());
+        }
+        constellations.push(constellation);
+    }
     let mut sat_vec = vec![];
     if !sats.is_empty() {
         for s in sats.split(',') {
-            let prn = s.parse::<u8>().unwrap();
-            sat_vec.push(SV::new(Constellation::GPS, prn));
+            let s = s.trim();
+            let letter = s.chars().next().and_then(get_constellation_letter);
+            let (sig, prn) = match letter {
+                Some(constellation) => {
+                    let Some(sig) = Code::get_constellation_signal(&sigs, constellation) else {
+                        continue;
+                    };
+                    (sig, &s[1..])
+                }
+                None => (sigs[0], s),
+            };
+            let (prns, _) = Code::get_prns(sig);
+            let sv = (prn.parse::<u8>().ok())
+                .filter(|prn| prns.contains(prn))
+                .and_then(|prn| Code::get_sv(sig, prn))
+                .filter(|sv| letter.is_none_or(|c| c == sv.constellation))
+                .ok_or(format!("invalid satellite: {s}"))?;
+            sat_vec.push(sv);
         }
     } else {
-        for prn in 1..=32_u8 {
-            sat_vec.push(SV::new(Constellation::GPS, prn));
+        for sig in &sigs {
+            let constellation = Code::get_constellation(sig).unwrap();
+            let (_, default_prns) = Code::get_prns(sig);
+            for prn in default_prns {
+                sat_vec.push(SV::new(constellation, prn));
+            }
+            // QZSS, on the L1CA codes of gps
+            if *sig == "L1CA" {
+                for prn in 193..=199_u8 {
+                    sat_vec.push(SV::new(Constellation::QZSS, prn));
+                }
+            }
+        }
+    }
+    Ok(sat_vec)
+}
+
+// The signals of a front-end: supported, with codes short enough to acquire,
+// all on the carrier of the first.
+fn check_signals(sig: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let sigs = Code::get_sig_list(sig);
+    for sig in &sigs {
+        if Code::get_code_len(sig) == 0 {
+            return Err(format!("signal {sig} not supported").into());
+        }
+        if Code::get_code_period(sig) > MAX_CODE_SEC {
+            return Err(format!("signal {sig}: code too long to acquire").into());
+        }
+    }
+    let fc = Code::get_code_freq(sigs[0]);
+    if let Some(sig) = sigs.iter().find(|sig| Code::get_code_freq(sig) != fc) {
+        return Err(format!(
+            "{sig} at {:.3} MHz: not on the carrier of {} at {:.3} MHz",
+            Code::get_code_freq(sig) / 1e6,
+            sigs[0],
+            fc / 1e6
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn get_device_feed(sig: &str, fs: f64, config: &DeviceConfig) -> Option<Box<dyn SampleSource>> {
+    if config.clock_source.is_some() {
+        match config.device_type {
+            DeviceType::Uhd => {}
+            DeviceType::HackRf => {
+                tracing::info!(
+                    "hackrf: switches to CLKIN by itself when a 10 MHz reference is present"
+                )
+            }
+            _ => tracing::warn!(
+                "{}: no external reference support, ignoring clock source",
+                config.device_type
+            ),
+        }
+    }
+    match config.device_type {
+        #[cfg(all(unix, feature = "rtlsdr"))]
+        DeviceType::RtlSdr => {
+            let res = RtlSdrDevice::new(sig, fs, config);
+            if res.is_err() {
+                tracing::warn!("Failed to open rtl-sdr device.");
+                return None;
+            }
+            let dev = res.unwrap();
+
+            Some(Box::new(dev))
+        }
+        #[cfg(all(unix, not(feature = "rtlsdr")))]
+        DeviceType::RtlSdr => {
+            tracing::warn!("rtl-sdr support not enabled: rebuild with --features rtlsdr");
+            None
         }
-        let use_sbas = false;
-        if use_sbas {
-            for prn in 120..=158_u8 {
-                sat_vec.push(SV::new(Constellation::GPS, prn));
+        #[cfg(not(unix))]
+        DeviceType::RtlSdr => {
+            tracing::warn!("rtl-sdr support not available on this platform");
+            None
+        }
+        #[cfg(feature = "hackrf")]
+        DeviceType::HackRf => match HackRfDevice::new(sig, fs, config) {
+            Ok(dev) => Some(Box::new(dev)),
+            Err(e) => {
+                tracing::warn!("Failed to open hackrf device: {e}");
+                None
+            }
+        },
+        #[cfg(not(feature = "hackrf"))]
+        DeviceType::HackRf => {
+            tracing::warn!("hackrf support not enabled: rebuild with --features hackrf");
+            None
+        }
+        #[cfg(feature = "airspy")]
+        DeviceType::Airspy => match AirspyDevice::new(sig, fs, config) {
+            Ok(dev) => Some(Box::new(dev)),
+            Err(e) => {
+                tracing::warn!("Failed to open airspy device: {e}");
+                None
+            }
+        },
+        #[cfg(not(feature = "airspy"))]
+        DeviceType::Airspy => {
+            tracing::warn!("airspy support not enabled: rebuild with --features airspy");
+            None
+        }
+        #[cfg(feature = "uhd")]
+        DeviceType::Uhd => match UhdDevice::new(sig, fs, config) {
+            Ok(dev) => Some(Box::new(dev)),
+            Err(e) => {
+                tracing::warn!("Failed to open uhd device: {e}");
+                None
             }
+        },
+        #[cfg(not(feature = "uhd"))]
+        DeviceType::Uhd => {
+            tracing::warn!("uhd support not enabled: rebuild with --features uhd");
+            None
+        }
+    }
+}
+
+// While lagging, acquisition goes on for the high priority channels only, and
+// the low priority ones wait for the others of their band to be done.
+fn may_acquire(channel: &Channel, lagging: bool, others_acquiring: bool) -> bool {
+    match channel.get_priority() {
+        SvPriority::High => true,
+        SvPriority::Normal => !lagging,
+        SvPriority::Low => !lagging && !others_acquiring,
+    }
+}
+
+// acquisition steps batched on the gpu, if enabled: returns the channels done
+#[cfg(feature = "cuda")]
+fn gpu_acquisition(
+    gpu_fft: &mut Option<GpuFft>,
+    channels: &mut BTreeMap<SV, Channel>,
+    iq_vec: &[IQSample],
+    ts_sec: f64,
+    is_due: impl Fn(&Channel) -> bool,
+    pool: Option<&ThreadPool>,
+) -> Vec<SV> {
+    let Some(gpu) = gpu_fft else {
+        return vec![];
+    };
+    match gpu.process_acquisition(channels, iq_vec, ts_sec, is_due, pool) {
+        Ok(svs) => svs,
+        Err(e) => {
+            tracing::warn!("{e}: falling back to the cpu");
+            *gpu_fft = None;
+            vec![]
         }
     }
-    sat_vec
 }
 
+#[cfg(feature = "net")]
+fn get_rtl_tcp_feed(
+    hostname: &str,
+    exit_req: Arc<AtomicBool>,
+    sig: &str,
+    fs: f64,
+    device: Option<&DeviceConfig>,
+) -> Result<Box<dyn SampleSource>, Box<dyn std::error::Error>> {
+    let config = device.cloned().unwrap_or_default();
+    let net = RtlSdrTcp::new(hostname, exit_req, sig, fs, &config)?;
+
+    tracing::info!("Using rtl_tcp backend: {}", hostname);
+    Ok(Box::new(net))
+}
+
+#[cfg(not(feature = "net"))]
+fn get_rtl_tcp_feed(
+    _hostname: &str,
+    _exit_req: Arc<AtomicBool>,
+    _sig: &str,
+    _fs: f64,
+    _device: Option<&DeviceConfig>,
+) -> Result<Box<dyn SampleSource>, Box<dyn std::error::Error>> {
+    Err("rtl_tcp support not enabled: rebuild with --features net".into())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn get_iq_feed(
-    use_device: bool,
+    device: Option<&DeviceConfig>,
+    use_noise: bool,
     hostname: &str,
     sig: &str,
     fs: f64,
     file: &Path,
     iq_file_type: &IQFileType,
+    loop_file: bool,
     exit_req: Arc<AtomicBool>,
-) -> Option<Box<dyn IQReader>> {
-    if use_device {
-        let res = RtlSdrDevice::new(sig, fs);
-        if res.is_err() {
-            log::warn!("Failed to open rtl-sdr device.");
-            return None;
-        }
-        let dev = res.unwrap();
-
-        Some(Box::new(dev))
-    } else if !hostname.is_empty() {
-        let net = RtlSdrTcp::new(hostname, exit_req.clone(), sig, fs).unwrap();
-
-        log::warn!("Using rtl_tcp backend: {}", hostname);
-        Some(Box::new(net))
+) -> Result<Box<dyn SampleSource>, Box<dyn std::error::Error>> {
+    if !hostname.is_empty() {
+        get_rtl_tcp_feed(hostname, exit_req, sig, fs, device)
+    } else if let Some(config) = device {
+        get_device_feed(sig, fs, config)
+            .ok_or_else(|| format!("failed to open {} device", config.device_type).into())
+    } else if use_noise {
+        Ok(Box::new(NoiseSource::new(fs, NOISE_SEED)))
     } else {
-        Some(Box::new(IQRecording::new(file, fs, iq_file_type)))
+        Ok(Box::new(IQRecording::new(
+            file,
+            fs,
+            iq_file_type,
+            loop_file,
+        )?))
     }
 }
 
-impl Receiver {
+impl Band {
     #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        use_device: bool,
+    fn new(
+        device: Option<&DeviceConfig>,
+        use_noise: bool,
         hostname: &str,
         file: &Path,
         iq_file_type: &IQFileType,
+        loop_file: bool,
         fs: f64,
+        fs_internal: f64,
         fi: f64,
         off_msec: usize,
         sig: &str,
-        sats: &str,
         exit_req: Arc<AtomicBool>,
-        state: Arc<Mutex<GnssState>>,
-    ) -> Self {
-        let period_sp = (PERIOD_RCV * fs) as usize;
-        let mut channels = HashMap::<SV, Channel>::new();
-        let sat_vec = get_sat_list(sats);
-
-        for sv in sat_vec {
-            let pub_state = state.clone();
-            channels.insert(sv, Channel::new(sig, sv, fs, fi, pub_state));
-        }
-
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let iq_feed = get_iq_feed(
-            use_device,
+            device,
+            use_noise,
             hostname,
-            sig,
+            Code::get_sig_list(sig)[0],
             fs,
             file,
             iq_file_type,
-            exit_req.clone(),
-        )
-        .unwrap();
+            loop_file,
+            exit_req,
+        )?;
+        let real_input =
+            device.is_none() && !use_noise && hostname.is_empty() && iq_file_type.is_real();
+        Ok(Self::with_feed(
+            iq_feed,
+            real_input,
+            fs,
+            fs_internal,
+            fi,
+            off_msec,
+            sig,
+        ))
+    }
+
+    fn with_feed(
+        iq_feed: Box<dyn SampleSource>,
+        real_input: bool,
+        fs: f64,
+        fs_internal: f64,
+        fi: f64,
+        off_msec: usize,
+        sig: &str,
+    ) -> Self {
+        // real samples from files are brought to baseband before the channels
+        if real_input && fi == 0.0 {
+            tracing::warn!("real samples with fi=0: the signal image overlaps the signal");
+        }
+        let if_mixer = if real_input && fi != 0.0 {
+            Some(IFMixer::new(fi, fs))
+        } else {
+            None
+        };
+
+        let resampler = if fs != fs_internal || if_mixer.is_some() {
+            Some(Resampler::new(fs, fs_internal))
+        } else {
+            None
+        };
 
+        let sigs = Code::get_sig_list(sig);
         Self {
             iq_feed,
-            period_sp,
-            off_samples: off_msec * period_sp,
-            cached_iq_vec: Vec::<Complex64>::new(),
-            cached_ts_sec_tail: 0.0,
-            channels,
-            solver: PositionSolver::new(state),
-            last_fix_sec: 0.0,
-            exit_req: exit_req.clone(),
+            if_mixer,
+            resampler,
+            notch: None,
+            fs,
+            fi,
+            sig: sigs[0].to_string(),
+            sigs: sigs.iter().map(|sig| sig.to_string()).collect(),
+            off_samples: (off_msec as f64 * PERIOD_RCV * fs) as usize,
+            cached_iq_vec: Vec::<IQSample>::new(),
+            resampled_iq_vec: Vec::<IQSample>::new(),
+            block: Arc::from([]),
+            channels: BTreeMap::new(),
+            ring_buf: None,
+            recorder: None,
+            time_jump: false,
+            num_dropped: 0,
+            num_lost: 0,
+            antennas: vec![],
         }
     }
 
-    fn fetch_samples_msec(&mut self) -> Result<(Vec<Complex64>, f64), Box<dyn std::error::Error>> {
-        let num_samples = if self.cached_iq_vec.is_empty() {
-            2 * self.period_sp
+    fn add_channels(
+        &mut self,
+        sats: &str,
+        codes: &CodeTables,
+        fs_internal: f64,
+        state: &Arc<Mutex<GnssState>>,
+        fft_planner: &mut FftPlanner<Float>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let fi_channel = if self.if_mixer.is_some() {
+            0.0
         } else {
-            self.period_sp
+            self.fi
         };
+        let sigs: Vec<_> = self.sigs.iter().map(String::as_str).collect();
+        for sv in get_sat_list(&self.sigs.join(","), sats)? {
+            let sig = Code::get_constellation_signal(&sigs, sv.constellation).unwrap();
+            if codes.gen_code(sig, sv.prn).is_none() {
+                tracing::warn!("{sv}: no {sig} code, skipped");
+                continue;
+            }
+            let channel = Channel::new(
+                sig,
+                sv,
+                codes,
+                fs_internal,
+                fi_channel,
+                state.clone(),
+                fft_planner,
+            );
+            self.channels.insert(sv, channel);
+        }
+        Ok(())
+    }
+
+    fn get_iq_data(
+        &mut self,
+        num_samples: usize,
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        let block = self.iq_feed.read_block(self.off_samples, num_samples)?;
+
+        if !block.is_contiguous() {
+            self.time_jump = true;
+        }
+        if self.off_samples == 0
+            && let Some(hw_ts_sec) = block.hw_ts_sec
+        {
+            tracing::info!(
+                "{}: source provides hardware timestamps: {hw_ts_sec:.6}",
+                self.sig
+            );
+        }
+        self.num_dropped += block.num_dropped;
+        self.num_lost += block.num_dropped;
+        let iq_vec = block.iq_vec;
 
-        let mut iq_vec = self.iq_feed.get_iq_data(self.off_samples, num_samples)?;
+        if let Some(ring_buf) = &mut self.ring_buf {
+            ring_buf.push(&iq_vec);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.push(&iq_vec);
+        }
 
         self.off_samples += num_samples;
-        self.cached_iq_vec.append(&mut iq_vec);
-        self.cached_ts_sec_tail += num_samples as f64 / (1000.0 * self.period_sp as f64);
+        Ok(iq_vec)
+    }
+
+    // returns num_samples at the internal sampling frequency, notched
+    fn get_resampled_iq_data(
+        &mut self,
+        num_samples: usize,
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        let mut iq_vec = self.get_internal_iq_data(num_samples)?;
+        if let Some(notch) = &mut self.notch {
+            notch.process(&mut iq_vec);
+        }
+        Ok(iq_vec)
+    }
+
+    fn get_internal_iq_data(
+        &mut self,
+        num_samples: usize,
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        if self.resampler.is_none() {
+            return self.get_iq_data(num_samples);
+        }
+        let mut iq_vec = std::mem::take(&mut self.resampled_iq_vec);
+        while iq_vec.len() < num_samples {
+            let resampler = self.resampler.as_ref().unwrap();
+            let n = usize::max(
+                1,
+                resampler.get_num_input_samples(num_samples - iq_vec.len()),
+            );
+            let mut iq_in = self.get_iq_data(n)?;
+            if let Some(if_mixer) = &mut self.if_mixer {
+                if_mixer.mix(&mut iq_in);
+            }
+            let resampler = self.resampler.as_mut().unwrap();
+            iq_vec.append(&mut resampler.process(&iq_in));
+        }
+        self.resampled_iq_vec = iq_vec.split_off(num_samples);
+        Ok(iq_vec)
+    }
+
+    fn get_lag_sec(&self) -> Option<f64> {
+        let lag_sec = self
+            .iq_feed
+            .get_num_samples_queued()
+            .map(|n| n as f64 / self.fs);
+        (self.antennas.iter())
+            .filter_map(|antenna| antenna.get_lag_sec())
+            .chain(lag_sec)
+            .reduce(f64::max)
+    }
 
-        if self.cached_iq_vec.len() > 2 * self.period_sp {
-            let num_samples = self.period_sp;
-            let _ = self.cached_iq_vec.drain(0..num_samples);
+    // what tells the recording apart in a checkpoint
+    fn get_recording_files(&self) -> Vec<RecordingFile> {
+        (self.iq_feed.get_file_paths().iter())
+            .map(|path| RecordingFile::new(path))
+            .collect()
+    }
+
+    // Recordings only: the next samples are read from sec into the file. The
+    // channels start over, as after a discontinuity.
+    fn seek(&mut self, sec: f64) -> Result<(), Box<dyn std::error::Error>> {
+        let total = (self.iq_feed.get_num_samples_total()).ok_or("seek: not a recording")?;
+        self.off_samples = usize::min((sec.max(0.0) * self.fs) as usize, total);
+        self.cached_iq_vec.clear();
+        self.resampled_iq_vec.clear();
+        self.time_jump = true;
+        for antenna in &mut self.antennas {
+            antenna.seek(sec)?;
         }
+        Ok(())
+    }
+
+    // drops num_msec whole code periods, the 2-code window stays aligned
+    fn skip_msec(
+        &mut self,
+        num_msec: usize,
+        period_sp: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut iq_vec = self.get_resampled_iq_data(num_msec * period_sp)?;
+        self.cached_iq_vec.append(&mut iq_vec);
         let len = self.cached_iq_vec.len();
+        let _ = self.cached_iq_vec.drain(0..len - 2 * period_sp);
+        for antenna in &mut self.antennas {
+            antenna.skip_msec(num_msec, period_sp)?;
+        }
+        Ok(())
+    }
 
-        // we pass 2 code worth of iq data back
-        // the timestamp given corresponds to the beginning of the last code
-        // [...code...][...code...]
-        //             ^
+    // we pass num_msec new codes back, preceded by the last one of the
+    // previous block
+    fn fetch_samples_msec(
+        &mut self,
+        period_sp: usize,
+        num_msec: usize,
+    ) -> Result<Arc<[IQSample]>, Box<dyn std::error::Error>> {
+        let block_sp = (num_msec + 1) * period_sp;
+        let num_samples = if self.cached_iq_vec.is_empty() {
+            block_sp
+        } else {
+            num_msec * period_sp
+        };
 
-        Ok((
-            self.cached_iq_vec[len - 2 * self.period_sp..].to_vec(),
-            self.cached_ts_sec_tail - 0.001,
-        ))
+        let mut iq_vec = self.get_resampled_iq_data(num_samples)?;
+        self.cached_iq_vec.append(&mut iq_vec);
+
+        let len = self.cached_iq_vec.len();
+        if len > block_sp {
+            let _ = self.cached_iq_vec.drain(0..len - block_sp);
+        }
+        let iq_vec = &self.cached_iq_vec[..];
+
+        match Arc::get_mut(&mut self.block) {
+            Some(block) if block.len() == iq_vec.len() => block.copy_from_slice(iq_vec),
+            _ => self.block = Arc::from(iq_vec),
+        }
+        Ok(self.block.clone())
     }
 
-    fn compute_fix(&mut self, ts_sec: f64) {
-        if ts_sec - self.last_fix_sec < 2.0 {
-            return;
+    // num_samples of the input lost on purpose, as if dropped by the source
+    fn skip_samples(&mut self, num_samples: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = self.get_iq_data(num_samples)?;
+        self.num_dropped += num_samples;
+        self.num_lost += num_samples;
+        self.time_jump = true;
+        Ok(())
+    }
+
+    // The antenna streams that lost fewer samples than the others are behind
+    // them: they skip as many samples to line up again. The block where the
+    // samples were lost is a discontinuity anyway, the realignment lands with
+    // the next one.
+    fn align_antennas(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let num_lost_max = (self.antennas.iter())
+            .map(|antenna| antenna.num_lost)
+            .fold(self.num_lost, usize::max);
+        if num_lost_max > self.num_lost {
+            self.skip_samples(num_lost_max - self.num_lost)?;
         }
+        for antenna in &mut self.antennas {
+            if num_lost_max > antenna.num_lost {
+                antenna.skip_samples(num_lost_max - antenna.num_lost)?;
+            }
+        }
+        Ok(())
+    }
 
-        let ephs: Vec<_> = self
-            .channels
-            .values()
-            .filter(|&ch| ch.is_state_tracking())
-            .filter(|&ch| ch.is_ephemeris_complete())
-            .map(|ch| ch.nav.eph)
-            .collect();
+    // the block of each antenna, the reference first, lined up: a
+    // discontinuity on any of them is one for all.
+    fn fetch_array_msec(
+        &mut self,
+        period_sp: usize,
+        num_msec: usize,
+    ) -> Result<Blocks, Box<dyn std::error::Error>> {
+        if !self.antennas.is_empty() {
+            self.align_antennas()?;
+        }
+        let mut blocks = vec![self.fetch_samples_msec(period_sp, num_msec)?];
+        for antenna in &mut self.antennas {
+            blocks.push(antenna.fetch_samples_msec(period_sp, num_msec)?);
+            if std::mem::take(&mut antenna.time_jump) {
+                self.time_jump = true;
+            }
+        }
+        Ok(blocks)
+    }
+}
 
-        if ephs.len() < 4 {
-            return;
+enum ReceiverSource {
+    File(PathBuf),
+    Device(DeviceConfig),
+    RtlTcp(String, DeviceConfig), // the device settings sent to the server
+    Noise,
+    Memory(Arc<[u8]>),
+    Sim(SimConfig),
+}
+
+// Configuration of a receiver, checked when building it:
+//   ReceiverBuilder::from_file(path).fs(4092000.0).signal("L1CA").sats("5,7").build()
+// The defaults are the command line's.
+pub struct ReceiverBuilder {
+    source: ReceiverSource,
+    iq_file_type: IQFileType,
+    loop_file: bool,
+    fs: f64,
+    fs_internal: f64,
+    fi: f64,
+    off_msec: usize,
+    sig: String,
+    sats: String,
+    codes: CodeTables,
+    exit_req: Arc<AtomicBool>,
+    state: Arc<Mutex<GnssState>>,
+}
+
+impl ReceiverBuilder {
+    fn new(source: ReceiverSource) -> Self {
+        Self {
+            source,
+            iq_file_type: IQFileType::TypePairFloat32,
+            loop_file: false,
+            fs: DEFAULT_FS,
+            fs_internal: DEFAULT_FS,
+            fi: 0.0,
+            off_msec: 0,
+            sig: DEFAULT_SIG.to_string(),
+            sats: String::new(),
+            codes: CodeTables::new(),
+            exit_req: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(Mutex::new(GnssState::new())),
         }
+    }
 
-        log::warn!(
-            "t={ts_sec:.3} -- {}",
-            format!("attempting fix with {} SVs", ephs.len()).red()
-        );
+    // IQ file(s): comma-separated list or glob pattern
+    pub fn from_file(file: &Path) -> Self {
+        Self::new(ReceiverSource::File(file.to_path_buf()))
+    }
 
-        self.solver.compute_position(ts_sec, &ephs);
-        self.last_fix_sec = ts_sec;
+    pub fn from_device(config: &DeviceConfig) -> Self {
+        Self::new(ReceiverSource::Device(config.clone()))
     }
 
-    fn process_step(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let (iq_vec, ts_sec) = self.fetch_samples_msec()?;
+    pub fn from_rtl_tcp(hostname: &str, config: &DeviceConfig) -> Self {
+        Self::new(ReceiverSource::RtlTcp(hostname.to_string(), config.clone()))
+    }
+
+    pub fn from_noise() -> Self {
+        Self::new(ReceiverSource::Noise)
+    }
+
+    // the content of an IQ file, e.g. without a file system as in wasm
+    pub fn from_memory(data: Arc<[u8]>) -> Self {
+        Self::new(ReceiverSource::Memory(data))
+    }
+
+    // simulated satellites over noise, complex baseband samples at fs
+    pub fn from_sim(config: SimConfig) -> Self {
+        Self::new(ReceiverSource::Sim(config))
+    }
+
+    pub fn iq_file_type(mut self, iq_file_type: &IQFileType) -> Self {
+        self.iq_file_type = iq_file_type.clone();
+        self
+    }
+
+    pub fn loop_file(mut self, loop_file: bool) -> Self {
+        self.loop_file = loop_file;
+        self
+    }
+
+    pub fn fs(mut self, fs: f64) -> Self {
+        self.fs = fs;
+        self
+    }
 
-        self.channels
-            .par_iter_mut()
-            .for_each(|(_id, channel)| channel.process_samples(&iq_vec, ts_sec));
+    pub fn fs_internal(mut self, fs_internal: f64) -> Self {
+        self.fs_internal = fs_internal;
+        self
+    }
+
+    pub fn fi(mut self, fi: f64) -> Self {
+        self.fi = fi;
+        self
+    }
+
+    pub fn off_msec(mut self, off_msec: usize) -> Self {
+        self.off_msec = off_msec;
+        self
+    }
+
+    // a signal, or a list of them on one carrier, e.g. L1CA,E1B: a channel per
+    // satellite, on the signal of its constellation
+    pub fn signal(mut self, sig: &str) -> Self {
+        self.sig = sig.to_string();
+        self
+    }
+
+    // comma-separated satellites, as get_sat_list() takes them: all those of
+    // the signals when empty
+    pub fn sats(mut self, sats: &str) -> Self {
+        self.sats = sats.to_string();
+        self
+    }
+
+    // the memory codes and GLONASS frequency channels of the receiver, its
+    // simulated satellites included
+    pub fn code_tables(mut self, codes: CodeTables) -> Self {
+        self.codes = codes;
+        self
+    }
+
+    // set to stop the receiver, e.g. from a Ctrl-C handler
+    pub fn exit_req(mut self, exit_req: Arc<AtomicBool>) -> Self {
+        self.exit_req = exit_req;
+        self
+    }
+
+    // where the receiver publishes its state, e.g. for a UI
+    pub fn state(mut self, state: Arc<Mutex<GnssState>>) -> Self {
+        self.state = state;
+        self
+    }
 
-        self.compute_fix(ts_sec);
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        check_signals(&self.sig)?;
+        if !(self.fs > 0.0 && self.fs.is_finite()) {
+            return Err(format!("invalid sampling frequency: {}", self.fs).into());
+        }
+        if !self.fi.is_finite() || self.fi.abs() >= self.fs {
+            return Err(format!("invalid intermediate frequency: {}", self.fi).into());
+        }
+        let sigs = Code::get_sig_list(&self.sig);
+        let svs = get_sat_list(&self.sig, &self.sats)?;
+        if svs.is_empty() {
+            return Err(format!("no satellite of {} in {}", self.sig, self.sats).into());
+        }
+        for sig in &sigs {
+            let sig_svs: Vec<_> = (svs.iter().copied())
+                .filter(|sv| Code::get_constellation_signal(&sigs, sv.constellation) == Some(sig))
+                .collect();
+            self.validate_signal(sig, &sig_svs)?;
+        }
+        if let ReceiverSource::File(file) = &self.source {
+            check_recording_range(file, &self.iq_file_type, self.fs, self.off_msec, 0)?;
+        }
+        Ok(())
+    }
 
+    // one of the signals, for its satellites
+    fn validate_signal(&self, sig: &str, svs: &[SV]) -> Result<(), Box<dyn std::error::Error>> {
+        let code_len = Code::get_code_len(sig);
+        if Code::get_code_sp(sig, self.fs_internal) < code_len {
+            return Err(format!(
+                "internal sampling frequency {} below the chip rate of {sig}",
+                self.fs_internal
+            )
+            .into());
+        }
+        // the main lobe of the signal spans +/- the chip rate around fi: real
+        // samples only hold the positive half of the band
+        let chip_rate = code_len as f64 / Code::get_code_period(sig);
+        let real = self.iq_file_type.is_real();
+        let band_hz = if real { self.fs / 2.0 } else { self.fs };
+        if band_hz < chip_rate {
+            return Err(format!(
+                "sampling frequency {} below the Nyquist rate of {sig} for {} samples",
+                self.fs, self.iq_file_type
+            )
+            .into());
+        }
+        if self.fi.abs() + chip_rate > self.fs / 2.0 || (real && self.fi.abs() < chip_rate) {
+            tracing::warn!(
+                "fi={} with fs={}: part of the {sig} main lobe is aliased",
+                self.fi,
+                self.fs
+            );
+        }
+        if !svs.is_empty() && !(svs.iter()).any(|sv| self.codes.gen_code(sig, sv.prn).is_some()) {
+            return Err(format!(
+                "no {sig} code for the satellites: the memory codes come from --code-file"
+            )
+            .into());
+        }
+        // FDMA: the carriers of all the satellites within the band
+        let fs_min = f64::min(self.fs, self.fs_internal);
+        if let Some(sv) = (svs.iter())
+            .find(|sv| self.codes.get_carrier_offset(sig, sv.prn).abs() + chip_rate > fs_min / 2.0)
+        {
+            return Err(format!(
+                "{sv}: carrier {:+.0} KHz off {sig}, beyond a sampling frequency of {fs_min}",
+                self.codes.get_carrier_offset(sig, sv.prn) / 1000.0
+            )
+            .into());
+        }
         Ok(())
     }
 
-    pub fn run_loop(&mut self, num_msec: usize) {
-        let mut n = 0;
-        loop {
-            if self.process_step().is_err() {
-                break;
+    pub fn build(self) -> Result<Receiver, Box<dyn std::error::Error>> {
+        self.validate()?;
+        Receiver::new(self)
+    }
+}
+
+impl Receiver {
+    fn new(builder: ReceiverBuilder) -> Result<Self, Box<dyn std::error::Error>> {
+        let ReceiverBuilder {
+            source,
+            iq_file_type,
+            loop_file,
+            fs,
+            fs_internal,
+            fi,
+            off_msec,
+            sig,
+            sats,
+            codes,
+            exit_req,
+            state,
+        } = builder;
+        let (device, use_noise, hostname, file, feed) = match source {
+            ReceiverSource::File(file) => (None, false, String::new(), file, None),
+            ReceiverSource::Device(config) => {
+                (Some(config), false, String::new(), PathBuf::new(), None)
             }
-            if self.exit_req.load(Ordering::SeqCst) {
-                log::info!("exit requested");
-                break;
+            ReceiverSource::RtlTcp(hostname, config) => {
+                (Some(config), false, hostname, PathBuf::new(), None)
             }
-            n += 1;
-            if num_msec != 0 && n >= num_msec {
-                log::info!("{num_msec} msecs of iq-data processed");
-                break;
+            ReceiverSource::Noise => (None, true, String::new(), PathBuf::new(), None),
+            ReceiverSource::Memory(data) => {
+                let iq_feed: Box<dyn SampleSource> = Box::new(IQBuffer::new(data, &iq_file_type));
+                let feed = Some((iq_feed, iq_file_type.is_real()));
+                (None, false, String::new(), PathBuf::new(), feed)
+            }
+            ReceiverSource::Sim(config) => {
+                let iq_feed: Box<dyn SampleSource> =
+                    Box::new(SimSource::new(config, &sig, &codes, fs)?);
+                (
+                    None,
+                    false,
+                    String::new(),
+                    PathBuf::new(),
+                    Some((iq_feed, false)),
+                )
+            }
+        };
+        let mut fft_planner = FftPlanner::new();
+        let mut band = match feed {
+            Some((iq_feed, real_input)) => {
+                Band::with_feed(iq_feed, real_input, fs, fs_internal, fi, off_msec, &sig)
             }
+            None => Band::new(
+                device.as_ref(),
+                use_noise,
+                &hostname,
+                &file,
+                &iq_file_type,
+                loop_file,
+                fs,
+                fs_internal,
+                fi,
+                off_msec,
+                &sig,
+                exit_req.clone(),
+            )?,
+        };
+        band.add_channels(&sats, &codes, fs_internal, &state, &mut fft_planner)?;
+
+        Ok(Self {
+            bands: vec![band],
+            period_sp: (PERIOD_RCV * fs_internal) as usize,
+            clock: SampleClock::new(fs_internal),
+            solver: PositionSolver::new(state.clone()),
+            last_fix_sec: 0.0,
+            num_fix_svs: 0,
+            fix_requests: VecDeque::new(),
+            residuals: ResidualMonitor::new(),
+            residual_writer: None,
+            exit_req: exit_req.clone(),
+            pub_state: state,
+            fs_internal,
+            sats,
+            codes,
+            fft_planner,
+            playback_speed: None,
+            lagging: false,
+            start_mode: StartMode::Cold,
+            start_state: StartState::default(),
+            state_file: None,
+            start_unix: get_unix_time_now(),
+            thread_pool: None,
+            bench: None,
+            deterministic: false,
+            block_msec: 1,
+            pipelined: false,
+            pipeline: None,
+            sv_enabled: BTreeMap::new(),
+            sv_priority: BTreeMap::new(),
+            paused: false,
+            checkpoint_file: None,
+            checkpoint_sec: 0.0,
+            last_checkpoint_sec: 0.0,
+            resumed_msec: 0,
+            spectrum: None,
+            last_spectrum: Instant::now(),
+            tracking_svs: BTreeSet::new(),
+            outputs: Outputs::default(),
+            auto_ppm: false,
+            clock_offset_ppm: 0.0,
+            last_calibration_sec: 0.0,
+            interference: InterferenceDetector::new(),
+            last_interference_sec: 0.0,
+            timing: None,
+            last_timing_sec: 0.0,
+            visible_svs: None,
+            last_visibility_sec: f64::NEG_INFINITY,
+            last_aiding_sec: 0.0,
+            obs_writer: None,
+            callbacks: Callbacks::default(),
+            eph_svs: BTreeSet::new(),
+            last_measurement_sec: 0.0,
+            stats: RunStats::default(),
+            stats_file: None,
+            // no file system for the charts in wasm32
+            plot_period_sec: (!cfg!(target_arch = "wasm32")).then_some(PLOT_PERIOD_SEC),
+            plot_worker: None,
+            #[cfg(feature = "cuda")]
+            gpu_fft: None,
+        })
+    }
+
+    // Secondary front-end, e.g. a second rtl-sdr tuned to another band. Its
+    // samples are processed in lockstep with the primary front-end and the
+    // tracked satellites contribute to the same position fix.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_band(
+        &mut self,
+        device: Option<&DeviceConfig>,
+        hostname: &str,
+        file: &Path,
+        iq_file_type: &IQFileType,
+        fs: f64,
+        fi: f64,
+        off_msec: usize,
+        sig: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        check_signals(sig)?;
+        tracing::info!(
+            "band #{}: {sig} at {:.1} MHz, fs={:.1} KHz",
+            self.bands.len(),
+            Code::get_code_freq(Code::get_sig_list(sig)[0]) / 1e6,
+            fs / 1000.0
+        );
+        let mut band = Band::new(
+            device,
+            false,
+            hostname,
+            file,
+            iq_file_type,
+            false,
+            fs,
+            self.fs_internal,
+            fi,
+            off_msec,
+            sig,
+            self.exit_req.clone(),
+        )?;
+        band.add_channels(
+            &self.sats,
+            &self.codes,
+            self.fs_internal,
+            &self.pub_state,
+            &mut self.fft_planner,
+        )?;
+        self.bands.push(band);
+        Ok(())
+    }
+
+    // Another antenna of the primary front-end, sampled coherently with it
+    // (common clock) at the same frequency. The channels track with the
+    // correlations of all the antennas combined; the first antenna is the
+    // reference, the one acquisition runs on.
+    pub fn add_antenna(
+        &mut self,
+        device: Option<&DeviceConfig>,
+        hostname: &str,
+        file: &Path,
+        iq_file_type: &IQFileType,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let primary = &self.bands[0];
+        let off_msec = (primary.off_samples as f64 / primary.fs / PERIOD_RCV) as usize;
+        let mut antenna = Band::new(
+            device,
+            false,
+            hostname,
+            file,
+            iq_file_type,
+            false,
+            primary.fs,
+            self.fs_internal,
+            primary.fi,
+            off_msec,
+            &primary.sig,
+            self.exit_req.clone(),
+        )?;
+        antenna.off_samples = primary.off_samples;
+        let primary = &mut self.bands[0];
+        primary.antennas.push(antenna);
+        tracing::info!("{}: antenna #{} added", primary.sig, primary.antennas.len());
+        Ok(())
+    }
+
+    // only the primary front-end is captured
+    pub fn set_pre_trigger_capture(&mut self, duration_sec: f64) {
+        let band = &mut self.bands[0];
+        band.ring_buf = Some(IQRingBuffer::new(duration_sec, band.fs, band.fi, &band.sig));
+    }
+
+    // pace playback at speed times the capture's real-time rate
+    pub fn set_playback_speed(&mut self, speed: f64) {
+        tracing::info!("playback paced at {speed}x real-time");
+        self.playback_speed = Some(speed);
+    }
+
+    // Frequency error of the front-end oscillator that wasn't corrected at
+    // tuning time: the signals show up shifted by -ppm * fc.
+    pub fn set_clock_offset_ppm(&mut self, ppm: f64) {
+        self.clock_offset_ppm = ppm;
+        for band in &mut self.bands {
+            let doppler_center_hz = -ppm * 1e-6 * Code::get_code_freq(&band.sig);
+            for channel in band.channels.values_mut() {
+                channel.set_doppler_center(doppler_center_hz);
+            }
+            tracing::info!(
+                "{}: clock offset {ppm} ppm: doppler search centered on {doppler_center_hz:.0} Hz",
+                band.sig
+            );
+        }
+    }
+
+    // the doppler search follows the oscillator error estimated while running
+    pub fn set_auto_ppm(&mut self) {
+        self.auto_ppm = true;
+    }
+
+    // CW interference notched out of every front-end, antennas included,
+    // before the channels correlate
+    pub fn set_notch_filter(&mut self) {
+        for band in &mut self.bands {
+            band.notch = Some(NotchFilter::new(self.fs_internal));
+            for antenna in &mut band.antennas {
+                antenna.notch = Some(NotchFilter::new(self.fs_internal));
+            }
+        }
+    }
+
+    // A static antenna as a time reference: its position surveyed in over
+    // survey_sec unless given, then the local clock, start_unix at the first
+    // sample, measured against gps time.
+    pub fn set_timing(&mut self, survey_sec: f64, position: Option<[f64; 3]>, start_unix: f64) {
+        if position.is_none() {
+            tracing::info!("timing mode: surveying the position in for {survey_sec:.0} sec");
+        }
+        self.timing = Some(TimingSolver::new(survey_sec, position, start_unix));
+    }
+
+    pub fn set_disciplined_clock(&mut self) {
+        for band in &mut self.bands {
+            band.channels
+                .values_mut()
+                .for_each(|channel| channel.set_disciplined_clock());
+        }
+        tracing::info!("disciplined clock: narrowing doppler search and tracking loops");
+    }
+
+    // The acquisition integrates periods code periods coherently, for weak
+    // signals such as L5: 10 or 20 msec instead of 1, and as many times more
+    // doppler bins. The periods span whole secondary codes, part of the
+    // replica.
+    pub fn set_acq_coherent(&mut self, periods: usize) -> Result<(), Box<dyn std::error::Error>> {
+        for band in &mut self.bands {
+            for channel in band.channels.values_mut() {
+                let len = Code::get_secondary_code(&channel.sig, channel.sv.prn).len();
+                if periods == 0 || (len > 0 && periods > 1 && periods % len != 0) {
+                    return Err(format!(
+                        "{}: {periods} code periods of {} aren't whole secondary codes of {len}",
+                        channel.sv, channel.sig
+                    )
+                    .into());
+                }
+                channel.set_acq_coherent_periods(periods, &mut self.fft_planner);
+            }
+        }
+        tracing::info!("acquisition: {periods} code periods integrated coherently");
+        Ok(())
+    }
+
+    // Seeds the channels with what a previous run saved in state_file: doppler
+    // predicted from the almanac (warm) or remembered, plus the ephemeris (hot).
+    // The mode degrades when the state lacks what it needs. now_unix is the
+    // rough time of the first sample.
+    pub fn set_start(
+        &mut self,
+        mode: StartMode,
+        state_file: Option<&Path>,
+        now_unix: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let state = match state_file {
+            Some(path) => StartState::read(path)?.unwrap_or_else(|| {
+                tracing::info!("{}: no saved state yet", path.display());
+                StartState {
+                    saved_at: now_unix,
+                    ..StartState::default()
+                }
+            }),
+            None => StartState::default(),
+        };
+        let mut mode = mode;
+        if mode == StartMode::Hot && !state.has_ephemeris(now_unix) {
+            tracing::warn!("{}", "hot start: no recent ephemeris, trying warm".red());
+            mode = StartMode::Warm;
+        }
+        if mode == StartMode::Warm && !state.has_almanac(now_unix) {
+            tracing::warn!("{}", "warm start: no almanac or position, cold start".red());
+            mode = StartMode::Cold;
+        }
+        self.apply_start(mode, state, now_unix);
+        self.state_file = state_file.map(Path::to_path_buf);
+        Ok(())
+    }
+
+    fn apply_start(&mut self, mode: StartMode, state: StartState, now_unix: f64) {
+        let mut num_hints = 0;
+        let mut num_ephs = 0;
+        let gps_sec = get_gps_sec(now_unix);
+        if mode != StartMode::Cold {
+            let mut pub_state = self.pub_state.lock().unwrap();
+            for alm in state
+                .almanac
+                .iter()
+                .filter(|alm| alm.sat >= 1 && alm.sat <= 32)
+            {
+                pub_state.almanac[alm.sat as usize - 1] = alm.clone();
+            }
+        }
+        for band in &mut self.bands {
+            let fc = Code::get_code_freq(&band.sig);
+            for channel in band.channels.values_mut() {
+                // the almanac is that of gps, the satellites saved those
+                // of the LNAV: gps and QZSS
+                if !matches!(
+                    channel.sv.constellation,
+                    Constellation::GPS | Constellation::QZSS
+                ) {
+                    continue;
+                }
+                let prn = channel.sv.prn;
+                if let Some((doppler_hz, spread_hz)) =
+                    state.get_doppler_hint(mode, prn, now_unix, fc)
+                {
+                    tracing::info!(
+                        "{}: doppler hint {doppler_hz:.0} +/- {spread_hz:.0} Hz",
+                        channel.sv
+                    );
+                    channel.set_doppler_hint(doppler_hz, spread_hz);
+                    num_hints += 1;
+                }
+                if mode == StartMode::Hot
+                    && let Some(sat) = (state.get_sat(prn)).filter(|sat| sat.has_ephemeris(gps_sec))
+                {
+                    channel.nav_load_subframes(&sat.subframes);
+                    num_ephs += 1;
+                }
+            }
+        }
+        tracing::info!(
+            "{} start: {num_hints} doppler hints, {num_ephs} ephemeris, state age {:.0} sec",
+            mode.to_string().green(),
+            state.get_age_sec(now_unix)
+        );
+
+        self.pub_state.lock().unwrap().start_mode = mode;
+        self.start_mode = mode;
+        self.start_state = state;
+        self.start_unix = now_unix;
+    }
+
+    // Recordings only: every period_sec of signal, where the run is and what
+    // it knows is saved to path. When path holds the checkpoint of a run on
+    // the same recording, this one resumes from there instead.
+    pub fn set_checkpoint(
+        &mut self,
+        path: &Path,
+        period_sec: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut totals = vec![];
+        for band in &self.bands {
+            let total = band.iq_feed.get_num_samples_total();
+            totals.push(total.ok_or("checkpoints need a recording")?);
+        }
+        self.checkpoint_file = Some(path.to_path_buf());
+        self.checkpoint_sec = f64::max(CHECKPOINT_MIN_SEC, period_sec);
+
+        let Some(checkpoint) = Checkpoint::read(path)? else {
+            return Ok(());
+        };
+        let same_input = checkpoint.bands.len() == self.bands.len()
+            && (checkpoint.bands.iter().zip(&self.bands).zip(&totals)).all(
+                |((c, band), &total)| {
+                    c.sig == band.sigs.join(",")
+                        && c.num_samples_total == total
+                        && c.files == band.get_recording_files()
+                },
+            );
+        if !same_input {
+            tracing::warn!(
+                "{}: checkpoint of another recording, starting over",
+                path.display()
+            );
+            return Ok(());
+        }
+
+        for (band, c) in self.bands.iter_mut().zip(&checkpoint.bands) {
+            band.off_samples = c.off_samples;
+            for antenna in &mut band.antennas {
+                antenna.off_samples = c.off_samples;
+            }
+        }
+        self.clock.num_samples = checkpoint.clock_samples;
+        self.last_checkpoint_sec = self.clock.get_sec();
+        self.resumed_msec = checkpoint.processed_msec;
+        tracing::info!(
+            "{}: resuming at t={:.3} after {} msec",
+            path.display(),
+            self.clock.get_sec(),
+            checkpoint.processed_msec
+        );
+        // the state is as of the checkpoint: whatever it has is still fresh
+        self.apply_start(StartMode::Hot, checkpoint.state, checkpoint.saved_at);
+        self.start_unix = checkpoint.saved_at - self.clock.get_sec();
+        Ok(())
+    }
+
+    fn save_checkpoint(&mut self, processed_msec: usize) {
+        let Some(path) = self.checkpoint_file.clone() else {
+            return;
+        };
+        self.update_start_state();
+        let bands = (self.bands.iter())
+            .map(|band| BandCheckpoint {
+                sig: band.sigs.join(","),
+                off_samples: band.off_samples,
+                num_samples_total: band.iq_feed.get_num_samples_total().unwrap_or(0),
+                files: band.get_recording_files(),
+            })
+            .collect();
+        let checkpoint = Checkpoint {
+            saved_at: self.start_state.saved_at,
+            clock_samples: self.clock.num_samples,
+            processed_msec,
+            bands,
+            state: self.start_state.clone(),
+        };
+        match checkpoint.write(&path) {
+            Ok(()) => tracing::info!("{}: checkpoint saved", path.display()),
+            Err(e) => tracing::warn!("{}: failed to save checkpoint: {e}", path.display()),
+        }
+        self.last_checkpoint_sec = self.clock.get_sec();
+    }
+
+    fn check_checkpoint(&mut self, processed_msec: usize) {
+        if self.checkpoint_file.is_some()
+            && self.clock.get_sec() - self.last_checkpoint_sec >= self.checkpoint_sec
+        {
+            self.save_checkpoint(processed_msec);
+        }
+    }
+
+    // A run that went through, to the end of the recording or of the msecs
+    // asked for, has nothing to resume. Any other stop, e.g. a read error,
+    // leaves a checkpoint to resume from.
+    fn end_checkpoint(&mut self, processed_msec: usize, completed: bool) {
+        let Some(path) = self.checkpoint_file.clone() else {
+            return;
+        };
+        if !completed {
+            self.save_checkpoint(processed_msec);
+        } else if let Err(e) = Checkpoint::remove(&path) {
+            tracing::warn!("{}: failed to remove checkpoint: {e}", path.display());
+        }
+    }
+
+    // less than a block left in one of the recordings
+    fn is_at_end_of_file(&self) -> bool {
+        let block_sec = (self.block_msec + 1) as f64 * PERIOD_RCV;
+        self.bands.iter().any(|band| {
+            band.iq_feed.get_num_samples_total().is_some_and(|total| {
+                total.saturating_sub(band.off_samples) as f64 / band.fs < block_sec
+            })
+        })
+    }
+
+    // Snapshot positioning: acquires the satellites on num_msec of samples and
+    // solves for a coarse position with the ephemeris, position and rough time
+    // loaded by set_start(). The saved state is left as is.
+    pub fn run_snapshot(
+        &mut self,
+        num_msec: usize,
+    ) -> Result<SnapshotFix, Box<dyn std::error::Error>> {
+        let rough_pos = self
+            .start_state
+            .position
+            .ok_or("snapshot: no saved position")?;
+        self.state_file = None;
+        self.run_loop(num_msec);
+
+        let meas: Vec<_> = self.bands[0]
+            .channels
+            .values()
+            .filter(|channel| channel.nav.eph.week != 0 && channel.nav.eph.a > 0.0)
+            .filter_map(|channel| {
+                let (code_off_sec, cn0) = channel.get_code_off_sec()?;
+                Some(SnapshotMeas::new(
+                    channel.sv,
+                    &channel.nav.eph,
+                    code_off_sec,
+                    cn0,
+                ))
+            })
+            .collect();
+        let svs: Vec<_> = meas.iter().map(|m| m.sv.to_string()).collect();
+        tracing::info!(
+            "snapshot: {} SVs with ephemeris: {}",
+            meas.len(),
+            svs.join(" ")
+        );
+
+        let ts_sec = self.clock.get_sec_before(self.period_sp);
+        let fix = solve_coarse_time(&meas, rough_pos, get_gps_sec(self.start_unix + ts_sec))?;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.latitude = fix.lat;
+        pub_state.longitude = fix.lon;
+        pub_state.height = fix.height;
+        Ok(fix)
+    }
+
+    // Satellites tracked after num_msec of signal, the strongest first.
+    pub fn run_acquire(&mut self, num_msec: usize) -> Vec<AcquiredSv> {
+        self.state_file = None;
+        self.run_loop(num_msec);
+
+        let mut svs: Vec<_> = self.bands[0]
+            .channels
+            .values()
+            .filter_map(|channel| {
+                let (code_off_sec, cn0) = channel.get_code_off_sec()?;
+                Some(AcquiredSv {
+                    sv: channel.sv,
+                    cn0,
+                    doppler_hz: channel.get_measured_doppler_hz()?,
+                    code_off_sec,
+                })
+            })
+            .collect();
+        svs.sort_by(|a, b| b.cn0.total_cmp(&a.cn0));
+        svs
+    }
+
+    // Acquisition only, over num_msec of signal at once: no tracking, a quick
+    // look at what a recording holds. The detected satellites, strongest first.
+    pub fn run_scan(
+        &mut self,
+        num_msec: usize,
+    ) -> Result<Vec<AcquiredSv>, Box<dyn std::error::Error>> {
+        let band = &mut self.bands[0];
+        let iq_vec = band.get_resampled_iq_data(num_msec * self.period_sp)?;
+        let scan = || {
+            band.channels
+                .par_iter_mut()
+                .filter_map(|(&sv, channel)| {
+                    // nothing tracked: no charts
+                    channel.take_final_plot();
+                    let (doppler_hz, code_off_sec, cn0) = channel.scan(&iq_vec)?;
+                    Some(AcquiredSv {
+                        sv,
+                        cn0,
+                        doppler_hz,
+                        code_off_sec,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut svs = install(self.thread_pool.as_deref(), scan);
+        svs.sort_by(|a, b| b.cn0.total_cmp(&a.cn0));
+        Ok(svs)
+    }
+
+    // Raw IQ of the primary front-end to path, for duration_sec or until
+    // stopped if 0: no processing.
+    pub fn run_record(
+        &mut self,
+        path: &Path,
+        file_type: &IQFileType,
+        duration_sec: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let band = &mut self.bands[0];
+        let mut recorder = IQRecorder::create(path, file_type, band.fs, band.fi, &band.sig)?;
+        let block_size = (RECORD_BLOCK_SEC * band.fs) as usize;
+        let num_samples = (duration_sec * band.fs) as usize;
+        let mut n = 0;
+        while duration_sec == 0.0 || n < num_samples {
+            if self.exit_req.load(Ordering::SeqCst) {
+                break;
+            }
+            let len = if duration_sec == 0.0 {
+                block_size
+            } else {
+                usize::min(block_size, num_samples - n)
+            };
+            match band.get_iq_data(len) {
+                Ok(iq_vec) => recorder.push(&iq_vec),
+                Err(e) => {
+                    tracing::warn!("recording: stopped: {e}");
+                    break;
+                }
+            }
+            n += len;
+        }
+        println!(
+            "recorded {:.1} sec to {}",
+            recorder.get_duration_sec(),
+            path.display().to_string().green()
+        );
+        recorder.finish();
+        Ok(())
+    }
+
+    // fixes, measurements and channel events to the embedding application
+    pub fn set_callbacks(&mut self, callbacks: Callbacks) {
+        self.callbacks = callbacks;
+    }
+
+    pub fn take_callbacks(&mut self) -> Callbacks {
+        std::mem::take(&mut self.callbacks)
+    }
+
+    // Every fix attempt goes to path as json lines, for the pvt subcommand.
+    pub fn set_obs_file(&mut self, path: &Path) -> std::io::Result<()> {
+        self.obs_writer = Some(ObsWriter::create(path)?);
+        tracing::info!("observables: writing to {}", path.display());
+        Ok(())
+    }
+
+    pub fn set_residuals_file(&mut self, path: &Path) -> std::io::Result<()> {
+        self.residual_writer = Some(ResidualWriter::create(path)?);
+        tracing::info!("residuals: writing to {}", path.display());
+        Ok(())
+    }
+
+    fn write_observables(&mut self, ts_sec: f64) {
+        let Some(writer) = &mut self.obs_writer else {
+            return;
+        };
+        let sats = self
+            .bands
+            .iter()
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .filter(|&ch| ch.is_ephemeris_complete())
+            .map(|ch| {
+                let mut sat = ObsSat::new(&ch.nav.eph, &ch.nav.subframes);
+                sat.quality = ch.get_quality();
+                (ch.sv, sat)
+            })
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect();
+        if let Err(e) = writer.write(&ObsEpoch { ts_sec, sats }) {
+            tracing::warn!("observables: failed to write: {e}");
+            self.obs_writer = None;
+        }
+    }
+
+    // what the next run needs for a warm or hot start
+    fn update_start_state(&mut self) {
+        let state = &mut self.start_state;
+        state.saved_at = self.start_unix + self.clock.get_sec();
+
+        let almanac: Vec<_> = (self.pub_state.lock().unwrap().almanac.iter())
+            .filter(|alm| alm.sat != 0)
+            .cloned()
+            .collect();
+        if !almanac.is_empty() {
+            state.almanac = almanac;
+        }
+
+        for channel in self.bands[0].channels.values() {
+            let doppler_hz = channel.get_doppler_hz();
+            let subframes = &channel.nav.subframes;
+            if !matches!(
+                channel.sv.constellation,
+                Constellation::GPS | Constellation::QZSS
+            ) || (doppler_hz.is_none() && subframes.iter().any(|sf| sf.is_empty()))
+            {
+                continue;
+            }
+            let sat = SatState {
+                prn: channel.sv.prn,
+                doppler_hz,
+                subframes: if subframes.iter().all(|sf| !sf.is_empty()) {
+                    subframes.to_vec()
+                } else {
+                    vec![]
+                },
+            };
+            state.satellites.retain(|s| s.prn != sat.prn);
+            state.satellites.push(sat);
+        }
+        state.satellites.sort_by_key(|sat| sat.prn);
+    }
+
+    fn save_start_state(&mut self) {
+        let Some(path) = self.state_file.clone() else {
+            return;
+        };
+        self.update_start_state();
+        match self.start_state.write(&path) {
+            Ok(()) => tracing::info!("{}: start state saved", path.display()),
+            Err(e) => tracing::warn!("{}: failed to save start state: {e}", path.display()),
+        }
+    }
+
+    // runs the channels, and whatever else the receiver does in parallel, on
+    // a dedicated pool, optionally pinned to cpus
+    pub fn set_thread_pool(
+        &mut self,
+        num_threads: usize,
+        cpus: &[usize],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.thread_pool = Some(Arc::new(build_thread_pool(num_threads, cpus)?));
+        Ok(())
+    }
+
+    // Same input and config, bit-identical output: the channels are processed
+    // one after the other in satellite order, so that their updates of the
+    // shared state always happen in the same order. Only for the inputs that
+    // are the same on every run: no device nor network stream on any band or
+    // antenna.
+    pub fn set_deterministic(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let is_live = (self.bands.iter())
+            .flat_map(|band| std::iter::once(band).chain(&band.antennas))
+            .any(|band| band.iq_feed.is_live());
+        if is_live {
+            return Err("--deterministic: not with a device or a network stream".into());
+        }
+        tracing::info!("deterministic: channels processed sequentially");
+        self.deterministic = true;
+        Ok(())
+    }
+
+    // Reads and hands out the samples n code periods at a time instead of one:
+    // fewer, larger reads and one parallel dispatch per block. The channels
+    // still step through each period.
+    pub fn set_block_msec(&mut self, block_msec: usize) {
+        tracing::info!("processing blocks of {block_msec} msec");
+        self.block_msec = usize::max(1, block_msec);
+    }
+
+    // Acquisition, solving and plotting run as stages on their own threads,
+    // fed by the sample path without waiting for them: it only does the
+    // reading and the tracking, which have to keep up with the signal.
+    pub fn set_pipelined(&mut self) {
+        self.pipelined = true;
+    }
+
+    fn start_pipeline(&mut self) {
+        let is_recording = self.bands[0].iq_feed.get_num_samples_total().is_some();
+        let pipeline = Pipeline::new(
+            self.pub_state.clone(),
+            self.thread_pool.clone(),
+            is_recording,
+        );
+        self.pipeline = Some(pipeline);
+    }
+
+    fn stop_pipeline(&mut self) {
+        let Some(mut pipeline) = self.pipeline.take() else {
+            return;
+        };
+        for (idx, channel) in pipeline.stop() {
+            self.bands[idx].channels.insert(channel.sv, channel);
+        }
+        pipeline
+            .take_fixes()
+            .into_iter()
+            .for_each(|pos| self.handle_fix(pos));
+    }
+
+    pub fn set_plot_period(&mut self, period_sec: Option<f64>) {
+        self.plot_period_sec = period_sec;
+    }
+
+    // The channels hand their charts to a worker thread while running, none
+    // when the UI or the dashboard draws them.
+    fn start_plot_worker(&mut self) {
+        let period_sec = self.plot_period_sec;
+        (self.bands.iter_mut())
+            .flat_map(|band| band.channels.values_mut())
+            .for_each(|channel| channel.set_plot_period(period_sec));
+        if period_sec.is_none() || self.pub_state.lock().unwrap().live_plots {
+            return;
+        }
+        match PlotWorker::start() {
+            Ok(worker) => {
+                (self.bands.iter_mut())
+                    .flat_map(|band| band.channels.values_mut())
+                    .for_each(|channel| channel.set_plot_sender(worker.get_sender()));
+                self.plot_worker = Some(worker);
+            }
+            Err(e) => tracing::warn!("plots: failed to start the worker, drawn inline: {e}"),
+        }
+    }
+
+    fn stop_plot_worker(&mut self) {
+        (self.bands.iter_mut())
+            .flat_map(|band| band.channels.values_mut())
+            .for_each(|channel| channel.set_plot_sender(None));
+        // dropping the worker waits for the charts queued
+        self.plot_worker = None;
+    }
+
+    // The channels move to the acquisition stage when they start searching
+    // and come back once locked: tracking picks up from the current block,
+    // the code phase found still holds a few periods later. The disabled ones
+    // and those not due, see may_acquire(), stay put.
+    fn exchange_acquiring_channels(&mut self, iq_vecs: &[Arc<[IQSample]>], ts_sec: f64) {
+        let Some(pipeline) = &mut self.pipeline else {
+            return;
+        };
+        for (idx, channel) in pipeline.take_acquired() {
+            self.bands[idx].channels.insert(channel.sv, channel);
+        }
+        for (idx, band) in self.bands.iter_mut().enumerate() {
+            let others_acquiring = pipeline.is_band_acquiring(idx)
+                || (band.channels.values()).any(|channel| {
+                    channel.is_acquiring() && channel.get_priority() != SvPriority::Low
+                });
+            let svs: Vec<_> = (band.channels.iter())
+                .filter(|(_sv, channel)| {
+                    channel.is_acquiring()
+                        && channel.is_enabled()
+                        && may_acquire(channel, self.lagging, others_acquiring)
+                })
+                .map(|(sv, _channel)| *sv)
+                .collect();
+            for sv in svs {
+                pipeline.hand_over(idx, band.channels.remove(&sv).unwrap());
+            }
+        }
+        pipeline.send_block(iq_vecs, ts_sec);
+    }
+
+    // per-stage timing of the run, reported when it ends
+    pub fn enable_bench(&mut self) {
+        self.bench = Some(StageTimes::default());
+        self.bands
+            .iter_mut()
+            .flat_map(|band| band.channels.values_mut())
+            .for_each(|channel| channel.enable_bench());
+    }
+
+    // The last charts of the channels and those of the whole run, drawn in
+    // parallel on their own thread while the run wraps up rather than one by
+    // one as the channels drop. None when there is nothing to draw.
+    fn finish_plots(&mut self) -> Option<JoinHandle<()>> {
+        let jobs: Vec<_> = (self.bands.iter_mut())
+            .flat_map(|band| band.channels.values_mut())
+            .filter_map(|channel| channel.take_final_plot())
+            .collect();
+        let overview = self.get_overview_job();
+        if jobs.is_empty() && overview.is_none() {
+            return None;
+        }
+        let pool = self.thread_pool.clone();
+        Some(std::thread::spawn(move || {
+            install(pool.as_deref(), || {
+                jobs.par_iter().for_each(|job| job.draw())
+            });
+            if let Some(overview) = overview {
+                overview.draw();
+            }
+        }))
+    }
+
+    // none when the UI or the dashboard draws the charts
+    fn get_overview_job(&self) -> Option<OverviewJob> {
+        let pub_state = self.pub_state.lock().unwrap();
+        if pub_state.live_plots || self.plot_period_sec.is_none() {
+            return None;
+        }
+        let acquisitions = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .map(|ch| {
+                let (num_attempts, num_locks) = ch.get_acquisition_counts();
+                (ch.sv, num_attempts, num_locks)
+            })
+            .filter(|&(_, num_attempts, _)| num_attempts > 0)
+            .collect();
+        Some(OverviewJob {
+            cn0: self.stats.get_cn0_history(),
+            acquisitions,
+            fixes: pub_state.fixes.clone(),
+        })
+    }
+
+    // what the sinks still buffer goes out before the receiver stops
+    fn finish_outputs(&mut self) {
+        for band in &mut self.bands {
+            if let Some(recorder) = band.recorder.take() {
+                recorder.finish();
+            }
+        }
+        self.outputs.flush();
+    }
+
+    pub fn set_stats_file(&mut self, path: &Path) {
+        self.stats_file = Some(path.to_path_buf());
+    }
+
+    fn get_summary(&self, wall_sec: f64, interrupted: bool) -> RunSummary {
+        let channels = || self.bands.iter().flat_map(|band| band.channels.values());
+        // of the primary front-end per satellite, all of them in the totals
+        let nav: BTreeMap<_, _> = (self.bands[0].channels.values())
+            .map(|channel| (channel.sv, channel.nav.get_status()))
+            .collect();
+        let pub_state = self.pub_state.lock().unwrap();
+        let ttff_sec = pub_state.ttff.get(Milestone::Fix);
+        RunSummary {
+            interrupted,
+            signal_sec: self.clock.get_sec(),
+            wall_sec,
+            num_subframes: channels().map(|ch| ch.nav.get_status().num_subframes).sum(),
+            num_parity_errors: (channels())
+                .map(|ch| ch.nav.get_status().num_parity_errors)
+                .sum(),
+            svs: self.stats.get_sv_summaries(&nav),
+            interference: self.interference.get_events().to_vec(),
+            solution: (self.stats).get_solution_summary(&pub_state.fixes, ttff_sec),
+        }
+    }
+
+    fn report_summary(&self, wall_sec: f64, interrupted: bool) -> RunSummary {
+        let summary = self.get_summary(wall_sec, interrupted);
+        if let Some(path) = &self.stats_file
+            && let Err(e) = summary.write(path)
+        {
+            tracing::warn!("{}: failed to write the statistics: {e}", path.display());
+        }
+        summary
+    }
+
+    fn report_bench(&self, wall_sec: f64) {
+        let Some(times) = &self.bench else {
+            return;
+        };
+        let channel_times: Vec<_> = self
+            .bands
+            .iter()
+            .flat_map(|band| band.channels.values())
+            .filter_map(|channel| Some((channel.sv, channel.get_bench()?)))
+            .collect();
+        print_bench_report(self.clock.get_sec(), wall_sec, times, &channel_times);
+    }
+
+    pub fn enable_gpu_fft(&mut self) {
+        #[cfg(feature = "cuda")]
+        match GpuFft::new() {
+            Ok(gpu_fft) => self.gpu_fft = Some(gpu_fft),
+            Err(e) => tracing::warn!("Failed to init gpu fft, using the cpu: {e}"),
+        }
+        #[cfg(not(feature = "cuda"))]
+        tracing::warn!("gpu fft support not enabled: rebuild with --features cuda");
+    }
+
+    pub fn trigger_capture(&mut self, reason: &str) {
+        match &self.bands[0].ring_buf {
+            Some(ring_buf) => {
+                ring_buf.dump(reason);
+            }
+            None => tracing::warn!("pre-trigger capture not enabled: ignoring {reason}"),
+        }
+    }
+
+    fn check_capture_req(&mut self) {
+        let req = std::mem::take(&mut self.pub_state.lock().unwrap().capture_req);
+        if req {
+            self.trigger_capture("user");
+        }
+    }
+
+    // NMEA, gpsd, websocket servers and csv file the fixes are sent to
+    pub fn set_outputs(&mut self, config: &OutputConfig) {
+        self.outputs.configure(config);
+        self.pub_state.lock().unwrap().outputs = self.outputs.get_status();
+    }
+
+    fn check_output_req(&mut self) {
+        let req = self.pub_state.lock().unwrap().output_req.take();
+        if let Some(config) = req {
+            self.set_outputs(&config);
+        } else if self.outputs.check_clients() {
+            self.pub_state.lock().unwrap().outputs = self.outputs.get_status();
+        }
+    }
+
+    // raw IQ of the primary front-end to disk, until stopped
+    pub fn set_recording(&mut self, on: bool) {
+        let band = &mut self.bands[0];
+        if on == band.recorder.is_some() {
+            return;
+        }
+        if !on {
+            if let Some(recorder) = band.recorder.take() {
+                let _ = recorder.stop();
+            }
+        } else {
+            match IQRecorder::start(band.fs, band.fi, &band.sig) {
+                Ok(recorder) => band.recorder = Some(recorder),
+                Err(e) => tracing::warn!("recording: failed to start: {e}"),
+            }
+        }
+        let status = band.recorder.as_ref().map(|recorder| recorder.get_status());
+        self.pub_state.lock().unwrap().recording = status;
+    }
+
+    // the status is published while recording only
+    fn check_record_req(&mut self) {
+        let mut pub_state = self.pub_state.lock().unwrap();
+        match pub_state.record_req.take() {
+            Some(on) => {
+                drop(pub_state);
+                self.set_recording(on);
+            }
+            None => {
+                if let Some(recorder) = &self.bands[0].recorder
+                    && let Some(status) = &mut pub_state.recording
+                {
+                    recorder.update_status(status);
+                }
+            }
+        }
+    }
+
+    // Processing stops until resumed: a real-time source drops what it can't
+    // queue meanwhile, a recording picks up where it was.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+        tracing::info!(
+            "t={:.3} -- {}",
+            self.clock.get_sec(),
+            if paused { "paused" } else { "resumed" }
+        );
+        self.paused = paused;
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.paused = paused;
+        (pub_state.update_func.func)();
+    }
+
+    // Moves all the bands to sec into their recording. The receiver time
+    // follows the recording's, as across the samples a source drops: forward
+    // or back by as much.
+    pub fn seek(&mut self, sec: f64) -> Result<(), Box<dyn std::error::Error>> {
+        let get_pos_sec = |band: &Band| band.off_samples as f64 / band.fs;
+        let from_sec = get_pos_sec(&self.bands[0]);
+        for band in &mut self.bands {
+            band.seek(sec)?;
+        }
+        let delta_sec = get_pos_sec(&self.bands[0]) - from_sec;
+        tracing::info!(
+            "t={:.3} -- seek to {sec:.3} sec ({delta_sec:+.3} sec)",
+            self.clock.get_sec()
+        );
+        self.clock.shift_sec(delta_sec);
+        if delta_sec < 0.0 {
+            self.rewind_timers();
+        }
+        Ok(())
+    }
+
+    // back in time: the periodic checks start over from now
+    fn rewind_timers(&mut self) {
+        let now_sec = self.clock.get_sec();
+        for last_sec in [
+            &mut self.last_fix_sec,
+            &mut self.last_checkpoint_sec,
+            &mut self.last_calibration_sec,
+            &mut self.last_interference_sec,
+            &mut self.last_timing_sec,
+            &mut self.last_visibility_sec,
+            &mut self.last_aiding_sec,
+            &mut self.last_measurement_sec,
+        ] {
+            *last_sec = last_sec.min(now_sec);
+        }
+    }
+
+    // true when the playback position changed
+    fn check_playback_req(&mut self) -> bool {
+        let (pause_req, seek_req) = {
+            let mut pub_state = self.pub_state.lock().unwrap();
+            (pub_state.pause_req.take(), pub_state.seek_req.take())
+        };
+        if let Some(paused) = pause_req {
+            self.set_paused(paused);
+        }
+        let Some(sec) = seek_req else {
+            return false;
+        };
+        match self.seek(sec) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("{e}");
+                false
+            }
+        }
+    }
+
+    // Satellites can be turned off and on, or searched ahead of the others,
+    // while running. A channel in the acquisition stage of the pipeline gets
+    // the change when it's back.
+    pub fn set_sv_enabled(&mut self, sv: SV, enabled: bool) {
+        self.sv_enabled.insert(sv, enabled);
+    }
+
+    pub fn set_sv_priority(&mut self, sv: SV, priority: SvPriority) {
+        self.sv_priority.insert(sv, priority);
+    }
+
+    fn check_sv_requests(&mut self) {
+        let reqs = std::mem::take(&mut self.pub_state.lock().unwrap().sv_requests);
+        for req in reqs {
+            match req {
+                SvRequest::Enable(sv, enabled) => self.set_sv_enabled(sv, enabled),
+                SvRequest::Priority(sv, priority) => self.set_sv_priority(sv, priority),
+            }
+        }
+        if self.sv_enabled.is_empty() && self.sv_priority.is_empty() {
+            return;
+        }
+        for channel in self
+            .bands
+            .iter_mut()
+            .flat_map(|band| band.channels.values_mut())
+        {
+            if let Some(&enabled) = self.sv_enabled.get(&channel.sv) {
+                channel.set_enabled(enabled);
+            }
+            if let Some(&priority) = self.sv_priority.get(&channel.sv) {
+                channel.set_priority(priority);
+            }
+        }
+    }
+
+    // the 2-code buffers of each band, one per antenna
+    fn fetch_samples_msec(&mut self) -> Result<Vec<Blocks>, Box<dyn std::error::Error>> {
+        let num_samples = if self.bands[0].cached_iq_vec.is_empty() {
+            (self.block_msec + 1) * self.period_sp
+        } else {
+            self.block_msec * self.period_sp
+        };
+
+        let mut iq_vecs = Vec::with_capacity(self.bands.len());
+        for band in &mut self.bands {
+            iq_vecs.push(band.fetch_array_msec(self.period_sp, self.block_msec)?);
+        }
+        self.clock.advance(num_samples);
+
+        for idx in 0..self.bands.len() {
+            if std::mem::take(&mut self.bands[idx].time_jump) {
+                self.handle_time_jump(idx);
+            }
+        }
+
+        Ok(iq_vecs)
+    }
+
+    // the signal is not continuous anymore: restart all the channels from scratch.
+    fn handle_time_jump(&mut self, band_idx: usize) {
+        let band = &mut self.bands[band_idx];
+        let num_dropped = std::mem::take(&mut band.num_dropped);
+        tracing::warn!(
+            "t={:.3} -- {}: {} ({num_dropped} samples dropped)",
+            self.clock.get_sec(),
+            band.sig,
+            "time jump".yellow()
+        );
+        band.channels
+            .values_mut()
+            .for_each(|channel| channel.restart_acquisition());
+
+        // the primary front-end is the time reference: keep following the
+        // source's time across the samples it lost.
+        if band_idx == 0 {
+            self.clock.advance_at(num_dropped, band.fs);
+        }
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.num_time_jumps += 1;
+        pub_state.num_dropped_samples += num_dropped;
+    }
+
+    // of the tracked satellites with a complete ephemeris: a satellite tracked
+    // on several bands counts once
+    fn get_tracked_ephs(&self) -> Vec<Ephemeris> {
+        self.bands
+            .iter()
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .filter(|&ch| ch.is_ephemeris_complete())
+            .map(|ch| (ch.sv, ch.nav.eph))
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect()
+    }
+
+    // The tracked SBAS GEOs as ranging sources, their time from the other
+    // satellites, and the corrections of the first one that has some applied
+    // to them all: those it says not to use are left out.
+    fn add_sbas(&self, ephs: &mut Vec<Ephemeris>, ts_sec: f64) {
+        let channels: Vec<_> = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking() && is_sbas(ch.sv))
+            .collect();
+        let Some(other) = ephs.first().copied() else {
+            return;
+        };
+        for ch in &channels {
+            if let Some(geo) = ch.nav.sbas.get_geo_ephemeris(&ch.nav.eph, &other, ts_sec)
+                && ephs.iter().all(|eph| eph.sv != geo.sv)
+            {
+                ephs.push(geo);
+            }
+        }
+        if let Some(ch) = channels.iter().find(|ch| ch.nav.sbas.has_corrections()) {
+            ephs.retain_mut(|eph| {
+                let usable = ch.nav.sbas.apply(eph, ts_sec);
+                if !usable {
+                    tracing::warn!("{}: not to be used, per {}", eph.sv, ch.sv);
+                }
+                usable
+            });
+        }
+    }
+
+    fn compute_fix(&mut self, ts_sec: f64) {
+        if ts_sec - self.last_fix_sec < 2.0 {
+            return;
+        }
+
+        let mut ephs = self.get_tracked_ephs();
+        self.add_sbas(&mut ephs, ts_sec);
+        if ephs.len() < 4 && self.num_fix_svs >= 4 {
+            tracing::warn!("t={ts_sec:.3} -- {}", "fix lost".red());
+            self.trigger_capture("fix-lost");
+        }
+        self.num_fix_svs = ephs.len();
+
+        if ephs.len() < 4 {
+            return;
+        }
+
+        tracing::debug!(
+            "t={ts_sec:.3} -- {}",
+            format!("attempting fix with {} SVs", ephs.len()).red()
+        );
+        self.write_observables(ts_sec);
+
+        let fix_ephs = self.residuals.select(ephs.clone());
+        let pos = match &mut self.pipeline {
+            Some(pipeline) => {
+                // the result comes back through check_fixes()
+                if pipeline.request_fix(ts_sec, fix_ephs) {
+                    self.last_fix_sec = ts_sec;
+                    self.fix_requests.push_back((ts_sec, ephs));
+                }
+                return;
+            }
+            None => self.solver.compute_position(ts_sec, &fix_ephs),
+        };
+        self.last_fix_sec = ts_sec;
+        self.fix_requests.push_back((ts_sec, ephs));
+        self.handle_fix(pos);
+    }
+
+    // fixes computed by the solve stage
+    fn check_fixes(&mut self) {
+        let Some(pipeline) = &mut self.pipeline else {
+            return;
+        };
+        for pos in pipeline.take_fixes() {
+            self.handle_fix(pos);
+        }
+    }
+
+    fn handle_fix(&mut self, pos: Option<[f64; 3]>) {
+        let request = self.fix_requests.pop_front();
+        if let Some(fix) = pos {
+            if let Some((ts_sec, ephs)) = request {
+                self.update_residuals(ts_sec, fix, &ephs);
+            }
+            self.start_state.position = pos;
+            self.stats.add_fix();
+            let mut pub_state = self.pub_state.lock().unwrap();
+            if pub_state.fixes.len() >= MAX_FIXES {
+                pub_state.fixes.remove(0);
+            }
+            pub_state.fixes.push(fix);
+            // the time of the last subframe when decoded, rough otherwise
+            let tow_gpst = pub_state.tow_gpst;
+            drop(pub_state);
+            let unix_sec = if tow_gpst != Epoch::default() {
+                tow_gpst.to_unix_seconds()
+            } else {
+                self.start_unix + self.clock.get_sec()
+            };
+            self.outputs.send(&Solution {
+                unix_sec,
+                lat: fix[0],
+                lon: fix[1],
+                height: fix[2],
+                num_svs: self.num_fix_svs,
+            });
+            self.callbacks.fix(&GnssFix {
+                ts_sec: self.clock.get_sec(),
+                unix_sec,
+                lat: fix[0],
+                lon: fix[1],
+                height: fix[2],
+                num_svs: self.num_fix_svs,
+            });
+            self.update_sky(fix);
+            if let Some(timing) = &mut self.timing
+                && timing.add_fix(self.clock.get_sec(), fix)
+            {
+                let status = timing.get_status();
+                tracing::info!(
+                    "{}: {} fixes, rms {:.1} m",
+                    "timing survey-in done".green(),
+                    status.num_survey_fixes,
+                    status.survey_rms_m.unwrap_or_default()
+                );
+            }
+            let ttff_sec = self.clock.get_sec();
+            let first =
+                (self.pub_state.lock().unwrap().ttff).record(Milestone::Fix, ttff_sec, None);
+            if first {
+                tracing::info!(
+                    "{}",
+                    format!("TTFF ({} start): {ttff_sec:.1} sec", self.start_mode).green()
+                );
+                self.add_event(PlaybackEventKind::Fix);
+                self.save_start_state();
+            }
+        }
+    }
+
+    // pseudorange residuals of the satellites tracked at the fix request
+    fn update_residuals(&mut self, ts_sec: f64, fix: [f64; 3], ephs: &[Ephemeris]) {
+        let changed = self.residuals.update(ts_sec, fix, ephs);
+        let residuals = self.residuals.get_status();
+        for res in residuals.iter().filter(|res| changed.contains(&res.sv)) {
+            if res.biased {
+                tracing::warn!("{}: {res}", "biased pseudorange".yellow());
+            } else {
+                tracing::info!("bias cleared: {res}");
+            }
+        }
+        if let Some(writer) = &mut self.residual_writer
+            && let Err(e) = writer.write(ts_sec, &residuals)
+        {
+            tracing::warn!("residuals: failed to write: {e}");
+            self.residual_writer = None;
+        }
+        self.pub_state.lock().unwrap().residuals = residuals;
+    }
+
+    // Azimuth and elevation of the tracked satellites seen from the fix, for
+    // the UI skyplot: from their ephemeris, or their almanac until then.
+    fn update_sky(&mut self, fix: [f64; 3]) {
+        let ts_sec = self.clock.get_sec();
+        let channels: BTreeMap<_, _> = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .map(|ch| (ch.sv, ch))
+            .collect();
+        // a fix means complete ephemerides: the time of week comes from them
+        let Some(tow_sec) = (channels.values())
+            .find(|ch| ch.is_ephemeris_complete())
+            .map(|ch| ch.nav.eph.tow as f64 + ts_sec - ch.nav.eph.ts_sec)
+        else {
+            return;
+        };
+
+        let mut pub_state = self.pub_state.lock().unwrap();
+        let sky: Vec<_> = (channels.values())
+            .filter_map(|ch| {
+                let sv_ecef = if ch.is_ephemeris_complete() {
+                    get_sv_position_at(&ch.nav.eph, ts_sec)
+                } else {
+                    let alm = (pub_state.almanac.iter())
+                        .filter(|_| ch.sv.constellation == Constellation::GPS)
+                        .find(|alm| alm.sat == ch.sv.prn as u32)?;
+                    alm.get_position_ecef(tow_sec)
+                };
+                let (azimuth_deg, elevation_deg) = get_azimuth_elevation(fix, sv_ecef);
+                Some(SkyPosition {
+                    sv: ch.sv,
+                    azimuth_deg,
+                    elevation_deg,
+                    cn0: ch.get_cn0(),
+                })
+            })
+            .collect();
+        if let Some(dop) = get_dop(&sky) {
+            self.stats.add_dop(dop);
+        }
+        pub_state.sky = sky;
+        (pub_state.update_func.func)();
+    }
+
+    // a front-end disappeared: the channels are left untouched (holdover)
+    // until it comes back, which shows up as a time jump.
+    fn is_holdover(&mut self) -> bool {
+        let lost = self.bands.iter().any(|band| band.iq_feed.is_device_lost());
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if lost != pub_state.device_lost {
+            pub_state.device_lost = lost;
+            if lost {
+                tracing::warn!(
+                    "t={:.3} -- {}",
+                    self.clock.get_sec(),
+                    "device lost: holdover".red()
+                );
+            } else {
+                tracing::info!("t={:.3} -- device back", self.clock.get_sec());
+            }
+            (pub_state.update_func.func)();
+        }
+        lost
+    }
+
+    // Real-time sources queue what processing didn't keep up with. Past
+    // LAG_DEGRADED_SEC the channels stop acquiring new satellites; past
+    // LAG_SKIP_SEC whole code periods are skipped and accounted for in the
+    // time base, the tracking channels coasting through them.
+    fn check_lag(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.bands[0].cached_iq_vec.is_empty() {
+            return Ok(());
+        }
+        let Some(lag_sec) = self
+            .bands
+            .iter()
+            .filter_map(|band| band.get_lag_sec())
+            .reduce(f64::max)
+        else {
+            return Ok(());
+        };
+
+        let lagging = lag_sec >= LAG_DEGRADED_SEC;
+        if lagging != self.lagging {
+            self.lagging = lagging;
+            if lagging {
+                tracing::warn!(
+                    "t={:.3} -- {}: {lag_sec:.2} sec behind, acquisition paused",
+                    self.clock.get_sec(),
+                    "lagging".yellow()
+                );
+            } else {
+                tracing::info!("t={:.3} -- caught up", self.clock.get_sec());
+            }
+            let mut pub_state = self.pub_state.lock().unwrap();
+            pub_state.lagging = lagging;
+            (pub_state.update_func.func)();
+        }
+
+        if lag_sec >= LAG_SKIP_SEC {
+            let num_msec = ((lag_sec - LAG_DEGRADED_SEC) / PERIOD_RCV) as usize;
+            for band in &mut self.bands {
+                band.skip_msec(num_msec, self.period_sp)?;
+                install(self.thread_pool.as_deref(), || {
+                    band.channels
+                        .par_iter_mut()
+                        .for_each(|(_id, channel)| channel.skip_code_periods(num_msec))
+                });
+            }
+            self.clock.advance(num_msec * self.period_sp);
+            tracing::warn!(
+                "t={:.3} -- {lag_sec:.2} sec behind: skipped {num_msec} msec",
+                self.clock.get_sec()
+            );
+            self.pub_state.lock().unwrap().num_skipped_msec += num_msec;
+        }
+        Ok(())
+    }
+
+    fn process_step(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_sv_requests();
+        self.check_lag()?;
+        let ts = StageTimes::start(&self.bench);
+        let iq_vecs = self.fetch_samples_msec()?;
+        StageTimes::add(&mut self.bench, Stage::Read, ts);
+        // the timestamp given corresponds to the beginning of the last code
+        // of the first window, then one code further for each of the others
+        // [...code...][...code...][...code...]
+        //             ^           ^
+        let ts_sec = self.clock.get_sec_before(self.block_msec * self.period_sp);
+        let ref_vecs: Vec<_> = iq_vecs.iter().map(|blocks| blocks[0].clone()).collect();
+        self.update_spectrum(&ref_vecs[0]);
+        self.interference.add_samples(&ref_vecs[0]);
+        self.exchange_acquiring_channels(&ref_vecs, ts_sec);
+
+        for (band, blocks) in self.bands.iter_mut().zip(iq_vecs) {
+            let lagging = self.lagging;
+            let others_acquiring = band
+                .channels
+                .values()
+                .any(|channel| channel.is_acquiring() && channel.get_priority() != SvPriority::Low);
+            #[cfg(feature = "cuda")]
+            let gpu_svs = gpu_acquisition(
+                &mut self.gpu_fft,
+                &mut band.channels,
+                &blocks[0],
+                ts_sec,
+                |channel| channel.is_enabled() && may_acquire(channel, lagging, others_acquiring),
+                self.thread_pool.as_deref(),
+            );
+            #[cfg(not(feature = "cuda"))]
+            let gpu_svs: Vec<SV> = vec![];
+
+            let is_due = |sv: &SV, channel: &Channel| {
+                channel.is_enabled()
+                    && !gpu_svs.contains(sv)
+                    && (!channel.is_acquiring() || may_acquire(channel, lagging, others_acquiring))
+            };
+            if self.deterministic {
+                band.channels
+                    .iter_mut()
+                    .filter(|(sv, channel)| is_due(sv, channel))
+                    .for_each(|(_id, channel)| channel.process_array_block(&blocks, ts_sec));
+                continue;
+            }
+            install(self.thread_pool.as_deref(), || {
+                band.channels
+                    .par_iter_mut()
+                    .filter(|(sv, channel)| is_due(sv, channel))
+                    .for_each(|(_id, channel)| channel.process_array_block(&blocks, ts_sec))
+            });
+        }
+
+        let ts = StageTimes::start(&self.bench);
+        self.compute_fix(self.clock.get_sec_before(self.period_sp));
+        self.check_fixes();
+        StageTimes::add(&mut self.bench, Stage::Solve, ts);
+        self.check_capture_req();
+        self.check_record_req();
+        self.check_output_req();
+        self.check_lock_events();
+        self.check_interference();
+        self.check_timing();
+        self.check_measurements();
+        self.check_calibration();
+        self.check_visibility();
+        self.check_aiding();
+
+        Ok(())
+    }
+
+    // Oscillator error of the primary front-end from the dopplers of its
+    // tracked satellites, predicted from their ephemeris once there is a fix.
+    fn check_calibration(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if ts_sec - self.last_calibration_sec < CALIBRATION_PERIOD_SEC {
+            return;
+        }
+        self.last_calibration_sec = ts_sec;
+        let band = &self.bands[0];
+        let fc = Code::get_code_freq(&band.sig);
+        let dopplers: Vec<_> = (band.channels.values())
+            .filter_map(|ch| {
+                let measured = ch.get_measured_doppler_hz()?;
+                let predicted = (self.start_state.position)
+                    .filter(|_| ch.is_ephemeris_complete())
+                    .map(|pos| get_predicted_doppler(&ch.nav.eph, ts_sec, pos, fc));
+                Some((measured, predicted))
+            })
+            .collect();
+        let Some(estimate) = estimate_ppm(&dopplers, fc) else {
+            return;
+        };
+
+        let mut pub_state = self.pub_state.lock().unwrap();
+        let method_changed = pub_state.ppm_estimate.map(|e| e.method) != Some(estimate.method);
+        pub_state.ppm_estimate = Some(estimate);
+        drop(pub_state);
+        if method_changed {
+            tracing::info!("{}: clock offset {estimate}", band.sig);
+        } else {
+            tracing::debug!("{}: clock offset {estimate}", band.sig);
+        }
+        if self.auto_ppm
+            && estimate.std_ppm < AUTO_PPM_MAX_STD
+            && (estimate.ppm - self.clock_offset_ppm).abs() >= AUTO_PPM_MIN_CHANGE
+        {
+            self.set_clock_offset_ppm(estimate.ppm);
+        }
+    }
+
+    // With an almanac, decoded or loaded, and a rough position and time, the
+    // satellites above the horizon are searched first and around their
+    // predicted doppler, the others only when nothing else is. Priorities set
+    // by the user stay.
+    fn check_visibility(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if ts_sec - self.last_visibility_sec < VISIBILITY_PERIOD_SEC {
+            return;
+        }
+        self.last_visibility_sec = ts_sec;
+        let Some(pos) = self.start_state.position else {
+            return;
+        };
+        let almanac: Vec<_> = (self.pub_state.lock().unwrap().almanac.iter())
+            .filter(|alm| alm.sat != 0)
+            .cloned()
+            .collect();
+        if almanac.is_empty() {
+            return;
+        }
+        // from the ephemeris once decoded, the rough time until then
+        let tow_sec = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .find(|ch| ch.is_ephemeris_complete())
+            .map(|ch| ch.nav.eph.tow as f64 + ts_sec - ch.nav.eph.ts_sec)
+            .unwrap_or_else(|| get_tow_sec(self.start_unix + ts_sec));
+
+        let mut visible_svs = BTreeSet::new();
+        for band in &mut self.bands {
+            let fc = Code::get_code_freq(&band.sig);
+            for channel in band.channels.values_mut() {
+                let sv = channel.sv;
+                let Some(alm) = (almanac.iter())
+                    .filter(|_| sv.constellation == Constellation::GPS)
+                    .find(|alm| alm.sat == sv.prn as u32)
+                else {
+                    continue;
+                };
+                let doppler_hz = predict_doppler_from_almanac(alm, pos, tow_sec, fc);
+                if doppler_hz.is_some() {
+                    visible_svs.insert(sv);
+                }
+                if channel.is_state_tracking() || self.sv_priority.contains_key(&sv) {
+                    continue;
+                }
+                channel.set_priority(match doppler_hz {
+                    Some(_) => SvPriority::High,
+                    None => SvPriority::Low,
+                });
+                // not in the middle of a search
+                if let Some(doppler_hz) = doppler_hz
+                    && !channel.is_acquiring()
+                {
+                    channel.set_doppler_hint(doppler_hz, WARM_DOPPLER_SPREAD_HZ);
+                }
+            }
+        }
+        if self.visible_svs.as_ref() != Some(&visible_svs) {
+            let svs: Vec<_> = visible_svs.iter().map(|sv| sv.to_string()).collect();
+            tracing::info!(
+                "almanac: {} satellites above the horizon: {}",
+                svs.len(),
+                svs.join(" ")
+            );
+            self.visible_svs = Some(visible_svs);
+        }
+    }
+
+    // A satellite tracked on a band is searched on the others around the same
+    // doppler scaled to their carrier, e.g. L2C aided by L1CA: a full search
+    // of its 20 msec code takes a thousand doppler bins.
+    fn check_aiding(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if self.bands.len() < 2 || ts_sec - self.last_aiding_sec < AIDING_PERIOD_SEC {
+            return;
+        }
+        self.last_aiding_sec = ts_sec;
+        // doppler over carrier: the same on all the bands
+        let tracked: BTreeMap<_, _> = (self.bands.iter())
+            .flat_map(|band| {
+                let fc = Code::get_code_freq(&band.sig);
+                (band.channels.values())
+                    .filter_map(move |ch| Some((ch.sv, ch.get_doppler_hz()? / fc)))
+            })
+            .collect();
+        for band in &mut self.bands {
+            let fc = Code::get_code_freq(&band.sig);
+            for channel in band.channels.values_mut() {
+                // not in the middle of a search
+                if channel.is_state_tracking() || channel.is_acquiring() {
+                    continue;
+                }
+                if let Some(ratio) = tracked.get(&channel.sv) {
+                    channel.set_doppler_hint(ratio * fc, AIDED_DOPPLER_SPREAD_HZ);
+                }
+            }
+        }
+    }
+
+    fn add_event(&mut self, kind: PlaybackEventKind) {
+        let band = &self.bands[0];
+        let event = PlaybackEvent {
+            pos_sec: band.off_samples as f64 / band.fs,
+            kind,
+        };
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if pub_state.events.len() >= MAX_EVENTS {
+            pub_state.events.remove(0);
+        }
+        pub_state.events.push(event);
+    }
+
+    fn check_lock_events(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        let eph_svs: BTreeSet<SV> = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_ephemeris_complete())
+            .map(|ch| ch.sv)
+            .collect();
+        for &sv in eph_svs.difference(&self.eph_svs) {
+            let kind = ChannelEventKind::Ephemeris;
+            self.callbacks
+                .channel_event(&ChannelEvent { sv, ts_sec, kind });
+        }
+        self.eph_svs = eph_svs;
+
+        let tracking: BTreeSet<SV> = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .map(|ch| ch.sv)
+            .collect();
+        let cn0s = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .map(|ch| (ch.sv, ch.get_cn0()));
+        self.stats.add_tracking(ts_sec, cn0s);
+        if tracking == self.tracking_svs {
+            return;
+        }
+        let locked: Vec<_> = tracking.difference(&self.tracking_svs).copied().collect();
+        let lost: Vec<_> = self.tracking_svs.difference(&tracking).copied().collect();
+        for sv in locked {
+            self.stats.add_lock(sv);
+            self.add_event(PlaybackEventKind::Lock(sv));
+            let kind = ChannelEventKind::Locked;
+            self.callbacks
+                .channel_event(&ChannelEvent { sv, ts_sec, kind });
+        }
+        for sv in lost {
+            self.interference.add_loss(ts_sec);
+            self.add_event(PlaybackEventKind::Loss(sv));
+            let kind = ChannelEventKind::Lost;
+            self.callbacks
+                .channel_event(&ChannelEvent { sv, ts_sec, kind });
+        }
+        self.tracking_svs = tracking;
+    }
+
+    // Jamming or interference, from the power of the primary front-end's raw
+    // samples, the cn0 of its tracked satellites and the channels lost.
+    fn check_interference(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if ts_sec - self.last_interference_sec < INTERFERENCE_PERIOD_SEC {
+            return;
+        }
+        self.last_interference_sec = ts_sec;
+        let cn0s: Vec<_> = (self.bands[0].channels.values())
+            .filter(|&ch| ch.is_state_tracking())
+            .map(|ch| (ch.sv, ch.get_cn0()))
+            .collect();
+        let changed = self.interference.update(ts_sec, &cn0s);
+        let status = self.interference.get_status();
+        if changed {
+            if status.is_detected() {
+                tracing::warn!("t={ts_sec:.3} -- {}: {status}", "interference".red());
+                self.add_event(PlaybackEventKind::Interference);
+            } else {
+                tracing::info!("t={ts_sec:.3} -- interference cleared: {status}");
+            }
+        }
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.interference = status;
+        if changed {
+            pub_state.interference_events = self.interference.get_events().to_vec();
+            (pub_state.update_func.func)();
+        }
+    }
+
+    // The local clock against gps time, in timing mode.
+    fn check_timing(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if self.timing.is_none() || ts_sec - self.last_timing_sec < TIMING_PERIOD_SEC {
+            return;
+        }
+        self.last_timing_sec = ts_sec;
+        let ephs = self.get_tracked_ephs();
+        let timing = self.timing.as_mut().unwrap();
+        let prev_stage = timing.get_status().stage;
+        let stage = timing.update(self.clock.get_sec_before(self.period_sp), &ephs);
+        let status = timing.get_status();
+        if stage != prev_stage {
+            tracing::info!("t={ts_sec:.3} -- {}: {status}", "timing".green());
+        } else {
+            tracing::debug!("t={ts_sec:.3} -- timing: {status}");
+        }
+        self.callbacks.timing(&status);
+        let mut pub_state = self.pub_state.lock().unwrap();
+        pub_state.timing = Some(status);
+        if stage != prev_stage {
+            (pub_state.update_func.func)();
+        }
+    }
+
+    // the tracked satellites, to the measurements callback
+    fn check_measurements(&mut self) {
+        let ts_sec = self.clock.get_sec();
+        if !self.callbacks.has_measurements()
+            || ts_sec - self.last_measurement_sec < MEASUREMENT_PERIOD_SEC
+        {
+            return;
+        }
+        self.last_measurement_sec = ts_sec;
+        let measurements: Vec<_> = (self.bands.iter())
+            .flat_map(|band| band.channels.values())
+            .filter_map(|ch| {
+                let (code_off_sec, cn0) = ch.get_code_off_sec()?;
+                Some(GnssMeasurement {
+                    sv: ch.sv,
+                    ts_sec,
+                    cn0,
+                    doppler_hz: ch.get_measured_doppler_hz()?,
+                    code_off_sec,
+                    has_ephemeris: ch.is_ephemeris_complete(),
+                    quality: ch.get_quality()?,
+                })
+            })
+            .collect();
+        self.callbacks.measurements(&measurements);
+    }
+
+    // spectrum of the primary band for the UI, a few times per second
+    fn update_spectrum(&mut self, iq_vec: &[IQSample]) {
+        if self.last_spectrum.elapsed() < Duration::from_millis(SPECTRUM_PERIOD_MSEC) {
+            return;
+        }
+        self.last_spectrum = Instant::now();
+        let mut pub_state = self.pub_state.lock().unwrap();
+        if !pub_state.live_plots {
+            return;
+        }
+        let spectrum = (self.spectrum).get_or_insert_with(|| Spectrum::new(&mut self.fft_planner));
+        pub_state.spectrum = Some(spectrum.compute(iq_vec));
+        pub_state.spectrum_fs = self.fs_internal;
+        (pub_state.update_func.func)();
+    }
+
+    // the percent done of the samples from off_start to off_end, e.g. from
+    // --off-msec or a checkpoint on
+    fn update_progress(
+        &mut self,
+        num_msec: usize,
+        off_start: usize,
+        off_end: usize,
+        ts: Instant,
+        verbose: bool,
+    ) {
+        let band = &self.bands[0];
+        let elapsed_sec = ts.elapsed().as_secs_f64();
+        let processed_sec = (num_msec - self.resumed_msec) as f64 * PERIOD_RCV;
+        let remaining_sec = off_end.saturating_sub(band.off_samples) as f64 / band.fs;
+        let speed = (elapsed_sec >= PROGRESS_MIN_SEC && processed_sec > 0.0)
+            .then(|| processed_sec / elapsed_sec);
+        let progress = PlaybackProgress {
+            percent: 100.0 * band.off_samples.saturating_sub(off_start) as f64
+                / off_end.saturating_sub(off_start).max(1) as f64,
+            speed,
+            eta_sec: speed.map(|speed| remaining_sec / speed),
+            pos_sec: band.off_samples as f64 / band.fs,
+            duration_sec: off_end as f64 / band.fs,
+        };
+
+        if verbose {
+            match progress.speed.zip(progress.eta_sec) {
+                Some((speed, eta_sec)) => tracing::info!(
+                    "progress: {:.1}% -- speed: {speed:.2}x real-time -- eta: {eta_sec:.0} sec",
+                    progress.percent
+                ),
+                None => tracing::info!("progress: {:.1}%", progress.percent),
+            }
+        }
+        self.pub_state.lock().unwrap().progress = Some(progress);
+        (self.pub_state.lock().unwrap().update_func.func)();
+    }
+
+    // The summary of the run, left to the caller to print: the time to fix is
+    // in the public state.
+    pub fn run_loop(&mut self, num_msec: usize) -> RunSummary {
+        let _span = tracing::info_span!("receiver").entered();
+        let mut n = self.resumed_msec;
+        let mut interrupted = false;
+        let mut completed = false; // through the recording or the msecs asked for
+        let mut ts = Instant::now(); // moved forward by the pauses
+        let mut last_progress_sec = 0.0;
+        let mut last_progress_log_sec = 0.0;
+        let progress_period_sec = if self.pub_state.lock().unwrap().live_plots {
+            PROGRESS_PERIOD_UI_SEC
+        } else {
+            PROGRESS_PERIOD_SEC
+        };
+        self.start_plot_worker();
+        if self.pipelined {
+            self.start_pipeline();
+        }
+        let off_start = self.bands[0].off_samples;
+        let off_end = self.bands[0].iq_feed.get_num_samples_total().map(|total| {
+            if num_msec != 0 {
+                let num_samples =
+                    (num_msec.saturating_sub(n) as f64 * PERIOD_RCV * self.bands[0].fs) as usize;
+                usize::min(total, off_start + num_samples)
+            } else {
+                total
+            }
+        });
+
+        loop {
+            if self.check_playback_req()
+                && let Some(off_end) = off_end
+            {
+                self.update_progress(n, off_start, off_end, ts, true);
+            }
+            if self.paused {
+                if self.exit_req.load(Ordering::SeqCst) {
+                    interrupted = true;
+                    break;
+                }
+                let ts_pause = Instant::now();
+                std::thread::sleep(Duration::from_millis(PAUSE_SLEEP_MSEC));
+                ts += ts_pause.elapsed();
+                continue;
+            }
+            let res = self.process_step();
+            if self.is_holdover() {
+                if self.exit_req.load(Ordering::SeqCst) {
+                    tracing::info!("exit requested in holdover");
+                    interrupted = true;
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(HOLDOVER_SLEEP_MSEC));
+                continue;
+            }
+            if res.is_err() {
+                completed = self.is_at_end_of_file();
+                break;
+            }
+            if let Some(speed) = self.playback_speed {
+                // from the start of this run, not of a checkpoint resumed from
+                let num_msec = n - self.resumed_msec + self.block_msec;
+                let target_sec = num_msec as f64 * PERIOD_RCV / speed;
+                let elapsed_sec = ts.elapsed().as_secs_f64();
+                if target_sec > elapsed_sec {
+                    std::thread::sleep(Duration::from_secs_f64(target_sec - elapsed_sec));
+                }
+            }
+            if let Some(off_end) = off_end {
+                let elapsed_sec = ts.elapsed().as_secs_f64();
+                if elapsed_sec - last_progress_sec >= progress_period_sec {
+                    let verbose = elapsed_sec - last_progress_log_sec >= PROGRESS_PERIOD_SEC;
+                    self.update_progress(n, off_start, off_end, ts, verbose);
+                    last_progress_sec = elapsed_sec;
+                    if verbose {
+                        last_progress_log_sec = elapsed_sec;
+                    }
+                }
+            }
+            n += self.block_msec;
+            self.check_checkpoint(n);
+            if self.exit_req.load(Ordering::SeqCst) {
+                tracing::info!("exit requested");
+                interrupted = true;
+                break;
+            }
+            if num_msec != 0 && n >= num_msec {
+                tracing::info!("{num_msec} msecs of iq-data processed");
+                completed = true;
+                break;
+            }
+        }
+        self.stop_pipeline();
+        self.stop_plot_worker();
+        let plot_thread = self.finish_plots();
+        self.finish_outputs();
+        self.save_start_state();
+        self.end_checkpoint(n, completed);
+        self.report_bench(ts.elapsed().as_secs_f64());
+        let summary = self.report_summary(ts.elapsed().as_secs_f64(), interrupted);
+        if let Some(plot_thread) = plot_thread {
+            let _ = plot_thread.join();
         }
+        summary
     }
 }
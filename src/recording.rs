@@ -1,20 +1,23 @@
 use bytesize::ByteSize;
 use colored::Colorize;
-use rustfft::num_complex::Complex64;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::io::Write;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Instant;
+use std::sync::Arc;
 
-use crate::receiver::IQReader;
+use crate::clock::Instant;
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleSource};
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum IQFileType {
     TypePairFloat32,
     TypePairInt16,
@@ -46,18 +49,151 @@ impl fmt::Display for IQFileType {
     }
 }
 
+impl IQFileType {
+    // real-sampled formats only carry the in-phase component
+    pub fn is_real(&self) -> bool {
+        *self == IQFileType::TypeOneInt8
+    }
+}
+
+struct IQFileSegment {
+    path: PathBuf,
+    num_samples: usize,
+}
+
 pub struct IQRecording {
-    file_path: PathBuf,
+    files: Vec<IQFileSegment>, // processed back-to-back
+    file_type: IQFileType,
+    num_samples_file: usize,
+    looping: bool,
+    num_loops: usize,
+    time_jump: bool,
+}
+
+impl SampleSource for IQRecording {
+    fn read_block(
+        &mut self,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        let iq_vec = self.read_looped_iq_data(off_samples, num_samples)?;
+        Ok(SampleBlock {
+            time_jump: std::mem::take(&mut self.time_jump),
+            ..SampleBlock::new(iq_vec)
+        })
+    }
+
+    fn get_num_samples_total(&self) -> Option<usize> {
+        if self.looping {
+            return None;
+        }
+        Some(self.num_samples_file)
+    }
+
+    fn get_file_paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+}
+
+// The content of an IQ file already in memory, e.g. uploaded to a browser:
+// no file system needed.
+pub struct IQBuffer {
+    data: Arc<[u8]>,
     file_type: IQFileType,
 }
 
-impl IQReader for IQRecording {
-    fn get_iq_data(
+impl IQBuffer {
+    pub fn new(data: Arc<[u8]>, file_type: &IQFileType) -> Self {
+        Self {
+            data,
+            file_type: file_type.clone(),
+        }
+    }
+}
+
+impl SampleSource for IQBuffer {
+    fn read_block(
+        &mut self,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        let sample_size = IQRecording::get_sample_size_bytes(&self.file_type);
+        let start = off_samples * sample_size;
+        let end = start + num_samples * sample_size;
+        if end > self.data.len() {
+            return Err("end of IQ buffer".into());
+        }
+        let iq_vec = decode_iq_data(&self.data[start..end], &self.file_type);
+        Ok(SampleBlock::new(iq_vec))
+    }
+
+    fn get_num_samples_total(&self) -> Option<usize> {
+        Some(self.data.len() / IQRecording::get_sample_size_bytes(&self.file_type))
+    }
+}
+
+impl IQRecording {
+    fn read_looped_iq_data(
         &mut self,
         off_samples: usize,
         num_samples: usize,
-    ) -> Result<Vec<Complex64>, Box<dyn std::error::Error>> {
-        let file = File::open(self.file_path.clone())?;
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        if !self.looping {
+            return self.read_iq_data(off_samples, num_samples);
+        }
+
+        // wrap around to the start of the file when reaching EOF
+        let off = off_samples % self.num_samples_file;
+        let num_loops = (off_samples + num_samples) / self.num_samples_file;
+        if num_loops != self.num_loops {
+            tracing::info!("looping back to start of recording (loop #{num_loops})");
+            self.num_loops = num_loops;
+            self.time_jump = true;
+        }
+        if off + num_samples <= self.num_samples_file {
+            return self.read_iq_data(off, num_samples);
+        }
+        let n = self.num_samples_file - off;
+        let mut iq_vec = self.read_iq_data(off, n)?;
+        iq_vec.append(&mut self.read_iq_data(0, num_samples - n)?);
+
+        Ok(iq_vec)
+    }
+
+    fn read_iq_data(
+        &self,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        let mut iq_vec = Vec::with_capacity(num_samples);
+        let mut off = off_samples;
+
+        for segment in &self.files {
+            if iq_vec.len() == num_samples {
+                break;
+            }
+            if off >= segment.num_samples {
+                off -= segment.num_samples;
+                continue;
+            }
+            let n = usize::min(num_samples - iq_vec.len(), segment.num_samples - off);
+            iq_vec.append(&mut self.read_file_iq_data(&segment.path, off, n)?);
+            off = 0;
+        }
+        if iq_vec.len() < num_samples {
+            return Err("end of file".into());
+        }
+
+        Ok(iq_vec)
+    }
+
+    fn read_file_iq_data(
+        &self,
+        file_path: &Path,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<Vec<IQSample>, Box<dyn std::error::Error>> {
+        let file = File::open(file_path)?;
         let sample_size = Self::get_sample_size_bytes(&self.file_type);
         let buf_size = sample_size * num_samples;
         let mut reader = BufReader::with_capacity(buf_size, &file);
@@ -68,18 +204,19 @@ impl IQReader for IQRecording {
         let off_file = off_samples * sample_size;
 
         if false {
-            log::debug!(
+            tracing::debug!(
                 "read_iq_file: off_samples={} num_samples={}",
                 off_samples,
                 num_samples
             );
         }
 
-        let _ = reader.seek(SeekFrom::Current(off_file as i64)).unwrap();
+        reader.seek(SeekFrom::Current(off_file as i64))?;
 
         loop {
             let buf = reader.fill_buf()?;
-            let len = buf.len();
+            // a truncated last sample is left out
+            let len = buf.len() / sample_size * sample_size;
 
             if len == 0 {
                 break;
@@ -88,9 +225,9 @@ impl IQReader for IQRecording {
             match self.file_type {
                 IQFileType::TypeRtlSdrFile => {
                     for off in (0..len).step_by(2) {
-                        iq_vec.push(Complex64 {
-                            re: (buf[off] as f64 - 127.3) / 128.0,
-                            im: (buf[off + 1] as f64 - 127.3) / 128.0,
+                        iq_vec.push(IQSample {
+                            re: (buf[off] as Float - 127.3) / 128.0,
+                            im: (buf[off + 1] as Float - 127.3) / 128.0,
                         });
                         n += 1;
                         if n >= num_samples {
@@ -100,8 +237,8 @@ impl IQReader for IQRecording {
                 }
                 IQFileType::TypeOneInt8 => {
                     for v in buf.iter().take(len) {
-                        iq_vec.push(Complex64 {
-                            re: *v as i8 as f64 / i8::MAX as f64,
+                        iq_vec.push(IQSample {
+                            re: *v as i8 as Float / i8::MAX as Float,
                             im: 0.0,
                         });
                         n += 1;
@@ -114,9 +251,9 @@ impl IQReader for IQRecording {
                     for off in (0..len).step_by(4) {
                         let i = i16::from_le_bytes([buf[off], buf[off + 1]]);
                         let q = i16::from_le_bytes([buf[off + 2], buf[off + 3]]);
-                        iq_vec.push(Complex64 {
-                            re: i as f64 / i16::MAX as f64,
-                            im: q as f64 / i16::MAX as f64,
+                        iq_vec.push(IQSample {
+                            re: i as Float / i16::MAX as Float,
+                            im: q as Float / i16::MAX as Float,
                         });
                         n += 1;
                         if n >= num_samples {
@@ -138,11 +275,18 @@ impl IQReader for IQRecording {
                             buf[off + 6],
                             buf[off + 7],
                         ]);
-                        assert!((-1.0..=1.0).contains(&i));
-                        assert!((-1.0..=1.0).contains(&q));
-                        iq_vec.push(Complex64 {
-                            re: i as f64,
-                            im: q as f64,
+                        if !(-1.0..=1.0).contains(&i) || !(-1.0..=1.0).contains(&q) {
+                            return Err(format!(
+                                "{}: sample {} out of [-1, 1]: not {} samples?",
+                                file_path.display(),
+                                off_samples + n,
+                                self.file_type
+                            )
+                            .into());
+                        }
+                        iq_vec.push(IQSample {
+                            re: i as Float,
+                            im: q as Float,
                         });
                         n += 1;
                         if n >= num_samples {
@@ -159,11 +303,10 @@ impl IQReader for IQRecording {
         if n < num_samples {
             return Err("end of file".into());
         }
-        assert_eq!(n, num_samples);
 
         let bw = n as f64 * buf_size as f64 / 1024.0 / 1024.0 / ts.elapsed().as_secs_f64();
         if false {
-            log::debug!(
+            tracing::debug!(
                 "read_from_file: {} msec -- bandwidth: {:.1} MB/sec -- num_read_ops={}",
                 ts.elapsed().as_millis(),
                 bw,
@@ -175,25 +318,162 @@ impl IQReader for IQRecording {
     }
 }
 
+pub const IQ_FILE_TYPES: [IQFileType; 4] = [
+    IQFileType::TypePairFloat32,
+    IQFileType::TypePairInt16,
+    IQFileType::TypeRtlSdrFile,
+    IQFileType::TypeOneInt8,
+];
+
+// decodes all the complete samples found in buf, without any sanity check.
+pub fn decode_iq_data(buf: &[u8], file_type: &IQFileType) -> Vec<IQSample> {
+    let sample_size = IQRecording::get_sample_size_bytes(file_type);
+    buf.chunks_exact(sample_size)
+        .map(|b| match file_type {
+            IQFileType::TypeRtlSdrFile => IQSample {
+                re: (b[0] as Float - 127.3) / 128.0,
+                im: (b[1] as Float - 127.3) / 128.0,
+            },
+            IQFileType::TypeOneInt8 => IQSample {
+                re: b[0] as i8 as Float / i8::MAX as Float,
+                im: 0.0,
+            },
+            IQFileType::TypePairInt16 => IQSample {
+                re: i16::from_le_bytes([b[0], b[1]]) as Float / i16::MAX as Float,
+                im: i16::from_le_bytes([b[2], b[3]]) as Float / i16::MAX as Float,
+            },
+            IQFileType::TypePairFloat32 => IQSample {
+                re: f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float,
+                im: f32::from_le_bytes([b[4], b[5], b[6], b[7]]) as Float,
+            },
+        })
+        .collect()
+}
+
+// file may be a comma-separated list of paths and/or glob patterns
+pub fn get_file_list(file: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for s in file.to_string_lossy().split(',') {
+        if s.contains(['*', '?', '[']) {
+            let mut matches: Vec<_> = glob::glob(s)
+                .map(|paths| paths.filter_map(Result::ok).collect())
+                .unwrap_or_default();
+            if matches.is_empty() {
+                tracing::warn!("no file matching {s}");
+            }
+            matches.sort();
+            files.append(&mut matches);
+        } else if !s.is_empty() {
+            files.push(PathBuf::from(s));
+        }
+    }
+    files
+}
+
+// Checks the files of a recording against the requested slice: num_msec
+// from off_msec, up to the end if 0. The files have to be there, and are
+// expected to hold whole samples.
+pub fn check_recording_range(
+    file: &Path,
+    file_type: &IQFileType,
+    fs: f64,
+    off_msec: usize,
+    num_msec: usize,
+) -> Result<(), Box<dyn Error>> {
+    let sample_size = IQRecording::get_sample_size_bytes(file_type) as u64;
+    let files = get_file_list(file);
+    if files.is_empty() {
+        return Err(format!("no IQ file found: {}", file.display()).into());
+    }
+    let mut num_samples = 0;
+    for path in files {
+        let file_size = path
+            .metadata()
+            .map_err(|e| format!("{}: {e}", path.display()))?
+            .len();
+        if file_size % sample_size != 0 {
+            tracing::warn!(
+                "{}: {} bytes are not a whole number of {file_type} samples: wrong --iq-file-type?",
+                path.display(),
+                file_size
+            );
+        }
+        num_samples += file_size / sample_size;
+    }
+    let total_msec = (num_samples as f64 / fs * 1000.0) as usize;
+    if off_msec >= total_msec {
+        return Err(format!(
+            "offset {off_msec} msec past the end of the recording: {total_msec} msec at {fs} Hz"
+        )
+        .into());
+    }
+    if num_msec > 0 && off_msec + num_msec > total_msec {
+        return Err(format!(
+            "{num_msec} msec from {off_msec} msec requested, the recording has {total_msec} msec at {fs} Hz"
+        )
+        .into());
+    }
+    Ok(())
+}
+
 impl IQRecording {
-    pub fn new(file_path: &Path, fs: f64, file_type: &IQFileType) -> Self {
-        let file_size = file_path.metadata().unwrap().len();
+    pub fn new(
+        file_path: &Path,
+        fs: f64,
+        file_type: &IQFileType,
+        looping: bool,
+    ) -> Result<Self, Box<dyn Error>> {
         let sample_size = Self::get_sample_size_bytes(file_type) as f64;
-        let recording_duration_sec = file_size as f64 / fs / sample_size;
-
-        println!(
-            "file: {} -- {file_type} {} duration: {:.1} secs",
-            file_path.display().to_string().green(),
-            ByteSize::b(file_size).to_string().bold(),
-            recording_duration_sec
-        );
-        Self {
-            file_path: file_path.to_path_buf(),
-            file_type: file_type.clone(),
+        let mut files = vec![];
+        let mut total_size = 0;
+
+        for path in get_file_list(file_path) {
+            let file_size = path
+                .metadata()
+                .map_err(|e| format!("{}: {e}", path.display()))?
+                .len();
+            let recording_duration_sec = file_size as f64 / fs / sample_size;
+
+            println!(
+                "file: {} -- {file_type} {} duration: {:.1} secs",
+                path.display().to_string().green(),
+                ByteSize::b(file_size).to_string().bold(),
+                recording_duration_sec
+            );
+            total_size += file_size;
+            files.push(IQFileSegment {
+                path,
+                num_samples: (file_size as f64 / sample_size) as usize,
+            });
+        }
+        if files.is_empty() {
+            return Err(format!("no IQ file found: {}", file_path.display()).into());
+        }
+
+        if files.len() > 1 {
+            println!(
+                "{} files -- {} duration: {:.1} secs",
+                files.len(),
+                ByteSize::b(total_size).to_string().bold(),
+                total_size as f64 / fs / sample_size
+            );
         }
+        if looping {
+            tracing::info!("file looping enabled");
+        }
+        let num_samples_file = files.iter().map(|f| f.num_samples).sum();
+
+        Ok(Self {
+            files,
+            file_type: file_type.clone(),
+            num_samples_file,
+            looping,
+            num_loops: 0,
+            time_jump: false,
+        })
     }
 
-    fn get_sample_size_bytes(file_type: &IQFileType) -> usize {
+    pub fn get_sample_size_bytes(file_type: &IQFileType) -> usize {
         match file_type {
             IQFileType::TypeRtlSdrFile => 2,
             IQFileType::TypeOneInt8 => 1,
@@ -202,3 +482,35 @@ impl IQRecording {
         }
     }
 }
+
+pub fn write_iq_data(
+    writer: &mut impl Write,
+    iq_vec: &[IQSample],
+    file_type: &IQFileType,
+) -> std::io::Result<()> {
+    for c in iq_vec {
+        match file_type {
+            IQFileType::TypeRtlSdrFile => {
+                let re = (c.re * 128.0 + 127.3).round().clamp(0.0, 255.0) as u8;
+                let im = (c.im * 128.0 + 127.3).round().clamp(0.0, 255.0) as u8;
+                writer.write_all(&[re, im])?;
+            }
+            IQFileType::TypeOneInt8 => {
+                let re = (c.re * i8::MAX as Float).round() as i8;
+                writer.write_all(&re.to_le_bytes())?;
+            }
+            IQFileType::TypePairInt16 => {
+                let i = (c.re * i16::MAX as Float).round() as i16;
+                let q = (c.im * i16::MAX as Float).round() as i16;
+                writer.write_all(&i.to_le_bytes())?;
+                writer.write_all(&q.to_le_bytes())?;
+            }
+            #[allow(clippy::unnecessary_cast)] // no-op with the f32 feature
+            IQFileType::TypePairFloat32 => {
+                writer.write_all(&(c.re as f32).to_le_bytes())?;
+                writer.write_all(&(c.im as f32).to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()
+}
@@ -0,0 +1,125 @@
+use crate::sample::{Float, IQSample};
+
+const PI: f64 = std::f64::consts::PI;
+
+const NUM_PHASES: usize = 64;
+const TAPS_PER_PHASE: usize = 24;
+
+// Arbitrary ratio polyphase resampler: a windowed-sinc low-pass prototype is
+// split into NUM_PHASES sub-filters, and each output sample uses the
+// sub-filter closest to its fractional position in the input stream.
+pub struct Resampler {
+    fs_in: f64,
+    fs_out: f64,
+    ratio: f64, // input samples per output sample
+    taps: Vec<Vec<Float>>,
+    buf: Vec<IQSample>,
+    t: f64, // position of the next output sample in buf
+}
+
+fn gen_prototype_filter(cutoff: f64) -> Vec<f64> {
+    let len = NUM_PHASES * TAPS_PER_PHASE;
+    let mid = (len - 1) as f64 / 2.0;
+    let mut h: Vec<_> = (0..len)
+        .map(|i| {
+            let x = i as f64 - mid;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            // blackman window
+            let w = 0.42 - 0.5 * (2.0 * PI * i as f64 / (len - 1) as f64).cos()
+                + 0.08 * (4.0 * PI * i as f64 / (len - 1) as f64).cos();
+            sinc * w
+        })
+        .collect();
+
+    let sum: f64 = h.iter().sum();
+    h.iter_mut().for_each(|v| *v *= NUM_PHASES as f64 / sum);
+    h
+}
+
+impl Resampler {
+    pub fn new(fs_in: f64, fs_out: f64) -> Self {
+        let ratio = fs_in / fs_out;
+        // cutoff relative to the NUM_PHASES-times upsampled prototype rate
+        let cutoff = 0.45 * f64::min(1.0, 1.0 / ratio) / NUM_PHASES as f64;
+        let h = gen_prototype_filter(cutoff);
+        let taps = (0..NUM_PHASES)
+            .map(|p| {
+                (0..TAPS_PER_PHASE)
+                    .map(|k| h[k * NUM_PHASES + p] as Float)
+                    .collect()
+            })
+            .collect();
+
+        tracing::info!(
+            "resampler: {:.1} KHz -> {:.1} KHz ratio={:.5}",
+            fs_in / 1000.0,
+            fs_out / 1000.0,
+            ratio
+        );
+
+        Self {
+            fs_in,
+            fs_out,
+            ratio,
+            taps,
+            buf: vec![IQSample::default(); TAPS_PER_PHASE - 1],
+            t: (TAPS_PER_PHASE - 1) as f64,
+        }
+    }
+
+    pub fn get_fs_in(&self) -> f64 {
+        self.fs_in
+    }
+
+    pub fn get_fs_out(&self) -> f64 {
+        self.fs_out
+    }
+
+    // number of input samples needed to produce num_out output samples
+    pub fn get_num_input_samples(&self, num_out: usize) -> usize {
+        let needed = self.t + (num_out as f64 - 1.0) * self.ratio + 1.0;
+        let avail = self.buf.len() as f64;
+        f64::max(0.0, (needed - avail).ceil()) as usize
+    }
+
+    pub fn process(&mut self, iq_vec: &[IQSample]) -> Vec<IQSample> {
+        self.buf.extend_from_slice(iq_vec);
+
+        let mut out = Vec::with_capacity((iq_vec.len() as f64 / self.ratio) as usize + 1);
+        loop {
+            let i = self.t as usize;
+            if i >= self.buf.len() {
+                break;
+            }
+            let frac = self.t - i as f64;
+            let mut p = (frac * NUM_PHASES as f64).round() as usize;
+            let mut i = i;
+            if p == NUM_PHASES {
+                p = 0;
+                i += 1;
+                if i >= self.buf.len() {
+                    break;
+                }
+            }
+            let taps = &self.taps[p];
+            let mut v = IQSample::default();
+            for (k, tap) in taps.iter().enumerate() {
+                v += self.buf[i - k] * tap;
+            }
+            out.push(v);
+            self.t += self.ratio;
+        }
+
+        // keep enough history for the next call
+        let keep = TAPS_PER_PHASE - 1;
+        let consumed = self.buf.len() - keep;
+        let _ = self.buf.drain(0..consumed);
+        self.t -= consumed as f64;
+
+        out
+    }
+}
@@ -0,0 +1,181 @@
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::constants::SPEED_OF_LIGHT;
+use crate::ephemeris::Ephemeris;
+use crate::timing::{CODE_SEC, get_clock_offset, get_ecef, get_median};
+
+const RESIDUAL_WINDOW: usize = 30; // fixes the statistics are taken over
+const MIN_RESIDUALS: usize = 5;
+const BIAS_THRESHOLD_M: f64 = 30.0; // mean residual of a biased satellite
+const BIAS_CLEAR_M: f64 = 15.0; // hysteresis
+const BIAS_SIGMA: f64 = 3.0; // mean over its standard error
+const MIN_FIX_SVS: usize = 4;
+
+// Pseudorange residuals of a satellite against the fixes: the measured
+// pseudorange minus the one predicted from the fix, the clock bias common to
+// all satellites removed.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SvResidual {
+    pub sv: SV,
+    pub last_m: f64,
+    pub mean_m: f64,
+    pub rms_m: f64,
+    pub num: usize, // residuals the statistics are over
+    pub biased: bool,
+    pub excluded: bool, // left out of the latest fix
+}
+
+impl fmt::Display for SvResidual {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: residual={:+.1} m mean={:+.1} m rms={:.1} m",
+            self.sv, self.last_m, self.mean_m, self.rms_m
+        )?;
+        if self.biased {
+            write!(f, " biased")?;
+        }
+        if self.excluded {
+            write!(f, " excluded")?;
+        }
+        Ok(())
+    }
+}
+
+// A satellite whose residuals keep the same sign, e.g. multipath or a bad
+// ephemeris, pulls every fix away: once its mean residual stands out of its
+// own noise, it's flagged and left out of the next fixes, as long as enough
+// satellites remain. Its residuals are still computed against the fixes it's
+// no part of, and the flag clears once they're back around zero.
+#[derive(Default)]
+pub struct ResidualMonitor {
+    residuals: BTreeMap<SV, VecDeque<f64>>,
+    status: BTreeMap<SV, SvResidual>,
+}
+
+impl ResidualMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_status(&self) -> Vec<SvResidual> {
+        self.status.values().copied().collect()
+    }
+
+    // The ephemerides for the next fix: the biased satellites left out, the
+    // most biased first, while enough remain.
+    pub fn select(&mut self, ephs: Vec<Ephemeris>) -> Vec<Ephemeris> {
+        let mut biased: Vec<_> = (self.status.values())
+            .filter(|res| res.biased)
+            .filter(|res| ephs.iter().any(|eph| eph.sv == res.sv))
+            .map(|res| (res.sv, res.mean_m.abs()))
+            .collect();
+        biased.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let num_excluded = biased.len().min(ephs.len().saturating_sub(MIN_FIX_SVS));
+        let excluded: Vec<_> = biased.iter().take(num_excluded).map(|b| b.0).collect();
+        for res in self.status.values_mut() {
+            res.excluded = excluded.contains(&res.sv);
+        }
+        (ephs.into_iter())
+            .filter(|eph| !excluded.contains(&eph.sv))
+            .collect()
+    }
+
+    // After a fix at the receiver time ts_sec, with the ephemerides of the
+    // tracked satellites: the satellites whose flag changed. The ones gone
+    // are forgotten.
+    pub fn update(&mut self, ts_sec: f64, fix: [f64; 3], ephs: &[Ephemeris]) -> Vec<SV> {
+        let pos = (fix, get_ecef(fix));
+        // any local clock will do, its offset is common to all satellites:
+        // one close to gps time keeps the offsets small and precise
+        let Some(eph) = ephs.first() else {
+            return vec![];
+        };
+        let local = eph.tow_gpst + Duration::from_seconds(ts_sec - eph.ts_sec);
+        let offsets: Vec<_> = (ephs.iter())
+            .filter_map(|eph| Some((eph.sv, get_clock_offset(eph, ts_sec, local, pos)?)))
+            .collect();
+        let Some(median) = get_median(&offsets.iter().map(|o| o.1).collect::<Vec<_>>()) else {
+            return vec![];
+        };
+
+        self.residuals
+            .retain(|sv, _| ephs.iter().any(|eph| eph.sv == *sv));
+        self.status
+            .retain(|sv, _| ephs.iter().any(|eph| eph.sv == *sv));
+        let mut changed = vec![];
+        for (sv, offset) in offsets {
+            let diff = offset - median;
+            let residual_m = (diff - (diff / CODE_SEC).round() * CODE_SEC) * SPEED_OF_LIGHT;
+            let residuals = self.residuals.entry(sv).or_default();
+            residuals.push_back(residual_m);
+            if residuals.len() > RESIDUAL_WINDOW {
+                residuals.pop_front();
+            }
+            let num = residuals.len();
+            let mean_m = residuals.iter().sum::<f64>() / num as f64;
+            let rms_m = (residuals.iter().map(|r| r * r).sum::<f64>() / num as f64).sqrt();
+            let std_m = (rms_m.powi(2) - mean_m.powi(2)).max(0.0).sqrt();
+
+            let was_biased = self.status.get(&sv).is_some_and(|res| res.biased);
+            let biased = if was_biased {
+                mean_m.abs() >= BIAS_CLEAR_M
+            } else {
+                num >= MIN_RESIDUALS
+                    && mean_m.abs() >= BIAS_THRESHOLD_M
+                    && mean_m.abs() >= BIAS_SIGMA * std_m / (num as f64).sqrt()
+            };
+            if biased != was_biased {
+                changed.push(sv);
+            }
+            let excluded = self.status.get(&sv).is_some_and(|res| res.excluded);
+            self.status.insert(
+                sv,
+                SvResidual {
+                    sv,
+                    last_m: residual_m,
+                    mean_m,
+                    rms_m,
+                    num,
+                    biased,
+                    excluded,
+                },
+            );
+        }
+        changed
+    }
+}
+
+// The residuals after each fix as csv lines.
+pub struct ResidualWriter {
+    writer: BufWriter<File>,
+}
+
+impl ResidualWriter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "ts_sec,sv,residual_m,mean_m,rms_m,num,biased,excluded"
+        )?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, ts_sec: f64, residuals: &[SvResidual]) -> std::io::Result<()> {
+        for res in residuals {
+            writeln!(
+                self.writer,
+                "{ts_sec:.3},{},{:.2},{:.2},{:.2},{},{},{}",
+                res.sv, res.last_m, res.mean_m, res.rms_m, res.num, res.biased, res.excluded
+            )?;
+        }
+        self.writer.flush()
+    }
+}
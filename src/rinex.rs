@@ -0,0 +1,196 @@
+use chrono::NaiveDate;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use std::error::Error;
+use std::path::Path;
+
+use crate::ephemeris::Ephemeris;
+
+const SECS_PER_WEEK: f64 = 604800.0;
+
+// gps time (sec) of a calendar date and time, the epochs of a GPS navigation
+// file being in gps time
+pub fn get_gps_sec_from_date(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    min: u32,
+    sec: f64,
+) -> Option<f64> {
+    let gps_epoch = NaiveDate::from_ymd_opt(1980, 1, 6)?;
+    let days = (NaiveDate::from_ymd_opt(year, month, day)? - gps_epoch).num_days();
+    Some(days as f64 * 86400.0 + (hour * 3600 + min * 60) as f64 + sec)
+}
+
+// URA index of an accuracy in meters (IS-GPS-200 20.3.3.3.1.3)
+fn get_ura_index(accuracy_m: f64) -> u32 {
+    const URA_M: [f64; 15] = [
+        2.4, 3.4, 4.85, 6.85, 9.65, 13.65, 24.0, 48.0, 96.0, 192.0, 384.0, 768.0, 1536.0, 3072.0,
+        6144.0,
+    ];
+    URA_M.iter().position(|&m| accuracy_m <= m).unwrap_or(15) as u32
+}
+
+// the fixed-width numbers of a record line, D exponents included
+fn parse_fields(line: &str, start: usize, num: usize) -> Vec<f64> {
+    (0..num)
+        .map(|i| {
+            let beg = start + 19 * i;
+            let end = usize::min(beg + 19, line.len());
+            let field = line.get(beg..end).unwrap_or("");
+            field.trim().replace(['D', 'd'], "E").parse().unwrap_or(0.0)
+        })
+        .collect()
+}
+
+fn parse_int(s: Option<&str>) -> Result<i64, Box<dyn Error>> {
+    let s = s.ok_or("truncated record")?.trim();
+    s.parse().map_err(|e| format!("{s}: {e}").into())
+}
+
+// Ephemeris of a broadcast record: 8 lines, the epoch of its clock first.
+fn parse_gps_record(lines: &[&str], version: u32) -> Result<Option<Ephemeris>, Box<dyn Error>> {
+    let line0 = lines[0];
+    let (prn, date, start, orbit_start) = if version >= 3 {
+        if !line0.starts_with('G') {
+            return Ok(None);
+        }
+        let date = [
+            parse_int(line0.get(4..8))?,
+            parse_int(line0.get(9..11))?,
+            parse_int(line0.get(12..14))?,
+            parse_int(line0.get(15..17))?,
+            parse_int(line0.get(18..20))?,
+            parse_int(line0.get(21..23))?,
+        ];
+        (parse_int(line0.get(1..3))?, date, 23, 4)
+    } else {
+        let year = parse_int(line0.get(3..5))?;
+        let date = [
+            if year < 80 { 2000 + year } else { 1900 + year },
+            parse_int(line0.get(6..8))?,
+            parse_int(line0.get(9..11))?,
+            parse_int(line0.get(12..14))?,
+            parse_int(line0.get(15..17))?,
+            parse_int(
+                line0
+                    .get(17..22)
+                    .map(|s| s.trim().split('.').next().unwrap_or("")),
+            )?,
+        ];
+        (parse_int(line0.get(0..2))?, date, 22, 3)
+    };
+    let clock = parse_fields(line0, start, 3);
+    let orbits: Vec<_> = lines[1..8]
+        .iter()
+        .map(|line| parse_fields(line, orbit_start, 4))
+        .collect();
+
+    let toc_sec = get_gps_sec_from_date(
+        date[0] as i32,
+        date[1] as u32,
+        date[2] as u32,
+        date[3] as u32,
+        date[4] as u32,
+        date[5] as f64,
+    )
+    .ok_or("invalid epoch")?;
+
+    let mut eph = Ephemeris::new(SV::new(Constellation::GPS, prn as u8));
+    eph.f0 = clock[0];
+    eph.f1 = clock[1];
+    eph.f2 = clock[2];
+    eph.iode = orbits[0][0] as u32;
+    eph.crs = orbits[0][1];
+    eph.deln = orbits[0][2];
+    eph.m0 = orbits[0][3];
+    eph.cuc = orbits[1][0];
+    eph.ecc = orbits[1][1];
+    eph.cus = orbits[1][2];
+    eph.a = orbits[1][3].powi(2);
+    eph.toe = orbits[2][0] as u32;
+    eph.cic = orbits[2][1];
+    eph.omg0 = orbits[2][2];
+    eph.cis = orbits[2][3];
+    eph.i0 = orbits[3][0];
+    eph.crc = orbits[3][1];
+    eph.omg = orbits[3][2];
+    eph.omg_dot = orbits[3][3];
+    eph.i_dot = orbits[4][0];
+    eph.code = orbits[4][1] as u32;
+    eph.week = orbits[4][2] as u32;
+    eph.flag = orbits[4][3] as u32;
+    eph.sva = get_ura_index(orbits[5][0]);
+    eph.svh = orbits[5][1] as u32;
+    eph.tgd = orbits[5][2];
+    eph.iodc = orbits[5][3] as u32;
+    // fit interval in hours, the LNAV flag only tells whether it exceeds 4
+    eph.fit = (orbits[6][1] > 4.0) as u32;
+    eph.toc = (toc_sec % SECS_PER_WEEK).round() as u32;
+    eph.tow = eph.toe;
+    eph.set_gpst();
+    Ok(Some(eph))
+}
+
+// The GPS ephemerides of a RINEX 2 or 3 navigation file, as broadcast: a
+// satellite has a record every 2 hours or so. The other constellations of a
+// mixed file are skipped.
+pub fn read_rinex_nav(path: &Path) -> Result<Vec<Ephemeris>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut lines = text.lines();
+    let header = lines.next().ok_or("empty navigation file")?;
+    let version = header
+        .get(0..9)
+        .and_then(|s| s.trim().parse::<f64>().ok())
+        .ok_or_else(|| format!("{}: not a RINEX file", path.display()))? as u32;
+    for line in lines.by_ref() {
+        if line.contains("END OF HEADER") {
+            break;
+        }
+    }
+
+    let body: Vec<_> = lines.filter(|line| !line.trim().is_empty()).collect();
+    let mut ephs = vec![];
+    let mut i = 0;
+    while i < body.len() {
+        // other constellations have records of other sizes
+        let len = match body[i].chars().next() {
+            Some('R') | Some('S') => 4,
+            _ => 8,
+        };
+        if i + len > body.len() {
+            break;
+        }
+        if let Some(eph) = parse_gps_record(&body[i..i + len], version)
+            .map_err(|e| format!("{}: record {}: {e}", path.display(), ephs.len() + 1))?
+        {
+            ephs.push(eph);
+        }
+        i += len;
+    }
+    tracing::info!(
+        "{}: {} GPS ephemerides, RINEX {version}",
+        path.display(),
+        ephs.len()
+    );
+    Ok(ephs)
+}
+
+fn get_toe_sec(eph: &Ephemeris) -> f64 {
+    eph.week as f64 * SECS_PER_WEEK + eph.toe as f64
+}
+
+// the ephemeris of sv closest to gps time t_sec
+pub fn get_closest_ephemeris(ephs: &[Ephemeris], sv: SV, t_sec: f64) -> Option<Ephemeris> {
+    let dist = |eph: &Ephemeris| (get_toe_sec(eph) - t_sec).abs();
+    ephs.iter()
+        .filter(|eph| eph.sv == sv)
+        .min_by(|&a, &b| dist(a).total_cmp(&dist(b)))
+        .copied()
+}
+
+// gps time (sec) of the earliest ephemeris, a default start for a simulation
+pub fn get_first_toe_sec(ephs: &[Ephemeris]) -> Option<f64> {
+    ephs.iter().map(get_toe_sec).min_by(f64::total_cmp)
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::code::Code;
+use crate::device::{DeviceConfig, get_rtlsdr_devices};
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleQueue, SampleSource};
+
+const RTLSDR_REOPEN_PERIOD_SEC: f64 = 1.0;
+
+pub struct RtlSdrDevice {
+    controller: rtlsdr_mt::Controller,
+    queue: Arc<SampleQueue>,
+    num_sleep: u64,
+    sig: String,
+    fs: f64,
+    config: DeviceConfig,
+    stop_req: Arc<AtomicBool>,
+    lost: Arc<AtomicBool>, // the read thread exited without being asked to
+    last_reopen: Option<Instant>,
+    time_jump: bool,
+}
+
+impl Drop for RtlSdrDevice {
+    fn drop(&mut self) {
+        tracing::info!(
+            "rtlsdr: stopping read. num_samples={} dropped={}",
+            self.queue.get_num_samples_total(),
+            self.queue.get_num_dropped_total()
+        );
+        tracing::debug!("rtlsdr: num_sleep={}", self.num_sleep);
+
+        self.stop_req.store(true, Ordering::SeqCst);
+        self.controller.cancel_async_read();
+    }
+}
+
+impl SampleSource for RtlSdrDevice {
+    fn read_block(
+        &mut self,
+        _off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        loop {
+            if let Some(block) = self.queue.pop(num_samples) {
+                return Ok(SampleBlock {
+                    time_jump: std::mem::take(&mut self.time_jump),
+                    ..block
+                });
+            }
+            if self.lost.load(Ordering::SeqCst) {
+                self.reopen()?;
+                continue;
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+            self.num_sleep += 1;
+        }
+    }
+
+    fn is_device_lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    fn get_num_samples_queued(&self) -> Option<usize> {
+        Some(self.queue.get_num_samples())
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+impl RtlSdrDevice {
+    #[allow(clippy::result_unit_err)]
+    pub fn new(sig: &str, fs: f64, config: &DeviceConfig) -> Result<RtlSdrDevice, ()> {
+        for dev in get_rtlsdr_devices() {
+            tracing::info!("found rtl-sdr: {dev}");
+        }
+
+        let queue = Arc::new(config.new_sample_queue(fs));
+        let stop_req = Arc::new(AtomicBool::new(false));
+        let lost = Arc::new(AtomicBool::new(false));
+        let controller = Self::open(
+            sig,
+            fs,
+            config,
+            queue.clone(),
+            stop_req.clone(),
+            lost.clone(),
+        )?;
+
+        Ok(Self {
+            controller,
+            queue,
+            num_sleep: 0,
+            sig: sig.to_string(),
+            fs,
+            config: config.clone(),
+            stop_req,
+            lost,
+            last_reopen: None,
+            time_jump: false,
+        })
+    }
+
+    // the dongle was unplugged or crashed: try to open it again, at most once
+    // per RTLSDR_REOPEN_PERIOD_SEC. Errors until the device is back.
+    fn reopen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self
+            .last_reopen
+            .is_some_and(|ts| ts.elapsed() < Duration::from_secs_f64(RTLSDR_REOPEN_PERIOD_SEC))
+        {
+            return Err("rtlsdr: device lost".into());
+        }
+        self.last_reopen = Some(Instant::now());
+        tracing::warn!("rtlsdr: device lost, trying to re-open..");
+
+        // samples left from before the loss aren't contiguous with the new ones
+        self.queue.clear();
+
+        let controller = Self::open(
+            &self.sig,
+            self.fs,
+            &self.config,
+            self.queue.clone(),
+            self.stop_req.clone(),
+            self.lost.clone(),
+        )
+        .map_err(|_| "rtlsdr: device lost")?;
+
+        tracing::info!("rtlsdr: device re-opened");
+        self.controller = controller;
+        self.lost.store(false, Ordering::SeqCst);
+        self.time_jump = true;
+        Ok(())
+    }
+
+    fn open(
+        sig: &str,
+        fs: f64,
+        config: &DeviceConfig,
+        queue: Arc<SampleQueue>,
+        stop_req: Arc<AtomicBool>,
+        lost: Arc<AtomicBool>,
+    ) -> Result<rtlsdr_mt::Controller, ()> {
+        // several dongles: --device-args selects the index
+        let index = config.device_args.parse::<u32>().unwrap_or(0);
+        let (mut controller, mut reader) = rtlsdr_mt::open(index)?;
+
+        let mut tunes = rtlsdr_mt::TunerGains::default();
+        let gains = controller.tuner_gains(&mut tunes);
+        tracing::info!("gain: {:?}", gains);
+
+        // gains are in tenths of dB: use the closest supported one, or the max.
+        let gain = match config.gain {
+            Some(g) => *gains
+                .iter()
+                .min_by_key(|&&v| (v - (g * 10.0) as i32).abs())
+                .unwrap(),
+            None => *gains.iter().max().unwrap(),
+        };
+
+        if config.agc.unwrap_or(false) {
+            tracing::info!("Using agc");
+            controller.enable_agc()?;
+        } else {
+            tracing::info!("Using gain: {gain}");
+            controller.disable_agc()?;
+            controller.set_tuner_gain(gain)?;
+        }
+        controller.set_bias_tee(config.bias_tee.unwrap_or(true) as i32)?;
+        controller.set_center_freq(Code::get_code_freq(sig) as u32)?;
+        controller.set_sample_rate(fs as u32)?;
+        if config.ppm != 0 {
+            controller.set_ppm(config.ppm)?;
+        }
+        controller.reset_buffer()?;
+        let ppm = controller.ppm();
+
+        tracing::info!("ppm={ppm}");
+
+        thread::spawn(move || {
+            tracing::debug!("starting async_read");
+            let res = reader.read_async(0, 0, |array| {
+                let mut v = vec![IQSample::default(); array.len() / 2];
+                for i in 0..array.len() / 2 {
+                    let re = (array[2 * i] as Float - 127.3) / 128.0;
+                    let im = (array[2 * i + 1] as Float - 127.3) / 128.0;
+                    v[i] = IQSample { re, im };
+                }
+                queue.push(v);
+            });
+            if !stop_req.load(Ordering::SeqCst) {
+                tracing::warn!("rtlsdr: async_read exited: {res:?}");
+                lost.store(true, Ordering::SeqCst);
+            }
+        });
+
+        Ok(controller)
+    }
+}
@@ -0,0 +1,20 @@
+use once_cell::sync::Lazy;
+use tokio::runtime::{Builder, Runtime};
+
+const NET_WORKER_THREADS: usize = 2;
+
+// One async runtime for all the sockets (rtl_tcp, outputs, control APIs): a
+// couple of workers service any number of connections, instead of a thread
+// blocked on each of them.
+static NET_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Builder::new_multi_thread()
+        .worker_threads(NET_WORKER_THREADS)
+        .thread_name("gnss-rcv-net")
+        .enable_all()
+        .build()
+        .expect("Failed to start the network runtime")
+});
+
+pub fn get_net_runtime() -> &'static Runtime {
+    &NET_RUNTIME
+}
@@ -0,0 +1,17 @@
+use rustfft::num_complex::{Complex, Complex64};
+
+// Precision of the sample path, from the sources through the FFTs and the
+// correlators: f64 by default, f32 with the `f32` feature. The tracking
+// loops and the navigation stay in f64.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+pub type IQSample = Complex<Float>;
+
+// the correlator outputs feed the tracking loops in f64
+#[allow(clippy::unnecessary_cast)]
+pub fn to_complex64(c: IQSample) -> Complex64 {
+    Complex64::new(c.re as f64, c.im as f64)
+}
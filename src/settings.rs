@@ -0,0 +1,337 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::device::{DeviceConfig, DeviceType, get_rtlsdr_devices};
+use crate::receiver::{DEFAULT_FS, ReceiverBuilder};
+use crate::recording::IQFileType;
+use crate::sidecar::IQSidecar;
+use crate::start::get_unix_time_now;
+
+const SIGNALS: [&str; 1] = ["L1CA"];
+const IQ_FILE_TYPES: [IQFileType; 4] = [
+    IQFileType::TypePairFloat32,
+    IQFileType::TypePairInt16,
+    IQFileType::TypeRtlSdrFile,
+    IQFileType::TypeOneInt8,
+];
+const SOURCES: [SourceKind; 4] = [
+    SourceKind::File,
+    SourceKind::Device,
+    SourceKind::RtlTcp,
+    SourceKind::Noise,
+];
+const DEVICE_TYPES: [DeviceType; 4] = [
+    DeviceType::RtlSdr,
+    DeviceType::HackRf,
+    DeviceType::Airspy,
+    DeviceType::Uhd,
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SourceKind {
+    File,
+    Device,
+    RtlTcp,
+    Noise,
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SourceKind::File => write!(f, "file"),
+            SourceKind::Device => write!(f, "device"),
+            SourceKind::RtlTcp => write!(f, "rtl_tcp"),
+            SourceKind::Noise => write!(f, "noise"),
+        }
+    }
+}
+
+// What the receiver is built from in the UI: the options of the command line,
+// checked by the builder before a start. Saved with the session.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub source: SourceKind,
+    pub iq_file: String,
+    pub iq_file_type: IQFileType,
+    pub loop_file: bool,
+    pub device_type: DeviceType,
+    pub device_args: String,
+    pub manual_gain: bool,
+    pub gain_db: f64,
+    pub agc: bool,
+    pub bias_tee: bool,
+    pub ppm: i32,
+    pub hostname: String,
+    pub fs_khz: f64,
+    pub fs_internal_khz: f64,
+    pub fi_khz: f64,
+    pub off_msec: usize,
+    pub sig: String,
+    pub sats: String, // comma-separated prns, all when empty
+    #[serde(skip)]
+    pub rtlsdr_devices: Vec<String>, // enumerated on refresh
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            source: SourceKind::File,
+            iq_file: "resources/nov_3_time_18_48_st_ives".to_owned(),
+            iq_file_type: IQFileType::TypePairFloat32,
+            loop_file: false,
+            device_type: DeviceType::RtlSdr,
+            device_args: String::new(),
+            manual_gain: false,
+            gain_db: 40.0,
+            agc: false,
+            bias_tee: true,
+            ppm: 0,
+            hostname: "localhost:1234".to_owned(),
+            fs_khz: DEFAULT_FS / 1000.0,
+            fs_internal_khz: DEFAULT_FS / 1000.0,
+            fi_khz: 0.0,
+            off_msec: 0,
+            sig: SIGNALS[0].to_string(),
+            sats: String::new(),
+            rtlsdr_devices: vec![],
+        }
+    }
+}
+
+impl Settings {
+    fn get_device_config(&self) -> DeviceConfig {
+        DeviceConfig {
+            device_type: self.device_type.clone(),
+            device_args: self.device_args.clone(),
+            gain: self.manual_gain.then_some(self.gain_db),
+            agc: Some(self.agc),
+            bias_tee: Some(self.bias_tee),
+            ppm: self.ppm,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_builder(&self) -> ReceiverBuilder {
+        let builder = match self.source {
+            SourceKind::File => ReceiverBuilder::from_file(Path::new(&self.iq_file))
+                .iq_file_type(&self.iq_file_type)
+                .loop_file(self.loop_file),
+            SourceKind::Device => ReceiverBuilder::from_device(&self.get_device_config()),
+            SourceKind::RtlTcp => {
+                ReceiverBuilder::from_rtl_tcp(&self.hostname, &self.get_device_config())
+            }
+            SourceKind::Noise => ReceiverBuilder::from_noise(),
+        };
+        builder
+            .fs(self.fs_khz * 1000.0)
+            .fs_internal(self.fs_internal_khz * 1000.0)
+            .fi(self.fi_khz * 1000.0)
+            .off_msec(self.off_msec)
+            .signal(&self.sig)
+            .sats(&self.sats)
+    }
+
+    // a recording, with the parameters of its sidecar if any
+    pub fn set_replay(&mut self, path: &Path) {
+        self.source = SourceKind::File;
+        self.iq_file = path.display().to_string();
+        match IQSidecar::read(path) {
+            Ok(Some(sidecar)) => {
+                if let Ok(file_type) = sidecar.file_type.parse() {
+                    self.iq_file_type = file_type;
+                }
+                self.fs_khz = sidecar.fs / 1000.0;
+                self.fs_internal_khz = sidecar.fs / 1000.0;
+                self.fi_khz = sidecar.fi / 1000.0;
+                self.sig = sidecar.sig;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("{}: bad sidecar: {e}", path.display()),
+        }
+    }
+
+    // time of the first sample: from the sidecar of a recording, now otherwise
+    pub fn get_start_unix(&self) -> f64 {
+        if self.source != SourceKind::File {
+            return get_unix_time_now();
+        }
+        let start_time = IQSidecar::read(Path::new(&self.iq_file))
+            .ok()
+            .flatten()
+            .and_then(|sidecar| sidecar.start_time)
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok());
+        match start_time {
+            Some(t) => t.timestamp_millis() as f64 / 1000.0 + self.off_msec as f64 / 1000.0,
+            None => get_unix_time_now(),
+        }
+    }
+
+    pub fn refresh_devices(&mut self) {
+        self.rtlsdr_devices = get_rtlsdr_devices();
+    }
+
+    pub fn update_source_kind(&mut self, ui: &mut egui::Ui) {
+        for source in SOURCES {
+            ui.selectable_value(&mut self.source, source, source.to_string());
+        }
+    }
+
+    // several dongles: the index goes in the device args
+    pub fn update_rtlsdr_pick(&mut self, ui: &mut egui::Ui) {
+        let index = self.device_args.parse::<usize>().unwrap_or(0);
+        let selected = match self.rtlsdr_devices.get(index) {
+            Some(name) => format!("#{index}: {name}"),
+            None => "no rtl-sdr found".to_string(),
+        };
+        egui::ComboBox::from_id_salt("rtlsdr_pick")
+            .width(200.0)
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (i, name) in self.rtlsdr_devices.iter().enumerate() {
+                    if ui
+                        .selectable_label(i == index, format!("#{i}: {name}"))
+                        .clicked()
+                    {
+                        self.device_args = i.to_string();
+                    }
+                }
+            });
+        if ui.button("refresh").clicked() {
+            self.refresh_devices();
+        }
+    }
+
+    pub fn update_gain(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.manual_gain, "manual");
+        ui.add_enabled(
+            self.manual_gain,
+            egui::Slider::new(&mut self.gain_db, 0.0..=50.0).suffix(" dB"),
+        );
+        ui.checkbox(&mut self.agc, "agc");
+        ui.checkbox(&mut self.bias_tee, "bias-tee");
+        ui.add(
+            egui::DragValue::new(&mut self.ppm)
+                .range(-200..=200)
+                .suffix(" ppm"),
+        );
+    }
+
+    fn update_source(&mut self, ui: &mut egui::Ui) {
+        ui.label("source");
+        ui.horizontal(|ui| self.update_source_kind(ui));
+        ui.end_row();
+
+        match self.source {
+            SourceKind::File => {
+                ui.label("iq file");
+                ui.add(egui::TextEdit::singleline(&mut self.iq_file).desired_width(300.0));
+                ui.end_row();
+                ui.label("iq format");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("iq_file_type")
+                        .selected_text(self.iq_file_type.to_string())
+                        .show_ui(ui, |ui| {
+                            for t in IQ_FILE_TYPES {
+                                let text = t.to_string();
+                                ui.selectable_value(&mut self.iq_file_type, t, text);
+                            }
+                        });
+                    ui.checkbox(&mut self.loop_file, "loop");
+                });
+                ui.end_row();
+            }
+            SourceKind::Device | SourceKind::RtlTcp => {
+                if self.source == SourceKind::RtlTcp {
+                    ui.label("rtl_tcp host");
+                    ui.add(egui::TextEdit::singleline(&mut self.hostname).desired_width(150.0));
+                    ui.end_row();
+                } else {
+                    ui.label("device");
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("device_type")
+                            .selected_text(self.device_type.to_string())
+                            .show_ui(ui, |ui| {
+                                for t in DEVICE_TYPES {
+                                    let text = t.to_string();
+                                    ui.selectable_value(&mut self.device_type, t, text);
+                                }
+                            });
+                        if self.device_type == DeviceType::RtlSdr {
+                            self.update_rtlsdr_pick(ui);
+                        } else {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.device_args)
+                                    .hint_text("device args")
+                                    .desired_width(150.0),
+                            );
+                        }
+                    });
+                    ui.end_row();
+                }
+                ui.label("gain");
+                ui.horizontal(|ui| self.update_gain(ui));
+                ui.end_row();
+            }
+            SourceKind::Noise => {}
+        }
+    }
+
+    fn update_sampling(&mut self, ui: &mut egui::Ui) {
+        ui.label("fs");
+        ui.add(
+            egui::DragValue::new(&mut self.fs_khz)
+                .range(500.0..=100_000.0)
+                .suffix(" KHz"),
+        );
+        ui.end_row();
+        ui.label("fs internal");
+        ui.add(
+            egui::DragValue::new(&mut self.fs_internal_khz)
+                .range(500.0..=100_000.0)
+                .suffix(" KHz"),
+        );
+        ui.end_row();
+        ui.label("fi");
+        ui.add(
+            egui::DragValue::new(&mut self.fi_khz)
+                .range(-50_000.0..=50_000.0)
+                .suffix(" KHz"),
+        );
+        ui.end_row();
+    }
+
+    fn update_signal(&mut self, ui: &mut egui::Ui) {
+        ui.label("signal");
+        egui::ComboBox::from_id_salt("signal")
+            .selected_text(self.sig.clone())
+            .show_ui(ui, |ui| {
+                for s in SIGNALS {
+                    ui.selectable_value(&mut self.sig, s.to_string(), s);
+                }
+            });
+        ui.end_row();
+        ui.label("satellites");
+        ui.add(
+            egui::TextEdit::singleline(&mut self.sats)
+                .hint_text("all, or e.g. 5,7,13")
+                .desired_width(150.0),
+        );
+        ui.end_row();
+        ui.label("skip");
+        ui.add(egui::DragValue::new(&mut self.off_msec).suffix(" msec"));
+        ui.end_row();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("SettingsGrid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                self.update_source(ui);
+                self.update_sampling(ui);
+                self.update_signal(ui);
+            });
+    }
+}
@@ -0,0 +1,144 @@
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::recording::IQFileType;
+
+pub const SIDECAR_EXT: &str = "meta.json";
+
+// Small json file written next to the recordings made by gnss-rcv, describing
+// how to interpret the samples and allowing to detect truncated/corrupted files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IQSidecar {
+    pub file_type: String,
+    pub fs: f64,
+    pub fi: f64,
+    pub sig: String,
+    pub start_time: Option<String>, // RFC 3339
+    pub sha256: String,
+}
+
+pub fn get_sidecar_path(file_path: &Path) -> PathBuf {
+    let mut s = file_path.as_os_str().to_owned();
+    s.push(".");
+    s.push(SIDECAR_EXT);
+    PathBuf::from(s)
+}
+
+pub fn compute_sha256(file_path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(file_path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl IQSidecar {
+    pub fn new(
+        file_path: &Path,
+        file_type: &IQFileType,
+        fs: f64,
+        fi: f64,
+        sig: &str,
+        start_time: Option<String>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            file_type: file_type.to_string(),
+            fs,
+            fi,
+            sig: sig.to_string(),
+            start_time,
+            sha256: compute_sha256(file_path)?,
+        })
+    }
+
+    pub fn write(&self, file_path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(get_sidecar_path(file_path), json)
+    }
+
+    // None when there is no sidecar next to file_path.
+    pub fn read(file_path: &Path) -> std::io::Result<Option<Self>> {
+        let sidecar_path = get_sidecar_path(file_path);
+        if !sidecar_path.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(sidecar_path)?;
+        let sidecar = serde_json::from_str(&json).map_err(std::io::Error::other)?;
+        Ok(Some(sidecar))
+    }
+}
+
+// Warns about CLI options contradicting the sidecar and, with verify, about
+// content that doesn't match the recorded checksum: hashing reads the whole
+// recording. Returns the sidecar if any.
+pub fn check_sidecar(
+    file_path: &Path,
+    file_type: &IQFileType,
+    fs: f64,
+    fi: f64,
+    sig: &str,
+    verify: bool,
+) -> std::io::Result<Option<IQSidecar>> {
+    let Some(sidecar) = IQSidecar::read(file_path)? else {
+        return Ok(None);
+    };
+    tracing::info!(
+        "{}: sidecar {} fs={:.1} KHz fi={:.1} KHz sig={} start={}",
+        file_path.display(),
+        sidecar.file_type,
+        sidecar.fs / 1000.0,
+        sidecar.fi / 1000.0,
+        sidecar.sig,
+        sidecar.start_time.as_deref().unwrap_or("unknown")
+    );
+    if sidecar.file_type != file_type.to_string() {
+        tracing::warn!(
+            "{}: file type {} contradicts sidecar {}",
+            file_path.display(),
+            file_type.to_string().red(),
+            sidecar.file_type.green()
+        );
+    }
+    if sidecar.fs != fs {
+        tracing::warn!(
+            "{}: fs {} contradicts sidecar {}",
+            file_path.display(),
+            fs.to_string().red(),
+            sidecar.fs.to_string().green()
+        );
+    }
+    if sidecar.fi != fi {
+        tracing::warn!(
+            "{}: fi {} contradicts sidecar {}",
+            file_path.display(),
+            fi.to_string().red(),
+            sidecar.fi.to_string().green()
+        );
+    }
+    if sidecar.sig != sig {
+        tracing::warn!(
+            "{}: signal {} contradicts sidecar {}",
+            file_path.display(),
+            sig.red(),
+            sidecar.sig.green()
+        );
+    }
+    if verify && compute_sha256(file_path)? != sidecar.sha256 {
+        tracing::warn!(
+            "{}: {}",
+            file_path.display(),
+            "sha256 mismatch, recording is truncated or corrupted".red()
+        );
+    }
+    Ok(Some(sidecar))
+}
@@ -0,0 +1,497 @@
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+use map_3d::{Ellipsoid, ecef2geodetic, geodetic2ecef};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::code::{Code, CodeTables};
+use crate::constants::{BDT_GPST_OFFSET_SEC, EARTH_ROTATION_RATE, SPEED_OF_LIGHT};
+use crate::ephemeris::Ephemeris;
+use crate::navigation::nav_encode_lnav_parity;
+use crate::navigation_bds::bds_d1_encode_bits;
+use crate::noise::NoiseSource;
+use crate::rinex::get_closest_ephemeris;
+use crate::sample::{Float, IQSample};
+use crate::sky::get_azimuth_elevation;
+use crate::solver::get_sv_position_ecef;
+use crate::source::{SampleBlock, SampleSource};
+
+const PI: f64 = std::f64::consts::PI;
+const SECS_PER_WEEK: f64 = 604800.0;
+const SIM_NOISE_POWER: f64 = 0.125; // complex noise: 2xi16 and 2xu8 recordings don't clip
+const SIM_NOISE_SEED: u64 = 0x5eed;
+const SIM_NOMINAL_DELAY: f64 = 0.07; // sec, without a trajectory
+const SIM_MIN_ELEVATION: f64 = 5.0; // degrees
+
+// A simulated satellite: with an ephemeris, its LNAV subframes modulate the
+// signal, and with a trajectory its orbit gives the delay and doppler.
+#[derive(Clone, Debug)]
+pub struct SimSv {
+    pub sv: SV,
+    pub cn0: f64,               // dB-Hz
+    pub doppler_hz: f64,        // without a trajectory
+    pub code_phase_chips: f64,  // at the first sample, without a trajectory
+    pub eph: Option<Ephemeris>, // nav data, geometry with a trajectory
+}
+
+impl SimSv {
+    // unmodulated, at a doppler and code phase derived from the prn
+    pub fn new(sv: SV, cn0: f64) -> Self {
+        let prn = sv.prn as f64;
+        Self {
+            sv,
+            cn0,
+            doppler_hz: (prn * 733.0) % 8000.0 - 4000.0,
+            code_phase_chips: (prn * 97.0) % 1023.0,
+            eph: None,
+        }
+    }
+}
+
+fn get_ecef(lat: f64, lon: f64, height: f64) -> [f64; 3] {
+    let (x, y, z) = geodetic2ecef(lat.to_radians(), lon.to_radians(), height, Ellipsoid::WGS84);
+    [x, y, z]
+}
+
+// Where the simulated receiver is: ecef positions at times from the start of
+// the simulation, linearly interpolated.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    points: Vec<(f64, [f64; 3])>,
+}
+
+impl Trajectory {
+    // latitude, longitude (degrees) and height (m)
+    pub fn fixed(lat: f64, lon: f64, height: f64) -> Self {
+        Self {
+            points: vec![(0.0, get_ecef(lat, lon, height))],
+        }
+    }
+
+    // lines of t_sec,lat,lon,height: seconds from the start, degrees and m
+    pub fn read_csv(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut points: Vec<(f64, [f64; 3])> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("t_sec") {
+                continue;
+            }
+            let v = (line.split(','))
+                .map(|s| s.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+            if v.len() != 4 {
+                return Err(format!(
+                    "{}:{}: expected t_sec,lat,lon,height",
+                    path.display(),
+                    i + 1
+                )
+                .into());
+            }
+            if points.last().is_some_and(|&(t, _)| v[0] <= t) {
+                return Err(format!("{}:{}: time going backwards", path.display(), i + 1).into());
+            }
+            points.push((v[0], get_ecef(v[1], v[2], v[3])));
+        }
+        if points.is_empty() {
+            return Err(format!("{}: no position", path.display()).into());
+        }
+        Ok(Self { points })
+    }
+
+    // ecef position at t_sec, the first and last ones held outside
+    pub fn get_position(&self, t_sec: f64) -> [f64; 3] {
+        let i = self.points.partition_point(|&(t, _)| t <= t_sec);
+        if i == 0 {
+            return self.points[0].1;
+        }
+        if i == self.points.len() {
+            return self.points[i - 1].1;
+        }
+        let (t0, p0) = self.points[i - 1];
+        let (t1, p1) = self.points[i];
+        let a = (t_sec - t0) / (t1 - t0);
+        std::array::from_fn(|j| p0[j] + a * (p1[j] - p0[j]))
+    }
+
+    // latitude, longitude (degrees) and height (m) at t_sec
+    pub fn get_geodetic(&self, t_sec: f64) -> [f64; 3] {
+        let [x, y, z] = self.get_position(t_sec);
+        let (lat, lon, h) = ecef2geodetic(x, y, z, Ellipsoid::WGS84);
+        [lat.to_degrees(), lon.to_degrees(), h]
+    }
+}
+
+fn get_toe_sec(eph: &Ephemeris) -> f64 {
+    eph.week as f64 * SECS_PER_WEEK + eph.toe as f64
+}
+
+fn get_toc_sec(eph: &Ephemeris) -> f64 {
+    eph.week as f64 * SECS_PER_WEEK + eph.toc as f64
+}
+
+// satellite position at gps time t_sec
+fn get_sv_ecef(eph: &Ephemeris, t_sec: f64) -> [f64; 3] {
+    let t = eph.toe_gpst + Duration::from_seconds(t_sec - get_toe_sec(eph));
+    let (x, y, z) = get_sv_position_ecef(eph, t);
+    [x, y, z]
+}
+
+// A reflection of the signals: later than the direct path by delay_m, weaker
+// by attenuation_db, its phase rotating at fading_hz relative to it.
+#[derive(Clone, Copy, Debug)]
+pub struct MultipathRay {
+    pub delay_m: f64,
+    pub attenuation_db: f64,
+    pub fading_hz: f64,
+}
+
+// delay_m:attenuation_db, optionally followed by :fading_hz
+impl FromStr for MultipathRay {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<MultipathRay, Self::Err> {
+        let v = (input.split(':'))
+            .map(|s| s.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("multipath {input}: {e}"))?;
+        match v[..] {
+            [delay_m, attenuation_db] => Ok(MultipathRay {
+                delay_m,
+                attenuation_db,
+                fading_hz: 0.0,
+            }),
+            [delay_m, attenuation_db, fading_hz] => Ok(MultipathRay {
+                delay_m,
+                attenuation_db,
+                fading_hz,
+            }),
+            _ => Err(
+                format!("multipath {input}: expected delay_m:attenuation_db[:fading_hz]").into(),
+            ),
+        }
+    }
+}
+
+// What happens to the signals on their way to the samples, on top of the
+// noise: the known truth to check the receiver against.
+#[derive(Clone, Debug, Default)]
+pub struct SimImpairments {
+    pub multipath: Vec<MultipathRay>, // same rays for all the satellites
+    pub clock_ppm: f64,               // frequency error of the receiver clock
+    pub clock_drift_ppm: f64,         // change of clock_ppm per second
+    pub iono_zenith_m: f64,           // L1 ionospheric delay at the zenith
+}
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub svs: Vec<SimSv>,
+    pub trajectory: Option<Trajectory>,
+    pub start_gps_sec: f64, // gps time of the first sample
+    pub seed: u64,
+    pub impairments: SimImpairments,
+}
+
+impl SimConfig {
+    pub fn from_sats(sats: &[SV], cn0: f64) -> Self {
+        Self {
+            svs: sats.iter().map(|&sv| SimSv::new(sv, cn0)).collect(),
+            trajectory: None,
+            start_gps_sec: 0.0,
+            seed: SIM_NOISE_SEED,
+            impairments: SimImpairments::default(),
+        }
+    }
+
+    // The satellites of a navigation file, only sats if not empty, above the
+    // horizon at the start of the trajectory.
+    pub fn from_ephemerides(
+        ephs: &[Ephemeris],
+        sats: &[SV],
+        trajectory: Trajectory,
+        start_gps_sec: f64,
+        cn0: f64,
+    ) -> Self {
+        let pos = trajectory.get_geodetic(0.0);
+        let mut all_svs: Vec<SV> = ephs.iter().map(|eph| eph.sv).collect();
+        all_svs.sort();
+        all_svs.dedup();
+
+        let mut svs = vec![];
+        for sv in all_svs {
+            if !sats.is_empty() && !sats.contains(&sv) {
+                continue;
+            }
+            let Some(eph) = get_closest_ephemeris(ephs, sv, start_gps_sec) else {
+                continue;
+            };
+            let (azimuth, elevation) = get_azimuth_elevation(pos, get_sv_ecef(&eph, start_gps_sec));
+            if elevation < SIM_MIN_ELEVATION {
+                continue;
+            }
+            tracing::info!("sim: {sv} azimuth={azimuth:.0} elevation={elevation:.0}");
+            svs.push(SimSv {
+                eph: Some(eph),
+                ..SimSv::new(sv, cn0)
+            });
+        }
+        Self {
+            svs,
+            trajectory: Some(trajectory),
+            start_gps_sec,
+            seed: SIM_NOISE_SEED,
+            impairments: SimImpairments::default(),
+        }
+    }
+}
+
+struct SimChannel {
+    sv: SimSv,
+    code: Vec<i8>,
+    chip_rate: f64,
+    code_sec: f64,
+    amplitude: f64,
+    secondary_code: Vec<i8>,           // over the code periods, empty if none
+    fc: f64,                           // carrier of the satellite
+    carrier_offset: f64,               // to the fc of the signal: GLONASS frequency channels
+    subframes: BTreeMap<i64, Vec<u8>>, // bits of the subframes in use, by index in the week
+}
+
+impl SimChannel {
+    // data bit transmitted at t_sv (sec of the week), as +1 or -1: LNAV, or
+    // D1 in BDT for BeiDou
+    fn get_nav_bit(&mut self, t_sv: f64) -> f64 {
+        let Some(eph) = &self.sv.eph else {
+            return 1.0;
+        };
+        let bds = self.sv.sv.constellation == Constellation::BeiDou;
+        let offset = if bds { BDT_GPST_OFFSET_SEC as f64 } else { 0.0 };
+        let tow = (t_sv - offset).rem_euclid(SECS_PER_WEEK);
+        let k = (tow / 6.0) as i64;
+        let n = usize::min(((tow - k as f64 * 6.0) / 0.02) as usize, 299);
+        if !self.subframes.contains_key(&k) && self.subframes.len() > 2 {
+            self.subframes.retain(|&j, _| (j - k).abs() <= 1);
+        }
+        let bits = self.subframes.entry(k).or_insert_with(|| {
+            let id = (k % 5 + 1) as u32;
+            if bds {
+                return bds_d1_encode_bits(&eph.nav_encode_d1_subframe(id, (k * 6) as u32));
+            }
+            let tow_next = ((k + 1) * 6) as u32 % SECS_PER_WEEK as u32;
+            nav_encode_lnav_parity(&eph.nav_encode_lnav_subframe(id, tow_next))
+        });
+        if bits[n] != 0 { -1.0 } else { 1.0 }
+    }
+
+    // chip of the secondary code on the code period transmitted at t_sv
+    fn get_secondary_chip(&self, t_sv: f64) -> f64 {
+        let len = self.secondary_code.len() as i64;
+        if len == 0 {
+            return 1.0;
+        }
+        let k = (t_sv / self.code_sec).floor() as i64;
+        self.secondary_code[k.rem_euclid(len) as usize] as f64
+    }
+}
+
+// Complex baseband samples of the satellites of a SimConfig over gaussian
+// noise: the sample offset is the time since the start, so that the source
+// can be read again from anywhere with the same result.
+pub struct SimSource {
+    channels: Vec<SimChannel>,
+    trajectory: Option<Trajectory>,
+    noise: NoiseSource,
+    fs: f64,
+    start_gps_sec: f64,
+    start_tow: f64,
+    impairments: SimImpairments,
+    paths: Vec<(f64, f64, f64)>, // delay (sec), gain and fading (Hz), the direct one first
+}
+
+// Klobuchar's slant factor of the ionospheric delay at an elevation (degrees)
+fn get_iono_obliquity(elevation: f64) -> f64 {
+    1.0 + 16.0 * (0.53 - elevation / 180.0).powi(3)
+}
+
+impl SimSource {
+    // each satellite on the signal of its constellation in sig, a list such
+    // as L1CA,E1B, with the codes of the receiver
+    pub fn new(
+        config: SimConfig,
+        sig: &str,
+        codes: &CodeTables,
+        fs: f64,
+    ) -> Result<Self, Box<dyn Error>> {
+        let sigs = Code::get_sig_list(sig);
+        let mut channels = vec![];
+        for sv in config.svs {
+            let sig = Code::get_constellation_signal(&sigs, sv.sv.constellation)
+                .ok_or_else(|| format!("sim: no signal for {} in {sig}", sv.sv))?;
+            let code = codes
+                .gen_code(sig, sv.sv.prn)
+                .ok_or_else(|| format!("sim: no {sig} code for {}", sv.sv))?;
+            let amplitude = (10f64.powf(sv.cn0 / 10.0) * SIM_NOISE_POWER / fs).sqrt();
+            let carrier_offset = codes.get_carrier_offset(sig, sv.sv.prn);
+            channels.push(SimChannel {
+                sv,
+                chip_rate: Code::get_code_len(sig) as f64 / Code::get_code_period(sig),
+                code_sec: Code::get_code_period(sig),
+                code,
+                amplitude,
+                secondary_code: Code::get_secondary_code(sig, sv.sv.prn),
+                fc: Code::get_code_freq(sig) + carrier_offset,
+                carrier_offset,
+                subframes: BTreeMap::new(),
+            });
+        }
+        let impairments = config.impairments;
+        let paths = std::iter::once((0.0, 1.0, 0.0))
+            .chain(impairments.multipath.iter().map(|ray| {
+                (
+                    ray.delay_m / SPEED_OF_LIGHT,
+                    10f64.powf(-ray.attenuation_db / 20.0),
+                    ray.fading_hz,
+                )
+            }))
+            .collect();
+        let source = Self {
+            channels,
+            trajectory: config.trajectory,
+            noise: NoiseSource::new(fs, config.seed),
+            fs,
+            start_gps_sec: config.start_gps_sec,
+            start_tow: config.start_gps_sec.rem_euclid(SECS_PER_WEEK),
+            impairments,
+            paths,
+        };
+        let imp = &source.impairments;
+        tracing::info!(
+            "sim: clock={:+.3} ppm drift={:+.4} ppm/s iono={:.1} m multipath={:?}",
+            imp.clock_ppm,
+            imp.clock_drift_ppm,
+            imp.iono_zenith_m,
+            imp.multipath
+        );
+        for ch in &source.channels {
+            let (code_delay, phase_delay0) = source.get_delays(ch, 0.0);
+            let (_, phase_delay1) = source.get_delays(ch, 1e-3);
+            let code_phase =
+                ((source.start_tow - code_delay) * ch.chip_rate).rem_euclid(ch.code.len() as f64);
+            tracing::info!(
+                "sim: {} cn0={:.1} dB-Hz doppler={:+.0} Hz code phase={code_phase:.1} chips{}",
+                ch.sv.sv,
+                ch.sv.cn0,
+                -(phase_delay1 - phase_delay0) / 1e-3 * ch.fc,
+                if ch.sv.eph.is_some() { " nav data" } else { "" }
+            );
+        }
+        Ok(source)
+    }
+
+    // Delays of the code and of the carrier received at t_sec from the start:
+    // the ionosphere delays the first and advances the second, the receiver
+    // clock running fast delays both.
+    fn get_delays(&self, ch: &SimChannel, t_sec: f64) -> (f64, f64) {
+        let imp = &self.impairments;
+        let (delay, elevation) = self.get_geometric_delay(ch, t_sec);
+        let clock = (imp.clock_ppm * t_sec + 0.5 * imp.clock_drift_ppm * t_sec.powi(2)) * 1e-6;
+        let iono = if imp.iono_zenith_m != 0.0 {
+            imp.iono_zenith_m * get_iono_obliquity(elevation) / SPEED_OF_LIGHT
+        } else {
+            0.0
+        };
+        (delay + clock + iono, delay + clock - iono)
+    }
+
+    // How much earlier, in the satellite time, the signal received at t_sec
+    // from the start was transmitted, and the elevation of the satellite.
+    fn get_geometric_delay(&self, ch: &SimChannel, t_sec: f64) -> (f64, f64) {
+        let (Some(trajectory), Some(eph)) = (&self.trajectory, &ch.sv.eph) else {
+            let delay = SIM_NOMINAL_DELAY
+                - ch.sv.code_phase_chips / ch.chip_rate
+                - ch.sv.doppler_hz / ch.fc * t_sec;
+            return (delay, 90.0);
+        };
+        let rx = trajectory.get_position(t_sec);
+        let t_rx = self.start_gps_sec + t_sec;
+        let mut tau = SIM_NOMINAL_DELAY;
+        let mut sv_pos = [0.0; 3];
+        for _ in 0..3 {
+            let [x, y, z] = get_sv_ecef(eph, t_rx - tau);
+            // earth rotation during the propagation
+            let theta = EARTH_ROTATION_RATE * tau;
+            sv_pos = [
+                x * theta.cos() + y * theta.sin(),
+                y * theta.cos() - x * theta.sin(),
+                z,
+            ];
+            let range = ((sv_pos[0] - rx[0]).powi(2)
+                + (sv_pos[1] - rx[1]).powi(2)
+                + (sv_pos[2] - rx[2]).powi(2))
+            .sqrt();
+            tau = range / SPEED_OF_LIGHT;
+        }
+        let elevation = if self.impairments.iono_zenith_m != 0.0 {
+            get_azimuth_elevation(trajectory.get_geodetic(t_sec), sv_pos).1
+        } else {
+            90.0
+        };
+        // the satellite clock, L1 group delay included
+        let dt = t_rx - tau - get_toc_sec(eph);
+        let delay = tau - (eph.f0 + eph.f1 * dt + eph.f2 * dt * dt - eph.tgd);
+        (delay, elevation)
+    }
+}
+
+impl SampleSource for SimSource {
+    fn read_block(
+        &mut self,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        let mut iq_vec = self.noise.read_block(off_samples, num_samples)?.iq_vec;
+        let sigma = SIM_NOISE_POWER.sqrt() as Float;
+        iq_vec.iter_mut().for_each(|s| *s *= sigma);
+
+        // the delays are interpolated over 1 msec
+        let step = (self.fs * 1e-3) as usize;
+        let mut n = 0;
+        while n < num_samples {
+            let len = usize::min(step, num_samples - n);
+            let t0 = (off_samples + n) as f64 / self.fs;
+            let t1 = (off_samples + n + len) as f64 / self.fs;
+            for i in 0..self.channels.len() {
+                let (code0, phase0) = self.get_delays(&self.channels[i], t0);
+                let (code1, phase1) = self.get_delays(&self.channels[i], t1);
+                let ch = &mut self.channels[i];
+                for (k, s) in iq_vec[n..n + len].iter_mut().enumerate() {
+                    let a = k as f64 / len as f64;
+                    let t = t0 + a * (t1 - t0);
+                    let code_delay = code0 + a * (code1 - code0);
+                    let phase_delay = phase0 + a * (phase1 - phase0);
+                    for &(path_delay, gain, fading_hz) in &self.paths {
+                        let t_sv = self.start_tow + t - code_delay - path_delay;
+                        let code_len = ch.code.len();
+                        let chip =
+                            (t_sv * ch.chip_rate).rem_euclid(code_len as f64) as usize % code_len;
+                        let v = ch.amplitude
+                            * gain
+                            * ch.get_nav_bit(t_sv)
+                            * ch.get_secondary_chip(t_sv)
+                            * ch.code[chip] as f64;
+                        let cycles = (ch.fc * (phase_delay + path_delay)).fract()
+                            - (ch.carrier_offset * t).fract()
+                            - fading_hz * t;
+                        let phase = -2.0 * PI * cycles;
+                        *s += IQSample::from_polar(v as Float, phase as Float);
+                    }
+                }
+            }
+            n += len;
+        }
+        Ok(SampleBlock::new(iq_vec))
+    }
+}
@@ -0,0 +1,115 @@
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+use map_3d::{Ellipsoid, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+
+use crate::{ephemeris::Ephemeris, solver::get_sv_position_ecef};
+
+// Where a tracked satellite is in the sky of the receiver, for the skyplot.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SkyPosition {
+    pub sv: SV,
+    pub azimuth_deg: f64, // clockwise from north
+    pub elevation_deg: f64,
+    pub cn0: f64,
+}
+
+// satellite position at receiver time ts_sec, from its ephemeris
+pub fn get_sv_position_at(eph: &Ephemeris, ts_sec: f64) -> [f64; 3] {
+    let t = eph.tow_gpst + Duration::from_seconds(ts_sec - eph.ts_sec);
+    let (x, y, z) = get_sv_position_ecef(eph, t);
+    [x, y, z]
+}
+
+// East, north and up (m) of an ecef point in the local frame at pos:
+// latitude, longitude (degrees) and height (m).
+pub fn get_enu(pos: [f64; 3], ecef: [f64; 3]) -> [f64; 3] {
+    let (lat, lon) = (pos[0].to_radians(), pos[1].to_radians());
+    let (x, y, z) = geodetic2ecef(lat, lon, pos[2], Ellipsoid::WGS84);
+    let [dx, dy, dz] = [ecef[0] - x, ecef[1] - y, ecef[2] - z];
+    [
+        -lon.sin() * dx + lon.cos() * dy,
+        -lat.sin() * lon.cos() * dx - lat.sin() * lon.sin() * dy + lat.cos() * dz,
+        lat.cos() * lon.cos() * dx + lat.cos() * lon.sin() * dy + lat.sin() * dz,
+    ]
+}
+
+// east and north (m) of a fix relative to another, both geodetic
+pub fn get_east_north(origin: [f64; 3], pos: [f64; 3]) -> (f64, f64) {
+    let (x, y, z) = geodetic2ecef(
+        pos[0].to_radians(),
+        pos[1].to_radians(),
+        pos[2],
+        Ellipsoid::WGS84,
+    );
+    let [east, north, _up] = get_enu(origin, [x, y, z]);
+    (east, north)
+}
+
+// Azimuth and elevation (degrees) of a satellite seen from pos.
+pub fn get_azimuth_elevation(pos: [f64; 3], sv_ecef: [f64; 3]) -> (f64, f64) {
+    let [east, north, up] = get_enu(pos, sv_ecef);
+    let azimuth = east.atan2(north).to_degrees().rem_euclid(360.0);
+    let elevation = up.atan2(east.hypot(north)).to_degrees();
+    (azimuth, elevation)
+}
+
+// Dilution of precision: how the geometry of the satellites scales the range
+// errors into the fix.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Dop {
+    pub gdop: f64,
+    pub pdop: f64,
+    pub hdop: f64,
+    pub vdop: f64,
+}
+
+// diagonal of the inverse, gauss-jordan elimination
+fn get_inverse_diagonal(a: [[f64; 4]; 4]) -> Option<[f64; 4]> {
+    let mut m: Vec<Vec<f64>> = (a.iter().enumerate())
+        .map(|(i, row)| {
+            let mut row = row.to_vec();
+            row.extend((0..4).map(|j| if i == j { 1.0 } else { 0.0 }));
+            row
+        })
+        .collect();
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let p = m[col][col];
+        m[col].iter_mut().for_each(|x| *x /= p);
+        let pivot_row = m[col].clone();
+        for (_, row) in m.iter_mut().enumerate().filter(|(i, _)| *i != col) {
+            let f = row[col];
+            row.iter_mut()
+                .zip(&pivot_row)
+                .for_each(|(x, p)| *x -= f * p);
+        }
+    }
+    Some([0, 1, 2, 3].map(|i| m[i][4 + i]))
+}
+
+// from the azimuth and elevation of the satellites, None under 4 of them
+pub fn get_dop(sky: &[SkyPosition]) -> Option<Dop> {
+    if sky.len() < 4 {
+        return None;
+    }
+    let mut a = [[0.0; 4]; 4];
+    for pos in sky {
+        let (az, el) = (pos.azimuth_deg.to_radians(), pos.elevation_deg.to_radians());
+        let g = [el.cos() * az.sin(), el.cos() * az.cos(), el.sin(), 1.0];
+        for (row, gi) in a.iter_mut().zip(g) {
+            row.iter_mut().zip(g).for_each(|(x, gj)| *x += gi * gj);
+        }
+    }
+    let [east, north, up, time] = get_inverse_diagonal(a)?;
+    Some(Dop {
+        gdop: (east + north + up + time).sqrt(),
+        pdop: (east + north + up).sqrt(),
+        hdop: (east + north).sqrt(),
+        vdop: up.sqrt(),
+    })
+}
@@ -0,0 +1,189 @@
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Epoch;
+use map_3d::{Ellipsoid, ecef2geodetic, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{EARTH_ROTATION_RATE, SPEED_OF_LIGHT};
+use crate::ephemeris::Ephemeris;
+use crate::solver::get_sv_position_ecef;
+
+const SECS_PER_WEEK: f64 = 604800.0;
+const CODE_PERIOD_M: f64 = SPEED_OF_LIGHT * 0.001; // L1CA: ~300 km
+const MAX_ITER: usize = 20;
+const CONVERGED_M: f64 = 0.01;
+
+// Sub-millisecond pseudorange of a satellite acquired in the snapshot.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotMeas {
+    pub sv: SV,
+    pub eph: Ephemeris,
+    pub code_off_sec: f64, // code phase, the full ms are unknown
+    pub cn0: f64,
+}
+
+impl SnapshotMeas {
+    pub fn new(sv: SV, eph: &Ephemeris, code_off_sec: f64, cn0: f64) -> Self {
+        // the ephemeris may only come from a saved state: no live tow
+        let toe_sec = eph.week as f64 * SECS_PER_WEEK + eph.toe as f64;
+        let eph = Ephemeris {
+            toe_gpst: Epoch::from_gpst_seconds(toe_sec),
+            ..*eph
+        };
+        Self {
+            sv,
+            eph,
+            code_off_sec,
+            cn0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SnapshotFix {
+    pub lat: f64, // degrees
+    pub lon: f64,
+    pub height: f64,          // m
+    pub time_offset_sec: f64, // correction to the rough time
+    pub num_svs: usize,
+    pub residual_rms_m: f64,
+}
+
+// geometric range to the satellite, its rate and the unit vector pointing to
+// it, for a signal received at gps time t_sec.
+fn predict(eph: &Ephemeris, pos: &[f64; 3], t_sec: f64) -> (f64, f64, [f64; 3]) {
+    let sat_at = |t: f64, tau: f64| {
+        let (x, y, z) = get_sv_position_ecef(eph, Epoch::from_gpst_seconds(t));
+        // earth rotation during the transit
+        let (s, c) = (EARTH_ROTATION_RATE * tau).sin_cos();
+        [c * x + s * y, -s * x + c * y, z]
+    };
+    let mut tau = 0.075;
+    let mut sat = [0.0; 3];
+    for _ in 0..3 {
+        sat = sat_at(t_sec - tau, tau);
+        tau = dist(&sat, pos) / SPEED_OF_LIGHT;
+    }
+    let range = dist(&sat, pos);
+    let u = [
+        (sat[0] - pos[0]) / range,
+        (sat[1] - pos[1]) / range,
+        (sat[2] - pos[2]) / range,
+    ];
+    let sat_next = sat_at(t_sec - tau + 1.0, tau);
+    let rate: f64 = (0..3).map(|i| u[i] * (sat_next[i] - sat[i])).sum();
+    (range, rate, u)
+}
+
+fn dist(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// satellite clock correction, in meters
+fn sv_clock_m(eph: &Ephemeris, t_sec: f64) -> f64 {
+    let toc_sec = eph.week as f64 * SECS_PER_WEEK + eph.toc as f64;
+    let dt = t_sec - toc_sec;
+    SPEED_OF_LIGHT * (eph.f0 + eph.f1 * dt + eph.f2 * dt.powi(2) - eph.tgd)
+}
+
+// least squares on the normal equations, gaussian elimination
+fn solve_normal(h: &[Vec<f64>], r: &[f64]) -> Option<Vec<f64>> {
+    let n = h[0].len();
+    let mut a = vec![vec![0.0; n + 1]; n];
+    for (row, res) in h.iter().zip(r) {
+        for i in 0..n {
+            for j in 0..n {
+                a[i][j] += row[i] * row[j];
+            }
+            a[i][n] += row[i] * res;
+        }
+    }
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for (_, row) in a.iter_mut().enumerate().filter(|(i, _)| *i != col) {
+            let f = row[col] / pivot_row[col];
+            for (x, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                *x -= f * p;
+            }
+        }
+    }
+    Some((0..n).map(|i| a[i][n] / a[i][i]).collect())
+}
+
+// Coarse-time navigation: with only the code phases, the full milliseconds of
+// the pseudoranges are rebuilt from a rough position (< ~100 km) and rough
+// time, and the time error is solved for along with the position and clock
+// bias. Needs 5 satellites, or 4 when the rough time is right to a few ms.
+pub fn solve_coarse_time(
+    meas: &[SnapshotMeas],
+    rough_pos: [f64; 3], // latitude, longitude (degrees), height (m)
+    rough_gps_sec: f64,
+) -> Result<SnapshotFix, Box<dyn std::error::Error>> {
+    if meas.len() < 4 {
+        return Err(format!("snapshot: {} satellites, at least 4 needed", meas.len()).into());
+    }
+    let (x, y, z) = geodetic2ecef(
+        rough_pos[0].to_radians(),
+        rough_pos[1].to_radians(),
+        rough_pos[2],
+        Ellipsoid::WGS84,
+    );
+    let mut pos = [x, y, z];
+    let mut t_sec = rough_gps_sec;
+    let solve_time = meas.len() >= 5;
+
+    // the strongest satellite sets the common part of the unknown milliseconds
+    let reference = meas.iter().max_by(|a, b| a.cn0.total_cmp(&b.cn0)).unwrap();
+    let frac_m = |m: &SnapshotMeas| m.code_off_sec * SPEED_OF_LIGHT;
+    let model_m =
+        |m: &SnapshotMeas| predict(&m.eph, &pos, t_sec).0 - sv_clock_m(&m.eph, t_sec) - frac_m(m);
+    let ref_ms = model_m(reference) / CODE_PERIOD_M;
+    let ref_frac = ref_ms - ref_ms.round();
+    let pseudoranges: Vec<_> = meas
+        .iter()
+        .map(|m| (model_m(m) / CODE_PERIOD_M - ref_frac).round() * CODE_PERIOD_M + frac_m(m))
+        .collect();
+
+    let mut bias_m = -ref_frac * CODE_PERIOD_M;
+    let mut residuals = vec![0.0; meas.len()];
+    for iter in 0.. {
+        let mut h = vec![];
+        for (i, m) in meas.iter().enumerate() {
+            let (range, rate, u) = predict(&m.eph, &pos, t_sec);
+            residuals[i] = pseudoranges[i] - (range - sv_clock_m(&m.eph, t_sec) + bias_m);
+            let mut row = vec![-u[0], -u[1], -u[2], 1.0];
+            if solve_time {
+                row.push(rate);
+            }
+            h.push(row);
+        }
+        let dx = solve_normal(&h, &residuals).ok_or("snapshot: singular geometry")?;
+        (0..3).for_each(|i| pos[i] += dx[i]);
+        bias_m += dx[3];
+        if solve_time {
+            t_sec += dx[4];
+        }
+        let step_m = (dx[0].powi(2) + dx[1].powi(2) + dx[2].powi(2)).sqrt();
+        if step_m < CONVERGED_M {
+            break;
+        }
+        if iter == MAX_ITER {
+            return Err("snapshot: no convergence".into());
+        }
+    }
+
+    let (lat_rad, lon_rad, height) = ecef2geodetic(pos[0], pos[1], pos[2], Ellipsoid::WGS84);
+    let residual_rms_m = (residuals.iter().map(|r| r * r).sum::<f64>() / meas.len() as f64).sqrt();
+    Ok(SnapshotFix {
+        lat: lat_rad.to_degrees(),
+        lon: lon_rad.to_degrees(),
+        height,
+        time_offset_sec: t_sec - rough_gps_sec,
+        num_svs: meas.len(),
+        residual_rms_m,
+    })
+}
@@ -1,24 +1,49 @@
 use colored::Colorize;
+use gnss_rs::constellation::Constellation;
 use gnss_rs::sv::SV;
 use gnss_rtk::prelude::{
     AprioriPosition, Candidate, Carrier, Config, Duration, Epoch, InterpolationResult,
     IonosphereBias, Method, Observation, Solver, TroposphereBias, Vector3,
 };
 use map_3d::{Ellipsoid, ecef2geodetic};
-use once_cell::sync::Lazy;
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    constants::{EARTH_MU_GPS, EARTH_ROTATION_RATE, SPEED_OF_LIGHT},
+    constants::{
+        BDT_GPST_OFFSET_SEC, EARTH_J2_GLO, EARTH_MU_BDS, EARTH_MU_GLO, EARTH_MU_GPS,
+        EARTH_RADIUS_GLO, EARTH_ROTATION_RATE, EARTH_ROTATION_RATE_BDS, EARTH_ROTATION_RATE_GLO,
+        SPEED_OF_LIGHT,
+    },
     ephemeris::Ephemeris,
+    navigation_sbas::is_sbas,
     state::GnssState,
 };
 
 const PI: f64 = std::f64::consts::PI;
+const GLO_STEP_SEC: f64 = 60.0; // of the orbit integration
+const MAS_TO_RAD: f64 = PI / 180.0 / 3600.0 / 1000.0;
+// PZ-90.11 to WGS-84 (G1762), both aligned on ITRF2008: translations (m)
+// and rotations (mas) of the frames, a few mm on the orbits
+const PZ90_TRANSLATION: [f64; 3] = [-0.003, -0.001, 0.0];
+const PZ90_ROTATION_MAS: [f64; 3] = [0.019, -0.042, 0.002];
+
+// gravitational constant and rotation rate of the earth the orbit is given
+// with, and toe in the time of the constellation: BDT for BeiDou, whose toe
+// is kept in gps time
+fn get_kepler_constants(eph: &Ephemeris) -> (f64, f64, f64) {
+    match eph.sv.constellation {
+        Constellation::BeiDou => (
+            EARTH_MU_BDS,
+            EARTH_ROTATION_RATE_BDS,
+            eph.toe as f64 - BDT_GPST_OFFSET_SEC as f64,
+        ),
+        _ => (EARTH_MU_GPS, EARTH_ROTATION_RATE, eph.toe as f64),
+    }
+}
 
-fn get_eccentric_anomaly(eph: &Ephemeris, t_k: f64) -> f64 {
+fn get_eccentric_anomaly(eph: &Ephemeris, mu: f64, t_k: f64) -> f64 {
     // computed mean motion
-    let n0 = (EARTH_MU_GPS / eph.a.powi(3)).sqrt();
+    let n0 = (mu / eph.a.powi(3)).sqrt();
     // corrected mean motion
     let n = n0 + eph.deln;
     // mean anomaly
@@ -38,12 +63,86 @@ fn get_eccentric_anomaly(eph: &Ephemeris, t_k: f64) -> f64 {
     e
 }
 
-fn compute_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
+// derivative of the GLONASS state: position and velocity in the rotating
+// PZ-90 frame, the lunisolar acceleration held as the ephemeris gave it
+fn get_glo_derivative(x: &[f64; 6], acc: &[f64; 3]) -> [f64; 6] {
+    let r2 = x[0].powi(2) + x[1].powi(2) + x[2].powi(2);
+    let r3 = r2 * r2.sqrt();
+    let omg2 = EARTH_ROTATION_RATE_GLO.powi(2);
+    let a = 1.5 * EARTH_J2_GLO * EARTH_MU_GLO * EARTH_RADIUS_GLO.powi(2) / r2 / r3;
+    let b = 5.0 * x[2].powi(2) / r2;
+    let c = -EARTH_MU_GLO / r3 - a * (1.0 - b);
+    [
+        x[3],
+        x[4],
+        x[5],
+        (c + omg2) * x[0] + 2.0 * EARTH_ROTATION_RATE_GLO * x[4] + acc[0],
+        (c + omg2) * x[1] - 2.0 * EARTH_ROTATION_RATE_GLO * x[3] + acc[1],
+        (c - 2.0 * a) * x[2] + acc[2],
+    ]
+}
+
+// one Runge-Kutta step of dt seconds
+fn glo_orbit_step(x: &mut [f64; 6], acc: &[f64; 3], dt: f64) {
+    let k1 = get_glo_derivative(x, acc);
+    let w: [f64; 6] = std::array::from_fn(|i| x[i] + k1[i] * dt / 2.0);
+    let k2 = get_glo_derivative(&w, acc);
+    let w: [f64; 6] = std::array::from_fn(|i| x[i] + k2[i] * dt / 2.0);
+    let k3 = get_glo_derivative(&w, acc);
+    let w: [f64; 6] = std::array::from_fn(|i| x[i] + k3[i] * dt);
+    let k4 = get_glo_derivative(&w, acc);
+    *x = std::array::from_fn(|i| x[i] + (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]) * dt / 6.0);
+}
+
+// small-angle Helmert transform of a PZ-90.11 position
+fn pz90_to_wgs84(p: [f64; 3]) -> [f64; 3] {
+    let [rx, ry, rz] = PZ90_ROTATION_MAS.map(|r| r * MAS_TO_RAD);
+    let [tx, ty, tz] = PZ90_TRANSLATION;
+    [
+        tx + p[0] + rz * p[1] - ry * p[2],
+        ty - rz * p[0] + p[1] + rx * p[2],
+        tz + ry * p[0] - rx * p[1] + p[2],
+    ]
+}
+
+// A GLONASS ephemeris is a state vector at toe: integrated to t.
+fn get_glo_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
     let mut dte = (t - eph.toe_gpst).to_seconds();
+    if dte > 302400.0 {
+        dte -= 604800.0;
+    }
+    if dte < -302400.0 {
+        dte += 604800.0;
+    }
+    let mut x = [
+        eph.pos[0], eph.pos[1], eph.pos[2], eph.vel[0], eph.vel[1], eph.vel[2],
+    ];
+    while dte.abs() > 1e-9 {
+        let dt = dte.clamp(-GLO_STEP_SEC, GLO_STEP_SEC);
+        glo_orbit_step(&mut x, &eph.acc, dt);
+        dte -= dt;
+    }
+    let [x, y, z] = pz90_to_wgs84([x[0], x[1], x[2]]);
+    (x, y, z)
+}
 
-    log::warn!("{}: ---- now={t:?}", eph.sv);
-    log::warn!("{}: ---- toe={:?} delta-t={dte} ", eph.sv, eph.toe_gpst);
+// An SBAS GEO ephemeris is a state vector at toe, in ecef: extrapolated to
+// t, with the GEO hardly moving.
+fn get_sbas_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
+    let dt = (t - eph.toe_gpst).to_seconds();
+    let [x, y, z] = [0, 1, 2].map(|i| eph.pos[i] + eph.vel[i] * dt + 0.5 * eph.acc[i] * dt.powi(2));
+    (x, y, z)
+}
 
+// satellite position at gps time t, no logging: also used by the snapshot solver
+pub fn get_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
+    if eph.sv.constellation == Constellation::Glonass {
+        return get_glo_position_ecef(eph, t);
+    }
+    if is_sbas(eph.sv) {
+        return get_sbas_position_ecef(eph, t);
+    }
+    let mut dte = (t - eph.toe_gpst).to_seconds();
     if dte > 302400.0 {
         dte -= 604800.0;
     }
@@ -51,7 +150,8 @@ fn compute_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
         dte += 604800.0;
     }
 
-    let ecc_anomaly = get_eccentric_anomaly(eph, dte);
+    let (mu, rotation_rate, toe_sec) = get_kepler_constants(eph);
+    let ecc_anomaly = get_eccentric_anomaly(eph, mu, dte);
     let v_k =
         ((1.0 - eph.ecc.powi(2)).sqrt() * ecc_anomaly.sin()).atan2(ecc_anomaly.cos() - eph.ecc);
 
@@ -67,14 +167,30 @@ fn compute_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
     let orb_plane_x = rk * uk.cos();
     let orb_plane_y = rk * uk.sin();
 
-    let omega =
-        eph.omg0 + (eph.omg_dot - EARTH_ROTATION_RATE) * dte - EARTH_ROTATION_RATE * eph.toe as f64;
+    let omega = eph.omg0 + (eph.omg_dot - rotation_rate) * dte - rotation_rate * toe_sec;
 
     let ecef_x = orb_plane_x * omega.cos() - orb_plane_y * ik.cos() * omega.sin();
     let ecef_y = orb_plane_x * omega.sin() + orb_plane_y * ik.cos() * omega.cos();
     let ecef_z = orb_plane_y * ik.sin();
+    // plus the SBAS correction, if any
+    (
+        ecef_x + eph.dpos[0],
+        ecef_y + eph.dpos[1],
+        ecef_z + eph.dpos[2],
+    )
+}
 
-    log::warn!(
+fn compute_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
+    tracing::trace!("{}: ---- now={t:?}", eph.sv);
+    tracing::trace!(
+        "{}: ---- toe={:?} delta-t={} ",
+        eph.sv,
+        eph.toe_gpst,
+        (t - eph.toe_gpst).to_seconds()
+    );
+
+    let (ecef_x, ecef_y, ecef_z) = get_sv_position_ecef(eph, t);
+    tracing::trace!(
         "{}: position: x={:8.1} y={:8.1} z={:8.1} h={:.1}",
         eph.sv,
         ecef_x / 1000.0,
@@ -83,7 +199,7 @@ fn compute_sv_position_ecef(eph: &Ephemeris, t: Epoch) -> (f64, f64, f64) {
         (ecef_x.powi(2) + ecef_y.powi(2) + ecef_z.powi(2)).sqrt() / 1000.0
     );
     let (lat_rad, lon_rad, h) = ecef2geodetic(ecef_x, ecef_y, ecef_z, Ellipsoid::WGS84);
-    log::warn!(
+    tracing::trace!(
         "{}: position: lat/lon: {:.6},{:.6} h={:.1}",
         eph.sv,
         lat_rad * 180.0 / PI,
@@ -107,18 +223,19 @@ fn get_tropo_iono_bias() -> (TroposphereBias, IonosphereBias) {
     (tropo_bias, iono_bias)
 }
 
-pub type I = fn(Epoch, SV, usize) -> Option<InterpolationResult>;
+pub type I = Box<dyn Fn(Epoch, SV, usize) -> Option<InterpolationResult> + Send>;
+
+// Each solver has its own ephemeris, shared with its interpolation callback:
+// several receivers can solve side by side.
 pub struct PositionSolver {
     solver: Solver<I>,
+    ephs: Arc<Mutex<Vec<Ephemeris>>>,
     pub_state: Arc<Mutex<GnssState>>,
 }
 
-static SOLVER_EPHEMERIS: Lazy<Mutex<Vec<Ephemeris>>> =
-    Lazy::new(|| Mutex::new(Vec::<Ephemeris>::new()));
-
-fn sv_interp(t: Epoch, sv: SV, _size: usize) -> Option<InterpolationResult> {
-    let ephs = SOLVER_EPHEMERIS.lock().unwrap();
-    let eph = ephs.iter().find(|&&e| e.sv == sv).unwrap();
+fn sv_interp(ephs: &Mutex<Vec<Ephemeris>>, t: Epoch, sv: SV) -> Option<InterpolationResult> {
+    let ephs = ephs.lock().unwrap();
+    let eph = ephs.iter().find(|&&e| e.sv == sv)?;
     let pos = compute_sv_position_ecef(eph, t);
 
     Some(InterpolationResult::from_apc_position(pos))
@@ -131,16 +248,22 @@ impl PositionSolver {
         let mut cfg = Config::static_preset(Method::SPP);
         cfg.min_sv_elev = Some(0.0);
 
-        let solver = Solver::new(&cfg, apriori, sv_interp as I).expect("Solver issue");
+        let ephs = Arc::new(Mutex::new(Vec::<Ephemeris>::new()));
+        let interp_ephs = ephs.clone();
+        let interp: I = Box::new(move |t, sv, _size| sv_interp(&interp_ephs, t, sv));
+        let solver = Solver::new(&cfg, apriori, interp).expect("Solver issue");
 
-        Self { solver, pub_state }
+        Self {
+            solver,
+            ephs,
+            pub_state,
+        }
     }
 
-    pub fn compute_position(&mut self, ts_sec: f64, ephs: &Vec<Ephemeris>) {
-        {
-            let mut glob_ephs = SOLVER_EPHEMERIS.lock().unwrap();
-            *glob_ephs = ephs.clone();
-        }
+    // latitude, longitude (degrees) and height (m) of the fix, if any
+    pub fn compute_position(&mut self, ts_sec: f64, ephs: &Vec<Ephemeris>) -> Option<[f64; 3]> {
+        let _span = tracing::info_span!("pvt", ts_sec).entered();
+        *self.ephs.lock().unwrap() = ephs.clone();
 
         /*
          * https://www.insidegnss.com/auto/IGM_janfeb12-Solutions.pdf
@@ -166,26 +289,29 @@ impl PositionSolver {
             .unwrap();
 
         let now_gpst = min_gpst + 0.01;
-        log::warn!("----- now_gpst={now_gpst:?}");
+        tracing::trace!("----- now_gpst={now_gpst:?}");
         for eph in ephs {
             let e_gpst = eph.tow_gpst + Duration::from_seconds(ts_sec - eph.ts_sec);
             let pseudo_range_sec = (e_gpst - min_gpst).to_seconds() + eph.code_off_sec;
-            let pseudo_range = pseudo_range_sec * SPEED_OF_LIGHT;
-            let dt = (now_gpst - eph.tow_gpst).to_seconds();
+            let pseudo_range = pseudo_range_sec * SPEED_OF_LIGHT + eph.prc;
+            // from toc: a tow just decoded can be ahead of now, when a GEO is
+            // the furthest
+            let dt = (now_gpst - eph.toc_gpst).to_seconds();
             let clock_corr = eph.f0 + eph.f1 * dt + eph.f2 * dt.powi(2);
-            assert!(dt >= 0.0);
 
-            log::warn!("{} - e_gpst={:?} eph.ts={}", eph.sv, e_gpst, eph.ts_sec);
-            log::warn!(
-                "{} - prng={pseudo_range_sec:+e}sec/{pseudo_range:.1}m tgd={:+e} clock_corr={clock_corr}",
+            tracing::trace!("{} - e_gpst={:?} eph.ts={}", eph.sv, e_gpst, eph.ts_sec);
+            tracing::trace!(
+                "{} - prng={pseudo_range_sec:+e}sec/{pseudo_range:.1}m tgd={:+e} clock_corr={clock_corr} prc={:.2}",
                 eph.sv,
                 eph.tgd,
+                eph.prc,
             );
 
             let candidate = Candidate::new(
                 eph.sv,
                 now_gpst,
-                Duration::from_seconds(0.0),
+                // with the SBAS long-term correction, if any
+                Duration::from_seconds(clock_corr),
                 Some(Duration::from_seconds(eph.tgd)),
                 vec![Observation {
                     carrier: Carrier::L1,
@@ -205,7 +331,10 @@ impl PositionSolver {
             .resolve(now_gpst, &pool, &iono_bias, &tropo_bias);
 
         match res {
-            Err(err) => log::warn!("Failed to get a position: {err}"),
+            Err(err) => {
+                tracing::warn!("Failed to get a position: {err}");
+                None
+            }
             Ok(solution) => {
                 let pos = solution.1.position;
                 let (lat_rad, lon_rad, h) = ecef2geodetic(pos[0], pos[1], pos[2], Ellipsoid::WGS84);
@@ -217,10 +346,11 @@ impl PositionSolver {
                 self.pub_state.lock().unwrap().longitude = lon;
                 self.pub_state.lock().unwrap().height = height;
 
-                log::warn!(
+                tracing::info!(
                     "{}",
                     format!("XXX: lat/lon: {:.4},{:.4} h={:.1}", lat, lon, height).red(),
                 );
+                Some([lat, lon, h])
             }
         }
     }
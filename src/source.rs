@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::sample::IQSample;
+
+// A block of samples read from a source, with what the source knows about
+// its continuity with the previous block.
+pub struct SampleBlock {
+    pub iq_vec: Vec<IQSample>,
+    pub hw_ts_sec: Option<f64>, // device time of the first sample, if provided
+    pub num_dropped: usize,     // samples lost by the source before this block
+    pub time_jump: bool,        // discontinuity of unknown size, e.g. file loop
+}
+
+impl SampleBlock {
+    pub fn new(iq_vec: Vec<IQSample>) -> Self {
+        Self {
+            iq_vec,
+            hw_ts_sec: None,
+            num_dropped: 0,
+            time_jump: false,
+        }
+    }
+
+    pub fn is_contiguous(&self) -> bool {
+        !self.time_jump && self.num_dropped == 0
+    }
+}
+
+// Implemented by the file, device, network and synthetic backends.
+pub trait SampleSource {
+    fn read_block(
+        &mut self,
+        off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>>;
+
+    // total number of samples available, for finite sources like files
+    fn get_num_samples_total(&self) -> Option<usize> {
+        None
+    }
+
+    // the device went away: reads fail until it is re-opened
+    fn is_device_lost(&self) -> bool {
+        false
+    }
+
+    // samples waiting to be read, for the sources running in real time
+    fn get_num_samples_queued(&self) -> Option<usize> {
+        None
+    }
+
+    // the files read, in order, for the recordings on disk
+    fn get_file_paths(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    // samples of a device or a network stream, different on every run
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DropPolicy {
+    DropOldest,
+    DropNewest,
+}
+
+impl FromStr for DropPolicy {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<DropPolicy, Self::Err> {
+        match input {
+            "drop-oldest" => Ok(DropPolicy::DropOldest),
+            "drop-newest" => Ok(DropPolicy::DropNewest),
+            _ => Err(format!("Failed to parse {}", input).into()),
+        }
+    }
+}
+
+impl fmt::Display for DropPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DropPolicy::DropOldest => write!(f, "drop-oldest"),
+            DropPolicy::DropNewest => write!(f, "drop-newest"),
+        }
+    }
+}
+
+struct SampleQueueInner {
+    deque: VecDeque<(usize, Vec<IQSample>)>, // samples dropped before each vector
+    num_samples: usize,
+    num_samples_total: usize,
+    num_dropped: usize, // after the last vector queued
+    num_dropped_total: usize,
+}
+
+// Bounded queue between a reader thread and the receiver. When processing
+// falls behind, samples get dropped according to the policy: the gap is kept
+// in the queue where they were, and the block popped right after it reports
+// how many.
+pub struct SampleQueue {
+    inner: Mutex<SampleQueueInner>,
+    pushed: Condvar,
+    max_samples: usize,
+    policy: DropPolicy,
+}
+
+impl SampleQueue {
+    pub fn new(max_samples: usize, policy: DropPolicy) -> Self {
+        Self {
+            inner: Mutex::new(SampleQueueInner {
+                deque: VecDeque::new(),
+                num_samples: 0,
+                num_samples_total: 0,
+                num_dropped: 0,
+                num_dropped_total: 0,
+            }),
+            pushed: Condvar::new(),
+            max_samples,
+            policy,
+        }
+    }
+
+    pub fn push(&self, mut v: Vec<IQSample>) {
+        let mut q = self.inner.lock().unwrap();
+        q.num_samples_total += v.len();
+        let mut num_dropped = 0;
+
+        if q.num_samples + v.len() > self.max_samples && self.policy == DropPolicy::DropNewest {
+            num_dropped += v.len();
+            q.num_dropped += v.len();
+        } else {
+            // the newest samples of a vector too large for the queue
+            let num_over = v.len().saturating_sub(self.max_samples);
+            v.drain(0..num_over);
+            num_dropped += num_over;
+            let mut gap = std::mem::take(&mut q.num_dropped) + num_over;
+
+            // the gap of the vectors dropped moves to the one after them
+            let mut num_front = 0;
+            while q.num_samples + v.len() > self.max_samples {
+                let Some((front_gap, front)) = q.deque.pop_front() else {
+                    break;
+                };
+                q.num_samples -= front.len();
+                num_dropped += front.len();
+                num_front += front_gap + front.len();
+            }
+            match q.deque.front_mut() {
+                Some((front_gap, _)) => *front_gap += num_front,
+                None => gap += num_front,
+            }
+            q.num_samples += v.len();
+            q.deque.push_back((gap, v));
+        }
+
+        if num_dropped > 0 {
+            if q.num_dropped_total == 0 {
+                tracing::warn!("sample queue full: {}", self.policy);
+            }
+            q.num_dropped_total += num_dropped;
+        }
+        self.pushed.notify_all();
+    }
+
+    // None until num_samples are available
+    pub fn pop(&self, num_samples: usize) -> Option<SampleBlock> {
+        self.pop_wait(num_samples, Duration::ZERO)
+    }
+
+    // as pop(), waiting up to timeout for the samples to be pushed
+    pub fn pop_wait(&self, num_samples: usize, timeout: Duration) -> Option<SampleBlock> {
+        let q = self.inner.lock().unwrap();
+        let (mut guard, _) = self
+            .pushed
+            .wait_timeout_while(q, timeout, |q| q.num_samples < num_samples)
+            .unwrap();
+        let q = &mut *guard;
+        if q.num_samples < num_samples {
+            return None;
+        }
+        let mut vec = Vec::with_capacity(num_samples);
+        let mut num_dropped = 0;
+
+        while vec.len() < num_samples {
+            let Some((gap, v_front)) = q.deque.front_mut() else {
+                // short after a gap: the samples wait for the next pop
+                q.num_samples += vec.len();
+                q.deque.push_front((num_dropped, vec));
+                return None;
+            };
+            // a block starts after a gap: the samples before it get dropped
+            if *gap > 0 {
+                num_dropped += std::mem::take(gap) + vec.len();
+                q.num_dropped_total += vec.len();
+                vec.clear();
+            }
+            let n = usize::min(num_samples - vec.len(), v_front.len());
+            vec.extend(v_front.drain(0..n));
+            if v_front.is_empty() {
+                let _ = q.deque.pop_front();
+            }
+            q.num_samples -= n;
+        }
+
+        Some(SampleBlock {
+            num_dropped,
+            ..SampleBlock::new(vec)
+        })
+    }
+
+    // discards the queued samples, e.g. after a discontinuity reported by
+    // other means: they don't count as dropped.
+    pub fn clear(&self) {
+        let mut q = self.inner.lock().unwrap();
+        q.deque.clear();
+        q.num_samples = 0;
+    }
+
+    pub fn get_num_samples(&self) -> usize {
+        self.inner.lock().unwrap().num_samples
+    }
+
+    pub fn get_num_samples_total(&self) -> usize {
+        self.inner.lock().unwrap().num_samples_total
+    }
+
+    pub fn get_num_dropped_total(&self) -> usize {
+        self.inner.lock().unwrap().num_dropped_total
+    }
+}
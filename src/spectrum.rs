@@ -0,0 +1,52 @@
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+use crate::sample::{Float, IQSample, to_complex64};
+
+const PI: f64 = std::f64::consts::PI;
+
+const SPECTRUM_LEN: usize = 256; // bins
+
+// Power spectrum of the incoming samples, averaged over as many segments as
+// there are in a block: whether the front-end, tuning and gain are sane shows
+// right away, before anything is acquired.
+pub struct Spectrum {
+    fft: Arc<dyn Fft<Float>>,
+    window: Vec<Float>, // hann
+    scratch: Vec<IQSample>,
+}
+
+impl Spectrum {
+    pub fn new(fft_planner: &mut FftPlanner<Float>) -> Self {
+        let n = SPECTRUM_LEN as f64;
+        let window = (0..SPECTRUM_LEN)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / n).cos()) as Float)
+            .collect();
+        Self {
+            fft: fft_planner.plan_fft_forward(SPECTRUM_LEN),
+            window,
+            scratch: vec![IQSample::default(); SPECTRUM_LEN],
+        }
+    }
+
+    // dB, the negative frequencies first: the carrier is in the middle
+    pub fn compute(&mut self, iq_vec: &[IQSample]) -> Vec<f64> {
+        let mut power = vec![0.0; SPECTRUM_LEN];
+        let segments = iq_vec.chunks_exact(SPECTRUM_LEN);
+        let num_segments = segments.len();
+        for segment in segments {
+            for ((s, &x), &w) in self.scratch.iter_mut().zip(segment).zip(&self.window) {
+                *s = x * w;
+            }
+            self.fft.process(&mut self.scratch);
+            for (p, &s) in power.iter_mut().zip(&self.scratch) {
+                *p += to_complex64(s).norm_sqr();
+            }
+        }
+        power.rotate_left(SPECTRUM_LEN / 2);
+        power
+            .iter()
+            .map(|p| 10.0 * (p / usize::max(num_segments, 1) as f64 + 1e-20).log10())
+            .collect()
+    }
+}
@@ -0,0 +1,220 @@
+use map_3d::{Ellipsoid, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::almanac::Almanac;
+use crate::code::Code;
+use crate::constants::SPEED_OF_LIGHT;
+use crate::ephemeris::Ephemeris;
+use crate::util::getbitu;
+
+const GPS_EPOCH_UNIX_SEC: f64 = 315_964_800.0; // 1980-01-06
+const GPS_LEAP_SEC: f64 = 18.0;
+const SECS_PER_WEEK: f64 = 604800.0;
+pub const ALMANAC_MAX_AGE_SEC: f64 = 7.0 * 24.0 * 3600.0;
+const MIN_ELEVATION_DEG: f64 = -5.0;
+pub const WARM_DOPPLER_SPREAD_HZ: f64 = 1500.0; // almanac, rough time and position
+const HOT_DOPPLER_SPREAD_HZ: f64 = 500.0;
+const DOPPLER_RATE_HZ: f64 = 1.0; // max doppler drift per second
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StartMode {
+    Cold, // full doppler search, ephemeris decoded from scratch
+    Warm, // almanac with rough time and position: predicted doppler
+    Hot,  // stored ephemeris and recent doppler
+}
+
+impl FromStr for StartMode {
+    type Err = Box<dyn Error>;
+    fn from_str(input: &str) -> Result<StartMode, Self::Err> {
+        match input {
+            "cold" => Ok(StartMode::Cold),
+            "warm" => Ok(StartMode::Warm),
+            "hot" => Ok(StartMode::Hot),
+            _ => Err(format!("Failed to parse {}", input).into()),
+        }
+    }
+}
+
+impl fmt::Display for StartMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StartMode::Cold => write!(f, "cold"),
+            StartMode::Warm => write!(f, "warm"),
+            StartMode::Hot => write!(f, "hot"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SatState {
+    pub prn: u8,
+    pub doppler_hz: Option<f64>, // relative to the doppler search center
+    pub subframes: Vec<Vec<u8>>, // raw subframes 1-3
+}
+
+impl SatState {
+    // the ephemeris of the saved subframes, within its fit interval at gps
+    // time gps_sec
+    pub fn has_ephemeris(&self, gps_sec: f64) -> bool {
+        let Some(sv) = Code::get_sv("L1CA", self.prn).filter(|_| self.subframes.len() == 3) else {
+            return false;
+        };
+        let mut eph = Ephemeris::new(sv);
+        for buf in self.subframes.iter().filter(|buf| buf.len() == 300) {
+            match getbitu(buf, 49, 3) {
+                1 => eph.nav_decode_lnav_subframe1(buf, sv),
+                2 => eph.nav_decode_lnav_subframe2(buf, sv),
+                3 => eph.nav_decode_lnav_subframe3(buf, sv),
+                _ => return false,
+            }
+        }
+        let toe_sec = eph.week as f64 * SECS_PER_WEEK + eph.toe as f64;
+        eph.week != 0 && (gps_sec - toe_sec).abs() <= eph.get_fit_interval_sec() / 2.0
+    }
+}
+
+// What a receiver knows at the end of a run, saved as json to speed up the
+// acquisition of the next one.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct StartState {
+    pub saved_at: f64,              // unix time, seconds
+    pub position: Option<[f64; 3]>, // latitude, longitude (degrees), height (m)
+    pub almanac: Vec<Almanac>,      // only the satellites decoded
+    pub satellites: Vec<SatState>,
+}
+
+pub fn get_unix_time_now() -> f64 {
+    // no wall clock in wasm32: unknown
+    if cfg!(target_arch = "wasm32") {
+        return 0.0;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+// seconds since the gps epoch from a unix time
+pub fn get_gps_sec(unix_sec: f64) -> f64 {
+    unix_sec - GPS_EPOCH_UNIX_SEC + GPS_LEAP_SEC
+}
+
+// gps time of week from a unix time
+pub fn get_tow_sec(unix_sec: f64) -> f64 {
+    get_gps_sec(unix_sec).rem_euclid(SECS_PER_WEEK)
+}
+
+// Doppler of a satellite seen from pos: latitude, longitude (degrees) and
+// height (m), at time of week tow_sec, for a carrier at fc. None when it's
+// below the horizon.
+pub fn predict_doppler_from_almanac(
+    alm: &Almanac,
+    pos: [f64; 3],
+    tow_sec: f64,
+    fc: f64,
+) -> Option<f64> {
+    let [lat, lon, height] = pos;
+    let (x, y, z) = geodetic2ecef(lat.to_radians(), lon.to_radians(), height, Ellipsoid::WGS84);
+    let rcv = [x, y, z];
+    let up = [
+        lat.to_radians().cos() * lon.to_radians().cos(),
+        lat.to_radians().cos() * lon.to_radians().sin(),
+        lat.to_radians().sin(),
+    ];
+
+    let sv_pos = alm.get_position_ecef(tow_sec);
+    let sv_pos_next = alm.get_position_ecef(tow_sec + 1.0);
+    let los: Vec<_> = sv_pos.iter().zip(rcv).map(|(p, r)| p - r).collect();
+    let range = los.iter().map(|v| v * v).sum::<f64>().sqrt();
+    let sin_elev = los.iter().zip(up).map(|(l, u)| l * u).sum::<f64>() / range;
+    if sin_elev.asin().to_degrees() < MIN_ELEVATION_DEG {
+        return None;
+    }
+
+    let range_next = sv_pos_next
+        .iter()
+        .zip(rcv)
+        .map(|(p, r)| (p - r).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    Some(-(range_next - range) * fc / SPEED_OF_LIGHT)
+}
+
+impl StartState {
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    // None when there is no state file yet.
+    pub fn read(path: &Path) -> std::io::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(path)?;
+        let state = serde_json::from_str(&json).map_err(std::io::Error::other)?;
+        Ok(Some(state))
+    }
+
+    pub fn get_age_sec(&self, now_unix: f64) -> f64 {
+        now_unix - self.saved_at
+    }
+
+    pub fn has_almanac(&self, now_unix: f64) -> bool {
+        self.position.is_some()
+            && !self.almanac.is_empty()
+            && self.get_age_sec(now_unix) < ALMANAC_MAX_AGE_SEC
+    }
+
+    pub fn has_ephemeris(&self, now_unix: f64) -> bool {
+        let gps_sec = get_gps_sec(now_unix);
+        self.satellites.iter().any(|sat| sat.has_ephemeris(gps_sec))
+    }
+
+    pub fn get_sat(&self, prn: u8) -> Option<&SatState> {
+        self.satellites.iter().find(|sat| sat.prn == prn)
+    }
+
+    // Doppler of a satellite seen from the saved position, for a carrier at
+    // fc. None when there is no almanac for it or when it's below the horizon.
+    pub fn predict_doppler(&self, prn: u8, now_unix: f64, fc: f64) -> Option<f64> {
+        let alm = self.almanac.iter().find(|alm| alm.sat == prn as u32)?;
+        predict_doppler_from_almanac(alm, self.position?, get_tow_sec(now_unix), fc)
+    }
+
+    // Expected doppler and its uncertainty for a satellite, depending on the
+    // start mode: the remembered doppler gets less accurate as time passes.
+    pub fn get_doppler_hint(
+        &self,
+        mode: StartMode,
+        prn: u8,
+        now_unix: f64,
+        fc: f64,
+    ) -> Option<(f64, f64)> {
+        if mode == StartMode::Cold {
+            return None;
+        }
+        let predicted = self
+            .predict_doppler(prn, now_unix, fc)
+            .map(|doppler_hz| (doppler_hz, WARM_DOPPLER_SPREAD_HZ));
+        let remembered = self
+            .get_sat(prn)
+            .and_then(|sat| sat.doppler_hz)
+            .filter(|_| mode == StartMode::Hot)
+            .map(|doppler_hz| {
+                let age_sec = self.get_age_sec(now_unix).max(0.0);
+                (
+                    doppler_hz,
+                    HOT_DOPPLER_SPREAD_HZ + DOPPLER_RATE_HZ * age_sec,
+                )
+            });
+        match (predicted, remembered) {
+            (Some(p), Some(r)) => Some(if r.1 < p.1 { r } else { p }),
+            (p, r) => p.or(r),
+        }
+    }
+}
@@ -1,12 +1,50 @@
-use crate::{almanac::Almanac, channel::State};
+use crate::{
+    almanac::Almanac,
+    calibration::PpmEstimate,
+    channel::{LoopStage, State, SvPriority},
+    ephemeris::Ephemeris,
+    interference::{InterferenceEvent, InterferenceStatus},
+    navigation::{NavStatus, SubframeLog},
+    output::{OutputConfig, OutputStatus},
+    plots::PlotJob,
+    quality::SignalQuality,
+    residuals::SvResidual,
+    sky::SkyPosition,
+    start::StartMode,
+    timing::TimingStatus,
+    ttff::TimeToFix,
+};
 use gnss_rs::sv::SV;
 use gnss_rtk::prelude::Epoch;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
 
 pub struct UpdateFunc {
     pub func: Box<dyn Fn() + Send + Sync>,
 }
 
+// A channel: a satellite on one signal, the same satellite can be tracked on
+// the signals of several bands.
+pub type ChannelKey = (SV, String);
+
+// json keys are strings: the channels go as a list of (key, state) pairs
+fn serialize_channels<S: Serializer>(
+    channels: &HashMap<ChannelKey, ChannelState>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(channels.iter())
+}
+
+fn deserialize_channels<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<ChannelKey, ChannelState>, D::Error> {
+    let channels = Vec::<(ChannelKey, ChannelState)>::deserialize(deserializer)?;
+    Ok(channels.into_iter().collect())
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct ChannelState {
     pub state: State,
     pub cn0: f64,
@@ -14,6 +52,15 @@ pub struct ChannelState {
     pub code_idx: f64,
     pub phi: f64,
     pub has_eph: bool,
+    pub enabled: bool,
+    pub priority: SvPriority,
+    pub loop_stage: LoopStage,
+    pub pll_lock: f64,
+    pub trk_sec: f64, // time since the lock
+    pub quality: SignalQuality,
+    pub nav: NavStatus,
+    pub eph: Option<Ephemeris>, // once a subframe is decoded, maybe partial
+    pub subframes: VecDeque<SubframeLog>, // the latest first
 }
 impl Default for ChannelState {
     fn default() -> Self {
@@ -24,10 +71,84 @@ impl Default for ChannelState {
             code_idx: 0.0,
             phi: 0.0,
             has_eph: false,
+            enabled: true,
+            priority: SvPriority::Normal,
+            loop_stage: LoopStage::FllWide,
+            pll_lock: 0.0,
+            trk_sec: 0.0,
+            quality: SignalQuality::default(),
+            nav: NavStatus::default(),
+            eph: None,
+            subframes: VecDeque::new(),
         }
     }
 }
 
+// changes asked for while running, e.g. from the UI
+pub enum SvRequest {
+    Enable(SV, bool),
+    Priority(SV, SvPriority),
+}
+
+// Last acquisition attempt of the channel selected in the UI: peak power over
+// the average, per doppler bin and code phase bin.
+#[derive(Serialize, Deserialize)]
+pub struct AcquisitionGrid {
+    pub sv: SV,
+    pub doppler_min_hz: f64,
+    pub doppler_step_hz: f64,
+    pub code_len: usize, // chips
+    pub power: Vec<Vec<f64>>,
+    pub cn0: f64,
+    pub locked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum PlaybackEventKind {
+    Fix,
+    Lock(SV),
+    Loss(SV),
+    Interference,
+}
+
+impl fmt::Display for PlaybackEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlaybackEventKind::Fix => write!(f, "first fix"),
+            PlaybackEventKind::Lock(sv) => write!(f, "{sv} locked"),
+            PlaybackEventKind::Loss(sv) => write!(f, "{sv} lost"),
+            PlaybackEventKind::Interference => write!(f, "interference"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlaybackEvent {
+    pub pos_sec: f64, // in the recording
+    pub kind: PlaybackEventKind,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordingStatus {
+    pub path: PathBuf,
+    pub duration_sec: f64,
+    pub num_bytes: u64,
+    pub num_dropped: usize, // samples, the disk too slow
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlaybackProgress {
+    pub percent: f64,
+    pub speed: Option<f64>, // relative to real-time, none until measurable
+    pub eta_sec: Option<f64>,
+    pub pos_sec: f64, // in the recording
+    pub duration_sec: f64,
+}
+
+// What the receiver shares with the UI and the outputs. The requests and what
+// only the UI takes are left out of the serialized form.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct GnssState {
     pub tow_gpst: Epoch,
     pub almanac: Vec<Almanac>,
@@ -37,8 +158,51 @@ pub struct GnssState {
     pub longitude: f64,
     pub height: f64,
 
-    pub channels: HashMap<SV, ChannelState>,
+    #[serde(serialize_with = "serialize_channels")]
+    #[serde(deserialize_with = "deserialize_channels")]
+    pub channels: HashMap<ChannelKey, ChannelState>,
+    #[serde(skip)]
     pub update_func: UpdateFunc,
+    #[serde(skip)]
+    pub capture_req: bool, // dump the pre-trigger IQ buffer
+    #[serde(skip)]
+    pub record_req: Option<bool>, // start or stop recording, taken by the receiver
+    pub recording: Option<RecordingStatus>,
+    #[serde(skip)]
+    pub output_req: Option<OutputConfig>, // taken by the receiver
+    pub outputs: Vec<OutputStatus>,
+    #[serde(skip)]
+    pub sv_requests: Vec<SvRequest>, // taken by the receiver at its next step
+    pub num_time_jumps: usize, // discontinuities in the sample stream, e.g. file loops
+    pub num_dropped_samples: usize, // lost by the sources, e.g. usb overflows
+    pub progress: Option<PlaybackProgress>,
+    pub events: Vec<PlaybackEvent>, // shown along the playback timeline
+    pub paused: bool,
+    #[serde(skip)]
+    pub pause_req: Option<bool>, // pause or resume, taken by the receiver
+    #[serde(skip)]
+    pub seek_req: Option<f64>, // seconds into the recording
+    pub device_lost: bool, // channels are in holdover until the device is back
+    pub lagging: bool,     // processing behind a real-time source: acquisition paused
+    pub num_skipped_msec: usize, // code periods skipped to catch up
+    pub start_mode: StartMode,
+    pub ttff: TimeToFix,
+    pub ppm_estimate: Option<PpmEstimate>, // of the front-end oscillator
+    pub timing: Option<TimingStatus>,
+    pub interference: InterferenceStatus,
+    pub interference_events: Vec<InterferenceEvent>, // in signal time, the latest last
+    pub live_plots: bool,                  // tracking plots drawn by the UI, no png files
+    pub plot_sv: Option<SV>,               // channel whose plots the UI shows
+    #[serde(skip)]
+    pub plot: Option<PlotJob>, // its latest histories
+    pub sky: Vec<SkyPosition>,             // tracked satellites, seen from the last fix
+    pub fixes: Vec<[f64; 3]>,              // position track: latitude, longitude, height
+    pub residuals: Vec<SvResidual>,        // of the satellites at the last fix
+    #[serde(skip)]
+    pub spectrum: Option<Vec<f64>>, // of the incoming samples, dB: taken by the UI
+    pub spectrum_fs: f64,
+    #[serde(skip)]
+    pub acq_grid: Option<AcquisitionGrid>, // taken by the UI
 }
 
 impl GnssState {
@@ -51,13 +215,75 @@ impl GnssState {
             latitude: 0.0,
             longitude: 0.0,
             height: 0.0,
-            channels: HashMap::<SV, ChannelState>::new(),
+            channels: HashMap::<ChannelKey, ChannelState>::new(),
             update_func: UpdateFunc {
                 func: Box::new(|| {}),
             },
+            capture_req: false,
+            record_req: None,
+            recording: None,
+            output_req: None,
+            outputs: vec![],
+            sv_requests: vec![],
+            num_time_jumps: 0,
+            num_dropped_samples: 0,
+            progress: None,
+            events: vec![],
+            paused: false,
+            pause_req: None,
+            seek_req: None,
+            device_lost: false,
+            lagging: false,
+            num_skipped_msec: 0,
+            start_mode: StartMode::Cold,
+            ttff: TimeToFix::default(),
+            ppm_estimate: None,
+            timing: None,
+            interference: InterferenceStatus::default(),
+            interference_events: vec![],
+            live_plots: false,
+            plot_sv: None,
+            plot: None,
+            sky: vec![],
+            fixes: vec![],
+            residuals: vec![],
+            spectrum: None,
+            spectrum_fs: 0.0,
+            acq_grid: None,
         }
     }
     pub fn set_update_func(&mut self, func: Box<dyn Fn() + Send + Sync>) {
         self.update_func.func = func;
     }
+
+    // the satellites of the channels, once each whatever their signals
+    pub fn get_svs(&self) -> Vec<SV> {
+        let mut svs: Vec<_> = self.channels.keys().map(|(sv, _sig)| *sv).collect();
+        svs.sort();
+        svs.dedup();
+        svs
+    }
+
+    // the channel of a satellite on the first of its signals
+    pub fn get_sv_channel(&self, sv: SV) -> Option<&ChannelState> {
+        (self.channels.iter())
+            .filter(|((s, _sig), _channel)| *s == sv)
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_key, channel)| channel)
+    }
+
+    // a channel by its satellite, with its signal when several track it
+    pub fn get_channel_label(&self, (sv, sig): &ChannelKey) -> String {
+        if self.channels.keys().filter(|(s, _sig)| s == sv).count() > 1 {
+            format!("{sv} {sig}")
+        } else {
+            sv.to_string()
+        }
+    }
+}
+
+impl Default for GnssState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,205 @@
+use gnss_rs::sv::SV;
+use map_3d::{Ellipsoid, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::interference::InterferenceEvent;
+use crate::navigation::NavStatus;
+use crate::sky::{Dop, get_enu};
+
+const CN0_PERIOD_SEC: f64 = 1.0;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SvSummary {
+    pub sv: String,
+    pub trk_sec: f64, // of signal, all the locks together
+    pub mean_cn0: f64,
+    pub num_reacquisitions: usize, // locks after the first one
+    pub num_subframes: usize,
+    pub num_parity_errors: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SolutionSummary {
+    pub num_fixes: usize,
+    pub ttff_sec: Option<f64>,
+    // of the latest fixes around their average position: with no reference
+    // position to compare to, how far they wander
+    pub mean_error_m: Option<f64>,
+    pub scatter_h_m: Option<f64>, // rms
+    pub scatter_v_m: Option<f64>,
+    pub mean_dop: Option<Dop>,
+}
+
+// What a run achieved, printed as a single json line at its end for the
+// scripts driving gnss-rcv, e.g. to compare runs or parameter sets.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunSummary {
+    pub interrupted: bool, // by Ctrl-C, rather than at the end of the input
+    pub signal_sec: f64,
+    pub wall_sec: f64,
+    pub num_subframes: usize, // all the channels together
+    pub num_parity_errors: usize,
+    pub svs: Vec<SvSummary>, // tracked at some point
+    #[serde(default)]
+    pub interference: Vec<InterferenceEvent>, // detected, in signal time
+    pub solution: SolutionSummary,
+}
+
+impl RunSummary {
+    pub fn print(&self) {
+        match serde_json::to_string(self) {
+            Ok(json) => println!("{json}"),
+            Err(e) => tracing::warn!("summary: {e}"),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[derive(Default)]
+struct SvTotals {
+    trk_sec: f64,
+    cn0_sec: f64, // cn0 integrated over the tracking time
+    num_locks: usize,
+}
+
+// What the summary is built from, accumulated as the run goes.
+#[derive(Default)]
+pub struct RunStats {
+    svs: BTreeMap<SV, SvTotals>,
+    last_sec: Option<f64>,
+    num_fixes: usize,
+    dop_sum: [f64; 4],
+    num_dops: usize,
+    cn0: BTreeMap<SV, Vec<(f64, f64)>>, // one point per CN0_PERIOD_SEC, for the run charts
+    last_cn0_sec: Option<f64>,
+}
+
+impl RunStats {
+    pub fn add_lock(&mut self, sv: SV) {
+        self.svs.entry(sv).or_default().num_locks += 1;
+    }
+
+    // once per step, with the cn0 of the tracked satellites
+    pub fn add_tracking(&mut self, ts_sec: f64, tracking: impl Iterator<Item = (SV, f64)>) {
+        let dt = ts_sec - self.last_sec.unwrap_or(ts_sec);
+        self.last_sec = Some(ts_sec);
+        let sample = self
+            .last_cn0_sec
+            .is_none_or(|last_sec| ts_sec - last_sec >= CN0_PERIOD_SEC);
+        if sample {
+            self.last_cn0_sec = Some(ts_sec);
+        }
+        for (sv, cn0) in tracking {
+            let totals = self.svs.entry(sv).or_default();
+            totals.trk_sec += dt;
+            totals.cn0_sec += cn0 * dt;
+            if sample {
+                self.cn0.entry(sv).or_default().push((ts_sec, cn0));
+            }
+        }
+    }
+
+    // C/N0 of each satellite over time, while tracked
+    pub fn get_cn0_history(&self) -> Vec<(SV, Vec<(f64, f64)>)> {
+        (self.cn0.iter())
+            .map(|(sv, points)| (*sv, points.clone()))
+            .collect()
+    }
+
+    pub fn add_fix(&mut self) {
+        self.num_fixes += 1;
+    }
+
+    pub fn add_dop(&mut self, dop: Dop) {
+        let values = [dop.gdop, dop.pdop, dop.hdop, dop.vdop];
+        for (sum, value) in self.dop_sum.iter_mut().zip(values) {
+            *sum += value;
+        }
+        self.num_dops += 1;
+    }
+
+    pub fn get_sv_summaries(&self, nav: &BTreeMap<SV, NavStatus>) -> Vec<SvSummary> {
+        (self.svs.iter())
+            .filter(|(_, totals)| totals.num_locks > 0)
+            .map(|(sv, totals)| {
+                let nav = nav.get(sv).copied().unwrap_or_default();
+                SvSummary {
+                    sv: sv.to_string(),
+                    trk_sec: totals.trk_sec,
+                    mean_cn0: if totals.trk_sec > 0.0 {
+                        totals.cn0_sec / totals.trk_sec
+                    } else {
+                        0.0
+                    },
+                    num_reacquisitions: totals.num_locks - 1,
+                    num_subframes: nav.num_subframes,
+                    num_parity_errors: nav.num_parity_errors,
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_solution_summary(
+        &self,
+        fixes: &[[f64; 3]],
+        ttff_sec: Option<f64>,
+    ) -> SolutionSummary {
+        let scatter = get_scatter(fixes);
+        let n = self.num_dops as f64;
+        let [gdop, pdop, hdop, vdop] = self.dop_sum.map(|sum| sum / n);
+        SolutionSummary {
+            num_fixes: self.num_fixes,
+            ttff_sec,
+            mean_error_m: scatter.map(|s| s.0),
+            scatter_h_m: scatter.map(|s| s.1),
+            scatter_v_m: scatter.map(|s| s.2),
+            mean_dop: (self.num_dops > 0).then_some(Dop {
+                gdop,
+                pdop,
+                hdop,
+                vdop,
+            }),
+        }
+    }
+}
+
+// East, north and up (m) of the fixes from their average position.
+pub fn get_enus(fixes: &[[f64; 3]]) -> Vec<[f64; 3]> {
+    if fixes.is_empty() {
+        return vec![];
+    }
+    let n = fixes.len() as f64;
+    let mean = [0, 1, 2].map(|i| fixes.iter().map(|fix| fix[i]).sum::<f64>() / n);
+    fixes
+        .iter()
+        .map(|fix| {
+            let (lat, lon) = (fix[0].to_radians(), fix[1].to_radians());
+            let (x, y, z) = geodetic2ecef(lat, lon, fix[2], Ellipsoid::WGS84);
+            get_enu(mean, [x, y, z])
+        })
+        .collect()
+}
+
+// Mean distance, horizontal and vertical rms (m) of the fixes to their
+// average position.
+pub fn get_scatter(fixes: &[[f64; 3]]) -> Option<(f64, f64, f64)> {
+    if fixes.is_empty() {
+        return None;
+    }
+    let n = fixes.len() as f64;
+    let enus = get_enus(fixes);
+    let mean_m = enus
+        .iter()
+        .map(|[e, n, u]| (e * e + n * n + u * u).sqrt())
+        .sum::<f64>()
+        / n;
+    let h_rms_m = (enus.iter().map(|[e, n, _]| e * e + n * n).sum::<f64>() / n).sqrt();
+    let v_rms_m = (enus.iter().map(|[_, _, u]| u * u).sum::<f64>() / n).sqrt();
+    Some((mean_m, h_rms_m, v_rms_m))
+}
@@ -0,0 +1,60 @@
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+// "2,3,6-8" -> [2, 3, 6, 7, 8]
+pub fn parse_cpu_list(s: &str) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let mut cpus = vec![];
+    for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        match item.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>()?..=last.parse::<usize>()?),
+            None => cpus.push(item.parse::<usize>()?),
+        }
+    }
+    Ok(cpus)
+}
+
+// Dedicated pool for the channel processing, instead of rayon's global one
+// sized after all the cpus. With a cpu list, worker i is pinned to
+// cpus[i % len] and the pool defaults to one worker per cpu.
+pub fn build_thread_pool(
+    num_threads: usize,
+    cpus: &[usize],
+) -> Result<ThreadPool, Box<dyn std::error::Error>> {
+    let num_threads = if num_threads == 0 {
+        cpus.len()
+    } else {
+        num_threads
+    };
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    for cpu in cpus {
+        if !core_ids.iter().any(|core_id| core_id.id == *cpu) {
+            return Err(format!("cpu {cpu} not available").into());
+        }
+    }
+
+    let cpus = cpus.to_vec();
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|idx| format!("gnss-rcv-{idx}"))
+        .start_handler(move |idx| {
+            if cpus.is_empty() {
+                return;
+            }
+            let cpu = cpus[idx % cpus.len()];
+            if !core_affinity::set_for_current(core_affinity::CoreId { id: cpu }) {
+                tracing::warn!("worker {idx}: failed to pin to cpu {cpu}");
+            }
+        })
+        .build()?;
+
+    tracing::info!("thread pool: {} workers", pool.current_num_threads());
+    Ok(pool)
+}
+
+// runs f on the dedicated pool if there is one, on rayon's global pool
+// otherwise
+pub fn install<R: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
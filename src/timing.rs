@@ -0,0 +1,266 @@
+use gnss_rtk::prelude::{Duration, Epoch};
+use map_3d::{Ellipsoid, ecef2geodetic, geodetic2ecef};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::constants::{EARTH_ROTATION_RATE, SPEED_OF_LIGHT};
+use crate::ephemeris::Ephemeris;
+use crate::sky::get_azimuth_elevation;
+use crate::solver::get_sv_position_ecef;
+use crate::util::fit_line;
+
+pub const TIMING_PERIOD_SEC: f64 = 1.0; // of signal between two estimates
+pub const DEFAULT_SURVEY_SEC: f64 = 600.0;
+const MIN_SURVEY_FIXES: usize = 10;
+const NAV_DECODE_DELAY_SEC: f64 = 0.16; // a subframe is decoded 8 bits into the next one
+pub const CODE_SEC: f64 = 1e-3; // ambiguity of the code offsets
+const MIN_ELEVATION: f64 = 10.0; // degrees
+const MAX_RESIDUAL_SEC: f64 = 1e-6; // satellites further from the others are dropped
+const MAX_PREDICTION_SEC: f64 = 300.0; // of holdover, to resolve the ambiguity
+const FIT_SEC: f64 = 30.0; // of offsets the frequency error is fitted over
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TimingStage {
+    Survey,   // averaging position fixes
+    Locked,   // clock offset measured from the satellites
+    Holdover, // no satellite: offset predicted from the last frequency error
+}
+
+impl fmt::Display for TimingStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimingStage::Survey => write!(f, "survey-in"),
+            TimingStage::Locked => write!(f, "locked"),
+            TimingStage::Holdover => write!(f, "holdover"),
+        }
+    }
+}
+
+// The local clock against gps time: the local clock is the time of the first
+// sample, from the recording's sidecar or the system clock, plus the samples
+// counted since at the nominal sampling rate.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct TimingStatus {
+    pub stage: TimingStage,
+    pub ts_sec: f64,                // receiver time of the estimate
+    pub position: Option<[f64; 3]>, // latitude, longitude (degrees), height (m)
+    pub num_survey_fixes: usize,
+    pub survey_rms_m: Option<f64>, // of the fixes around the surveyed position
+    pub offset_sec: Option<f64>,   // local clock minus gps time
+    pub freq_error_ppb: Option<f64>, // of the local clock, > 0 when fast
+    pub num_svs: usize,
+    pub holdover_sec: f64, // since the last measurement
+}
+
+impl fmt::Display for TimingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stage)?;
+        if self.stage == TimingStage::Survey {
+            return write!(f, ": {} fixes", self.num_survey_fixes);
+        }
+        if let Some(offset_sec) = self.offset_sec {
+            write!(f, ": offset={offset_sec:+.9} s")?;
+        }
+        if let Some(ppb) = self.freq_error_ppb {
+            write!(f, " freq={ppb:+.1} ppb")?;
+        }
+        match self.stage {
+            TimingStage::Holdover => write!(f, " for {:.0} s", self.holdover_sec),
+            _ => write!(f, " ({} satellites)", self.num_svs),
+        }
+    }
+}
+
+pub fn get_ecef(pos: [f64; 3]) -> [f64; 3] {
+    let (lat, lon) = (pos[0].to_radians(), pos[1].to_radians());
+    let (x, y, z) = geodetic2ecef(lat, lon, pos[2], Ellipsoid::WGS84);
+    [x, y, z]
+}
+
+fn get_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+pub fn get_median(v: &[f64]) -> Option<f64> {
+    let mut v = v.to_vec();
+    v.sort_by(f64::total_cmp);
+    v.get(v.len() / 2).copied()
+}
+
+// The local clock, at local, minus gps time at the receiver time ts_sec, as
+// one satellite sees it from pos (geodetic and ecef), up to a code period:
+// None below the mask. The ionospheric delay, tens of nsec, is left in.
+pub fn get_clock_offset(
+    eph: &Ephemeris,
+    ts_sec: f64,
+    local: Epoch,
+    (pos, pos_ecef): ([f64; 3], [f64; 3]),
+) -> Option<f64> {
+    // the signal received at ts_sec left the satellite at t_sv, its time
+    let dt = ts_sec - eph.ts_sec + NAV_DECODE_DELAY_SEC - eph.code_off_sec;
+    let t_sv = eph.tow_gpst + Duration::from_seconds(dt);
+    let dt_clock = (t_sv - eph.toc_gpst).to_seconds();
+    let sv_clock = eph.f0 + eph.f1 * dt_clock + eph.f2 * dt_clock.powi(2) - eph.tgd;
+    let t_tx = t_sv - Duration::from_seconds(sv_clock);
+
+    let (x, y, z) = get_sv_position_ecef(eph, t_tx);
+    // earth rotation during the propagation
+    let theta = EARTH_ROTATION_RATE * get_distance([x, y, z], pos_ecef) / SPEED_OF_LIGHT;
+    let sv_pos = [
+        x * theta.cos() + y * theta.sin(),
+        y * theta.cos() - x * theta.sin(),
+        z,
+    ];
+    let (_, elevation) = get_azimuth_elevation(pos, sv_pos);
+    if elevation < MIN_ELEVATION {
+        return None;
+    }
+    let tropo_m = 2.47 / (elevation.to_radians().sin() + 0.0121);
+    let range_m = get_distance(sv_pos, pos_ecef) + tropo_m;
+    let t_rx = t_tx + Duration::from_seconds(range_m / SPEED_OF_LIGHT);
+    Some((local - t_rx).to_seconds())
+}
+
+// For an antenna that doesn't move, e.g. a lab time reference: its position
+// is surveyed in by averaging fixes, unless given, and then a single
+// satellite is enough to measure the local clock against gps time. Without
+// any, the offset is predicted from the last frequency error (holdover).
+pub struct TimingSolver {
+    survey_sec: f64,
+    start: Epoch, // local clock at the first sample
+    survey: Vec<[f64; 3]>,
+    survey_start_sec: Option<f64>,
+    position: Option<([f64; 3], [f64; 3])>, // geodetic and ecef
+    offsets: VecDeque<(f64, f64)>,          // receiver time and measured offset
+    fit: Option<(f64, f64, f64)>,           // receiver time, offset and its slope
+    status: TimingStatus,
+}
+
+impl TimingSolver {
+    pub fn new(survey_sec: f64, position: Option<[f64; 3]>, start_unix: f64) -> Self {
+        Self {
+            survey_sec,
+            start: Epoch::from_unix_seconds(start_unix),
+            survey: vec![],
+            survey_start_sec: None,
+            position: position.map(|pos| (pos, get_ecef(pos))),
+            offsets: VecDeque::new(),
+            fit: None,
+            status: TimingStatus {
+                stage: if position.is_some() {
+                    TimingStage::Holdover
+                } else {
+                    TimingStage::Survey
+                },
+                ts_sec: 0.0,
+                position,
+                num_survey_fixes: 0,
+                survey_rms_m: None,
+                offset_sec: None,
+                freq_error_ppb: None,
+                num_svs: 0,
+                holdover_sec: 0.0,
+            },
+        }
+    }
+
+    pub fn get_status(&self) -> TimingStatus {
+        self.status
+    }
+
+    pub fn is_surveying(&self) -> bool {
+        self.position.is_none()
+    }
+
+    // a position fix while surveying: true when it completes the survey
+    pub fn add_fix(&mut self, ts_sec: f64, fix: [f64; 3]) -> bool {
+        if !self.is_surveying() {
+            return false;
+        }
+        self.survey.push(get_ecef(fix));
+        self.status.num_survey_fixes = self.survey.len();
+        let start_sec = *self.survey_start_sec.get_or_insert(ts_sec);
+        if ts_sec - start_sec < self.survey_sec || self.survey.len() < MIN_SURVEY_FIXES {
+            return false;
+        }
+        let n = self.survey.len() as f64;
+        let mean = [0, 1, 2].map(|i| self.survey.iter().map(|p| p[i]).sum::<f64>() / n);
+        let rms_m = (self.survey.iter())
+            .map(|&p| get_distance(p, mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let (lat, lon, height) = ecef2geodetic(mean[0], mean[1], mean[2], Ellipsoid::WGS84);
+        let pos = [lat.to_degrees(), lon.to_degrees(), height];
+        self.position = Some((pos, mean));
+        self.status.position = Some(pos);
+        self.status.survey_rms_m = Some(rms_m.sqrt());
+        self.status.stage = TimingStage::Holdover;
+        true
+    }
+
+    fn get_offset(&self, eph: &Ephemeris, ts_sec: f64) -> Option<f64> {
+        let local = self.start + Duration::from_seconds(ts_sec);
+        get_clock_offset(eph, ts_sec, local, self.position?)
+    }
+
+    fn predict(&self, ts_sec: f64) -> Option<f64> {
+        let (fit_sec, offset, slope) = self.fit?;
+        Some(offset + slope * (ts_sec - fit_sec))
+    }
+
+    // Every TIMING_PERIOD_SEC with the ephemerides of the tracked satellites,
+    // once the position is known: the stage it's at.
+    pub fn update(&mut self, ts_sec: f64, ephs: &[Ephemeris]) -> TimingStage {
+        if self.is_surveying() {
+            return TimingStage::Survey;
+        }
+        let offsets: Vec<_> = ephs
+            .iter()
+            .filter_map(|eph| self.get_offset(eph, ts_sec))
+            .collect();
+        // the code period ambiguity, against the prediction or the majority
+        let prediction = self
+            .predict(ts_sec)
+            .filter(|_| self.status.holdover_sec < MAX_PREDICTION_SEC);
+        let offsets: Vec<_> = match prediction.or_else(|| get_median(&offsets)) {
+            Some(reference) => (offsets.iter())
+                .map(|&o| o - ((o - reference) / CODE_SEC).round() * CODE_SEC)
+                .collect(),
+            None => vec![],
+        };
+        let offsets: Vec<_> = match get_median(&offsets) {
+            Some(median) => (offsets.into_iter())
+                .filter(|o| (o - median).abs() <= MAX_RESIDUAL_SEC)
+                .collect(),
+            None => vec![],
+        };
+
+        self.status.ts_sec = ts_sec;
+        self.status.num_svs = offsets.len();
+        if offsets.is_empty() {
+            self.status.holdover_sec = self.fit.map_or(0.0, |(t, _, _)| ts_sec - t);
+            self.status.offset_sec = self.predict(ts_sec);
+            self.status.stage = TimingStage::Holdover;
+            return self.status.stage;
+        }
+
+        let offset = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        self.offsets.push_back((ts_sec, offset));
+        while (self.offsets.front()).is_some_and(|&(t, _)| ts_sec - t > FIT_SEC) {
+            self.offsets.pop_front();
+        }
+        // a single offset keeps the previous frequency error
+        let (offset, mut slope) = fit_line(&self.offsets, ts_sec);
+        if self.offsets.len() >= 2 {
+            self.status.freq_error_ppb = Some(slope * 1e9);
+        } else {
+            slope = self.fit.map_or(0.0, |(_, _, slope)| slope);
+        }
+        self.fit = Some((ts_sec, offset, slope));
+        self.status.offset_sec = Some(offset);
+        self.status.holdover_sec = 0.0;
+        self.status.stage = TimingStage::Locked;
+        self.status.stage
+    }
+}
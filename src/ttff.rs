@@ -0,0 +1,70 @@
+use colored::Colorize;
+use gnss_rs::sv::SV;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::start::StartMode;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Milestone {
+    Acquisition, // first satellite locked
+    Subframe,    // first navigation subframe decoded
+    Ephemeris,   // first satellite with a complete ephemeris
+    Fix,
+}
+
+const MILESTONES: [Milestone; 4] = [
+    Milestone::Acquisition,
+    Milestone::Subframe,
+    Milestone::Ephemeris,
+    Milestone::Fix,
+];
+
+impl fmt::Display for Milestone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Milestone::Acquisition => write!(f, "first acquisition"),
+            Milestone::Subframe => write!(f, "first subframe"),
+            Milestone::Ephemeris => write!(f, "ephemeris"),
+            Milestone::Fix => write!(f, "first fix"),
+        }
+    }
+}
+
+// Receiver time at which a run first reached each milestone, and the
+// satellite that did: what a start mode gains shows up here.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
+pub struct TimeToFix {
+    times: [Option<(f64, Option<SV>)>; MILESTONES.len()],
+}
+
+impl TimeToFix {
+    // true when the milestone is reached for the first time
+    pub fn record(&mut self, milestone: Milestone, ts_sec: f64, sv: Option<SV>) -> bool {
+        let time = &mut self.times[milestone as usize];
+        if time.is_some() {
+            return false;
+        }
+        *time = Some((ts_sec, sv));
+        true
+    }
+
+    pub fn get(&self, milestone: Milestone) -> Option<f64> {
+        self.times[milestone as usize].map(|(ts_sec, _sv)| ts_sec)
+    }
+
+    pub fn print_report(&self, start_mode: StartMode) {
+        println!("ttff: {} start", start_mode.to_string().bold());
+        for milestone in MILESTONES {
+            match self.times[milestone as usize] {
+                Some((ts_sec, sv)) => println!(
+                    "  {:<18} {:>8.3} sec {}",
+                    milestone.to_string(),
+                    ts_sec,
+                    sv.map_or(String::new(), |sv| sv.to_string())
+                ),
+                None => println!("  {:<18} {:>8} sec", milestone.to_string(), "-"),
+            }
+        }
+    }
+}
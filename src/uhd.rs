@@ -0,0 +1,419 @@
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::DeviceConfig;
+use crate::sample::{Float, IQSample};
+use crate::source::{SampleBlock, SampleSource};
+
+const UHD_ERROR_NONE: c_int = 0;
+const UHD_TUNE_REQUEST_POLICY_AUTO: c_int = 65;
+const UHD_STREAM_MODE_START_CONTINUOUS: c_int = 97;
+const UHD_STREAM_MODE_STOP_CONTINUOUS: c_int = 111;
+const UHD_RX_METADATA_ERROR_CODE_NONE: c_int = 0x0;
+const UHD_RX_METADATA_ERROR_CODE_TIMEOUT: c_int = 0x1;
+const UHD_RX_METADATA_ERROR_CODE_OVERFLOW: c_int = 0x8;
+const UHD_RECV_TIMEOUT_SEC: f64 = 1.0;
+const UHD_CHANNEL: usize = 0;
+const UHD_MBOARD: usize = 0;
+
+#[repr(C)]
+struct UhdUsrp {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct UhdRxStreamer {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct UhdRxMetadata {
+    _private: [u8; 0],
+}
+type UhdUsrpHandle = *mut UhdUsrp;
+type UhdRxStreamerHandle = *mut UhdRxStreamer;
+type UhdRxMetadataHandle = *mut UhdRxMetadata;
+
+#[repr(C)]
+struct UhdTuneRequest {
+    target_freq: f64,
+    rf_freq_policy: c_int,
+    rf_freq: f64,
+    dsp_freq_policy: c_int,
+    dsp_freq: f64,
+    args: *mut c_char,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct UhdTuneResult {
+    clipped_rf_freq: f64,
+    target_rf_freq: f64,
+    actual_rf_freq: f64,
+    target_dsp_freq: f64,
+    actual_dsp_freq: f64,
+}
+
+#[repr(C)]
+struct UhdStreamArgs {
+    cpu_format: *mut c_char,
+    otw_format: *mut c_char,
+    args: *mut c_char,
+    channel_list: *mut usize,
+    n_channels: c_int,
+}
+
+#[repr(C)]
+struct UhdStreamCmd {
+    stream_mode: c_int,
+    num_samps: usize,
+    stream_now: bool,
+    time_spec_full_secs: i64,
+    time_spec_frac_secs: f64,
+}
+
+#[link(name = "uhd")]
+unsafe extern "C" {
+    fn uhd_usrp_make(h: *mut UhdUsrpHandle, args: *const c_char) -> c_int;
+    fn uhd_usrp_free(h: *mut UhdUsrpHandle) -> c_int;
+    fn uhd_rx_streamer_make(h: *mut UhdRxStreamerHandle) -> c_int;
+    fn uhd_rx_streamer_free(h: *mut UhdRxStreamerHandle) -> c_int;
+    fn uhd_rx_metadata_make(h: *mut UhdRxMetadataHandle) -> c_int;
+    fn uhd_rx_metadata_free(h: *mut UhdRxMetadataHandle) -> c_int;
+    fn uhd_usrp_set_clock_source(h: UhdUsrpHandle, src: *const c_char, mboard: usize) -> c_int;
+    fn uhd_usrp_set_time_source(h: UhdUsrpHandle, src: *const c_char, mboard: usize) -> c_int;
+    fn uhd_usrp_set_time_unknown_pps(h: UhdUsrpHandle, full_secs: i64, frac_secs: f64) -> c_int;
+    fn uhd_usrp_set_rx_rate(h: UhdUsrpHandle, rate: f64, chan: usize) -> c_int;
+    fn uhd_usrp_get_rx_rate(h: UhdUsrpHandle, chan: usize, rate_out: *mut f64) -> c_int;
+    fn uhd_usrp_set_rx_gain(
+        h: UhdUsrpHandle,
+        gain: f64,
+        chan: usize,
+        gain_name: *const c_char,
+    ) -> c_int;
+    fn uhd_usrp_set_rx_freq(
+        h: UhdUsrpHandle,
+        tune_request: *mut UhdTuneRequest,
+        chan: usize,
+        tune_result: *mut UhdTuneResult,
+    ) -> c_int;
+    fn uhd_usrp_get_rx_stream(
+        h: UhdUsrpHandle,
+        stream_args: *mut UhdStreamArgs,
+        h_out: UhdRxStreamerHandle,
+    ) -> c_int;
+    fn uhd_rx_streamer_max_num_samps(
+        h: UhdRxStreamerHandle,
+        max_num_samps_out: *mut usize,
+    ) -> c_int;
+    fn uhd_rx_streamer_issue_stream_cmd(
+        h: UhdRxStreamerHandle,
+        stream_cmd: *const UhdStreamCmd,
+    ) -> c_int;
+    fn uhd_rx_streamer_recv(
+        h: UhdRxStreamerHandle,
+        buffs: *mut *mut c_void,
+        samps_per_buff: usize,
+        md: *mut UhdRxMetadataHandle,
+        timeout: f64,
+        one_packet: bool,
+        items_recvd: *mut usize,
+    ) -> c_int;
+    fn uhd_rx_metadata_has_time_spec(h: UhdRxMetadataHandle, result_out: *mut bool) -> c_int;
+    fn uhd_rx_metadata_time_spec(
+        h: UhdRxMetadataHandle,
+        full_secs_out: *mut i64,
+        frac_secs_out: *mut f64,
+    ) -> c_int;
+    fn uhd_rx_metadata_error_code(h: UhdRxMetadataHandle, error_code_out: *mut c_int) -> c_int;
+}
+
+pub struct UhdDevice {
+    usrp: UhdUsrpHandle,
+    streamer: UhdRxStreamerHandle,
+    md: UhdRxMetadataHandle,
+    fs: f64,
+    buf: Vec<[f32; 2]>,
+    pending: Vec<IQSample>,
+    pending_ts_sec: Option<f64>, // device time of pending[0]
+    ts_next_sec: Option<f64>,    // expected device time of the next sample
+    num_samples_total: usize,
+    num_overflows: usize,
+    num_dropped: usize, // not reported yet
+    time_jump: bool,
+}
+
+// the handles are only used from the thread owning the UhdDevice.
+unsafe impl Send for UhdDevice {}
+
+impl Drop for UhdDevice {
+    fn drop(&mut self) {
+        tracing::info!(
+            "uhd: stopping read. num_samples={} overflows={}",
+            self.num_samples_total,
+            self.num_overflows
+        );
+        let cmd = UhdStreamCmd {
+            stream_mode: UHD_STREAM_MODE_STOP_CONTINUOUS,
+            num_samps: 0,
+            stream_now: true,
+            time_spec_full_secs: 0,
+            time_spec_frac_secs: 0.0,
+        };
+        unsafe {
+            uhd_rx_streamer_issue_stream_cmd(self.streamer, &cmd);
+            uhd_rx_metadata_free(&mut self.md);
+            uhd_rx_streamer_free(&mut self.streamer);
+            uhd_usrp_free(&mut self.usrp);
+        }
+    }
+}
+
+fn check(res: c_int, what: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if res != UHD_ERROR_NONE {
+        return Err(format!("uhd: {what} failed: {res}").into());
+    }
+    Ok(())
+}
+
+impl UhdDevice {
+    fn get_time_spec(&self) -> Option<f64> {
+        let mut has_time_spec = false;
+        let mut full_secs = 0i64;
+        let mut frac_secs = 0.0;
+        unsafe {
+            uhd_rx_metadata_has_time_spec(self.md, &mut has_time_spec);
+            if !has_time_spec {
+                return None;
+            }
+            uhd_rx_metadata_time_spec(self.md, &mut full_secs, &mut frac_secs);
+        }
+        Some(full_secs as f64 + frac_secs)
+    }
+
+    fn recv_packet(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut ptr = self.buf.as_mut_ptr() as *mut c_void;
+        let mut n = 0;
+        let mut error_code = UHD_RX_METADATA_ERROR_CODE_NONE;
+        unsafe {
+            check(
+                uhd_rx_streamer_recv(
+                    self.streamer,
+                    &mut ptr,
+                    self.buf.len(),
+                    &mut self.md,
+                    UHD_RECV_TIMEOUT_SEC,
+                    true,
+                    &mut n,
+                ),
+                "recv",
+            )?;
+            uhd_rx_metadata_error_code(self.md, &mut error_code);
+        }
+        match error_code {
+            UHD_RX_METADATA_ERROR_CODE_NONE => {}
+            UHD_RX_METADATA_ERROR_CODE_OVERFLOW => {
+                // samples were lost: the next time spec tells how many
+                self.num_overflows += 1;
+                tracing::warn!("uhd: overflow #{}", self.num_overflows);
+            }
+            UHD_RX_METADATA_ERROR_CODE_TIMEOUT => return Err("uhd: recv timeout".into()),
+            _ => return Err(format!("uhd: recv error {error_code:#x}").into()),
+        }
+        if n == 0 {
+            return Ok(());
+        }
+
+        let ts = self.get_time_spec();
+        if let Some(ts) = ts {
+            match self.ts_next_sec {
+                None => tracing::info!("uhd: first sample at device time {ts:.9}"),
+                Some(ts_next) => {
+                    let gap = ((ts - ts_next) * self.fs).round() as i64;
+                    if gap > 0 {
+                        tracing::warn!("uhd: {gap} samples dropped at {ts:.9}");
+                        self.num_dropped += gap as usize;
+                    } else if gap < 0 {
+                        tracing::warn!("uhd: device time went back by {} samples", -gap);
+                        self.time_jump = true;
+                    }
+                }
+            }
+            self.ts_next_sec = Some(ts + n as f64 / self.fs);
+        }
+
+        // after a gap, the pending samples are timestamped consistently with
+        // the most recent ones.
+        let num_pending = self.pending.len();
+        self.pending_ts_sec = ts.map(|ts| ts - num_pending as f64 / self.fs);
+        self.pending.extend(self.buf[..n].iter().map(|s| IQSample {
+            re: s[0] as Float,
+            im: s[1] as Float,
+        }));
+        self.num_samples_total += n;
+        Ok(())
+    }
+
+    pub fn new(
+        sig: &str,
+        fs: f64,
+        config: &DeviceConfig,
+    ) -> Result<UhdDevice, Box<dyn std::error::Error>> {
+        let args = CString::new(config.device_args.as_str())?;
+        let mut usrp = std::ptr::null_mut();
+        let mut streamer = std::ptr::null_mut();
+        let mut md = std::ptr::null_mut();
+
+        unsafe {
+            check(uhd_usrp_make(&mut usrp, args.as_ptr()), "usrp_make")?;
+            // no drop yet: the handles already made are released here
+            if let Err(e) = check(uhd_rx_streamer_make(&mut streamer), "rx_streamer_make") {
+                uhd_usrp_free(&mut usrp);
+                return Err(e);
+            }
+            if let Err(e) = check(uhd_rx_metadata_make(&mut md), "rx_metadata_make") {
+                uhd_rx_streamer_free(&mut streamer);
+                uhd_usrp_free(&mut usrp);
+                return Err(e);
+            }
+        }
+        // from here on the handles are released by drop
+        let mut m = Self {
+            usrp,
+            streamer,
+            md,
+            fs,
+            buf: vec![],
+            pending: vec![],
+            pending_ts_sec: None,
+            ts_next_sec: None,
+            num_samples_total: 0,
+            num_overflows: 0,
+            num_dropped: 0,
+            time_jump: false,
+        };
+
+        // "external" or "gpsdo": lock to the reference and align the device
+        // time to the host's UTC time on the next PPS edge. The internal
+        // clock has no PPS to wait for.
+        if config.has_external_reference()
+            && let Some(clock_source) = &config.clock_source
+        {
+            let src = CString::new(clock_source.as_str())?;
+            tracing::info!("uhd: clock and time source: {clock_source}");
+            unsafe {
+                check(
+                    uhd_usrp_set_clock_source(usrp, src.as_ptr(), UHD_MBOARD),
+                    "set_clock_source",
+                )?;
+                check(
+                    uhd_usrp_set_time_source(usrp, src.as_ptr(), UHD_MBOARD),
+                    "set_time_source",
+                )?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                check(
+                    uhd_usrp_set_time_unknown_pps(usrp, now + 1, 0.0),
+                    "set_time_unknown_pps",
+                )?;
+            }
+        }
+
+        let mut actual_fs = 0.0;
+        let mut tune_request = UhdTuneRequest {
+            target_freq: config.get_tuning_freq(sig),
+            rf_freq_policy: UHD_TUNE_REQUEST_POLICY_AUTO,
+            rf_freq: 0.0,
+            dsp_freq_policy: UHD_TUNE_REQUEST_POLICY_AUTO,
+            dsp_freq: 0.0,
+            args: std::ptr::null_mut(),
+        };
+        let mut tune_result = UhdTuneResult::default();
+        let empty = CString::default();
+        unsafe {
+            check(uhd_usrp_set_rx_rate(usrp, fs, UHD_CHANNEL), "set_rx_rate")?;
+            check(
+                uhd_usrp_get_rx_rate(usrp, UHD_CHANNEL, &mut actual_fs),
+                "get_rx_rate",
+            )?;
+            check(
+                uhd_usrp_set_rx_freq(usrp, &mut tune_request, UHD_CHANNEL, &mut tune_result),
+                "set_rx_freq",
+            )?;
+            if let Some(gain) = config.gain {
+                check(
+                    uhd_usrp_set_rx_gain(usrp, gain, UHD_CHANNEL, empty.as_ptr()),
+                    "set_rx_gain",
+                )?;
+            }
+        }
+        if actual_fs != fs {
+            return Err(format!("uhd: requested fs={fs} but device uses {actual_fs}").into());
+        }
+        tracing::info!(
+            "uhd: fs={:.1} KHz rf={:.3} MHz gain={:?}",
+            actual_fs / 1000.0,
+            tune_result.actual_rf_freq / 1e6,
+            config.gain
+        );
+
+        let cpu_format = CString::new("fc32")?;
+        let otw_format = CString::new("sc16")?;
+        let mut channel = UHD_CHANNEL;
+        let mut stream_args = UhdStreamArgs {
+            cpu_format: cpu_format.as_ptr() as *mut c_char,
+            otw_format: otw_format.as_ptr() as *mut c_char,
+            args: empty.as_ptr() as *mut c_char,
+            channel_list: &mut channel,
+            n_channels: 1,
+        };
+        let mut max_num_samps = 0;
+        let cmd = UhdStreamCmd {
+            stream_mode: UHD_STREAM_MODE_START_CONTINUOUS,
+            num_samps: 0,
+            stream_now: true,
+            time_spec_full_secs: 0,
+            time_spec_frac_secs: 0.0,
+        };
+        unsafe {
+            check(
+                uhd_usrp_get_rx_stream(usrp, &mut stream_args, streamer),
+                "get_rx_stream",
+            )?;
+            check(
+                uhd_rx_streamer_max_num_samps(streamer, &mut max_num_samps),
+                "max_num_samps",
+            )?;
+            check(
+                uhd_rx_streamer_issue_stream_cmd(streamer, &cmd),
+                "issue_stream_cmd",
+            )?;
+        }
+        m.buf = vec![[0.0; 2]; max_num_samps];
+
+        Ok(m)
+    }
+}
+
+impl SampleSource for UhdDevice {
+    fn read_block(
+        &mut self,
+        _off_samples: usize,
+        num_samples: usize,
+    ) -> Result<SampleBlock, Box<dyn std::error::Error>> {
+        while self.pending.len() < num_samples {
+            self.recv_packet()?;
+        }
+        let rest = self.pending.split_off(num_samples);
+        let hw_ts_sec = self.pending_ts_sec;
+        self.pending_ts_sec = hw_ts_sec.map(|ts| ts + num_samples as f64 / self.fs);
+
+        Ok(SampleBlock {
+            iq_vec: std::mem::replace(&mut self.pending, rest),
+            hw_ts_sec,
+            num_dropped: std::mem::take(&mut self.num_dropped),
+            time_jump: std::mem::take(&mut self.time_jump),
+        })
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
@@ -1,13 +1,18 @@
-use rustfft::{FftPlanner, num_complex::Complex64};
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
 use std::ops::Mul;
 
+use crate::sample::{Float, IQSample, to_complex64};
+
 const PI: f64 = std::f64::consts::PI;
+const NCO_LANES: usize = 8;
+const NCO_RESYNC_CHUNKS: usize = 64;
 
-pub fn norm_square(v: &[Complex64]) -> f64 {
-    v.iter().map(|&x| x.norm_sqr()).sum::<f64>()
+pub fn norm_square(v: &[IQSample]) -> f64 {
+    v.iter().map(|&x| to_complex64(x).norm_sqr()).sum::<f64>()
 }
 
-pub fn norm(v: &[Complex64]) -> f64 {
+pub fn norm(v: &[IQSample]) -> f64 {
     norm_square(v).sqrt()
 }
 
@@ -28,13 +33,24 @@ pub fn get_average(v: &[f64]) -> f64 {
     v.iter().sum::<f64>() / v.len() as f64
 }
 
-fn normalize_post_fft(data: &mut [Complex64]) {
-    let len = data.len() as f64;
+// offset and slope at t of the least squares line through points
+pub fn fit_line(points: &VecDeque<(f64, f64)>, t: f64) -> (f64, f64) {
+    let n = points.len() as f64;
+    let t_mean = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let y_mean = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let stt: f64 = points.iter().map(|p| (p.0 - t_mean).powi(2)).sum();
+    let sty: f64 = points.iter().map(|p| (p.0 - t_mean) * (p.1 - y_mean)).sum();
+    let slope = if stt > 0.0 { sty / stt } else { 0.0 };
+    (y_mean + slope * (t - t_mean), slope)
+}
+
+fn normalize_post_fft(data: &mut [IQSample]) {
+    let len = data.len() as Float;
     data.iter_mut().for_each(|x| *x /= len);
 }
 
-pub fn correlate_vec(a: &[Complex64], b: &[Complex64]) -> Complex64 {
-    let mut sum = Complex64 { re: 0.0, im: 0.0 };
+pub fn correlate_vec(a: &[IQSample], b: &[IQSample]) -> IQSample {
+    let mut sum = IQSample { re: 0.0, im: 0.0 };
     for i in 0..a.len() {
         sum += a[i].mul(b[i].conj());
     }
@@ -42,47 +58,73 @@ pub fn correlate_vec(a: &[Complex64], b: &[Complex64]) -> Complex64 {
 }
 
 pub fn calc_correlation(
-    fft_planner: &mut FftPlanner<f64>,
-    iq_vec: &[Complex64],
-    prn_code_fft: &[Complex64],
-) -> Vec<Complex64> {
+    fft_planner: &mut FftPlanner<Float>,
+    iq_vec: &[IQSample],
+    prn_code_fft: &[IQSample],
+) -> Vec<IQSample> {
     let num_samples = iq_vec.len();
-    assert_eq!(iq_vec.len(), prn_code_fft.len());
     let fft_fw = fft_planner.plan_fft_forward(num_samples);
-
-    let mut iq_samples_fft = iq_vec.to_owned();
-
-    fft_fw.process(&mut iq_samples_fft);
-
-    let mut v_res: Vec<_> = (0..num_samples)
-        .map(|i| iq_samples_fft[i] * prn_code_fft[i].conj())
-        .collect();
-
     let fft_bw = fft_planner.plan_fft_inverse(num_samples);
-    fft_bw.process(&mut v_res);
-    normalize_post_fft(&mut v_res);
-    v_res
-}
 
-fn doppler_shifted_carrier(doppler_hz: f64, phi: f64, fs: f64, len: usize) -> Vec<Complex64> {
-    let imaginary = 2.0 * PI * doppler_hz;
-    let phi_off = 2.0 * PI * phi;
-
-    let carrier: Vec<Complex64> = (0..len)
-        .map(|x| x as f64)
-        .map(|y| Complex64::from_polar(1.0, -imaginary * (y / fs) - phi_off))
-        .collect();
-
-    carrier
+    let mut v_res = iq_vec.to_owned();
+    calc_correlation_in_place(
+        fft_fw.as_ref(),
+        fft_bw.as_ref(),
+        &mut v_res,
+        prn_code_fft,
+        &mut vec![],
+    );
+    v_res
 }
 
-pub fn doppler_shift(iq_vec: &mut [Complex64], doppler_hz: f64, phi: f64, fs: f64) {
-    let carrier = doppler_shifted_carrier(doppler_hz, phi, fs, iq_vec.len());
-
-    assert_eq!(iq_vec.len(), carrier.len());
-
-    for i in 0..iq_vec.len() {
-        iq_vec[i] *= carrier[i];
+// Same as calc_correlation() with plans made beforehand and the result
+// written over iq_vec: no allocation once scratch is large enough.
+pub fn calc_correlation_in_place(
+    fft_fw: &dyn Fft<Float>,
+    fft_bw: &dyn Fft<Float>,
+    iq_vec: &mut [IQSample],
+    prn_code_fft: &[IQSample],
+    scratch: &mut Vec<IQSample>,
+) {
+    assert_eq!(iq_vec.len(), prn_code_fft.len());
+    assert_eq!(iq_vec.len(), fft_fw.len());
+    let scratch_len = usize::max(
+        fft_fw.get_inplace_scratch_len(),
+        fft_bw.get_inplace_scratch_len(),
+    );
+    scratch.resize(scratch_len, IQSample::default());
+
+    fft_fw.process_with_scratch(iq_vec, scratch);
+    for (x, c) in iq_vec.iter_mut().zip(prn_code_fft.iter()) {
+        *x *= c.conj();
+    }
+    fft_bw.process_with_scratch(iq_vec, scratch);
+    normalize_post_fft(iq_vec);
+}
+
+// Mixes the samples with a carrier at -doppler_hz. The carrier is a bank of
+// NCO_LANES phase rotators advanced by a fixed step per chunk, which the
+// compiler can vectorize; the phases are re-evaluated every
+// NCO_RESYNC_CHUNKS chunks so that the rounding errors don't build up.
+pub fn doppler_shift(iq_vec: &mut [IQSample], doppler_hz: f64, phi: f64, fs: f64) {
+    let w = -2.0 * PI * doppler_hz / fs;
+    let phi_off = -2.0 * PI * phi;
+    let carrier_at = |i: usize| {
+        IQSample::from_polar(1.0, (w * i as f64 + phi_off).rem_euclid(2.0 * PI) as Float)
+    };
+    let step = IQSample::from_polar(1.0, (w * NCO_LANES as f64).rem_euclid(2.0 * PI) as Float);
+
+    let mut nco = [IQSample::new(1.0, 0.0); NCO_LANES];
+    for (n, chunk) in iq_vec.chunks_mut(NCO_LANES).enumerate() {
+        if n.is_multiple_of(NCO_RESYNC_CHUNKS) {
+            for (k, c) in nco.iter_mut().enumerate() {
+                *c = carrier_at(n * NCO_LANES + k);
+            }
+        }
+        for (x, c) in chunk.iter_mut().zip(nco.iter_mut()) {
+            *x *= *c;
+            *c *= step;
+        }
     }
 }
 
@@ -96,6 +138,9 @@ pub fn getbitu(buf: &[u8], pos: usize, len: usize) -> u32 {
 }
 
 pub fn getbits(buf: &[u8], pos: usize, len: usize) -> i32 {
+    if len == 0 {
+        return 0;
+    }
     let bits = getbitu(buf, pos, len);
 
     let sign = (1 << (len - 1)) & bits;
@@ -108,13 +153,13 @@ pub fn getbitu2(buf: &[u8], p1: usize, l1: usize, p2: usize, l2: usize) -> u32 {
     assert!(l1 + l2 <= 32);
     let hi = getbitu(buf, p1, l1);
     let lo = getbitu(buf, p2, l2);
-    (hi << l2) + lo
+    hi.checked_shl(l2 as u32).unwrap_or(0) + lo
 }
 
 pub fn getbits2(buf: &[u8], p1: usize, l1: usize, p2: usize, l2: usize) -> i32 {
     assert!(l1 + l2 <= 32);
-    if getbitu(buf, p1, 1) != 0 {
-        ((getbits(buf, p1, l1) << l2) + getbitu(buf, p2, l2) as i32) as i32
+    if l1 > 0 && getbitu(buf, p1, 1) != 0 {
+        getbits(buf, p1, l1).checked_shl(l2 as u32).unwrap_or(0) + getbitu(buf, p2, l2) as i32
     } else {
         getbitu2(buf, p1, l1, p2, l2) as i32
     }
@@ -158,11 +203,19 @@ pub fn bits_equal(bits0: &[u8], bits1: &[u8]) -> bool {
     bits0 == bits1
 }
 
+// the reverse of getbitu2(): the upper bits of data at p1, the l2 lower ones
+// at p2. Signed values are written as their two's complement.
+pub fn setbitu2(buf: &mut [u8], p1: usize, l1: usize, p2: usize, l2: usize, data: u32) {
+    assert!(l1 + l2 <= 32);
+    setbitu(buf, p1, l1, data.checked_shr(l2 as u32).unwrap_or(0));
+    setbitu(buf, p2, l2, data & ((1u64 << l2) - 1) as u32);
+}
+
 pub fn setbitu(buf: &mut [u8], pos: usize, len: usize, data: u32) {
-    let mut mask = 1u32 << (len - 1);
-    if len > 32 {
+    if len == 0 || len > 32 {
         return;
     }
+    let mut mask = 1u32 << (len - 1);
     for i in pos..pos + len {
         let bit = 1u8 << (7 - i % 8);
         if data & mask != 0 {
@@ -0,0 +1,70 @@
+use std::sync::{Arc, Mutex};
+
+use crate::api::{GnssConfig, GnssFix, GnssReceiver, GnssSource};
+use crate::recording::IQ_FILE_TYPES;
+
+// Entry points of the browser demo (assets/wasm-demo.html), a C ABI the page
+// calls without generated bindings: it copies an IQ file into the memory of
+// the module at gnss_alloc(len), gnss_process() runs a receiver over it and
+// leaves the fixes as json, or the error, at gnss_result().
+
+static INPUT: Mutex<Vec<u8>> = Mutex::new(vec![]);
+static RESULT: Mutex<String> = Mutex::new(String::new());
+
+// where the page writes the len bytes of the file
+#[unsafe(no_mangle)]
+pub extern "C" fn gnss_alloc(len: usize) -> *mut u8 {
+    let mut input = INPUT.lock().unwrap();
+    *input = vec![0; len];
+    input.as_mut_ptr()
+}
+
+// file_type indexes IQ_FILE_TYPES. 0 once done, -1 on error.
+#[unsafe(no_mangle)]
+pub extern "C" fn gnss_process(fs: f64, file_type: usize) -> i32 {
+    let data: Arc<[u8]> = std::mem::take(&mut *INPUT.lock().unwrap()).into();
+    let mut result = RESULT.lock().unwrap();
+    match process(data, fs, file_type) {
+        Ok(fixes) => {
+            *result = serde_json::to_string(&fixes).unwrap_or_default();
+            0
+        }
+        Err(e) => {
+            *result = e.to_string();
+            -1
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gnss_result() -> *const u8 {
+    RESULT.lock().unwrap().as_ptr()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gnss_result_len() -> usize {
+    RESULT.lock().unwrap().len()
+}
+
+fn process(
+    data: Arc<[u8]>,
+    fs: f64,
+    file_type: usize,
+) -> Result<Vec<GnssFix>, Box<dyn std::error::Error>> {
+    let iq_file_type = IQ_FILE_TYPES.get(file_type).ok_or("unknown file type")?;
+    let config = GnssConfig {
+        source: GnssSource::Memory(data),
+        iq_file_type: iq_file_type.clone(),
+        fs,
+        ..Default::default()
+    };
+    let fixes = Arc::new(Mutex::new(vec![]));
+    let fixes_cb = fixes.clone();
+    let mut receiver = GnssReceiver::new(config);
+    receiver.on_fix(move |fix| fixes_cb.lock().unwrap().push(*fix));
+    // no threads: the whole file is processed before start() returns
+    receiver.start()?;
+    receiver.wait();
+    let fixes = fixes.lock().unwrap().clone();
+    Ok(fixes)
+}
@@ -0,0 +1,212 @@
+use futures_util::{SinkExt, StreamExt};
+use gnss_rs::sv::SV;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::channel::State;
+use crate::runtime::get_net_runtime;
+use crate::state::GnssState;
+use crate::ttff::Milestone;
+
+const WEB_PERIOD_MSEC: u64 = 500; // between two updates of the dashboards
+const WEB_PLOT_LEN: usize = 1000; // latest points of each history sent
+const WEB_SCATTER_LEN: usize = 500;
+const WEB_MAX_REQUEST: usize = 4096;
+const WEB_ACCEPT_BACKOFF_MSEC: u64 = 10; // after a failed accept, doubled up to the max
+const WEB_ACCEPT_BACKOFF_MAX_MSEC: u64 = 1000;
+const INDEX_HTML: &str = include_str!("web.html");
+
+type ClientResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+fn get_tail<T: Clone>(v: &[T], n: usize) -> Vec<T> {
+    v[v.len().saturating_sub(n)..].to_vec()
+}
+
+// what the dashboard shows, as json
+fn get_snapshot(pub_state: &GnssState) -> String {
+    let mut channels: Vec<_> = pub_state
+        .channels
+        .iter()
+        .filter(|(_, channel)| channel.state == State::Tracking)
+        .map(|(key @ (sv, _sig), channel)| {
+            serde_json::json!({
+                "sv": sv.to_string(),
+                "label": pub_state.get_channel_label(key),
+                "cn0": channel.cn0,
+                "doppler_hz": channel.doppler_hz,
+                "code_idx": channel.code_idx,
+                "trk_sec": channel.trk_sec,
+                "has_eph": channel.has_eph,
+                "subframes": channel.nav.num_subframes,
+                "cmc_rms_m": channel.quality.cmc_rms_m,
+                "multipath": channel.quality.multipath,
+            })
+        })
+        .collect();
+    channels.sort_by(|a, b| a["label"].as_str().cmp(&b["label"].as_str()));
+
+    let fix = (pub_state.ttff.get(Milestone::Fix).is_some()).then(|| {
+        serde_json::json!({
+            "lat": pub_state.latitude,
+            "lon": pub_state.longitude,
+            "height": pub_state.height,
+            "tow_gpst": pub_state.tow_gpst.to_string(),
+            "ttff_sec": pub_state.ttff.get(Milestone::Fix),
+        })
+    });
+    let plot = pub_state.plot.as_ref().map(|plot| {
+        let scatter: Vec<_> = get_tail(&plot.corr_p, WEB_SCATTER_LEN)
+            .iter()
+            .map(|c| [c.re, c.im])
+            .collect();
+        serde_json::json!({
+            "sv": plot.sv.to_string(),
+            "doppler_hz": get_tail(&plot.doppler_hz, WEB_PLOT_LEN),
+            "phi_error": get_tail(&plot.phi_error, WEB_PLOT_LEN),
+            "code_phase_offset": get_tail(&plot.code_phase_offset, WEB_PLOT_LEN),
+            "corr_p": scatter,
+        })
+    });
+    serde_json::json!({
+        "start_mode": pub_state.start_mode,
+        "channels": channels,
+        "fix": fix,
+        "plot": plot,
+        "progress": pub_state.progress,
+        "device_lost": pub_state.device_lost,
+        "lagging": pub_state.lagging,
+        "interference": pub_state.interference,
+        "timing": pub_state.timing,
+    })
+    .to_string()
+}
+
+// Serves the dashboard page over http on bind:port, and pushes the state of
+// the receiver to the pages over a websocket on the same port: for running
+// headless, e.g. on a Raspberry Pi, and monitoring from a browser.
+pub fn start_web_dashboard(
+    bind: &str,
+    port: u16,
+    pub_state: Arc<Mutex<GnssState>>,
+) -> std::io::Result<()> {
+    let runtime = get_net_runtime();
+    let listener = runtime.block_on(TcpListener::bind((bind, port)))?;
+    let (sender, _) = broadcast::channel::<Arc<String>>(1);
+    tracing::info!("web: dashboard on http://{}/", listener.local_addr()?);
+
+    let sender_clone = sender.clone();
+    let state_clone = pub_state.clone();
+    runtime.spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(WEB_PERIOD_MSEC));
+        loop {
+            interval.tick().await;
+            if sender_clone.receiver_count() == 0 {
+                continue;
+            }
+            let snapshot = get_snapshot(&state_clone.lock().unwrap());
+            let _ = sender_clone.send(Arc::new(snapshot));
+        }
+    });
+
+    runtime.spawn(async move {
+        // e.g. out of file descriptors: retried, without spinning
+        let mut backoff_msec = 0;
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(client) => {
+                    backoff_msec = 0;
+                    client
+                }
+                Err(e) => {
+                    if backoff_msec == 0 {
+                        tracing::warn!("web: accept failed: {e}");
+                    }
+                    backoff_msec = (backoff_msec * 2)
+                        .clamp(WEB_ACCEPT_BACKOFF_MSEC, WEB_ACCEPT_BACKOFF_MAX_MSEC);
+                    tokio::time::sleep(Duration::from_millis(backoff_msec)).await;
+                    continue;
+                }
+            };
+            let receiver = sender.subscribe();
+            let pub_state = pub_state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_client(stream, receiver, pub_state).await {
+                    tracing::debug!("web: client {addr}: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn serve_client(
+    mut stream: TcpStream,
+    receiver: broadcast::Receiver<Arc<String>>,
+    pub_state: Arc<Mutex<GnssState>>,
+) -> ClientResult {
+    // left in the stream for the websocket handshake
+    let mut buf = [0u8; WEB_MAX_REQUEST];
+    let len = stream.peek(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+    if request.contains("upgrade: websocket") {
+        return serve_websocket(stream, receiver, pub_state).await;
+    }
+
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let response = match path {
+        "/" | "/index.html" => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{INDEX_HTML}",
+            INDEX_HTML.len()
+        ),
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+async fn serve_websocket(
+    stream: TcpStream,
+    mut receiver: broadcast::Receiver<Arc<String>>,
+    pub_state: Arc<Mutex<GnssState>>,
+) -> ClientResult {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut requests) = ws.split();
+
+    // the page picks the satellite whose plots it shows
+    let reader = tokio::spawn(async move {
+        while let Some(Ok(msg)) = requests.next().await {
+            if let Message::Text(text) = msg {
+                let plot_sv = text.trim().parse::<SV>().ok();
+                let mut pub_state = pub_state.lock().unwrap();
+                if plot_sv.is_some() && plot_sv != pub_state.plot_sv {
+                    pub_state.plot_sv = plot_sv;
+                    pub_state.plot = None;
+                }
+            }
+        }
+    });
+
+    let res = loop {
+        match receiver.recv().await {
+            Ok(snapshot) => {
+                if reader.is_finished() {
+                    break Ok(());
+                }
+                if let Err(e) = sink.send(Message::text(snapshot.as_str())).await {
+                    break Err(e.into());
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break Ok(()),
+        }
+    };
+    reader.abort();
+    res
+}
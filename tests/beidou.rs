@@ -0,0 +1,157 @@
+// BeiDou B1I: the codes and their NH20 overlay, the BCH words of the D1
+// subframes and the ephemeris they carry, the acquisition of simulated
+// satellites and the sync of a tracked one on its secondary code.
+
+use std::sync::{Arc, Mutex};
+
+use gnss_rcv::code::{B1I_CODE_LEN, Code, CodeTables, NH20_CODE};
+use gnss_rcv::ephemeris::Ephemeris;
+use gnss_rcv::navigation_bds::{bds_d1_decode_bits, bds_d1_encode_bits};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rcv::state::GnssState;
+use gnss_rcv::util::getbitu;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+
+mod common;
+use common::{assert_acquired, get_corr, sim_config};
+
+const FS: f64 = 4092000.0;
+
+#[test]
+fn beidou_codes() {
+    let codes = CodeTables::new();
+    let code6 = codes.gen_code("B1I", 6).unwrap();
+    let code7 = codes.gen_code("B1I", 7).unwrap();
+    assert_eq!(code6.len(), B1I_CODE_LEN);
+    assert_ne!(code6, code7);
+    // Gold codes, truncated by a chip: low sidelobes and cross-correlation
+    for shift in 0..B1I_CODE_LEN {
+        if shift != 0 {
+            let corr = get_corr(&code6, &code6, shift);
+            assert!(corr.abs() < 80, "shift {shift}: {corr}");
+        }
+        let corr = get_corr(&code6, &code7, shift);
+        assert!(corr.abs() < 80, "shift {shift}: {corr}");
+    }
+    assert!(codes.gen_code("B1I", 38).is_none());
+    assert_eq!(Code::get_code_freq("B1I"), 1561.098e6);
+    let svs = get_sat_list("B1I", "").unwrap();
+    assert_eq!(svs.len(), 37);
+    assert!(
+        svs.iter()
+            .all(|sv| sv.constellation == Constellation::BeiDou)
+    );
+    // the GEO satellites have no NH code
+    assert!(Code::get_secondary_code("B1I", 5).is_empty());
+    assert_eq!(Code::get_secondary_code("B1I", 6), NH20_CODE);
+    assert!(Code::get_secondary_code("L1CA", 6).is_empty());
+}
+
+// The subframes 1 to 3 of an ephemeris, a bit in error in each of their BCH
+// words, decoded back.
+#[test]
+fn beidou_d1_subframes() {
+    let sv = SV::new(Constellation::BeiDou, 11);
+    let mut eph = Ephemeris::new(sv);
+    eph.week = 2300;
+    eph.toe = 5400 * 8 + 14;
+    eph.toc = eph.toe;
+    eph.iode = 17;
+    eph.iodc = 17;
+    eph.sva = 2;
+    eph.tgd = 4.2e-9;
+    eph.f0 = -3.1e-4;
+    eph.f1 = 2.5e-11;
+    eph.m0 = 1.234;
+    eph.ecc = 0.0021;
+    eph.a = 5282.6f64.powi(2);
+    eph.deln = 4.1e-9;
+    eph.cuc = -1.2e-6;
+    eph.cus = 8.5e-6;
+    eph.crc = 212.5;
+    eph.crs = -18.75;
+    eph.i0 = 0.96;
+    eph.omg0 = -2.1;
+    eph.omg = 0.7;
+    eph.omg_dot = -6.8e-9;
+    eph.i_dot = 3.2e-10;
+
+    let mut decoded = Ephemeris::new(sv);
+    for id in 1..=3 {
+        let sow = 6000 + 6 * (id - 1);
+        let mut bits = bds_d1_encode_bits(&eph.nav_encode_d1_subframe(id, sow));
+        assert_eq!(bits.len(), 300);
+        bits[20] ^= 1;
+        for i in 1..10 {
+            bits[30 * i + 3] ^= 1;
+            bits[30 * i + 8] ^= 1;
+        }
+        let mut nav_data = vec![0; 300];
+        assert_eq!(bds_d1_decode_bits(&bits, &mut nav_data), Some(19));
+        assert_eq!(getbitu(&nav_data, 0, 11), 0b11100010010);
+        assert_eq!(decoded.nav_decode_d1_subframe(&nav_data), id);
+    }
+    // the next subframe, in gps time
+    assert_eq!(decoded.tow, 6012 + 6 + 14);
+    assert_eq!(decoded.week, eph.week);
+    assert_eq!(decoded.toe, eph.toe);
+    assert_eq!(decoded.toc, eph.toc);
+    assert_eq!(decoded.iode, eph.iode);
+    assert_eq!(decoded.iodc, eph.iodc);
+    assert_eq!(decoded.sva, eph.sva);
+    assert!((decoded.tgd - eph.tgd).abs() <= 0.1e-9);
+    assert!((decoded.f0 - eph.f0).abs() <= 1e-9);
+    assert!((decoded.f1 - eph.f1).abs() <= 1e-14);
+    assert!((decoded.m0 - eph.m0).abs() <= 1e-8);
+    assert!((decoded.ecc - eph.ecc).abs() <= 1e-9);
+    assert!((decoded.a.sqrt() - eph.a.sqrt()).abs() <= 1e-5);
+    assert!((decoded.deln - eph.deln).abs() <= 1e-12);
+    assert!((decoded.cuc - eph.cuc).abs() <= 1e-9);
+    assert!((decoded.cus - eph.cus).abs() <= 1e-9);
+    assert!((decoded.crc - eph.crc).abs() <= 0.1);
+    assert!((decoded.crs - eph.crs).abs() <= 0.1);
+    assert!((decoded.i0 - eph.i0).abs() <= 1e-8);
+    assert!((decoded.omg0 - eph.omg0).abs() <= 1e-8);
+    assert!((decoded.omg - eph.omg).abs() <= 1e-8);
+    assert!((decoded.omg_dot - eph.omg_dot).abs() <= 1e-12);
+    assert!((decoded.i_dot - eph.i_dot).abs() <= 1e-12);
+
+    assert_eq!(bds_d1_decode_bits(&[0; 299], &mut [0; 300]), None);
+}
+
+#[test]
+fn beidou_acquisition() {
+    let config = sim_config(Constellation::BeiDou, &[6, 7]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("B1I")
+        .sats("6,7,8")
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(40).unwrap();
+    assert_acquired(&config, &acquired, &[8]);
+}
+
+#[test]
+fn beidou_tracking() {
+    let config = sim_config(Constellation::BeiDou, &[9]);
+    let state = Arc::new(Mutex::new(GnssState::new()));
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("B1I")
+        .sats("9")
+        .state(state.clone())
+        .build()
+        .unwrap();
+    receiver.set_plot_period(None);
+    let tracked = receiver.run_acquire(2000);
+    assert_eq!(tracked.len(), 1, "not tracked");
+    assert!(tracked[0].cn0 > 40.0, "cn0={:.1}", tracked[0].cn0);
+    let sv = config.svs[0].sv;
+    let pub_state = state.lock().unwrap();
+    let channel = pub_state.get_sv_channel(sv).unwrap();
+    assert!(channel.nav.secondary_sync, "no NH20 sync");
+}
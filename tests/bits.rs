@@ -0,0 +1,66 @@
+// Properties of the bit field accessors the navigation decoders are built on.
+
+use gnss_rcv::util::{getbits, getbits2, getbitu, getbitu2, setbitu, setbitu2};
+use proptest::prelude::*;
+
+const BUF_LEN: usize = 38; // a subframe: 300 bits
+
+fn get_mask(len: usize) -> u32 {
+    ((1u64 << len) - 1) as u32
+}
+
+proptest! {
+    #[test]
+    fn setbitu_getbitu(pos in 0..268usize, len in 1..=32usize, v: u32) {
+        let mut buf = vec![0; BUF_LEN];
+        setbitu(&mut buf, pos, len, v);
+        prop_assert_eq!(getbitu(&buf, pos, len), v & get_mask(len));
+    }
+
+    // only the bits of the field change
+    #[test]
+    fn setbitu_neighbours(buf in prop::collection::vec(any::<u8>(), BUF_LEN), pos in 0..268usize, len in 1..=32usize, v: u32) {
+        let mut out = buf.clone();
+        setbitu(&mut out, pos, len, v);
+        for i in (0..BUF_LEN * 8).filter(|i| !(pos..pos + len).contains(i)) {
+            prop_assert_eq!(getbitu(&out, i, 1), getbitu(&buf, i, 1), "bit {}", i);
+        }
+    }
+
+    #[test]
+    fn getbits_sign(pos in 0..268usize, len in 1..=32usize, v: i32) {
+        let v = if len == 32 { v } else { v >> (32 - len) };
+        let mut buf = vec![0; BUF_LEN];
+        setbitu(&mut buf, pos, len, v as u32);
+        prop_assert_eq!(getbits(&buf, pos, len), v);
+    }
+
+    #[test]
+    fn setbitu2_getbitu2(p1 in 0..120usize, l1 in 1..=16usize, p2 in 150..268usize, l2 in 1..=16usize, v: u32) {
+        let v = v & get_mask(l1 + l2);
+        let mut buf = vec![0; BUF_LEN];
+        setbitu2(&mut buf, p1, l1, p2, l2, v);
+        prop_assert_eq!(getbitu2(&buf, p1, l1, p2, l2), v);
+        prop_assert_eq!(getbitu(&buf, p1, l1), v >> l2);
+    }
+
+    #[test]
+    fn getbits2_sign(p1 in 0..120usize, l1 in 1..=16usize, p2 in 150..268usize, l2 in 1..=16usize, v: i32) {
+        let v = if l1 + l2 == 32 { v } else { v >> (32 - l1 - l2) };
+        let mut buf = vec![0; BUF_LEN];
+        setbitu2(&mut buf, p1, l1, p2, l2, v as u32 & get_mask(l1 + l2));
+        prop_assert_eq!(getbits2(&buf, p1, l1, p2, l2), v);
+    }
+
+    // the fields of the edges don't panic
+    #[test]
+    fn empty_fields(buf in prop::collection::vec(any::<u8>(), BUF_LEN), pos in 0..300usize) {
+        let mut out = buf.clone();
+        setbitu(&mut out, pos, 0, u32::MAX);
+        prop_assert_eq!(&out, &buf);
+        prop_assert_eq!(getbitu(&buf, pos, 0), 0);
+        prop_assert_eq!(getbits(&buf, pos, 0), 0);
+        prop_assert_eq!(getbitu2(&buf, 0, 0, 0, 32), getbitu(&buf, 0, 32));
+        prop_assert_eq!(getbits2(&buf, 0, 0, 0, 32), getbitu(&buf, 0, 32) as i32);
+    }
+}
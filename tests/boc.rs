@@ -0,0 +1,112 @@
+// The bump-jump of BOC(1,1) signals: the side peaks of the correlation of a
+// sampled E1 code, here a random one, and the jumps called for by the very
+// early and very late correlators around them.
+
+use gnss_rcv::boc::{BumpJump, Jump};
+use gnss_rcv::code::{Code, CodeTables, E1_CODE_LEN};
+use rustfft::num_complex::Complex64;
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+const FS: f64 = 4092000.0;
+const HALF_CHIP_SP: isize = 2; // samples per subcarrier half-chip at FS
+const PRN: u8 = 7;
+
+static CODE: LazyLock<Vec<i8>> = LazyLock::new(load_code);
+
+fn load_code() -> Vec<i8> {
+    let mut text = format!("E1B {PRN} ");
+    let mut x: u64 = 0xD1B54A32D192ED03;
+    for _ in 0..E1_CODE_LEN / 4 {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        write!(text, "{:X}", x & 0xF).unwrap();
+    }
+    let path = std::env::temp_dir().join(format!("gnss-rcv-boc-{}.txt", std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    let mut codes = CodeTables::new();
+    assert_eq!(codes.load_memory_codes(&path).unwrap(), 1);
+    let _ = std::fs::remove_file(&path);
+    codes.gen_sampled_code("E1B", PRN, FS).unwrap()
+}
+
+// normalized correlation with the code advanced by shift samples
+fn get_corr(code: &[i8], shift: isize) -> f64 {
+    let n = code.len() as isize;
+    let sum: i64 = (0..n)
+        .map(|j| (code[j as usize] * code[(j + shift).rem_euclid(n) as usize]) as i64)
+        .sum();
+    sum as f64 / n as f64
+}
+
+// windows of a channel whose prompt is d samples off the main peak: a
+// signal matching the code advanced by d, correlated as the channels do
+fn run(bump_jump: &mut BumpJump, code: &[i8], d: isize, windows: usize) -> Option<Jump> {
+    let c = |shift| Complex64::new(get_corr(code, shift), 0.0);
+    let (c_p, c_ve, c_vl) = (c(d), c(d - HALF_CHIP_SP), c(d + HALF_CHIP_SP));
+    for _ in 0..windows {
+        for _ in 0..5 {
+            bump_jump.add_correlations(c_p, c_ve, c_vl);
+        }
+        if let Some(jump) = bump_jump.update() {
+            return Some(jump);
+        }
+    }
+    None
+}
+
+#[test]
+fn boc_side_peaks() {
+    assert!(Code::is_boc("E1") && Code::is_boc("E1C"));
+    assert!(!Code::is_boc("L1CA") && !Code::is_boc("L2C") && !Code::is_boc("B1I"));
+    let code = &*CODE;
+    assert_eq!(code.len(), Code::get_code_sp("E1B", FS));
+    assert_eq!(get_corr(code, 0), 1.0);
+    // BOC(1,1): 1 - 3|tau| within a quarter chip, side peaks at -1/2 half a
+    // chip away, nothing left a chip away
+    for (shift, expected) in [(1, 0.25), (2, -0.5), (3, -0.25), (4, 0.0)] {
+        for shift in [shift, -shift] {
+            let corr = get_corr(code, shift);
+            assert!(
+                (corr - expected).abs() < 0.05,
+                "shift {shift}: {corr:.3} instead of {expected}"
+            );
+        }
+    }
+    // the early and late correlators, a quarter chip away, balance on a side
+    // peak as they do on the main one
+    let e = get_corr(code, HALF_CHIP_SP - 1).abs();
+    let l = get_corr(code, HALF_CHIP_SP + 1).abs();
+    assert!((e - l).abs() < 0.05, "early {e:.3} late {l:.3}");
+}
+
+#[test]
+fn boc_bump_jump() {
+    let code = &*CODE;
+    // on the main peak: the very early and late correlators on the side peaks
+    let mut bump_jump = BumpJump::new();
+    assert_eq!(run(&mut bump_jump, code, 0, 100), None);
+    // on a side peak: the main one is a jump away, in 5 windows
+    assert_eq!(run(&mut bump_jump, code, HALF_CHIP_SP, 4), None);
+    assert_eq!(
+        run(&mut bump_jump, code, HALF_CHIP_SP, 1),
+        Some(Jump::Early)
+    );
+    let mut bump_jump = BumpJump::new();
+    assert_eq!(
+        run(&mut bump_jump, code, -HALF_CHIP_SP, 5),
+        Some(Jump::Late)
+    );
+    // a window back on the main peak doesn't start the count over
+    let mut bump_jump = BumpJump::new();
+    assert_eq!(run(&mut bump_jump, code, HALF_CHIP_SP, 3), None);
+    assert_eq!(run(&mut bump_jump, code, 0, 1), None);
+    assert_eq!(run(&mut bump_jump, code, HALF_CHIP_SP, 2), None);
+    assert_eq!(
+        run(&mut bump_jump, code, HALF_CHIP_SP, 1),
+        Some(Jump::Early)
+    );
+    // and the count starts over after a jump
+    assert_eq!(run(&mut bump_jump, code, HALF_CHIP_SP, 4), None);
+}
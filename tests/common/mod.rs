@@ -0,0 +1,85 @@
+// The helpers shared by the tests of the signals: the simulated satellites,
+// the correlation of two codes and the checks of an acquisition scan. Each
+// test uses a part of them.
+#![allow(dead_code)]
+
+use gnss_rcv::code::{CodeTables, E1_CODE_LEN};
+use gnss_rcv::receiver::AcquiredSv;
+use gnss_rcv::sim::SimConfig;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use std::fmt::Write;
+
+pub const DOPPLER_TOLERANCE_HZ: f64 = 320.0; // one acquisition bin of 1 msec
+pub const SIM_CN0: f64 = 48.0;
+
+pub fn sim_config(constellation: Constellation, prns: &[u8]) -> SimConfig {
+    let svs: Vec<_> = (prns.iter())
+        .map(|&prn| SV::new(constellation, prn))
+        .collect();
+    SimConfig::from_sats(&svs, SIM_CN0)
+}
+
+// random E1B codes for prns drawn from seed, written as the ICD tabulates
+// them
+pub fn load_e1_codes(seed: u64, prns: &[u8]) -> CodeTables {
+    let mut text = String::from("# random codes, not the ICD ones\n");
+    let mut x = seed;
+    for prn in prns {
+        write!(text, "E1B {prn} ").unwrap();
+        for _ in 0..E1_CODE_LEN / 4 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            write!(text, "{:X}", x & 0xF).unwrap();
+        }
+        text.push('\n');
+    }
+    let name = format!("gnss-rcv-e1-{seed:x}-{}.txt", std::process::id());
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, text).unwrap();
+    let mut codes = CodeTables::new();
+    assert_eq!(codes.load_memory_codes(&path).unwrap(), prns.len());
+    let _ = std::fs::remove_file(&path);
+    codes
+}
+
+// correlation of a with b advanced by shift chips
+pub fn get_corr(a: &[i8], b: &[i8], shift: usize) -> i32 {
+    (0..a.len())
+        .map(|i| (a[i] * b[(i + shift) % b.len()]) as i32)
+        .sum()
+}
+
+// the simulated satellites found within an acquisition bin of 1 msec, and
+// none of the absent prns
+pub fn assert_acquired(config: &SimConfig, acquired: &[AcquiredSv], absent: &[u8]) {
+    assert_acquired_within(config, acquired, absent, DOPPLER_TOLERANCE_HZ);
+}
+
+pub fn assert_acquired_within(
+    config: &SimConfig,
+    acquired: &[AcquiredSv],
+    absent: &[u8],
+    tolerance_hz: f64,
+) {
+    for sim_sv in &config.svs {
+        let acq = (acquired.iter())
+            .find(|acq| acq.sv == sim_sv.sv)
+            .unwrap_or_else(|| panic!("{}: not acquired", sim_sv.sv));
+        assert!(
+            (acq.doppler_hz - sim_sv.doppler_hz).abs() <= tolerance_hz,
+            "{}: doppler {:.0} Hz instead of {:.0} Hz",
+            sim_sv.sv,
+            acq.doppler_hz,
+            sim_sv.doppler_hz
+        );
+    }
+    for acq in acquired {
+        assert!(
+            !absent.contains(&acq.sv.prn),
+            "{}: acquired, not simulated",
+            acq.sv
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// Galileo E1 on simulated signals: memory codes loaded from a file, here
+// random ones, acquired over their 4 msec code period and tracked with the
+// receiver periods gathered into whole codes.
+
+use gnss_rcv::code::{Code, CodeTables};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rs::constellation::Constellation;
+use std::sync::LazyLock;
+
+mod common;
+use common::{assert_acquired_within, load_e1_codes, sim_config};
+
+const FS: f64 = 4092000.0;
+const DOPPLER_TOLERANCE_HZ: f64 = 80.0; // one acquisition bin of 4 msec
+
+static CODES: LazyLock<CodeTables> =
+    LazyLock::new(|| load_e1_codes(0x9E3779B97F4A7C15, &[1, 2, 3, 4]));
+
+#[test]
+fn galileo_codes() {
+    let code = CODES.gen_code("E1", 1).unwrap();
+    assert_eq!(code.len(), Code::get_code_len("E1"));
+    // BOC(1,1): each chip followed by its opposite
+    assert!(code.chunks(2).all(|c| c[0] == -c[1]));
+    assert_eq!(Code::get_code_sp("E1", FS), 4 * 4092);
+    assert!(CODES.gen_code("E1", 5).is_none());
+    let svs = get_sat_list("E1", "").unwrap();
+    assert_eq!(svs.len(), 36);
+    assert!(
+        svs.iter()
+            .all(|sv| sv.constellation == Constellation::Galileo)
+    );
+    assert!(get_sat_list("E1", "51").is_err());
+}
+
+#[test]
+fn galileo_acquisition() {
+    let config = sim_config(Constellation::Galileo, &[1, 2]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("E1")
+        .sats("1,2,4")
+        .code_tables(CODES.clone())
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(40).unwrap();
+    assert_acquired_within(&config, &acquired, &[4], DOPPLER_TOLERANCE_HZ);
+}
+
+// still tracked after a second, the gathered periods aligned on the codes
+#[test]
+fn galileo_tracking() {
+    let config = sim_config(Constellation::Galileo, &[3]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("E1")
+        .sats("3")
+        .code_tables(CODES.clone())
+        .build()
+        .unwrap();
+    receiver.set_plot_period(None);
+    let tracked = receiver.run_acquire(1000);
+    assert_eq!(tracked.len(), 1, "not tracked");
+    let sim_sv = &config.svs[0];
+    assert!(
+        (tracked[0].doppler_hz - sim_sv.doppler_hz).abs() <= 10.0,
+        "doppler {:.1} Hz instead of {:.1} Hz",
+        tracked[0].doppler_hz,
+        sim_sv.doppler_hz
+    );
+    assert!(tracked[0].cn0 > 40.0, "cn0={:.1}", tracked[0].cn0);
+}
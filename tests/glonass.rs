@@ -0,0 +1,187 @@
+// GLONASS L1OF: the code shared by all the satellites, their frequency
+// channels, the strings of the navigation message, the orbit integrated from
+// a state vector and the acquisition of simulated satellites, each one on its
+// own carrier.
+
+use gnss_rcv::code::{CodeTables, GLO_FREQ_STEP_HZ, L1OF_CODE_LEN};
+use gnss_rcv::ephemeris::Ephemeris;
+use gnss_rcv::navigation_glo::{glo_string_check, glo_string_from_symbols};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rcv::solver::get_sv_position_ecef;
+use gnss_rcv::util::{getbitu, setbitu};
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+
+mod common;
+use common::{assert_acquired, sim_config};
+
+const FS: f64 = 10000000.0; // the frequency channels span 7.3 MHz
+const STRING_BITS: usize = 85;
+
+#[test]
+fn glonass_codes() {
+    let codes = CodeTables::new();
+    let code = codes.gen_code("L1OF", 1).unwrap();
+    assert_eq!(code.len(), L1OF_CODE_LEN);
+    assert_eq!(code.iter().filter(|&&c| c == 1).count(), 256);
+    // a maximal sequence: -1 at all the shifts
+    for shift in 1..L1OF_CODE_LEN {
+        let corr: i32 = (0..L1OF_CODE_LEN)
+            .map(|i| (code[i] * code[(i + shift) % L1OF_CODE_LEN]) as i32)
+            .sum();
+        assert_eq!(corr, -1, "shift {shift}");
+    }
+    assert_eq!(codes.gen_code("L1OF", 7), Some(code));
+    assert!(codes.gen_code("L1OF", 25).is_none());
+    let svs = get_sat_list("L1OF", "").unwrap();
+    assert_eq!(svs.len(), 24);
+    assert!(
+        svs.iter()
+            .all(|sv| sv.constellation == Constellation::Glonass)
+    );
+}
+
+#[test]
+fn glonass_freq_channels() {
+    let mut codes = CodeTables::new();
+    assert_eq!(codes.get_freq_channel("L1OF", 1), Some(1));
+    assert_eq!(codes.get_freq_channel("L1OF", 10), Some(-7));
+    assert_eq!(codes.get_freq_channel("L1CA", 1), None);
+    assert_eq!(codes.get_carrier_offset("L1OF", 2), -4.0 * GLO_FREQ_STEP_HZ);
+    assert_eq!(codes.get_carrier_offset("L1CA", 2), 0.0);
+    codes.set_freq_channels("24:-5").unwrap();
+    assert_eq!(codes.get_freq_channel("L1OF", 24), Some(-5));
+    assert!(codes.set_freq_channels("24:7").is_err());
+    assert!(codes.set_freq_channels("25:0").is_err());
+    assert!(codes.set_freq_channels("24").is_err());
+    // those of the other receivers stay
+    assert_eq!(CodeTables::new().get_freq_channel("L1OF", 24), Some(2));
+    // the carriers beyond the band of the samples are rejected
+    let config = sim_config(Constellation::Glonass, &[1]);
+    assert!(
+        ReceiverBuilder::from_sim(config)
+            .fs(4092000.0)
+            .fs_internal(4092000.0)
+            .signal("L1OF")
+            .sats("4")
+            .build()
+            .is_err()
+    );
+}
+
+// bit n of a string, as the ICD numbers them: 85 first
+fn set_string_bit(buf: &mut [u8], n: usize, v: u32) {
+    setbitu(buf, STRING_BITS - n, 1, v);
+}
+
+// data bits b9..b84 of x, then the check bits b1..b8 of the Hamming code
+fn gen_string(mut x: u64) -> Vec<u8> {
+    let mut buf = vec![0; STRING_BITS];
+    for n in 9..STRING_BITS {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        set_string_bit(&mut buf, n, (x & 1) as u32);
+    }
+    let mut syndrome = 0;
+    let mut pos: u32 = 2;
+    for n in 9..=STRING_BITS {
+        pos += 1;
+        while pos.is_power_of_two() {
+            pos += 1;
+        }
+        if getbitu(&buf, STRING_BITS - n, 1) != 0 {
+            syndrome ^= pos;
+        }
+    }
+    for j in 0..7 {
+        set_string_bit(&mut buf, j + 1, (syndrome >> j) & 1);
+    }
+    let parity = (1..=STRING_BITS).fold(0, |p, n| p ^ getbitu(&buf, STRING_BITS - n, 1));
+    set_string_bit(&mut buf, 8, parity);
+    buf
+}
+
+// the relative code on the meander, from a symbol of either polarity
+fn gen_symbols(buf: &[u8], first: u8) -> Vec<u8> {
+    let mut syms = vec![];
+    let mut prev = first;
+    for i in 0..STRING_BITS {
+        let s = if i == 0 {
+            first
+        } else {
+            prev ^ getbitu(buf, i, 1) as u8
+        };
+        syms.extend([s, 1 - s]);
+        prev = s;
+    }
+    syms
+}
+
+#[test]
+fn glonass_strings() {
+    for seed in 1..20 {
+        let buf = gen_string(0x9E3779B97F4A7C15 ^ seed);
+        assert!(glo_string_check(&buf));
+        for first in [0, 1] {
+            let syms = gen_symbols(&buf, first);
+            assert_eq!(glo_string_from_symbols(&syms).as_ref(), Some(&buf));
+        }
+        // any single bit in error
+        for n in 1..STRING_BITS {
+            let mut bad = buf.clone();
+            set_string_bit(&mut bad, n, 1 - getbitu(&buf, STRING_BITS - n, 1));
+            assert!(!glo_string_check(&bad), "bit {n}");
+        }
+        // a symbol in error breaks the meander
+        let mut syms = gen_symbols(&buf, 0);
+        syms[41] ^= 1;
+        assert!(glo_string_from_symbols(&syms).is_none());
+    }
+}
+
+// A circular orbit at the GLONASS altitude stays on its sphere and goes
+// round at the expected speed.
+#[test]
+fn glonass_orbit() {
+    let mu = 3.9860044e14;
+    let omega_e = 7.292115e-5;
+    let r = 25510e3;
+    let inc = 64.8f64.to_radians();
+    let v = (mu / r).sqrt();
+    let mut eph = Ephemeris::new(SV::new(Constellation::Glonass, 1));
+    eph.week = 2300;
+    eph.toe = 345600;
+    eph.toc = eph.toe;
+    eph.pos = [r, 0.0, 0.0];
+    // the inertial velocity seen from the rotating earth
+    eph.vel = [0.0, v * inc.cos() - omega_e * r, v * inc.sin()];
+    eph.set_gpst();
+
+    let (x, y, z) = get_sv_position_ecef(&eph, eph.toe_gpst);
+    assert!((x - r).abs() < 0.01 && y.abs() < 0.01 && z.abs() < 0.01);
+    for dt in [-900.0, 450.0, 900.0, 1800.0] {
+        let (x, y, z) = get_sv_position_ecef(&eph, eph.toe_gpst + Duration::from_seconds(dt));
+        let radius = (x.powi(2) + y.powi(2) + z.powi(2)).sqrt();
+        assert!((radius - r).abs() < 20e3, "dt={dt}: radius={radius:.0}");
+        // the height above the equator, as the satellite climbs
+        let expected_z = r * (v * dt / r).sin() * inc.sin();
+        assert!((z - expected_z).abs() < 20e3, "dt={dt}: z={z:.0}");
+    }
+}
+
+#[test]
+fn glonass_acquisition() {
+    // slots 1 and 2 on channels 1 and -4, slot 3 on 5 isn't there
+    let config = sim_config(Constellation::Glonass, &[1, 2]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L1OF")
+        .sats("1,2,3")
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(40).unwrap();
+    assert_acquired(&config, &acquired, &[3]);
+}
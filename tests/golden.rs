@@ -0,0 +1,210 @@
+// Golden vectors: simulated signals with a known truth, processed end to end
+// and checked within tolerances, and the same output twice in deterministic
+// mode. The long ones are ignored by default:
+//   cargo test --release --test golden -- --ignored
+
+use gnss_rcv::api::{ChannelEventKind, GnssConfig, GnssReceiver, GnssSource};
+use gnss_rcv::receiver::ReceiverBuilder;
+use gnss_rcv::rinex::{get_first_toe_sec, read_rinex_nav};
+use gnss_rcv::sim::{SimConfig, SimImpairments, SimSv, Trajectory};
+use gnss_rcv::sky::get_east_north;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const L1CA_CODE_LEN: f64 = 1023.0;
+const L1CA_CHIP_RATE: f64 = 1.023e6;
+const L1_FREQ: f64 = 1575.42e6;
+const DOPPLER_TOLERANCE_HZ: f64 = 320.0; // one acquisition bin
+const CODE_TOLERANCE_CHIPS: f64 = 1.0;
+const POSITION_TOLERANCE_M: f64 = 100.0;
+const GOLDEN_NAV: &str = "tests/data/golden_nav.rnx"; // synthetic constellation
+const GOLDEN_POSITION: [f64; 3] = [40.7, -74.0, 10.0]; // 8 satellites above 10 degrees
+
+fn get_svs(prns: &[u8]) -> Vec<SV> {
+    prns.iter()
+        .map(|&prn| SV::new(Constellation::GPS, prn))
+        .collect()
+}
+
+fn get_sats(config: &SimConfig) -> String {
+    let prns: Vec<_> = config.svs.iter().map(|s| s.sv.prn.to_string()).collect();
+    prns.join(",")
+}
+
+// where the code starts in the samples, as acquisition reports it
+fn get_code_off_chips(sim_sv: &SimSv) -> f64 {
+    (L1CA_CODE_LEN - sim_sv.code_phase_chips).rem_euclid(L1CA_CODE_LEN)
+}
+
+fn get_code_distance(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(L1CA_CODE_LEN);
+    f64::min(d, L1CA_CODE_LEN - d)
+}
+
+// scans 10 msec of config, only its satellites and absent_prn there
+fn check_acquisition(config: SimConfig, absent_prn: u8, doppler_offset_hz: f64) {
+    let sats = format!("{},{absent_prn}", get_sats(&config));
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .sats(&sats)
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(10).unwrap();
+
+    for sim_sv in &config.svs {
+        let acq = (acquired.iter())
+            .find(|acq| acq.sv == sim_sv.sv)
+            .unwrap_or_else(|| panic!("{}: not acquired", sim_sv.sv));
+        let doppler_hz = sim_sv.doppler_hz + doppler_offset_hz;
+        assert!(
+            (acq.doppler_hz - doppler_hz).abs() <= DOPPLER_TOLERANCE_HZ,
+            "{}: doppler {:.0} Hz instead of {doppler_hz:.0} Hz",
+            sim_sv.sv,
+            acq.doppler_hz
+        );
+        let code_off_chips = acq.code_off_sec * L1CA_CHIP_RATE;
+        assert!(
+            get_code_distance(code_off_chips, get_code_off_chips(sim_sv)) <= CODE_TOLERANCE_CHIPS,
+            "{}: code offset {code_off_chips:.1} chips instead of {:.1}",
+            sim_sv.sv,
+            get_code_off_chips(sim_sv)
+        );
+    }
+    assert!(
+        acquired.iter().all(|acq| acq.sv.prn != absent_prn),
+        "G{absent_prn:02}: acquired without signal"
+    );
+}
+
+#[test]
+fn golden_acquisition() {
+    let config = SimConfig::from_sats(&get_svs(&[5, 7, 13, 24]), 45.0);
+    check_acquisition(config, 30, 0.0);
+}
+
+#[test]
+fn golden_acquisition_weak() {
+    let config = SimConfig::from_sats(&get_svs(&[2, 17]), 40.0);
+    check_acquisition(config, 11, 0.0);
+}
+
+// a receiver clock 1 ppm fast shifts all the dopplers by -1575 Hz
+#[test]
+fn golden_acquisition_clock_error() {
+    let mut config = SimConfig::from_sats(&get_svs(&[9, 21]), 45.0);
+    config.impairments = SimImpairments {
+        clock_ppm: 1.0,
+        ..Default::default()
+    };
+    check_acquisition(config, 3, -L1_FREQ * 1e-6);
+}
+
+#[test]
+fn golden_nav_file() {
+    let ephs = read_rinex_nav(Path::new(GOLDEN_NAV)).unwrap();
+    assert_eq!(ephs.len(), 24);
+    let start_gps_sec = get_first_toe_sec(&ephs).unwrap();
+    let [lat, lon, height] = GOLDEN_POSITION;
+    let config = SimConfig::from_ephemerides(
+        &ephs,
+        &[],
+        Trajectory::fixed(lat, lon, height),
+        start_gps_sec,
+        45.0,
+    );
+    assert!(
+        config.svs.len() >= 8,
+        "{} satellites in view",
+        config.svs.len()
+    );
+}
+
+// the measurements and channel events of 2 sec of signal, run twice in
+// deterministic mode
+fn get_deterministic_output(sim: &SimConfig) -> Vec<String> {
+    let config = GnssConfig {
+        sats: get_sats(sim),
+        source: GnssSource::Sim(sim.clone()),
+        num_msec: 2000,
+        deterministic: true,
+        ..Default::default()
+    };
+    let output = Arc::new(Mutex::new(vec![]));
+    let mut receiver = GnssReceiver::new(config);
+    let out = output.clone();
+    receiver.on_measurements(move |measurements| {
+        let json = serde_json::to_string(measurements).unwrap();
+        out.lock().unwrap().push(json);
+    });
+    let out = output.clone();
+    receiver.on_channel_event(move |event| {
+        let json = serde_json::to_string(event).unwrap();
+        out.lock().unwrap().push(json);
+    });
+    receiver.start().unwrap();
+    receiver.wait();
+    output.lock().unwrap().clone()
+}
+
+// the same output, bit for bit
+#[test]
+fn golden_deterministic() {
+    let sim = SimConfig::from_sats(&get_svs(&[4, 10, 19, 28]), 45.0);
+    let first = get_deterministic_output(&sim);
+    assert!(first.len() >= 2, "output: {first:?}");
+    assert_eq!(first, get_deterministic_output(&sim));
+}
+
+// the subframes decoded and a fix close to the simulated position
+#[test]
+#[ignore]
+fn golden_fix() {
+    let ephs = read_rinex_nav(Path::new(GOLDEN_NAV)).unwrap();
+    let [lat, lon, height] = GOLDEN_POSITION;
+    let sim = SimConfig::from_ephemerides(
+        &ephs,
+        &[],
+        Trajectory::fixed(lat, lon, height),
+        get_first_toe_sec(&ephs).unwrap(),
+        45.0,
+    );
+    let config = GnssConfig {
+        sats: get_sats(&sim),
+        source: GnssSource::Sim(sim.clone()),
+        num_msec: 45000,
+        deterministic: true,
+        ..Default::default()
+    };
+
+    let num_ephemerides = Arc::new(Mutex::new(0));
+    let fixes = Arc::new(Mutex::new(vec![]));
+    let mut receiver = GnssReceiver::new(config);
+    let num = num_ephemerides.clone();
+    receiver.on_channel_event(move |event| {
+        if event.kind == ChannelEventKind::Ephemeris {
+            *num.lock().unwrap() += 1;
+        }
+    });
+    let fixes_cb = fixes.clone();
+    receiver.on_fix(move |fix| fixes_cb.lock().unwrap().push(*fix));
+    receiver.start().unwrap();
+    receiver.wait();
+
+    let num_ephemerides = *num_ephemerides.lock().unwrap();
+    assert!(
+        num_ephemerides >= 4,
+        "{num_ephemerides} ephemerides decoded out of {}",
+        sim.svs.len()
+    );
+    let fix = *(fixes.lock().unwrap().last()).expect("no fix");
+    let (east, north) = get_east_north(GOLDEN_POSITION, [fix.lat, fix.lon, fix.height]);
+    assert!(
+        east.hypot(north) <= POSITION_TOLERANCE_M,
+        "fix {:.5},{:.5} {:.0} m east {:.0} m north of the truth",
+        fix.lat,
+        fix.lon,
+        east,
+        north
+    );
+}
@@ -0,0 +1,84 @@
+// The interference detector on synthetic inputs: each sign of jamming on its
+// own starts a detection, which ends once they are gone.
+
+use gnss_rcv::interference::{INTERFERENCE_PERIOD_SEC, InterferenceDetector};
+use gnss_rcv::sample::{Float, IQSample};
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+
+const BLOCK_LEN: usize = 2046; // 1 msec at the default sampling rate
+
+fn get_block(power: f64) -> Vec<IQSample> {
+    vec![IQSample::new(power.sqrt() as Float, 0.0); BLOCK_LEN]
+}
+
+fn get_cn0s(cn0: f64) -> Vec<(SV, f64)> {
+    (1..=6)
+        .map(|prn| (SV::new(Constellation::GPS, prn), cn0))
+        .collect()
+}
+
+// num_periods of INTERFERENCE_PERIOD_SEC from t_sec: the time after them and
+// whether the detection started or ended during them
+fn run(
+    detector: &mut InterferenceDetector,
+    t_sec: f64,
+    num_periods: usize,
+    power: f64,
+    cn0: f64,
+) -> (f64, bool) {
+    let mut changed = false;
+    let mut t_sec = t_sec;
+    for _ in 0..num_periods {
+        detector.add_samples(&get_block(power));
+        changed |= detector.update(t_sec, &get_cn0s(cn0));
+        t_sec += INTERFERENCE_PERIOD_SEC;
+    }
+    (t_sec, changed)
+}
+
+#[test]
+fn interference_clear() {
+    let mut detector = InterferenceDetector::new();
+    let (_, changed) = run(&mut detector, 0.0, 100, 1.0, 45.0);
+    assert!(!changed);
+    assert!(detector.get_events().is_empty());
+}
+
+#[test]
+fn interference_agc() {
+    let mut detector = InterferenceDetector::new();
+    let (t_sec, _) = run(&mut detector, 0.0, 100, 1.0, 45.0);
+    let (t_sec, changed) = run(&mut detector, t_sec, 10, 4.0, 45.0);
+    assert!(changed && detector.get_status().is_detected());
+    assert!(detector.get_status().agc_db > 5.0);
+
+    let (_, changed) = run(&mut detector, t_sec, 30, 1.0, 45.0);
+    assert!(changed && !detector.get_status().is_detected());
+    let events = detector.get_events();
+    assert_eq!(events.len(), 1);
+    assert!(events[0].end_sec.is_some());
+    assert!(events[0].max_agc_db > 5.0);
+}
+
+// an AGC keeping the power constant: only the cn0 tells
+#[test]
+fn interference_noise_floor() {
+    let mut detector = InterferenceDetector::new();
+    let (t_sec, _) = run(&mut detector, 0.0, 100, 1.0, 45.0);
+    let (_, changed) = run(&mut detector, t_sec, 10, 1.0, 38.0);
+    assert!(changed && detector.get_status().is_detected());
+    assert!(detector.get_status().noise_rise_db > 5.0);
+}
+
+#[test]
+fn interference_losses() {
+    let mut detector = InterferenceDetector::new();
+    let (t_sec, _) = run(&mut detector, 0.0, 100, 1.0, 45.0);
+    for i in 0..3 {
+        detector.add_loss(t_sec + 0.1 * i as f64);
+    }
+    let (_, changed) = run(&mut detector, t_sec + 0.3, 1, 1.0, 45.0);
+    assert!(changed && detector.get_status().is_detected());
+    assert_eq!(detector.get_status().num_lost, 3);
+}
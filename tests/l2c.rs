@@ -0,0 +1,72 @@
+// GPS L2C on simulated signals: the CM code, time-multiplexed with the CL
+// slots left empty, acquired over its 20 msec period and tracked.
+
+use gnss_rcv::code::{Code, CodeTables, L2CL_CODE_LEN, L2CM_CODE_LEN};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rs::constellation::Constellation;
+
+mod common;
+use common::{assert_acquired_within, sim_config};
+
+const FS: f64 = 2046000.0;
+const DOPPLER_TOLERANCE_HZ: f64 = 16.0; // one acquisition bin of 20 msec
+
+#[test]
+fn l2c_codes() {
+    let codes = CodeTables::new();
+    let code = codes.gen_code("L2C", 1).unwrap();
+    assert_eq!(code.len(), 2 * L2CM_CODE_LEN);
+    assert!(code.chunks(2).all(|c| c[0].abs() == 1 && c[1] == 0));
+    assert_eq!(codes.gen_code("L2CL", 1).unwrap().len(), L2CL_CODE_LEN);
+    assert_eq!(Code::get_code_sp("L2C", FS), 20 * 2046);
+    assert_eq!(Code::get_code_freq("L2C"), 1227.60e6);
+    assert_ne!(codes.gen_code("L2C", 1), codes.gen_code("L2C", 2));
+    assert!(codes.gen_code("L2C", 38).is_none());
+    assert_eq!(get_sat_list("L2C", "").unwrap().len(), 32);
+    assert!(get_sat_list("L2C", "38").is_err());
+    // the 1.5 sec CL code is out of reach of the acquisition
+    let config = sim_config(Constellation::GPS, &[1]);
+    assert!(
+        ReceiverBuilder::from_sim(config)
+            .signal("L2CL")
+            .build()
+            .is_err()
+    );
+}
+
+#[test]
+fn l2c_acquisition() {
+    let config = sim_config(Constellation::GPS, &[5]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L2C")
+        .sats("5,6")
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(40).unwrap();
+    assert_acquired_within(&config, &acquired, &[6], DOPPLER_TOLERANCE_HZ);
+}
+
+#[test]
+fn l2c_tracking() {
+    let config = sim_config(Constellation::GPS, &[7]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L2C")
+        .sats("7")
+        .build()
+        .unwrap();
+    receiver.set_plot_period(None);
+    let tracked = receiver.run_acquire(2000);
+    assert_eq!(tracked.len(), 1, "not tracked");
+    let sim_sv = &config.svs[0];
+    assert!(
+        (tracked[0].doppler_hz - sim_sv.doppler_hz).abs() <= 5.0,
+        "doppler {:.1} Hz instead of {:.1} Hz",
+        tracked[0].doppler_hz,
+        sim_sv.doppler_hz
+    );
+    assert!(tracked[0].cn0 > 40.0, "cn0={:.1}", tracked[0].cn0);
+}
@@ -0,0 +1,119 @@
+// GPS L5 on simulated signals: the I5 and Q5 codes and their Neuman-Hofman
+// secondary codes, acquired period by period or coherently over a whole
+// secondary code, and tracked with the secondary code wiped off.
+
+use std::sync::{Arc, Mutex};
+
+use gnss_rcv::code::{Code, CodeTables, L5_CODE_LEN, NH10_CODE, NH20_CODE};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rcv::state::GnssState;
+use gnss_rs::constellation::Constellation;
+
+mod common;
+use common::{DOPPLER_TOLERANCE_HZ, assert_acquired_within, sim_config};
+
+const FS: f64 = 10230000.0; // a sample per chip
+
+#[test]
+fn l5_codes() {
+    let codes = CodeTables::new();
+    let i5 = codes.gen_code("L5I", 1).unwrap();
+    let q5 = codes.gen_code("L5Q", 1).unwrap();
+    assert_eq!(i5.len(), L5_CODE_LEN);
+    assert_eq!(q5.len(), L5_CODE_LEN);
+    assert_eq!(codes.gen_code("L5", 1).as_ref(), Some(&i5));
+    assert_ne!(i5, q5);
+    assert_ne!(codes.gen_code("L5I", 2).as_ref(), Some(&i5));
+    let ones = i5.iter().filter(|&&c| c == 1).count();
+    assert!(ones.abs_diff(L5_CODE_LEN / 2) < 300, "{ones} chips at 1");
+    // the sidelobes of a random-like code, and its correlation with Q5
+    for shift in 0..L5_CODE_LEN {
+        let corr = |other: &[i8]| -> i32 {
+            (0..L5_CODE_LEN)
+                .map(|i| (i5[i] * other[(i + shift) % L5_CODE_LEN]) as i32)
+                .sum()
+        };
+        if shift != 0 {
+            assert!(corr(&i5).abs() < 600, "shift {shift}");
+        }
+        assert!(corr(&q5).abs() < 600, "shift {shift}");
+    }
+    assert!(codes.gen_code("L5I", 38).is_none());
+    assert_eq!(Code::get_secondary_code("L5I", 1), NH10_CODE);
+    assert_eq!(Code::get_secondary_code("L5Q", 1), NH20_CODE);
+    assert_eq!(Code::get_code_freq("L5"), 1176.45e6);
+    assert_eq!(Code::get_code_sp("L5", FS), L5_CODE_LEN);
+    let svs = get_sat_list("L5", "").unwrap();
+    assert_eq!(svs.len(), 32);
+    assert!(svs.iter().all(|sv| sv.constellation == Constellation::GPS));
+    // below the chip rate
+    assert!(
+        ReceiverBuilder::from_sim(sim_config(Constellation::GPS, &[1]))
+            .signal("L5")
+            .build()
+            .is_err()
+    );
+}
+
+fn check_acquisition(sig: &str, coherent: usize) {
+    let config = sim_config(Constellation::GPS, &[3]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal(sig)
+        .sats("3,4")
+        .build()
+        .unwrap();
+    receiver.set_acq_coherent(coherent).unwrap();
+    let acquired = receiver.run_scan(20).unwrap();
+    let tolerance_hz = DOPPLER_TOLERANCE_HZ / coherent as f64;
+    assert_acquired_within(&config, &acquired, &[4], tolerance_hz);
+}
+
+#[test]
+fn l5_acquisition() {
+    check_acquisition("L5I", 1);
+}
+
+#[test]
+fn l5_acquisition_coherent() {
+    check_acquisition("L5I", 10);
+    check_acquisition("L5Q", 20);
+    // the secondary code doesn't fit
+    let mut receiver = ReceiverBuilder::from_sim(sim_config(Constellation::GPS, &[3]))
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L5Q")
+        .sats("3")
+        .build()
+        .unwrap();
+    assert!(receiver.set_acq_coherent(10).is_err());
+    assert!(receiver.set_acq_coherent(0).is_err());
+}
+
+#[test]
+fn l5_tracking() {
+    let config = sim_config(Constellation::GPS, &[7]);
+    let state = Arc::new(Mutex::new(GnssState::new()));
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L5I")
+        .sats("7")
+        .state(state.clone())
+        .build()
+        .unwrap();
+    receiver.set_plot_period(None);
+    let tracked = receiver.run_acquire(2000);
+    assert_eq!(tracked.len(), 1, "not tracked");
+    let sim_sv = &config.svs[0];
+    assert!(
+        (tracked[0].doppler_hz - sim_sv.doppler_hz).abs() <= 5.0,
+        "doppler {:.1} Hz instead of {:.1} Hz",
+        tracked[0].doppler_hz,
+        sim_sv.doppler_hz
+    );
+    let pub_state = state.lock().unwrap();
+    let channel = pub_state.get_sv_channel(sim_sv.sv).unwrap();
+    assert!(channel.nav.secondary_sync, "no NH10 sync");
+}
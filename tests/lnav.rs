@@ -0,0 +1,135 @@
+// Properties of the LNAV parity and of the subframes 1-3 encoding, and the
+// fit interval of the subframes saved for a hot start.
+
+use gnss_rcv::ephemeris::Ephemeris;
+use gnss_rcv::navigation::{nav_encode_lnav_parity, nav_test_lnav_parity};
+use gnss_rcv::start::SatState;
+use gnss_rcv::util::{getbitu, setbitu};
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use proptest::prelude::*;
+
+fn get_nav_data(words: &[u32]) -> Vec<u8> {
+    let mut nav_data = vec![0; 300];
+    for (i, &word) in words.iter().enumerate() {
+        setbitu(&mut nav_data, 30 * i, 24, word);
+    }
+    nav_data
+}
+
+proptest! {
+    // the data comes back, but for the bits of words 2 and 10 chosen for the parity
+    #[test]
+    fn parity_round_trip(words in prop::collection::vec(0..1u32 << 24, 10)) {
+        let bits = nav_encode_lnav_parity(&get_nav_data(&words));
+        prop_assert_eq!(bits.len(), 300);
+        let mut nav_data = vec![0; 300];
+        prop_assert!(nav_test_lnav_parity(&bits, &mut nav_data));
+        for (i, &word) in words.iter().enumerate() {
+            let mask = if i == 1 || i == 9 { !3 } else { !0 };
+            prop_assert_eq!(getbitu(&nav_data, 30 * i, 24) & mask, word & mask, "word {}", i + 1);
+            prop_assert_eq!(getbitu(&nav_data, 30 * i + 24, 6), 0);
+        }
+        prop_assert_eq!(getbitu(&nav_data, 298, 2), 0);
+    }
+
+    // any single bit error is caught
+    #[test]
+    fn parity_bit_error(words in prop::collection::vec(0..1u32 << 24, 10), pos in 0..300usize) {
+        let mut bits = nav_encode_lnav_parity(&get_nav_data(&words));
+        bits[pos] ^= 1;
+        prop_assert!(!nav_test_lnav_parity(&bits, &mut vec![0; 300]));
+    }
+
+    // whatever the bits, no panic
+    #[test]
+    fn parity_any_bits(bits in prop::collection::vec(any::<u8>(), 0..400)) {
+        let _ = nav_test_lnav_parity(&bits, &mut vec![0; 300]);
+    }
+
+    #[test]
+    fn ephemeris_round_trip(
+        week in 2048..3072u32,
+        toe in 0..37800u32,
+        iode in 0..256u32,
+        f0 in -1e-3..1e-3f64,
+        m0 in -3.14..3.14f64,
+        ecc in 0.0..0.03f64,
+        sqrt_a in 5000.0..5300.0f64,
+        omg0 in -3.14..3.14f64,
+        i0 in 0.9..1.0f64,
+        omg_dot in -1e-8..0.0f64,
+    ) {
+        let sv = SV::new(Constellation::GPS, 5);
+        let mut eph = Ephemeris::new(sv);
+        eph.week = week;
+        eph.toe = toe * 16;
+        eph.toc = toe * 16;
+        eph.iode = iode;
+        eph.iodc = iode;
+        eph.f0 = f0;
+        eph.m0 = m0;
+        eph.ecc = ecc;
+        eph.a = sqrt_a * sqrt_a;
+        eph.omg0 = omg0;
+        eph.i0 = i0;
+        eph.omg_dot = omg_dot;
+
+        let mut decoded = Ephemeris::new(sv);
+        for id in 1..=3 {
+            let bits = nav_encode_lnav_parity(&eph.nav_encode_lnav_subframe(id, 6000));
+            let mut nav_data = vec![0; 300];
+            prop_assert!(nav_test_lnav_parity(&bits, &mut nav_data));
+            prop_assert_eq!(getbitu(&nav_data, 49, 3), id);
+            match id {
+                1 => decoded.nav_decode_lnav_subframe1(&nav_data, sv),
+                2 => decoded.nav_decode_lnav_subframe2(&nav_data, sv),
+                _ => decoded.nav_decode_lnav_subframe3(&nav_data, sv),
+            }
+        }
+        prop_assert_eq!(decoded.tow, 6000);
+        prop_assert_eq!(decoded.week, week);
+        prop_assert_eq!(decoded.toe, eph.toe);
+        prop_assert_eq!(decoded.toc, eph.toc);
+        prop_assert_eq!(decoded.iode, iode);
+        prop_assert_eq!(decoded.iodc, iode);
+        prop_assert!((decoded.f0 - f0).abs() <= 1e-9);
+        prop_assert!((decoded.m0 - m0).abs() <= 1e-8);
+        prop_assert!((decoded.ecc - ecc).abs() <= 1e-9);
+        prop_assert!((decoded.a.sqrt() - sqrt_a).abs() <= 1e-5);
+        prop_assert!((decoded.omg0 - omg0).abs() <= 1e-8);
+        prop_assert!((decoded.i0 - i0).abs() <= 1e-8);
+        prop_assert!((decoded.omg_dot - omg_dot).abs() <= 1e-12);
+    }
+}
+
+// the saved subframes are good within half the fit interval of toe: 2 hours
+// for gps, 1 for QZSS
+#[test]
+fn saved_ephemeris_fit() {
+    let get_sat = |sv: SV| {
+        let mut eph = Ephemeris::new(sv);
+        eph.week = 2350;
+        eph.toe = 3600 * 50;
+        eph.toc = eph.toe;
+        SatState {
+            prn: sv.prn,
+            doppler_hz: None,
+            subframes: (1..=3)
+                .map(|id| eph.nav_encode_lnav_subframe(id, eph.toe))
+                .collect(),
+        }
+    };
+    let toe_sec = 2350.0 * 604800.0 + 3600.0 * 50.0;
+    let gps = get_sat(SV::new(Constellation::GPS, 5));
+    assert!(gps.has_ephemeris(toe_sec - 5400.0));
+    assert!(gps.has_ephemeris(toe_sec + 5400.0));
+    assert!(!gps.has_ephemeris(toe_sec + 3.0 * 3600.0));
+    let qzss = get_sat(SV::new(Constellation::QZSS, 193));
+    assert!(qzss.has_ephemeris(toe_sec + 1800.0));
+    assert!(!qzss.has_ephemeris(toe_sec + 5400.0));
+    // not all the subframes
+    let mut partial = gps.clone();
+    partial.subframes.pop();
+    assert!(!partial.has_ephemeris(toe_sec));
+}
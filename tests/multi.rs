@@ -0,0 +1,115 @@
+// Several constellations on one front-end: the satellites of a list of
+// signals, GPS L1CA and Galileo E1B simulated together on the same carrier,
+// acquired and tracked by the channels of their own signal.
+
+use gnss_rcv::code::{Code, CodeTables};
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rcv::sim::SimConfig;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use std::sync::LazyLock;
+
+mod common;
+use common::{SIM_CN0, assert_acquired, load_e1_codes};
+
+const FS: f64 = 4092000.0;
+
+static CODES: LazyLock<CodeTables> = LazyLock::new(|| load_e1_codes(0x2545F4914F6CDD1D, &[2, 3]));
+
+fn get_config(gps_prn: u8, gal_prn: u8) -> SimConfig {
+    let svs = [
+        SV::new(Constellation::GPS, gps_prn),
+        SV::new(Constellation::Galileo, gal_prn),
+    ];
+    SimConfig::from_sats(&svs, SIM_CN0)
+}
+
+#[test]
+fn multi_sat_list() {
+    let sigs = Code::get_sig_list("L1CA, E1B");
+    assert_eq!(sigs, ["L1CA", "E1B"]);
+    assert_eq!(
+        Code::get_constellation_signal(&sigs, Constellation::Galileo),
+        Some("E1B")
+    );
+    assert_eq!(
+        Code::get_constellation_signal(&sigs, Constellation::BeiDou),
+        None
+    );
+    let svs = get_sat_list("L1CA,E1B", "").unwrap();
+    assert_eq!(svs.len(), 32 + 7 + 36);
+    // a prn alone is one of the first signal
+    let svs = get_sat_list("L1CA,E1B", "3,E11,G5").unwrap();
+    assert_eq!(
+        svs,
+        [
+            SV::new(Constellation::GPS, 3),
+            SV::new(Constellation::Galileo, 11),
+            SV::new(Constellation::GPS, 5)
+        ]
+    );
+    let svs = get_sat_list("E1B,L1CA", "3").unwrap();
+    assert_eq!(svs, [SV::new(Constellation::Galileo, 3)]);
+    // without a signal of their constellation
+    assert_eq!(get_sat_list("L1CA", "3,E11").unwrap().len(), 1);
+    assert!(get_sat_list("L1CA,E1B", "E51").is_err());
+    assert!(get_sat_list("L1CA,L2C", "").is_err());
+    assert!(get_sat_list("L1CA,X1", "").is_err());
+
+    let builder = |sig: &str, sats: &str| {
+        ReceiverBuilder::from_sim(get_config(3, 2))
+            .fs(FS)
+            .fs_internal(FS)
+            .signal(sig)
+            .sats(sats)
+            .build()
+    };
+    // not on one carrier
+    assert!(builder("L1CA,B1I", "").is_err());
+    assert!(builder("L1CA,L5", "").is_err());
+    // nothing left to search
+    assert!(builder("L1CA", "R3").is_err());
+}
+
+#[test]
+fn multi_acquisition() {
+    let config = get_config(7, 2);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L1CA,E1B")
+        .sats("7,8,E2,E3")
+        .code_tables(CODES.clone())
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(40).unwrap();
+    assert_acquired(&config, &acquired, &[8, 3]);
+}
+
+#[test]
+fn multi_tracking() {
+    let config = get_config(11, 3);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .fs(FS)
+        .fs_internal(FS)
+        .signal("L1CA,E1B")
+        .sats("11,E3")
+        .code_tables(CODES.clone())
+        .build()
+        .unwrap();
+    receiver.set_plot_period(None);
+    let tracked = receiver.run_acquire(1000);
+    assert_eq!(tracked.len(), 2, "tracked: {tracked:?}");
+    for sim_sv in &config.svs {
+        let trk = (tracked.iter())
+            .find(|trk| trk.sv == sim_sv.sv)
+            .unwrap_or_else(|| panic!("{}: not tracked", sim_sv.sv));
+        assert!(
+            (trk.doppler_hz - sim_sv.doppler_hz).abs() <= 10.0,
+            "{}: doppler {:.1} Hz instead of {:.1} Hz",
+            sim_sv.sv,
+            trk.doppler_hz,
+            sim_sv.doppler_hz
+        );
+    }
+}
@@ -0,0 +1,57 @@
+// The notch filter on noise, with and without a carrier in it.
+
+use gnss_rcv::noise::NoiseSource;
+use gnss_rcv::notch::NotchFilter;
+use gnss_rcv::sample::{Float, IQSample};
+use gnss_rcv::source::SampleSource;
+
+const FS: f64 = 2046000.0;
+const BLOCK_LEN: usize = 2046; // 1 msec
+const CW_HZ: f64 = 100e3;
+const CW_TOLERANCE_HZ: f64 = 2000.0; // a bin of the detection
+
+// num_msec of unit power noise, plus a carrier of cw_power, through the
+// filter: the power of the last 100 msec out of it
+fn run(notch: &mut NotchFilter, num_msec: usize, cw_power: f64) -> f64 {
+    let mut noise = NoiseSource::new(FS, 1);
+    let mut power = 0.0;
+    for i in 0..num_msec {
+        let off = i * BLOCK_LEN;
+        let mut iq_vec = noise.read_block(off, BLOCK_LEN).unwrap().iq_vec;
+        for (k, s) in iq_vec.iter_mut().enumerate() {
+            let phase = 2.0 * std::f64::consts::PI * CW_HZ * (off + k) as f64 / FS;
+            *s += IQSample::from_polar(cw_power.sqrt() as Float, phase as Float);
+        }
+        notch.process(&mut iq_vec);
+        if i + 100 >= num_msec {
+            power += iq_vec.iter().map(|s| s.norm_sqr() as f64).sum::<f64>();
+        }
+    }
+    power / (100 * BLOCK_LEN) as f64
+}
+
+#[test]
+fn notch_carrier() {
+    let mut notch = NotchFilter::new(FS);
+    let power = run(&mut notch, 500, 1.0);
+    let notches_hz = notch.get_notches_hz();
+    assert_eq!(notches_hz.len(), 1, "notches at {notches_hz:?} Hz");
+    assert!(
+        (notches_hz[0] - CW_HZ).abs() <= CW_TOLERANCE_HZ,
+        "notch at {:.0} Hz",
+        notches_hz[0]
+    );
+    // the noise and the carrier in, about only the noise out
+    assert!(power < 1.2, "power {power:.2} out of the filter");
+}
+
+#[test]
+fn notch_noise_only() {
+    let mut notch = NotchFilter::new(FS);
+    let power = run(&mut notch, 500, 0.0);
+    assert!(notch.get_notches_hz().is_empty());
+    assert!(
+        (power - 1.0).abs() < 0.05,
+        "power {power:.2} out of the filter"
+    );
+}
@@ -0,0 +1,67 @@
+// The multipath indicators on synthetic correlations and code minus carrier:
+// a clean signal, a distorted correlation peak and a reflection moving the
+// code.
+
+use gnss_rcv::quality::QualityMonitor;
+use rustfft::num_complex::Complex64;
+
+const NOISE_M: f64 = 0.5; // expected code tracking noise
+
+// num_sec of one update per second from t_sec, with the early and late
+// correlators at e and l of the prompt: whether the flag changed
+fn run(
+    monitor: &mut QualityMonitor,
+    t_sec: f64,
+    num_sec: usize,
+    e: f64,
+    l: f64,
+    cmc: impl Fn(f64) -> f64,
+) -> bool {
+    let mut changed = false;
+    for i in 0..num_sec {
+        let t = t_sec + i as f64;
+        for _ in 0..1000 {
+            let c_p = Complex64::new(1.0, 0.0);
+            monitor.add_correlations(c_p, c_p * e, c_p * l);
+        }
+        changed |= monitor.update(t, cmc(t), NOISE_M);
+    }
+    changed
+}
+
+#[test]
+fn quality_clean() {
+    let mut monitor = QualityMonitor::new();
+    // the ionospheric divergence: a drift, fitted out
+    let changed = run(&mut monitor, 0.0, 60, 0.5, 0.5, |t| 3.0 + 0.02 * t);
+    assert!(!changed);
+    let quality = monitor.get_quality();
+    assert!(!quality.multipath);
+    assert!(quality.delta.abs() < 1e-9 && (quality.ratio - 1.0).abs() < 1e-9);
+    assert!(quality.cmc_rms_m.unwrap() < 1e-6);
+}
+
+#[test]
+fn quality_asymmetric_peak() {
+    let mut monitor = QualityMonitor::new();
+    run(&mut monitor, 0.0, 5, 0.5, 0.5, |_| 0.0);
+    let changed = run(&mut monitor, 5.0, 1, 0.6, 0.4, |_| 0.0);
+    assert!(changed && monitor.get_quality().multipath);
+    assert!((monitor.get_quality().delta - 0.2).abs() < 1e-9);
+
+    let changed = run(&mut monitor, 6.0, 1, 0.5, 0.5, |_| 0.0);
+    assert!(changed && !monitor.get_quality().multipath);
+}
+
+#[test]
+fn quality_code_minus_carrier() {
+    let mut monitor = QualityMonitor::new();
+    // a reflection fading in and out over a few seconds
+    let cmc = |t: f64| 3.0 * (2.0 * std::f64::consts::PI * t / 7.0).sin();
+    let changed = run(&mut monitor, 0.0, 30, 0.5, 0.5, cmc);
+    assert!(changed);
+    let quality = monitor.get_quality();
+    assert!(quality.multipath && quality.delta.abs() < 1e-9);
+    let rms_m = quality.cmc_rms_m.unwrap();
+    assert!(rms_m > 1.5 && rms_m < 2.5, "rms {rms_m:.2} m");
+}
@@ -0,0 +1,83 @@
+// The sample queue between a reader thread and the receiver: the gaps of the
+// samples dropped under either policy, reported by the block popped right
+// after them, and its bound kept with vectors larger than it.
+
+use gnss_rcv::sample::{Float, IQSample};
+use gnss_rcv::source::{DropPolicy, SampleQueue};
+
+const MAX_SAMPLES: usize = 100;
+
+// samples numbered from start
+fn get_vec(start: usize, len: usize) -> Vec<IQSample> {
+    (start..start + len)
+        .map(|i| IQSample::new(i as Float, 0.0))
+        .collect()
+}
+
+fn first(iq_vec: &[IQSample]) -> usize {
+    iq_vec[0].re as usize
+}
+
+#[test]
+fn queue_contiguous() {
+    let queue = SampleQueue::new(MAX_SAMPLES, DropPolicy::DropNewest);
+    queue.push(get_vec(0, 30));
+    queue.push(get_vec(30, 30));
+    assert!(queue.pop(70).is_none());
+    for start in [0, 20, 40] {
+        let block = queue.pop(20).unwrap();
+        assert!(block.is_contiguous());
+        assert_eq!(first(&block.iq_vec), start);
+    }
+    assert_eq!(queue.get_num_samples(), 0);
+    assert_eq!(queue.get_num_dropped_total(), 0);
+}
+
+#[test]
+fn queue_drop_newest() {
+    let queue = SampleQueue::new(MAX_SAMPLES, DropPolicy::DropNewest);
+    queue.push(get_vec(0, 60));
+    queue.push(get_vec(60, 60)); // dropped
+    queue.push(get_vec(120, 30));
+    // before the gap: contiguous
+    let block = queue.pop(50).unwrap();
+    assert_eq!((first(&block.iq_vec), block.num_dropped), (0, 0));
+    // across it: the 10 samples left before it go with it
+    let block = queue.pop(30).unwrap();
+    assert_eq!((first(&block.iq_vec), block.num_dropped), (120, 70));
+    assert_eq!(queue.get_num_dropped_total(), 70);
+    assert_eq!(queue.get_num_samples(), 0);
+}
+
+#[test]
+fn queue_drop_newest_short() {
+    let queue = SampleQueue::new(MAX_SAMPLES, DropPolicy::DropNewest);
+    queue.push(get_vec(0, 90));
+    queue.push(get_vec(90, 20)); // dropped
+    queue.push(get_vec(110, 10));
+    queue.pop(80).unwrap();
+    // 20 samples queued, 10 of them after the gap: not enough for a block
+    assert!(queue.pop(20).is_none());
+    assert_eq!(queue.get_num_samples(), 10);
+    queue.push(get_vec(120, 10));
+    let block = queue.pop(20).unwrap();
+    assert_eq!((first(&block.iq_vec), block.num_dropped), (110, 30));
+}
+
+#[test]
+fn queue_drop_oldest() {
+    let queue = SampleQueue::new(MAX_SAMPLES, DropPolicy::DropOldest);
+    queue.push(get_vec(0, 60));
+    queue.push(get_vec(60, 30));
+    queue.push(get_vec(90, 60)); // the first vector dropped
+    let block = queue.pop(30).unwrap();
+    assert_eq!((first(&block.iq_vec), block.num_dropped), (60, 60));
+    let block = queue.pop(60).unwrap();
+    assert!(block.is_contiguous());
+    // larger than the queue: its newest samples kept
+    queue.push(get_vec(150, 250));
+    assert_eq!(queue.get_num_samples(), MAX_SAMPLES);
+    let block = queue.pop(MAX_SAMPLES).unwrap();
+    assert_eq!((first(&block.iq_vec), block.num_dropped), (300, 150));
+    assert_eq!(queue.get_num_dropped_total(), 210);
+}
@@ -0,0 +1,138 @@
+// QZSS on L1CA: the codes of PRN 193 to 199 among those of gps and the
+// satellites that go by them, the LNAV
+// subframes of a QZSS satellite and their shorter fit interval, the orbit
+// of an inclined geosynchronous satellite and the acquisition of simulated
+// ones.
+
+use gnss_rcv::code::{CodeTables, L1CA_CODE_LEN};
+use gnss_rcv::ephemeris::{Ephemeris, is_qzss};
+use gnss_rcv::navigation::{nav_encode_lnav_parity, nav_test_lnav_parity};
+use gnss_rcv::navigation_sbas::is_sbas;
+use gnss_rcv::receiver::{ReceiverBuilder, get_sat_list};
+use gnss_rcv::solver::get_sv_position_ecef;
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+
+mod common;
+use common::{assert_acquired, get_corr, sim_config};
+
+const GEO_RADIUS_M: f64 = 42_164_000.0;
+
+fn get_qzss_eph(prn: u8) -> Ephemeris {
+    let mut eph = Ephemeris::new(SV::new(Constellation::QZSS, prn));
+    eph.week = 2350;
+    eph.toe = 3600 * 50;
+    eph.toc = eph.toe;
+    eph.iode = 41;
+    eph.iodc = 41;
+    eph.f0 = 1.2e-5;
+    eph.m0 = 0.5;
+    eph.ecc = 0.075;
+    eph.a = GEO_RADIUS_M;
+    eph.omg0 = 2.5;
+    eph.omg = -1.6;
+    eph.i0 = 0.72;
+    eph.omg_dot = -2.9e-9;
+    eph.fit = 1;
+    eph
+}
+
+#[test]
+fn qzss_codes() {
+    let codes = CodeTables::new();
+    let code193 = codes.gen_code("L1CA", 193).unwrap();
+    let code194 = codes.gen_code("L1CA", 194).unwrap();
+    let code1 = codes.gen_code("L1CA", 1).unwrap();
+    assert_eq!(code193.len(), L1CA_CODE_LEN);
+    assert_ne!(code193, code194);
+    // Gold codes of the same family as gps: three-valued correlations
+    for shift in 0..L1CA_CODE_LEN {
+        for (other, is_same) in [(&code193, true), (&code194, false), (&code1, false)] {
+            let corr = get_corr(&code193, other, shift);
+            if !is_same || shift != 0 {
+                assert!([-65, -1, 63].contains(&corr), "shift {shift}: {corr}");
+            }
+        }
+    }
+    let svs = get_sat_list("L1CA", "").unwrap();
+    assert_eq!(svs.len(), 39);
+    let qzss: Vec<_> = svs.iter().filter(|&&sv| is_qzss(sv)).collect();
+    assert_eq!(qzss.len(), 7);
+    assert!(qzss.iter().all(|sv| (193..=199).contains(&sv.prn)));
+    assert!(qzss.iter().all(|&&sv| !is_sbas(sv)));
+    assert!(
+        qzss.iter()
+            .all(|sv| sv.constellation == Constellation::QZSS)
+    );
+    let svs = get_sat_list("L1CA", "193,J202").unwrap();
+    assert_eq!(
+        svs,
+        [
+            SV::new(Constellation::QZSS, 193),
+            SV::new(Constellation::QZSS, 202)
+        ]
+    );
+    assert!(get_sat_list("L1CA", "G193").is_err());
+    assert!(get_sat_list("L1CA", "J5").is_err());
+    assert!(!is_qzss(SV::new(Constellation::GPS, 32)));
+    // only on L1CA
+    assert_eq!(get_sat_list("L2C", "").unwrap().len(), 32);
+}
+
+// The subframes 1 to 3 of a QZSS satellite decoded back, and the fit
+// interval of its flag.
+#[test]
+fn qzss_lnav_subframes() {
+    let eph = get_qzss_eph(195);
+    let mut decoded = Ephemeris::new(eph.sv);
+    for id in 1..=3 {
+        let bits = nav_encode_lnav_parity(&eph.nav_encode_lnav_subframe(id, 6000));
+        let mut nav_data = vec![0; 300];
+        assert!(nav_test_lnav_parity(&bits, &mut nav_data));
+        match id {
+            1 => decoded.nav_decode_lnav_subframe1(&nav_data, eph.sv),
+            2 => decoded.nav_decode_lnav_subframe2(&nav_data, eph.sv),
+            _ => decoded.nav_decode_lnav_subframe3(&nav_data, eph.sv),
+        }
+    }
+    assert_eq!(decoded.week, eph.week);
+    assert_eq!(decoded.toe, eph.toe);
+    assert_eq!(decoded.iode, eph.iode);
+    assert_eq!(decoded.fit, 1);
+    assert!((decoded.ecc - eph.ecc).abs() <= 1e-9);
+    assert!((decoded.a.sqrt() - eph.a.sqrt()).abs() <= 1e-5);
+    assert_eq!(decoded.get_fit_interval_sec(), 2.0 * 3600.0);
+    let gps = Ephemeris::new(SV::new(Constellation::GPS, 5));
+    assert_eq!(gps.get_fit_interval_sec(), 4.0 * 3600.0);
+}
+
+#[test]
+fn qzss_orbit() {
+    let mut eph = get_qzss_eph(194);
+    eph.set_gpst();
+    let mut last_pos = None;
+    for hours in [-1, 0, 1] {
+        let t = eph.toe_gpst + Duration::from_seconds(hours as f64 * 3600.0);
+        let pos = get_sv_position_ecef(&eph, t);
+        let r = (pos.0 * pos.0 + pos.1 * pos.1 + pos.2 * pos.2).sqrt();
+        // within the eccentricity of the geosynchronous radius
+        assert!(
+            (r - GEO_RADIUS_M).abs() <= 1.01 * eph.ecc * GEO_RADIUS_M,
+            "{hours} h: r={r:.0} m"
+        );
+        assert_ne!(last_pos, Some(pos));
+        last_pos = Some(pos);
+    }
+}
+
+#[test]
+fn qzss_acquisition() {
+    let config = sim_config(Constellation::QZSS, &[193, 199]);
+    let mut receiver = ReceiverBuilder::from_sim(config.clone())
+        .sats("193,194,199")
+        .build()
+        .unwrap();
+    let acquired = receiver.run_scan(10).unwrap();
+    assert_acquired(&config, &acquired, &[194]);
+}
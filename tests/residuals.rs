@@ -0,0 +1,96 @@
+// The pseudorange residuals on ephemerides whose code offsets are made
+// consistent with a known position: a satellite given a bias is flagged, left
+// out of the fixes and cleared once the bias is gone.
+
+use gnss_rcv::ephemeris::Ephemeris;
+use gnss_rcv::residuals::ResidualMonitor;
+use gnss_rcv::rinex::read_rinex_nav;
+use gnss_rcv::timing::{get_clock_offset, get_ecef};
+use std::path::Path;
+
+const GOLDEN_NAV: &str = "tests/data/golden_nav.rnx";
+const POSITION: [f64; 3] = [40.7, -74.0, 10.0];
+const SPEED_OF_LIGHT: f64 = 299792458.0;
+const BIAS_M: f64 = 100.0;
+
+// The satellites above the mask at POSITION, their code offsets set so that
+// they all see the same clock offset: zero residuals.
+fn get_consistent_ephs() -> Vec<Ephemeris> {
+    let mut ephs: Vec<Ephemeris> = vec![];
+    for eph in read_rinex_nav(Path::new(GOLDEN_NAV)).unwrap() {
+        if ephs.iter().all(|e| e.sv != eph.sv) {
+            ephs.push(eph);
+        }
+    }
+    let local = ephs[0].tow_gpst;
+    let pos = (POSITION, get_ecef(POSITION));
+    ephs.retain(|eph| get_clock_offset(eph, 0.0, local, pos).is_some());
+    // the satellites move a little with the offsets: a few rounds
+    for _ in 0..3 {
+        let offsets: Vec<_> = (ephs.iter())
+            .map(|eph| get_clock_offset(eph, 0.0, local, pos).unwrap())
+            .collect();
+        for (eph, offset) in ephs.iter_mut().zip(&offsets) {
+            eph.code_off_sec += offsets[0] - offset;
+        }
+    }
+    ephs
+}
+
+#[test]
+fn residuals_consistent() {
+    let ephs = get_consistent_ephs();
+    assert!(ephs.len() >= 5, "{} satellites", ephs.len());
+    let mut monitor = ResidualMonitor::new();
+    for i in 0..10 {
+        assert!(monitor.update(i as f64, POSITION, &ephs).is_empty());
+    }
+    let status = monitor.get_status();
+    assert_eq!(status.len(), ephs.len());
+    for res in &status {
+        assert!(res.rms_m < 1.0, "{res}");
+        assert!(!res.biased && !res.excluded);
+    }
+    assert_eq!(monitor.select(ephs.clone()).len(), ephs.len());
+}
+
+#[test]
+fn residuals_biased() {
+    let mut ephs = get_consistent_ephs();
+    let sv = ephs[1].sv;
+    ephs[1].code_off_sec += BIAS_M / SPEED_OF_LIGHT;
+    let mut monitor = ResidualMonitor::new();
+    let changed: Vec<_> = (0..5)
+        .flat_map(|i| monitor.update(i as f64, POSITION, &ephs))
+        .collect();
+    assert_eq!(changed, vec![sv]);
+    let res = (monitor.get_status().into_iter())
+        .find(|res| res.sv == sv)
+        .unwrap();
+    assert!(res.biased && (res.mean_m - BIAS_M).abs() < 5.0, "{res}");
+
+    let selected = monitor.select(ephs.clone());
+    assert_eq!(selected.len(), ephs.len() - 1);
+    assert!(selected.iter().all(|eph| eph.sv != sv));
+
+    // the bias gone, the mean of the window comes back under the threshold
+    ephs[1].code_off_sec -= BIAS_M / SPEED_OF_LIGHT;
+    let changed: Vec<_> = (5..40)
+        .flat_map(|i| monitor.update(i as f64, POSITION, &ephs))
+        .collect();
+    assert_eq!(changed, vec![sv]);
+    assert_eq!(monitor.select(ephs.clone()).len(), ephs.len());
+}
+
+// never fewer than 4 satellites for a fix
+#[test]
+fn residuals_min_svs() {
+    let mut ephs = get_consistent_ephs();
+    ephs.truncate(4);
+    ephs[1].code_off_sec += BIAS_M / SPEED_OF_LIGHT;
+    let mut monitor = ResidualMonitor::new();
+    for i in 0..5 {
+        monitor.update(i as f64, POSITION, &ephs);
+    }
+    assert_eq!(monitor.select(ephs.clone()).len(), 4);
+}
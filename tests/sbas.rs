@@ -0,0 +1,321 @@
+// SBAS L1: the convolutional code and the CRC of the 250-bit messages, the
+// messages found in a window of symbols of either polarity, the corrections
+// of the mask applied to gps ephemerides and the GEO ephemeris put in gps
+// time.
+
+use gnss_rcv::constants::{P2_11, P2_31, P2_39, P2_40};
+use gnss_rcv::ephemeris::Ephemeris;
+use gnss_rcv::navigation_sbas::{
+    SbasData, is_sbas, sbas_crc24q, sbas_decode_symbols, sbas_encode_symbols, sbas_viterbi_decode,
+};
+use gnss_rcv::solver::get_sv_position_ecef;
+use gnss_rcv::util::{getbitu, setbitu};
+use gnss_rs::constellation::Constellation;
+use gnss_rs::sv::SV;
+use gnss_rtk::prelude::Duration;
+
+const MSG_BITS: usize = 250;
+const MARGIN_BITS: usize = 35;
+
+fn gen_bits(seed: u64, num: usize) -> Vec<u8> {
+    let mut x = 0x9E3779B97F4A7C15 ^ seed;
+    (0..num)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x & 1) as u8
+        })
+        .collect()
+}
+
+// a message of type id with the fields (pos, len, value), the rest at 0
+fn gen_message(preamble: u32, id: u32, fields: &[(usize, usize, i64)]) -> Vec<u8> {
+    let mut buf = vec![0; MSG_BITS.div_ceil(8)];
+    setbitu(&mut buf, 0, 8, preamble);
+    setbitu(&mut buf, 8, 6, id);
+    for &(pos, len, value) in fields {
+        setbitu(&mut buf, pos, len, value as u32);
+    }
+    let crc = sbas_crc24q(&buf, 226);
+    setbitu(&mut buf, 226, 24, crc);
+    buf
+}
+
+fn unpack(buf: &[u8]) -> Vec<u8> {
+    (0..MSG_BITS).map(|i| getbitu(buf, i, 1) as u8).collect()
+}
+
+#[test]
+fn sbas_convolutional_code() {
+    // the check value of CRC-24Q
+    assert_eq!(sbas_crc24q(b"123456789", 72), 0xCDE703);
+
+    let bits = gen_bits(1, 600);
+    let syms = sbas_encode_symbols(&bits);
+    assert_eq!(syms.len(), 1200);
+    assert_eq!(sbas_viterbi_decode(&syms), bits);
+    // a symbol in error every 20, of either polarity: the bits away from the
+    // ends of the window come out right
+    let mut bad = syms.clone();
+    for i in (7..bad.len()).step_by(20) {
+        bad[i] ^= 1;
+    }
+    let decoded = sbas_viterbi_decode(&bad[200..1000]);
+    assert_eq!(
+        decoded[MARGIN_BITS..400 - MARGIN_BITS],
+        bits[100 + MARGIN_BITS..500 - MARGIN_BITS]
+    );
+    let inverted: Vec<_> = bad.iter().map(|s| s ^ 1).collect();
+    let decoded = sbas_viterbi_decode(&inverted[200..1000]);
+    assert!(
+        (MARGIN_BITS..400 - MARGIN_BITS).all(|i| decoded[i] != bits[100 + i]),
+        "not inverted"
+    );
+}
+
+#[test]
+fn sbas_messages() {
+    // three messages in a row, their preambles in turn
+    let msgs: Vec<_> = [0x53, 0x9A, 0xC6]
+        .into_iter()
+        .enumerate()
+        .map(|(k, preamble)| {
+            let data = gen_bits(k as u64 + 2, 212);
+            let fields: Vec<(usize, usize, i64)> = (data.iter().enumerate())
+                .map(|(i, &b)| (14 + i, 1, b as i64))
+                .collect();
+            gen_message(preamble, 63, &fields)
+        })
+        .collect();
+    let bits: Vec<_> = msgs.iter().flat_map(|msg| unpack(msg)).collect();
+    // inverted, a symbol off the pairs
+    let mut syms = vec![1];
+    syms.extend(sbas_encode_symbols(&bits).iter().map(|s| s ^ 1));
+
+    // the second message starts at symbol 501, too close to the end of this
+    // window, and the first too close to its start
+    assert!(sbas_decode_symbols(&syms[..900]).is_empty());
+    // on either alignment of the pairs
+    for start in [300, 301, 350] {
+        let found = sbas_decode_symbols(&syms[start..start + 800]);
+        assert_eq!(found, [(501 - start, msgs[1].clone())], "from {start}");
+    }
+
+    // a bit of the data in error
+    let mut bad = bits.clone();
+    bad[250 + 100] ^= 1;
+    let syms = sbas_encode_symbols(&bad);
+    assert!(sbas_decode_symbols(&syms[300..1100]).is_empty());
+}
+
+fn gps_ephemeris(prn: u8, iode: u32) -> Ephemeris {
+    let mut eph = Ephemeris::new(SV::new(Constellation::GPS, prn));
+    eph.week = 2300;
+    eph.tow = 5 * 86400 + 43290; // 43300 s of the day at a fix at 30 sec
+    eph.ts_sec = 20.0;
+    eph.iode = iode;
+    eph.f0 = 1e-5;
+    eph
+}
+
+#[test]
+fn sbas_corrections() {
+    let gps = |prn| SV::new(Constellation::GPS, prn);
+    let mut data = SbasData::default();
+    let mut geo = Ephemeris::new(gps(133));
+    assert!(is_sbas(geo.sv) && !is_sbas(gps(32)));
+
+    // no mask yet
+    let fast = gen_message(
+        0x9A,
+        2,
+        &[(14, 2, 1), (16, 2, 2), (18, 12, -84), (174, 4, 5)],
+    );
+    data.decode_message(&fast, &mut geo, 10.0);
+    assert!(!data.has_corrections());
+
+    // gps 3, 7 and 12, GLONASS slot 2 and the GEO 133
+    let mask: Vec<(usize, usize, i64)> = [3, 7, 12, 39, 133]
+        .into_iter()
+        .map(|i| (13 + i, 1, 1))
+        .chain([(224, 2, 2)])
+        .collect();
+    assert_eq!(
+        data.decode_message(&gen_message(0x53, 1, &mask), &mut geo, 10.0),
+        1
+    );
+    assert_eq!(data.iodp, Some(2));
+    assert_eq!(
+        data.mask,
+        [
+            Some(gps(3)),
+            Some(gps(7)),
+            Some(gps(12)),
+            Some(SV::new(Constellation::Glonass, 2)),
+            Some(gps(133))
+        ]
+    );
+
+    // slot 1 at -10.5 m, slot 2 not to be used, slot 3 not monitored
+    let fast = gen_message(
+        0x9A,
+        2,
+        &[
+            (14, 2, 1),
+            (16, 2, 2),
+            (18, 12, -84),
+            (174, 4, 5),
+            (30, 12, 40),
+            (178, 4, 15),
+            (42, 12, 26),
+            (182, 4, 14),
+        ],
+    );
+    data.decode_message(&fast, &mut geo, 10.0);
+    // for another mask
+    let stale = gen_message(0xC6, 2, &[(14, 2, 1), (16, 2, 1), (18, 12, 80)]);
+    data.decode_message(&stale, &mut geo, 11.0);
+    assert_eq!(data.fast[&gps(3)].prc, -10.5);
+    assert_eq!(data.fast[&gps(3)].udrei, 5);
+
+    // slot 1 without its rates, slot 3 with them
+    let long = gen_message(
+        0x53,
+        25,
+        &[
+            (15, 6, 1),
+            (21, 8, 45),
+            (29, 9, 8),
+            (38, 9, -16),
+            (47, 9, 4),
+            (56, 10, -20),
+            (117, 2, 2),
+            (120, 1, 1),
+            (121, 6, 3),
+            (127, 8, 7),
+            (135, 11, 100),
+            (146, 11, -40),
+            (168, 11, 5),
+            (179, 8, 64),
+            (195, 8, -32),
+            (203, 8, 10),
+            (211, 13, 2700),
+            (224, 2, 2),
+        ],
+    );
+    assert_eq!(data.decode_message(&long, &mut geo, 12.0), 25);
+
+    let mut eph = gps_ephemeris(3, 45);
+    assert!(data.apply(&mut eph, 30.0));
+    assert_eq!(eph.prc, -10.5);
+    assert_eq!(eph.dpos, [1.0, -2.0, 0.5]);
+    assert_eq!(eph.f0, 1e-5 - 20.0 * P2_31);
+
+    let mut eph = gps_ephemeris(7, 1);
+    assert!(!data.apply(&mut eph, 30.0));
+
+    let mut eph = gps_ephemeris(12, 7);
+    assert!(data.apply(&mut eph, 30.0));
+    assert_eq!(eph.prc, 0.0);
+    let dvz = -32.0 * P2_11;
+    assert!((eph.dpos[0] - (12.5 + 64.0 * P2_11 * 100.0)).abs() < 1e-9);
+    assert!((eph.dpos[1] + 5.0).abs() < 1e-9);
+    assert!((eph.dpos[2] - dvz * 100.0).abs() < 1e-9);
+    let df0 = 5.0 * P2_31 + 10.0 * P2_39 * 100.0;
+    assert!((eph.f0 - 1e-5 - df0).abs() < 1e-18);
+
+    // another IODE, or too late
+    let mut eph = gps_ephemeris(12, 8);
+    assert!(data.apply(&mut eph, 30.0));
+    assert_eq!(eph.dpos, [0.0; 3]);
+    let mut eph = gps_ephemeris(3, 45);
+    assert!(data.apply(&mut eph, 1000.0));
+    assert_eq!(eph.prc, 0.0);
+    assert_eq!(eph.dpos, [0.0; 3]);
+
+    // the GEO almanac
+    let almanac = gen_message(
+        0x9A,
+        17,
+        &[
+            (16, 8, 133),
+            (32, 15, 16000),
+            (47, 15, -3000),
+            (62, 9, 1),
+            (71, 3, -1),
+            (74, 3, 2),
+            (77, 4, -3),
+            (215, 11, 675),
+        ],
+    );
+    assert_eq!(data.decode_message(&almanac, &mut geo, 13.0), 17);
+    assert_eq!(data.almanac.len(), 1);
+    let alm = &data.almanac[&133];
+    assert_eq!(alm.pos, [41.6e6, -7.8e6, 26000.0]);
+    assert_eq!(alm.vel, [-10.0, 20.0, -180.0]);
+    assert_eq!(alm.t0, 43200);
+}
+
+#[test]
+fn sbas_geo_ephemeris() {
+    let mut data = SbasData::default();
+    let mut geo = Ephemeris::new(SV::new(Constellation::GPS, 133));
+    let mut other = gps_ephemeris(3, 45);
+    other.tow = 5 * 86400 + 43190;
+    other.ts_sec = 100.16;
+    // the receiver time of the start of the message, as a channel has it
+    geo.ts_sec = 105.16;
+    assert!(data.get_geo_ephemeris(&geo, &other, 110.0).is_none());
+
+    let msg = gen_message(
+        0xC6,
+        9,
+        &[
+            (14, 8, 1),
+            (22, 13, 2700),
+            (35, 4, 3),
+            (39, 30, 500_000_000),
+            (69, 30, -100_000_000),
+            (99, 25, 1000),
+            (124, 17, 1600),
+            (158, 18, -250),
+            (176, 10, 80),
+            (206, 12, -100),
+            (218, 8, 3),
+        ],
+    );
+    assert_eq!(data.decode_message(&msg, &mut geo, 105.0), 9);
+    assert_eq!(data.geo_t0, Some(43200));
+    assert_eq!(geo.iode, 1);
+    assert_eq!(geo.sva, 3);
+    assert!((geo.pos[0] - 40e6).abs() < 1e-6);
+    assert!((geo.pos[1] + 8e6).abs() < 1e-6);
+    assert!((geo.pos[2] - 400.0).abs() < 1e-9);
+    assert!((geo.vel[0] - 1.0).abs() < 1e-12 && geo.vel[1] == 0.0);
+    assert!((geo.vel[2] + 1.0).abs() < 1e-12);
+    assert!((geo.acc[0] - 0.001).abs() < 1e-12);
+    assert_eq!(geo.f0, -100.0 * P2_31);
+    assert_eq!(geo.f1, 3.0 * P2_40);
+
+    // the message 5 sec after the gps subframe, the GEO a little further
+    let eph = data.get_geo_ephemeris(&geo, &other, 110.0).unwrap();
+    assert_eq!(eph.week, 2300);
+    assert_eq!(eph.tow, 5 * 86400 + 43195);
+    assert_eq!(eph.toe, 5 * 86400 + 43200);
+    let (x, y, z) = get_sv_position_ecef(&eph, eph.toe_gpst + Duration::from_seconds(100.0));
+    assert!((x - (40e6 + 100.0 + 5.0)).abs() < 1e-6);
+    assert!((y + 8e6).abs() < 1e-6);
+    assert!((z - 300.0).abs() < 1e-6);
+    // too old
+    assert!(data.get_geo_ephemeris(&geo, &other, 500.0).is_none());
+
+    // not for ranging
+    let mut msg = msg.clone();
+    setbitu(&mut msg, 35, 4, 15);
+    let crc = sbas_crc24q(&msg, 226);
+    setbitu(&mut msg, 226, 24, crc);
+    data.decode_message(&msg, &mut geo, 106.0);
+    assert_eq!(geo.svh, 1);
+    assert!(data.get_geo_ephemeris(&geo, &other, 110.0).is_none());
+}
@@ -0,0 +1,53 @@
+// The survey-in of the timing mode on fixes scattered around a position, and
+// the holdover without any satellite.
+
+use gnss_rcv::timing::{TimingSolver, TimingStage};
+
+const POS: [f64; 3] = [37.4, -122.1, 30.0];
+
+// about 1 m around POS
+fn get_fix(i: usize) -> [f64; 3] {
+    let d = if i % 2 == 0 { 1e-5 } else { -1e-5 };
+    [POS[0] + d, POS[1] - d, POS[2] + d * 1e5]
+}
+
+#[test]
+fn timing_survey() {
+    let mut timing = TimingSolver::new(10.0, None, 0.0);
+    assert!(timing.is_surveying());
+    for i in 0..10 {
+        assert!(!timing.add_fix(i as f64, get_fix(i)));
+        assert_eq!(timing.update(i as f64, &[]), TimingStage::Survey);
+    }
+    assert!(timing.add_fix(10.0, POS));
+    assert!(!timing.is_surveying());
+
+    let status = timing.get_status();
+    assert_eq!(status.num_survey_fixes, 11);
+    let pos = status.position.unwrap();
+    assert!((pos[0] - POS[0]).abs() < 1e-6 && (pos[1] - POS[1]).abs() < 1e-6);
+    assert!((pos[2] - POS[2]).abs() < 0.5, "height {:.2}", pos[2]);
+    let rms_m = status.survey_rms_m.unwrap();
+    assert!(rms_m > 0.5 && rms_m < 3.0, "rms {rms_m:.2} m");
+}
+
+// the survey lasts survey_sec, but never fewer than 10 fixes
+#[test]
+fn timing_survey_min_fixes() {
+    let mut timing = TimingSolver::new(1.0, None, 0.0);
+    for i in 0..9 {
+        assert!(!timing.add_fix(10.0 * i as f64, POS));
+    }
+    assert!(timing.add_fix(90.0, POS));
+}
+
+#[test]
+fn timing_holdover() {
+    let mut timing = TimingSolver::new(600.0, Some(POS), 0.0);
+    assert!(!timing.is_surveying());
+    assert!(!timing.add_fix(0.0, POS));
+    assert_eq!(timing.update(1.0, &[]), TimingStage::Holdover);
+    let status = timing.get_status();
+    assert_eq!(status.num_svs, 0);
+    assert!(status.offset_sec.is_none() && status.freq_error_ppb.is_none());
+}